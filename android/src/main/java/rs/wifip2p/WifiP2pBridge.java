@@ -0,0 +1,169 @@
+package rs.wifip2p;
+
+import android.annotation.SuppressLint;
+import android.content.BroadcastReceiver;
+import android.content.Context;
+import android.content.Intent;
+import android.content.IntentFilter;
+import android.content.pm.PackageManager;
+import android.os.Build;
+import android.net.wifi.p2p.WifiP2pConfig;
+import android.net.wifi.p2p.WifiP2pDevice;
+import android.net.wifi.p2p.WifiP2pDeviceList;
+import android.net.wifi.p2p.WifiP2pManager;
+
+/**
+ * Java side of wifi-p2p-rs' Android backend (`backend::android::AndroidBackend`).
+ *
+ * Construct one from app code, hand it to the Rust side, and it forwards
+ * `WifiP2pManager` calls for the backend. Listener results and the P2P
+ * broadcast intents come back through the native methods below. The app must
+ * hold the Wi-Fi Direct permissions (NEARBY_WIFI_DEVICES or ACCESS_FINE_LOCATION).
+ */
+@SuppressLint("MissingPermission")
+public final class WifiP2pBridge {
+    /** Reported to the native side instead of a failure reason on success. */
+    private static final int SUCCESS = -1;
+
+    private final Context context;
+    private final WifiP2pManager manager;
+    private final WifiP2pManager.Channel channel;
+    private volatile long handle;
+
+    private final BroadcastReceiver receiver = new BroadcastReceiver() {
+        @Override
+        public void onReceive(Context context, Intent intent) {
+            long handle = WifiP2pBridge.this.handle;
+            if (handle == 0) {
+                return;
+            }
+            String action = intent.getAction();
+            if (WifiP2pManager.WIFI_P2P_PEERS_CHANGED_ACTION.equals(action)) {
+                WifiP2pDeviceList peers =
+                        intent.getParcelableExtra(WifiP2pManager.EXTRA_P2P_DEVICE_LIST);
+                nativeOnPeersChanged(handle, toArray(peers));
+            } else if (WifiP2pManager.WIFI_P2P_DISCOVERY_CHANGED_ACTION.equals(action)) {
+                int state = intent.getIntExtra(WifiP2pManager.EXTRA_DISCOVERY_STATE,
+                        WifiP2pManager.WIFI_P2P_DISCOVERY_STOPPED);
+                nativeOnDiscoveryChanged(handle,
+                        state == WifiP2pManager.WIFI_P2P_DISCOVERY_STARTED);
+            } else if (WifiP2pManager.ACTION_WIFI_P2P_LISTEN_STATE_CHANGED.equals(action)) {
+                int state = intent.getIntExtra(WifiP2pManager.EXTRA_LISTEN_STATE,
+                        WifiP2pManager.WIFI_P2P_LISTEN_STOPPED);
+                nativeOnListenChanged(handle, state == WifiP2pManager.WIFI_P2P_LISTEN_STARTED);
+            }
+        }
+    };
+
+    public WifiP2pBridge(Context context) {
+        this.context = context.getApplicationContext();
+        this.manager = this.context.getSystemService(WifiP2pManager.class);
+        this.channel = manager.initialize(this.context, this.context.getMainLooper(), null);
+    }
+
+    /** Called by the backend once it is ready to receive callbacks. */
+    public void attach(long handle) {
+        this.handle = handle;
+        IntentFilter filter = new IntentFilter();
+        filter.addAction(WifiP2pManager.WIFI_P2P_PEERS_CHANGED_ACTION);
+        filter.addAction(WifiP2pManager.WIFI_P2P_DISCOVERY_CHANGED_ACTION);
+        // Listen state broadcasts only exist from Android 14 on.
+        if (Build.VERSION.SDK_INT >= Build.VERSION_CODES.UPSIDE_DOWN_CAKE) {
+            filter.addAction(WifiP2pManager.ACTION_WIFI_P2P_LISTEN_STATE_CHANGED);
+        }
+        context.registerReceiver(receiver, filter);
+    }
+
+    /** Called by the backend when it is dropped. */
+    public void detach() {
+        handle = 0;
+        context.unregisterReceiver(receiver);
+    }
+
+    public boolean isP2pSupported() {
+        return context.getPackageManager().hasSystemFeature(PackageManager.FEATURE_WIFI_DIRECT);
+    }
+
+    public void discoverPeers(long request) {
+        manager.discoverPeers(channel, listener(request));
+    }
+
+    public void discoverPeersOnSocialChannels(long request) {
+        manager.discoverPeersOnSocialChannels(channel, listener(request));
+    }
+
+    public void discoverPeersOnSpecificFrequency(long request, int frequencyMhz) {
+        manager.discoverPeersOnSpecificFrequency(channel, frequencyMhz, listener(request));
+    }
+
+    public void stopPeerDiscovery(long request) {
+        manager.stopPeerDiscovery(channel, listener(request));
+    }
+
+    public void startListening(long request) {
+        manager.startListening(channel, listener(request));
+    }
+
+    public void stopListening(long request) {
+        manager.stopListening(channel, listener(request));
+    }
+
+    public void connect(long request, String address, int wpsSetup, String pin) {
+        WifiP2pConfig config = new WifiP2pConfig();
+        config.deviceAddress = address;
+        config.wps.setup = wpsSetup;
+        if (pin != null) {
+            config.wps.pin = pin;
+        }
+        manager.connect(channel, config, listener(request));
+    }
+
+    public void cancelConnect(long request) {
+        manager.cancelConnect(channel, listener(request));
+    }
+
+    public void requestPeers(long request) {
+        long handle = this.handle;
+        manager.requestPeers(channel, peers -> nativeOnPeers(handle, request, toArray(peers)));
+    }
+
+    public void createGroup(long request) {
+        manager.createGroup(channel, listener(request));
+    }
+
+    public void removeGroup(long request) {
+        manager.removeGroup(channel, listener(request));
+    }
+
+    private WifiP2pManager.ActionListener listener(long request) {
+        long handle = this.handle;
+        return new WifiP2pManager.ActionListener() {
+            @Override
+            public void onSuccess() {
+                nativeOnActionResult(handle, request, SUCCESS);
+            }
+
+            @Override
+            public void onFailure(int reason) {
+                nativeOnActionResult(handle, request, reason);
+            }
+        };
+    }
+
+    private static WifiP2pDevice[] toArray(WifiP2pDeviceList peers) {
+        if (peers == null) {
+            return new WifiP2pDevice[0];
+        }
+        return peers.getDeviceList().toArray(new WifiP2pDevice[0]);
+    }
+
+    private static native void nativeOnActionResult(long handle, long request, int reason);
+
+    private static native void nativeOnPeers(long handle, long request, WifiP2pDevice[] peers);
+
+    private static native void nativeOnPeersChanged(long handle, WifiP2pDevice[] peers);
+
+    private static native void nativeOnDiscoveryChanged(long handle, boolean started);
+
+    private static native void nativeOnListenChanged(long handle, boolean started);
+}
@@ -0,0 +1,20 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "grpc")]
+    grpc();
+    // Lets the addon leave Node's symbols to be resolved at load time on macOS.
+    #[cfg(feature = "node")]
+    napi_build::setup();
+}
+
+/// Generate the `grpc::proto` module from proto/wifip2p.proto.
+#[cfg(feature = "grpc")]
+fn grpc() {
+    println!("cargo:rerun-if-changed=proto/wifip2p.proto");
+    let descriptors = protox::compile(["proto/wifip2p.proto"], ["proto"])
+        .expect("proto/wifip2p.proto should compile");
+    tonic_build::configure()
+        .build_client(false)
+        .compile_fds(descriptors)
+        .expect("gRPC code generation should succeed");
+}
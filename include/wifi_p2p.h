@@ -0,0 +1,129 @@
+/*
+ * C API of wifi-p2p-rs, built with the `ffi` feature:
+ *
+ *     cargo rustc --release --features ffi --crate-type cdylib
+ *
+ * Declarations mirror src/ffi.rs; keep the two in step.
+ *
+ * Functions returning int32_t return WIFI_P2P_OK or a negative WIFI_P2P_ERR_*
+ * code, with the message available from wifi_p2p_last_error() on the same
+ * thread. Functions returning a handle return NULL on failure.
+ *
+ * Callbacks run on the library's runtime threads, never on the caller's, so
+ * user_data must be safe to use from another thread. A callback must not block
+ * for long: events and results for the same manager wait behind it.
+ */
+#ifndef WIFI_P2P_H
+#define WIFI_P2P_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define WIFI_P2P_OK 0
+#define WIFI_P2P_ERR_DBUS (-1)
+#define WIFI_P2P_ERR_ZVARIANT (-2)
+#define WIFI_P2P_ERR_CHANNEL_CLOSED (-3)
+#define WIFI_P2P_ERR_INVALID_INTERFACE (-4)
+#define WIFI_P2P_ERR_INVALID_MAC_ADDRESS (-5)
+#define WIFI_P2P_ERR_INVALID_CONFIG (-6)
+#define WIFI_P2P_ERR_TIMEOUT (-7)
+#define WIFI_P2P_ERR_CANCELLED (-8)
+#define WIFI_P2P_ERR_STATUS (-9)
+#define WIFI_P2P_ERR_INTERFACE_UNKNOWN (-10)
+#define WIFI_P2P_ERR_NOT_CONNECTED (-11)
+#define WIFI_P2P_ERR_INVALID_ARGS (-12)
+#define WIFI_P2P_ERR_BUSY (-13)
+#define WIFI_P2P_ERR_PERMISSION_DENIED (-14)
+#define WIFI_P2P_ERR_UNSUPPORTED (-15)
+#define WIFI_P2P_ERR_SUPPLICANT_UNAVAILABLE (-16)
+#define WIFI_P2P_ERR_RADIO_BLOCKED (-17)
+#define WIFI_P2P_ERR_BLOB (-18)
+#define WIFI_P2P_ERR_UNSUPPORTED_PLATFORM (-19)
+#define WIFI_P2P_ERR_BACKEND (-20)
+
+/* wps_method for wifi_p2p_connect. */
+#define WIFI_P2P_WPS_PBC 0
+#define WIFI_P2P_WPS_DISPLAY 1
+#define WIFI_P2P_WPS_KEYPAD 2
+#define WIFI_P2P_WPS_LABEL 3
+
+/* Event kinds, as passed to wifi_p2p_event_cb and masked in wifi_p2p_register_events. */
+#define WIFI_P2P_EVENT_DISCOVERY (1u << 0)
+#define WIFI_P2P_EVENT_PEER (1u << 1)
+#define WIFI_P2P_EVENT_CONNECTION (1u << 2)
+#define WIFI_P2P_EVENT_GROUP (1u << 3)
+#define WIFI_P2P_EVENT_TRANSFER (1u << 4)
+#define WIFI_P2P_EVENT_STATS (1u << 5)
+#define WIFI_P2P_EVENT_BACKEND (1u << 6)
+
+typedef struct wifi_p2p_manager wifi_p2p_manager;
+typedef struct wifi_p2p_channel wifi_p2p_channel;
+typedef struct wifi_p2p_listener wifi_p2p_listener;
+
+/*
+ * Called exactly once per _async call. message is the error text on failure
+ * and NULL on success, except for connects, where it is the PIN the supplicant
+ * generated (or NULL). It is only valid during the call.
+ */
+typedef void (*wifi_p2p_result_cb)(int32_t status, const char *message, void *user_data);
+
+/*
+ * Called for each event: kind is one WIFI_P2P_EVENT_* bit, json an object
+ * {"time": <unix seconds>, "type": "<variant>", "data": ...} valid during the call.
+ */
+typedef void (*wifi_p2p_event_cb)(uint32_t kind, const char *json, void *user_data);
+
+/* Managers and channels. */
+wifi_p2p_manager *wifi_p2p_manager_new(const char *interface_name);
+/* Shuts the manager down; channels taken from it must still be freed. */
+void wifi_p2p_manager_free(wifi_p2p_manager *manager);
+wifi_p2p_channel *wifi_p2p_manager_initialize(const wifi_p2p_manager *manager);
+void wifi_p2p_channel_free(wifi_p2p_channel *channel);
+
+const char *wifi_p2p_last_error(void);
+void wifi_p2p_string_free(char *string);
+
+/* Blocking actions. */
+int32_t wifi_p2p_discover_peers(const wifi_p2p_channel *channel);
+int32_t wifi_p2p_stop_discovery(const wifi_p2p_channel *channel);
+/* pin may be NULL; *generated_pin (if not NULL) gets a PIN to free with wifi_p2p_string_free, or NULL. */
+int32_t wifi_p2p_connect(const wifi_p2p_channel *channel, const char *device_address,
+                         int32_t wps_method, const char *pin, char **generated_pin);
+int32_t wifi_p2p_cancel_connect(const wifi_p2p_channel *channel);
+/* JSON arrays of devices and groups, freed with wifi_p2p_string_free. */
+int32_t wifi_p2p_request_peers(const wifi_p2p_channel *channel, char **peers);
+int32_t wifi_p2p_request_groups(const wifi_p2p_channel *channel, char **groups);
+int32_t wifi_p2p_create_group(const wifi_p2p_channel *channel);
+int32_t wifi_p2p_invite(const wifi_p2p_channel *channel, const char *peer);
+int32_t wifi_p2p_remove_group(const wifi_p2p_channel *channel);
+
+/* Callback variants: an error return means the callback will not be called. */
+int32_t wifi_p2p_discover_peers_async(const wifi_p2p_channel *channel, wifi_p2p_result_cb callback,
+                                      void *user_data);
+int32_t wifi_p2p_stop_discovery_async(const wifi_p2p_channel *channel, wifi_p2p_result_cb callback,
+                                      void *user_data);
+int32_t wifi_p2p_connect_async(const wifi_p2p_channel *channel, const char *device_address,
+                               int32_t wps_method, const char *pin, wifi_p2p_result_cb callback,
+                               void *user_data);
+int32_t wifi_p2p_cancel_connect_async(const wifi_p2p_channel *channel, wifi_p2p_result_cb callback,
+                                      void *user_data);
+int32_t wifi_p2p_create_group_async(const wifi_p2p_channel *channel, wifi_p2p_result_cb callback,
+                                    void *user_data);
+int32_t wifi_p2p_invite_async(const wifi_p2p_channel *channel, const char *peer,
+                              wifi_p2p_result_cb callback, void *user_data);
+int32_t wifi_p2p_remove_group_async(const wifi_p2p_channel *channel, wifi_p2p_result_cb callback,
+                                    void *user_data);
+
+/* Events; kinds is a mask of WIFI_P2P_EVENT_* bits, 0 for all. */
+wifi_p2p_listener *wifi_p2p_register_events(const wifi_p2p_channel *channel, uint32_t kinds,
+                                            wifi_p2p_event_cb callback, void *user_data);
+void wifi_p2p_unregister_events(wifi_p2p_listener *listener);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* WIFI_P2P_H */
@@ -0,0 +1,34 @@
+'use strict';
+
+// The napi-rs addon, plus `events()` as an EventEmitter over its `subscribe` callback.
+
+const { EventEmitter } = require('events');
+const native = require('./wifi_p2p.node');
+
+/**
+ * Emits every event as `'event'` and again under its type, e.g. `'PeersChanged'`.
+ * `close()` stops delivery; until then the emitter keeps the process alive.
+ */
+class P2pEvents extends EventEmitter {
+  constructor(channel) {
+    super();
+    this._subscription = channel.subscribe((event) => {
+      this.emit('event', event);
+      this.emit(event.type, event.data);
+    });
+  }
+
+  close() {
+    this._subscription.unsubscribe();
+  }
+}
+
+native.WifiP2pChannel.prototype.events = function events() {
+  return new P2pEvents(this);
+};
+
+module.exports = {
+  WifiP2pManager: native.WifiP2pManager,
+  WifiP2pChannel: native.WifiP2pChannel,
+  P2pEvents,
+};
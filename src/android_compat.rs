@@ -0,0 +1,662 @@
+//! Names and shapes from `android.net.wifi.p2p`, for porting Android Wi-Fi
+//! Direct code line by line.
+//!
+//! As on Android, actions return at once and report through listeners:
+//!
+//! ```ignore
+//! let manager = WifiP2pManager::new("wlan0").await?;
+//! let channel = manager.initialize(());
+//! manager.discover_peers(&channel, |result: Result<(), i32>| {
+//!     if let Err(reason) = result {
+//!         eprintln!("discovery failed: {reason}");
+//!     }
+//! });
+//! manager.request_peers(&channel, |peers: WifiP2pDeviceList| {
+//!     for device in peers.get_device_list() {
+//!         println!("{:?}", device.device_name);
+//!     }
+//! });
+//! ```
+//!
+//! Listeners run on a tokio task rather than a looper, so this needs a tokio
+//! runtime. Pass `()` where Android code passes `null`. Broadcast intents such
+//! as `WIFI_P2P_PEERS_CHANGED_ACTION` become `P2pEventListener` callbacks;
+//! register one on `Channel::inner`.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use tokio::sync::oneshot;
+use tokio_stream::StreamExt;
+
+use crate::backend::{P2pBackend, P2pBackendImpl};
+use crate::channel::{EventKind, P2pEvent, WifiP2pChannel};
+use crate::config::{ConnectConfig, DiscoveryType, WpsMethod};
+use crate::device::{LocalDeviceInfo, P2pDevice};
+use crate::error::P2pError;
+use crate::group::{GroupRole, P2pGroup};
+use crate::mac::MacAddress;
+use crate::manager;
+use crate::service::LocalService;
+use crate::state::{DiscoveryState, ListenState, P2pState};
+use crate::stream::EventFilter;
+
+/// `WifiP2pManager.ActionListener`.
+pub trait ActionListener: Send + 'static {
+    fn on_success(&mut self);
+    /// `reason` is one of `WifiP2pManager::ERROR`, `P2P_UNSUPPORTED` or `BUSY`.
+    fn on_failure(&mut self, reason: i32);
+}
+
+/// A `null` listener.
+impl ActionListener for () {
+    fn on_success(&mut self) {}
+    fn on_failure(&mut self, _reason: i32) {}
+}
+
+/// A lambda taking the outcome, `Err` holding the failure reason.
+impl<F: FnMut(Result<(), i32>) + Send + 'static> ActionListener for F {
+    fn on_success(&mut self) {
+        self(Ok(()));
+    }
+
+    fn on_failure(&mut self, reason: i32) {
+        self(Err(reason));
+    }
+}
+
+/// `WifiP2pManager.ChannelListener`.
+pub trait ChannelListener: Send + 'static {
+    /// The manager stopped; the channel no longer takes commands.
+    fn on_channel_disconnected(&mut self);
+}
+
+impl ChannelListener for () {
+    fn on_channel_disconnected(&mut self) {}
+}
+
+impl<F: FnMut() + Send + 'static> ChannelListener for F {
+    fn on_channel_disconnected(&mut self) {
+        self();
+    }
+}
+
+/// `WifiP2pManager.PeerListListener`.
+pub trait PeerListListener: Send + 'static {
+    fn on_peers_available(&mut self, peers: WifiP2pDeviceList);
+}
+
+impl<F: FnMut(WifiP2pDeviceList) + Send + 'static> PeerListListener for F {
+    fn on_peers_available(&mut self, peers: WifiP2pDeviceList) {
+        self(peers);
+    }
+}
+
+/// `WifiP2pManager.ConnectionInfoListener`.
+pub trait ConnectionInfoListener: Send + 'static {
+    fn on_connection_info_available(&mut self, info: WifiP2pInfo);
+}
+
+impl<F: FnMut(WifiP2pInfo) + Send + 'static> ConnectionInfoListener for F {
+    fn on_connection_info_available(&mut self, info: WifiP2pInfo) {
+        self(info);
+    }
+}
+
+/// `WifiP2pManager.GroupInfoListener`; `None` when we are in no group.
+pub trait GroupInfoListener: Send + 'static {
+    fn on_group_info_available(&mut self, group: Option<P2pGroup>);
+}
+
+impl<F: FnMut(Option<P2pGroup>) + Send + 'static> GroupInfoListener for F {
+    fn on_group_info_available(&mut self, group: Option<P2pGroup>) {
+        self(group);
+    }
+}
+
+/// `WifiP2pManager.DiscoveryStateListener`; `state` is
+/// `WifiP2pManager::WIFI_P2P_DISCOVERY_STARTED` or `WIFI_P2P_DISCOVERY_STOPPED`.
+pub trait DiscoveryStateListener: Send + 'static {
+    fn on_discovery_state_available(&mut self, state: i32);
+}
+
+impl<F: FnMut(i32) + Send + 'static> DiscoveryStateListener for F {
+    fn on_discovery_state_available(&mut self, state: i32) {
+        self(state);
+    }
+}
+
+/// The `Consumer<Integer>` of `WifiP2pManager.getListenState`; `state` is
+/// `WifiP2pManager::WIFI_P2P_LISTEN_STARTED` or `WIFI_P2P_LISTEN_STOPPED`.
+pub trait ListenStateListener: Send + 'static {
+    fn on_listen_state_available(&mut self, state: i32);
+}
+
+impl<F: FnMut(i32) + Send + 'static> ListenStateListener for F {
+    fn on_listen_state_available(&mut self, state: i32) {
+        self(state);
+    }
+}
+
+/// `WifiP2pManager.P2pStateListener`; `state` is
+/// `WifiP2pManager::WIFI_P2P_STATE_ENABLED` or `WIFI_P2P_STATE_DISABLED`.
+pub trait P2pStateListener: Send + 'static {
+    fn on_p2p_state_available(&mut self, state: i32);
+}
+
+impl<F: FnMut(i32) + Send + 'static> P2pStateListener for F {
+    fn on_p2p_state_available(&mut self, state: i32) {
+        self(state);
+    }
+}
+
+/// `WifiP2pManager.DeviceInfoListener`; `None` where Android passes `null`.
+pub trait DeviceInfoListener: Send + 'static {
+    fn on_device_info_available(&mut self, device: Option<LocalDeviceInfo>);
+}
+
+impl<F: FnMut(Option<LocalDeviceInfo>) + Send + 'static> DeviceInfoListener for F {
+    fn on_device_info_available(&mut self, device: Option<LocalDeviceInfo>) {
+        self(device);
+    }
+}
+
+/// `WifiP2pManager.DnsSdServiceResponseListener`; `registration_type` is e.g.
+/// `_ipp._tcp.local.`.
+pub trait DnsSdServiceResponseListener: Send + 'static {
+    fn on_dns_sd_service_available(
+        &mut self,
+        instance_name: String,
+        registration_type: String,
+        src_device: P2pDevice,
+    );
+}
+
+impl DnsSdServiceResponseListener for () {
+    fn on_dns_sd_service_available(&mut self, _: String, _: String, _: P2pDevice) {}
+}
+
+impl<F: FnMut(String, String, P2pDevice) + Send + 'static> DnsSdServiceResponseListener for F {
+    fn on_dns_sd_service_available(
+        &mut self,
+        instance_name: String,
+        registration_type: String,
+        src_device: P2pDevice,
+    ) {
+        self(instance_name, registration_type, src_device);
+    }
+}
+
+/// `WifiP2pManager.DnsSdTxtRecordListener`; `full_domain_name` is e.g.
+/// `myprinter._ipp._tcp.local.`.
+pub trait DnsSdTxtRecordListener: Send + 'static {
+    fn on_dns_sd_txt_record_available(
+        &mut self,
+        full_domain_name: String,
+        txt_record_map: HashMap<String, String>,
+        src_device: P2pDevice,
+    );
+}
+
+impl DnsSdTxtRecordListener for () {
+    fn on_dns_sd_txt_record_available(
+        &mut self,
+        _: String,
+        _: HashMap<String, String>,
+        _: P2pDevice,
+    ) {
+    }
+}
+
+impl<F: FnMut(String, HashMap<String, String>, P2pDevice) + Send + 'static> DnsSdTxtRecordListener
+    for F
+{
+    fn on_dns_sd_txt_record_available(
+        &mut self,
+        full_domain_name: String,
+        txt_record_map: HashMap<String, String>,
+        src_device: P2pDevice,
+    ) {
+        self(full_domain_name, txt_record_map, src_device);
+    }
+}
+
+/// `WifiP2pManager`, over the crate's manager.
+pub struct WifiP2pManager<B: P2pBackend = P2pBackendImpl> {
+    inner: manager::WifiP2pManager<B>,
+}
+
+impl WifiP2pManager<P2pBackendImpl> {
+    /// Internal error, or a failure this facade has no closer reason for.
+    pub const ERROR: i32 = 0;
+    /// Wi-Fi Direct is not supported by the interface or the platform.
+    pub const P2P_UNSUPPORTED: i32 = 1;
+    /// The command queue or the supplicant is busy.
+    pub const BUSY: i32 = 2;
+
+    pub const WIFI_P2P_DISCOVERY_STOPPED: i32 = 1;
+    pub const WIFI_P2P_DISCOVERY_STARTED: i32 = 2;
+    pub const WIFI_P2P_LISTEN_STOPPED: i32 = 1;
+    pub const WIFI_P2P_LISTEN_STARTED: i32 = 2;
+    pub const WIFI_P2P_STATE_DISABLED: i32 = 1;
+    pub const WIFI_P2P_STATE_ENABLED: i32 = 2;
+
+    /// The platform manager on `interface_name`, as `getSystemService` gives on Android.
+    pub async fn new(interface_name: &str) -> Result<Self, P2pError> {
+        Ok(Self {
+            inner: manager::WifiP2pManager::new(interface_name).await?,
+        })
+    }
+}
+
+impl<B: P2pBackend> From<manager::WifiP2pManager<B>> for WifiP2pManager<B> {
+    /// Wrap a manager built with `WifiP2pManager::builder` or a custom backend.
+    fn from(inner: manager::WifiP2pManager<B>) -> Self {
+        Self { inner }
+    }
+}
+
+impl<B: P2pBackend> WifiP2pManager<B> {
+    /// Start the manager's command loop; `listener` hears when it stops.
+    pub fn initialize(&self, listener: impl ChannelListener) -> Channel {
+        let inner = self.inner.initialize();
+        let watched = inner.clone();
+        let mut listener = listener;
+        tokio::spawn(async move {
+            watched.closed().await;
+            listener.on_channel_disconnected();
+        });
+        Channel { inner }
+    }
+
+    /// The crate's manager, e.g. to shut it down.
+    pub fn inner(&mut self) -> &mut manager::WifiP2pManager<B> {
+        &mut self.inner
+    }
+
+    pub fn discover_peers(&self, channel: &Channel, listener: impl ActionListener) {
+        let inner = channel.inner.clone();
+        act(listener, async move { reply(inner.discover_peers()).await });
+    }
+
+    pub fn discover_peers_on_social_channels(
+        &self,
+        channel: &Channel,
+        listener: impl ActionListener,
+    ) {
+        let inner = channel.inner.clone();
+        act(listener, async move {
+            reply(inner.discover_peers_with(DiscoveryType::Social)).await
+        });
+    }
+
+    pub fn discover_peers_on_specific_frequency(
+        &self,
+        channel: &Channel,
+        frequency_mhz: i32,
+        listener: impl ActionListener,
+    ) {
+        let inner = channel.inner.clone();
+        act(listener, async move {
+            // Android throws for a frequency that is not positive; we fail the listener.
+            let frequency = u32::try_from(frequency_mhz)
+                .map_err(|_| P2pError::InvalidArgs(format!("frequency {frequency_mhz} MHz")))?;
+            let discovery = DiscoveryType::SingleFrequency(frequency);
+            reply(inner.discover_peers_with(discovery)).await
+        });
+    }
+
+    pub fn stop_peer_discovery(&self, channel: &Channel, listener: impl ActionListener) {
+        let inner = channel.inner.clone();
+        act(listener, async move { reply(inner.stop_discovery()).await });
+    }
+
+    pub fn start_listening(&self, channel: &Channel, listener: impl ActionListener) {
+        let inner = channel.inner.clone();
+        act(
+            listener,
+            async move { reply(inner.start_listening()).await },
+        );
+    }
+
+    pub fn stop_listening(&self, channel: &Channel, listener: impl ActionListener) {
+        let inner = channel.inner.clone();
+        act(listener, async move { reply(inner.stop_listening()).await });
+    }
+
+    /// Stopped if the manager cannot be asked.
+    pub fn get_listen_state(&self, channel: &Channel, listener: impl ListenStateListener) {
+        let inner = channel.inner.clone();
+        let mut listener = listener;
+        tokio::spawn(async move {
+            let state = match reply(inner.listen_state()).await {
+                Ok(ListenState::Started) => WifiP2pManager::WIFI_P2P_LISTEN_STARTED,
+                Ok(ListenState::Stopped) | Err(_) => WifiP2pManager::WIFI_P2P_LISTEN_STOPPED,
+            };
+            listener.on_listen_state_available(state);
+        });
+    }
+
+    pub fn connect(&self, channel: &Channel, config: WifiP2pConfig, listener: impl ActionListener) {
+        let inner = channel.inner.clone();
+        act(listener, async move {
+            let config = config.into_connect_config()?;
+            // Android reports a generated PIN through the system dialog, not the listener.
+            reply(inner.connect_with_config(config)).await.map(drop)
+        });
+    }
+
+    pub fn cancel_connect(&self, channel: &Channel, listener: impl ActionListener) {
+        let inner = channel.inner.clone();
+        act(listener, async move { reply(inner.cancel_connect()).await });
+    }
+
+    pub fn factory_reset(&self, channel: &Channel, listener: impl ActionListener) {
+        let inner = channel.inner.clone();
+        act(listener, async move { reply(inner.factory_reset()).await });
+    }
+
+    pub fn create_group(&self, channel: &Channel, listener: impl ActionListener) {
+        let inner = channel.inner.clone();
+        act(listener, async move { reply(inner.create_group()).await });
+    }
+
+    pub fn remove_group(&self, channel: &Channel, listener: impl ActionListener) {
+        let inner = channel.inner.clone();
+        act(listener, async move { reply(inner.remove_group()).await });
+    }
+
+    pub fn add_local_service(
+        &self,
+        channel: &Channel,
+        service: LocalService,
+        listener: impl ActionListener,
+    ) {
+        let inner = channel.inner.clone();
+        act(listener, async move {
+            reply(inner.add_local_service(service)).await
+        });
+    }
+
+    pub fn remove_local_service(
+        &self,
+        channel: &Channel,
+        service: LocalService,
+        listener: impl ActionListener,
+    ) {
+        let inner = channel.inner.clone();
+        act(listener, async move {
+            reply(inner.remove_local_service(service)).await
+        });
+    }
+
+    pub fn clear_local_services(&self, channel: &Channel, listener: impl ActionListener) {
+        let inner = channel.inner.clone();
+        act(listener, async move {
+            reply(inner.clear_local_services()).await
+        });
+    }
+
+    /// Query discovered peers for their services; answers arrive as
+    /// `P2pEvent::ServicesFound`, and Bonjour ones at the listeners set with
+    /// `set_dns_sd_response_listeners`.
+    pub fn discover_services(&self, channel: &Channel, listener: impl ActionListener) {
+        let inner = channel.inner.clone();
+        act(
+            listener,
+            async move { reply(inner.discover_services()).await },
+        );
+    }
+
+    pub fn clear_service_requests(&self, channel: &Channel, listener: impl ActionListener) {
+        let inner = channel.inner.clone();
+        act(listener, async move {
+            reply(inner.clear_service_requests()).await
+        });
+    }
+
+    /// Hear the Bonjour answers to `discover_services` from now on, until the
+    /// manager stops. Unlike Android, a second call adds listeners rather than
+    /// replacing the first ones.
+    pub fn set_dns_sd_response_listeners(
+        &self,
+        channel: &Channel,
+        serv_listener: impl DnsSdServiceResponseListener,
+        txt_listener: impl DnsSdTxtRecordListener,
+    ) {
+        let inner = channel.inner.clone();
+        let mut events = inner.subscribe_filtered(EventFilter::only(EventKind::Peer));
+        let (mut serv_listener, mut txt_listener) = (serv_listener, txt_listener);
+        tokio::spawn(async move {
+            // Android hands over the peer as known from discovery.
+            let device = |source: MacAddress| {
+                inner
+                    .peers_snapshot()
+                    .into_iter()
+                    .find(|device| device.mac_address == source)
+                    .unwrap_or_else(|| P2pDevice::new(source))
+            };
+            while let Some(event) = events.next().await {
+                match event {
+                    P2pEvent::DnsSdServiceAvailable(response) => {
+                        serv_listener.on_dns_sd_service_available(
+                            response.instance,
+                            response.registration_type,
+                            device(response.source),
+                        );
+                    }
+                    P2pEvent::DnsSdTxtRecordAvailable(record) => {
+                        txt_listener.on_dns_sd_txt_record_available(
+                            record.full_domain,
+                            record.txt_map,
+                            device(record.source),
+                        );
+                    }
+                    _ => {}
+                }
+            }
+        });
+    }
+
+    /// The supplicant's current peers; an empty list if it cannot be asked.
+    pub fn request_peers(&self, channel: &Channel, listener: impl PeerListListener) {
+        let inner = channel.inner.clone();
+        let mut listener = listener;
+        tokio::spawn(async move {
+            let devices = reply(inner.request_peers()).await.unwrap_or_default();
+            listener.on_peers_available(WifiP2pDeviceList { devices });
+        });
+    }
+
+    /// Stopped if the manager cannot be asked.
+    pub fn request_discovery_state(
+        &self,
+        channel: &Channel,
+        listener: impl DiscoveryStateListener,
+    ) {
+        let inner = channel.inner.clone();
+        let mut listener = listener;
+        tokio::spawn(async move {
+            let state = match reply(inner.discovery_state()).await {
+                Ok(DiscoveryState::Started) => WifiP2pManager::WIFI_P2P_DISCOVERY_STARTED,
+                Ok(DiscoveryState::Stopped) | Err(_) => WifiP2pManager::WIFI_P2P_DISCOVERY_STOPPED,
+            };
+            listener.on_discovery_state_available(state);
+        });
+    }
+
+    /// Disabled if the manager cannot be asked.
+    pub fn request_p2p_state(&self, channel: &Channel, listener: impl P2pStateListener) {
+        let inner = channel.inner.clone();
+        let mut listener = listener;
+        tokio::spawn(async move {
+            let state = match reply(inner.p2p_state()).await {
+                Ok(P2pState::Enabled) => WifiP2pManager::WIFI_P2P_STATE_ENABLED,
+                Ok(P2pState::Disabled) | Err(_) => WifiP2pManager::WIFI_P2P_STATE_DISABLED,
+            };
+            listener.on_p2p_state_available(state);
+        });
+    }
+
+    /// `None` if the platform does not report our own device.
+    pub fn request_device_info(&self, channel: &Channel, listener: impl DeviceInfoListener) {
+        let inner = channel.inner.clone();
+        let mut listener = listener;
+        tokio::spawn(async move {
+            let device = reply(inner.request_device_info()).await.ok();
+            listener.on_device_info_available(device);
+        });
+    }
+
+    pub fn request_connection_info(
+        &self,
+        channel: &Channel,
+        listener: impl ConnectionInfoListener,
+    ) {
+        let info = WifiP2pInfo::from_groups(&channel.inner.groups_snapshot());
+        let mut listener = listener;
+        tokio::spawn(async move { listener.on_connection_info_available(info) });
+    }
+
+    pub fn request_group_info(&self, channel: &Channel, listener: impl GroupInfoListener) {
+        let group = channel.inner.groups_snapshot().into_iter().next();
+        let mut listener = listener;
+        tokio::spawn(async move { listener.on_group_info_available(group) });
+    }
+}
+
+/// `WifiP2pManager.Channel`.
+#[derive(Clone)]
+pub struct Channel {
+    /// The crate's channel, for events and everything Android has no call for.
+    pub inner: WifiP2pChannel,
+}
+
+/// `WpsInfo`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WpsInfo {
+    /// One of the constants below.
+    pub setup: i32,
+    pub pin: Option<String>,
+}
+
+impl WpsInfo {
+    pub const PBC: i32 = 0;
+    pub const DISPLAY: i32 = 1;
+    pub const KEYPAD: i32 = 2;
+    pub const LABEL: i32 = 3;
+    pub const INVALID: i32 = 4;
+}
+
+/// `WifiP2pConfig`. `groupOwnerIntent` has no equivalent; the supplicant's
+/// configured intent is used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WifiP2pConfig {
+    pub device_address: MacAddress,
+    pub wps: WpsInfo,
+}
+
+impl WifiP2pConfig {
+    /// Push button to `device_address`, Android's default.
+    pub fn new(device_address: MacAddress) -> Self {
+        Self {
+            device_address,
+            wps: WpsInfo::default(),
+        }
+    }
+
+    fn into_connect_config(self) -> Result<ConnectConfig, P2pError> {
+        let wps_method = match self.wps.setup {
+            WpsInfo::PBC => WpsMethod::Pbc,
+            WpsInfo::DISPLAY => WpsMethod::PinDisplay,
+            WpsInfo::KEYPAD => WpsMethod::PinKeypad,
+            WpsInfo::LABEL => WpsMethod::PinLabel,
+            other => {
+                return Err(P2pError::InvalidConfig(format!(
+                    "unknown WpsInfo setup {other}"
+                )));
+            }
+        };
+        Ok(ConnectConfig {
+            device_address: self.device_address,
+            wps_method,
+            pin: self.wps.pin,
+        })
+    }
+}
+
+/// `WifiP2pDeviceList`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WifiP2pDeviceList {
+    devices: Vec<P2pDevice>,
+}
+
+impl WifiP2pDeviceList {
+    pub fn get_device_list(&self) -> &[P2pDevice] {
+        &self.devices
+    }
+
+    pub fn get(&self, device_address: MacAddress) -> Option<&P2pDevice> {
+        self.devices
+            .iter()
+            .find(|device| device.mac_address == device_address)
+    }
+}
+
+/// `WifiP2pInfo`, for the first group we are a member of.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WifiP2pInfo {
+    pub group_formed: bool,
+    pub is_group_owner: bool,
+    /// Known once P2P IP allocation handed out addresses.
+    pub group_owner_address: Option<IpAddr>,
+}
+
+impl WifiP2pInfo {
+    fn from_groups(groups: &[P2pGroup]) -> Self {
+        match groups.first() {
+            Some(group) => Self {
+                group_formed: true,
+                is_group_owner: group.role == GroupRole::Owner,
+                group_owner_address: group
+                    .ip_allocation
+                    .map(|allocation| IpAddr::V4(allocation.go_ip)),
+            },
+            None => Self::default(),
+        }
+    }
+}
+
+/// Run `action` and tell `listener` how it went.
+fn act(
+    listener: impl ActionListener,
+    action: impl Future<Output = Result<(), P2pError>> + Send + 'static,
+) {
+    let mut listener = listener;
+    tokio::spawn(async move {
+        match action.await {
+            Ok(()) => listener.on_success(),
+            Err(err) => listener.on_failure(reason(&err)),
+        }
+    });
+}
+
+fn reason(err: &P2pError) -> i32 {
+    match err {
+        P2pError::Unsupported(_) | P2pError::UnsupportedPlatform(_) => {
+            WifiP2pManager::P2P_UNSUPPORTED
+        }
+        P2pError::Busy(_) => WifiP2pManager::BUSY,
+        _ => WifiP2pManager::ERROR,
+    }
+}
+
+/// Wait for a queued command's answer.
+async fn reply<T>(
+    queued: impl Future<Output = Result<oneshot::Receiver<Result<T, P2pError>>, P2pError>>,
+) -> Result<T, P2pError> {
+    queued
+        .await?
+        .await
+        .unwrap_or_else(|_| Err(P2pError::ChannelClosed("manager".to_string())))
+}
@@ -0,0 +1,71 @@
+//! Deciding incoming connection requests without a user in the loop.
+//!
+//! A `ConnectionApprover` set with `WifiP2pChannel::set_connection_policy` is
+//! asked about every GO negotiation, invitation and provision discovery request
+//! a peer sends us, like Android's external approver. `Accept` connects to the
+//! peer with the request's method, `Reject` turns it down with `reject_peer`,
+//! and `Defer` reports it as `P2pEvent::IncomingRequest` (followed by
+//! `ConnectionRequested` for GO negotiation), as when no approver is set.
+//!
+//! Only GO negotiation and provision discovery requests can be accepted: an
+//! accepted invitation is deferred, since joining the inviting group (or
+//! re-invoking a persistent one) is up to the app. So is `Accept` of a request
+//! whose method needs a PIN it does not carry (`PinKeypad`, `PinLabel`); answer
+//! those with `AcceptWithPin`. When we display the PIN, the one to show is
+//! reported as `P2pEvent::PinToDisplay`.
+//!
+//! The approver runs on the manager's task between two commands, so it should
+//! answer quickly: a policy lookup, not a prompt.
+
+use crate::config::WpsMethod;
+use crate::mac::MacAddress;
+
+/// What a peer asked for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RequestKind {
+    /// The peer started GO negotiation to form a new group with us.
+    GoNegotiation,
+    /// The peer invited us into its group, often a persistent one we share.
+    Invitation,
+    /// The peer announced how it wants to provision before connecting.
+    ProvisionDiscovery,
+}
+
+/// An incoming request, as `P2pEvent::IncomingRequest` and for `ConnectionApprover`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConnectionRequest {
+    pub peer: MacAddress,
+    pub kind: RequestKind,
+    /// The method we connect with to match the peer's, e.g. `PinKeypad` when the
+    /// peer displays a PIN.
+    pub method: WpsMethod,
+    /// The PIN for us to display, when the peer picked it.
+    pub pin: Option<String>,
+}
+
+/// A `ConnectionApprover`'s answer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    /// Connect with the request's method and PIN.
+    Accept,
+    /// Connect with the request's method and this PIN, e.g. the one the peer
+    /// displays for `PinKeypad`.
+    AcceptWithPin(String),
+    Reject,
+    /// Leave the request to the app, which hears it as an event.
+    Defer,
+}
+
+/// Policy for incoming requests; see the module docs. Closures taking a
+/// `&ConnectionRequest` implement it.
+pub trait ConnectionApprover: Send + 'static {
+    fn approve(&mut self, request: &ConnectionRequest) -> Decision;
+}
+
+impl<F: FnMut(&ConnectionRequest) -> Decision + Send + 'static> ConnectionApprover for F {
+    fn approve(&mut self, request: &ConnectionRequest) -> Decision {
+        self(request)
+    }
+}
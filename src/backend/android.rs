@@ -0,0 +1,564 @@
+//! Android backend forwarding to the platform `WifiP2pManager` over JNI.
+//!
+//! The framework API is callback based, so the backend drives a small Java
+//! helper, `rs.wifip2p.WifiP2pBridge` (shipped in this repository under
+//! `android/`), that owns the `WifiP2pManager.Channel`, turns listener results
+//! into native calls and forwards the P2P broadcast intents.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex, MutexGuard, Weak};
+use std::time::Duration;
+
+use jni::objects::{GlobalRef, JClass, JObject, JObjectArray, JString, JValue};
+use jni::sys::{jboolean, jint, jlong};
+use jni::{JNIEnv, JavaVM};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::capabilities::P2pCapabilities;
+use crate::channel::P2pEvent;
+use crate::config::{ConnectConfig, DiscoveryOptions, DiscoveryType, WpsMethod};
+use crate::device::{DeviceStatus, LocalDeviceInfo, P2pDevice, wps_config};
+use crate::error::P2pError;
+use crate::group::P2pGroup;
+use crate::mac::MacAddress;
+use crate::state::ListenState;
+
+use super::P2pBackend;
+
+/// Passed by the bridge instead of a failure reason when an action succeeded.
+const ACTION_SUCCESS: jint = -1;
+// WifiP2pManager.ActionListener failure reasons.
+const REASON_ERROR: jint = 0;
+const REASON_P2P_UNSUPPORTED: jint = 1;
+const REASON_BUSY: jint = 2;
+// android.net.wifi.WpsInfo setup constants.
+const WPS_PBC: jint = 0;
+const WPS_DISPLAY: jint = 1;
+const WPS_KEYPAD: jint = 2;
+const WPS_LABEL: jint = 3;
+
+// Native callbacks only carry a handle; look the backend up here so a late
+// callback after drop finds nothing instead of a dangling pointer.
+static BRIDGES: LazyLock<Mutex<HashMap<jlong, Weak<Mutex<AndroidState>>>>> =
+    LazyLock::new(Mutex::default);
+static NEXT_HANDLE: AtomicI64 = AtomicI64::new(1);
+
+enum Reply {
+    Action(jint),
+    Peers(Vec<P2pDevice>),
+}
+
+#[derive(Default)]
+struct AndroidState {
+    pending: HashMap<jlong, oneshot::Sender<Reply>>,
+    next_request: jlong,
+    peers: HashMap<MacAddress, P2pDevice>,
+    events: Option<mpsc::Sender<P2pEvent>>,
+}
+
+impl AndroidState {
+    fn emit(&self, event: P2pEvent) {
+        // JNI callbacks run on the main looper and must not block.
+        if let Some(events) = &self.events {
+            let _ = events.try_send(event);
+        }
+    }
+}
+
+/// Backend for apps that share Rust core logic with Android.
+///
+/// Create a `WifiP2pBridge` from app code and pass it with the `JavaVM` to
+/// `AndroidBackend::new`. The framework neither reports a generated PIN nor
+/// exposes Notice of Absence, vendor elements or raw calls, so `PinDisplay`
+/// without a PIN returns `None` and the latter report `P2pError::Unsupported`.
+pub struct AndroidBackend {
+    vm: JavaVM,
+    bridge: GlobalRef,
+    handle: jlong,
+    state: Arc<Mutex<AndroidState>>,
+}
+
+impl AndroidBackend {
+    /// Take over `bridge` (a `rs.wifip2p.WifiP2pBridge`) and start receiving its callbacks.
+    pub fn new(env: &mut JNIEnv<'_>, bridge: &JObject<'_>) -> Result<Self, P2pError> {
+        let vm = env.get_java_vm().map_err(jni_error)?;
+        let bridge = env.new_global_ref(bridge).map_err(jni_error)?;
+        let state = Arc::new(Mutex::new(AndroidState::default()));
+        let handle = NEXT_HANDLE.fetch_add(1, Ordering::Relaxed);
+        lock(&BRIDGES).insert(handle, Arc::downgrade(&state));
+        let backend = Self {
+            vm,
+            bridge,
+            handle,
+            state,
+        };
+        backend.call_bridge("attach", "(J)V", &[JValue::Long(handle)])?;
+        Ok(backend)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, AndroidState> {
+        lock(&self.state)
+    }
+
+    fn call_bridge(
+        &self,
+        method: &str,
+        signature: &str,
+        args: &[JValue<'_, '_>],
+    ) -> Result<(), P2pError> {
+        let mut env = self.vm.attach_current_thread().map_err(jni_error)?;
+        let result = env.call_method(&self.bridge, method, signature, args);
+        check_exception(&mut env, result).map(drop)
+    }
+
+    /// Register a pending request, then let `call` hand its id to the bridge.
+    /// The JNI guard is dropped before waiting, so the future stays `Send`.
+    async fn request(
+        &self,
+        call: impl FnOnce(&mut JNIEnv<'_>, jlong) -> jni::errors::Result<()>,
+    ) -> Result<Reply, P2pError> {
+        let (tx, rx) = oneshot::channel();
+        let request = {
+            let mut state = self.lock();
+            state.next_request += 1;
+            let request = state.next_request;
+            state.pending.insert(request, tx);
+            request
+        };
+        let sent = {
+            let mut env = self.vm.attach_current_thread().map_err(jni_error)?;
+            let result = call(&mut env, request);
+            check_exception(&mut env, result)
+        };
+        if let Err(err) = sent {
+            self.lock().pending.remove(&request);
+            return Err(err);
+        }
+        rx.await
+            .map_err(|_| P2pError::ChannelClosed("Android bridge dropped the request".to_string()))
+    }
+
+    async fn action(&self, method: &str) -> Result<(), P2pError> {
+        let reply = self
+            .request(|env, request| {
+                env.call_method(&self.bridge, method, "(J)V", &[JValue::Long(request)])
+                    .map(drop)
+            })
+            .await?;
+        match reply {
+            Reply::Action(reason) => action_result(method, reason),
+            Reply::Peers(_) => Err(P2pError::Backend(format!("unexpected reply to {method}"))),
+        }
+    }
+}
+
+impl Drop for AndroidBackend {
+    fn drop(&mut self) {
+        lock(&BRIDGES).remove(&self.handle);
+        let _ = self.call_bridge("detach", "()V", &[]);
+    }
+}
+
+impl P2pBackend for AndroidBackend {
+    async fn discover_peers(&self, options: DiscoveryOptions) -> Result<(), P2pError> {
+        if !options.seek.is_empty() {
+            return Err(P2pError::Unsupported("Android has no P2PS API".to_string()));
+        }
+        let freq = match options.discovery_type {
+            DiscoveryType::Full => return self.action("discoverPeers").await,
+            DiscoveryType::Social => return self.action("discoverPeersOnSocialChannels").await,
+            DiscoveryType::Progressive => {
+                return Err(P2pError::Unsupported(
+                    "Android has no progressive discovery".to_string(),
+                ));
+            }
+            DiscoveryType::SingleFrequency(freq) => jint::try_from(freq)
+                .map_err(|_| P2pError::InvalidArgs(format!("frequency {freq} MHz")))?,
+        };
+        let method = "discoverPeersOnSpecificFrequency";
+        let reply = self
+            .request(|env, request| {
+                env.call_method(
+                    &self.bridge,
+                    method,
+                    "(JI)V",
+                    &[JValue::Long(request), JValue::Int(freq)],
+                )
+                .map(drop)
+            })
+            .await?;
+        match reply {
+            Reply::Action(reason) => action_result(method, reason),
+            Reply::Peers(_) => Err(P2pError::Backend(format!("unexpected reply to {method}"))),
+        }
+    }
+
+    async fn stop_discovery(&self) -> Result<(), P2pError> {
+        self.action("stopPeerDiscovery").await
+    }
+
+    async fn start_listening(&self) -> Result<(), P2pError> {
+        self.action("startListening").await
+    }
+
+    async fn start_listening_windowed(
+        &self,
+        _listen: Duration,
+        _every: Duration,
+    ) -> Result<(), P2pError> {
+        Err(P2pError::Unsupported(
+            "Android only listens continuously".to_string(),
+        ))
+    }
+
+    async fn stop_listening(&self) -> Result<(), P2pError> {
+        self.action("stopListening").await
+    }
+
+    async fn connect(&self, config: ConnectConfig) -> Result<Option<String>, P2pError> {
+        let setup = match config.wps_method {
+            WpsMethod::Pbc => WPS_PBC,
+            WpsMethod::PinDisplay => WPS_DISPLAY,
+            WpsMethod::PinKeypad => WPS_KEYPAD,
+            WpsMethod::PinLabel => WPS_LABEL,
+        };
+        let reply = self
+            .request(|env, request| {
+                let address = env.new_string(config.device_address.to_string())?;
+                let pin = match &config.pin {
+                    Some(pin) => JObject::from(env.new_string(pin)?),
+                    None => JObject::null(),
+                };
+                env.call_method(
+                    &self.bridge,
+                    "connect",
+                    "(JLjava/lang/String;ILjava/lang/String;)V",
+                    &[
+                        JValue::Long(request),
+                        JValue::Object(&address),
+                        JValue::Int(setup),
+                        JValue::Object(&pin),
+                    ],
+                )
+                .map(drop)
+            })
+            .await?;
+        match reply {
+            // The framework shows a generated PIN in its own dialog and never reports it.
+            Reply::Action(reason) => action_result("connect", reason).map(|()| None),
+            Reply::Peers(_) => Err(P2pError::Backend("unexpected reply to connect".to_string())),
+        }
+    }
+
+    async fn cancel_connect(&self) -> Result<(), P2pError> {
+        self.action("cancelConnect").await
+    }
+
+    async fn request_peers(&self) -> Result<Vec<P2pDevice>, P2pError> {
+        let reply = self
+            .request(|env, request| {
+                env.call_method(
+                    &self.bridge,
+                    "requestPeers",
+                    "(J)V",
+                    &[JValue::Long(request)],
+                )
+                .map(drop)
+            })
+            .await?;
+        match reply {
+            Reply::Peers(peers) => Ok(peers),
+            Reply::Action(reason) => action_result("requestPeers", reason).map(|()| Vec::new()),
+        }
+    }
+
+    async fn create_group(&self) -> Result<(), P2pError> {
+        self.action("createGroup").await
+    }
+
+    async fn request_groups(&self) -> Result<Vec<P2pGroup>, P2pError> {
+        Err(P2pError::Unsupported(
+            "group info is not wired to the Android API".to_string(),
+        ))
+    }
+
+    async fn invite(&self, _peer: MacAddress) -> Result<(), P2pError> {
+        Err(P2pError::Unsupported(
+            "invitations are not wired to the Android API".to_string(),
+        ))
+    }
+
+    async fn reject_peer(&self, _peer: MacAddress) -> Result<(), P2pError> {
+        Err(P2pError::Unsupported(
+            "Android answers connection requests through its own dialog".to_string(),
+        ))
+    }
+
+    async fn remove_groups(&self) -> Result<(), P2pError> {
+        // The framework reports a generic error when there is no group to remove.
+        match self.action("removeGroup").await {
+            Err(P2pError::Backend(_)) => Ok(()),
+            result => result,
+        }
+    }
+
+    async fn resolve_peer_ip(&self, _peer: MacAddress) -> Result<Option<IpAddr>, P2pError> {
+        Err(P2pError::Unsupported(
+            "Android does not expose the group's client addresses to apps".to_string(),
+        ))
+    }
+
+    async fn factory_reset(&self) -> Result<(), P2pError> {
+        Err(P2pError::Unsupported(
+            "Android only lets system apps reset Wi-Fi Direct".to_string(),
+        ))
+    }
+
+    async fn capabilities(&self) -> Result<P2pCapabilities, P2pError> {
+        let supported = {
+            let mut env = self.vm.attach_current_thread().map_err(jni_error)?;
+            let result = env.call_method(&self.bridge, "isP2pSupported", "()Z", &[]);
+            check_exception(&mut env, result)?.z().map_err(jni_error)?
+        };
+        Ok(P2pCapabilities {
+            p2p_supported: supported,
+            wps_methods: vec![
+                "push_button".to_string(),
+                "display".to_string(),
+                "keypad".to_string(),
+                "label".to_string(),
+            ],
+            modes: vec!["p2p".to_string(), "ap".to_string()],
+            ..P2pCapabilities::default()
+        })
+    }
+
+    async fn local_device(&self) -> Result<LocalDeviceInfo, P2pError> {
+        Err(P2pError::Unsupported(
+            "the local device is not wired to the Android API".to_string(),
+        ))
+    }
+
+    async fn set_device_name(&self, _name: String) -> Result<(), P2pError> {
+        Err(P2pError::Unsupported(
+            "Android only lets system apps rename the device".to_string(),
+        ))
+    }
+
+    async fn ping(&self) -> Result<(), P2pError> {
+        // A synchronous call into the bridge shows the Java side still answers.
+        let mut env = self.vm.attach_current_thread().map_err(jni_error)?;
+        let result = env.call_method(&self.bridge, "isP2pSupported", "()Z", &[]);
+        check_exception(&mut env, result)?;
+        Ok(())
+    }
+
+    async fn watch_events(&self, events: mpsc::Sender<P2pEvent>) -> Result<(), P2pError> {
+        // Events are pushed from the bridge's broadcast receiver; park until the manager goes away.
+        self.lock().events = Some(events.clone());
+        events.closed().await;
+        Ok(())
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // A panic on another thread must not wedge every later JNI callback.
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn bridge_state(handle: jlong) -> Option<Arc<Mutex<AndroidState>>> {
+    lock(&BRIDGES).get(&handle).and_then(Weak::upgrade)
+}
+
+fn jni_error(err: jni::errors::Error) -> P2pError {
+    P2pError::Backend(format!("JNI: {err}"))
+}
+
+fn check_exception<T>(env: &mut JNIEnv<'_>, result: jni::errors::Result<T>) -> Result<T, P2pError> {
+    // A pending Java exception would poison every later call on this thread.
+    if env.exception_check().unwrap_or(false) {
+        let _ = env.exception_describe();
+        let _ = env.exception_clear();
+    }
+    result.map_err(jni_error)
+}
+
+fn action_result(method: &str, reason: jint) -> Result<(), P2pError> {
+    match reason {
+        ACTION_SUCCESS => Ok(()),
+        REASON_P2P_UNSUPPORTED => Err(P2pError::Unsupported(format!(
+            "{method}: Wi-Fi Direct is not supported on this device"
+        ))),
+        REASON_BUSY => Err(P2pError::Busy(format!("{method}: framework is busy"))),
+        REASON_ERROR => Err(P2pError::Backend(format!(
+            "{method}: internal framework error"
+        ))),
+        other => Err(P2pError::Backend(format!(
+            "{method} failed with reason {other}"
+        ))),
+    }
+}
+
+fn read_string(env: &mut JNIEnv<'_>, object: &JObject<'_>, field: &str) -> Option<String> {
+    let value = env
+        .get_field(object, field, "Ljava/lang/String;")
+        .and_then(|value| value.l())
+        .ok()?;
+    if value.is_null() {
+        return None;
+    }
+    let value = JString::from(value);
+    let text: String = env.get_string(&value).ok()?.into();
+    Some(text).filter(|text| !text.is_empty())
+}
+
+fn read_device(env: &mut JNIEnv<'_>, object: &JObject<'_>) -> Option<P2pDevice> {
+    let address = read_string(env, object, "deviceAddress")?.parse().ok()?;
+    let mut device = P2pDevice::new(address);
+    device.device_name = read_string(env, object, "deviceName");
+    device.primary_type = read_string(env, object, "primaryDeviceType");
+    device.secondary_types = read_string(env, object, "secondaryDeviceType")
+        .into_iter()
+        .collect();
+    device.wps_config_methods = [
+        ("wpsPbcSupported", wps_config::PUSH_BUTTON),
+        ("wpsDisplaySupported", wps_config::DISPLAY),
+        ("wpsKeypadSupported", wps_config::KEYPAD),
+    ]
+    .into_iter()
+    .filter(|(method, _)| {
+        env.call_method(object, *method, "()Z", &[])
+            .and_then(|value| value.z())
+            .unwrap_or(false)
+    })
+    .fold(0, |methods, (_, bit)| methods | bit);
+    // WifiP2pDevice.CONNECTED, INVITED, FAILED, AVAILABLE, UNAVAILABLE.
+    device.status = match env
+        .get_field(object, "status", "I")
+        .and_then(|value| value.i())
+    {
+        Ok(0) => DeviceStatus::Connected,
+        Ok(1) => DeviceStatus::Invited,
+        Ok(2) => DeviceStatus::Failed,
+        Ok(4) => DeviceStatus::Unavailable,
+        _ => DeviceStatus::Available,
+    };
+    Some(device)
+}
+
+fn read_devices(env: &mut JNIEnv<'_>, peers: &JObjectArray<'_>) -> Vec<P2pDevice> {
+    let length = env.get_array_length(peers).unwrap_or(0);
+    let mut devices = Vec::new();
+    for index in 0..length {
+        let Ok(object) = env.get_object_array_element(peers, index) else {
+            continue;
+        };
+        if let Some(device) = read_device(env, &object) {
+            devices.push(device);
+        }
+        let _ = env.delete_local_ref(object);
+    }
+    devices
+}
+
+fn complete(handle: jlong, request: jlong, reply: Reply) {
+    let Some(state) = bridge_state(handle) else {
+        return;
+    };
+    if let Some(tx) = lock(&state).pending.remove(&request) {
+        let _ = tx.send(reply);
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_rs_wifip2p_WifiP2pBridge_nativeOnActionResult(
+    _env: JNIEnv<'_>,
+    _class: JClass<'_>,
+    handle: jlong,
+    request: jlong,
+    reason: jint,
+) {
+    complete(handle, request, Reply::Action(reason));
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_rs_wifip2p_WifiP2pBridge_nativeOnPeers(
+    mut env: JNIEnv<'_>,
+    _class: JClass<'_>,
+    handle: jlong,
+    request: jlong,
+    peers: JObjectArray<'_>,
+) {
+    let devices = read_devices(&mut env, &peers);
+    complete(handle, request, Reply::Peers(devices));
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_rs_wifip2p_WifiP2pBridge_nativeOnPeersChanged(
+    mut env: JNIEnv<'_>,
+    _class: JClass<'_>,
+    handle: jlong,
+    peers: JObjectArray<'_>,
+) {
+    let Some(state) = bridge_state(handle) else {
+        return;
+    };
+    let devices = read_devices(&mut env, &peers);
+    // The intent carries the full list; diff it into found/lost events.
+    let mut state = lock(&state);
+    let current: HashMap<MacAddress, P2pDevice> = devices
+        .into_iter()
+        .map(|device| (device.mac_address, device))
+        .collect();
+    let lost: Vec<MacAddress> = state
+        .peers
+        .keys()
+        .filter(|address| !current.contains_key(address))
+        .copied()
+        .collect();
+    for address in lost {
+        state.emit(P2pEvent::PeerLost(address));
+    }
+    for (address, device) in &current {
+        if state.peers.get(address) != Some(device) {
+            state.emit(P2pEvent::PeerFound(Arc::new(device.clone())));
+        }
+    }
+    state.peers = current;
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_rs_wifip2p_WifiP2pBridge_nativeOnDiscoveryChanged(
+    _env: JNIEnv<'_>,
+    _class: JClass<'_>,
+    handle: jlong,
+    started: jboolean,
+) {
+    // A started scan is reported by the manager when discover_peers succeeds.
+    if started == 0
+        && let Some(state) = bridge_state(handle)
+    {
+        lock(&state).emit(P2pEvent::DiscoveryStopped);
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_rs_wifip2p_WifiP2pBridge_nativeOnListenChanged(
+    _env: JNIEnv<'_>,
+    _class: JClass<'_>,
+    handle: jlong,
+    started: jboolean,
+) {
+    if let Some(state) = bridge_state(handle) {
+        let listen = if started == 0 {
+            ListenState::Stopped
+        } else {
+            ListenState::Started
+        };
+        lock(&state).emit(P2pEvent::ListenStateChanged(listen));
+    }
+}
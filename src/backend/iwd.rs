@@ -0,0 +1,396 @@
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use tokio_stream::StreamExt;
+use zbus::Connection;
+use zbus::fdo::ObjectManagerProxy;
+use zbus::proxy::CacheProperties;
+use zbus::zvariant::{ObjectPath, OwnedObjectPath};
+
+use crate::capabilities::P2pCapabilities;
+use crate::channel::P2pEvent;
+use crate::config::{ConnectConfig, DiscoveryOptions, DiscoveryType, WpsMethod};
+use crate::device::{DeviceStatus, LocalDeviceInfo, P2pDevice, wps_config};
+use crate::error::P2pError;
+use crate::group::P2pGroup;
+use crate::mac::MacAddress;
+
+use super::P2pBackend;
+
+mod proxy;
+
+use proxy::{IwdP2pDeviceProxy, IwdPeerProxy, IwdSimpleConfigurationProxy};
+
+const IWD_SERVICE: &str = "net.connman.iwd";
+const IWD_DEVICE_INTERFACE: &str = "net.connman.iwd.Device";
+const IWD_P2P_DEVICE_INTERFACE: &str = "net.connman.iwd.p2p.Device";
+const IWD_P2P_PEER_INTERFACE: &str = "net.connman.iwd.p2p.Peer";
+
+/// Backend for systems where iwd, not wpa_supplicant, owns the radio.
+///
+/// iwd only implements the P2P client role: group creation, Notice of Absence,
+/// vendor elements and raw calls report `P2pError::Unsupported`. WPS methods
+/// block until the connection completes, so a generated `PinDisplay` PIN is
+/// only returned once the peer has entered it.
+#[derive(Debug, Clone)]
+pub struct IwdBackend {
+    connection: Connection,
+    device_path: OwnedObjectPath,
+}
+
+impl IwdBackend {
+    /// Build a backend by locating the P2P device object that iwd exposes for
+    /// the adapter behind `interface_name` (e.g. "wlan0").
+    pub async fn new(connection: &Connection, interface_name: &str) -> Result<Self, P2pError> {
+        if interface_name.trim().is_empty() {
+            return Err(P2pError::InvalidInterface(interface_name.to_string()));
+        }
+        let device_path = Self::get_device_path(connection, interface_name).await?;
+        Ok(Self {
+            connection: connection.clone(),
+            device_path,
+        })
+    }
+
+    /// The system bus connection the backend talks to iwd over.
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    async fn get_device_path(
+        connection: &Connection,
+        interface_name: &str,
+    ) -> Result<OwnedObjectPath, P2pError> {
+        // The netdev object carries the interface name and points at its adapter;
+        // the P2P device is the adapter's "p2p" child.
+        let objects = Self::object_manager(connection)
+            .await?
+            .get_managed_objects()
+            .await?;
+        let adapter = objects
+            .values()
+            .filter_map(|interfaces| interfaces.get(IWD_DEVICE_INTERFACE))
+            .find(|props| {
+                props
+                    .get("Name")
+                    .and_then(|name| <&str>::try_from(name).ok())
+                    .is_some_and(|name| name == interface_name)
+            })
+            .and_then(|props| props.get("Adapter"))
+            .and_then(|adapter| <&ObjectPath<'_>>::try_from(adapter).ok())
+            .map(|adapter| adapter.to_string())
+            .ok_or_else(|| P2pError::InterfaceUnknown(interface_name.to_string()))?;
+        let path = OwnedObjectPath::try_from(format!("{adapter}/p2p"))?;
+        let has_p2p = objects
+            .get(&path)
+            .is_some_and(|interfaces| interfaces.contains_key(IWD_P2P_DEVICE_INTERFACE));
+        if !has_p2p {
+            return Err(P2pError::Unsupported(format!(
+                "iwd exposes no P2P device for {interface_name}"
+            )));
+        }
+        Ok(path)
+    }
+
+    async fn object_manager(connection: &Connection) -> Result<ObjectManagerProxy<'_>, P2pError> {
+        let proxy = ObjectManagerProxy::builder(connection)
+            .destination(IWD_SERVICE)?
+            .path("/")?
+            .build()
+            .await?;
+        Ok(proxy)
+    }
+
+    async fn device_proxy(&self) -> Result<IwdP2pDeviceProxy<'_>, P2pError> {
+        let proxy = IwdP2pDeviceProxy::builder(&self.connection)
+            .path(self.device_path.clone())?
+            .build()
+            .await?;
+        Ok(proxy)
+    }
+
+    async fn peer_proxy(&self, peer_path: OwnedObjectPath) -> Result<IwdPeerProxy<'_>, P2pError> {
+        // Peer objects are short-lived, so skip zbus's property cache and its signal match.
+        let proxy = IwdPeerProxy::builder(&self.connection)
+            .path(peer_path)?
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await?;
+        Ok(proxy)
+    }
+
+    fn peer_path(&self, address: &MacAddress) -> Result<OwnedObjectPath, P2pError> {
+        // Peer objects live under <p2p device>/p2p_peers/<address with underscores>.
+        let octets = address.octets().map(|byte| format!("{byte:02x}"));
+        let path = format!(
+            "{}/p2p_peers/{}",
+            self.device_path.as_str(),
+            octets.join("_")
+        );
+        Ok(OwnedObjectPath::try_from(path)?)
+    }
+
+    fn peer_address(&self, path: &str) -> Option<MacAddress> {
+        // Inverse of peer_path; anything outside our device's peer list is ignored.
+        let suffix = path.strip_prefix(self.device_path.as_str())?;
+        suffix
+            .strip_prefix("/p2p_peers/")?
+            .replace('_', ":")
+            .parse()
+            .ok()
+    }
+
+    async fn peer_device(
+        &self,
+        peer_path: OwnedObjectPath,
+        address: MacAddress,
+    ) -> Result<P2pDevice, P2pError> {
+        let proxy = self.peer_proxy(peer_path).await?;
+        let mut device = P2pDevice::new(address);
+        device.device_name = proxy.name().await.ok().filter(|name| !name.is_empty());
+        // iwd negotiates WPS itself and supports these methods for every peer.
+        device.wps_config_methods =
+            wps_config::PUSH_BUTTON | wps_config::DISPLAY | wps_config::KEYPAD;
+        if proxy.connected().await.unwrap_or(false) {
+            device.status = DeviceStatus::Connected;
+        }
+        Ok(device)
+    }
+}
+
+impl P2pBackend for IwdBackend {
+    async fn discover_peers(&self, options: DiscoveryOptions) -> Result<(), P2pError> {
+        if options.discovery_type != DiscoveryType::Full {
+            return Err(P2pError::Unsupported(
+                "iwd picks the discovery channels itself".to_string(),
+            ));
+        }
+        if !options.seek.is_empty() {
+            return Err(P2pError::Unsupported(
+                "iwd does not implement P2PS".to_string(),
+            ));
+        }
+        // Discovery stays active until released or until our bus name goes away.
+        self.device_proxy().await?.request_discovery().await?;
+        Ok(())
+    }
+
+    async fn stop_discovery(&self) -> Result<(), P2pError> {
+        self.device_proxy().await?.release_discovery().await?;
+        Ok(())
+    }
+
+    async fn start_listening(&self) -> Result<(), P2pError> {
+        Err(P2pError::Unsupported(
+            "iwd is only discoverable while discovery runs".to_string(),
+        ))
+    }
+
+    async fn start_listening_windowed(
+        &self,
+        _listen: Duration,
+        _every: Duration,
+    ) -> Result<(), P2pError> {
+        Err(P2pError::Unsupported(
+            "iwd is only discoverable while discovery runs".to_string(),
+        ))
+    }
+
+    async fn stop_listening(&self) -> Result<(), P2pError> {
+        Err(P2pError::Unsupported(
+            "iwd is only discoverable while discovery runs".to_string(),
+        ))
+    }
+
+    async fn connect(&self, config: ConnectConfig) -> Result<Option<String>, P2pError> {
+        // WPS runs on the peer object; the call returns once the connection is up.
+        let wps = IwdSimpleConfigurationProxy::builder(&self.connection)
+            .path(self.peer_path(&config.device_address)?)?
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await?;
+        match (config.wps_method, config.pin) {
+            (WpsMethod::Pbc, _) => {
+                wps.push_button().await?;
+                Ok(None)
+            }
+            (WpsMethod::PinDisplay, None) => {
+                let pin = wps.generate_pin().await?;
+                wps.start_pin(&pin).await?;
+                Ok(Some(pin))
+            }
+            (_, Some(pin)) => {
+                wps.start_pin(&pin).await?;
+                Ok(None)
+            }
+            (method, None) => Err(P2pError::InvalidConfig(format!(
+                "{} requires a PIN",
+                method.as_str()
+            ))),
+        }
+    }
+
+    async fn cancel_connect(&self) -> Result<(), P2pError> {
+        // iwd has no device-wide cancel; WPS is cancelled on the peer it runs on,
+        // and every other peer answers NotAvailable.
+        let proxy = self.device_proxy().await?;
+        for (path, _) in proxy.get_peers().await? {
+            let wps = IwdSimpleConfigurationProxy::builder(&self.connection)
+                .path(path)?
+                .cache_properties(CacheProperties::No)
+                .build()
+                .await?;
+            let _ = wps.cancel().await;
+        }
+        Ok(())
+    }
+
+    async fn request_peers(&self) -> Result<Vec<P2pDevice>, P2pError> {
+        let proxy = self.device_proxy().await?;
+        let known = proxy.get_peers().await?;
+        let mut peers = Vec::with_capacity(known.len());
+        for (path, strength) in known {
+            let Some(address) = self.peer_address(path.as_str()) else {
+                continue;
+            };
+            let mut device = self.peer_device(path, address).await?;
+            device.signal_level = Some(i32::from(strength) / 100);
+            peers.push(device);
+        }
+        Ok(peers)
+    }
+
+    async fn create_group(&self) -> Result<(), P2pError> {
+        Err(P2pError::Unsupported(
+            "iwd does not support acting as group owner".to_string(),
+        ))
+    }
+
+    async fn request_groups(&self) -> Result<Vec<P2pGroup>, P2pError> {
+        Err(P2pError::Unsupported(
+            "iwd does not report running groups".to_string(),
+        ))
+    }
+
+    async fn invite(&self, _peer: MacAddress) -> Result<(), P2pError> {
+        Err(P2pError::Unsupported(
+            "iwd cannot act as group owner".to_string(),
+        ))
+    }
+
+    async fn reject_peer(&self, _peer: MacAddress) -> Result<(), P2pError> {
+        Err(P2pError::Unsupported(
+            "iwd does not accept incoming connection requests".to_string(),
+        ))
+    }
+
+    async fn remove_groups(&self) -> Result<(), P2pError> {
+        // Without GO support our only groups are client connections to peers.
+        let proxy = self.device_proxy().await?;
+        for (path, _) in proxy.get_peers().await? {
+            let peer = self.peer_proxy(path).await?;
+            if peer.connected().await.unwrap_or(false) {
+                peer.disconnect().await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn resolve_peer_ip(&self, peer: MacAddress) -> Result<Option<IpAddr>, P2pError> {
+        // iwd runs DHCP on the group itself and publishes the peer's address once known.
+        let proxy = self.peer_proxy(self.peer_path(&peer)?).await?;
+        if !proxy.connected().await.unwrap_or(false) {
+            return Ok(None);
+        }
+        let ip = proxy.connected_ip().await.ok();
+        Ok(ip.and_then(|ip| ip.parse().ok()))
+    }
+
+    async fn factory_reset(&self) -> Result<(), P2pError> {
+        Err(P2pError::Unsupported(
+            "iwd keeps no persistent groups or services to reset".to_string(),
+        ))
+    }
+
+    async fn capabilities(&self) -> Result<P2pCapabilities, P2pError> {
+        // Reaching the P2P device object in `new` already proved P2P support.
+        let enabled = self.device_proxy().await?.enabled().await?;
+        Ok(P2pCapabilities {
+            p2p_supported: enabled,
+            wps_methods: vec![
+                "push_button".to_string(),
+                "display".to_string(),
+                "keypad".to_string(),
+            ],
+            modes: vec!["p2p".to_string()],
+            ..P2pCapabilities::default()
+        })
+    }
+
+    async fn local_device(&self) -> Result<LocalDeviceInfo, P2pError> {
+        // iwd keeps the device address and type to itself.
+        let name = self.device_proxy().await?.name().await?;
+        Ok(LocalDeviceInfo {
+            device_name: Some(name).filter(|name| !name.is_empty()),
+            wps_methods: vec![
+                "push_button".to_string(),
+                "display".to_string(),
+                "keypad".to_string(),
+            ],
+            ..LocalDeviceInfo::default()
+        })
+    }
+
+    async fn set_device_name(&self, name: String) -> Result<(), P2pError> {
+        self.device_proxy().await?.set_name(name).await?;
+        Ok(())
+    }
+
+    async fn ping(&self) -> Result<(), P2pError> {
+        self.device_proxy().await?.enabled().await?;
+        Ok(())
+    }
+
+    async fn watch_events(&self, events: mpsc::Sender<P2pEvent>) -> Result<(), P2pError> {
+        // iwd announces peers through the ObjectManager rather than P2P signals.
+        let manager = Self::object_manager(&self.connection).await?;
+        let mut added = manager.receive_interfaces_added().await?;
+        let mut removed = manager.receive_interfaces_removed().await?;
+
+        loop {
+            let event = tokio::select! {
+                Some(signal) = added.next() => {
+                    let args = signal.args()?;
+                    if !args.interfaces_and_properties.contains_key(IWD_P2P_PEER_INTERFACE) {
+                        continue;
+                    }
+                    let Some(address) = self.peer_address(args.object_path.as_str()) else {
+                        continue;
+                    };
+                    let path = OwnedObjectPath::from(args.object_path.clone());
+                    // The peer may vanish between the signal and the property reads.
+                    match self.peer_device(path, address).await {
+                        Ok(device) => P2pEvent::PeerFound(Arc::new(device)),
+                        Err(_) => continue,
+                    }
+                }
+                Some(signal) = removed.next() => {
+                    let args = signal.args()?;
+                    if !args.interfaces.contains(&IWD_P2P_PEER_INTERFACE) {
+                        continue;
+                    }
+                    match self.peer_address(args.object_path.as_str()) {
+                        Some(address) => P2pEvent::PeerLost(address),
+                        None => continue,
+                    }
+                }
+                else => return Ok(()),
+            };
+            if events.send(event).await.is_err() {
+                return Ok(());
+            }
+        }
+    }
+}
@@ -0,0 +1,75 @@
+//! Typed proxies for iwd's P2P D-Bus API (see `doc/p2p-*-api.txt` upstream).
+//!
+//! Peers are discovered through the standard ObjectManager on `/`, so only the
+//! iwd-specific interfaces are declared here.
+
+use zbus::proxy;
+use zbus::zvariant::OwnedObjectPath;
+
+#[proxy(
+    interface = "net.connman.iwd.p2p.Device",
+    default_service = "net.connman.iwd",
+    gen_blocking = false
+)]
+pub(crate) trait IwdP2pDevice {
+    fn request_discovery(&self) -> zbus::Result<()>;
+
+    fn release_discovery(&self) -> zbus::Result<()>;
+
+    /// Known peers with their signal strength in 100 * dBm.
+    fn get_peers(&self) -> zbus::Result<Vec<(OwnedObjectPath, i16)>>;
+
+    #[zbus(property)]
+    fn name(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn set_name(&self, name: String) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn enabled(&self) -> zbus::Result<bool>;
+
+    #[zbus(property)]
+    fn available_connections(&self) -> zbus::Result<u16>;
+}
+
+#[proxy(
+    interface = "net.connman.iwd.p2p.Peer",
+    default_service = "net.connman.iwd",
+    gen_blocking = false
+)]
+pub(crate) trait IwdPeer {
+    fn disconnect(&self) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn name(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn device_category(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn device_subcategory(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn connected(&self) -> zbus::Result<bool>;
+
+    #[zbus(property)]
+    fn connected_interface(&self) -> zbus::Result<String>;
+
+    #[zbus(property, name = "ConnectedIP")]
+    fn connected_ip(&self) -> zbus::Result<String>;
+}
+
+#[proxy(
+    interface = "net.connman.iwd.SimpleConfiguration",
+    default_service = "net.connman.iwd",
+    gen_blocking = false
+)]
+pub(crate) trait IwdSimpleConfiguration {
+    fn push_button(&self) -> zbus::Result<()>;
+
+    fn generate_pin(&self) -> zbus::Result<String>;
+
+    fn start_pin(&self, pin: &str) -> zbus::Result<()>;
+
+    fn cancel(&self) -> zbus::Result<()>;
+}
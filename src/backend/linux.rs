@@ -1,111 +1,1833 @@
 use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram as StdUnixDatagram};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
 
+use futures_util::future::join_all;
+use tokio::sync::mpsc;
+use tokio_stream::StreamExt;
 use zbus::Connection;
-use zbus::zvariant::{OwnedObjectPath, OwnedValue, Value};
+use zbus::fdo::{DBusProxy, NameOwnerChangedStream, PropertiesProxy};
+use zbus::names::{BusName, InterfaceName};
+use zbus::proxy::CacheProperties;
+use zbus::zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Structure, Value};
 
+use crate::approver::{ConnectionRequest, RequestKind};
+use crate::capabilities::P2pCapabilities;
+use crate::channel::P2pEvent;
+use crate::config::{ConnectConfig, DiscoveryOptions, DiscoveryType, WpsMethod};
+use crate::credentials::GroupCredentials;
+use crate::device::{LocalDeviceInfo, P2pDevice, format_device_type, parse_device_type};
 use crate::error::P2pError;
+use crate::group::{GroupRole, IpAllocation, IpAllocationPool, NoaSchedule, P2pGroup};
+use crate::mac::MacAddress;
+use crate::service::{AspProvision, AspService, LocalService, QUERY_ALL, ServiceResponse};
+use crate::stats::LinkStats;
+use crate::status::P2pStatusCode;
+use crate::vendor::VendorElementFrame;
+use crate::wfd::WfdDeviceInfo;
 
-use super::{P2pBackend, P2pFuture};
+use super::P2pBackend;
 
-const WPA_SUPPLICANT_DEST: &str = "fi.w1.wpa_supplicant1";
-const WPA_SUPPLICANT_PATH: &str = "/fi/w1/wpa_supplicant1";
-const WPA_SUPPLICANT_IFACE: &str = "fi.w1.wpa_supplicant1";
-const WPA_SUPPLICANT_P2P_IFACE: &str = "fi.w1.wpa_supplicant1.Interface.P2PDevice";
+mod proxy;
+
+use proxy::{
+    WpaGroupProxy, WpaInterfaceProxy, WpaP2pDeviceProxy, WpaPersistentGroupProxy,
+    WpaSupplicantProxy, WpaWpsProxy,
+};
+
+const SUPPLICANT_SERVICE: &str = "fi.w1.wpa_supplicant1";
+const PEER_INTERFACE: &str = "fi.w1.wpa_supplicant1.Peer";
+const WPA_CTRL_DIR: &str = "/var/run/wpa_supplicant";
+const NEIGHBOR_TABLE: &str = "/proc/net/arp";
+const WPA_CTRL_TIMEOUT: Duration = Duration::from_secs(5);
+/// WPS device password ids a GO negotiation request carries: the peer enters
+/// a PIN, or displays one.
+const DEV_PW_USER_SPECIFIED: u16 = 1;
+const DEV_PW_REGISTRAR_SPECIFIED: u16 = 5;
+/// Listen period and interval for `start_listening`, in milliseconds; Android
+/// uses the same for startListening.
+const EXT_LISTEN_MS: i32 = 500;
+
+static CTRL_SOCKET_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// How to register an interface wpa_supplicant does not manage yet
+/// (`CreateInterface`); see `WifiP2pManagerBuilder::interface_config`.
+#[derive(Debug, Clone)]
+pub struct InterfaceConfig {
+    /// Register the interface when `GetInterface` does not know it (default on).
+    pub create: bool,
+    /// Driver wrapper, e.g. `"nl80211"`; the supplicant's default when `None`.
+    pub driver: Option<String>,
+    /// Configuration file for the interface, e.g. one that keeps persistent groups.
+    pub config_file: Option<PathBuf>,
+    /// Remove an interface registered here from wpa_supplicant at shutdown (default off).
+    pub remove_on_shutdown: bool,
+    /// Open even when the interface does not exist yet, attach once it appears
+    /// and again after it is unplugged and plugged back in (default off).
+    #[cfg(feature = "hotplug")]
+    pub hotplug: bool,
+}
+
+impl Default for InterfaceConfig {
+    fn default() -> Self {
+        Self {
+            create: true,
+            driver: None,
+            config_file: None,
+            remove_on_shutdown: false,
+            #[cfg(feature = "hotplug")]
+            hotplug: false,
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct P2pBackendImpl {
     connection: Connection,
-    interface_path: OwnedObjectPath,
+    interface_name: String,
+    config: InterfaceConfig,
+    // The supplicant's objects for the interface; they change when it restarts or
+    // the interface is plugged back in.
+    paths: Arc<Mutex<Paths>>,
+    // Pool last passed to `set_ip_allocation`, reported on groups we own.
+    ip_allocation: Arc<Mutex<Option<IpAllocationPool>>>,
+    // Peer of the group we are about to own, from GO negotiation or a persistent
+    // group re-invocation; taken by the next GroupStarted.
+    forming_with: Arc<Mutex<Option<MacAddress>>>,
+}
+
+#[derive(Debug)]
+struct Paths {
+    // `None` until the first attach, with hot-plug before the interface exists.
+    interface: Option<OwnedObjectPath>,
+    // Where P2P methods and signals live: the dedicated P2P device (p2p-dev-<ifname>)
+    // when the driver has one, else the interface itself.
+    device: Option<OwnedObjectPath>,
+    // Whether we registered the interface with CreateInterface.
+    created: bool,
+    // Why the paths are out of date, if they are; resolved again before re-subscribing.
+    stale: Option<Detached>,
+    // Proxies built for the paths above, kept until they change.
+    p2p: Option<WpaP2pDeviceProxy<'static>>,
+    groups: HashMap<OwnedObjectPath, WpaP2pDeviceProxy<'static>>,
+    // Ids of the service queries sent from here, by peer (`None` for every peer).
+    service_requests: HashMap<Option<MacAddress>, u64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Detached {
+    /// The supplicant left the bus.
+    Supplicant,
+    /// The interface was unplugged, removed from the supplicant or not there yet.
+    Interface,
 }
 
 impl P2pBackendImpl {
     /// Build a backend by resolving the interface object path
     /// from wpa_supplicant using the provided interface name (e.g. "wlan0").
     pub async fn new(connection: &Connection, interface_name: &str) -> Result<Self, P2pError> {
+        let config = InterfaceConfig {
+            create: false,
+            ..InterfaceConfig::default()
+        };
+        Self::open(connection, interface_name, &config).await
+    }
+
+    /// Like `new`, but registers the interface with `CreateInterface` when
+    /// wpa_supplicant does not manage it yet and `config.create` is set.
+    pub async fn open(
+        connection: &Connection,
+        interface_name: &str,
+        config: &InterfaceConfig,
+    ) -> Result<Self, P2pError> {
         if interface_name.trim().is_empty() {
             return Err(P2pError::InvalidInterface(interface_name.to_string()));
         }
-        let interface_path = Self::get_interface_path(connection, interface_name).await?;
-        Ok(Self {
+        let backend = Self {
             connection: connection.clone(),
-            interface_path,
-        })
+            interface_name: interface_name.to_string(),
+            config: config.clone(),
+            paths: Arc::new(Mutex::new(Paths {
+                interface: None,
+                device: None,
+                created: false,
+                stale: Some(Detached::Interface),
+                p2p: None,
+                groups: HashMap::new(),
+                service_requests: HashMap::new(),
+            })),
+            ip_allocation: Arc::new(Mutex::new(None)),
+            forming_with: Arc::new(Mutex::new(None)),
+        };
+        // With hot-plug, `watch_events` attaches once the interface shows up.
+        #[cfg(feature = "hotplug")]
+        if config.hotplug && !Path::new("/sys/class/net").join(interface_name).exists() {
+            return Ok(backend);
+        }
+        match backend.attach(false).await {
+            #[cfg(feature = "hotplug")]
+            Err(P2pError::InterfaceUnknown(_)) if config.hotplug => Ok(backend),
+            result => result.map(|()| backend),
+        }
+    }
+
+    /// The wpa_supplicant object P2P calls go to: the interface's dedicated P2P
+    /// device (`p2p-dev-wlan0` for `wlan0`) when the driver has one. `None` until
+    /// a hot-plugged interface appears.
+    pub fn p2p_device_path(&self) -> Option<OwnedObjectPath> {
+        lock(&self.paths).device.clone()
+    }
+
+    fn device_path(&self) -> Result<OwnedObjectPath, P2pError> {
+        lock(&self.paths)
+            .device
+            .clone()
+            .ok_or_else(|| P2pError::InterfaceUnknown(self.interface_name.clone()))
+    }
+
+    fn interface_path(&self) -> Result<OwnedObjectPath, P2pError> {
+        lock(&self.paths)
+            .interface
+            .clone()
+            .ok_or_else(|| P2pError::InterfaceUnknown(self.interface_name.clone()))
+    }
+
+    /// Whether the backend registered the interface with wpa_supplicant.
+    pub fn created_interface(&self) -> bool {
+        lock(&self.paths).created
+    }
+
+    /// Unregister the interface from wpa_supplicant (`RemoveInterface`). The
+    /// backend cannot be used afterwards.
+    pub async fn remove_interface(&self) -> Result<(), P2pError> {
+        let proxy = WpaSupplicantProxy::new(&self.connection).await?;
+        let path = self.interface_path()?;
+        traced("RemoveInterface", proxy.remove_interface(&path.as_ref())).await?;
+        Ok(())
+    }
+
+    /// The system bus connection the backend talks to wpa_supplicant over.
+    pub fn connection(&self) -> &Connection {
+        &self.connection
     }
 
     async fn get_interface_path(
         connection: &Connection,
         interface_name: &str,
     ) -> Result<OwnedObjectPath, P2pError> {
-        let proxy =
-            zbus::Proxy::new(connection, WPA_SUPPLICANT_DEST, WPA_SUPPLICANT_PATH, WPA_SUPPLICANT_IFACE)
-                .await?;
+        let proxy = WpaSupplicantProxy::new(connection).await?;
 
         // The wpa_supplicant root object exposes GetInterface(ifname) -> object path.
-        let path: OwnedObjectPath = proxy.call("GetInterface", &(interface_name)).await?;
+        let path = traced("GetInterface", proxy.get_interface(interface_name)).await?;
         Ok(path)
     }
 
-    async fn p2p_proxy(&self) -> Result<zbus::Proxy<'_>, P2pError> {
-        // Create a fresh proxy per call to avoid lifetime gymnastics and
-        // keep each operation independent (important for async call ordering).
-        let proxy = zbus::Proxy::new(
-            &self.connection,
-            WPA_SUPPLICANT_DEST,
-            self.interface_path.clone(),
-            WPA_SUPPLICANT_P2P_IFACE,
-        )
-        .await?;
+    /// The dedicated P2P device wpa_supplicant created for `interface_name`, if any.
+    async fn find_p2p_device(
+        connection: &Connection,
+        interface_name: &str,
+    ) -> Result<Option<OwnedObjectPath>, P2pError> {
+        if interface_name.starts_with("p2p-dev-") {
+            return Ok(None);
+        }
+        // wpa_supplicant names it "p2p-dev-<ifname>", cut to the 15 bytes of IFNAMSIZ.
+        let mut name = format!("p2p-dev-{interface_name}");
+        while name.len() > 15 {
+            name.pop();
+        }
+        match Self::get_interface_path(connection, &name).await {
+            Ok(path) => Ok(Some(path)),
+            Err(P2pError::InterfaceUnknown(_)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Register `interface_name`, returning its path and whether we created it.
+    async fn create_interface_path(
+        connection: &Connection,
+        interface_name: &str,
+        config: &InterfaceConfig,
+    ) -> Result<(OwnedObjectPath, bool), P2pError> {
+        let proxy = WpaSupplicantProxy::new(connection).await?;
+        let mut args = HashMap::from([("Ifname", Value::from(interface_name))]);
+        if let Some(driver) = &config.driver {
+            args.insert("Driver", Value::from(driver.as_str()));
+        }
+        if let Some(config_file) = &config.config_file {
+            let config_file = config_file.to_string_lossy().into_owned();
+            args.insert("ConfigFile", Value::from(config_file));
+        }
+        match traced("CreateInterface", proxy.create_interface(args)).await {
+            Ok(path) => Ok((path, true)),
+            // Someone else registered it since GetInterface; use theirs.
+            Err(zbus::Error::MethodError(name, _, _))
+                if name.as_str() == "fi.w1.wpa_supplicant1.InterfaceExists" =>
+            {
+                let path = traced("GetInterface", proxy.get_interface(interface_name)).await?;
+                Ok((path, false))
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Point the backend at the supplicant's objects for the interface, then put
+    /// back the IP allocation pool. The first time, the interface is looked up
+    /// and registered if `config.create` allows; later (the supplicant restarted
+    /// or the interface came back) one we registered is registered again and
+    /// otherwise waited for. `wait` also waits the first time.
+    async fn attach(&self, wait: bool) -> Result<(), P2pError> {
+        let (first, recreate) = {
+            let paths = lock(&self.paths);
+            (paths.interface.is_none(), paths.created)
+        };
+        let connection = &self.connection;
+        let name = self.interface_name.as_str();
+        let (interface, created) = if recreate || (first && self.config.create) {
+            match Self::get_interface_path(connection, name).await {
+                Err(P2pError::InterfaceUnknown(_)) => {
+                    Self::create_interface_path(connection, name, &self.config).await?
+                }
+                path => (path?, recreate),
+            }
+        } else if first && !wait {
+            (Self::get_interface_path(connection, name).await?, false)
+        } else {
+            (self.wait_for_interface().await?, false)
+        };
+        let device = Self::find_p2p_device(connection, name)
+            .await?
+            .unwrap_or_else(|| interface.clone());
+        *lock(&self.paths) = Paths {
+            interface: Some(interface),
+            device: Some(device),
+            created,
+            stale: None,
+            p2p: None,
+            groups: HashMap::new(),
+            service_requests: HashMap::new(),
+        };
+        let pool = *lock(&self.ip_allocation);
+        if pool.is_some() {
+            self.set_ip_allocation(pool).await?;
+        }
+        Ok(())
+    }
+
+    /// The interface's path once the supplicant manages it again. The bus name
+    /// comes back before the interfaces given on its command line do.
+    async fn wait_for_interface(&self) -> Result<OwnedObjectPath, P2pError> {
+        let proxy = WpaSupplicantProxy::new(&self.connection).await?;
+        // Subscribe first, so an interface added right after GetInterface is not missed.
+        let mut added = proxy.receive_interface_added().await?;
+        match Self::get_interface_path(&self.connection, &self.interface_name).await {
+            Err(P2pError::InterfaceUnknown(_)) => {}
+            path => return path,
+        }
+        while let Some(signal) = added.next().await {
+            let args = signal.args()?;
+            let name = args
+                .properties
+                .get("Ifname")
+                .and_then(|name| <&str>::try_from(name).ok());
+            if name == Some(self.interface_name.as_str()) {
+                return Ok(args.path.into());
+            }
+        }
+        Err(P2pError::SupplicantUnavailable(
+            "wpa_supplicant left the bus".to_string(),
+        ))
+    }
+
+    /// Mark the interface gone and report it.
+    async fn detach(&self, events: &mpsc::Sender<P2pEvent>) -> Result<(), P2pError> {
+        lock(&self.paths).stale = Some(Detached::Interface);
+        let interface = self.interface_name.clone();
+        let _ = events.send(P2pEvent::AdapterRemoved { interface }).await;
+        Ok(())
+    }
+
+    /// Resolves when the interface's network device goes away, with hot-plug on.
+    async fn unplugged(&self) {
+        #[cfg(feature = "hotplug")]
+        if self.config.hotplug
+            && crate::net::rtnetlink::wait_for_link_removal(&self.interface_name)
+                .await
+                .is_ok()
+        {
+            return;
+        }
+        std::future::pending::<()>().await
+    }
+
+    async fn wait_for_owner(owners: &mut NameOwnerChangedStream<'_>) -> Result<(), P2pError> {
+        while let Some(signal) = owners.next().await {
+            if signal.args()?.new_owner.is_some() {
+                return Ok(());
+            }
+        }
+        Err(P2pError::SupplicantUnavailable(
+            "wpa_supplicant left the bus".to_string(),
+        ))
+    }
+
+    async fn p2p_proxy(&self) -> Result<WpaP2pDeviceProxy<'static>, P2pError> {
+        if let Some(proxy) = lock(&self.paths).p2p.clone() {
+            return Ok(proxy);
+        }
+        let path = self.device_path()?;
+        // Read properties on every call: the supplicant does not announce every change.
+        let proxy = WpaP2pDeviceProxy::builder(&self.connection)
+            .path(path.clone())?
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await?;
+        let mut paths = lock(&self.paths);
+        // Attached again while building: leave the slot to the new path.
+        if paths.device.as_ref() == Some(&path) {
+            paths.p2p = Some(proxy.clone());
+        }
+        Ok(proxy)
+    }
+
+    async fn interface_proxy(&self) -> Result<WpaInterfaceProxy<'_>, P2pError> {
+        // Interface-level methods (vendor elements, capabilities) live on the base interface.
+        let proxy = WpaInterfaceProxy::builder(&self.connection)
+            .path(self.interface_path()?)?
+            .build()
+            .await?;
         Ok(proxy)
     }
 
+    async fn wps_proxy(&self) -> Result<WpaWpsProxy<'_>, P2pError> {
+        let proxy = WpaWpsProxy::builder(&self.connection)
+            .path(self.device_path()?)?
+            .build()
+            .await?;
+        Ok(proxy)
+    }
+
+    fn peer_path(&self, address: &MacAddress) -> Result<OwnedObjectPath, P2pError> {
+        // Peer objects live under <P2P device>/Peers/<address without colons>.
+        let path = format!(
+            "{}/Peers/{}",
+            self.device_path()?.as_str(),
+            address.to_hex()
+        );
+        Ok(OwnedObjectPath::try_from(path)?)
+    }
+
+    fn peer_address(path: &str) -> Option<MacAddress> {
+        // Inverse of peer_path: the last path segment is the address in plain hex.
+        let hex = path.rsplit('/').next()?;
+        if hex.len() != 12 {
+            return None;
+        }
+        let mut bytes = [0u8; 6];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+        }
+        Some(MacAddress::new(bytes))
+    }
+
+    fn path_option(options: &HashMap<String, OwnedValue>, key: &str) -> Option<OwnedObjectPath> {
+        options
+            .get(key)
+            .and_then(|value| <&ObjectPath<'_>>::try_from(value).ok())
+            .map(|path| OwnedObjectPath::from(path.clone()))
+    }
+
+    fn mac_option(options: &HashMap<String, OwnedValue>, key: &str) -> Option<MacAddress> {
+        let bytes: Vec<u8> = options.get(key)?.try_clone().ok()?.try_into().ok()?;
+        MacAddress::try_from(bytes.as_slice()).ok()
+    }
+
+    fn request(
+        peer: MacAddress,
+        kind: RequestKind,
+        method: WpsMethod,
+        pin: Option<String>,
+    ) -> P2pEvent {
+        P2pEvent::IncomingRequest(ConnectionRequest {
+            peer,
+            kind,
+            method,
+            pin,
+        })
+    }
+
+    fn ipv4_option(options: &HashMap<String, OwnedValue>, key: &str) -> Option<Ipv4Addr> {
+        // Addresses in signal dictionaries are four-byte arrays in network order.
+        let bytes: Vec<u8> = options.get(key)?.try_clone().ok()?.try_into().ok()?;
+        <[u8; 4]>::try_from(bytes).ok().map(Ipv4Addr::from)
+    }
+
+    fn client_allocation(properties: &HashMap<String, OwnedValue>) -> Option<IpAllocation> {
+        // Present only when the GO allocated us an address in the 4-way handshake.
+        Some(IpAllocation {
+            ip: Self::ipv4_option(properties, "IpAddr")?,
+            mask: Self::ipv4_option(properties, "IpAddrMask")?,
+            go_ip: Self::ipv4_option(properties, "IpAddrGo")?,
+        })
+    }
+
+    fn group_role(role: &str) -> Option<GroupRole> {
+        // The supplicant reports "GO", "client" or "device" (not in a group).
+        match role {
+            "GO" => Some(GroupRole::Owner),
+            "client" => Some(GroupRole::Client),
+            _ => None,
+        }
+    }
+
+    fn status_option(options: &HashMap<String, OwnedValue>, key: &str) -> P2pStatusCode {
+        let code = options
+            .get(key)
+            .and_then(|value| i32::try_from(value).ok())
+            .unwrap_or(-1);
+        P2pStatusCode::from_code(code)
+    }
+
+    fn service_options(
+        service: &LocalService,
+        with_response: bool,
+    ) -> Result<HashMap<String, OwnedValue>, P2pError> {
+        // AddService and DeleteService take the same keys; only adding needs the
+        // Bonjour response.
+        let mut options = Self::empty_options();
+        let value = |value: Value<'_>| OwnedValue::try_from(value);
+        match service {
+            LocalService::Bonjour { query, response } => {
+                options.insert("service_type".to_string(), value("bonjour".into())?);
+                options.insert("query".to_string(), value(query.clone().into())?);
+                if with_response {
+                    options.insert("response".to_string(), value(response.clone().into())?);
+                }
+            }
+            LocalService::Upnp { version, service } => {
+                options.insert("service_type".to_string(), value("upnp".into())?);
+                options.insert("version".to_string(), value(i32::from(*version).into())?);
+                options.insert("service".to_string(), value(service.as_str().into())?);
+            }
+            LocalService::Asp(_) => {
+                return Err(P2pError::Unsupported(
+                    "ASP services are added through the control socket".to_string(),
+                ));
+            }
+        }
+        Ok(options)
+    }
+
+    fn asp_service_command(service: &AspService) -> Result<String, P2pError> {
+        // P2P_SERVICE_ADD asp <auto_accept> <adv_id> <state> <config_methods>
+        // <name> [svc_info='<info>'], numbers in hex.
+        if service.name.is_empty() || service.name.contains(char::is_whitespace) {
+            return Err(P2pError::InvalidArgs(format!(
+                "service name {:?} must be one word",
+                service.name
+            )));
+        }
+        let mut command = format!(
+            "P2P_SERVICE_ADD asp {} {:x} {:x} {:x} {}",
+            u8::from(service.auto_accept),
+            service.advertisement_id,
+            u8::from(service.available),
+            service.config_methods,
+            service.name
+        );
+        if let Some(info) = &service.info {
+            command.push_str(&format!(" svc_info={}", single_quoted(info)?));
+        }
+        Ok(command)
+    }
+
+    fn persistent_credentials(
+        path: &str,
+        properties: &HashMap<String, OwnedValue>,
+    ) -> Option<GroupCredentials> {
+        // Network block values: strings are quoted, anything else is plain or hex.
+        let text = |key: &str| {
+            properties
+                .get(key)
+                .and_then(|value| <&str>::try_from(value).ok())
+        };
+        let ssid = match text("ssid")?.strip_prefix('"') {
+            Some(quoted) => quoted.strip_suffix('"')?.to_string(),
+            None => String::from_utf8(decode_hex(text("ssid")?)?).ok()?,
+        };
+        // Without a quoted passphrase or a raw key (hidden as "*" on some builds)
+        // there is nothing to re-invoke the group with.
+        let psk = text("psk")?;
+        let psk = match psk.strip_prefix('"') {
+            Some(quoted) => quoted.strip_suffix('"')?.to_string(),
+            None if psk.len() == 64 && decode_hex(psk).is_some() => psk.to_string(),
+            None => return None,
+        };
+        // A GO stores its clients' device addresses, a client the GO's as BSSID.
+        let (role, peer) = match text("mode") {
+            Some("3") => (
+                GroupRole::Owner,
+                text("p2p_client_list")?.split_whitespace().next()?,
+            ),
+            _ => (GroupRole::Client, text("bssid")?),
+        };
+        Some(GroupCredentials {
+            network_id: path.rsplit('/').next()?.parse().ok()?,
+            ssid,
+            psk,
+            peer: peer.parse().ok()?,
+            role,
+        })
+    }
+
+    async fn peer_device(&self, peer_path: OwnedObjectPath) -> Result<P2pDevice, P2pError> {
+        // One GetAll instead of a Get per property. Peer objects are short-lived,
+        // so skip zbus's property cache and its signal match.
+        let proxy = PropertiesProxy::builder(&self.connection)
+            .destination(SUPPLICANT_SERVICE)?
+            .path(peer_path)?
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await?;
+        let interface = InterfaceName::from_static_str_unchecked(PEER_INTERFACE);
+        let properties = &mut proxy.get_all(Some(interface).into()).await?;
+
+        let address: Vec<u8> = take_property(properties, "DeviceAddress")
+            .ok_or_else(|| P2pError::Backend("peer has no DeviceAddress".to_string()))?;
+        let mut device = P2pDevice::new(MacAddress::try_from(address.as_slice())?);
+        let mut text =
+            |name: &str| take_property::<String>(properties, name).filter(|s| !s.is_empty());
+        device.device_name = text("DeviceName");
+        device.manufacturer = text("Manufacturer");
+        device.model_name = text("ModelName");
+        device.model_number = text("ModelNumber");
+        device.serial_number = text("SerialNumber");
+        if let Some(primary) = take_property::<Vec<u8>>(properties, "PrimaryDeviceType") {
+            device.primary_type = format_device_type(&primary);
+        }
+        if let Some(secondary) = take_property::<Vec<Vec<u8>>>(properties, "SecondaryDeviceTypes") {
+            device.secondary_types = secondary
+                .iter()
+                .filter_map(|t| format_device_type(t))
+                .collect();
+        }
+        device.device_capability = take_property(properties, "devicecapability").unwrap_or(0);
+        device.group_capability = take_property(properties, "groupcapability").unwrap_or(0);
+        device.wps_config_methods = take_property(properties, "config_method").unwrap_or(0);
+        device.signal_level = take_property(properties, "level");
+        // "IEs" holds the WFD subelements the peer announced.
+        device.wfd = take_property::<Vec<u8>>(properties, "IEs")
+            .and_then(|subelements| WfdDeviceInfo::parse(&subelements));
+        Ok(device)
+    }
+
+    async fn interface_name_at(&self, path: OwnedObjectPath) -> Result<String, P2pError> {
+        // Group interfaces come and go with their group; don't cache their properties.
+        let proxy = WpaInterfaceProxy::builder(&self.connection)
+            .path(path)?
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await?;
+        Ok(proxy.ifname().await?)
+    }
+
+    async fn group_device_proxy(
+        &self,
+        path: OwnedObjectPath,
+    ) -> Result<WpaP2pDeviceProxy<'static>, P2pError> {
+        if let Some(proxy) = lock(&self.paths).groups.get(&path) {
+            return Ok(proxy.clone());
+        }
+        let proxy = WpaP2pDeviceProxy::builder(&self.connection)
+            .path(path.clone())?
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await?;
+        lock(&self.paths).groups.insert(path, proxy.clone());
+        Ok(proxy)
+    }
+
+    async fn signal_group(
+        &self,
+        properties: &HashMap<String, OwnedValue>,
+    ) -> Option<(OwnedObjectPath, P2pGroup)> {
+        // GroupStarted carries the group interface's object path and our role in it.
+        let path = Self::path_option(properties, "interface_object")?;
+        let role = properties
+            .get("role")
+            .and_then(|value| <&str>::try_from(value).ok())
+            .and_then(Self::group_role)?;
+        let interface = self.interface_name_at(path.clone()).await.ok()?;
+        let forming_with = lock(&self.forming_with).take();
+        let (ip_allocation, peer) = match role {
+            GroupRole::Client => (
+                Self::client_allocation(properties),
+                self.group_owner(path.clone()).await,
+            ),
+            // The signal carries nothing for the GO; use the pool we configured.
+            GroupRole::Owner => (
+                lock(&self.ip_allocation).map(|pool| IpAllocation {
+                    ip: pool.go_ip,
+                    mask: pool.mask,
+                    go_ip: pool.go_ip,
+                }),
+                forming_with,
+            ),
+        };
+        let group = P2pGroup {
+            interface,
+            role,
+            ip_allocation,
+            peer,
+        };
+        Some((path, group))
+    }
+
+    async fn active_groups(&self) -> Result<Vec<(OwnedObjectPath, P2pGroup)>, P2pError> {
+        // Each group runs on its own supplicant interface (or on ours when the driver
+        // has no separate group interfaces), with a P2P role of GO or client.
+        let root = WpaSupplicantProxy::new(&self.connection).await?;
+        let mut groups = Vec::new();
+        let interfaces = root.interfaces().await?;
+        // Drop proxies for group interfaces that are gone.
+        lock(&self.paths)
+            .groups
+            .retain(|path, _| interfaces.contains(path));
+        for path in interfaces {
+            let device = self.group_device_proxy(path.clone()).await?;
+            let role = device.role().await.ok();
+            let Some(role) = role.as_deref().and_then(Self::group_role) else {
+                continue;
+            };
+            let interface = self.interface_name_at(path.clone()).await?;
+            // Which peer a group we own was formed with is only known from the
+            // signals; as a client it is the group owner.
+            let peer = match role {
+                GroupRole::Client => self.group_owner(path.clone()).await,
+                GroupRole::Owner => None,
+            };
+            // Only the GroupStarted signal carries the allocated addresses.
+            let group = P2pGroup {
+                interface,
+                role,
+                ip_allocation: None,
+                peer,
+            };
+            groups.push((path, group));
+        }
+        Ok(groups)
+    }
+
+    /// The group owner's P2P device address, for a group we are a client of.
+    async fn group_owner(&self, path: OwnedObjectPath) -> Option<MacAddress> {
+        let device = self.group_device_proxy(path).await.ok()?;
+        Self::peer_address(device.peer_go().await.ok()?.as_str())
+    }
+
+    async fn group_owner_address(
+        &self,
+        path: OwnedObjectPath,
+        peer: MacAddress,
+    ) -> Result<Option<MacAddress>, P2pError> {
+        // As a client our only peer is the GO, whose interface address is the BSSID.
+        let device = self.group_device_proxy(path).await?;
+        let go = device.peer_go().await?;
+        if Self::peer_address(go.as_str()) != Some(peer) {
+            return Ok(None);
+        }
+        let group = WpaGroupProxy::builder(&self.connection)
+            .path(device.group().await?)?
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await?;
+        Ok(Some(MacAddress::try_from(group.bssid().await?.as_slice())?))
+    }
+
+    async fn station_address(&self, interface: &str, peer: MacAddress) -> Option<MacAddress> {
+        let devices = self.station_devices(interface).await;
+        devices
+            .into_iter()
+            .find_map(|(station, device)| (device == peer).then_some(station))
+    }
+
+    async fn station_devices(&self, interface: &str) -> HashMap<MacAddress, MacAddress> {
+        // As GO, STA-FIRST/STA-NEXT walk the associated stations; each entry starts
+        // with the station's interface address and lists its P2P device address.
+        let mut devices = HashMap::new();
+        let mut reply = self.ctrl_reply_on(interface, "STA-FIRST").await;
+        while let Ok(entry) = &reply {
+            let station = entry.lines().next().and_then(|line| line.parse().ok());
+            let Some(station): Option<MacAddress> = station else {
+                break;
+            };
+            let device = entry
+                .lines()
+                .find_map(|line| line.strip_prefix("p2p_device_addr="))
+                .and_then(|address| address.parse().ok());
+            devices.extend(device.map(|device| (station, device)));
+            let next = format!("STA-NEXT {station}");
+            reply = self.ctrl_reply_on(interface, &next).await;
+        }
+        devices
+    }
+
+    #[cfg(feature = "stats")]
+    async fn group_link_stats(
+        &self,
+        path: OwnedObjectPath,
+        group: &P2pGroup,
+    ) -> Result<Vec<LinkStats>, P2pError> {
+        let mut stations = crate::stats::nl80211::stations(&group.interface)
+            .map_err(|err| P2pError::Backend(format!("nl80211: {err}")))?;
+        // nl80211 knows stations by interface address; peers go by device address.
+        match group.role {
+            GroupRole::Owner => {
+                let devices = self.station_devices(&group.interface).await;
+                for station in &mut stations {
+                    if let Some(device) = devices.get(&station.peer) {
+                        station.peer = *device;
+                    }
+                }
+            }
+            GroupRole::Client => {
+                if let Some(go) = self.group_owner(path).await {
+                    for station in &mut stations {
+                        station.peer = go;
+                    }
+                }
+            }
+        }
+        Ok(stations)
+    }
+
+    /// Hand out addresses from `pool` in the 4-way handshake of groups we own
+    /// afterwards (P2P IP allocation), so clients need no DHCP. `None` turns it off.
+    pub async fn set_ip_allocation(&self, pool: Option<IpAllocationPool>) -> Result<(), P2pError> {
+        let unspecified = Ipv4Addr::UNSPECIFIED;
+        let values = match pool {
+            Some(pool) => [pool.go_ip, pool.mask, pool.start, pool.end],
+            // The supplicant only allocates while ip_addr_go is set.
+            None => [unspecified; 4],
+        };
+        let names = ["ip_addr_go", "ip_addr_mask", "ip_addr_start", "ip_addr_end"];
+        for (name, value) in names.iter().zip(values) {
+            self.ctrl_request(&format!("SET {name} {value}")).await?;
+        }
+        *lock(&self.ip_allocation) = pool;
+        Ok(())
+    }
+
+    /// Call any P2PDevice method that takes a{sv} options, for supplicant features
+    /// this crate does not wrap yet. Returns the reply arguments in order.
+    pub async fn call_raw(
+        &self,
+        method: &str,
+        options: HashMap<String, Value<'_>>,
+    ) -> Result<Vec<OwnedValue>, P2pError> {
+        let proxy = self.p2p_proxy().await?;
+        let reply = traced(method, proxy.inner().call_method(method, &(options))).await?;
+        let body = reply.body();
+        if body.is_empty() {
+            return Ok(Vec::new());
+        }
+        // Deserializing as a structure accepts any reply signature, one field per argument.
+        let fields: Structure<'_> = body.deserialize()?;
+        let values = fields
+            .into_fields()
+            .into_iter()
+            .map(OwnedValue::try_from)
+            .collect::<Result<_, _>>()?;
+        Ok(values)
+    }
+
+    /// Find only advertisers of `discovery.seek`; D-Bus Find cannot seek, the
+    /// control socket's P2P_FIND can. Device types are left to the manager.
+    async fn seek(&self, discovery: &DiscoveryOptions) -> Result<(), P2pError> {
+        let mut command = "P2P_FIND".to_string();
+        match discovery.discovery_type {
+            DiscoveryType::Full => {}
+            DiscoveryType::Social => command.push_str(" type=social"),
+            DiscoveryType::Progressive => command.push_str(" type=progressive"),
+            DiscoveryType::SingleFrequency(freq) => command.push_str(&format!(" freq={freq}")),
+        }
+        for name in &discovery.seek {
+            command.push_str(&format!(" seek={name}"));
+        }
+        self.ctrl_request(&command).await?;
+        Ok(())
+    }
+
+    async fn ctrl_request(&self, command: &str) -> Result<String, P2pError> {
+        self.ctrl_request_on(&self.interface_name, command).await
+    }
+
+    async fn ctrl_request_on(&self, interface: &str, command: &str) -> Result<String, P2pError> {
+        let reply = self.ctrl_reply_on(interface, command).await?;
+        if reply.starts_with("FAIL") || reply.starts_with("UNKNOWN COMMAND") {
+            return Err(P2pError::Backend(format!("{command}: {reply}")));
+        }
+        Ok(reply)
+    }
+
+    async fn ctrl_reply(&self, command: &str) -> Result<String, P2pError> {
+        self.ctrl_reply_on(&self.interface_name, command).await
+    }
+
+    async fn ctrl_reply_on(&self, interface: &str, command: &str) -> Result<String, P2pError> {
+        // Some P2P knobs (e.g. NoA) are only reachable through the control socket,
+        // not D-Bus. Bind an abstract client address so the supplicant can reply.
+        let name = format!(
+            "wifi-p2p-rs-{}-{}",
+            std::process::id(),
+            CTRL_SOCKET_COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+        let local = SocketAddr::from_abstract_name(name.as_bytes()).map_err(ctrl_io_err)?;
+        let socket = StdUnixDatagram::bind_addr(&local).map_err(ctrl_io_err)?;
+        socket.set_nonblocking(true).map_err(ctrl_io_err)?;
+        socket
+            .connect(Path::new(WPA_CTRL_DIR).join(interface))
+            .map_err(ctrl_io_err)?;
+
+        let mut buf = vec![0u8; 4096];
+        let len = ctrl_exchange(socket, command, &mut buf).await?;
+        Ok(String::from_utf8_lossy(&buf[..len]).trim_end().to_string())
+    }
+
+    fn parse_channels(reply: &str) -> Vec<u16> {
+        // GET_CAPABILITY channels prints one "Mode[X] Channels: 1 2 3" line per band.
+        let mut channels: Vec<u16> = reply
+            .lines()
+            .filter_map(|line| line.split_once("Channels:"))
+            .flat_map(|(_, list)| list.split_whitespace())
+            .filter_map(|channel| channel.parse().ok())
+            .collect();
+        channels.sort_unstable();
+        channels.dedup();
+        channels
+    }
+
     fn empty_options() -> HashMap<String, OwnedValue> {
         // Most P2P D-Bus methods accept a{sv} options; this starts with defaults.
         HashMap::new()
     }
 }
 
+fn neighbor_ipv4(interface: &str, addresses: &[MacAddress]) -> Option<Ipv4Addr> {
+    // One "IP address, HW type, Flags, HW address, Mask, Device" row per entry after
+    // the header; flags 0x0 marks an entry whose ARP request went unanswered.
+    let table = std::fs::read_to_string(NEIGHBOR_TABLE).ok()?;
+    table.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [ip, _, flags, hardware, _, device] = fields[..] else {
+            return None;
+        };
+        if device != interface || flags == "0x0" {
+            return None;
+        }
+        let hardware: MacAddress = hardware.parse().ok()?;
+        addresses.contains(&hardware).then(|| ip.parse().ok())?
+    })
+}
+
+fn neighbor_ipv6(interface: &str, addresses: &[MacAddress]) -> Option<Ipv6Addr> {
+    // There is no /proc table for IPv6 neighbours; `ip -6 neighbour` prints one
+    // "fe80::... lladdr 02:11:22:33:44:55 STALE" line per entry, without "lladdr"
+    // for entries that never resolved.
+    let output = std::process::Command::new("ip")
+        .args(["-6", "neighbour", "show", "dev", interface])
+        .output()
+        .ok()?;
+    let table = String::from_utf8_lossy(&output.stdout);
+    table.lines().find_map(|line| {
+        let mut words = line.split_whitespace();
+        let ip: Ipv6Addr = words.next()?.parse().ok()?;
+        words.find(|word| *word == "lladdr")?;
+        let hardware: MacAddress = words.next()?.parse().ok()?;
+        (ip.is_unicast_link_local() && addresses.contains(&hardware)).then_some(ip)
+    })
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Remove `name` from a `GetAll` reply as a `T`; `None` when missing or of another type.
+fn take_property<T: TryFrom<OwnedValue>>(
+    properties: &mut HashMap<String, OwnedValue>,
+    name: &str,
+) -> Option<T> {
+    properties
+        .remove(name)
+        .and_then(|value| T::try_from(value).ok())
+}
+
+/// Log a supplicant method call and how it went at debug level, to tell a slow
+/// supplicant from a slow peer.
+async fn traced<T>(method: &str, call: impl Future<Output = zbus::Result<T>>) -> zbus::Result<T> {
+    let started = Instant::now();
+    let result = call.await;
+    let elapsed_ms = started.elapsed().as_millis() as u64;
+    match &result {
+        Ok(_) => tracing::debug!(method, elapsed_ms, "D-Bus call succeeded"),
+        Err(err) => tracing::debug!(method, elapsed_ms, error = %err, "D-Bus call failed"),
+    }
+    result
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// `text` quoted for the control socket, which has no escapes.
+fn single_quoted(text: &str) -> Result<String, P2pError> {
+    if text.contains('\'') {
+        return Err(P2pError::InvalidArgs(format!(
+            "{text:?} must not contain single quotes"
+        )));
+    }
+    Ok(format!("'{text}'"))
+}
+
+fn ctrl_io_err(err: std::io::Error) -> P2pError {
+    P2pError::Backend(format!("control socket: {err}"))
+}
+
+fn ctrl_no_reply(command: &str) -> P2pError {
+    P2pError::Backend(format!("control socket: no reply to {command}"))
+}
+
+#[cfg(feature = "tokio")]
+async fn ctrl_exchange(
+    socket: StdUnixDatagram,
+    command: &str,
+    buf: &mut [u8],
+) -> Result<usize, P2pError> {
+    let socket = tokio::net::UnixDatagram::from_std(socket).map_err(ctrl_io_err)?;
+    socket.send(command.as_bytes()).await.map_err(ctrl_io_err)?;
+    tokio::time::timeout(WPA_CTRL_TIMEOUT, socket.recv(buf))
+        .await
+        .map_err(|_| ctrl_no_reply(command))?
+        .map_err(ctrl_io_err)
+}
+
+#[cfg(all(feature = "async-io", not(feature = "tokio")))]
+async fn ctrl_exchange(
+    socket: StdUnixDatagram,
+    command: &str,
+    buf: &mut [u8],
+) -> Result<usize, P2pError> {
+    // async-io drives the socket for async-std, smol and other non-tokio executors.
+    let socket = async_io::Async::new(socket).map_err(ctrl_io_err)?;
+    socket.send(command.as_bytes()).await.map_err(ctrl_io_err)?;
+    tokio::select! {
+        biased;
+        len = socket.recv(buf) => len.map_err(ctrl_io_err),
+        _ = async_io::Timer::after(WPA_CTRL_TIMEOUT) => Err(ctrl_no_reply(command)),
+    }
+}
+
 impl P2pBackend for P2pBackendImpl {
-    fn discover_peers(&self) -> P2pFuture<'_, ()> {
-        Box::pin(async move {
-            let proxy = self.p2p_proxy().await?;
-            // Maps to p2p_find; options follow wpa_supplicant's a{sv} signature.
-            let options = Self::empty_options();
-            let _: () = proxy.call("Find", &(options)).await?;
-            Ok(())
-        })
+    async fn discover_peers(&self, discovery: DiscoveryOptions) -> Result<(), P2pError> {
+        if !discovery.seek.is_empty() {
+            return self.seek(&discovery).await;
+        }
+        let proxy = self.p2p_proxy().await?;
+        // Maps to p2p_find; options follow wpa_supplicant's a{sv} signature.
+        let mut options = Self::empty_options();
+        let kind = match discovery.discovery_type {
+            DiscoveryType::Full | DiscoveryType::SingleFrequency(_) => "start_with_full",
+            DiscoveryType::Social => "social",
+            DiscoveryType::Progressive => "progressive",
+        };
+        options.insert(
+            "DiscoveryType".to_string(),
+            OwnedValue::try_from(Value::from(kind))?,
+        );
+        if let DiscoveryType::SingleFrequency(freq) = discovery.discovery_type {
+            let freq = i32::try_from(freq)
+                .map_err(|_| P2pError::InvalidArgs(format!("frequency {freq} MHz")))?;
+            options.insert("freq".to_string(), OwnedValue::from(freq));
+        }
+        if !discovery.device_types.is_empty() {
+            let types = discovery
+                .device_types
+                .iter()
+                .map(|device_type| {
+                    parse_device_type(device_type)
+                        .map(|bytes| bytes.to_vec())
+                        .ok_or_else(|| {
+                            P2pError::InvalidArgs(format!("device type {device_type:?}"))
+                        })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            options.insert(
+                "RequestedDeviceTypes".to_string(),
+                OwnedValue::try_from(Value::from(types))?,
+            );
+        }
+        traced("Find", proxy.find(options)).await?;
+        Ok(())
     }
 
-    fn stop_discovery(&self) -> P2pFuture<'_, ()> {
-        Box::pin(async move {
-            let proxy = self.p2p_proxy().await?;
-            // Maps to p2p_stop_find.
-            let _: () = proxy.call("StopFind", &()).await?;
-            Ok(())
-        })
+    async fn stop_discovery(&self) -> Result<(), P2pError> {
+        let proxy = self.p2p_proxy().await?;
+        // Maps to p2p_stop_find.
+        traced("StopFind", proxy.stop_find()).await?;
+        Ok(())
+    }
+
+    async fn start_listening(&self) -> Result<(), P2pError> {
+        let proxy = self.p2p_proxy().await?;
+        // Maps to p2p_ext_listen, which keeps listening between scans.
+        let mut options = Self::empty_options();
+        options.insert("period".to_string(), OwnedValue::from(EXT_LISTEN_MS));
+        options.insert("interval".to_string(), OwnedValue::from(EXT_LISTEN_MS));
+        traced("ExtendedListen", proxy.extended_listen(options)).await?;
+        Ok(())
+    }
+
+    async fn start_listening_windowed(
+        &self,
+        listen: Duration,
+        every: Duration,
+    ) -> Result<(), P2pError> {
+        // p2p_ext_listen takes both in milliseconds, up to 65535.
+        let millis = |duration: Duration| {
+            u16::try_from(duration.as_millis())
+                .map(i32::from)
+                .map_err(|_| {
+                    P2pError::InvalidArgs("listen windows are at most 65535ms".to_string())
+                })
+        };
+        let proxy = self.p2p_proxy().await?;
+        let mut options = Self::empty_options();
+        options.insert("period".to_string(), OwnedValue::from(millis(listen)?));
+        options.insert("interval".to_string(), OwnedValue::from(millis(every)?));
+        traced("ExtendedListen", proxy.extended_listen(options)).await?;
+        Ok(())
+    }
+
+    async fn stop_listening(&self) -> Result<(), P2pError> {
+        let proxy = self.p2p_proxy().await?;
+        // A zero period turns extended listen off; StopFind ends the current one.
+        let options = Self::empty_options();
+        traced("ExtendedListen", proxy.extended_listen(options)).await?;
+        traced("StopFind", proxy.stop_find()).await?;
+        Ok(())
+    }
+
+    async fn connect(&self, config: ConnectConfig) -> Result<Option<String>, P2pError> {
+        let proxy = self.p2p_proxy().await?;
+        // Maps to p2p_connect; "peer" must be the peer's object path.
+        let mut options = Self::empty_options();
+        let peer = OwnedValue::try_from(Value::from(self.peer_path(&config.device_address)?))?;
+        let wps = OwnedValue::try_from(Value::from(config.wps_method.as_str()))?;
+        options.insert("peer".to_string(), peer);
+        options.insert("wps_method".to_string(), wps);
+        if let Some(pin) = &config.pin {
+            let pin = OwnedValue::try_from(Value::from(pin.as_str()))?;
+            options.insert("pin".to_string(), pin);
+        }
+        // Connect replies with the PIN it generated, or an empty string.
+        let generated = traced("Connect", proxy.connect(options)).await?;
+        if config.pin.is_none() && !generated.is_empty() {
+            Ok(Some(generated))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn cancel_connect(&self) -> Result<(), P2pError> {
+        // Maps to p2p_cancel; the pending attempt ends with GONegotiationFailure or
+        // GroupFormationFailure.
+        let proxy = self.p2p_proxy().await?;
+        traced("Cancel", proxy.cancel()).await?;
+        Ok(())
+    }
+
+    async fn request_peers(&self) -> Result<Vec<P2pDevice>, P2pError> {
+        let proxy = self.p2p_proxy().await?;
+        // Maps to p2p_peers, then reads each Peer object's properties.
+        let paths = proxy.peers().await?;
+        // Concurrently: zbus pipelines the calls over the one connection. A peer
+        // may vanish between the listing and its property reads; leave it out.
+        let devices = join_all(paths.into_iter().map(|path| self.peer_device(path))).await;
+        Ok(devices.into_iter().filter_map(Result::ok).collect())
+    }
+
+    async fn create_group(&self) -> Result<(), P2pError> {
+        let proxy = self.p2p_proxy().await?;
+        // Maps to p2p_group_add.
+        let options = Self::empty_options();
+        *lock(&self.forming_with) = None;
+        traced("GroupAdd", proxy.group_add(options)).await?;
+        Ok(())
+    }
+
+    async fn request_groups(&self) -> Result<Vec<P2pGroup>, P2pError> {
+        let groups = self.active_groups().await?;
+        Ok(groups.into_iter().map(|(_, group)| group).collect())
+    }
+
+    async fn invite(&self, peer: MacAddress) -> Result<(), P2pError> {
+        // Without a persistent group object the supplicant invites to the group
+        // running on the interface the call is made on, so call it on the group's.
+        let Some((path, _)) = self
+            .active_groups()
+            .await?
+            .into_iter()
+            .find(|(_, group)| group.role == GroupRole::Owner)
+        else {
+            return Err(P2pError::NotConnected(
+                "no group we own to invite into".to_string(),
+            ));
+        };
+        let device = self.group_device_proxy(path).await?;
+        let mut options = Self::empty_options();
+        let peer = OwnedValue::try_from(Value::from(self.peer_path(&peer)?))?;
+        options.insert("peer".to_string(), peer);
+        traced("Invite", device.invite(options)).await?;
+        Ok(())
+    }
+
+    async fn reject_peer(&self, peer: MacAddress) -> Result<(), P2pError> {
+        let proxy = self.p2p_proxy().await?;
+        let peer = self.peer_path(&peer)?;
+        traced("RejectPeer", proxy.reject_peer(&peer.as_ref())).await?;
+        Ok(())
+    }
+
+    async fn remove_groups(&self) -> Result<(), P2pError> {
+        // Maps to p2p_group_remove *; the supplicant answers FAIL when there is no group.
+        let reply = self.ctrl_reply("P2P_GROUP_REMOVE *").await?;
+        if reply.starts_with("UNKNOWN COMMAND") {
+            return Err(P2pError::Backend(format!("P2P_GROUP_REMOVE *: {reply}")));
+        }
+        Ok(())
+    }
+
+    async fn set_noa(&self, schedule: NoaSchedule) -> Result<(), P2pError> {
+        // Maps to p2p_set noa <count>,<start_ms>,<duration_ms>, which the supplicant
+        // only applies on the group interface of a group we own. It has no
+        // interval: absences repeat every beacon.
+        schedule.check_interval()?;
+        let groups = self.active_groups().await?;
+        let Some((_, group)) = groups
+            .iter()
+            .find(|(_, group)| group.role == GroupRole::Owner)
+        else {
+            return Err(P2pError::NotConnected("we own no group".to_string()));
+        };
+        let command = format!(
+            "P2P_SET noa {},{},{}",
+            schedule.count,
+            schedule.start.as_millis(),
+            schedule.duration.as_millis()
+        );
+        self.ctrl_request_on(&group.interface, &command).await?;
+        Ok(())
+    }
+
+    async fn call_raw(
+        &self,
+        method: String,
+        options: HashMap<String, OwnedValue>,
+    ) -> Result<Vec<OwnedValue>, P2pError> {
+        let options = options
+            .into_iter()
+            .map(|(key, value)| (key, Value::from(value)))
+            .collect();
+        P2pBackendImpl::call_raw(self, &method, options).await
+    }
+
+    async fn set_vendor_elements(
+        &self,
+        frame: VendorElementFrame,
+        elements: Vec<u8>,
+    ) -> Result<(), P2pError> {
+        let proxy = self.interface_proxy().await?;
+        // Maps to vendor_elem_remove <frame> * followed by vendor_elem_add.
+        let frame = frame.id();
+        traced(
+            "VendorElementRemove",
+            proxy.vendor_element_remove(frame, &[]),
+        )
+        .await?;
+        if !elements.is_empty() {
+            traced(
+                "VendorElementAdd",
+                proxy.vendor_element_add(frame, &elements),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn set_wfd_info(&self, info: Option<WfdDeviceInfo>) -> Result<(), P2pError> {
+        // Wi-Fi Display is global to the supplicant and only set on the control socket.
+        let Some(info) = info else {
+            self.ctrl_request("SET wifi_display 0").await?;
+            return Ok(());
+        };
+        // WFD_SUBELEM_SET takes the subelement's length and body after its id.
+        let body: String = info.subelement()[1..]
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        self.ctrl_request(&format!("WFD_SUBELEM_SET 0 {body}"))
+            .await?;
+        self.ctrl_request("SET wifi_display 1").await?;
+        Ok(())
+    }
+
+    async fn resolve_peer_ip(&self, peer: MacAddress) -> Result<Option<IpAddr>, P2pError> {
+        for (path, group) in self.active_groups().await? {
+            // The neighbour table is keyed by interface addresses, which differ from
+            // the P2P device address when the peer runs a separate group interface.
+            let mut addresses = vec![peer];
+            let interface_address = match group.role {
+                GroupRole::Owner => self.station_address(&group.interface, peer).await,
+                GroupRole::Client => self.group_owner_address(path, peer).await?,
+            };
+            addresses.extend(interface_address);
+            if let Some(ip) = neighbor_ipv4(&group.interface, &addresses) {
+                return Ok(Some(IpAddr::V4(ip)));
+            }
+            // Groups addressed over IPv6 link-local only (see `net::link_local`).
+            if let Some(ip) = neighbor_ipv6(&group.interface, &addresses) {
+                return Ok(Some(IpAddr::V6(ip)));
+            }
+        }
+        Ok(None)
+    }
+
+    async fn link_stats(&self) -> Result<Vec<LinkStats>, P2pError> {
+        #[cfg(feature = "stats")]
+        {
+            let mut stats = Vec::new();
+            for (path, group) in self.active_groups().await? {
+                stats.extend(self.group_link_stats(path, &group).await?);
+            }
+            Ok(stats)
+        }
+        #[cfg(not(feature = "stats"))]
+        Err(P2pError::Unsupported(
+            "link statistics need the `stats` feature".to_string(),
+        ))
+    }
+
+    async fn persistent_groups(&self) -> Result<Vec<GroupCredentials>, P2pError> {
+        let proxy = self.p2p_proxy().await?;
+        let mut groups = Vec::new();
+        for path in proxy.persistent_groups().await? {
+            let group = WpaPersistentGroupProxy::builder(&self.connection)
+                .path(path.clone())?
+                .cache_properties(CacheProperties::No)
+                .build()
+                .await?;
+            let properties = group.properties().await?;
+            groups.extend(Self::persistent_credentials(path.as_str(), &properties));
+        }
+        Ok(groups)
+    }
+
+    async fn add_persistent_group(&self, credentials: GroupCredentials) -> Result<u32, P2pError> {
+        let proxy = self.p2p_proxy().await?;
+        // String values are quoted by the supplicant (except addresses); a byte
+        // array becomes hex, which is how a raw PSK is stored.
+        let mut options = Self::empty_options();
+        let value = |value: Value<'_>| OwnedValue::try_from(value);
+        options.insert("ssid".to_string(), value(credentials.ssid.as_str().into())?);
+        let psk = if credentials.is_raw_psk() {
+            let key = decode_hex(&credentials.psk).unwrap_or_default();
+            value(key.into())?
+        } else {
+            value(credentials.psk.as_str().into())?
+        };
+        options.insert("psk".to_string(), psk);
+        let peer = credentials.peer.to_string();
+        match credentials.role {
+            GroupRole::Owner => {
+                options.insert("mode".to_string(), value(3u32.into())?);
+                options.insert("p2p_client_list".to_string(), value(peer.as_str().into())?);
+            }
+            GroupRole::Client => {
+                options.insert("bssid".to_string(), value(peer.as_str().into())?);
+            }
+        }
+        let path = traced("AddPersistentGroup", proxy.add_persistent_group(options)).await?;
+        path.as_str()
+            .rsplit('/')
+            .next()
+            .and_then(|id| id.parse().ok())
+            .ok_or_else(|| P2pError::Backend(format!("unexpected persistent group {path}")))
+    }
+
+    async fn invoke_persistent_group(
+        &self,
+        network_id: u32,
+        peer: MacAddress,
+    ) -> Result<(), P2pError> {
+        let proxy = self.p2p_proxy().await?;
+        // Maps to p2p_invite persistent=<id> peer=<address>; the peer has to be in
+        // the supplicant's peer table, i.e. found by a recent discovery.
+        let group = format!(
+            "{}/PersistentGroups/{network_id}",
+            self.device_path()?.as_str()
+        );
+        let mut options = Self::empty_options();
+        let invited = peer;
+        let peer = OwnedValue::try_from(Value::from(self.peer_path(&peer)?))?;
+        let group = OwnedValue::try_from(Value::from(OwnedObjectPath::try_from(group)?))?;
+        options.insert("peer".to_string(), peer);
+        options.insert("persistent_group_object".to_string(), group);
+        traced("Invite", proxy.invite(options)).await?;
+        *lock(&self.forming_with) = Some(invited);
+        Ok(())
+    }
+
+    async fn factory_reset(&self) -> Result<(), P2pError> {
+        let proxy = self.p2p_proxy().await?;
+        traced(
+            "RemoveAllPersistentGroups",
+            proxy.remove_all_persistent_groups(),
+        )
+        .await?;
+        traced("FlushService", proxy.flush_service()).await?;
+        // Also stops discovery and drops pending service queries with the peers.
+        traced("Flush", proxy.flush()).await?;
+        lock(&self.paths).service_requests.clear();
+        Ok(())
+    }
+
+    async fn add_service(&self, service: LocalService) -> Result<(), P2pError> {
+        if let LocalService::Asp(service) = &service {
+            self.ctrl_request(&Self::asp_service_command(service)?)
+                .await?;
+            return Ok(());
+        }
+        let proxy = self.p2p_proxy().await?;
+        let options = Self::service_options(&service, true)?;
+        traced("AddService", proxy.add_service(options)).await?;
+        Ok(())
+    }
+
+    async fn remove_service(&self, service: LocalService) -> Result<(), P2pError> {
+        if let LocalService::Asp(service) = &service {
+            let command = format!("P2P_SERVICE_DEL asp {:x}", service.advertisement_id);
+            self.ctrl_request(&command).await?;
+            return Ok(());
+        }
+        let proxy = self.p2p_proxy().await?;
+        let options = Self::service_options(&service, false)?;
+        traced("DeleteService", proxy.delete_service(options)).await?;
+        Ok(())
+    }
+
+    async fn discover_services(&self, peer: Option<MacAddress>) -> Result<(), P2pError> {
+        let proxy = self.p2p_proxy().await?;
+        // Without a peer object the query goes to every peer found from now on.
+        let mut options = Self::empty_options();
+        if let Some(peer) = peer {
+            let peer = OwnedValue::try_from(Value::from(self.peer_path(&peer)?))?;
+            options.insert("peer_object".to_string(), peer);
+        }
+        let tlv = OwnedValue::try_from(Value::from(QUERY_ALL.to_vec()))?;
+        options.insert("tlv".to_string(), tlv);
+        let id = traced(
+            "ServiceDiscoveryRequest",
+            proxy.service_discovery_request(options),
+        )
+        .await?;
+        let replaced = lock(&self.paths).service_requests.insert(peer, id);
+        if let Some(replaced) = replaced {
+            // Asked again: the older query would only bring the same answers.
+            let cancel = proxy.service_discovery_cancel_request(replaced);
+            let _ = traced("ServiceDiscoveryCancelRequest", cancel).await;
+        }
+        Ok(())
     }
 
-    fn connect(&self, device_address: String) -> P2pFuture<'_, ()> {
-        Box::pin(async move {
-            let proxy = self.p2p_proxy().await?;
-            // Maps to p2p_connect. Adjust option keys to match your wpa_supplicant build.
-            // Some builds expect "peer" as an object path; others accept the MAC address.
-            let mut options = Self::empty_options();
-            let peer = OwnedValue::try_from(Value::from(device_address))?;
-            let wps = OwnedValue::try_from(Value::from("pbc"))?;
-            options.insert("peer".to_string(), peer);
-            options.insert("wps_method".to_string(), wps);
-            let _: () = proxy.call("Connect", &(options)).await?;
-            Ok(())
+    async fn cancel_service_discovery(&self, peer: Option<MacAddress>) -> Result<(), P2pError> {
+        let Some(id) = lock(&self.paths).service_requests.remove(&peer) else {
+            return Ok(());
+        };
+        let proxy = self.p2p_proxy().await?;
+        let cancel = traced(
+            "ServiceDiscoveryCancelRequest",
+            proxy.service_discovery_cancel_request(id),
+        )
+        .await;
+        match cancel {
+            // The supplicant drops a query to one peer once that peer answered.
+            Err(_) if peer.is_some() => Ok(()),
+            result => Ok(result?),
+        }
+    }
+
+    async fn asp_provision(&self, request: AspProvision) -> Result<(), P2pError> {
+        // The session is named by its id and our device address, which only the
+        // control socket tells.
+        let status = self.ctrl_request("STATUS").await?;
+        let own = status
+            .lines()
+            .find_map(|line| line.strip_prefix("p2p_device_address="))
+            .ok_or_else(|| P2pError::Backend("STATUS: no p2p_device_address".to_string()))?;
+        let peer = request.peer;
+        let mut command = format!(
+            "P2P_ASP_PROVISION {peer} adv_id={:x} adv_mac={peer} session={:x} session_mac={own} method={:x}",
+            request.advertisement_id, request.session_id, request.config_method
+        );
+        if let Some(info) = &request.session_info {
+            command.push_str(&format!(" info={}", single_quoted(info)?));
+        }
+        self.ctrl_request(&command).await?;
+        Ok(())
+    }
+
+    async fn capabilities(&self) -> Result<P2pCapabilities, P2pError> {
+        let interface = self.interface_proxy().await?;
+        let caps = interface.capabilities().await?;
+        let modes: Vec<String> = match caps.get("Modes") {
+            Some(value) => value.try_clone()?.try_into()?,
+            None => Vec::new(),
+        };
+
+        let wps = self.wps_proxy().await?;
+        let config_methods = wps.config_methods().await?;
+        let wps_methods = config_methods
+            .split_whitespace()
+            .map(str::to_string)
+            .collect();
+
+        // Driver flags and channel lists are only exposed on the control socket.
+        // Older supplicants lack some commands, so treat failures as "unknown".
+        let driver_flags: Vec<String> = match self.ctrl_request("DRIVER_FLAGS").await {
+            Ok(reply) => reply
+                .lines()
+                .filter(|line| !line.ends_with(':'))
+                .map(str::to_string)
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        let channels = match self.ctrl_request("GET_CAPABILITY channels").await {
+            Ok(reply) => Self::parse_channels(&reply),
+            Err(_) => Vec::new(),
+        };
+        let max_peers = match self.ctrl_request("GET max_num_sta").await {
+            Ok(reply) => reply.trim().parse().ok(),
+            Err(_) => None,
+        };
+
+        let has_flag = |flag: &str| driver_flags.iter().any(|f| f == flag);
+        Ok(P2pCapabilities {
+            p2p_supported: modes.iter().any(|m| m == "p2p") || has_flag("P2P_CAPABLE"),
+            concurrent_sta_p2p: has_flag("P2P_CONCURRENT"),
+            dedicated_p2p_device: has_flag("DEDICATED_P2P_DEVICE"),
+            max_peers,
+            channels,
+            wps_methods,
+            modes,
+            driver_flags,
         })
     }
 
-    fn create_group(&self) -> P2pFuture<'_, ()> {
-        Box::pin(async move {
-            let proxy = self.p2p_proxy().await?;
-            // Maps to p2p_group_add.
-            let options = Self::empty_options();
-            let _: () = proxy.call("GroupAdd", &(options)).await?;
-            Ok(())
+    async fn local_device(&self) -> Result<LocalDeviceInfo, P2pError> {
+        let proxy = self.p2p_proxy().await?;
+        let config = &mut proxy.p2p_device_config().await?;
+        let config_methods = self.wps_proxy().await?.config_methods().await?;
+        // The device address is only on the control socket; it is unknown while
+        // that is unreachable.
+        let device_address = match self.ctrl_request("STATUS").await {
+            Ok(reply) => reply
+                .lines()
+                .find_map(|line| line.strip_prefix("p2p_device_address="))
+                .and_then(|address| address.parse().ok()),
+            Err(_) => None,
+        };
+        Ok(LocalDeviceInfo {
+            device_address,
+            device_name: take_property::<String>(config, "DeviceName").filter(|s| !s.is_empty()),
+            primary_type: take_property::<Vec<u8>>(config, "PrimaryDeviceType")
+                .and_then(|primary| format_device_type(&primary)),
+            wps_methods: config_methods
+                .split_whitespace()
+                .map(str::to_string)
+                .collect(),
         })
     }
+
+    async fn set_device_name(&self, name: String) -> Result<(), P2pError> {
+        let proxy = self.p2p_proxy().await?;
+        // Keys left out of the dictionary keep their value.
+        let config = HashMap::from([(
+            "DeviceName".to_string(),
+            OwnedValue::try_from(Value::from(name))?,
+        )]);
+        traced("P2PDeviceConfig", proxy.set_p2p_device_config(config)).await?;
+        Ok(())
+    }
+
+    async fn ping(&self) -> Result<(), P2pError> {
+        // One uncached property read: a single round trip through the supplicant's loop.
+        let proxy = WpaInterfaceProxy::builder(&self.connection)
+            .path(self.interface_path()?)?
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await?;
+        proxy.state().await?;
+        Ok(())
+    }
+
+    async fn watch_events(&self, events: mpsc::Sender<P2pEvent>) -> Result<(), P2pError> {
+        let dbus = DBusProxy::new(&self.connection).await?;
+        // Subscribed for the whole watch, so a restart while re-attaching is not missed.
+        let mut owners = dbus
+            .receive_name_owner_changed_with_args(&[(0, SUPPLICANT_SERVICE)])
+            .await?;
+        loop {
+            let stale = lock(&self.paths).stale;
+            if let Some(detached) = stale {
+                let name = BusName::try_from(SUPPLICANT_SERVICE).map_err(zbus::Error::from)?;
+                if !dbus.name_has_owner(name).await? {
+                    Self::wait_for_owner(&mut owners).await?;
+                }
+                #[cfg(feature = "hotplug")]
+                if self.config.hotplug {
+                    crate::net::rtnetlink::wait_for_link(&self.interface_name)
+                        .await
+                        .map_err(|err| P2pError::Backend(format!("rtnetlink: {err}")))?;
+                }
+                self.attach(true).await?;
+                let event = match detached {
+                    Detached::Supplicant => P2pEvent::BackendRestored,
+                    Detached::Interface => P2pEvent::AdapterAdded {
+                        interface: self.interface_name.clone(),
+                    },
+                };
+                if events.send(event).await.is_err() {
+                    return Ok(());
+                }
+            }
+            self.forward_signals(&events, &mut owners).await?;
+            if lock(&self.paths).stale.is_none() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl P2pBackendImpl {
+    /// Forward the supplicant's signals until the streams end, `events` closes or
+    /// the supplicant leaves the bus (which marks the paths stale).
+    async fn forward_signals(
+        &self,
+        events: &mpsc::Sender<P2pEvent>,
+        owners: &mut NameOwnerChangedStream<'_>,
+    ) -> Result<(), P2pError> {
+        let proxy = self.p2p_proxy().await?;
+        let supplicant = WpaSupplicantProxy::new(&self.connection).await?;
+        let mut removed = supplicant.receive_interface_removed().await?;
+        let interface_path = self.interface_path()?;
+        let mut unplugged = std::pin::pin!(self.unplugged());
+        let mut found = proxy.receive_device_found().await?;
+        let mut lost = proxy.receive_device_lost().await?;
+        let mut find_stopped = proxy.receive_find_stopped().await?;
+        let mut go_request = proxy.receive_go_negotiation_request().await?;
+        let mut invitation_received = proxy.receive_invitation_received().await?;
+        let mut pd_pbc = proxy.receive_provision_discovery_pbc_request().await?;
+        let mut pd_display = proxy
+            .receive_provision_discovery_request_display_pin()
+            .await?;
+        let mut pd_enter = proxy
+            .receive_provision_discovery_request_enter_pin()
+            .await?;
+        let mut go_success = proxy.receive_go_negotiation_success().await?;
+        let mut go_failure = proxy.receive_go_negotiation_failure().await?;
+        let mut pd_failure = proxy.receive_provision_discovery_failure().await?;
+        let mut invitation = proxy.receive_invitation_result().await?;
+        let mut group_started = proxy.receive_group_started().await?;
+        let mut group_finished = proxy.receive_group_finished().await?;
+        let mut service_response = proxy.receive_service_discovery_response().await?;
+        // GroupFinished may arrive after the group interface is gone, so remember
+        // what each started group looked like, starting with those already up.
+        let mut groups: HashMap<_, _> = self
+            .active_groups()
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .collect();
+
+        loop {
+            let event = tokio::select! {
+                Some(signal) = found.next() => {
+                    let path = OwnedObjectPath::from(signal.args()?.path);
+                    // The peer may vanish between the signal and the property reads.
+                    match self.peer_device(path).await {
+                        Ok(device) => P2pEvent::PeerFound(Arc::new(device)),
+                        Err(_) => continue,
+                    }
+                }
+                Some(signal) = lost.next() => {
+                    match Self::peer_address(signal.args()?.path.as_str()) {
+                        Some(address) => P2pEvent::PeerLost(address),
+                        None => continue,
+                    }
+                }
+                Some(_) = find_stopped.next() => P2pEvent::DiscoveryStopped,
+                Some(signal) = go_request.next() => {
+                    let args = signal.args()?;
+                    // The peer's device password id, as Android reads it.
+                    let method = match args.dev_passwd_id {
+                        DEV_PW_USER_SPECIFIED => WpsMethod::PinDisplay,
+                        DEV_PW_REGISTRAR_SPECIFIED => WpsMethod::PinKeypad,
+                        _ => WpsMethod::Pbc,
+                    };
+                    match Self::peer_address(args.path.as_str()) {
+                        Some(peer) => Self::request(peer, RequestKind::GoNegotiation, method, None),
+                        None => continue,
+                    }
+                }
+                Some(signal) = invitation_received.next() => {
+                    let args = signal.args()?;
+                    let peer = Self::mac_option(&args.properties, "go_dev_addr")
+                        .or_else(|| Self::mac_option(&args.properties, "sa"));
+                    match peer {
+                        Some(peer) => {
+                            Self::request(peer, RequestKind::Invitation, WpsMethod::Pbc, None)
+                        }
+                        None => continue,
+                    }
+                }
+                Some(signal) = pd_pbc.next() => {
+                    match Self::peer_address(signal.args()?.peer.as_str()) {
+                        Some(peer) => {
+                            Self::request(peer, RequestKind::ProvisionDiscovery, WpsMethod::Pbc, None)
+                        }
+                        None => continue,
+                    }
+                }
+                Some(signal) = pd_display.next() => {
+                    // The peer enters the PIN the supplicant picked for us to show.
+                    let args = signal.args()?;
+                    match Self::peer_address(args.peer.as_str()) {
+                        Some(peer) => Self::request(
+                            peer,
+                            RequestKind::ProvisionDiscovery,
+                            WpsMethod::PinDisplay,
+                            Some(args.pin.to_string()),
+                        ),
+                        None => continue,
+                    }
+                }
+                Some(signal) = pd_enter.next() => {
+                    let method = WpsMethod::PinKeypad;
+                    match Self::peer_address(signal.args()?.peer.as_str()) {
+                        Some(peer) => Self::request(peer, RequestKind::ProvisionDiscovery, method, None),
+                        None => continue,
+                    }
+                }
+                Some(signal) = go_success.next() => {
+                    // Reported with the group; remember the peer until it starts.
+                    let args = signal.args()?;
+                    *lock(&self.forming_with) = Self::path_option(&args.info, "peer_object")
+                        .and_then(|path| Self::peer_address(path.as_str()));
+                    continue;
+                }
+                Some(signal) = go_failure.next() => {
+                    let args = signal.args()?;
+                    let peer = Self::path_option(&args.info, "peer_object")
+                        .and_then(|path| Self::peer_address(path.as_str()));
+                    let status = Self::status_option(&args.info, "status");
+                    P2pEvent::GoNegotiationFailed { peer, status }
+                }
+                Some(signal) = pd_failure.next() => {
+                    let args = signal.args()?;
+                    match Self::peer_address(args.peer.as_str()) {
+                        Some(peer) => P2pEvent::ProvisionDiscoveryFailed {
+                            peer,
+                            status: P2pStatusCode::from_code(args.status),
+                        },
+                        None => continue,
+                    }
+                }
+                Some(signal) = invitation.next() => {
+                    let args = signal.args()?;
+                    P2pEvent::InvitationResult {
+                        status: Self::status_option(&args.result, "status"),
+                    }
+                }
+                Some(signal) = group_started.next() => {
+                    let args = signal.args()?;
+                    match self.signal_group(&args.properties).await {
+                        Some((path, group)) => {
+                            groups.insert(path, group.clone());
+                            P2pEvent::GroupStarted(Arc::new(group))
+                        }
+                        None => continue,
+                    }
+                }
+                Some(signal) = group_finished.next() => {
+                    let args = signal.args()?;
+                    match Self::path_option(&args.properties, "interface_object")
+                        .and_then(|path| groups.remove(&path))
+                    {
+                        Some(group) => P2pEvent::GroupFinished(Arc::new(group)),
+                        None => continue,
+                    }
+                }
+                Some(signal) = service_response.next() => {
+                    let args = signal.args()?;
+                    let response = &args.sd_response;
+                    let Some(peer) = Self::path_option(response, "peer_object")
+                        .and_then(|path| Self::peer_address(path.as_str()))
+                    else {
+                        continue;
+                    };
+                    let update_indicator = response
+                        .get("update_indicator")
+                        .and_then(|value| u16::try_from(value).ok())
+                        .unwrap_or_default();
+                    let tlvs: Vec<u8> = match response.get("tlvs") {
+                        Some(value) => value.try_clone()?.try_into()?,
+                        None => Vec::new(),
+                    };
+                    P2pEvent::ServicesFound(ServiceResponse::parse(peer, update_indicator, &tlvs))
+                }
+                Some(signal) = owners.next() => {
+                    // Losing the name or handing it over (--replace) both drop our objects.
+                    if signal.args()?.old_owner.is_none() {
+                        continue;
+                    }
+                    lock(&self.paths).stale = Some(Detached::Supplicant);
+                    let reason = "wpa_supplicant left the bus".to_string();
+                    let _ = events.send(P2pEvent::BackendLost { reason }).await;
+                    return Ok(());
+                }
+                Some(signal) = removed.next() => {
+                    if signal.args()?.path != *interface_path {
+                        continue;
+                    }
+                    return self.detach(events).await;
+                }
+                () = unplugged.as_mut() => return self.detach(events).await,
+                else => return Ok(()),
+            };
+            if events.send(event).await.is_err() {
+                return Ok(());
+            }
+        }
+    }
 }
@@ -0,0 +1,299 @@
+//! Typed proxies for the wpa_supplicant D-Bus API (see `doc/dbus.doxygen` upstream).
+//!
+//! Names follow the D-Bus spelling where zbus's default case conversion
+//! would get them wrong (e.g. "GONegotiationSuccess", "config_method").
+
+use std::collections::HashMap;
+
+use zbus::proxy;
+use zbus::zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value};
+
+#[proxy(
+    interface = "fi.w1.wpa_supplicant1",
+    default_service = "fi.w1.wpa_supplicant1",
+    default_path = "/fi/w1/wpa_supplicant1",
+    gen_blocking = false
+)]
+pub(crate) trait WpaSupplicant {
+    fn get_interface(&self, ifname: &str) -> zbus::Result<OwnedObjectPath>;
+
+    fn create_interface(&self, args: HashMap<&str, Value<'_>>) -> zbus::Result<OwnedObjectPath>;
+
+    fn remove_interface(&self, path: &ObjectPath<'_>) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn interface_added(
+        &self,
+        path: ObjectPath<'_>,
+        properties: HashMap<String, OwnedValue>,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn interface_removed(&self, path: ObjectPath<'_>) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn capabilities(&self) -> zbus::Result<Vec<String>>;
+
+    #[zbus(property)]
+    fn interfaces(&self) -> zbus::Result<Vec<OwnedObjectPath>>;
+}
+
+#[proxy(
+    interface = "fi.w1.wpa_supplicant1.Interface",
+    default_service = "fi.w1.wpa_supplicant1",
+    gen_blocking = false
+)]
+pub(crate) trait WpaInterface {
+    fn vendor_element_add(&self, frame_id: i32, elements: &[u8]) -> zbus::Result<()>;
+
+    fn vendor_element_remove(&self, frame_id: i32, elements: &[u8]) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn capabilities(&self) -> zbus::Result<HashMap<String, OwnedValue>>;
+
+    #[zbus(property)]
+    fn state(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn ifname(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn driver(&self) -> zbus::Result<String>;
+}
+
+#[proxy(
+    interface = "fi.w1.wpa_supplicant1.Interface.WPS",
+    default_service = "fi.w1.wpa_supplicant1",
+    gen_blocking = false
+)]
+pub(crate) trait WpaWps {
+    #[zbus(property)]
+    fn config_methods(&self) -> zbus::Result<String>;
+}
+
+#[proxy(
+    interface = "fi.w1.wpa_supplicant1.Interface.P2PDevice",
+    default_service = "fi.w1.wpa_supplicant1",
+    gen_blocking = false
+)]
+pub(crate) trait WpaP2pDevice {
+    fn find(&self, args: HashMap<String, OwnedValue>) -> zbus::Result<()>;
+
+    fn stop_find(&self) -> zbus::Result<()>;
+
+    fn listen(&self, timeout: i32) -> zbus::Result<()>;
+
+    fn extended_listen(&self, args: HashMap<String, OwnedValue>) -> zbus::Result<()>;
+
+    fn presence_request(&self, args: HashMap<String, OwnedValue>) -> zbus::Result<()>;
+
+    fn provision_discovery_request(
+        &self,
+        peer: &ObjectPath<'_>,
+        config_method: &str,
+    ) -> zbus::Result<()>;
+
+    fn connect(&self, args: HashMap<String, OwnedValue>) -> zbus::Result<String>;
+
+    fn group_add(&self, args: HashMap<String, OwnedValue>) -> zbus::Result<()>;
+
+    fn cancel(&self) -> zbus::Result<()>;
+
+    fn invite(&self, args: HashMap<String, OwnedValue>) -> zbus::Result<()>;
+
+    fn disconnect(&self) -> zbus::Result<()>;
+
+    fn reject_peer(&self, peer: &ObjectPath<'_>) -> zbus::Result<()>;
+
+    fn remove_client(&self, args: HashMap<String, OwnedValue>) -> zbus::Result<()>;
+
+    fn flush(&self) -> zbus::Result<()>;
+
+    fn add_service(&self, args: HashMap<String, OwnedValue>) -> zbus::Result<()>;
+
+    fn delete_service(&self, args: HashMap<String, OwnedValue>) -> zbus::Result<()>;
+
+    fn flush_service(&self) -> zbus::Result<()>;
+
+    fn service_discovery_request(&self, args: HashMap<String, OwnedValue>) -> zbus::Result<u64>;
+
+    fn service_discovery_response(&self, args: HashMap<String, OwnedValue>) -> zbus::Result<()>;
+
+    fn service_discovery_cancel_request(&self, id: u64) -> zbus::Result<()>;
+
+    fn service_update(&self) -> zbus::Result<()>;
+
+    fn add_persistent_group(
+        &self,
+        args: HashMap<String, OwnedValue>,
+    ) -> zbus::Result<OwnedObjectPath>;
+
+    fn remove_persistent_group(&self, path: &ObjectPath<'_>) -> zbus::Result<()>;
+
+    fn remove_all_persistent_groups(&self) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn device_found(&self, path: ObjectPath<'_>) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn device_found_properties(
+        &self,
+        path: ObjectPath<'_>,
+        properties: HashMap<String, OwnedValue>,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn device_lost(&self, path: ObjectPath<'_>) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn find_stopped(&self) -> zbus::Result<()>;
+
+    #[zbus(signal, name = "GONegotiationSuccess")]
+    fn go_negotiation_success(&self, info: HashMap<String, OwnedValue>) -> zbus::Result<()>;
+
+    #[zbus(signal, name = "GONegotiationFailure")]
+    fn go_negotiation_failure(&self, info: HashMap<String, OwnedValue>) -> zbus::Result<()>;
+
+    #[zbus(signal, name = "GONegotiationRequest")]
+    fn go_negotiation_request(
+        &self,
+        path: ObjectPath<'_>,
+        dev_passwd_id: u16,
+        device_go_intent: u8,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn group_started(&self, properties: HashMap<String, OwnedValue>) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn group_formation_failure(&self, reason: String) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn group_finished(&self, properties: HashMap<String, OwnedValue>) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn invitation_result(&self, result: HashMap<String, OwnedValue>) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn invitation_received(&self, properties: HashMap<String, OwnedValue>) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn provision_discovery_request_display_pin(
+        &self,
+        peer: ObjectPath<'_>,
+        pin: String,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn provision_discovery_response_display_pin(
+        &self,
+        peer: ObjectPath<'_>,
+        pin: String,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn provision_discovery_request_enter_pin(&self, peer: ObjectPath<'_>) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn provision_discovery_response_enter_pin(&self, peer: ObjectPath<'_>) -> zbus::Result<()>;
+
+    #[zbus(signal, name = "ProvisionDiscoveryPBCRequest")]
+    fn provision_discovery_pbc_request(&self, peer: ObjectPath<'_>) -> zbus::Result<()>;
+
+    #[zbus(signal, name = "ProvisionDiscoveryPBCResponse")]
+    fn provision_discovery_pbc_response(&self, peer: ObjectPath<'_>) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn provision_discovery_failure(&self, peer: ObjectPath<'_>, status: i32) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn service_discovery_request(
+        &self,
+        sd_request: HashMap<String, OwnedValue>,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn service_discovery_response(
+        &self,
+        sd_response: HashMap<String, OwnedValue>,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn persistent_group_added(
+        &self,
+        path: ObjectPath<'_>,
+        properties: HashMap<String, OwnedValue>,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn persistent_group_removed(&self, path: ObjectPath<'_>) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn wps_failed(&self, name: String, args: HashMap<String, OwnedValue>) -> zbus::Result<()>;
+
+    #[zbus(property, name = "P2PDeviceConfig")]
+    fn p2p_device_config(&self) -> zbus::Result<HashMap<String, OwnedValue>>;
+
+    #[zbus(property, name = "P2PDeviceConfig")]
+    fn set_p2p_device_config(&self, config: HashMap<String, OwnedValue>) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn peers(&self) -> zbus::Result<Vec<OwnedObjectPath>>;
+
+    #[zbus(property)]
+    fn role(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn group(&self) -> zbus::Result<OwnedObjectPath>;
+
+    #[zbus(property, name = "PeerGO")]
+    fn peer_go(&self) -> zbus::Result<OwnedObjectPath>;
+
+    #[zbus(property)]
+    fn persistent_groups(&self) -> zbus::Result<Vec<OwnedObjectPath>>;
+}
+
+#[proxy(
+    interface = "fi.w1.wpa_supplicant1.Group",
+    default_service = "fi.w1.wpa_supplicant1",
+    gen_blocking = false
+)]
+pub(crate) trait WpaGroup {
+    #[zbus(signal)]
+    fn peer_joined(&self, peer: ObjectPath<'_>) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn peer_disconnected(&self, peer: ObjectPath<'_>) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn members(&self) -> zbus::Result<Vec<OwnedObjectPath>>;
+
+    #[zbus(property)]
+    fn role(&self) -> zbus::Result<String>;
+
+    #[zbus(property, name = "SSID")]
+    fn ssid(&self) -> zbus::Result<Vec<u8>>;
+
+    #[zbus(property, name = "BSSID")]
+    fn bssid(&self) -> zbus::Result<Vec<u8>>;
+
+    #[zbus(property)]
+    fn frequency(&self) -> zbus::Result<u16>;
+
+    #[zbus(property)]
+    fn passphrase(&self) -> zbus::Result<String>;
+
+    #[zbus(property, name = "PSK")]
+    fn psk(&self) -> zbus::Result<Vec<u8>>;
+}
+
+#[proxy(
+    interface = "fi.w1.wpa_supplicant1.PersistentGroup",
+    default_service = "fi.w1.wpa_supplicant1",
+    gen_blocking = false
+)]
+pub(crate) trait WpaPersistentGroup {
+    /// The stored network block, with values formatted as in wpa_supplicant.conf.
+    #[zbus(property)]
+    fn properties(&self) -> zbus::Result<HashMap<String, OwnedValue>>;
+}
@@ -0,0 +1,887 @@
+//! Scriptable simulation backend for tests and demos without hardware or a system bus.
+//!
+//! Script virtual peers up front (or while running), hand the backend to
+//! `WifiP2pManager::with_backend`, and the full manager/event pipeline runs
+//! against them. Timings use tokio's clock, so `tokio::time::pause()` makes
+//! scenarios deterministic.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use tokio::sync::{Notify, mpsc};
+use zbus::zvariant::OwnedValue;
+
+use crate::approver::{ConnectionRequest, RequestKind};
+use crate::capabilities::P2pCapabilities;
+use crate::channel::P2pEvent;
+use crate::config::{ConnectConfig, DiscoveryOptions, WpsMethod};
+use crate::credentials::GroupCredentials;
+use crate::device::{LocalDeviceInfo, P2pDevice, wps_config};
+use crate::error::P2pError;
+use crate::group::{GroupRole, NoaSchedule, P2pGroup};
+use crate::mac::MacAddress;
+use crate::service::{AspProvision, LocalService, ServiceResponse, ServiceTlv};
+use crate::state::ListenState;
+use crate::stats::LinkStats;
+use crate::status::P2pStatusCode;
+use crate::vendor::VendorElementFrame;
+use crate::wfd::WfdDeviceInfo;
+
+use super::P2pBackend;
+
+/// PIN handed out for `WpsMethod::PinDisplay` connects without a PIN.
+pub const SIM_GENERATED_PIN: &str = "12345670";
+
+/// Interface name reported for the simulated group.
+pub const SIM_GROUP_INTERFACE: &str = "p2p-sim-0";
+
+/// Our own device address until `SimBackend::set_device_address` changes it.
+pub const SIM_DEVICE_ADDRESS: MacAddress = MacAddress::new([0x02, 0x00, 0x00, 0x00, 0x00, 0x01]);
+
+/// How a virtual peer answers connect requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConnectBehavior {
+    /// Accept and form a group after the peer's connect delay.
+    #[default]
+    Accept,
+    /// Fail GO negotiation with the given status.
+    Reject(P2pStatusCode),
+    /// Never answer, so the manager's command timeout fires.
+    Ignore,
+}
+
+/// A scripted peer that shows up while discovery is running.
+#[derive(Debug, Clone)]
+pub struct VirtualPeer {
+    device: P2pDevice,
+    appear_after: Duration,
+    disappear_after: Option<Duration>,
+    connect_delay: Duration,
+    behavior: ConnectBehavior,
+    ip: Option<IpAddr>,
+    rssi: Option<i32>,
+    services: Vec<LocalService>,
+}
+
+impl VirtualPeer {
+    /// A push-button capable peer that appears as soon as discovery starts.
+    pub fn new(address: MacAddress) -> Self {
+        let mut device = P2pDevice::new(address);
+        device.wps_config_methods =
+            wps_config::PUSH_BUTTON | wps_config::DISPLAY | wps_config::KEYPAD;
+        Self {
+            device,
+            appear_after: Duration::ZERO,
+            disappear_after: None,
+            connect_delay: Duration::ZERO,
+            behavior: ConnectBehavior::Accept,
+            ip: None,
+            rssi: None,
+            services: Vec::new(),
+        }
+    }
+
+    pub fn named(mut self, name: impl Into<String>) -> Self {
+        self.device.device_name = Some(name.into());
+        self
+    }
+
+    /// Replace the reported device properties (the address is kept).
+    pub fn with_device(mut self, device: P2pDevice) -> Self {
+        let address = self.device.mac_address;
+        self.device = device;
+        self.device.mac_address = address;
+        self
+    }
+
+    /// Delay between the start of discovery and the peer being found.
+    pub fn appear_after(mut self, delay: Duration) -> Self {
+        self.appear_after = delay;
+        self
+    }
+
+    /// Delay between the peer being found and it being lost again.
+    pub fn disappear_after(mut self, delay: Duration) -> Self {
+        self.disappear_after = Some(delay);
+        self
+    }
+
+    /// How long GO negotiation and group formation take on accept or reject.
+    pub fn connect_delay(mut self, delay: Duration) -> Self {
+        self.connect_delay = delay;
+        self
+    }
+
+    pub fn on_connect(mut self, behavior: ConnectBehavior) -> Self {
+        self.behavior = behavior;
+        self
+    }
+
+    /// Address the peer reports on the group interface once connected.
+    pub fn with_ip(mut self, ip: IpAddr) -> Self {
+        self.ip = Some(ip);
+        self
+    }
+
+    /// Signal strength reported in `LinkStats` while connected, in dBm.
+    pub fn with_rssi(mut self, rssi: i32) -> Self {
+        self.rssi = Some(rssi);
+        self
+    }
+
+    /// WFD Device Information the peer announces.
+    pub fn with_wfd(mut self, info: WfdDeviceInfo) -> Self {
+        self.device.wfd = Some(info);
+        self
+    }
+
+    /// A service the peer answers service discovery queries with.
+    pub fn with_service(mut self, service: LocalService) -> Self {
+        self.services.push(service);
+        self
+    }
+
+    pub fn address(&self) -> MacAddress {
+        self.device.mac_address
+    }
+
+    /// Whether the peer advertises a P2PS service of one of `names`; names are
+    /// hashed in lower case, so they match regardless of case.
+    fn advertises_any(&self, names: &[String]) -> bool {
+        self.services.iter().any(|service| match service {
+            LocalService::Asp(service) => names
+                .iter()
+                .any(|name| name.eq_ignore_ascii_case(&service.name)),
+            _ => false,
+        })
+    }
+
+    fn service_response(&self) -> ServiceResponse {
+        let tlvs = self
+            .services
+            .iter()
+            .map(|service| ServiceTlv {
+                protocol: service.protocol(),
+                transaction_id: 1,
+                status: 0,
+                data: service.response_data(),
+            })
+            .collect();
+        ServiceResponse {
+            peer: self.address(),
+            update_indicator: 1,
+            tlvs,
+        }
+    }
+}
+
+#[derive(Default)]
+struct SimState {
+    peers: HashMap<MacAddress, VirtualPeer>,
+    visible: HashMap<MacAddress, P2pDevice>,
+    connected: Vec<MacAddress>,
+    discovering: bool,
+    // Virtual peers have no channel and every scan finds all of them; the
+    // manager drops those of other device types.
+    discovery: DiscoveryOptions,
+    listening: bool,
+    // Listen and interval of a windowed listen, `None` when listening continuously.
+    listen_window: Option<(Duration, Duration)>,
+    // Bumped on every discovery start so stale appearance timers are ignored.
+    discovery_round: u64,
+    group: Option<P2pGroup>,
+    noa: Option<NoaSchedule>,
+    vendor_elements: HashMap<VendorElementFrame, Vec<u8>>,
+    wfd: Option<WfdDeviceInfo>,
+    capabilities: P2pCapabilities,
+    local_device: LocalDeviceInfo,
+    // Stored like the supplicant does after every successful connect.
+    persistent: Vec<GroupCredentials>,
+    services: Vec<LocalService>,
+    // Peers that asked to connect and have not been answered yet.
+    requests: Vec<MacAddress>,
+    // A query for every peer stays pending until discovery stops, like the supplicant's.
+    querying_services: bool,
+    // Wakes connects in progress when `cancel_connect` is called.
+    cancel: Arc<Notify>,
+    events: Option<mpsc::Sender<P2pEvent>>,
+}
+
+impl SimState {
+    fn emit(&self, event: P2pEvent) {
+        // No watcher yet (or it went away) just means nobody is listening.
+        if let Some(events) = &self.events {
+            let _ = events.try_send(event);
+        }
+    }
+
+    fn start_group(&mut self, role: GroupRole, peer: Option<MacAddress>) {
+        if self.group.is_none() {
+            let group = P2pGroup {
+                interface: SIM_GROUP_INTERFACE.to_string(),
+                role,
+                ip_allocation: None,
+                peer,
+            };
+            self.group = Some(group.clone());
+            self.emit(P2pEvent::GroupStarted(Arc::new(group)));
+        }
+    }
+
+    fn answer_service_query(&self, address: MacAddress) {
+        if let Some(peer) = self.peers.get(&address)
+            && !peer.services.is_empty()
+        {
+            self.emit(P2pEvent::ServicesFound(peer.service_response()));
+        }
+    }
+}
+
+/// Simulated backend with scripted virtual peers; cheap to clone and share
+/// between the manager and the test driving it.
+#[derive(Clone)]
+pub struct SimBackend {
+    state: Arc<Mutex<SimState>>,
+}
+
+impl Default for SimBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimBackend {
+    pub fn new() -> Self {
+        let capabilities = P2pCapabilities {
+            p2p_supported: true,
+            wps_methods: vec![
+                "push_button".to_string(),
+                "display".to_string(),
+                "keypad".to_string(),
+            ],
+            modes: vec![
+                "infrastructure".to_string(),
+                "ap".to_string(),
+                "p2p".to_string(),
+            ],
+            ..P2pCapabilities::default()
+        };
+        let local_device = LocalDeviceInfo {
+            device_address: Some(SIM_DEVICE_ADDRESS),
+            device_name: Some("sim".to_string()),
+            primary_type: Some("1-0050F204-1".to_string()),
+            wps_methods: capabilities.wps_methods.clone(),
+        };
+        let state = SimState {
+            capabilities,
+            local_device,
+            ..SimState::default()
+        };
+        Self {
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    /// Add (or replace) a scripted peer. If discovery is already running its
+    /// appearance delay counts from now.
+    pub fn add_peer(&self, peer: VirtualPeer) {
+        let mut state = self.lock();
+        let round = state.discovery_round;
+        let discovering = state.discovering;
+        state.peers.insert(peer.address(), peer.clone());
+        drop(state);
+        if discovering {
+            self.schedule_appearance(peer, round);
+        }
+    }
+
+    /// Make a found peer disappear immediately.
+    pub fn lose_peer(&self, address: MacAddress) {
+        let mut state = self.lock();
+        if state.visible.remove(&address).is_some() {
+            state.connected.retain(|peer| *peer != address);
+            state.emit(P2pEvent::PeerLost(address));
+        }
+    }
+
+    /// End the group from the far side, as a group owner leaving or the link
+    /// dropping does.
+    pub fn end_group(&self) {
+        let mut state = self.lock();
+        if let Some(group) = state.group.take() {
+            state.connected.clear();
+            state.emit(P2pEvent::GroupFinished(Arc::new(group)));
+        }
+    }
+
+    /// Change the signal strength a peer reports in `LinkStats`, e.g. to let
+    /// the link fade while connected.
+    pub fn set_rssi(&self, address: MacAddress, rssi: Option<i32>) {
+        if let Some(peer) = self.lock().peers.get_mut(&address) {
+            peer.rssi = rssi;
+        }
+    }
+
+    /// Report the capabilities returned by `capabilities()`.
+    pub fn set_capabilities(&self, capabilities: P2pCapabilities) {
+        self.lock().capabilities = capabilities;
+    }
+
+    /// Change our own device address, as MAC randomization does. The manager
+    /// notices when it next reads the local device.
+    pub fn set_device_address(&self, address: MacAddress) {
+        self.lock().local_device.device_address = Some(address);
+    }
+
+    pub fn is_discovering(&self) -> bool {
+        self.lock().discovering
+    }
+
+    /// How many times `discover_peers` was called, restarts of a running scan
+    /// included.
+    pub fn discovery_rounds(&self) -> u64 {
+        self.lock().discovery_round
+    }
+
+    /// What the last `discover_peers` asked for.
+    pub fn discovery_options(&self) -> DiscoveryOptions {
+        self.lock().discovery.clone()
+    }
+
+    pub fn is_listening(&self) -> bool {
+        self.lock().listening
+    }
+
+    /// The `(listen, every)` window we listen in, if listening windowed.
+    pub fn listen_window(&self) -> Option<(Duration, Duration)> {
+        self.lock().listen_window
+    }
+
+    /// End discovery as the supplicant does when its find times out.
+    pub fn end_discovery(&self) {
+        let mut state = self.lock();
+        if state.discovering {
+            state.discovering = false;
+            state.emit(P2pEvent::DiscoveryStopped);
+        }
+    }
+
+    /// End listen state as the platform would on its own, e.g. when another
+    /// app takes the radio.
+    pub fn end_listen(&self) {
+        let mut state = self.lock();
+        if state.listening {
+            state.listening = false;
+            state.emit(P2pEvent::ListenStateChanged(ListenState::Stopped));
+        }
+    }
+
+    /// Whether we are currently group owner (after `create_group`) or in a group
+    /// with a connected peer.
+    pub fn group_active(&self) -> bool {
+        self.lock().group.is_some()
+    }
+
+    pub fn connected_peers(&self) -> Vec<MacAddress> {
+        self.lock().connected.clone()
+    }
+
+    pub fn noa(&self) -> Option<NoaSchedule> {
+        self.lock().noa
+    }
+
+    /// Have a found peer ask to connect to us, as a push-button GO negotiation
+    /// request does.
+    pub fn request_connection(&self, address: MacAddress) {
+        self.send_request(address, RequestKind::GoNegotiation, WpsMethod::Pbc);
+    }
+
+    /// Have a found peer send a request of `kind`, to be answered with `method`.
+    pub fn send_request(&self, address: MacAddress, kind: RequestKind, method: WpsMethod) {
+        let mut state = self.lock();
+        if state.visible.contains_key(&address) && !state.requests.contains(&address) {
+            state.requests.push(address);
+            state.emit(P2pEvent::IncomingRequest(ConnectionRequest {
+                peer: address,
+                kind,
+                method,
+                pin: None,
+            }));
+        }
+    }
+
+    /// Connection requests not yet accepted with `connect` or turned down.
+    pub fn pending_requests(&self) -> Vec<MacAddress> {
+        self.lock().requests.clone()
+    }
+
+    /// Services added with `add_service`, in order.
+    pub fn local_services(&self) -> Vec<LocalService> {
+        self.lock().services.clone()
+    }
+
+    pub fn vendor_elements(&self, frame: VendorElementFrame) -> Vec<u8> {
+        self.lock()
+            .vendor_elements
+            .get(&frame)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// The WFD Device Information we announce, `None` with Wi-Fi Display off.
+    pub fn wfd_info(&self) -> Option<WfdDeviceInfo> {
+        self.lock().wfd
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SimState> {
+        // A panicking test thread must not hide the state from the rest of the test.
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn schedule_appearance(&self, peer: VirtualPeer, round: u64) {
+        let sim = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(peer.appear_after).await;
+            let address = peer.address();
+            {
+                let mut state = sim.lock();
+                if !state.discovering || state.discovery_round != round {
+                    return;
+                }
+                // Seeking peers only hear from advertisers of a sought service.
+                let seek = &state.discovery.seek;
+                if !seek.is_empty() && !peer.advertises_any(seek) {
+                    return;
+                }
+                state.visible.insert(address, peer.device.clone());
+                state.emit(P2pEvent::PeerFound(Arc::new(peer.device.clone())));
+                if state.querying_services {
+                    state.answer_service_query(address);
+                }
+            }
+            if let Some(delay) = peer.disappear_after {
+                tokio::time::sleep(delay).await;
+                sim.lose_peer(address);
+            }
+        });
+    }
+
+    /// The scripted outcome of connecting to `peer`.
+    async fn negotiate(
+        &self,
+        peer: VirtualPeer,
+        config: ConnectConfig,
+    ) -> Result<Option<String>, P2pError> {
+        let address = peer.address();
+        match peer.behavior {
+            ConnectBehavior::Ignore => std::future::pending().await,
+            ConnectBehavior::Reject(status) => {
+                tokio::time::sleep(peer.connect_delay).await;
+                self.lock().emit(P2pEvent::GoNegotiationFailed {
+                    peer: Some(address),
+                    status,
+                });
+                Err(P2pError::Status(status))
+            }
+            ConnectBehavior::Accept => {
+                tokio::time::sleep(peer.connect_delay).await;
+                let mut state = self.lock();
+                if !state.connected.contains(&address) {
+                    state.connected.push(address);
+                }
+                if !state.persistent.iter().any(|group| group.peer == address) {
+                    let network_id = state.persistent.len() as u32;
+                    state.persistent.push(GroupCredentials {
+                        network_id,
+                        ssid: format!("DIRECT-{}", &address.to_hex()[8..]),
+                        psk: "sim-passphrase".to_string(),
+                        peer: address,
+                        role: GroupRole::Client,
+                    });
+                }
+                state.start_group(GroupRole::Client, Some(address));
+                let generated = (config.wps_method == WpsMethod::PinDisplay
+                    && config.pin.is_none())
+                .then(|| SIM_GENERATED_PIN.to_string());
+                Ok(generated)
+            }
+        }
+    }
+}
+
+impl P2pBackend for SimBackend {
+    async fn discover_peers(&self, options: DiscoveryOptions) -> Result<(), P2pError> {
+        let mut state = self.lock();
+        state.discovering = true;
+        state.discovery = options;
+        state.discovery_round += 1;
+        let round = state.discovery_round;
+        let pending: Vec<VirtualPeer> = state
+            .peers
+            .values()
+            .filter(|peer| !state.visible.contains_key(&peer.address()))
+            .cloned()
+            .collect();
+        drop(state);
+        for peer in pending {
+            self.schedule_appearance(peer, round);
+        }
+        Ok(())
+    }
+
+    async fn stop_discovery(&self) -> Result<(), P2pError> {
+        let mut state = self.lock();
+        state.querying_services = false;
+        if state.discovering {
+            state.discovering = false;
+            state.emit(P2pEvent::DiscoveryStopped);
+        }
+        Ok(())
+    }
+
+    async fn start_listening(&self) -> Result<(), P2pError> {
+        let mut state = self.lock();
+        state.listening = true;
+        state.listen_window = None;
+        Ok(())
+    }
+
+    async fn start_listening_windowed(
+        &self,
+        listen: Duration,
+        every: Duration,
+    ) -> Result<(), P2pError> {
+        let mut state = self.lock();
+        state.listening = true;
+        state.listen_window = Some((listen, every));
+        Ok(())
+    }
+
+    async fn stop_listening(&self) -> Result<(), P2pError> {
+        {
+            let mut state = self.lock();
+            state.listening = false;
+            state.listen_window = None;
+        }
+        // Like the supplicant's StopFind after ending extended listen.
+        self.stop_discovery().await
+    }
+
+    async fn connect(&self, config: ConnectConfig) -> Result<Option<String>, P2pError> {
+        let address = config.device_address;
+        let peer = {
+            let state = self.lock();
+            if !state.visible.contains_key(&address) {
+                return Err(P2pError::InvalidArgs(format!("unknown peer {address}")));
+            }
+            state.peers.get(&address).cloned()
+        };
+        self.lock().requests.retain(|request| *request != address);
+        let Some(peer) = peer else {
+            return Err(P2pError::InvalidArgs(format!("unknown peer {address}")));
+        };
+        let cancel = Arc::clone(&self.lock().cancel);
+        tokio::select! {
+            result = self.negotiate(peer, config) => result,
+            () = cancel.notified() => {
+                Err(P2pError::Cancelled(format!("connect to {address} was cancelled")))
+            }
+        }
+    }
+
+    async fn cancel_connect(&self) -> Result<(), P2pError> {
+        self.lock().cancel.notify_waiters();
+        Ok(())
+    }
+
+    async fn request_peers(&self) -> Result<Vec<P2pDevice>, P2pError> {
+        let mut peers: Vec<P2pDevice> = self.lock().visible.values().cloned().collect();
+        peers.sort_by_key(|peer| peer.mac_address);
+        Ok(peers)
+    }
+
+    async fn create_group(&self) -> Result<(), P2pError> {
+        self.lock().start_group(GroupRole::Owner, None);
+        Ok(())
+    }
+
+    async fn request_groups(&self) -> Result<Vec<P2pGroup>, P2pError> {
+        Ok(self.lock().group.iter().cloned().collect())
+    }
+
+    async fn invite(&self, peer: MacAddress) -> Result<(), P2pError> {
+        let mut state = self.lock();
+        if state.group.as_ref().map(|group| group.role) != Some(GroupRole::Owner) {
+            return Err(P2pError::NotConnected(
+                "no group we own to invite into".to_string(),
+            ));
+        }
+        if !state.visible.contains_key(&peer) {
+            return Err(P2pError::InvalidArgs(format!("unknown peer {peer}")));
+        }
+        // The peer answers the invitation as it would a connect.
+        let status = match state.peers.get(&peer).map(|peer| peer.behavior) {
+            Some(ConnectBehavior::Accept) => P2pStatusCode::Success,
+            Some(ConnectBehavior::Reject(status)) => status,
+            Some(ConnectBehavior::Ignore) | None => return Ok(()),
+        };
+        if status == P2pStatusCode::Success && !state.connected.contains(&peer) {
+            state.connected.push(peer);
+        }
+        state.emit(P2pEvent::InvitationResult { status });
+        Ok(())
+    }
+
+    async fn reject_peer(&self, peer: MacAddress) -> Result<(), P2pError> {
+        let mut state = self.lock();
+        if !state.visible.contains_key(&peer) {
+            return Err(P2pError::InvalidArgs(format!("unknown peer {peer}")));
+        }
+        state.requests.retain(|request| *request != peer);
+        Ok(())
+    }
+
+    async fn remove_groups(&self) -> Result<(), P2pError> {
+        let mut state = self.lock();
+        if let Some(group) = state.group.take() {
+            state.emit(P2pEvent::GroupFinished(Arc::new(group)));
+        }
+        state.connected.clear();
+        state.noa = None;
+        Ok(())
+    }
+
+    async fn set_noa(&self, schedule: NoaSchedule) -> Result<(), P2pError> {
+        schedule.check_interval()?;
+        let mut state = self.lock();
+        if state.group.is_none() {
+            return Err(P2pError::NotConnected("no active group".to_string()));
+        }
+        state.noa = Some(schedule);
+        Ok(())
+    }
+
+    async fn set_vendor_elements(
+        &self,
+        frame: VendorElementFrame,
+        elements: Vec<u8>,
+    ) -> Result<(), P2pError> {
+        let mut state = self.lock();
+        if elements.is_empty() {
+            state.vendor_elements.remove(&frame);
+        } else {
+            state.vendor_elements.insert(frame, elements);
+        }
+        Ok(())
+    }
+
+    async fn set_wfd_info(&self, info: Option<WfdDeviceInfo>) -> Result<(), P2pError> {
+        self.lock().wfd = info;
+        Ok(())
+    }
+
+    async fn resolve_peer_ip(&self, peer: MacAddress) -> Result<Option<IpAddr>, P2pError> {
+        let state = self.lock();
+        if !state.connected.contains(&peer) {
+            return Ok(None);
+        }
+        Ok(state.peers.get(&peer).and_then(|peer| peer.ip))
+    }
+
+    async fn link_stats(&self) -> Result<Vec<LinkStats>, P2pError> {
+        let state = self.lock();
+        let Some(group) = &state.group else {
+            return Ok(Vec::new());
+        };
+        let stats = state
+            .connected
+            .iter()
+            .map(|address| LinkStats {
+                peer: *address,
+                interface: group.interface.clone(),
+                rssi: state.peers.get(address).and_then(|peer| peer.rssi),
+                tx_rate: None,
+                rx_rate: None,
+                tx_failed: None,
+                tx_packets: None,
+                tx_retries: None,
+            })
+            .collect();
+        Ok(stats)
+    }
+
+    async fn persistent_groups(&self) -> Result<Vec<GroupCredentials>, P2pError> {
+        Ok(self.lock().persistent.clone())
+    }
+
+    async fn add_persistent_group(
+        &self,
+        mut credentials: GroupCredentials,
+    ) -> Result<u32, P2pError> {
+        let mut state = self.lock();
+        let network_id = state
+            .persistent
+            .iter()
+            .map(|group| group.network_id + 1)
+            .max()
+            .unwrap_or(0);
+        credentials.network_id = network_id;
+        state.persistent.push(credentials);
+        Ok(network_id)
+    }
+
+    async fn invoke_persistent_group(
+        &self,
+        network_id: u32,
+        peer: MacAddress,
+    ) -> Result<(), P2pError> {
+        // Like an invitation the peer accepts right away: no provisioning.
+        let mut state = self.lock();
+        let Some(group) = state
+            .persistent
+            .iter()
+            .find(|group| group.network_id == network_id)
+        else {
+            return Err(P2pError::InvalidArgs(format!(
+                "unknown persistent group {network_id}"
+            )));
+        };
+        let role = group.role;
+        match state.peers.get(&peer).map(|peer| peer.behavior) {
+            Some(ConnectBehavior::Accept) => {}
+            Some(_) => return Err(P2pError::Status(P2pStatusCode::RejectedByUser)),
+            None => return Err(P2pError::InvalidArgs(format!("unknown peer {peer}"))),
+        }
+        if !state.connected.contains(&peer) {
+            state.connected.push(peer);
+        }
+        state.start_group(role, Some(peer));
+        Ok(())
+    }
+
+    async fn factory_reset(&self) -> Result<(), P2pError> {
+        let mut state = self.lock();
+        state.persistent.clear();
+        state.services.clear();
+        state.querying_services = false;
+        if state.discovering {
+            state.discovering = false;
+            state.emit(P2pEvent::DiscoveryStopped);
+        }
+        let mut lost: Vec<MacAddress> = state.visible.drain().map(|(address, _)| address).collect();
+        lost.sort();
+        for address in lost {
+            state.emit(P2pEvent::PeerLost(address));
+        }
+        Ok(())
+    }
+
+    async fn add_service(&self, service: LocalService) -> Result<(), P2pError> {
+        let mut state = self.lock();
+        if !state.services.contains(&service) {
+            state.services.push(service);
+        }
+        Ok(())
+    }
+
+    async fn remove_service(&self, service: LocalService) -> Result<(), P2pError> {
+        let mut state = self.lock();
+        let count = state.services.len();
+        state.services.retain(|local| *local != service);
+        if state.services.len() == count {
+            return Err(P2pError::InvalidArgs("no such local service".to_string()));
+        }
+        Ok(())
+    }
+
+    async fn discover_services(&self, peer: Option<MacAddress>) -> Result<(), P2pError> {
+        let mut state = self.lock();
+        match peer {
+            Some(address) if !state.visible.contains_key(&address) => {
+                Err(P2pError::InvalidArgs(format!("unknown peer {address}")))
+            }
+            Some(address) => {
+                state.answer_service_query(address);
+                Ok(())
+            }
+            None => {
+                state.querying_services = true;
+                let mut visible: Vec<MacAddress> = state.visible.keys().copied().collect();
+                visible.sort();
+                for address in visible {
+                    state.answer_service_query(address);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    async fn cancel_service_discovery(&self, peer: Option<MacAddress>) -> Result<(), P2pError> {
+        // Queries to one peer are answered at once, so only the open one is left.
+        if peer.is_none() {
+            self.lock().querying_services = false;
+        }
+        Ok(())
+    }
+
+    async fn asp_provision(&self, request: AspProvision) -> Result<(), P2pError> {
+        let peer = request.peer;
+        let advertised = {
+            let state = self.lock();
+            state.visible.contains_key(&peer)
+                && state.peers.get(&peer).is_some_and(|virtual_peer| {
+                    virtual_peer.services.iter().any(|service| {
+                        matches!(service, LocalService::Asp(service)
+                            if service.advertisement_id == request.advertisement_id
+                                && service.available)
+                    })
+                })
+        };
+        if !advertised {
+            return Err(P2pError::InvalidArgs(format!(
+                "{peer} has no available service {:x}",
+                request.advertisement_id
+            )));
+        }
+        // The session comes up as a group like any other connection.
+        self.connect(ConnectConfig::new(peer)).await.map(drop)
+    }
+
+    async fn capabilities(&self) -> Result<P2pCapabilities, P2pError> {
+        Ok(self.lock().capabilities.clone())
+    }
+
+    async fn local_device(&self) -> Result<LocalDeviceInfo, P2pError> {
+        Ok(self.lock().local_device.clone())
+    }
+
+    async fn set_device_name(&self, name: String) -> Result<(), P2pError> {
+        self.lock().local_device.device_name = Some(name);
+        Ok(())
+    }
+
+    async fn ping(&self) -> Result<(), P2pError> {
+        Ok(())
+    }
+
+    async fn watch_events(&self, events: mpsc::Sender<P2pEvent>) -> Result<(), P2pError> {
+        // Events are pushed from the scripted timers; just park until the manager goes away.
+        self.lock().events = Some(events.clone());
+        events.closed().await;
+        Ok(())
+    }
+
+    async fn call_raw(
+        &self,
+        method: String,
+        _options: HashMap<String, OwnedValue>,
+    ) -> Result<Vec<OwnedValue>, P2pError> {
+        Err(P2pError::Unsupported(format!(
+            "the simulation backend has no raw method {method}"
+        )))
+    }
+}
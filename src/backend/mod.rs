@@ -1,26 +1,225 @@
+use std::collections::HashMap;
 use std::future::Future;
-use std::pin::Pin;
+use std::net::IpAddr;
+use std::time::Duration;
 
-use crate::error::P2pError;
+use tokio::sync::mpsc;
+use zbus::zvariant::OwnedValue;
 
-pub type P2pFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, P2pError>> + Send + 'a>>;
+use crate::capabilities::P2pCapabilities;
+use crate::channel::P2pEvent;
+use crate::config::{ConnectConfig, DiscoveryOptions};
+use crate::credentials::GroupCredentials;
+use crate::device::{LocalDeviceInfo, P2pDevice};
+use crate::error::P2pError;
+use crate::group::{NoaSchedule, P2pGroup};
+use crate::mac::MacAddress;
+use crate::service::{AspProvision, LocalService};
+use crate::stats::LinkStats;
+use crate::vendor::VendorElementFrame;
+use crate::wfd::WfdDeviceInfo;
 
-pub trait P2pBackend: Send + Sync {
-    /// Start a peer discovery scan (maps to p2p_find).
-    fn discover_peers(&self) -> P2pFuture<'_, ()>;
+/// Platform backend driving the actual P2P operations.
+///
+/// Methods return `Send` futures so the manager can run them on a spawned task;
+/// implementations can simply use `async fn`. Power-saving, vendor element,
+/// Wi-Fi Display, P2PS, link statistics, service discovery, persistent group and
+/// raw-call methods fail with `P2pError::Unsupported` unless a backend overrides
+/// them.
+pub trait P2pBackend: Send + Sync + 'static {
+    /// Start a peer discovery scan over the channels of `options.discovery_type`
+    /// (maps to p2p_find type=, freq= and dev_type=). Backends that cannot
+    /// filter by device type scan for every peer; the manager filters.
+    fn discover_peers(
+        &self,
+        options: DiscoveryOptions,
+    ) -> impl Future<Output = Result<(), P2pError>> + Send;
     /// Stop the ongoing peer discovery scan (maps to p2p_stop_find).
-    fn stop_discovery(&self) -> P2pFuture<'_, ()>;
-    /// Connect to a peer by device address (maps to p2p_connect).
-    fn connect(&self, device_address: String) -> P2pFuture<'_, ()>;
+    fn stop_discovery(&self) -> impl Future<Output = Result<(), P2pError>> + Send;
+    /// Stay in listen state between scans so peers can discover us (maps to
+    /// p2p_ext_listen). Backends report listen periods they end on their own as
+    /// `P2pEvent::ListenStateChanged`.
+    fn start_listening(&self) -> impl Future<Output = Result<(), P2pError>> + Send;
+    /// Listen for `listen` out of every `every` instead (maps to p2p_ext_listen
+    /// with that period and interval).
+    fn start_listening_windowed(
+        &self,
+        listen: Duration,
+        every: Duration,
+    ) -> impl Future<Output = Result<(), P2pError>> + Send;
+    /// Leave listen state (maps to p2p_ext_listen 0 0 and p2p_stop_find).
+    fn stop_listening(&self) -> impl Future<Output = Result<(), P2pError>> + Send;
+    /// Connect to a peer (maps to p2p_connect). Returns the generated PIN, if any.
+    fn connect(
+        &self,
+        config: ConnectConfig,
+    ) -> impl Future<Output = Result<Option<String>, P2pError>> + Send;
+    /// Abort a GO negotiation or group formation in progress (maps to p2p_cancel).
+    fn cancel_connect(&self) -> impl Future<Output = Result<(), P2pError>> + Send;
+    /// Fetch the peers currently known to the supplicant with their properties.
+    fn request_peers(&self) -> impl Future<Output = Result<Vec<P2pDevice>, P2pError>> + Send;
     /// Create a P2P group (maps to p2p_group_add).
-    fn create_group(&self) -> P2pFuture<'_, ()>;
+    fn create_group(&self) -> impl Future<Output = Result<(), P2pError>> + Send;
+    /// Groups currently up on this interface, including ones formed before we
+    /// started watching.
+    fn request_groups(&self) -> impl Future<Output = Result<Vec<P2pGroup>, P2pError>> + Send;
+    /// Invite `peer` into the group we own (maps to p2p_invite group=); the
+    /// outcome arrives as `P2pEvent::InvitationResult`.
+    fn invite(&self, peer: MacAddress) -> impl Future<Output = Result<(), P2pError>> + Send;
+    /// Turn down a pending connection request from `peer` (maps to p2p_reject).
+    fn reject_peer(&self, peer: MacAddress) -> impl Future<Output = Result<(), P2pError>> + Send;
+    /// Tear down every P2P group on this interface (maps to p2p_group_remove *).
+    /// Succeeds when there is no group to remove.
+    fn remove_groups(&self) -> impl Future<Output = Result<(), P2pError>> + Send;
+    /// Set the Notice of Absence schedule while acting as group owner (maps to p2p_set noa).
+    fn set_noa(&self, _schedule: NoaSchedule) -> impl Future<Output = Result<(), P2pError>> + Send {
+        async { Err(unsupported("set_noa")) }
+    }
+    /// Replace the vendor elements appended to a frame type; empty bytes clear them.
+    fn set_vendor_elements(
+        &self,
+        _frame: VendorElementFrame,
+        _elements: Vec<u8>,
+    ) -> impl Future<Output = Result<(), P2pError>> + Send {
+        async { Err(unsupported("set_vendor_elements")) }
+    }
+    /// Announce our WFD Device Information, or stop doing Wi-Fi Display with `None`.
+    fn set_wfd_info(
+        &self,
+        _info: Option<WfdDeviceInfo>,
+    ) -> impl Future<Output = Result<(), P2pError>> + Send {
+        async { Err(unsupported("set_wfd_info")) }
+    }
+    /// Look up a peer's IP address on the group interface it shares with us
+    /// (neighbour table, address allocation, ...). `None` while it is not known yet.
+    fn resolve_peer_ip(
+        &self,
+        peer: MacAddress,
+    ) -> impl Future<Output = Result<Option<IpAddr>, P2pError>> + Send;
+    /// Signal and bitrates of the link to every peer on our active groups.
+    fn link_stats(&self) -> impl Future<Output = Result<Vec<LinkStats>, P2pError>> + Send {
+        async { Err(unsupported("link_stats")) }
+    }
+    /// Persistent groups the supplicant has stored, with their credentials.
+    fn persistent_groups(
+        &self,
+    ) -> impl Future<Output = Result<Vec<GroupCredentials>, P2pError>> + Send {
+        async { Err(unsupported("persistent_groups")) }
+    }
+    /// Store a persistent group (e.g. one restored from a `CredentialStore`) and
+    /// return the network id it was given.
+    fn add_persistent_group(
+        &self,
+        _credentials: GroupCredentials,
+    ) -> impl Future<Output = Result<u32, P2pError>> + Send {
+        async { Err(unsupported("add_persistent_group")) }
+    }
+    /// Re-invoke persistent group `network_id` with `peer` (maps to p2p_invite persistent=).
+    fn invoke_persistent_group(
+        &self,
+        _network_id: u32,
+        _peer: MacAddress,
+    ) -> impl Future<Output = Result<(), P2pError>> + Send {
+        async { Err(unsupported("invoke_persistent_group")) }
+    }
+    /// Drop every persistent group, local service and pending service query and
+    /// forget all peers (maps to p2p_flush, p2p_service_flush and
+    /// remove_network of each persistent group).
+    fn factory_reset(&self) -> impl Future<Output = Result<(), P2pError>> + Send;
+    /// Answer service discovery queries for `service` (maps to p2p_service_add).
+    fn add_service(
+        &self,
+        _service: LocalService,
+    ) -> impl Future<Output = Result<(), P2pError>> + Send {
+        async { Err(unsupported("add_service")) }
+    }
+    /// Stop answering for `service` (maps to p2p_service_del).
+    fn remove_service(
+        &self,
+        _service: LocalService,
+    ) -> impl Future<Output = Result<(), P2pError>> + Send {
+        async { Err(unsupported("remove_service")) }
+    }
+    /// Query `peer`, or every peer discovery finds, for all its services (maps to
+    /// p2p_serv_disc_req); answers arrive as `P2pEvent::ServicesFound`.
+    fn discover_services(
+        &self,
+        _peer: Option<MacAddress>,
+    ) -> impl Future<Output = Result<(), P2pError>> + Send {
+        async { Err(unsupported("discover_services")) }
+    }
+    /// Withdraw the query `discover_services(peer)` sent (maps to
+    /// p2p_serv_disc_cancel_req). Succeeds when none is pending.
+    fn cancel_service_discovery(
+        &self,
+        _peer: Option<MacAddress>,
+    ) -> impl Future<Output = Result<(), P2pError>> + Send {
+        async { Err(unsupported("cancel_service_discovery")) }
+    }
+    /// Open a session with a peer's P2PS service through provision discovery
+    /// (maps to p2p_asp_provision); the group it forms arrives as `GroupStarted`.
+    fn asp_provision(
+        &self,
+        _request: AspProvision,
+    ) -> impl Future<Output = Result<(), P2pError>> + Send {
+        async { Err(unsupported("asp_provision")) }
+    }
+    /// Query what the supplicant and driver support on this interface.
+    fn capabilities(&self) -> impl Future<Output = Result<P2pCapabilities, P2pError>> + Send;
+    /// Our own device's address, name and WPS settings as peers currently see them.
+    fn local_device(&self) -> impl Future<Output = Result<LocalDeviceInfo, P2pError>> + Send;
+    /// Change the device name advertised to peers (maps to P2PDeviceConfig DeviceName).
+    fn set_device_name(&self, name: String) -> impl Future<Output = Result<(), P2pError>> + Send;
+    /// Cheaply check that the supplicant (or platform service) answers, e.g. by
+    /// reading one property. Used by `WifiP2pManager::health`.
+    fn ping(&self) -> impl Future<Output = Result<(), P2pError>> + Send;
+    /// Forward asynchronous supplicant notifications (peers found/lost, negotiation
+    /// results, ...) as events until the sender closes or the signal streams end.
+    fn watch_events(
+        &self,
+        events: mpsc::Sender<P2pEvent>,
+    ) -> impl Future<Output = Result<(), P2pError>> + Send;
+    /// Call an arbitrary P2P method taking a{sv} options and return its reply arguments.
+    fn call_raw(
+        &self,
+        _method: String,
+        _options: HashMap<String, OwnedValue>,
+    ) -> impl Future<Output = Result<Vec<OwnedValue>, P2pError>> + Send {
+        async { Err(unsupported("call_raw")) }
+    }
+}
+
+fn unsupported(operation: &str) -> P2pError {
+    P2pError::Unsupported(format!("{operation} is not supported by this backend"))
 }
 
+#[cfg(all(feature = "android", target_os = "android"))]
+pub mod android;
+#[cfg(target_os = "linux")]
+pub mod iwd;
 #[cfg(target_os = "linux")]
 pub mod linux;
+#[cfg(feature = "tokio")]
+pub mod mock;
+#[cfg(any(feature = "noop", not(any(target_os = "linux", target_os = "windows"))))]
+pub mod noop;
+#[cfg(feature = "recording")]
+pub mod recording;
+#[cfg(target_os = "windows")]
+pub mod windows;
 
+#[cfg(target_os = "windows")]
+pub use self::windows::{WindowsBackend, WindowsBackend as P2pBackendImpl};
+#[cfg(all(feature = "android", target_os = "android"))]
+pub use android::AndroidBackend;
+#[cfg(target_os = "linux")]
+pub use iwd::IwdBackend;
 #[cfg(target_os = "linux")]
-pub use linux::P2pBackendImpl;
+pub use linux::{InterfaceConfig, P2pBackendImpl};
+#[cfg(any(feature = "noop", not(any(target_os = "linux", target_os = "windows"))))]
+pub use noop::NoopBackend;
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+pub use noop::NoopBackend as P2pBackendImpl;
 
-#[cfg(not(target_os = "linux"))]
-compile_error!("Only Linux is supported right now. Add a platform backend for this target.");
+#[cfg(not(any(feature = "tokio", feature = "async-io")))]
+compile_error!("Enable either the `tokio` or the `async-io` feature to select an I/O reactor.");
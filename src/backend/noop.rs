@@ -0,0 +1,130 @@
+use std::net::IpAddr;
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+
+use crate::capabilities::P2pCapabilities;
+use crate::channel::P2pEvent;
+use crate::config::{ConnectConfig, DiscoveryOptions};
+use crate::device::{LocalDeviceInfo, P2pDevice};
+use crate::error::P2pError;
+use crate::group::P2pGroup;
+use crate::mac::MacAddress;
+
+use super::P2pBackend;
+
+/// Placeholder backend for targets without Wi-Fi Direct support.
+///
+/// It is the platform backend wherever no real one exists, so the crate still
+/// builds in cross-platform workspaces; every operation fails with
+/// `P2pError::UnsupportedPlatform` (the optional ones keep the trait's
+/// `P2pError::Unsupported`), letting applications disable P2P at runtime.
+/// The `noop` feature also exposes it on supported platforms.
+#[derive(Debug, Clone)]
+pub struct NoopBackend {
+    interface_name: String,
+}
+
+impl NoopBackend {
+    /// Always succeeds, so a manager can be built and its calls inspected.
+    pub async fn new(interface_name: &str) -> Result<Self, P2pError> {
+        Ok(Self {
+            interface_name: interface_name.to_string(),
+        })
+    }
+
+    fn unsupported(&self) -> P2pError {
+        P2pError::UnsupportedPlatform(format!(
+            "no Wi-Fi Direct backend for {} ({})",
+            std::env::consts::OS,
+            self.interface_name
+        ))
+    }
+}
+
+impl P2pBackend for NoopBackend {
+    async fn discover_peers(&self, _options: DiscoveryOptions) -> Result<(), P2pError> {
+        Err(self.unsupported())
+    }
+
+    async fn stop_discovery(&self) -> Result<(), P2pError> {
+        // Nothing can be running; keeps shutdown and drop cleanup quiet.
+        Ok(())
+    }
+
+    async fn start_listening(&self) -> Result<(), P2pError> {
+        Err(self.unsupported())
+    }
+
+    async fn start_listening_windowed(
+        &self,
+        _listen: Duration,
+        _every: Duration,
+    ) -> Result<(), P2pError> {
+        Err(self.unsupported())
+    }
+
+    async fn stop_listening(&self) -> Result<(), P2pError> {
+        Ok(())
+    }
+
+    async fn connect(&self, _config: ConnectConfig) -> Result<Option<String>, P2pError> {
+        Err(self.unsupported())
+    }
+
+    async fn cancel_connect(&self) -> Result<(), P2pError> {
+        Err(self.unsupported())
+    }
+
+    async fn request_peers(&self) -> Result<Vec<P2pDevice>, P2pError> {
+        Err(self.unsupported())
+    }
+
+    async fn create_group(&self) -> Result<(), P2pError> {
+        Err(self.unsupported())
+    }
+
+    async fn request_groups(&self) -> Result<Vec<P2pGroup>, P2pError> {
+        Err(self.unsupported())
+    }
+
+    async fn invite(&self, _peer: MacAddress) -> Result<(), P2pError> {
+        Err(self.unsupported())
+    }
+
+    async fn reject_peer(&self, _peer: MacAddress) -> Result<(), P2pError> {
+        Err(self.unsupported())
+    }
+
+    async fn remove_groups(&self) -> Result<(), P2pError> {
+        Ok(())
+    }
+
+    async fn resolve_peer_ip(&self, _peer: MacAddress) -> Result<Option<IpAddr>, P2pError> {
+        Err(self.unsupported())
+    }
+
+    async fn factory_reset(&self) -> Result<(), P2pError> {
+        Err(self.unsupported())
+    }
+
+    async fn capabilities(&self) -> Result<P2pCapabilities, P2pError> {
+        Err(self.unsupported())
+    }
+
+    async fn local_device(&self) -> Result<LocalDeviceInfo, P2pError> {
+        Err(self.unsupported())
+    }
+
+    async fn set_device_name(&self, _name: String) -> Result<(), P2pError> {
+        Err(self.unsupported())
+    }
+
+    async fn ping(&self) -> Result<(), P2pError> {
+        Err(self.unsupported())
+    }
+
+    async fn watch_events(&self, _events: mpsc::Sender<P2pEvent>) -> Result<(), P2pError> {
+        Err(self.unsupported())
+    }
+}
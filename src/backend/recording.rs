@@ -0,0 +1,1014 @@
+//! Record a backend session to a file and replay it for deterministic tests.
+//!
+//! `RecordingBackend` wraps any backend and appends every call with its result,
+//! and every event the backend emits, to a JSON-lines file. `ReplayBackend`
+//! feeds such a file back: events are delivered in their recorded order around
+//! the calls, and each call must match the next recorded one, so a capture
+//! attached to a bug report becomes a regression test.
+//!
+//! `Recorder` writes the same format for a manager that is already running,
+//! from one of its channels, adding the commands the channel sent, the events
+//! it saw and (on Linux) the raw D-Bus signals of wpa_supplicant. Those extra
+//! entries are there for reading; replay skips them.
+
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::future::Future;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Notify, mpsc, watch};
+use tokio_stream::StreamExt;
+use zbus::zvariant::OwnedValue;
+
+use crate::capabilities::P2pCapabilities;
+use crate::channel::{P2pEvent, WifiP2pChannel};
+use crate::config::{ConnectConfig, DiscoveryOptions};
+use crate::credentials::GroupCredentials;
+use crate::device::{LocalDeviceInfo, P2pDevice};
+use crate::error::P2pError;
+use crate::executor::Executor;
+use crate::group::{NoaSchedule, P2pGroup};
+use crate::mac::MacAddress;
+use crate::service::{AspProvision, LocalService};
+use crate::stats::LinkStats;
+use crate::status::P2pStatusCode;
+use crate::vendor::VendorElementFrame;
+use crate::wfd::WfdDeviceInfo;
+
+use super::P2pBackend;
+
+/// One line of a recording.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RecordedEntry {
+    /// A backend call and what it returned.
+    Call {
+        /// Milliseconds since recording started, for reading captures.
+        at_ms: u64,
+        call: RecordedCall,
+        result: Result<RecordedValue, RecordedError>,
+    },
+    /// An event forwarded by `watch_events`.
+    Event { at_ms: u64, event: P2pEvent },
+    /// A command a channel queued for the manager (`Recorder` only).
+    Command {
+        at_ms: u64,
+        command: String,
+        #[serde(default)]
+        timeout_ms: Option<u64>,
+    },
+    /// An event the manager published to its channels (`Recorder` only).
+    Emitted { at_ms: u64, event: P2pEvent },
+    /// A D-Bus signal from wpa_supplicant, before the backend decoded it
+    /// (`Recorder::record_signals` only).
+    Signal {
+        at_ms: u64,
+        path: String,
+        interface: String,
+        member: String,
+        /// The arguments in GVariant text form.
+        args: String,
+    },
+}
+
+/// The backend method that was called, with its arguments.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RecordedCall {
+    DiscoverPeers(DiscoveryOptions),
+    StopDiscovery,
+    StartListening,
+    StartListeningWindowed {
+        listen: Duration,
+        every: Duration,
+    },
+    StopListening,
+    Connect(ConnectConfig),
+    CancelConnect,
+    RequestPeers,
+    RequestGroups,
+    CreateGroup,
+    Invite(MacAddress),
+    RejectPeer(MacAddress),
+    RemoveGroups,
+    SetNoa(NoaSchedule),
+    SetVendorElements {
+        frame: VendorElementFrame,
+        elements: Vec<u8>,
+    },
+    SetWfdInfo(Option<WfdDeviceInfo>),
+    ResolvePeerIp(MacAddress),
+    LinkStats,
+    PersistentGroups,
+    /// Credentials are recorded without their PSK.
+    AddPersistentGroup(GroupCredentials),
+    InvokePersistentGroup {
+        network_id: u32,
+        peer: MacAddress,
+    },
+    FactoryReset,
+    AddService(LocalService),
+    RemoveService(LocalService),
+    DiscoverServices(Option<MacAddress>),
+    CancelServiceDiscovery(Option<MacAddress>),
+    AspProvision(AspProvision),
+    Capabilities,
+    SetDeviceName(String),
+    CallRaw {
+        method: String,
+    },
+}
+
+/// A successful call result.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RecordedValue {
+    Unit,
+    /// The generated PIN returned by `connect`.
+    Pin(Option<String>),
+    Peers(Vec<P2pDevice>),
+    Groups(Vec<P2pGroup>),
+    /// The address returned by `resolve_peer_ip`.
+    Ip(Option<IpAddr>),
+    LinkStats(Vec<LinkStats>),
+    /// Persistent groups without their PSKs.
+    PersistentGroups(Vec<GroupCredentials>),
+    /// The network id returned by `add_persistent_group`.
+    NetworkId(u32),
+    Capabilities(P2pCapabilities),
+    /// `call_raw` succeeded; D-Bus values have no stable file format, so only
+    /// the number of reply arguments is kept.
+    Raw {
+        replies: usize,
+    },
+}
+
+/// A failed call result, precise enough to rebuild the `P2pError` variant.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedError {
+    /// `P2pError::kind()` of the original error.
+    pub kind: String,
+    /// The variant's detail text (or the full message for D-Bus errors).
+    pub message: String,
+    /// Timeout duration or P2P status code, for the variants that carry one.
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    #[serde(default)]
+    pub status: Option<P2pStatusCode>,
+}
+
+impl From<&P2pError> for RecordedError {
+    fn from(err: &P2pError) -> Self {
+        let mut recorded = Self {
+            kind: err.kind().to_string(),
+            message: err.to_string(),
+            timeout_ms: None,
+            status: None,
+        };
+        match err {
+            P2pError::ChannelClosed(detail)
+            | P2pError::InvalidInterface(detail)
+            | P2pError::InvalidMacAddress(detail)
+            | P2pError::InvalidConfig(detail)
+            | P2pError::Cancelled(detail)
+            | P2pError::InterfaceUnknown(detail)
+            | P2pError::NotConnected(detail)
+            | P2pError::InvalidArgs(detail)
+            | P2pError::Busy(detail)
+            | P2pError::PermissionDenied(detail)
+            | P2pError::Unsupported(detail)
+            | P2pError::SupplicantUnavailable(detail)
+            | P2pError::RadioBlocked(detail)
+            | P2pError::Blob(detail)
+            | P2pError::UnsupportedPlatform(detail)
+            | P2pError::Backend(detail) => recorded.message = detail.clone(),
+            P2pError::Timeout(limit) => recorded.timeout_ms = Some(limit.as_millis() as u64),
+            P2pError::Status(status) => recorded.status = Some(*status),
+            P2pError::DBus(_) | P2pError::ZVariant(_) => {}
+        }
+        recorded
+    }
+}
+
+impl RecordedError {
+    /// Rebuild the error; D-Bus errors cannot be reconstructed and come back as `Backend`.
+    pub fn to_error(&self) -> P2pError {
+        let message = self.message.clone();
+        match self.kind.as_str() {
+            "ChannelClosed" => P2pError::ChannelClosed(message),
+            "InvalidInterface" => P2pError::InvalidInterface(message),
+            "InvalidMacAddress" => P2pError::InvalidMacAddress(message),
+            "InvalidConfig" => P2pError::InvalidConfig(message),
+            "Cancelled" => P2pError::Cancelled(message),
+            "InterfaceUnknown" => P2pError::InterfaceUnknown(message),
+            "NotConnected" => P2pError::NotConnected(message),
+            "InvalidArgs" => P2pError::InvalidArgs(message),
+            "Busy" => P2pError::Busy(message),
+            "PermissionDenied" => P2pError::PermissionDenied(message),
+            "Unsupported" => P2pError::Unsupported(message),
+            "SupplicantUnavailable" => P2pError::SupplicantUnavailable(message),
+            "RadioBlocked" => P2pError::RadioBlocked(message),
+            "Blob" => P2pError::Blob(message),
+            "UnsupportedPlatform" => P2pError::UnsupportedPlatform(message),
+            "Timeout" => P2pError::Timeout(Duration::from_millis(self.timeout_ms.unwrap_or(0))),
+            "Status" => match self.status {
+                Some(status) => P2pError::Status(status),
+                None => P2pError::Backend(message),
+            },
+            _ => P2pError::Backend(message),
+        }
+    }
+}
+
+fn recording_error(err: impl std::fmt::Display) -> P2pError {
+    P2pError::Backend(format!("recording: {err}"))
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // A panicking test thread must not hide the session from the rest of the test.
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// An open capture file; entries are timed from its creation.
+struct Capture {
+    writer: Mutex<BufWriter<File>>,
+    started: Instant,
+}
+
+impl Capture {
+    fn create(path: &Path) -> Result<Self, P2pError> {
+        let file = File::create(path).map_err(recording_error)?;
+        Ok(Self {
+            writer: Mutex::new(BufWriter::new(file)),
+            started: Instant::now(),
+        })
+    }
+
+    fn write(&self, entry: impl FnOnce(u64) -> RecordedEntry) {
+        // Flush per entry so a crash still leaves a usable capture. A failed write
+        // must not change what the caller sees, so it is dropped.
+        let entry = entry(self.started.elapsed().as_millis() as u64);
+        let Ok(line) = serde_json::to_string(&entry) else {
+            return;
+        };
+        let mut writer = lock(&self.writer);
+        let _ = writeln!(writer, "{line}").and_then(|()| writer.flush());
+    }
+}
+
+/// The recorders attached to one manager, shared by its channels and the
+/// recording layer around its backend.
+#[derive(Clone, Default)]
+pub(crate) struct Taps(Arc<Mutex<Vec<Weak<Capture>>>>);
+
+impl Taps {
+    fn attach(&self, capture: &Arc<Capture>) {
+        lock(&self.0).push(Arc::downgrade(capture));
+    }
+
+    fn is_active(&self) -> bool {
+        lock(&self.0)
+            .iter()
+            .any(|capture| capture.strong_count() > 0)
+    }
+
+    /// Write to every attached recorder; `entry` is only built when there is one.
+    pub(crate) fn write(&self, entry: impl Fn(u64) -> RecordedEntry) {
+        let captures: Vec<Arc<Capture>> = {
+            let mut taps = lock(&self.0);
+            taps.retain(|capture| capture.strong_count() > 0);
+            taps.iter().filter_map(Weak::upgrade).collect()
+        };
+        for capture in captures {
+            capture.write(&entry);
+        }
+    }
+}
+
+enum Sink {
+    File(Capture),
+    Taps(Taps),
+}
+
+/// Wraps a backend and writes every call, result and event to a file.
+pub struct RecordingBackend<B> {
+    inner: Arc<B>,
+    sink: Sink,
+}
+
+impl<B: P2pBackend> RecordingBackend<B> {
+    /// Record `inner` into `path`, replacing any existing file.
+    pub fn new(inner: B, path: impl AsRef<Path>) -> Result<Self, P2pError> {
+        Ok(Self {
+            inner: Arc::new(inner),
+            sink: Sink::File(Capture::create(path.as_ref())?),
+        })
+    }
+
+    /// Record `inner` into whichever recorders get attached to `taps`; the
+    /// manager drives its backend through this.
+    pub(crate) fn tapped(inner: Arc<B>, taps: Taps) -> Self {
+        Self {
+            inner,
+            sink: Sink::Taps(taps),
+        }
+    }
+
+    /// The wrapped backend.
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    fn write(&self, entry: impl Fn(u64) -> RecordedEntry) {
+        match &self.sink {
+            Sink::File(capture) => capture.write(entry),
+            Sink::Taps(taps) => taps.write(entry),
+        }
+    }
+
+    fn record<T>(
+        &self,
+        call: RecordedCall,
+        result: Result<T, P2pError>,
+        value: impl FnOnce(&T) -> RecordedValue,
+    ) -> Result<T, P2pError> {
+        if let Sink::Taps(taps) = &self.sink
+            && !taps.is_active()
+        {
+            return result;
+        }
+        let recorded = match &result {
+            Ok(ok) => Ok(value(ok)),
+            Err(err) => Err(RecordedError::from(err)),
+        };
+        self.write(|at_ms| RecordedEntry::Call {
+            at_ms,
+            call: call.clone(),
+            result: recorded.clone(),
+        });
+        result
+    }
+}
+
+impl<B: P2pBackend> P2pBackend for RecordingBackend<B> {
+    async fn discover_peers(&self, options: DiscoveryOptions) -> Result<(), P2pError> {
+        let result = self.inner.discover_peers(options.clone()).await;
+        self.record(RecordedCall::DiscoverPeers(options), result, |()| {
+            RecordedValue::Unit
+        })
+    }
+
+    async fn stop_discovery(&self) -> Result<(), P2pError> {
+        let result = self.inner.stop_discovery().await;
+        self.record(RecordedCall::StopDiscovery, result, |()| {
+            RecordedValue::Unit
+        })
+    }
+
+    async fn start_listening(&self) -> Result<(), P2pError> {
+        let result = self.inner.start_listening().await;
+        self.record(RecordedCall::StartListening, result, |()| {
+            RecordedValue::Unit
+        })
+    }
+
+    async fn start_listening_windowed(
+        &self,
+        listen: Duration,
+        every: Duration,
+    ) -> Result<(), P2pError> {
+        let result = self.inner.start_listening_windowed(listen, every).await;
+        let call = RecordedCall::StartListeningWindowed { listen, every };
+        self.record(call, result, |()| RecordedValue::Unit)
+    }
+
+    async fn stop_listening(&self) -> Result<(), P2pError> {
+        let result = self.inner.stop_listening().await;
+        self.record(RecordedCall::StopListening, result, |()| {
+            RecordedValue::Unit
+        })
+    }
+
+    async fn connect(&self, config: ConnectConfig) -> Result<Option<String>, P2pError> {
+        let result = self.inner.connect(config.clone()).await;
+        self.record(RecordedCall::Connect(config), result, |pin| {
+            RecordedValue::Pin(pin.clone())
+        })
+    }
+
+    async fn cancel_connect(&self) -> Result<(), P2pError> {
+        let result = self.inner.cancel_connect().await;
+        self.record(RecordedCall::CancelConnect, result, |()| {
+            RecordedValue::Unit
+        })
+    }
+
+    async fn request_peers(&self) -> Result<Vec<P2pDevice>, P2pError> {
+        let result = self.inner.request_peers().await;
+        self.record(RecordedCall::RequestPeers, result, |peers| {
+            RecordedValue::Peers(peers.clone())
+        })
+    }
+
+    async fn request_groups(&self) -> Result<Vec<P2pGroup>, P2pError> {
+        let result = self.inner.request_groups().await;
+        self.record(RecordedCall::RequestGroups, result, |groups| {
+            RecordedValue::Groups(groups.clone())
+        })
+    }
+
+    async fn create_group(&self) -> Result<(), P2pError> {
+        let result = self.inner.create_group().await;
+        self.record(RecordedCall::CreateGroup, result, |()| RecordedValue::Unit)
+    }
+
+    async fn invite(&self, peer: MacAddress) -> Result<(), P2pError> {
+        let result = self.inner.invite(peer).await;
+        self.record(RecordedCall::Invite(peer), result, |()| RecordedValue::Unit)
+    }
+
+    async fn reject_peer(&self, peer: MacAddress) -> Result<(), P2pError> {
+        let result = self.inner.reject_peer(peer).await;
+        self.record(RecordedCall::RejectPeer(peer), result, |()| {
+            RecordedValue::Unit
+        })
+    }
+
+    async fn remove_groups(&self) -> Result<(), P2pError> {
+        let result = self.inner.remove_groups().await;
+        self.record(RecordedCall::RemoveGroups, result, |()| RecordedValue::Unit)
+    }
+
+    async fn set_noa(&self, schedule: NoaSchedule) -> Result<(), P2pError> {
+        let result = self.inner.set_noa(schedule).await;
+        self.record(RecordedCall::SetNoa(schedule), result, |()| {
+            RecordedValue::Unit
+        })
+    }
+
+    async fn set_vendor_elements(
+        &self,
+        frame: VendorElementFrame,
+        elements: Vec<u8>,
+    ) -> Result<(), P2pError> {
+        let result = self
+            .inner
+            .set_vendor_elements(frame, elements.clone())
+            .await;
+        let call = RecordedCall::SetVendorElements { frame, elements };
+        self.record(call, result, |()| RecordedValue::Unit)
+    }
+
+    async fn set_wfd_info(&self, info: Option<WfdDeviceInfo>) -> Result<(), P2pError> {
+        let result = self.inner.set_wfd_info(info).await;
+        self.record(RecordedCall::SetWfdInfo(info), result, |()| {
+            RecordedValue::Unit
+        })
+    }
+
+    async fn resolve_peer_ip(&self, peer: MacAddress) -> Result<Option<IpAddr>, P2pError> {
+        let result = self.inner.resolve_peer_ip(peer).await;
+        self.record(RecordedCall::ResolvePeerIp(peer), result, |ip| {
+            RecordedValue::Ip(*ip)
+        })
+    }
+
+    async fn link_stats(&self) -> Result<Vec<LinkStats>, P2pError> {
+        let result = self.inner.link_stats().await;
+        self.record(RecordedCall::LinkStats, result, |stats| {
+            RecordedValue::LinkStats(stats.clone())
+        })
+    }
+
+    async fn persistent_groups(&self) -> Result<Vec<GroupCredentials>, P2pError> {
+        let result = self.inner.persistent_groups().await;
+        self.record(RecordedCall::PersistentGroups, result, |groups| {
+            RecordedValue::PersistentGroups(groups.iter().map(redacted).collect())
+        })
+    }
+
+    async fn add_persistent_group(&self, credentials: GroupCredentials) -> Result<u32, P2pError> {
+        let call = RecordedCall::AddPersistentGroup(redacted(&credentials));
+        let result = self.inner.add_persistent_group(credentials).await;
+        self.record(call, result, |network_id| {
+            RecordedValue::NetworkId(*network_id)
+        })
+    }
+
+    async fn invoke_persistent_group(
+        &self,
+        network_id: u32,
+        peer: MacAddress,
+    ) -> Result<(), P2pError> {
+        let result = self.inner.invoke_persistent_group(network_id, peer).await;
+        let call = RecordedCall::InvokePersistentGroup { network_id, peer };
+        self.record(call, result, |()| RecordedValue::Unit)
+    }
+
+    async fn factory_reset(&self) -> Result<(), P2pError> {
+        let result = self.inner.factory_reset().await;
+        self.record(RecordedCall::FactoryReset, result, |()| RecordedValue::Unit)
+    }
+
+    async fn add_service(&self, service: LocalService) -> Result<(), P2pError> {
+        let call = RecordedCall::AddService(service.clone());
+        let result = self.inner.add_service(service).await;
+        self.record(call, result, |()| RecordedValue::Unit)
+    }
+
+    async fn remove_service(&self, service: LocalService) -> Result<(), P2pError> {
+        let call = RecordedCall::RemoveService(service.clone());
+        let result = self.inner.remove_service(service).await;
+        self.record(call, result, |()| RecordedValue::Unit)
+    }
+
+    async fn discover_services(&self, peer: Option<MacAddress>) -> Result<(), P2pError> {
+        let result = self.inner.discover_services(peer).await;
+        self.record(RecordedCall::DiscoverServices(peer), result, |()| {
+            RecordedValue::Unit
+        })
+    }
+
+    async fn cancel_service_discovery(&self, peer: Option<MacAddress>) -> Result<(), P2pError> {
+        let result = self.inner.cancel_service_discovery(peer).await;
+        self.record(RecordedCall::CancelServiceDiscovery(peer), result, |()| {
+            RecordedValue::Unit
+        })
+    }
+
+    async fn asp_provision(&self, request: AspProvision) -> Result<(), P2pError> {
+        let result = self.inner.asp_provision(request.clone()).await;
+        self.record(RecordedCall::AspProvision(request), result, |()| {
+            RecordedValue::Unit
+        })
+    }
+
+    async fn capabilities(&self) -> Result<P2pCapabilities, P2pError> {
+        let result = self.inner.capabilities().await;
+        self.record(RecordedCall::Capabilities, result, |capabilities| {
+            RecordedValue::Capabilities(capabilities.clone())
+        })
+    }
+
+    async fn local_device(&self) -> Result<LocalDeviceInfo, P2pError> {
+        // The manager re-reads it after group and backend events; recording it
+        // would make replays depend on the backend reporting it at all.
+        self.inner.local_device().await
+    }
+
+    async fn set_device_name(&self, name: String) -> Result<(), P2pError> {
+        let result = self.inner.set_device_name(name.clone()).await;
+        self.record(RecordedCall::SetDeviceName(name), result, |()| {
+            RecordedValue::Unit
+        })
+    }
+
+    async fn ping(&self) -> Result<(), P2pError> {
+        // Health probes run on their own clock; recording them would make replays
+        // depend on when they happened.
+        self.inner.ping().await
+    }
+
+    async fn watch_events(&self, events: mpsc::Sender<P2pEvent>) -> Result<(), P2pError> {
+        // Interpose a channel so every event is written before it is forwarded.
+        let (tx, mut rx) = mpsc::channel::<P2pEvent>(64);
+        let forward = async move {
+            while let Some(event) = rx.recv().await {
+                self.write(|at_ms| RecordedEntry::Event {
+                    at_ms,
+                    event: event.clone(),
+                });
+                if events.send(event).await.is_err() {
+                    // Dropping `rx` here tells the inner watcher to stop too.
+                    return;
+                }
+            }
+        };
+        let (result, ()) = tokio::join!(self.inner.watch_events(tx), forward);
+        result
+    }
+
+    async fn call_raw(
+        &self,
+        method: String,
+        options: HashMap<String, OwnedValue>,
+    ) -> Result<Vec<OwnedValue>, P2pError> {
+        let result = self.inner.call_raw(method.clone(), options).await;
+        self.record(RecordedCall::CallRaw { method }, result, |replies| {
+            RecordedValue::Raw {
+                replies: replies.len(),
+            }
+        })
+    }
+}
+
+/// Plays a recording back in place of a real backend.
+///
+/// Events recorded before a call are delivered before that call returns, and
+/// each call must match the next recorded call (including its arguments);
+/// a mismatch or running past the end fails with `P2pError::InvalidArgs`.
+/// `call_raw` replays its outcome but returns no reply arguments.
+#[derive(Clone)]
+pub struct ReplayBackend {
+    entries: Arc<Mutex<VecDeque<RecordedEntry>>>,
+    progressed: Arc<Notify>,
+}
+
+impl ReplayBackend {
+    /// Load a capture written by `RecordingBackend` or `Recorder`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, P2pError> {
+        let file = File::open(path).map_err(recording_error)?;
+        let mut entries = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line.map_err(recording_error)?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            entries.push(serde_json::from_str(&line).map_err(recording_error)?);
+        }
+        Ok(Self::from_entries(entries))
+    }
+
+    /// Replay entries built in code, e.g. a trimmed-down capture. Only calls and
+    /// backend events are replayed; the other entries are dropped.
+    pub fn from_entries(entries: impl IntoIterator<Item = RecordedEntry>) -> Self {
+        let entries = entries.into_iter().filter(|entry| {
+            matches!(
+                entry,
+                RecordedEntry::Call { .. } | RecordedEntry::Event { .. }
+            )
+        });
+        Self {
+            entries: Arc::new(Mutex::new(entries.collect())),
+            progressed: Arc::new(Notify::new()),
+        }
+    }
+
+    /// Entries not yet replayed; zero once the session has been played in full.
+    pub fn remaining(&self) -> usize {
+        lock(&self.entries).len()
+    }
+
+    async fn replay(&self, call: RecordedCall) -> Result<RecordedValue, P2pError> {
+        loop {
+            // Register for wakeups before looking, so progress in between is not missed.
+            let progressed = self.progressed.notified();
+            {
+                let mut entries = lock(&self.entries);
+                match entries.front() {
+                    // The watcher has not delivered the events preceding this call yet.
+                    Some(RecordedEntry::Event { .. }) => {}
+                    Some(
+                        RecordedEntry::Command { .. }
+                        | RecordedEntry::Emitted { .. }
+                        | RecordedEntry::Signal { .. },
+                    ) => unreachable!("from_entries keeps only calls and events"),
+                    Some(RecordedEntry::Call { call: expected, .. }) => {
+                        if *expected != call {
+                            return Err(P2pError::InvalidArgs(format!(
+                                "replay diverged: expected {expected:?}, got {call:?}"
+                            )));
+                        }
+                        let Some(RecordedEntry::Call { result, .. }) = entries.pop_front() else {
+                            unreachable!("front entry was a call");
+                        };
+                        drop(entries);
+                        self.progressed.notify_waiters();
+                        return result.map_err(|err| err.to_error());
+                    }
+                    None => {
+                        return Err(P2pError::InvalidArgs(format!(
+                            "replay finished, unexpected {call:?}"
+                        )));
+                    }
+                }
+            }
+            progressed.await;
+        }
+    }
+}
+
+fn unexpected(call: &str, value: RecordedValue) -> P2pError {
+    P2pError::InvalidArgs(format!("recorded {value:?} does not fit {call}"))
+}
+
+/// Captures end up in bug reports; keep group keys out of them.
+fn redacted(credentials: &GroupCredentials) -> GroupCredentials {
+    GroupCredentials {
+        psk: String::new(),
+        ..credentials.clone()
+    }
+}
+
+impl P2pBackend for ReplayBackend {
+    async fn discover_peers(&self, options: DiscoveryOptions) -> Result<(), P2pError> {
+        self.replay(RecordedCall::DiscoverPeers(options))
+            .await
+            .map(drop)
+    }
+
+    async fn stop_discovery(&self) -> Result<(), P2pError> {
+        self.replay(RecordedCall::StopDiscovery).await.map(drop)
+    }
+
+    async fn start_listening(&self) -> Result<(), P2pError> {
+        self.replay(RecordedCall::StartListening).await.map(drop)
+    }
+
+    async fn start_listening_windowed(
+        &self,
+        listen: Duration,
+        every: Duration,
+    ) -> Result<(), P2pError> {
+        let call = RecordedCall::StartListeningWindowed { listen, every };
+        self.replay(call).await.map(drop)
+    }
+
+    async fn stop_listening(&self) -> Result<(), P2pError> {
+        self.replay(RecordedCall::StopListening).await.map(drop)
+    }
+
+    async fn connect(&self, config: ConnectConfig) -> Result<Option<String>, P2pError> {
+        match self.replay(RecordedCall::Connect(config)).await? {
+            RecordedValue::Pin(pin) => Ok(pin),
+            other => Err(unexpected("connect", other)),
+        }
+    }
+
+    async fn cancel_connect(&self) -> Result<(), P2pError> {
+        self.replay(RecordedCall::CancelConnect).await.map(drop)
+    }
+
+    async fn request_peers(&self) -> Result<Vec<P2pDevice>, P2pError> {
+        match self.replay(RecordedCall::RequestPeers).await? {
+            RecordedValue::Peers(peers) => Ok(peers),
+            other => Err(unexpected("request_peers", other)),
+        }
+    }
+
+    async fn request_groups(&self) -> Result<Vec<P2pGroup>, P2pError> {
+        match self.replay(RecordedCall::RequestGroups).await? {
+            RecordedValue::Groups(groups) => Ok(groups),
+            other => Err(unexpected("request_groups", other)),
+        }
+    }
+
+    async fn create_group(&self) -> Result<(), P2pError> {
+        self.replay(RecordedCall::CreateGroup).await.map(drop)
+    }
+
+    async fn invite(&self, peer: MacAddress) -> Result<(), P2pError> {
+        self.replay(RecordedCall::Invite(peer)).await.map(drop)
+    }
+
+    async fn reject_peer(&self, peer: MacAddress) -> Result<(), P2pError> {
+        self.replay(RecordedCall::RejectPeer(peer)).await.map(drop)
+    }
+
+    async fn remove_groups(&self) -> Result<(), P2pError> {
+        self.replay(RecordedCall::RemoveGroups).await.map(drop)
+    }
+
+    async fn set_noa(&self, schedule: NoaSchedule) -> Result<(), P2pError> {
+        self.replay(RecordedCall::SetNoa(schedule)).await.map(drop)
+    }
+
+    async fn set_vendor_elements(
+        &self,
+        frame: VendorElementFrame,
+        elements: Vec<u8>,
+    ) -> Result<(), P2pError> {
+        let call = RecordedCall::SetVendorElements { frame, elements };
+        self.replay(call).await.map(drop)
+    }
+
+    async fn set_wfd_info(&self, info: Option<WfdDeviceInfo>) -> Result<(), P2pError> {
+        self.replay(RecordedCall::SetWfdInfo(info)).await.map(drop)
+    }
+
+    async fn resolve_peer_ip(&self, peer: MacAddress) -> Result<Option<IpAddr>, P2pError> {
+        match self.replay(RecordedCall::ResolvePeerIp(peer)).await? {
+            RecordedValue::Ip(ip) => Ok(ip),
+            other => Err(unexpected("resolve_peer_ip", other)),
+        }
+    }
+
+    async fn link_stats(&self) -> Result<Vec<LinkStats>, P2pError> {
+        match self.replay(RecordedCall::LinkStats).await? {
+            RecordedValue::LinkStats(stats) => Ok(stats),
+            other => Err(unexpected("link_stats", other)),
+        }
+    }
+
+    async fn persistent_groups(&self) -> Result<Vec<GroupCredentials>, P2pError> {
+        match self.replay(RecordedCall::PersistentGroups).await? {
+            RecordedValue::PersistentGroups(groups) => Ok(groups),
+            other => Err(unexpected("persistent_groups", other)),
+        }
+    }
+
+    async fn add_persistent_group(&self, credentials: GroupCredentials) -> Result<u32, P2pError> {
+        let call = RecordedCall::AddPersistentGroup(redacted(&credentials));
+        match self.replay(call).await? {
+            RecordedValue::NetworkId(network_id) => Ok(network_id),
+            other => Err(unexpected("add_persistent_group", other)),
+        }
+    }
+
+    async fn invoke_persistent_group(
+        &self,
+        network_id: u32,
+        peer: MacAddress,
+    ) -> Result<(), P2pError> {
+        let call = RecordedCall::InvokePersistentGroup { network_id, peer };
+        self.replay(call).await.map(drop)
+    }
+
+    async fn factory_reset(&self) -> Result<(), P2pError> {
+        self.replay(RecordedCall::FactoryReset).await.map(drop)
+    }
+
+    async fn add_service(&self, service: LocalService) -> Result<(), P2pError> {
+        self.replay(RecordedCall::AddService(service))
+            .await
+            .map(drop)
+    }
+
+    async fn remove_service(&self, service: LocalService) -> Result<(), P2pError> {
+        self.replay(RecordedCall::RemoveService(service))
+            .await
+            .map(drop)
+    }
+
+    async fn discover_services(&self, peer: Option<MacAddress>) -> Result<(), P2pError> {
+        self.replay(RecordedCall::DiscoverServices(peer))
+            .await
+            .map(drop)
+    }
+
+    async fn cancel_service_discovery(&self, peer: Option<MacAddress>) -> Result<(), P2pError> {
+        self.replay(RecordedCall::CancelServiceDiscovery(peer))
+            .await
+            .map(drop)
+    }
+
+    async fn asp_provision(&self, request: AspProvision) -> Result<(), P2pError> {
+        self.replay(RecordedCall::AspProvision(request))
+            .await
+            .map(drop)
+    }
+
+    async fn capabilities(&self) -> Result<P2pCapabilities, P2pError> {
+        match self.replay(RecordedCall::Capabilities).await? {
+            RecordedValue::Capabilities(capabilities) => Ok(capabilities),
+            other => Err(unexpected("capabilities", other)),
+        }
+    }
+
+    async fn local_device(&self) -> Result<LocalDeviceInfo, P2pError> {
+        // Not recorded, so the manager stops asking for it.
+        Err(P2pError::Unsupported(
+            "captures do not hold the local device".to_string(),
+        ))
+    }
+
+    async fn set_device_name(&self, name: String) -> Result<(), P2pError> {
+        self.replay(RecordedCall::SetDeviceName(name))
+            .await
+            .map(drop)
+    }
+
+    async fn ping(&self) -> Result<(), P2pError> {
+        Ok(())
+    }
+
+    async fn watch_events(&self, events: mpsc::Sender<P2pEvent>) -> Result<(), P2pError> {
+        loop {
+            let progressed = self.progressed.notified();
+            let batch: Vec<P2pEvent> = {
+                let mut entries = lock(&self.entries);
+                let mut batch = Vec::new();
+                while let Some(RecordedEntry::Event { .. }) = entries.front() {
+                    if let Some(RecordedEntry::Event { event, .. }) = entries.pop_front() {
+                        batch.push(event);
+                    }
+                }
+                if batch.is_empty() && entries.is_empty() {
+                    // Session over; nothing more will ever arrive.
+                    return Ok(());
+                }
+                batch
+            };
+            if batch.is_empty() {
+                // The next entry is a call; wait for the manager to make it.
+                progressed.await;
+                continue;
+            }
+            for event in batch {
+                if events.send(event).await.is_err() {
+                    return Ok(());
+                }
+            }
+            self.progressed.notify_waiters();
+        }
+    }
+
+    async fn call_raw(
+        &self,
+        method: String,
+        _options: HashMap<String, OwnedValue>,
+    ) -> Result<Vec<OwnedValue>, P2pError> {
+        self.replay(RecordedCall::CallRaw { method })
+            .await
+            .map(|_| Vec::new())
+    }
+}
+
+/// Records a running manager into a capture file from one of its channels, for
+/// bug reports: every backend call with its result and every backend event (as
+/// `RecordingBackend` would), plus each command queued on any of the manager's
+/// channels and each event they saw. Recording stops when it is dropped.
+///
+/// `ReplayBackend` plays the capture back from the point of attaching, so attach
+/// right after `initialize` to make the whole session replayable.
+pub struct Recorder {
+    capture: Arc<Capture>,
+    executor: Arc<dyn Executor>,
+    // Dropping the sender stops the tasks copying events and signals.
+    stop: watch::Sender<()>,
+}
+
+impl Recorder {
+    /// Start recording the manager behind `channel` into `path`, replacing any
+    /// existing file.
+    pub fn attach(channel: &WifiP2pChannel, path: impl AsRef<Path>) -> Result<Self, P2pError> {
+        let capture = Arc::new(Capture::create(path.as_ref())?);
+        channel.taps().attach(&capture);
+        let recorder = Self {
+            capture,
+            executor: Arc::clone(channel.executor()),
+            stop: watch::Sender::new(()),
+        };
+        let mut events = channel.event_stream();
+        recorder.spawn(move |capture| async move {
+            while let Some(event) = events.next().await {
+                capture.write(|at_ms| RecordedEntry::Emitted { at_ms, event });
+            }
+        });
+        Ok(recorder)
+    }
+
+    /// Also record every D-Bus signal wpa_supplicant sends on `connection` (see
+    /// `WifiP2pManager::connection`), including the ones no event comes from.
+    #[cfg(target_os = "linux")]
+    pub async fn record_signals(&self, connection: &zbus::Connection) -> Result<(), P2pError> {
+        use zbus::message::Type;
+        use zbus::{MatchRule, MessageStream};
+
+        let rule = MatchRule::builder()
+            .msg_type(Type::Signal)
+            .path_namespace("/fi/w1/wpa_supplicant1")?
+            .build();
+        let mut signals = MessageStream::for_match_rule(rule, connection, None).await?;
+        self.spawn(move |capture| async move {
+            while let Some(Ok(message)) = signals.next().await {
+                let header = message.header();
+                let args = message
+                    .body()
+                    .deserialize::<zbus::zvariant::Structure>()
+                    .map(|args| args.to_string())
+                    .unwrap_or_default();
+                capture.write(|at_ms| RecordedEntry::Signal {
+                    at_ms,
+                    path: header.path().map(ToString::to_string).unwrap_or_default(),
+                    interface: header
+                        .interface()
+                        .map(ToString::to_string)
+                        .unwrap_or_default(),
+                    member: header.member().map(ToString::to_string).unwrap_or_default(),
+                    args,
+                });
+            }
+        });
+        Ok(())
+    }
+
+    /// Run `copy` in the background until the recorder is dropped.
+    fn spawn<F>(&self, copy: impl FnOnce(Arc<Capture>) -> F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let copying = copy(Arc::clone(&self.capture));
+        let mut stop = self.stop.subscribe();
+        self.executor.spawn(Box::pin(async move {
+            tokio::select! {
+                () = copying => {}
+                _ = stop.changed() => {}
+            }
+        }));
+    }
+}
@@ -0,0 +1,558 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use ::windows::Devices::Enumeration::{
+    DeviceInformation, DeviceInformationCustomPairing, DeviceInformationKind,
+    DeviceInformationUpdate, DevicePairingKinds, DevicePairingProtectionLevel,
+    DevicePairingRequestedEventArgs, DevicePairingResultStatus, DeviceWatcher, DeviceWatcherStatus,
+};
+use ::windows::Devices::WiFiDirect::{
+    WiFiDirectAdvertisementListenStateDiscoverability, WiFiDirectAdvertisementPublisher,
+    WiFiDirectAdvertisementPublisherStatus, WiFiDirectAdvertisementPublisherStatusChangedEventArgs,
+    WiFiDirectConfigurationMethod, WiFiDirectConnectionListener, WiFiDirectConnectionParameters,
+    WiFiDirectConnectionRequestedEventArgs, WiFiDirectConnectionStatus, WiFiDirectDevice,
+    WiFiDirectDeviceSelectorType,
+};
+use ::windows::Foundation::Collections::IIterable;
+use ::windows::Foundation::{
+    AsyncOperationCompletedHandler, IAsyncOperation, IPropertyValue, TypedEventHandler,
+};
+use ::windows::core::{HSTRING, IInspectable, Interface, RuntimeType};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::capabilities::P2pCapabilities;
+use crate::channel::P2pEvent;
+use crate::config::{ConnectConfig, DiscoveryOptions, DiscoveryType, WpsMethod};
+use crate::device::{DeviceStatus, LocalDeviceInfo, P2pDevice, wps_config};
+use crate::error::P2pError;
+use crate::group::P2pGroup;
+use crate::mac::MacAddress;
+use crate::state::ListenState;
+
+use super::P2pBackend;
+
+const DEVICE_ADDRESS_PROPERTY: &str = "System.Devices.Aep.DeviceAddress";
+
+#[derive(Default)]
+struct WindowsState {
+    // Device ids are what WinRT wants back; addresses are what callers use.
+    peers: HashMap<MacAddress, (HSTRING, P2pDevice)>,
+    // Open WiFiDirectDevice handles keep their groups alive until closed.
+    connections: HashMap<MacAddress, WiFiDirectDevice>,
+    publisher: Option<WiFiDirectAdvertisementPublisher>,
+    // Advertises without forming a group, for `start_listening`.
+    listen_publisher: Option<WiFiDirectAdvertisementPublisher>,
+    listener: Option<WiFiDirectConnectionListener>,
+    events: Option<mpsc::Sender<P2pEvent>>,
+}
+
+impl WindowsState {
+    fn emit(&self, event: P2pEvent) {
+        // WinRT callbacks cannot wait; a full or missing watcher drops the event.
+        if let Some(events) = &self.events {
+            let _ = events.try_send(event);
+        }
+    }
+}
+
+/// Backend on top of the WinRT `Windows.Devices.WiFiDirect` API.
+///
+/// Discovery runs a device watcher over Wi-Fi Direct association endpoints,
+/// connect pairs with the peer and opens a `WiFiDirectDevice`, and
+/// `create_group` starts an autonomous group owner advertisement that accepts
+/// incoming connections. Windows picks the adapter itself, so the interface
+/// name is only checked for being non-empty. Notice of Absence, vendor elements and raw
+/// calls report `P2pError::Unsupported`; like with iwd, a generated
+/// `PinDisplay` PIN is only returned once pairing completes.
+#[derive(Clone)]
+pub struct WindowsBackend {
+    watcher: DeviceWatcher,
+    state: Arc<Mutex<WindowsState>>,
+}
+
+impl WindowsBackend {
+    /// Create the device watcher used for discovery; nothing is scanned until
+    /// `discover_peers`.
+    pub async fn new(interface_name: &str) -> Result<Self, P2pError> {
+        if interface_name.trim().is_empty() {
+            return Err(P2pError::InvalidInterface(interface_name.to_string()));
+        }
+        let selector = WiFiDirectDevice::GetDeviceSelector2(
+            WiFiDirectDeviceSelectorType::AssociationEndpoint,
+        )?;
+        let properties =
+            IIterable::<HSTRING>::try_from(vec![HSTRING::from(DEVICE_ADDRESS_PROPERTY)])?;
+        let watcher = DeviceInformation::CreateWatcherWithKindAqsFilterAndAdditionalProperties(
+            &selector,
+            &properties,
+            DeviceInformationKind::AssociationEndpoint,
+        )?;
+        let backend = Self {
+            watcher,
+            state: Arc::default(),
+        };
+        backend.register_watcher_handlers()?;
+        Ok(backend)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, WindowsState> {
+        lock(&self.state)
+    }
+
+    fn register_watcher_handlers(&self) -> Result<(), P2pError> {
+        let state = Arc::clone(&self.state);
+        self.watcher.Added(&TypedEventHandler::new(
+            move |_: &Option<DeviceWatcher>, info: &Option<DeviceInformation>| {
+                let Some(info) = info else { return Ok(()) };
+                let Some(device) = peer_device(info) else {
+                    return Ok(());
+                };
+                let mut state = lock(&state);
+                state
+                    .peers
+                    .insert(device.mac_address, (info.Id()?, device.clone()));
+                state.emit(P2pEvent::PeerFound(Arc::new(device)));
+                Ok(())
+            },
+        ))?;
+
+        let state = Arc::clone(&self.state);
+        self.watcher.Removed(&TypedEventHandler::new(
+            move |_: &Option<DeviceWatcher>, update: &Option<DeviceInformationUpdate>| {
+                let Some(update) = update else { return Ok(()) };
+                let id = update.Id()?;
+                let mut state = lock(&state);
+                let address = state
+                    .peers
+                    .iter()
+                    .find(|(_, (peer_id, _))| *peer_id == id)
+                    .map(|(address, _)| *address);
+                if let Some(address) = address {
+                    state.peers.remove(&address);
+                    state.emit(P2pEvent::PeerLost(address));
+                }
+                Ok(())
+            },
+        ))?;
+
+        // Updates carry no properties we track, but without a handler the watcher
+        // never reports removals.
+        self.watcher.Updated(&TypedEventHandler::new(
+            |_: &Option<DeviceWatcher>, _: &Option<DeviceInformationUpdate>| Ok(()),
+        ))?;
+
+        let state = Arc::clone(&self.state);
+        self.watcher.Stopped(&TypedEventHandler::new(
+            move |_: &Option<DeviceWatcher>, _: &Option<IInspectable>| {
+                lock(&state).emit(P2pEvent::DiscoveryStopped);
+                Ok(())
+            },
+        ))?;
+        Ok(())
+    }
+
+    fn peer_id(&self, address: &MacAddress) -> Result<HSTRING, P2pError> {
+        self.lock()
+            .peers
+            .get(address)
+            .map(|(id, _)| id.clone())
+            .ok_or_else(|| P2pError::InvalidArgs(format!("unknown peer {address}")))
+    }
+}
+
+impl P2pBackend for WindowsBackend {
+    async fn discover_peers(&self, options: DiscoveryOptions) -> Result<(), P2pError> {
+        if options.discovery_type != DiscoveryType::Full {
+            return Err(P2pError::Unsupported(
+                "WinRT Wi-Fi Direct picks the discovery channels itself".to_string(),
+            ));
+        }
+        if !options.seek.is_empty() {
+            return Err(P2pError::Unsupported(
+                "P2PS is only reachable through WiFiDirectService sessions".to_string(),
+            ));
+        }
+        // A watcher can only be started from a stopped state; a running one keeps
+        // reporting peers after its initial enumeration completes.
+        let status = self.watcher.Status()?;
+        if status == DeviceWatcherStatus::Started
+            || status == DeviceWatcherStatus::EnumerationCompleted
+        {
+            return Ok(());
+        }
+        if status == DeviceWatcherStatus::Stopping {
+            return Err(P2pError::Busy("discovery is still stopping".to_string()));
+        }
+        self.watcher.Start()?;
+        Ok(())
+    }
+
+    async fn stop_discovery(&self) -> Result<(), P2pError> {
+        let status = self.watcher.Status()?;
+        if status == DeviceWatcherStatus::Started
+            || status == DeviceWatcherStatus::EnumerationCompleted
+        {
+            self.watcher.Stop()?;
+        }
+        Ok(())
+    }
+
+    async fn start_listening(&self) -> Result<(), P2pError> {
+        if self.lock().listen_publisher.is_some() {
+            return Ok(());
+        }
+        let publisher = WiFiDirectAdvertisementPublisher::new()?;
+        publisher.Advertisement()?.SetListenStateDiscoverability(
+            WiFiDirectAdvertisementListenStateDiscoverability::Normal,
+        )?;
+        // Windows aborts the advertisement when the radio goes off or another
+        // app takes the adapter; our own Stop reports `Stopped`.
+        let state = Arc::clone(&self.state);
+        publisher.StatusChanged(&TypedEventHandler::new(
+            move |_: &Option<WiFiDirectAdvertisementPublisher>,
+                  args: &Option<WiFiDirectAdvertisementPublisherStatusChangedEventArgs>| {
+                let Some(args) = args else { return Ok(()) };
+                if args.Status()? == WiFiDirectAdvertisementPublisherStatus::Aborted {
+                    let mut state = lock(&state);
+                    state.listen_publisher = None;
+                    state.emit(P2pEvent::ListenStateChanged(ListenState::Stopped));
+                }
+                Ok(())
+            },
+        ))?;
+        publisher.Start()?;
+        self.lock().listen_publisher = Some(publisher);
+        Ok(())
+    }
+
+    async fn start_listening_windowed(
+        &self,
+        _listen: Duration,
+        _every: Duration,
+    ) -> Result<(), P2pError> {
+        Err(P2pError::Unsupported(
+            "Windows does not schedule listen windows".to_string(),
+        ))
+    }
+
+    async fn stop_listening(&self) -> Result<(), P2pError> {
+        let publisher = self.lock().listen_publisher.take();
+        if let Some(publisher) = publisher {
+            publisher.Stop()?;
+        }
+        Ok(())
+    }
+
+    async fn connect(&self, config: ConnectConfig) -> Result<Option<String>, P2pError> {
+        let address = config.device_address;
+        let id = self.peer_id(&address)?;
+        let method = match config.wps_method {
+            WpsMethod::Pbc => WiFiDirectConfigurationMethod::PushButton,
+            WpsMethod::PinDisplay => WiFiDirectConfigurationMethod::DisplayPin,
+            WpsMethod::PinKeypad | WpsMethod::PinLabel => WiFiDirectConfigurationMethod::ProvidePin,
+        };
+        if config.wps_method.requires_pin() && config.pin.is_none() {
+            return Err(P2pError::InvalidConfig(format!(
+                "{} requires a PIN",
+                config.wps_method.as_str()
+            )));
+        }
+
+        let parameters = WiFiDirectConnectionParameters::new()?;
+        parameters
+            .PreferenceOrderedConfigurationMethods()?
+            .Append(method)?;
+        let kinds = WiFiDirectConnectionParameters::GetDevicePairingKinds(method)?;
+
+        // Pairing runs the WPS exchange; Windows asks us to confirm or supply the PIN.
+        let info = completed(DeviceInformation::CreateFromIdAsync(&id)?).await?;
+        let pairing = info.Pairing()?.Custom()?;
+        let shown_pin = Arc::new(Mutex::new(None));
+        let token =
+            pairing.PairingRequested(&pairing_handler(config.pin, Arc::clone(&shown_pin)))?;
+        let result = completed(pairing.PairWithProtectionLevelAndSettingsAsync(
+            kinds,
+            DevicePairingProtectionLevel::Default,
+            &parameters,
+        )?)
+        .await;
+        let _ = pairing.RemovePairingRequested(token);
+        let status = result?.Status()?;
+        if status != DevicePairingResultStatus::Paired
+            && status != DevicePairingResultStatus::AlreadyPaired
+        {
+            return Err(pairing_error(status));
+        }
+
+        let device = completed(WiFiDirectDevice::FromIdAsync2(&id, &parameters)?).await?;
+        let state = Arc::clone(&self.state);
+        device.ConnectionStatusChanged(&TypedEventHandler::new(
+            move |device: &Option<WiFiDirectDevice>, _: &Option<IInspectable>| {
+                let Some(device) = device else { return Ok(()) };
+                if device.ConnectionStatus()? == WiFiDirectConnectionStatus::Disconnected {
+                    lock(&state).connections.remove(&address);
+                }
+                Ok(())
+            },
+        ))?;
+        self.lock().connections.insert(address, device);
+
+        // Only a PIN we were asked to display is "generated"; a caller PIN is echoed nowhere.
+        let generated = lock(&shown_pin).take();
+        Ok(generated)
+    }
+
+    async fn cancel_connect(&self) -> Result<(), P2pError> {
+        Err(P2pError::Unsupported(
+            "WinRT pairing cannot be cancelled; the command deadline ends it".to_string(),
+        ))
+    }
+
+    async fn request_peers(&self) -> Result<Vec<P2pDevice>, P2pError> {
+        let state = self.lock();
+        let peers = state
+            .peers
+            .iter()
+            .map(|(address, (_, device))| {
+                let mut device = device.clone();
+                if state.connections.contains_key(address) {
+                    device.status = DeviceStatus::Connected;
+                }
+                device
+            })
+            .collect();
+        Ok(peers)
+    }
+
+    async fn create_group(&self) -> Result<(), P2pError> {
+        if self.lock().publisher.is_some() {
+            return Ok(());
+        }
+        let publisher = WiFiDirectAdvertisementPublisher::new()?;
+        let advertisement = publisher.Advertisement()?;
+        advertisement.SetIsAutonomousGroupOwnerEnabled(true)?;
+        advertisement.SetListenStateDiscoverability(
+            WiFiDirectAdvertisementListenStateDiscoverability::Normal,
+        )?;
+
+        // Peers joining our group show up as connection requests; accept them all,
+        // as wpa_supplicant does for an autonomous GO.
+        let listener = WiFiDirectConnectionListener::new()?;
+        let state = Arc::clone(&self.state);
+        listener.ConnectionRequested(&TypedEventHandler::new(
+            move |_: &Option<WiFiDirectConnectionListener>,
+                  args: &Option<WiFiDirectConnectionRequestedEventArgs>| {
+                let Some(args) = args else { return Ok(()) };
+                let request = args.GetConnectionRequest()?;
+                let info = request.DeviceInformation()?;
+                // The callback runs on a thread-pool thread, so blocking on the open is fine.
+                let device = WiFiDirectDevice::FromIdAsync(&info.Id()?)?.get()?;
+                if let Some(address) = peer_address(&info) {
+                    lock(&state).connections.insert(address, device);
+                }
+                Ok(())
+            },
+        ))?;
+        publisher.Start()?;
+
+        let mut state = self.lock();
+        state.publisher = Some(publisher);
+        state.listener = Some(listener);
+        Ok(())
+    }
+
+    async fn request_groups(&self) -> Result<Vec<P2pGroup>, P2pError> {
+        Err(P2pError::Unsupported(
+            "Windows does not report running Wi-Fi Direct groups".to_string(),
+        ))
+    }
+
+    async fn invite(&self, _peer: MacAddress) -> Result<(), P2pError> {
+        Err(P2pError::Unsupported(
+            "Windows does not expose invitations".to_string(),
+        ))
+    }
+
+    async fn reject_peer(&self, _peer: MacAddress) -> Result<(), P2pError> {
+        Err(P2pError::Unsupported(
+            "Windows answers connection requests through its own prompt".to_string(),
+        ))
+    }
+
+    async fn remove_groups(&self) -> Result<(), P2pError> {
+        let mut state = self.lock();
+        if let Some(publisher) = state.publisher.take() {
+            publisher.Stop()?;
+        }
+        state.listener = None;
+        // Closing the last handle to a peer tears down its group.
+        for (_, device) in state.connections.drain() {
+            let _ = device.Close();
+        }
+        Ok(())
+    }
+
+    async fn resolve_peer_ip(&self, peer: MacAddress) -> Result<Option<IpAddr>, P2pError> {
+        // Windows configures the group's addresses itself; the open connection's
+        // endpoint pairs name the peer's side.
+        let Some(device) = self.lock().connections.get(&peer).cloned() else {
+            return Ok(None);
+        };
+        let pairs = device.GetConnectionEndpointPairs()?;
+        for pair in &pairs {
+            let Ok(host) = pair.RemoteHostName() else {
+                continue;
+            };
+            if let Ok(ip) = host.CanonicalName()?.to_string().parse() {
+                return Ok(Some(ip));
+            }
+        }
+        Ok(None)
+    }
+
+    async fn factory_reset(&self) -> Result<(), P2pError> {
+        Err(P2pError::Unsupported(
+            "Windows keeps Wi-Fi Direct pairings in its own store".to_string(),
+        ))
+    }
+
+    async fn capabilities(&self) -> Result<P2pCapabilities, P2pError> {
+        // Windows only lists Wi-Fi Direct endpoints when a capable adapter is present,
+        // so the selector succeeding is all the API tells us.
+        WiFiDirectDevice::GetDeviceSelector()?;
+        Ok(P2pCapabilities {
+            p2p_supported: true,
+            wps_methods: vec![
+                "push_button".to_string(),
+                "display".to_string(),
+                "keypad".to_string(),
+            ],
+            modes: vec!["p2p".to_string(), "ap".to_string()],
+            ..P2pCapabilities::default()
+        })
+    }
+
+    async fn local_device(&self) -> Result<LocalDeviceInfo, P2pError> {
+        Err(P2pError::Unsupported(
+            "WinRT Wi-Fi Direct does not expose the local device".to_string(),
+        ))
+    }
+
+    async fn set_device_name(&self, _name: String) -> Result<(), P2pError> {
+        Err(P2pError::Unsupported(
+            "Windows advertises the computer name as the device name".to_string(),
+        ))
+    }
+
+    async fn ping(&self) -> Result<(), P2pError> {
+        // There is no service to ask; a working WinRT activation is the best sign.
+        WiFiDirectDevice::GetDeviceSelector()?;
+        Ok(())
+    }
+
+    async fn watch_events(&self, events: mpsc::Sender<P2pEvent>) -> Result<(), P2pError> {
+        // Events are pushed from the WinRT callbacks; park until the manager goes away.
+        self.lock().events = Some(events.clone());
+        events.closed().await;
+        Ok(())
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // A panicking WinRT callback must not wedge the backend.
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Await a WinRT async operation without tying the backend to a runtime.
+async fn completed<T>(operation: IAsyncOperation<T>) -> Result<T, P2pError>
+where
+    T: RuntimeType + Send + 'static,
+{
+    let (tx, rx) = oneshot::channel();
+    let mut tx = Some(tx);
+    operation.SetCompleted(&AsyncOperationCompletedHandler::new(
+        move |operation: Option<&IAsyncOperation<T>>, _| {
+            if let (Some(operation), Some(tx)) = (operation, tx.take()) {
+                let _ = tx.send(operation.GetResults());
+            }
+            Ok(())
+        },
+    ))?;
+    let result = rx
+        .await
+        .map_err(|_| P2pError::Backend("WinRT operation was dropped".to_string()))?;
+    Ok(result?)
+}
+
+fn pairing_handler(
+    pin: Option<String>,
+    shown_pin: Arc<Mutex<Option<String>>>,
+) -> TypedEventHandler<DeviceInformationCustomPairing, DevicePairingRequestedEventArgs> {
+    TypedEventHandler::new(
+        move |_: &Option<DeviceInformationCustomPairing>,
+              args: &Option<DevicePairingRequestedEventArgs>| {
+            let Some(args) = args else { return Ok(()) };
+            let kind = args.PairingKind()?;
+            if kind == DevicePairingKinds::DisplayPin {
+                *lock(&shown_pin) = Some(args.Pin()?.to_string());
+                args.Accept()
+            } else if kind == DevicePairingKinds::ProvidePin {
+                match &pin {
+                    Some(pin) => args.AcceptWithPin(&HSTRING::from(pin.as_str())),
+                    // Leaving the request unanswered makes pairing fail.
+                    None => Ok(()),
+                }
+            } else {
+                args.Accept()
+            }
+        },
+    )
+}
+
+fn pairing_error(status: DevicePairingResultStatus) -> P2pError {
+    if status == DevicePairingResultStatus::AccessDenied {
+        P2pError::PermissionDenied("pairing was denied".to_string())
+    } else if status == DevicePairingResultStatus::ConnectionRejected
+        || status == DevicePairingResultStatus::RejectedByHandler
+        || status == DevicePairingResultStatus::PairingCanceled
+    {
+        P2pError::Backend(format!("pairing rejected ({})", status.0))
+    } else if status == DevicePairingResultStatus::OperationAlreadyInProgress {
+        P2pError::Busy("another pairing is in progress".to_string())
+    } else if status == DevicePairingResultStatus::AuthenticationTimeout {
+        P2pError::Backend("pairing timed out".to_string())
+    } else {
+        P2pError::Backend(format!("pairing failed ({})", status.0))
+    }
+}
+
+fn peer_address(info: &DeviceInformation) -> Option<MacAddress> {
+    // Prefer the requested address property; association endpoint ids end in the
+    // address too (e.g. "WiFiDirect#aa:bb:cc:dd:ee:ff").
+    let from_property = info
+        .Properties()
+        .and_then(|properties| properties.Lookup(&HSTRING::from(DEVICE_ADDRESS_PROPERTY)))
+        .and_then(|value| value.cast::<IPropertyValue>()?.GetString())
+        .ok()
+        .and_then(|address| address.to_string().parse().ok());
+    from_property.or_else(|| {
+        let id = info.Id().ok()?.to_string();
+        id.rsplit(['#', '-']).find_map(|part| part.parse().ok())
+    })
+}
+
+fn peer_device(info: &DeviceInformation) -> Option<P2pDevice> {
+    let mut device = P2pDevice::new(peer_address(info)?);
+    device.device_name = info
+        .Name()
+        .ok()
+        .map(|name| name.to_string())
+        .filter(|name| !name.is_empty());
+    // Windows negotiates WPS itself and offers these methods for every peer.
+    device.wps_config_methods = wps_config::PUSH_BUTTON | wps_config::DISPLAY | wps_config::KEYPAD;
+    Some(device)
+}
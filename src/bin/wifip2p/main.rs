@@ -0,0 +1,698 @@
+//! `wifip2p`: drive Wi-Fi Direct from a shell, for debugging without writing code.
+//!
+//! Every subcommand is one run of the channel API against a fresh manager.
+//! Nothing is cleaned up on exit, so a group created or joined here stays up
+//! for the next command, as with `wpa_cli`.
+
+mod output;
+#[cfg(feature = "tui")]
+mod tui;
+
+use std::future::Future;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::{Args, Parser, Subcommand};
+use serde_json::json;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::oneshot;
+use tokio::time::Instant;
+use wifi_p2p_rs::backend::recording::Recorder;
+use wifi_p2p_rs::{
+    ConnectConfig, DiscoveryOptions, DiscoveryType, LocalService, MacAddress, P2pDevice, P2pError,
+    P2pEvent, P2pGroup, ServiceResponse, WifiP2pChannel, WifiP2pManager, WpsMethod,
+};
+
+#[cfg(target_os = "linux")]
+use wifi_p2p_rs::Severity;
+
+use crate::output::{Output, print_event, print_json};
+
+#[derive(Parser)]
+#[command(
+    name = "wifip2p",
+    version,
+    about = "Wi-Fi Direct from the command line"
+)]
+struct Cli {
+    /// Interface wpa_supplicant manages; picked automatically when omitted.
+    #[arg(short, long, global = true)]
+    interface: Option<String>,
+    /// Print JSON; streaming commands print one object per line.
+    #[arg(long, global = true)]
+    json: bool,
+    /// Record calls, events and supplicant signals to this file for a bug report.
+    #[arg(long, global = true, value_name = "FILE")]
+    record: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Discover peers, printing them as they are found.
+    Scan {
+        #[command(flatten)]
+        window: Window,
+        /// Only scan the social channels 1, 6 and 11.
+        #[arg(long, conflicts_with = "freq")]
+        social: bool,
+        /// Only scan this frequency, in MHz.
+        #[arg(long, value_name = "MHZ")]
+        freq: Option<u32>,
+        /// Only report peers of this device type, e.g. 7-0050F204-1 for
+        /// displays; may be repeated.
+        #[arg(long = "device-type", value_name = "TYPE")]
+        device_types: Vec<String>,
+        /// Only report peers advertising this P2PS service, e.g.
+        /// org.wi-fi.wfds.print.tx; may be repeated.
+        #[arg(long, value_name = "SERVICE")]
+        seek: Vec<String>,
+    },
+    /// List the peers the supplicant knows.
+    Peers,
+    /// Connect to a peer, discovering it first if needed.
+    Connect {
+        peer: MacAddress,
+        /// Enter this PIN, shown by the peer, instead of pushing the button.
+        #[arg(long, conflicts_with = "display")]
+        pin: Option<String>,
+        /// Show a PIN for the peer to enter instead of pushing the button.
+        #[arg(long)]
+        display: bool,
+        /// Seconds to wait for the peer and then for the group.
+        #[arg(short, long, default_value_t = 60)]
+        timeout: u64,
+    },
+    /// Create, remove or inspect groups.
+    #[command(subcommand)]
+    Group(GroupCommand),
+    /// Advertise local services and discover those of peers.
+    #[command(subcommand)]
+    Services(ServicesCommand),
+    /// Show capabilities, groups and peers.
+    Status,
+    /// Print every event as a line of JSON until interrupted.
+    Watch {
+        /// Run peer discovery while watching.
+        #[arg(long)]
+        discover: bool,
+        /// Stop after this many seconds.
+        #[arg(short, long)]
+        timeout: Option<u64>,
+    },
+    /// Browse peers interactively, with connect, invite and reject keys.
+    #[cfg(feature = "tui")]
+    Tui,
+    /// Check the bus, wpa_supplicant, the interface, rfkill and NetworkManager.
+    #[cfg(target_os = "linux")]
+    Doctor,
+    /// List wireless interfaces and whether they support Wi-Fi Direct.
+    #[cfg(target_os = "linux")]
+    Interfaces,
+}
+
+#[derive(Subcommand)]
+enum GroupCommand {
+    /// Become group owner of a new group.
+    Create,
+    /// Leave or end every group on the interface.
+    Remove,
+    /// Show the groups that are up.
+    Info,
+}
+
+#[derive(Subcommand)]
+enum ServicesCommand {
+    /// Answer service discovery queries for a service until it is removed.
+    Add {
+        #[command(subcommand)]
+        service: ServiceArg,
+    },
+    /// Stop answering for a service added earlier.
+    Remove {
+        #[command(subcommand)]
+        service: ServiceArg,
+    },
+    /// Query peers for their services and list them at the end.
+    List(Window),
+    /// Query peers (or one peer) for their services, printing answers as they come.
+    Discover {
+        peer: Option<MacAddress>,
+        #[command(flatten)]
+        window: Window,
+    },
+}
+
+#[derive(Subcommand)]
+enum ServiceArg {
+    /// A Bonjour record, as hex: the query (name, type, version) and the RDATA.
+    Bonjour { query: String, response: String },
+    /// A UPnP service string, e.g. uuid:...::urn:schemas-upnp-org:service:Foo:1.
+    Upnp {
+        service: String,
+        #[arg(long, default_value_t = 0x10)]
+        version: u8,
+    },
+}
+
+#[derive(Args)]
+struct Window {
+    /// Seconds to keep discovery running.
+    #[arg(short, long, default_value_t = 10)]
+    timeout: u64,
+}
+
+impl Window {
+    fn duration(&self) -> Duration {
+        Duration::from_secs(self.timeout)
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let json = cli.json;
+    match run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) if json => {
+            eprintln!("{}", json!({ "error": err.to_string() }));
+            ExitCode::FAILURE
+        }
+        Err(err) => {
+            eprintln!("wifip2p: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(cli: Cli) -> Result<(), P2pError> {
+    let output = if cli.json { Output::Json } else { Output::Text };
+    // These have to work when the manager cannot even be built.
+    #[cfg(target_os = "linux")]
+    match cli.command {
+        Command::Doctor => {
+            let interface = cli
+                .interface
+                .or_else(|| WifiP2pManager::auto_interface().ok());
+            return doctor(interface.as_deref().unwrap_or("wlan0"), output).await;
+        }
+        Command::Interfaces => return interfaces(output),
+        _ => {}
+    }
+    let interface = match cli.interface {
+        Some(interface) => interface,
+        #[cfg(target_os = "linux")]
+        None => WifiP2pManager::auto_interface()?,
+        #[cfg(not(target_os = "linux"))]
+        None => "wlan0".to_string(),
+    };
+    let mut manager = WifiP2pManager::builder()
+        .cleanup_on_shutdown(false)
+        .build(&interface)
+        .await?;
+    let channel = manager.initialize();
+    let _recorder = match &cli.record {
+        Some(path) => {
+            let recorder = Recorder::attach(&channel, path)?;
+            #[cfg(target_os = "linux")]
+            recorder.record_signals(manager.connection()).await?;
+            Some(recorder)
+        }
+        None => None,
+    };
+    let result = match cli.command {
+        Command::Scan {
+            window,
+            social,
+            freq,
+            device_types,
+            seek,
+        } => {
+            let discovery_type = match freq {
+                Some(freq) => DiscoveryType::SingleFrequency(freq),
+                None if social => DiscoveryType::Social,
+                None => DiscoveryType::Full,
+            };
+            let discovery = DiscoveryOptions {
+                discovery_type,
+                device_types,
+                seek,
+            };
+            scan(&channel, discovery, window.duration(), output).await
+        }
+        Command::Peers => peers(&channel, output).await,
+        Command::Connect {
+            peer,
+            pin,
+            display,
+            timeout,
+        } => {
+            let config = match (pin, display) {
+                (Some(pin), _) => ConnectConfig::with_pin(peer, WpsMethod::PinKeypad, pin),
+                (None, true) => ConnectConfig {
+                    wps_method: WpsMethod::PinDisplay,
+                    ..ConnectConfig::new(peer)
+                },
+                (None, false) => ConnectConfig::new(peer),
+            };
+            connect(&channel, config, Duration::from_secs(timeout), output).await
+        }
+        Command::Group(GroupCommand::Create) => create_group(&channel, output).await,
+        Command::Group(GroupCommand::Remove) => done(channel.remove_group()).await,
+        Command::Group(GroupCommand::Info) => group_info(&channel, output).await,
+        Command::Services(ServicesCommand::Add { service }) => {
+            done(channel.add_local_service(service.parse()?)).await
+        }
+        Command::Services(ServicesCommand::Remove { service }) => {
+            done(channel.remove_local_service(service.parse()?)).await
+        }
+        Command::Services(ServicesCommand::List(window)) => {
+            services(&channel, None, window.duration(), false, output).await
+        }
+        Command::Services(ServicesCommand::Discover { peer, window }) => {
+            services(&channel, peer, window.duration(), true, output).await
+        }
+        Command::Status => status(&manager, &channel, output).await,
+        Command::Watch { discover, timeout } => {
+            watch(&channel, discover, timeout.map(Duration::from_secs)).await
+        }
+        #[cfg(feature = "tui")]
+        Command::Tui => tui::run(&channel).await,
+        #[cfg(target_os = "linux")]
+        Command::Doctor | Command::Interfaces => {
+            unreachable!("handled before building the manager")
+        }
+    };
+    manager.shutdown().await?;
+    result
+}
+
+async fn scan(
+    channel: &WifiP2pChannel,
+    discovery: DiscoveryOptions,
+    duration: Duration,
+    output: Output,
+) -> Result<(), P2pError> {
+    let mut events = channel.subscribe_events();
+    done(channel.discover_peers_with(discovery)).await?;
+    let deadline = Instant::now() + duration;
+    while let Some(event) = next_event(&mut events, deadline).await? {
+        match &event {
+            P2pEvent::PeerFound(_) | P2pEvent::PeerLost(_) if output.is_json() => {
+                print_event(&event);
+            }
+            P2pEvent::PeerFound(device) => println!("{}", describe_peer(device)),
+            P2pEvent::PeerLost(address) => println!("{address}  lost"),
+            _ => {}
+        }
+    }
+    done(channel.stop_discovery()).await
+}
+
+async fn peers(channel: &WifiP2pChannel, output: Output) -> Result<(), P2pError> {
+    let peers = done(channel.request_peers()).await?;
+    if output.is_json() {
+        print_json(&peers);
+        return Ok(());
+    }
+    for device in peers {
+        println!("{}", describe_peer(&device));
+    }
+    Ok(())
+}
+
+async fn connect(
+    channel: &WifiP2pChannel,
+    config: ConnectConfig,
+    timeout: Duration,
+    output: Output,
+) -> Result<(), P2pError> {
+    let peer = config.device_address;
+    let deadline = Instant::now() + timeout;
+    let mut events = channel.subscribe_events();
+    // The supplicant only connects to peers that discovery has found.
+    let known = done(channel.request_peers()).await?;
+    if !known.iter().any(|device| device.mac_address == peer) {
+        done(channel.discover_peers()).await?;
+        loop {
+            match next_event(&mut events, deadline).await? {
+                Some(P2pEvent::PeerFound(device)) if device.mac_address == peer => break,
+                Some(_) => {}
+                None => return Err(P2pError::Timeout(timeout)),
+            }
+        }
+    }
+    let pin = done(channel.connect_with_timeout(config, timeout)).await?;
+    if let Some(pin) = &pin {
+        // Needed before the group forms, so not held back for the JSON result.
+        if output.is_json() {
+            eprintln!("enter PIN {pin} on {peer}");
+        } else {
+            println!("enter PIN {pin} on {peer}");
+        }
+    }
+    let group = wait_for_group(&mut events, deadline, timeout).await?;
+    if output.is_json() {
+        print_json(&json!({ "peer": peer, "pin": pin, "group": group }));
+        return Ok(());
+    }
+    println!("connected to {peer}");
+    println!("{}", describe_group(&group));
+    Ok(())
+}
+
+async fn create_group(channel: &WifiP2pChannel, output: Output) -> Result<(), P2pError> {
+    let mut events = channel.subscribe_events();
+    done(channel.create_group()).await?;
+    let timeout = Duration::from_secs(30);
+    let group = wait_for_group(&mut events, Instant::now() + timeout, timeout).await?;
+    if output.is_json() {
+        print_json(&group);
+    } else {
+        println!("{}", describe_group(&group));
+    }
+    Ok(())
+}
+
+async fn group_info(channel: &WifiP2pChannel, output: Output) -> Result<(), P2pError> {
+    let groups = done(channel.request_groups()).await?;
+    if output.is_json() {
+        print_json(&groups);
+        return Ok(());
+    }
+    if groups.is_empty() {
+        println!("no group");
+    }
+    for group in groups {
+        println!("{}", describe_group(&group));
+    }
+    Ok(())
+}
+
+async fn services(
+    channel: &WifiP2pChannel,
+    peer: Option<MacAddress>,
+    duration: Duration,
+    stream: bool,
+    output: Output,
+) -> Result<(), P2pError> {
+    let mut events = channel.subscribe_events();
+    // Queries go out to peers as discovery finds them.
+    done(channel.discover_peers()).await?;
+    match peer {
+        Some(peer) => {
+            wait_for_peer(channel, &mut events, peer, Instant::now() + duration).await?;
+            done(channel.discover_services_from(peer)).await?;
+        }
+        None => done(channel.discover_services()).await?,
+    }
+    let deadline = Instant::now() + duration;
+    let mut found: Vec<ServiceResponse> = Vec::new();
+    while let Some(event) = next_event(&mut events, deadline).await? {
+        if let P2pEvent::ServicesFound(response) = &event {
+            match (stream, output) {
+                (true, Output::Json) => print_event(&event),
+                (true, Output::Text) => print_services(response),
+                (false, _) => {}
+            }
+            found.retain(|known| known.peer != response.peer);
+            found.push(response.clone());
+        }
+    }
+    if !stream {
+        found.sort_by_key(|response| response.peer);
+        match output {
+            Output::Json => print_json(&found),
+            Output::Text => found.iter().for_each(print_services),
+        }
+    }
+    done(channel.stop_discovery()).await
+}
+
+async fn status(
+    manager: &WifiP2pManager,
+    channel: &WifiP2pChannel,
+    output: Output,
+) -> Result<(), P2pError> {
+    let capabilities = manager.capabilities().await?;
+    if output.is_json() {
+        let groups = done(channel.request_groups()).await;
+        let peers = done(channel.request_peers()).await?;
+        print_json(&json!({
+            "capabilities": capabilities,
+            "groups": groups.as_ref().ok(),
+            "groups_error": groups.as_ref().err().map(ToString::to_string),
+            "known_peers": peers.len(),
+            "queue": channel.queue_status(),
+        }));
+        return Ok(());
+    }
+    println!("p2p supported:   {}", capabilities.p2p_supported);
+    println!("concurrent sta:  {}", capabilities.concurrent_sta_p2p);
+    println!("p2p device:      {}", capabilities.dedicated_p2p_device);
+    if let Some(max_peers) = capabilities.max_peers {
+        println!("max clients:     {max_peers}");
+    }
+    println!("wps methods:     {}", capabilities.wps_methods.join(", "));
+    // Not every backend can list groups; the rest of the status still helps.
+    match done(channel.request_groups()).await {
+        Ok(groups) if groups.is_empty() => println!("groups:          none"),
+        Ok(groups) => {
+            for group in groups {
+                println!("group:           {}", describe_group(&group));
+            }
+        }
+        Err(err) => println!("groups:          {err}"),
+    }
+    let peers = done(channel.request_peers()).await?;
+    println!("known peers:     {}", peers.len());
+    let queue = channel.queue_status();
+    match queue.longest_running() {
+        Some(running) => println!(
+            "queue:           {} pending, {} running for {:.1?}",
+            queue.pending, running.name, running.elapsed
+        ),
+        None => println!("queue:           {} pending, idle", queue.pending),
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+async fn doctor(interface: &str, output: Output) -> Result<(), P2pError> {
+    let diagnosis = wifi_p2p_rs::diagnose(interface).await;
+    if output.is_json() {
+        print_json(&diagnosis);
+    } else {
+        for finding in &diagnosis.findings {
+            let label = match finding.severity {
+                Severity::Ok => "ok",
+                Severity::Warning => "warn",
+                Severity::Error => "FAIL",
+            };
+            println!("{label:<5} {:<21} {}", finding.check, finding.summary);
+            if let Some(hint) = &finding.hint {
+                println!("{:27}-> {hint}", "");
+            }
+        }
+    }
+    if diagnosis.is_healthy() {
+        Ok(())
+    } else {
+        Err(P2pError::Unsupported(
+            "doctor found problems that stop Wi-Fi Direct from working".to_string(),
+        ))
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn interfaces(output: Output) -> Result<(), P2pError> {
+    let interfaces = WifiP2pManager::discover_interfaces()?;
+    if output.is_json() {
+        print_json(&interfaces);
+        return Ok(());
+    }
+    for interface in &interfaces {
+        let p2p = match (interface.supports_p2p, interface.p2p_device) {
+            (true, true) => "P2P, P2P device",
+            (true, false) => "P2P",
+            (false, _) => "no P2P",
+        };
+        println!(
+            "{:<16} {:<6} {:<10} {:<4} {p2p}",
+            interface.name,
+            interface.phy,
+            interface.driver.as_deref().unwrap_or("-"),
+            if interface.up { "up" } else { "down" },
+        );
+    }
+    Ok(())
+}
+
+async fn watch(
+    channel: &WifiP2pChannel,
+    discover: bool,
+    timeout: Option<Duration>,
+) -> Result<(), P2pError> {
+    let mut events = channel.subscribe_events();
+    if discover {
+        done(channel.discover_peers()).await?;
+    }
+    // Far enough away to mean "until interrupted".
+    let deadline = Instant::now() + timeout.unwrap_or(Duration::from_secs(86400 * 365));
+    loop {
+        tokio::select! {
+            event = next_event(&mut events, deadline) => match event? {
+                Some(event) => print_event(&event),
+                None => break,
+            },
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+    if discover {
+        done(channel.stop_discovery()).await?;
+    }
+    Ok(())
+}
+
+async fn wait_for_peer(
+    channel: &WifiP2pChannel,
+    events: &mut broadcast::Receiver<P2pEvent>,
+    peer: MacAddress,
+    deadline: Instant,
+) -> Result<(), P2pError> {
+    if channel
+        .peers_snapshot()
+        .iter()
+        .any(|device| device.mac_address == peer)
+    {
+        return Ok(());
+    }
+    loop {
+        match next_event(events, deadline).await? {
+            Some(P2pEvent::PeerFound(device)) if device.mac_address == peer => return Ok(()),
+            Some(_) => {}
+            None => return Err(P2pError::InvalidArgs(format!("{peer} was not found"))),
+        }
+    }
+}
+
+async fn wait_for_group(
+    events: &mut broadcast::Receiver<P2pEvent>,
+    deadline: Instant,
+    timeout: Duration,
+) -> Result<P2pGroup, P2pError> {
+    loop {
+        match next_event(events, deadline).await? {
+            Some(P2pEvent::GroupStarted(group)) => return Ok(Arc::unwrap_or_clone(group)),
+            Some(P2pEvent::GoNegotiationFailed { status, .. }) => {
+                return Err(P2pError::Status(status));
+            }
+            Some(_) => {}
+            None => return Err(P2pError::Timeout(timeout)),
+        }
+    }
+}
+
+/// The next event before `deadline`, or `None` once it has passed.
+async fn next_event(
+    events: &mut broadcast::Receiver<P2pEvent>,
+    deadline: Instant,
+) -> Result<Option<P2pEvent>, P2pError> {
+    loop {
+        match tokio::time::timeout_at(deadline, events.recv()).await {
+            Err(_) => return Ok(None),
+            Ok(Ok(event)) => return Ok(Some(event)),
+            Ok(Err(RecvError::Lagged(_))) => {}
+            Ok(Err(RecvError::Closed)) => {
+                return Err(P2pError::ChannelClosed("manager".to_string()));
+            }
+        }
+    }
+}
+
+/// Queue a command and wait for the manager's answer.
+async fn done<T>(
+    queued: impl Future<Output = Result<oneshot::Receiver<Result<T, P2pError>>, P2pError>>,
+) -> Result<T, P2pError> {
+    queued
+        .await?
+        .await
+        .map_err(|_| P2pError::ChannelClosed("manager".to_string()))?
+}
+
+fn describe_peer(device: &P2pDevice) -> String {
+    let name = device.device_name.as_deref().unwrap_or("-");
+    let signal = device
+        .signal_level
+        .map_or_else(|| "-".to_string(), |level| format!("{level} dBm"));
+    format!(
+        "{}  {name:<24} {:<10} {signal}",
+        device.mac_address,
+        format!("{:?}", device.status)
+    )
+}
+
+fn describe_group(group: &P2pGroup) -> String {
+    let mut line = format!("{} ({:?})", group.interface, group.role);
+    if let Some(allocation) = group.ip_allocation {
+        line.push_str(&format!(
+            " ip {}/{} owner {}",
+            allocation.ip,
+            allocation.prefix_len(),
+            allocation.go_ip
+        ));
+    }
+    if let Some(peer) = group.peer {
+        line.push_str(&format!(" with {peer}"));
+    }
+    line
+}
+
+fn print_services(response: &ServiceResponse) {
+    for tlv in &response.tlvs {
+        let data = if let Some(record) = tlv.dns_sd() {
+            format!("{record:?}")
+        } else if let Some(upnp) = tlv.upnp() {
+            upnp.services.join(", ")
+        } else if tlv.is_success() {
+            hex(&tlv.data)
+        } else {
+            format!("status {}", tlv.status)
+        };
+        println!("{}  {:?}  {data}", response.peer, tlv.protocol);
+    }
+}
+
+impl ServiceArg {
+    fn parse(self) -> Result<LocalService, P2pError> {
+        match self {
+            Self::Bonjour { query, response } => Ok(LocalService::Bonjour {
+                query: unhex(&query)?,
+                response: unhex(&response)?,
+            }),
+            Self::Upnp { service, version } => Ok(LocalService::Upnp { version, service }),
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn unhex(text: &str) -> Result<Vec<u8>, P2pError> {
+    let invalid = || P2pError::InvalidArgs(format!("{text} is not hex"));
+    if !text.len().is_multiple_of(2) {
+        return Err(invalid());
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| {
+            text.get(i..i + 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(invalid)
+        })
+        .collect()
+}
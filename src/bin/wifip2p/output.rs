@@ -0,0 +1,64 @@
+//! `--json`: one JSON document per command, or one object per line for the
+//! commands that stream (`scan`, `services discover`, `watch`).
+//!
+//! Events are printed as `{"time": <unix seconds>, "type": "<variant>", "data": ...}`,
+//! with `data` left out for variants without fields, so every line has the
+//! same shape whatever the event.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use serde_json::{Map, Value, json};
+use wifi_p2p_rs::P2pEvent;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Output {
+    Text,
+    Json,
+}
+
+impl Output {
+    pub fn is_json(self) -> bool {
+        self == Self::Json
+    }
+}
+
+/// Print `value` as a single JSON document.
+pub fn print_json(value: &impl Serialize) {
+    match serde_json::to_string(value) {
+        Ok(text) => println!("{text}"),
+        Err(err) => eprintln!("wifip2p: {err}"),
+    }
+}
+
+/// Print `event` as one NDJSON line.
+pub fn print_event(event: &P2pEvent) {
+    println!("{}", event_json(event));
+}
+
+fn event_json(event: &P2pEvent) -> Value {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |since| since.as_secs_f64());
+    let mut line = Map::new();
+    line.insert("time".to_string(), json!(time));
+    // serde tags unit variants as a bare string and the rest as {"Variant": data}.
+    match serde_json::to_value(event) {
+        Ok(Value::String(kind)) => {
+            line.insert("type".to_string(), Value::String(kind));
+        }
+        Ok(Value::Object(tagged)) => {
+            for (kind, data) in tagged {
+                line.insert("type".to_string(), Value::String(kind));
+                line.insert("data".to_string(), data);
+            }
+        }
+        Ok(other) => {
+            line.insert("data".to_string(), other);
+        }
+        Err(err) => {
+            line.insert("error".to_string(), Value::String(err.to_string()));
+        }
+    }
+    Value::Object(line)
+}
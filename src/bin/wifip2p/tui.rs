@@ -0,0 +1,358 @@
+//! `wifip2p tui`: a live view of peers, the group and the event stream, like
+//! wavemon for Wi-Fi Direct.
+//!
+//! Discovery runs while the view is open. Commands run in the background and
+//! report back to the event log, so a slow connect never freezes the screen.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use ratatui::DefaultTerminal;
+use ratatui::Frame;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, Paragraph, Row, Table, TableState};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc;
+use wifi_p2p_rs::{
+    DeviceStatus, MacAddress, P2pDevice, P2pError, P2pEvent, P2pGroup, WifiP2pChannel,
+};
+
+use crate::{describe_group, done};
+
+/// Lines kept in the event log.
+const LOG_LINES: usize = 500;
+
+pub async fn run(channel: &WifiP2pChannel) -> Result<(), P2pError> {
+    let mut terminal = ratatui::init();
+    let result = App::new(channel.clone()).run(&mut terminal).await;
+    ratatui::restore();
+    result
+}
+
+struct App {
+    channel: WifiP2pChannel,
+    peers: Vec<P2pDevice>,
+    groups: Vec<P2pGroup>,
+    /// Peers that asked to connect and have not been answered.
+    requests: Vec<MacAddress>,
+    table: TableState,
+    log: VecDeque<String>,
+    discovering: bool,
+    quit: bool,
+}
+
+impl App {
+    fn new(channel: WifiP2pChannel) -> Self {
+        Self {
+            channel,
+            peers: Vec::new(),
+            groups: Vec::new(),
+            requests: Vec::new(),
+            table: TableState::default(),
+            log: VecDeque::new(),
+            discovering: false,
+            quit: false,
+        }
+    }
+
+    async fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<(), P2pError> {
+        let mut events = self.channel.subscribe_events();
+        let (results_tx, mut results) = mpsc::unbounded_channel();
+        let mut keys = read_keys();
+        let mut refresh = tokio::time::interval(Duration::from_secs(1));
+        self.spawn("discovery", &results_tx, |channel| async move {
+            done(channel.discover_peers()).await
+        });
+        while !self.quit {
+            self.refresh();
+            terminal.draw(|frame| self.draw(frame)).map_err(tui_error)?;
+            tokio::select! {
+                Some(key) = keys.recv() => self.on_key(key, &results_tx),
+                event = events.recv() => match event {
+                    Ok(event) => self.on_event(event),
+                    Err(RecvError::Lagged(skipped)) => {
+                        self.push_log(format!("({skipped} events skipped)"));
+                    }
+                    Err(RecvError::Closed) => {
+                        return Err(P2pError::ChannelClosed("manager".to_string()));
+                    }
+                },
+                Some(line) = results.recv() => self.push_log(line),
+                _ = refresh.tick() => {}
+            }
+        }
+        let _ = done(self.channel.stop_discovery()).await;
+        Ok(())
+    }
+
+    fn refresh(&mut self) {
+        self.peers = self.channel.peers_snapshot();
+        self.peers.sort_by_key(|device| device.mac_address);
+        self.groups = self.channel.groups_snapshot();
+        let selected = match self.peers.len() {
+            0 => None,
+            len => Some(self.table.selected().unwrap_or(0).min(len - 1)),
+        };
+        self.table.select(selected);
+    }
+
+    fn selected_peer(&self) -> Option<MacAddress> {
+        let index = self.table.selected()?;
+        self.peers.get(index).map(|device| device.mac_address)
+    }
+
+    fn on_key(&mut self, key: KeyEvent, results: &mpsc::UnboundedSender<String>) {
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.quit = true;
+            }
+            KeyCode::Down | KeyCode::Char('j') => self.table.select_next(),
+            KeyCode::Up | KeyCode::Char('k') => self.table.select_previous(),
+            KeyCode::Char('d') => {
+                if self.discovering {
+                    self.spawn("stop discovery", results, |channel| async move {
+                        done(channel.stop_discovery()).await
+                    });
+                } else {
+                    self.spawn("discovery", results, |channel| async move {
+                        done(channel.discover_peers()).await
+                    });
+                }
+            }
+            KeyCode::Enter | KeyCode::Char('c') => {
+                if let Some(peer) = self.selected_peer() {
+                    self.requests.retain(|request| *request != peer);
+                    self.push_log(format!("connecting to {peer}"));
+                    // Push-button, so there is never a PIN to show.
+                    self.spawn("connect", results, move |channel| async move {
+                        done(channel.connect(peer)).await.map(drop)
+                    });
+                }
+            }
+            KeyCode::Char('i') => {
+                if let Some(peer) = self.selected_peer() {
+                    self.spawn("invite", results, move |channel| async move {
+                        done(channel.invite(peer)).await
+                    });
+                }
+            }
+            KeyCode::Char('r') => {
+                if let Some(peer) = self.selected_peer() {
+                    self.requests.retain(|request| *request != peer);
+                    self.spawn("reject", results, move |channel| async move {
+                        done(channel.reject_peer(peer)).await
+                    });
+                }
+            }
+            KeyCode::Char('g') => self.spawn("create group", results, |channel| async move {
+                done(channel.create_group()).await
+            }),
+            KeyCode::Char('x') => self.spawn("remove group", results, |channel| async move {
+                done(channel.remove_group()).await
+            }),
+            _ => {}
+        }
+    }
+
+    /// Run a command off the UI loop; its outcome lands in the log.
+    fn spawn<F, Fut>(&self, what: &'static str, results: &mpsc::UnboundedSender<String>, action: F)
+    where
+        F: FnOnce(WifiP2pChannel) -> Fut,
+        Fut: Future<Output = Result<(), P2pError>> + Send + 'static,
+    {
+        let results = results.clone();
+        let action = action(self.channel.clone());
+        tokio::spawn(async move {
+            let line = match action.await {
+                Ok(()) => format!("{what}: ok"),
+                Err(err) => format!("{what}: {err}"),
+            };
+            let _ = results.send(line);
+        });
+    }
+
+    fn on_event(&mut self, event: P2pEvent) {
+        match &event {
+            P2pEvent::DiscoveryStarted => self.discovering = true,
+            P2pEvent::DiscoveryStopped => self.discovering = false,
+            P2pEvent::ConnectionRequested(peer) if !self.requests.contains(peer) => {
+                self.requests.push(*peer);
+            }
+            P2pEvent::PeerLost(peer) => self.requests.retain(|request| request != peer),
+            // The peer table itself shows these.
+            P2pEvent::PeersChanged { .. }
+            | P2pEvent::PeerStatusChanged(..)
+            | P2pEvent::LinkStats(_) => return,
+            _ => {}
+        }
+        self.push_log(describe_event(&event));
+    }
+
+    fn push_log(&mut self, line: String) {
+        if self.log.len() == LOG_LINES {
+            self.log.pop_front();
+        }
+        self.log.push_back(line);
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, log, help] = Layout::vertical([
+            Constraint::Min(8),
+            Constraint::Length(10),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [peers, group] =
+            Layout::horizontal([Constraint::Min(50), Constraint::Length(40)]).areas(main);
+        self.draw_peers(frame, peers);
+        self.draw_groups(frame, group);
+        self.draw_log(frame, log);
+        frame.render_widget(
+            Line::from(
+                " ↑↓ select  c connect  i invite  r reject  g group  x leave  d discovery  q quit",
+            )
+            .dim(),
+            help,
+        );
+    }
+
+    fn draw_peers(&mut self, frame: &mut Frame, area: Rect) {
+        let rows = self.peers.iter().map(|device| {
+            let name = device.device_name.clone().unwrap_or_default();
+            let marker = if self.requests.contains(&device.mac_address) {
+                Span::styled("!", Style::new().fg(Color::Yellow).bold())
+            } else {
+                Span::raw(" ")
+            };
+            Row::new(vec![
+                Line::from(marker),
+                Line::from(device.mac_address.to_string()),
+                Line::from(name),
+                Line::from(Span::styled(
+                    format!("{:?}", device.status),
+                    status_style(device.status),
+                )),
+                Line::from(signal(device.signal_level)),
+            ])
+        });
+        let widths = [
+            Constraint::Length(1),
+            Constraint::Length(17),
+            Constraint::Min(12),
+            Constraint::Length(11),
+            Constraint::Length(12),
+        ];
+        let title = if self.discovering {
+            " Peers (discovering) "
+        } else {
+            " Peers "
+        };
+        let table = Table::new(rows, widths)
+            .header(Row::new(vec!["", "Address", "Name", "Status", "Signal"]).bold())
+            .block(Block::bordered().title(title))
+            .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(table, area, &mut self.table);
+    }
+
+    fn draw_groups(&self, frame: &mut Frame, area: Rect) {
+        let lines: Vec<Line> = if self.groups.is_empty() {
+            vec![Line::from("no group").dim()]
+        } else {
+            self.groups
+                .iter()
+                .map(|group| Line::from(describe_group(group)))
+                .collect()
+        };
+        let paragraph = Paragraph::new(lines)
+            .wrap(ratatui::widgets::Wrap { trim: true })
+            .block(Block::bordered().title(" Group "));
+        frame.render_widget(paragraph, area);
+    }
+
+    fn draw_log(&self, frame: &mut Frame, area: Rect) {
+        // Only the newest lines that fit between the borders.
+        let visible = usize::from(area.height.saturating_sub(2));
+        let items: Vec<ListItem> = self
+            .log
+            .iter()
+            .skip(self.log.len().saturating_sub(visible))
+            .map(|line| ListItem::new(line.as_str()))
+            .collect();
+        frame.render_widget(
+            List::new(items).block(Block::bordered().title(" Events ")),
+            area,
+        );
+    }
+}
+
+/// Key presses, read on a thread of their own since crossterm blocks.
+fn read_keys() -> mpsc::UnboundedReceiver<KeyEvent> {
+    let (keys_tx, keys) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        while let Ok(event) = event::read() {
+            if let Event::Key(key) = event
+                && key.kind == KeyEventKind::Press
+                && keys_tx.send(key).is_err()
+            {
+                break;
+            }
+        }
+    });
+    keys
+}
+
+fn describe_event(event: &P2pEvent) -> String {
+    match event {
+        P2pEvent::PeerFound(device) => format!(
+            "found {} {}",
+            device.mac_address,
+            device.device_name.as_deref().unwrap_or_default()
+        ),
+        P2pEvent::PeerLost(peer) => format!("lost {peer}"),
+        P2pEvent::ConnectionRequested(peer) => {
+            format!("{peer} wants to connect: c to accept, r to reject")
+        }
+        P2pEvent::Connected(peer) => format!("connected to {peer}"),
+        P2pEvent::GoNegotiationFailed { peer, status } => match peer {
+            Some(peer) => format!("negotiation with {peer} failed: {status}"),
+            None => format!("negotiation failed: {status}"),
+        },
+        P2pEvent::InvitationResult { status } => format!("invitation: {status}"),
+        P2pEvent::GroupStarted(group) => format!("group started: {}", describe_group(group)),
+        P2pEvent::GroupFinished(group) => format!("group finished: {}", group.interface),
+        P2pEvent::PeerIpResolved { mac, ip } => format!("{mac} is at {ip}"),
+        event => format!("{event:?}"),
+    }
+}
+
+fn status_style(status: DeviceStatus) -> Style {
+    match status {
+        DeviceStatus::Available => Style::new(),
+        DeviceStatus::Invited => Style::new().fg(Color::Yellow),
+        DeviceStatus::Connected => Style::new().fg(Color::Green),
+        DeviceStatus::Failed => Style::new().fg(Color::Red),
+        DeviceStatus::Unavailable => Style::new().dim(),
+    }
+}
+
+/// RSSI with a four-step bar, as in the Wi-Fi status icons.
+fn signal(level: Option<i32>) -> String {
+    let Some(level) = level else {
+        return "-".to_string();
+    };
+    let bars = match level {
+        -55.. => "▂▄▆█",
+        -67..=-56 => "▂▄▆ ",
+        -78..=-68 => "▂▄  ",
+        _ => "▂   ",
+    };
+    format!("{bars} {level}")
+}
+
+fn tui_error(err: std::io::Error) -> P2pError {
+    P2pError::Backend(format!("tui: {err}"))
+}
@@ -0,0 +1,170 @@
+//! `wifip2pd`: run one manager and share it over D-Bus as `org.wifip2p`, so
+//! applications use Wi-Fi Direct without talking to wpa_supplicant themselves.
+//! See `wifi_p2p_rs::daemon` for the interface.
+
+use std::process::ExitCode;
+
+use clap::Parser;
+use wifi_p2p_rs::{P2pError, WifiP2pManager, daemon};
+use zbus::Connection;
+
+#[derive(Parser)]
+#[command(name = "wifip2pd", version, about = "Share Wi-Fi Direct over D-Bus")]
+struct Args {
+    /// Interface wpa_supplicant manages.
+    #[arg(short, long, default_value = "wlan0")]
+    interface: String,
+    /// Publish on the session bus instead of the system bus, for testing.
+    #[arg(long)]
+    session: bool,
+    /// Also serve the gRPC API on this address, e.g. 127.0.0.1:50051.
+    #[cfg(feature = "grpc")]
+    #[arg(long, value_name = "ADDR")]
+    grpc: Option<std::net::SocketAddr>,
+    /// Also serve the HTTP API on this address, e.g. 127.0.0.1:8080.
+    #[cfg(feature = "http")]
+    #[arg(long, value_name = "ADDR")]
+    http: Option<std::net::SocketAddr>,
+    /// Also serve JSON-RPC on a Unix socket at this path, e.g. /run/wifip2p.sock.
+    #[cfg(all(feature = "jsonrpc", unix))]
+    #[arg(long, value_name = "PATH")]
+    jsonrpc: Option<std::path::PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    match run(Args::parse()).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("wifip2pd: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(args: Args) -> Result<(), P2pError> {
+    let builder = WifiP2pManager::builder();
+    #[cfg(all(feature = "systemd", unix))]
+    let builder = builder.systemd_watchdog(true);
+    let mut manager = builder.build(&args.interface).await?;
+    let channel = manager.initialize();
+    let bus = if args.session {
+        Connection::session().await?
+    } else {
+        Connection::system().await?
+    };
+    #[cfg(any(feature = "grpc", feature = "http"))]
+    let mut activated = activated_sockets();
+    #[cfg(feature = "grpc")]
+    let grpc = serve_grpc(channel.clone(), listen("grpc", args.grpc, &mut activated));
+    #[cfg(not(feature = "grpc"))]
+    let grpc = std::future::pending();
+    #[cfg(feature = "http")]
+    let http = serve_http(channel.clone(), listen("http", args.http, &mut activated));
+    #[cfg(not(feature = "http"))]
+    let http = std::future::pending();
+    #[cfg(all(feature = "jsonrpc", unix))]
+    let jsonrpc = serve_jsonrpc(channel.clone(), args.jsonrpc);
+    #[cfg(not(all(feature = "jsonrpc", unix)))]
+    let jsonrpc = std::future::pending();
+    #[cfg(all(feature = "systemd", unix))]
+    wifi_p2p_rs::systemd::notify_ready()?;
+    let result = tokio::select! {
+        result = daemon::serve(channel, &bus) => result,
+        result = grpc => result,
+        result = http => result,
+        result = jsonrpc => result,
+        () = terminated() => Ok(()),
+    };
+    #[cfg(all(feature = "systemd", unix))]
+    let _ = wifi_p2p_rs::systemd::notify("STOPPING=1");
+    // Groups and discovery die with the daemon that owned them.
+    manager.shutdown().await?;
+    result
+}
+
+/// Where to serve an API from: a `--grpc`/`--http` address, or a socket
+/// systemd passed with that `FileDescriptorName=`.
+#[cfg(any(feature = "grpc", feature = "http"))]
+enum Listen {
+    Addr(std::net::SocketAddr),
+    Socket(std::net::TcpListener),
+}
+
+#[cfg(any(feature = "grpc", feature = "http"))]
+fn activated_sockets() -> Vec<(String, std::net::TcpListener)> {
+    #[cfg(all(feature = "systemd", unix))]
+    return wifi_p2p_rs::systemd::listen_fds()
+        .into_iter()
+        .map(|(name, fd)| (name, fd.into()))
+        .collect();
+    #[cfg(not(all(feature = "systemd", unix)))]
+    Vec::new()
+}
+
+/// An activated socket named `name` wins over the command-line address.
+#[cfg(any(feature = "grpc", feature = "http"))]
+fn listen(
+    name: &str,
+    addr: Option<std::net::SocketAddr>,
+    activated: &mut Vec<(String, std::net::TcpListener)>,
+) -> Option<Listen> {
+    match activated.iter().position(|(socket, _)| socket == name) {
+        Some(index) => Some(Listen::Socket(activated.remove(index).1)),
+        None => addr.map(Listen::Addr),
+    }
+}
+
+#[cfg(feature = "grpc")]
+async fn serve_grpc(
+    channel: wifi_p2p_rs::WifiP2pChannel,
+    listen: Option<Listen>,
+) -> Result<(), P2pError> {
+    use wifi_p2p_rs::grpc;
+
+    match listen {
+        Some(Listen::Addr(addr)) => grpc::serve(channel, addr).await,
+        Some(Listen::Socket(listener)) => grpc::serve_listener(channel, listener).await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(feature = "http")]
+async fn serve_http(
+    channel: wifi_p2p_rs::WifiP2pChannel,
+    listen: Option<Listen>,
+) -> Result<(), P2pError> {
+    use wifi_p2p_rs::http;
+
+    match listen {
+        Some(Listen::Addr(addr)) => http::serve(channel, addr).await,
+        Some(Listen::Socket(listener)) => http::serve_listener(channel, listener).await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(all(feature = "jsonrpc", unix))]
+async fn serve_jsonrpc(
+    channel: wifi_p2p_rs::WifiP2pChannel,
+    path: Option<std::path::PathBuf>,
+) -> Result<(), P2pError> {
+    match path {
+        Some(path) => wifi_p2p_rs::jsonrpc::serve(channel, path).await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn terminated() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        if let Ok(mut term) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = term.recv() => return,
+                _ = tokio::signal::ctrl_c() => return,
+            }
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
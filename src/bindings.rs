@@ -0,0 +1,343 @@
+//! UniFFI definitions for Kotlin and Swift, generated with the `uniffi-bindgen`
+//! binary from the built library.
+//!
+//! `P2pDevice`, `P2pGroup`, `ConnectConfig` and their enums derive the UniFFI
+//! traits where they are defined, and `P2pError` becomes `P2pException`.
+//! Addresses cross over as strings. The manager and channel are wrapped here
+//! as objects whose actions are `suspend`/`async` functions, and
+//! `WifiP2pChannel.events()` hands out an `EventStream` to pull `P2pEvent`s
+//! from, a flattened copy of the crate's event enum.
+
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::{Arc, Mutex};
+
+use tokio::runtime::Handle;
+use tokio::sync::oneshot;
+use tokio_stream::StreamExt;
+
+use crate::channel;
+use crate::config::ConnectConfig;
+use crate::device::{DeviceStatus, P2pDevice};
+use crate::error::P2pError;
+use crate::group::P2pGroup;
+use crate::mac::MacAddress;
+use crate::manager;
+use crate::stream::P2pEventStream;
+
+uniffi::custom_type!(MacAddress, String);
+uniffi::custom_type!(Ipv4Addr, String);
+uniffi::custom_type!(IpAddr, String);
+
+impl crate::UniffiCustomTypeConverter for MacAddress {
+    type Builtin = String;
+
+    fn into_custom(val: String) -> uniffi::Result<Self> {
+        Ok(val.parse::<MacAddress>()?)
+    }
+
+    fn from_custom(obj: Self) -> String {
+        obj.to_string()
+    }
+}
+
+impl crate::UniffiCustomTypeConverter for Ipv4Addr {
+    type Builtin = String;
+
+    fn into_custom(val: String) -> uniffi::Result<Self> {
+        Ok(val.parse()?)
+    }
+
+    fn from_custom(obj: Self) -> String {
+        obj.to_string()
+    }
+}
+
+impl crate::UniffiCustomTypeConverter for IpAddr {
+    type Builtin = String;
+
+    fn into_custom(val: String) -> uniffi::Result<Self> {
+        Ok(val.parse()?)
+    }
+
+    fn from_custom(obj: Self) -> String {
+        obj.to_string()
+    }
+}
+
+/// The manager, opened with `WifiP2pManager.open("wlan0")`.
+#[derive(uniffi::Object)]
+pub struct WifiP2pManager {
+    // Taken out by `shutdown`, which needs it across an await.
+    inner: Mutex<Option<manager::WifiP2pManager>>,
+    // Where `open` ran; the manager spawns its tasks there.
+    runtime: Handle,
+}
+
+#[uniffi::export(async_runtime = "tokio")]
+impl WifiP2pManager {
+    /// Build the platform manager on `interface_name`.
+    #[uniffi::constructor]
+    pub async fn open(interface_name: String) -> Result<Arc<Self>, P2pError> {
+        let manager = manager::WifiP2pManager::new(&interface_name).await?;
+        Ok(Arc::new(Self {
+            inner: Mutex::new(Some(manager)),
+            runtime: Handle::current(),
+        }))
+    }
+
+    /// Stop discovery, remove groups and end the command loop; see the Rust
+    /// `WifiP2pManager::shutdown`. The manager cannot be used afterwards.
+    pub async fn shutdown(&self) -> Result<(), P2pError> {
+        let manager = lock(&self.inner).take();
+        match manager {
+            Some(mut manager) => manager.shutdown().await,
+            None => Ok(()),
+        }
+    }
+}
+
+#[uniffi::export]
+impl WifiP2pManager {
+    /// Start the command loop and return a channel to it.
+    pub fn initialize(&self) -> Result<Arc<WifiP2pChannel>, P2pError> {
+        let _guard = self.runtime.enter();
+        let inner = lock(&self.inner)
+            .as_ref()
+            .map(manager::WifiP2pManager::initialize)
+            .ok_or_else(|| P2pError::ChannelClosed("manager shut down".to_string()))?;
+        Ok(Arc::new(WifiP2pChannel { inner }))
+    }
+}
+
+impl Drop for WifiP2pManager {
+    fn drop(&mut self) {
+        // Dropping the manager spawns its cleanup, which needs the runtime.
+        let _guard = self.runtime.enter();
+        lock(&self.inner).take();
+    }
+}
+
+/// A channel to the manager; every action is a `suspend`/`async` function.
+#[derive(uniffi::Object)]
+pub struct WifiP2pChannel {
+    inner: channel::WifiP2pChannel,
+}
+
+#[uniffi::export(async_runtime = "tokio")]
+impl WifiP2pChannel {
+    pub async fn discover_peers(&self) -> Result<(), P2pError> {
+        reply(self.inner.discover_peers()).await
+    }
+
+    pub async fn stop_discovery(&self) -> Result<(), P2pError> {
+        reply(self.inner.stop_discovery()).await
+    }
+
+    /// Returns the PIN the supplicant generated, if any.
+    pub async fn connect(&self, config: ConnectConfig) -> Result<Option<String>, P2pError> {
+        reply(self.inner.connect_with_config(config)).await
+    }
+
+    pub async fn cancel_connect(&self) -> Result<(), P2pError> {
+        reply(self.inner.cancel_connect()).await
+    }
+
+    pub async fn request_peers(&self) -> Result<Vec<P2pDevice>, P2pError> {
+        reply(self.inner.request_peers()).await
+    }
+
+    pub async fn request_groups(&self) -> Result<Vec<P2pGroup>, P2pError> {
+        reply(self.inner.request_groups()).await
+    }
+
+    pub async fn create_group(&self) -> Result<(), P2pError> {
+        reply(self.inner.create_group()).await
+    }
+
+    pub async fn invite(&self, peer: MacAddress) -> Result<(), P2pError> {
+        reply(self.inner.invite(peer)).await
+    }
+
+    pub async fn reject_peer(&self, peer: MacAddress) -> Result<(), P2pError> {
+        reply(self.inner.reject_peer(peer)).await
+    }
+
+    pub async fn remove_group(&self) -> Result<(), P2pError> {
+        reply(self.inner.remove_group()).await
+    }
+
+    pub async fn discover_services(&self) -> Result<(), P2pError> {
+        reply(self.inner.discover_services()).await
+    }
+}
+
+#[uniffi::export]
+impl WifiP2pChannel {
+    /// The manager's peer table, without going through the queue.
+    pub fn peers_snapshot(&self) -> Vec<P2pDevice> {
+        self.inner.peers_snapshot()
+    }
+
+    /// Events from now on; see `EventStream::next`.
+    pub fn events(&self) -> Arc<EventStream> {
+        Arc::new(EventStream {
+            inner: tokio::sync::Mutex::new(self.inner.event_stream()),
+        })
+    }
+}
+
+/// Pull events with `next()` until it returns null/nil, when the manager stops.
+#[derive(uniffi::Object)]
+pub struct EventStream {
+    inner: tokio::sync::Mutex<P2pEventStream>,
+}
+
+#[uniffi::export(async_runtime = "tokio")]
+impl EventStream {
+    pub async fn next(&self) -> Option<P2pEvent> {
+        let event = self.inner.lock().await.next().await?;
+        Some(P2pEvent::from(&event))
+    }
+}
+
+/// `channel::P2pEvent` with the variants apps act on spelled out and the rest
+/// in `Other`, as the gRPC API does.
+#[derive(Debug, Clone, uniffi::Enum)]
+pub enum P2pEvent {
+    DiscoveryStarted,
+    DiscoveryStopped,
+    PeerFound {
+        device: P2pDevice,
+    },
+    PeerLost {
+        address: MacAddress,
+    },
+    PeersChanged {
+        added: Vec<P2pDevice>,
+        removed: Vec<MacAddress>,
+        updated: Vec<P2pDevice>,
+    },
+    PeerStatusChanged {
+        address: MacAddress,
+        status: DeviceStatus,
+    },
+    ConnectionRequested {
+        address: MacAddress,
+    },
+    Connected {
+        address: MacAddress,
+    },
+    /// `status` is the P2P status code's description.
+    GoNegotiationFailed {
+        peer: Option<MacAddress>,
+        status: String,
+    },
+    ProvisionDiscoveryFailed {
+        peer: MacAddress,
+        status: String,
+    },
+    GroupCreated,
+    GroupStarted {
+        group: P2pGroup,
+    },
+    GroupFinished {
+        group: P2pGroup,
+    },
+    PeerIpResolved {
+        address: MacAddress,
+        ip: IpAddr,
+    },
+    BackendLost {
+        reason: String,
+    },
+    BackendRestored,
+    /// Any other event: its variant name and its Rust `Debug` form.
+    Other {
+        kind: String,
+        detail: String,
+    },
+}
+
+impl From<&channel::P2pEvent> for P2pEvent {
+    fn from(event: &channel::P2pEvent) -> Self {
+        use channel::P2pEvent as Event;
+
+        let devices = |devices: &[Arc<P2pDevice>]| {
+            devices
+                .iter()
+                .map(|device| P2pDevice::clone(device))
+                .collect()
+        };
+        match event {
+            Event::DiscoveryStarted => Self::DiscoveryStarted,
+            Event::DiscoveryStopped => Self::DiscoveryStopped,
+            Event::PeerFound(device) => Self::PeerFound {
+                device: P2pDevice::clone(device),
+            },
+            Event::PeerLost(address) => Self::PeerLost { address: *address },
+            Event::PeersChanged {
+                added,
+                removed,
+                updated,
+            } => Self::PeersChanged {
+                added: devices(added),
+                removed: removed.clone(),
+                updated: devices(updated),
+            },
+            Event::PeerStatusChanged(address, status) => Self::PeerStatusChanged {
+                address: *address,
+                status: *status,
+            },
+            Event::ConnectionRequested(address) => Self::ConnectionRequested { address: *address },
+            Event::Connected(address) => Self::Connected { address: *address },
+            Event::GoNegotiationFailed { peer, status } => Self::GoNegotiationFailed {
+                peer: *peer,
+                status: status.to_string(),
+            },
+            Event::ProvisionDiscoveryFailed { peer, status } => Self::ProvisionDiscoveryFailed {
+                peer: *peer,
+                status: status.to_string(),
+            },
+            Event::GroupCreated => Self::GroupCreated,
+            Event::GroupStarted(group) => Self::GroupStarted {
+                group: P2pGroup::clone(group),
+            },
+            Event::GroupFinished(group) => Self::GroupFinished {
+                group: P2pGroup::clone(group),
+            },
+            Event::PeerIpResolved { mac, ip } => Self::PeerIpResolved {
+                address: *mac,
+                ip: *ip,
+            },
+            Event::BackendLost { reason } => Self::BackendLost {
+                reason: reason.clone(),
+            },
+            Event::BackendRestored => Self::BackendRestored,
+            other => {
+                let detail = format!("{other:?}");
+                let kind = detail
+                    .chars()
+                    .take_while(char::is_ascii_alphanumeric)
+                    .collect();
+                Self::Other { kind, detail }
+            }
+        }
+    }
+}
+
+/// Await a queued command's result.
+async fn reply<T>(
+    queued: impl Future<Output = Result<oneshot::Receiver<Result<T, P2pError>>, P2pError>>,
+) -> Result<T, P2pError> {
+    queued
+        .await?
+        .await
+        .map_err(|_| P2pError::ChannelClosed("manager".to_string()))?
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    // The slot is only swapped whole, so a panicking holder leaves nothing half-done.
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
@@ -0,0 +1,319 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::runtime::Runtime;
+use tokio::sync::{broadcast, oneshot};
+use zbus::zvariant::OwnedValue;
+
+use crate::approver::ConnectionApprover;
+use crate::backend::{P2pBackend, P2pBackendImpl};
+use crate::capabilities::P2pCapabilities;
+use crate::channel::{P2pEvent, WifiP2pChannel};
+use crate::config::{ConnectConfig, DiscoverabilityPolicy, DiscoveryOptions};
+use crate::device::{LocalDeviceInfo, P2pDevice};
+use crate::error::P2pError;
+use crate::group::{NoaSchedule, P2pGroup};
+use crate::health::{HealthReport, QueueStatus};
+use crate::mac::MacAddress;
+use crate::manager::WifiP2pManager;
+use crate::peer_filter::PeerFilter;
+use crate::service::{AspProvision, LocalService};
+use crate::state::{DiscoveryState, ListenState, P2pState};
+use crate::vendor::VendorElementFrame;
+use crate::wfd::WfdDeviceInfo;
+
+/// Synchronous wrapper around `WifiP2pManager` for CLI tools and non-async code.
+///
+/// Owns a multi-threaded tokio runtime that drives the manager task and signal
+/// watcher in the background. Must not be used from inside an async context.
+pub struct BlockingWifiP2pManager<B: P2pBackend = P2pBackendImpl> {
+    // Declared first so the manager is dropped before the runtime it spawned onto.
+    inner: WifiP2pManager<B>,
+    runtime: Arc<Runtime>,
+}
+
+impl BlockingWifiP2pManager<P2pBackendImpl> {
+    /// Start a runtime and build the platform-backed manager on it.
+    pub fn new(interface_name: &str) -> Result<Self, P2pError> {
+        let runtime = build_runtime()?;
+        let inner = runtime.block_on(WifiP2pManager::new(interface_name))?;
+        Ok(Self {
+            inner,
+            runtime: Arc::new(runtime),
+        })
+    }
+}
+
+impl<B: P2pBackend> BlockingWifiP2pManager<B> {
+    /// Start a runtime and drive a caller-provided backend on it.
+    pub fn with_backend(backend: Arc<B>) -> Result<Self, P2pError> {
+        Ok(Self {
+            inner: WifiP2pManager::with_backend(backend),
+            runtime: Arc::new(build_runtime()?),
+        })
+    }
+
+    pub fn set_command_timeout(&mut self, timeout: Duration) {
+        self.inner.set_command_timeout(timeout);
+    }
+
+    pub fn initialize(&self) -> BlockingChannel {
+        // The manager spawns its tasks onto the ambient runtime, so enter ours first.
+        let _guard = self.runtime.enter();
+        BlockingChannel {
+            inner: self.inner.initialize(),
+            runtime: Arc::clone(&self.runtime),
+        }
+    }
+
+    pub fn capabilities(&self) -> Result<P2pCapabilities, P2pError> {
+        self.runtime.block_on(self.inner.capabilities())
+    }
+
+    /// See `WifiP2pManager::health`.
+    pub fn health(&self) -> HealthReport {
+        self.runtime.block_on(self.inner.health())
+    }
+
+    /// Stop discovery, remove groups and terminate the worker; see `WifiP2pManager::shutdown`.
+    pub fn shutdown(&mut self) -> Result<(), P2pError> {
+        self.runtime.block_on(self.inner.shutdown())
+    }
+}
+
+impl<B: P2pBackend> Drop for BlockingWifiP2pManager<B> {
+    fn drop(&mut self) {
+        // We own the runtime, so unlike the async manager the cleanup can finish here.
+        let _ = self.runtime.block_on(self.inner.shutdown());
+    }
+}
+
+fn build_runtime() -> Result<Runtime, P2pError> {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .map_err(|err| P2pError::Backend(format!("failed to start runtime: {err}")))
+}
+
+/// Synchronous counterpart of `WifiP2pChannel`.
+///
+/// Each call queues the command and blocks until the manager reports its result,
+/// instead of handing back a receiver.
+#[derive(Clone)]
+pub struct BlockingChannel {
+    inner: WifiP2pChannel,
+    runtime: Arc<Runtime>,
+}
+
+impl BlockingChannel {
+    /// Subscribe to events; use `blocking_recv()` on the receiver to wait for them.
+    pub fn subscribe_events(&self) -> broadcast::Receiver<P2pEvent> {
+        self.inner.subscribe_events()
+    }
+
+    /// The underlying async channel, for mixing in async code driven elsewhere.
+    pub fn as_async(&self) -> &WifiP2pChannel {
+        &self.inner
+    }
+
+    pub fn peers_snapshot(&self) -> Vec<P2pDevice> {
+        self.inner.peers_snapshot()
+    }
+
+    pub fn queue_status(&self) -> QueueStatus {
+        self.inner.queue_status()
+    }
+
+    pub fn discover_peers(&self) -> Result<(), P2pError> {
+        self.wait(self.inner.discover_peers())
+    }
+
+    pub fn discover_peers_with(
+        &self,
+        options: impl Into<DiscoveryOptions>,
+    ) -> Result<(), P2pError> {
+        self.wait(self.inner.discover_peers_with(options))
+    }
+
+    pub fn start_continuous_discovery(
+        &self,
+        interval: Duration,
+        duty_cycle: f32,
+    ) -> Result<(), P2pError> {
+        self.wait(self.inner.start_continuous_discovery(interval, duty_cycle))
+    }
+
+    pub fn stop_discovery(&self) -> Result<(), P2pError> {
+        self.wait(self.inner.stop_discovery())
+    }
+
+    pub fn connect(&self, device_address: MacAddress) -> Result<Option<String>, P2pError> {
+        self.wait(self.inner.connect(device_address))
+    }
+
+    pub fn connect_with_config(&self, config: ConnectConfig) -> Result<Option<String>, P2pError> {
+        self.wait(self.inner.connect_with_config(config))
+    }
+
+    pub fn connect_with_timeout(
+        &self,
+        config: ConnectConfig,
+        timeout: Duration,
+    ) -> Result<Option<String>, P2pError> {
+        self.wait(self.inner.connect_with_timeout(config, timeout))
+    }
+
+    pub fn cancel_connect(&self) -> Result<(), P2pError> {
+        self.wait(self.inner.cancel_connect())
+    }
+
+    pub fn request_peers(&self) -> Result<Vec<P2pDevice>, P2pError> {
+        self.wait(self.inner.request_peers())
+    }
+
+    pub fn request_peers_matching(&self, filter: PeerFilter) -> Result<Vec<P2pDevice>, P2pError> {
+        self.wait(self.inner.request_peers_matching(filter))
+    }
+
+    pub fn request_groups(&self) -> Result<Vec<P2pGroup>, P2pError> {
+        self.wait(self.inner.request_groups())
+    }
+
+    pub fn discovery_state(&self) -> Result<DiscoveryState, P2pError> {
+        self.wait(self.inner.discovery_state())
+    }
+
+    pub fn start_listening(&self) -> Result<(), P2pError> {
+        self.wait(self.inner.start_listening())
+    }
+
+    pub fn set_discoverability(&self, policy: DiscoverabilityPolicy) -> Result<(), P2pError> {
+        self.wait(self.inner.set_discoverability(policy))
+    }
+
+    pub fn stop_listening(&self) -> Result<(), P2pError> {
+        self.wait(self.inner.stop_listening())
+    }
+
+    pub fn listen_state(&self) -> Result<ListenState, P2pError> {
+        self.wait(self.inner.listen_state())
+    }
+
+    pub fn p2p_state(&self) -> Result<P2pState, P2pError> {
+        self.wait(self.inner.p2p_state())
+    }
+
+    pub fn request_device_info(&self) -> Result<LocalDeviceInfo, P2pError> {
+        self.wait(self.inner.request_device_info())
+    }
+
+    pub fn set_device_name(&self, name: impl Into<String>) -> Result<(), P2pError> {
+        self.wait(self.inner.set_device_name(name))
+    }
+
+    pub fn factory_reset(&self) -> Result<(), P2pError> {
+        self.wait(self.inner.factory_reset())
+    }
+
+    pub fn create_group(&self) -> Result<(), P2pError> {
+        self.wait(self.inner.create_group())
+    }
+
+    pub fn invite(&self, peer: MacAddress) -> Result<(), P2pError> {
+        self.wait(self.inner.invite(peer))
+    }
+
+    pub fn asp_provision(&self, request: AspProvision) -> Result<(), P2pError> {
+        self.wait(self.inner.asp_provision(request))
+    }
+
+    pub fn reject_peer(&self, peer: MacAddress) -> Result<(), P2pError> {
+        self.wait(self.inner.reject_peer(peer))
+    }
+
+    pub fn set_connection_policy(&self, approver: impl ConnectionApprover) -> Result<(), P2pError> {
+        self.wait(self.inner.set_connection_policy(approver))
+    }
+
+    pub fn clear_connection_policy(&self) -> Result<(), P2pError> {
+        self.wait(self.inner.clear_connection_policy())
+    }
+
+    pub fn remove_group(&self) -> Result<(), P2pError> {
+        self.wait(self.inner.remove_group())
+    }
+
+    pub fn add_local_service(&self, service: LocalService) -> Result<(), P2pError> {
+        self.wait(self.inner.add_local_service(service))
+    }
+
+    pub fn remove_local_service(&self, service: LocalService) -> Result<(), P2pError> {
+        self.wait(self.inner.remove_local_service(service))
+    }
+
+    pub fn clear_local_services(&self) -> Result<(), P2pError> {
+        self.wait(self.inner.clear_local_services())
+    }
+
+    pub fn discover_services(&self) -> Result<(), P2pError> {
+        self.wait(self.inner.discover_services())
+    }
+
+    pub fn clear_service_requests(&self) -> Result<(), P2pError> {
+        self.wait(self.inner.clear_service_requests())
+    }
+
+    pub fn set_noa(&self, schedule: NoaSchedule) -> Result<(), P2pError> {
+        self.wait(self.inner.set_noa(schedule))
+    }
+
+    pub fn set_wfd_info(&self, info: Option<WfdDeviceInfo>) -> Result<(), P2pError> {
+        self.wait(self.inner.set_wfd_info(info))
+    }
+
+    pub fn request_wfd_info(&self) -> Result<Option<WfdDeviceInfo>, P2pError> {
+        self.wait(self.inner.request_wfd_info())
+    }
+
+    pub fn set_vendor_elements(
+        &self,
+        frame: VendorElementFrame,
+        elements: Vec<u8>,
+    ) -> Result<(), P2pError> {
+        self.wait(self.inner.set_vendor_elements(frame, elements))
+    }
+
+    pub fn resolve_peer_ip(&self, device_address: MacAddress) -> Result<Option<IpAddr>, P2pError> {
+        self.wait(self.inner.resolve_peer_ip(device_address))
+    }
+
+    pub fn call_raw(
+        &self,
+        method: impl Into<String>,
+        options: HashMap<String, OwnedValue>,
+    ) -> Result<Vec<OwnedValue>, P2pError> {
+        self.wait(self.inner.call_raw(method, options))
+    }
+
+    /// Run `task` on the channel's runtime without waiting for it.
+    #[cfg(feature = "ffi")]
+    pub(crate) fn spawn(&self, task: impl Future<Output = ()> + Send + 'static) {
+        self.runtime.spawn(task);
+    }
+
+    fn wait<T>(
+        &self,
+        queued: impl Future<Output = Result<oneshot::Receiver<Result<T, P2pError>>, P2pError>>,
+    ) -> Result<T, P2pError> {
+        // Queue the command, then wait for the manager's reply on the same runtime.
+        self.runtime.block_on(async {
+            queued
+                .await?
+                .await
+                .map_err(|_| P2pError::ChannelClosed("manager".to_string()))?
+        })
+    }
+}
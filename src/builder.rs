@@ -0,0 +1,414 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::backend::P2pBackend;
+#[cfg(target_os = "linux")]
+use crate::backend::{InterfaceConfig, P2pBackendImpl};
+use crate::credentials::CredentialStore;
+use crate::error::P2pError;
+use crate::executor::Executor;
+#[cfg(feature = "tokio")]
+use crate::executor::TokioExecutor;
+use crate::manager::{DEFAULT_COMMAND_TIMEOUT, WifiP2pManager, open_platform_backend};
+#[cfg(feature = "metrics")]
+use crate::metrics::Metrics;
+#[cfg(all(feature = "connman", target_os = "linux"))]
+use crate::net::connman::ConnmanGuard;
+#[cfg(all(feature = "dhcp-client", target_os = "linux"))]
+use crate::net::dhcp_client::DhcpClientKind;
+#[cfg(all(feature = "dhcp-server", target_os = "linux"))]
+use crate::net::dhcp_server::DhcpServerConfig;
+#[cfg(all(feature = "firewall", target_os = "linux"))]
+use crate::net::firewall::FirewallConfig;
+#[cfg(all(feature = "network-manager", target_os = "linux"))]
+use crate::net::network_manager::NetworkManagerGuard;
+#[cfg(all(feature = "sharing", target_os = "linux"))]
+use crate::net::sharing::SharingConfig;
+
+/// How the manager re-subscribes to supplicant signals after the watcher fails
+/// (e.g. wpa_supplicant was not up yet) or its signal streams end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ReconnectPolicy {
+    /// Leave events off once the watcher stops; commands keep working.
+    #[default]
+    Never,
+    /// Wait `delay` and subscribe again, up to `max_attempts` times (unbounded if `None`).
+    Retry {
+        delay: Duration,
+        max_attempts: Option<u32>,
+    },
+}
+
+impl ReconnectPolicy {
+    /// Delay before re-subscription number `attempt` (starting at 0), or `None` to give up.
+    pub fn delay_for(&self, attempt: u32) -> Option<Duration> {
+        match *self {
+            Self::Never => None,
+            Self::Retry {
+                delay,
+                max_attempts,
+            } => match max_attempts {
+                Some(max) if attempt >= max => None,
+                _ => Some(delay),
+            },
+        }
+    }
+}
+
+/// What a channel call does when the command queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum QueueOverflow {
+    /// Wait for the manager task to take a command.
+    #[default]
+    Wait,
+    /// Fail right away with `P2pError::Busy`, so callers can shed load instead
+    /// of piling up behind a slow backend.
+    FailFast,
+}
+
+/// Tunables shared by the manager and the tasks it spawns.
+#[derive(Debug, Clone)]
+pub(crate) struct ManagerOptions {
+    pub(crate) command_queue_depth: usize,
+    pub(crate) queue_overflow: QueueOverflow,
+    pub(crate) event_buffer: usize,
+    pub(crate) command_timeout: Duration,
+    pub(crate) reconnect_policy: ReconnectPolicy,
+    pub(crate) stop_discovery_on_connect: bool,
+    pub(crate) cleanup_on_shutdown: bool,
+    #[cfg(all(feature = "dhcp-server", target_os = "linux"))]
+    pub(crate) dhcp_server: Option<DhcpServerConfig>,
+    #[cfg(all(feature = "dhcp-client", target_os = "linux"))]
+    pub(crate) dhcp_client: Option<DhcpClientKind>,
+    #[cfg(all(feature = "ipv6-link-local", target_os = "linux"))]
+    pub(crate) ipv6_link_local: bool,
+    #[cfg(target_os = "linux")]
+    pub(crate) apply_ip_allocation: bool,
+    #[cfg(all(feature = "sharing", target_os = "linux"))]
+    pub(crate) sharing: Option<SharingConfig>,
+    #[cfg(all(feature = "firewall", target_os = "linux"))]
+    pub(crate) firewall: Option<FirewallConfig>,
+    #[cfg(all(feature = "network-manager", target_os = "linux"))]
+    pub(crate) network_manager: Option<NetworkManagerGuard>,
+    #[cfg(all(feature = "connman", target_os = "linux"))]
+    pub(crate) connman: Option<ConnmanGuard>,
+    /// The interface passed to `build`, unknown with `build_with_backend`.
+    #[cfg(target_os = "linux")]
+    pub(crate) interface_name: Option<String>,
+    #[cfg(target_os = "linux")]
+    pub(crate) interface_config: InterfaceConfig,
+    /// The backend `build` opened, when an interface it registers is to be removed
+    /// at shutdown.
+    #[cfg(target_os = "linux")]
+    pub(crate) created_interface: Option<P2pBackendImpl>,
+    pub(crate) link_stats_interval: Option<Duration>,
+    pub(crate) watchdog_interval: Option<Duration>,
+    pub(crate) peer_ttl: Option<Duration>,
+    pub(crate) credential_store: Option<Arc<dyn CredentialStore>>,
+    #[cfg(feature = "metrics")]
+    pub(crate) metrics: Metrics,
+}
+
+impl Default for ManagerOptions {
+    fn default() -> Self {
+        Self {
+            command_queue_depth: 32,
+            queue_overflow: QueueOverflow::Wait,
+            event_buffer: 64,
+            command_timeout: DEFAULT_COMMAND_TIMEOUT,
+            reconnect_policy: ReconnectPolicy::Never,
+            stop_discovery_on_connect: false,
+            cleanup_on_shutdown: true,
+            #[cfg(all(feature = "dhcp-server", target_os = "linux"))]
+            dhcp_server: None,
+            #[cfg(all(feature = "dhcp-client", target_os = "linux"))]
+            dhcp_client: None,
+            #[cfg(all(feature = "ipv6-link-local", target_os = "linux"))]
+            ipv6_link_local: false,
+            #[cfg(target_os = "linux")]
+            apply_ip_allocation: false,
+            #[cfg(all(feature = "sharing", target_os = "linux"))]
+            sharing: None,
+            #[cfg(all(feature = "firewall", target_os = "linux"))]
+            firewall: None,
+            #[cfg(all(feature = "network-manager", target_os = "linux"))]
+            network_manager: None,
+            #[cfg(all(feature = "connman", target_os = "linux"))]
+            connman: None,
+            #[cfg(target_os = "linux")]
+            interface_name: None,
+            #[cfg(target_os = "linux")]
+            interface_config: InterfaceConfig::default(),
+            #[cfg(target_os = "linux")]
+            created_interface: None,
+            link_stats_interval: None,
+            watchdog_interval: None,
+            peer_ttl: None,
+            credential_store: None,
+            #[cfg(feature = "metrics")]
+            metrics: Metrics::new(),
+        }
+    }
+}
+
+/// Configures a `WifiP2pManager` before it is created; see `WifiP2pManager::builder`.
+#[derive(Default)]
+pub struct WifiP2pManagerBuilder {
+    options: ManagerOptions,
+    executor: Option<Arc<dyn Executor>>,
+}
+
+impl WifiP2pManagerBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of commands that can be queued before channel calls wait (default 32).
+    pub fn command_queue_depth(mut self, depth: usize) -> Self {
+        self.options.command_queue_depth = depth;
+        self
+    }
+
+    /// What channel calls do once `command_queue_depth` commands are waiting
+    /// (default `QueueOverflow::Wait`).
+    pub fn queue_overflow(mut self, overflow: QueueOverflow) -> Self {
+        self.options.queue_overflow = overflow;
+        self
+    }
+
+    /// Events retained per subscriber before slow receivers start lagging (default 64).
+    pub fn event_buffer(mut self, size: usize) -> Self {
+        self.options.event_buffer = size;
+        self
+    }
+
+    /// Default per-command deadline (default 30s).
+    pub fn command_timeout(mut self, timeout: Duration) -> Self {
+        self.options.command_timeout = timeout;
+        self
+    }
+
+    pub fn reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.options.reconnect_policy = policy;
+        self
+    }
+
+    /// Stop an active discovery scan before connecting, as Android does (default off).
+    pub fn stop_discovery_on_connect(mut self, enabled: bool) -> Self {
+        self.options.stop_discovery_on_connect = enabled;
+        self
+    }
+
+    /// Stop discovery and remove our groups when the manager shuts down or is
+    /// dropped (default on). Turn it off to leave them to a later process, as a
+    /// command-line tool does.
+    pub fn cleanup_on_shutdown(mut self, enabled: bool) -> Self {
+        self.options.cleanup_on_shutdown = enabled;
+        self
+    }
+
+    /// Run a DHCP server on the group interface whenever we become group owner,
+    /// so clients get addresses without an external daemon (default off).
+    #[cfg(all(feature = "dhcp-server", target_os = "linux"))]
+    pub fn dhcp_server(mut self, config: DhcpServerConfig) -> Self {
+        self.options.dhcp_server = Some(config);
+        self
+    }
+
+    /// Run `kind` on the group interface whenever we join a group as client;
+    /// `P2pEvent::IpConfigured` reports the address it obtains (default off).
+    #[cfg(all(feature = "dhcp-client", target_os = "linux"))]
+    pub fn dhcp_client(mut self, kind: DhcpClientKind) -> Self {
+        self.options.dhcp_client = Some(kind);
+        self
+    }
+
+    /// Bring every group interface up with IPv6 link-local addressing, so peers
+    /// can talk without DHCP; see `net::link_local` (default off).
+    #[cfg(all(feature = "ipv6-link-local", target_os = "linux"))]
+    pub fn ipv6_link_local(mut self, enabled: bool) -> Self {
+        self.options.ipv6_link_local = enabled;
+        self
+    }
+
+    /// Put the address from `P2pGroup::ip_allocation` on the group interface when
+    /// a group starts, reporting it as `P2pEvent::IpConfigured` (default off).
+    #[cfg(target_os = "linux")]
+    pub fn apply_ip_allocation(mut self, enabled: bool) -> Self {
+        self.options.apply_ip_allocation = enabled;
+        self
+    }
+
+    /// Forward and masquerade the group subnet out of `config.uplink` whenever we
+    /// own a group, so clients get internet access; see `net::sharing` (default off).
+    #[cfg(all(feature = "sharing", target_os = "linux"))]
+    pub fn internet_sharing(mut self, config: SharingConfig) -> Self {
+        self.options.sharing = Some(config);
+        self
+    }
+
+    /// Accept DHCP and `config`'s ports on every group interface while the group
+    /// is up; see `net::firewall` (default off).
+    #[cfg(all(feature = "firewall", target_os = "linux"))]
+    pub fn open_firewall(mut self, config: FirewallConfig) -> Self {
+        self.options.firewall = Some(config);
+        self
+    }
+
+    /// Mark the interface and every group interface unmanaged in NetworkManager
+    /// while the manager runs, and hand them back on shutdown; see
+    /// `net::network_manager` (default off). With `build_with_backend` only
+    /// group interfaces are covered.
+    #[cfg(all(feature = "network-manager", target_os = "linux"))]
+    pub fn unmanage_in_network_manager(mut self, enabled: bool) -> Self {
+        self.options.network_manager = enabled.then(NetworkManagerGuard::new);
+        self
+    }
+
+    /// Switch off ConnMan's own P2P technology and Wi-Fi tethering while the
+    /// manager runs, and switch them back on at shutdown; see `net::connman`
+    /// (default off).
+    #[cfg(all(feature = "connman", target_os = "linux"))]
+    pub fn release_connman(mut self, enabled: bool) -> Self {
+        self.options.connman = enabled.then(ConnmanGuard::new);
+        self
+    }
+
+    /// Whether and how `build` registers an interface wpa_supplicant does not
+    /// manage yet, and whether to remove it again at shutdown (default: register
+    /// with the supplicant's defaults, keep it).
+    #[cfg(target_os = "linux")]
+    pub fn interface_config(mut self, config: InterfaceConfig) -> Self {
+        self.options.interface_config = config;
+        self
+    }
+
+    /// Build even when the interface does not exist yet and attach once it appears
+    /// (`P2pEvent::AdapterAdded`); after an unplug (`P2pEvent::AdapterRemoved`) the
+    /// manager re-attaches when it is plugged back in (default off).
+    #[cfg(all(feature = "hotplug", target_os = "linux"))]
+    pub fn hotplug(mut self, enabled: bool) -> Self {
+        self.options.interface_config.hotplug = enabled;
+        self
+    }
+
+    /// Sample the link to every connected peer at `interval` while a group is up
+    /// and report it as `P2pEvent::LinkStats` (default off). Stops for good if the
+    /// backend cannot provide statistics.
+    pub fn link_stats_interval(mut self, interval: Duration) -> Self {
+        self.options.link_stats_interval = Some(interval);
+        self
+    }
+
+    /// Report a peer lost when discovery has not found it again for `ttl`, for
+    /// drivers that never send DeviceLost (default off). Only expires peers while
+    /// discovery runs, and never connected or invited ones.
+    pub fn peer_ttl(mut self, ttl: Duration) -> Self {
+        self.options.peer_ttl = Some(ttl);
+        self
+    }
+
+    /// Ping the systemd watchdog from the manager loop at half the unit's
+    /// `WatchdogSec=`, so a stuck loop gets the service restarted (default off;
+    /// nothing happens without a watchdog). Keep `WatchdogSec=` above the
+    /// command timeout, as the loop waits on each command in turn.
+    #[cfg(all(feature = "systemd", unix))]
+    pub fn systemd_watchdog(mut self, enabled: bool) -> Self {
+        self.options.watchdog_interval = enabled
+            .then(crate::systemd::watchdog_interval)
+            .flatten()
+            .map(|interval| interval / 2);
+        self
+    }
+
+    /// Save persistent group credentials to `store` as groups form and add them
+    /// back to the supplicant on startup, so `WifiP2pChannel::reinvoke` works
+    /// across restarts; see `credentials` (default off).
+    pub fn credential_store(mut self, store: impl CredentialStore) -> Self {
+        self.options.credential_store = Some(Arc::new(store));
+        self
+    }
+
+    /// Spawn worker tasks and timers on `executor` instead of tokio.
+    pub fn executor(mut self, executor: impl Executor) -> Self {
+        self.executor = Some(Arc::new(executor));
+        self
+    }
+
+    /// Open the platform backend (the system bus on Linux) and build a manager for `interface_name`.
+    pub async fn build(self, interface_name: &str) -> Result<WifiP2pManager, P2pError> {
+        let backend = open_platform_backend(interface_name, &self.options).await?;
+        self.named(interface_name)
+            .created(&backend)
+            .build_with_backend(Arc::new(backend))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn created(mut self, backend: &P2pBackendImpl) -> Self {
+        // A hot-plugged interface may only be registered later.
+        if self.options.interface_config.remove_on_shutdown {
+            self.options.created_interface = Some(backend.clone());
+        }
+        self
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn created(self, _backend: &crate::backend::P2pBackendImpl) -> Self {
+        self
+    }
+
+    #[cfg(target_os = "linux")]
+    fn named(mut self, interface_name: &str) -> Self {
+        // NetworkManager knows devices by interface name only, rfkill by the
+        // interface's phy; failures name it too.
+        self.options.interface_name = Some(interface_name.to_string());
+        self
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn named(self, _interface_name: &str) -> Self {
+        self
+    }
+
+    /// Build a manager around a caller-provided backend.
+    pub fn build_with_backend<B: P2pBackend>(
+        self,
+        backend: Arc<B>,
+    ) -> Result<WifiP2pManager<B>, P2pError> {
+        // Zero-capacity channels would panic when the manager is initialized.
+        if self.options.command_queue_depth == 0 || self.options.event_buffer == 0 {
+            return Err(P2pError::InvalidConfig(
+                "command queue depth and event buffer must be non-zero".to_string(),
+            ));
+        }
+        if self.options.link_stats_interval == Some(Duration::ZERO) {
+            return Err(P2pError::InvalidConfig(
+                "link stats interval must be non-zero".to_string(),
+            ));
+        }
+        if self.options.peer_ttl == Some(Duration::ZERO) {
+            return Err(P2pError::InvalidConfig(
+                "peer TTL must be non-zero".to_string(),
+            ));
+        }
+        let executor = match self.executor {
+            Some(executor) => executor,
+            None => default_executor()?,
+        };
+        Ok(WifiP2pManager::from_parts(backend, executor, self.options))
+    }
+}
+
+#[cfg(feature = "tokio")]
+fn default_executor() -> Result<Arc<dyn Executor>, P2pError> {
+    Ok(Arc::new(TokioExecutor))
+}
+
+#[cfg(not(feature = "tokio"))]
+fn default_executor() -> Result<Arc<dyn Executor>, P2pError> {
+    Err(P2pError::InvalidConfig(
+        "an executor is required without the tokio feature".to_string(),
+    ))
+}
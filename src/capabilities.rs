@@ -0,0 +1,29 @@
+/// Capability report for the managed interface, built from wpa_supplicant
+/// interface `Capabilities`, WPS configuration and driver flags.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct P2pCapabilities {
+    /// The supplicant and driver support P2P on this interface.
+    pub p2p_supported: bool,
+    /// The driver can run a station link and a P2P group at the same time.
+    pub concurrent_sta_p2p: bool,
+    /// The driver uses a dedicated P2P device (e.g. "p2p-dev-wlan0").
+    pub dedicated_p2p_device: bool,
+    /// Maximum number of clients accepted while acting as group owner, if reported.
+    pub max_peers: Option<u32>,
+    /// Channel numbers the driver allows, across all bands.
+    pub channels: Vec<u16>,
+    /// WPS config methods advertised by the interface (e.g. "push_button", "display").
+    pub wps_methods: Vec<String>,
+    /// Operating modes reported by the interface (e.g. "infrastructure", "ap", "p2p").
+    pub modes: Vec<String>,
+    /// Raw driver capability flags (e.g. "P2P_CONCURRENT").
+    pub driver_flags: Vec<String>,
+}
+
+impl P2pCapabilities {
+    /// Whether the given WPS config method name is supported.
+    pub fn supports_wps_method(&self, method: &str) -> bool {
+        self.wps_methods.iter().any(|m| m == method)
+    }
+}
@@ -1,37 +1,344 @@
-use tokio::sync::{broadcast, mpsc, oneshot};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use crate::device::P2pDevice;
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
+use zbus::zvariant::OwnedValue;
+
+use crate::approver::{ConnectionApprover, ConnectionRequest};
+#[cfg(feature = "recording")]
+use crate::backend::recording::{RecordedEntry, Taps};
+use crate::builder::QueueOverflow;
+use crate::config::{ConnectConfig, DiscoverabilityPolicy, DiscoveryOptions, DiscoveryType};
+use crate::device::{DeviceStatus, LocalDeviceInfo, P2pDevice};
 use crate::error::P2pError;
-use crate::manager::ManagerCommand;
+use crate::executor::Executor;
+use crate::group::{NoaSchedule, P2pGroup};
+use crate::health::{QueueStatus, QueueTracker};
+use crate::listener::{ListenerHandle, P2pEventListener};
+use crate::mac::MacAddress;
+use crate::manager::{ManagerCommand, QueuedCommand};
+#[cfg(feature = "metrics")]
+use crate::metrics::Metrics;
+#[cfg(feature = "tokio")]
+use crate::net::socket::P2pSocket;
+#[cfg(feature = "tokio")]
+use crate::pair::{PairOptions, PairedLink, PeerSelector};
+use crate::peer_filter::PeerFilter;
+#[cfg(feature = "probe")]
+use crate::probe::{LinkReport, ProbeOptions};
+use crate::service::{
+    AspAdvertisement, AspProvision, DnsSdServiceResponse, DnsSdTxtRecord, LocalService,
+    ServiceResponse,
+};
+use crate::state::{DiscoveryState, ListenState, P2pState};
+use crate::stats::{LinkIssue, LinkStats, LinkThresholds};
+use crate::status::P2pStatusCode;
+use crate::stream::{EventFilter, P2pEventStream};
+use crate::supervisor::{ConnectionSupervisor, SupervisorPolicy, SupervisorState};
+use crate::transfer::{TransferDirection, TransferProgress};
+use crate::vendor::VendorElementFrame;
+use crate::wfd::WfdDeviceInfo;
+#[cfg(feature = "tokio")]
+use crate::wfd::WfdSession;
 
 pub type ActionReceiver = oneshot::Receiver<Result<(), P2pError>>;
+/// Resolves with the supplicant-generated PIN for `WpsMethod::PinDisplay` without a PIN.
+pub type ConnectReceiver = oneshot::Receiver<Result<Option<String>, P2pError>>;
+pub type PeersReceiver = oneshot::Receiver<Result<Vec<P2pDevice>, P2pError>>;
+pub type GroupsReceiver = oneshot::Receiver<Result<Vec<P2pGroup>, P2pError>>;
+pub type RawReplyReceiver = oneshot::Receiver<Result<Vec<OwnedValue>, P2pError>>;
+/// Resolves with the peer's address on the group interface, or `None` while it is unknown.
+pub type PeerIpReceiver = oneshot::Receiver<Result<Option<IpAddr>, P2pError>>;
+pub type DiscoveryStateReceiver = oneshot::Receiver<Result<DiscoveryState, P2pError>>;
+pub type ListenStateReceiver = oneshot::Receiver<Result<ListenState, P2pError>>;
+pub type P2pStateReceiver = oneshot::Receiver<Result<P2pState, P2pError>>;
+pub type DeviceInfoReceiver = oneshot::Receiver<Result<LocalDeviceInfo, P2pError>>;
+pub type WfdInfoReceiver = oneshot::Receiver<Result<Option<WfdDeviceInfo>, P2pError>>;
+
+/// Matches the manager's background peer address lookups (30 x 1s).
+#[cfg(feature = "tokio")]
+const OPEN_CONNECTION_TIMEOUT: Duration = Duration::from_secs(30);
 
+/// Something the manager or its backend reports. Peers and groups are behind an
+/// `Arc`, so each subscriber's clone is a reference count bump.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum P2pEvent {
     /// Local discovery request succeeded and the scan is active.
     DiscoveryStarted,
-    /// Local request to stop discovery succeeded.
+    /// The scan stopped: a local request to stop discovery succeeded, the
+    /// supplicant ended its find on its own (e.g. when it timed out), or
+    /// `start_continuous_discovery` paused between scans.
     DiscoveryStopped,
+    /// We started or stopped listening, or the platform ended listen state on
+    /// its own (e.g. when the supplicant restarted).
+    ListenStateChanged(ListenState),
     /// Local request to form a group succeeded.
     GroupCreated,
     /// Local connect request succeeded for the given peer address.
-    Connected(String),
-    /// Placeholder event for peer detection (would be driven by D-Bus signals).
-    PeerFound(P2pDevice),
+    Connected(MacAddress),
+    /// A peer was discovered (driven by the supplicant's DeviceFound signal).
+    PeerFound(Arc<P2pDevice>),
+    /// A previously found peer is no longer reported.
+    PeerLost(MacAddress),
+    /// GO negotiation with a peer failed.
+    GoNegotiationFailed {
+        peer: Option<MacAddress>,
+        status: P2pStatusCode,
+    },
+    /// Provision discovery with a peer failed.
+    ProvisionDiscoveryFailed {
+        peer: MacAddress,
+        status: P2pStatusCode,
+    },
+    /// A peer asked to connect to us; accept with `connect` or turn it down with
+    /// `reject_peer`.
+    ConnectionRequested(MacAddress),
+    /// A peer started GO negotiation, invited us or asked to provision, and no
+    /// `ConnectionApprover` decided it; see `approver`. GO negotiation requests
+    /// are also reported as `ConnectionRequested`.
+    IncomingRequest(ConnectionRequest),
+    /// A `ConnectionApprover` accepted `peer`'s request with `PinDisplay`: show
+    /// `pin` (the supplicant's, unless the peer picked it) to be entered on the peer.
+    PinToDisplay { peer: MacAddress, pin: String },
+    /// Outcome of an invitation we sent.
+    InvitationResult { status: P2pStatusCode },
+    /// The manager's peer table changed; `peers_snapshot()` reflects the new state.
+    PeersChanged {
+        added: Vec<Arc<P2pDevice>>,
+        removed: Vec<MacAddress>,
+        updated: Vec<Arc<P2pDevice>>,
+    },
+    /// The manager changed its tracked status for a peer.
+    PeerStatusChanged(MacAddress, DeviceStatus),
+    /// Our own device's name, address (e.g. after MAC randomization) or WPS
+    /// settings changed, like Android's `WIFI_P2P_THIS_DEVICE_CHANGED_ACTION`.
+    ThisDeviceChanged(LocalDeviceInfo),
+    /// A peer answered a service discovery query; see `service`.
+    ServicesFound(ServiceResponse),
+    /// A peer's `ServicesFound` answer held a Bonjour PTR record: it offers a
+    /// service instance.
+    DnsSdServiceAvailable(DnsSdServiceResponse),
+    /// A peer's `ServicesFound` answer held a Bonjour TXT record.
+    DnsSdTxtRecordAvailable(DnsSdTxtRecord),
+    /// A peer's `ServicesFound` answer held a P2PS advertisement; see
+    /// `service::asp`.
+    AspServiceFound {
+        peer: MacAddress,
+        service: AspAdvertisement,
+    },
+    /// Local Notice of Absence schedule was applied while acting as group owner.
+    NoaUpdated(NoaSchedule),
+    /// Group formation finished and the group interface is up.
+    GroupStarted(Arc<P2pGroup>),
+    /// A group we were a member of was removed.
+    GroupFinished(Arc<P2pGroup>),
+    /// A connected peer's IP address on the group interface became known.
+    PeerIpResolved { mac: MacAddress, ip: IpAddr },
+    /// Addressing configured for a group (e.g. the DHCP server) could not be set up.
+    NetworkSetupFailed { interface: String, reason: String },
+    /// We got a new address on our group interface, from the DHCP client or P2P IP allocation.
+    IpConfigured(IpAddr),
+    /// A group interface is up with a carrier, so sockets on it can be opened.
+    GroupInterfaceUp { ifname: String },
+    /// A group interface lost its carrier, went down or was removed.
+    GroupInterfaceDown { ifname: String },
+    /// An address was added to a group interface.
+    GroupAddressAdded { ifname: String, ip: IpAddr },
+    /// An address was removed from a group interface.
+    GroupAddressRemoved { ifname: String, ip: IpAddr },
+    /// Another chunk of a file transfer went over the link.
+    TransferProgress(TransferProgress),
+    /// A file was transferred and its checksum verified.
+    TransferCompleted {
+        direction: TransferDirection,
+        file_name: String,
+    },
+    /// A file transfer was aborted; a later attempt resumes where it stopped.
+    TransferFailed {
+        direction: TransferDirection,
+        file_name: String,
+        reason: String,
+    },
+    /// Periodic signal and bitrate sample for a connected peer; see `stats`.
+    LinkStats(LinkStats),
+    /// A watched link crossed one of its `LinkThresholds`.
+    LinkDegraded { peer: MacAddress, issue: LinkIssue },
+    /// A degraded link is back within its thresholds.
+    LinkRecovered { peer: MacAddress },
+    /// Credentials could not be loaded from, added back from or saved to the
+    /// configured `CredentialStore`.
+    CredentialStoreFailed { reason: String },
+    /// A `ConnectionSupervisor` for `peer` moved to a new state.
+    SupervisorStateChanged {
+        peer: MacAddress,
+        state: SupervisorState,
+    },
+    /// wpa_supplicant went away (crashed or restarted); calls fail until
+    /// `BackendRestored`. Peers and groups it held are reported gone.
+    BackendLost { reason: String },
+    /// wpa_supplicant is back and the manager re-attached: signals are subscribed
+    /// again and local services, vendor elements and discovery are restored.
+    BackendRestored,
+    /// The interface went away: the adapter was unplugged or the interface was
+    /// removed from wpa_supplicant. Calls fail until `AdapterAdded`.
+    AdapterRemoved { interface: String },
+    /// The interface appeared and the backend attached to it, after a hot-plug
+    /// build or `AdapterRemoved`; state is restored as for `BackendRestored`.
+    AdapterAdded { interface: String },
+}
+
+/// Coarse category of a `P2pEvent`, used for filtered subscriptions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EventKind {
+    /// Discovery started/stopped.
+    Discovery,
+    /// Peers found, lost or changing status, and changes to our own device.
+    Peer,
+    /// Connection results for a peer.
+    Connection,
+    /// Group lifecycle and group owner settings.
+    Group,
+    /// File transfers over the group link.
+    Transfer,
+    /// Periodic link statistics.
+    Stats,
+    /// The supplicant or the interface going away and coming back.
+    Backend,
+}
+
+impl P2pEvent {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Self::DiscoveryStarted | Self::DiscoveryStopped | Self::ListenStateChanged(_) => {
+                EventKind::Discovery
+            }
+            Self::PeerFound(_)
+            | Self::PeerLost(_)
+            | Self::PeersChanged { .. }
+            | Self::PeerStatusChanged(..)
+            | Self::ThisDeviceChanged(_)
+            | Self::ServicesFound(_)
+            | Self::DnsSdServiceAvailable(_)
+            | Self::DnsSdTxtRecordAvailable(_)
+            | Self::AspServiceFound { .. } => EventKind::Peer,
+            Self::Connected(_)
+            | Self::ConnectionRequested(_)
+            | Self::IncomingRequest(_)
+            | Self::PinToDisplay { .. }
+            | Self::GoNegotiationFailed { .. }
+            | Self::ProvisionDiscoveryFailed { .. }
+            | Self::InvitationResult { .. }
+            | Self::PeerIpResolved { .. }
+            | Self::SupervisorStateChanged { .. } => EventKind::Connection,
+            Self::GroupCreated
+            | Self::NoaUpdated(_)
+            | Self::GroupStarted(_)
+            | Self::GroupFinished(_)
+            | Self::NetworkSetupFailed { .. }
+            | Self::IpConfigured(_)
+            | Self::GroupInterfaceUp { .. }
+            | Self::GroupInterfaceDown { .. }
+            | Self::GroupAddressAdded { .. }
+            | Self::GroupAddressRemoved { .. }
+            | Self::CredentialStoreFailed { .. } => EventKind::Group,
+            Self::TransferProgress(_)
+            | Self::TransferCompleted { .. }
+            | Self::TransferFailed { .. } => EventKind::Transfer,
+            Self::LinkStats(_) | Self::LinkDegraded { .. } | Self::LinkRecovered { .. } => {
+                EventKind::Stats
+            }
+            Self::BackendLost { .. }
+            | Self::BackendRestored
+            | Self::AdapterRemoved { .. }
+            | Self::AdapterAdded { .. } => EventKind::Backend,
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct WifiP2pChannel {
-    command_tx: mpsc::Sender<ManagerCommand>,
+    command_tx: mpsc::Sender<QueuedCommand>,
+    // Stop and cancel commands, which the manager takes before the regular queue.
+    priority_tx: mpsc::Sender<QueuedCommand>,
+    // Numbers commands in the order they are sent, shared by clones. Held while
+    // a command is put on its queue, so the numbers follow the queue order.
+    sequence: Arc<Mutex<u64>>,
     event_tx: broadcast::Sender<P2pEvent>,
+    peers_rx: watch::Receiver<Vec<P2pDevice>>,
+    groups_rx: watch::Receiver<Vec<P2pGroup>>,
+    executor: Arc<dyn Executor>,
+    queue: Arc<QueueTracker>,
+    overflow: QueueOverflow,
+    #[cfg(feature = "metrics")]
+    metrics: Metrics,
+    #[cfg(feature = "recording")]
+    taps: Taps,
 }
 
 impl WifiP2pChannel {
     pub(crate) fn new(
-        command_tx: mpsc::Sender<ManagerCommand>,
+        command_tx: mpsc::Sender<QueuedCommand>,
+        priority_tx: mpsc::Sender<QueuedCommand>,
         event_tx: broadcast::Sender<P2pEvent>,
+        peers_rx: watch::Receiver<Vec<P2pDevice>>,
+        groups_rx: watch::Receiver<Vec<P2pGroup>>,
+        executor: Arc<dyn Executor>,
+        queue: Arc<QueueTracker>,
     ) -> Self {
-        Self { command_tx, event_tx }
+        Self {
+            command_tx,
+            priority_tx,
+            sequence: Arc::default(),
+            event_tx,
+            peers_rx,
+            groups_rx,
+            executor,
+            queue,
+            overflow: QueueOverflow::Wait,
+            #[cfg(feature = "metrics")]
+            metrics: Metrics::new(),
+            #[cfg(feature = "recording")]
+            taps: Taps::default(),
+        }
+    }
+
+    pub(crate) fn with_overflow(mut self, overflow: QueueOverflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// Share the manager's counters, so event streams count what they skip.
+    #[cfg(feature = "metrics")]
+    pub(crate) fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// The manager's counters; see `WifiP2pManager::metrics`.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Share `taps` with the recording layer around the manager's backend.
+    #[cfg(feature = "recording")]
+    pub(crate) fn with_taps(mut self, taps: Taps) -> Self {
+        self.taps = taps;
+        self
+    }
+
+    #[cfg(feature = "recording")]
+    pub(crate) fn taps(&self) -> &Taps {
+        &self.taps
+    }
+
+    #[cfg(feature = "recording")]
+    pub(crate) fn executor(&self) -> &Arc<dyn Executor> {
+        &self.executor
     }
 
     pub fn subscribe_events(&self) -> broadcast::Receiver<P2pEvent> {
@@ -39,13 +346,116 @@ impl WifiP2pChannel {
         self.event_tx.subscribe()
     }
 
+    pub fn event_stream(&self) -> P2pEventStream {
+        // Same subscription as above, wrapped as a Stream that rides over lag.
+        self.subscribe_filtered(EventFilter::all())
+    }
+
+    pub fn subscribe_filtered(&self, filter: EventFilter) -> P2pEventStream {
+        // Events outside the filter are dropped inside the stream, never surfaced.
+        let stream = P2pEventStream::with_filter(self.event_tx.subscribe(), filter);
+        #[cfg(feature = "metrics")]
+        let stream = stream.counted(self.metrics.clone());
+        stream
+    }
+
+    pub fn register_listener(&self, listener: impl P2pEventListener) -> ListenerHandle {
+        // A dispatch task drives the callbacks from its own subscription.
+        ListenerHandle::spawn(listener, self.event_stream(), &*self.executor)
+    }
+
+    pub fn subscribe_peer_events(&self) -> P2pEventStream {
+        self.subscribe_filtered(EventFilter::only(EventKind::Peer))
+    }
+
+    /// Peer events about peers matching `filter` only; see
+    /// `P2pEventStream::peers_matching`.
+    pub fn subscribe_peers_matching(&self, filter: PeerFilter) -> P2pEventStream {
+        self.subscribe_peer_events().peers_matching(filter)
+    }
+
+    pub fn subscribe_group_events(&self) -> P2pEventStream {
+        self.subscribe_filtered(EventFilter::only(EventKind::Group))
+    }
+
+    /// The manager's current peer table, without a round trip to the supplicant.
+    /// Kept up to date from discovery signals and `request_peers` results.
+    pub fn peers_snapshot(&self) -> Vec<P2pDevice> {
+        self.peers_rx.borrow().clone()
+    }
+
+    /// Groups we are currently a member of, as reported by `GroupStarted`.
+    pub fn groups_snapshot(&self) -> Vec<P2pGroup> {
+        self.groups_rx.borrow().clone()
+    }
+
+    /// Follow group membership, e.g. to wait for group formation after `connect`.
+    pub fn subscribe_groups(&self) -> watch::Receiver<Vec<P2pGroup>> {
+        self.groups_rx.clone()
+    }
+
+    /// What the manager task is running and how much is waiting behind it, read
+    /// without going through the queue: a command whose `elapsed` keeps growing
+    /// past its deadline means the backend is wedged, not just busy.
+    pub fn queue_status(&self) -> QueueStatus {
+        let queued =
+            |commands: &mpsc::Sender<QueuedCommand>| commands.max_capacity() - commands.capacity();
+        self.queue
+            .status(queued(&self.command_tx) + queued(&self.priority_tx))
+    }
+
+    /// Resolves once the manager has stopped and no longer takes commands. Event
+    /// streams only end when every channel is gone, so servers holding a channel
+    /// wait on this instead.
+    pub async fn closed(&self) {
+        self.command_tx.closed().await;
+    }
+
     pub async fn discover_peers(&self) -> Result<ActionReceiver, P2pError> {
         // This mirrors ActionListener by returning a oneshot channel for the result.
+        self.discover_peers_with(DiscoveryType::Full).await
+    }
+
+    /// Discover on fewer channels, e.g. `DiscoveryType::Social` when peers are
+    /// known to be idle, which finds them in a fraction of a full scan's time,
+    /// and only for peers of `DiscoveryOptions::device_types`: other peers are
+    /// not reported while this discovery runs. A restarted supplicant is put
+    /// back to the same scan.
+    pub async fn discover_peers_with(
+        &self,
+        options: impl Into<DiscoveryOptions>,
+    ) -> Result<ActionReceiver, P2pError> {
         let (respond_to, receiver) = oneshot::channel();
-        self.send_command(ManagerCommand::Discover { respond_to }).await?;
+        self.send_command(ManagerCommand::Discover {
+            options: options.into(),
+            respond_to,
+        })
+        .await?;
         Ok(receiver)
     }
 
+    /// Keep discovering without re-arm timers of your own: scan for `duty_cycle`
+    /// (more than 0, at most 1) of every `interval`, starting a scan again when
+    /// the supplicant's times out early. Scans pause while a connection is set up
+    /// or a group runs. Runs until `stop_discovery`; `P2pError::InvalidArgs` if
+    /// that share of `interval` leaves no time to scan.
+    pub async fn start_continuous_discovery(
+        &self,
+        interval: Duration,
+        duty_cycle: f32,
+    ) -> Result<ActionReceiver, P2pError> {
+        let (respond_to, receiver) = oneshot::channel();
+        self.send_command(ManagerCommand::StartContinuousDiscovery {
+            interval,
+            duty_cycle,
+            respond_to,
+        })
+        .await?;
+        Ok(receiver)
+    }
+
+    /// Stop discovery. Goes ahead of commands still queued, and `discover` calls
+    /// sent before it that have not run are answered `P2pError::Cancelled`.
     pub async fn stop_discovery(&self) -> Result<ActionReceiver, P2pError> {
         // Stop discovery and report completion through the oneshot.
         let (respond_to, receiver) = oneshot::channel();
@@ -54,17 +464,251 @@ impl WifiP2pChannel {
         Ok(receiver)
     }
 
-    pub async fn connect(&self, device_address: String) -> Result<ActionReceiver, P2pError> {
+    pub async fn connect(&self, device_address: MacAddress) -> Result<ConnectReceiver, P2pError> {
+        // Push-button connect; use connect_with_config for PIN methods.
+        self.connect_with_config(ConnectConfig::new(device_address))
+            .await
+    }
+
+    pub async fn connect_with_config(
+        &self,
+        config: ConnectConfig,
+    ) -> Result<ConnectReceiver, P2pError> {
+        self.queue_connect(config, None).await
+    }
+
+    pub async fn connect_with_timeout(
+        &self,
+        config: ConnectConfig,
+        timeout: Duration,
+    ) -> Result<ConnectReceiver, P2pError> {
+        // Overrides the manager's default command timeout for this call only.
+        self.queue_connect(config, Some(timeout)).await
+    }
+
+    /// Abort the connection attempt in progress. Goes ahead of commands still
+    /// queued, and `connect` and `reinvoke` calls sent before it that have not
+    /// run are answered `P2pError::Cancelled`.
+    pub async fn cancel_connect(&self) -> Result<ActionReceiver, P2pError> {
+        let (respond_to, receiver) = oneshot::channel();
+        self.send_command(ManagerCommand::CancelConnect { respond_to })
+            .await?;
+        Ok(receiver)
+    }
+
+    /// Check the link statistics of `peer` against `thresholds` and report
+    /// `LinkDegraded` and `LinkRecovered` as they are crossed; watching the same
+    /// peer again replaces its thresholds. Needs
+    /// `WifiP2pManagerBuilder::link_stats_interval`.
+    pub async fn watch_link_quality(
+        &self,
+        peer: MacAddress,
+        thresholds: LinkThresholds,
+    ) -> Result<ActionReceiver, P2pError> {
+        let (respond_to, receiver) = oneshot::channel();
+        self.send_command(ManagerCommand::WatchLinkQuality {
+            peer,
+            thresholds: Some(thresholds),
+            respond_to,
+        })
+        .await?;
+        Ok(receiver)
+    }
+
+    /// Stop reporting threshold crossings for `peer`.
+    pub async fn unwatch_link_quality(&self, peer: MacAddress) -> Result<ActionReceiver, P2pError> {
+        let (respond_to, receiver) = oneshot::channel();
+        self.send_command(ManagerCommand::WatchLinkQuality {
+            peer,
+            thresholds: None,
+            respond_to,
+        })
+        .await?;
+        Ok(receiver)
+    }
+
+    /// Re-invoke the persistent group we share with `peer`, reconnecting without
+    /// provisioning. The peer must have been found by a recent discovery.
+    pub async fn reinvoke(&self, peer: MacAddress) -> Result<ActionReceiver, P2pError> {
+        let (respond_to, receiver) = oneshot::channel();
+        self.send_command(ManagerCommand::Reinvoke { peer, respond_to })
+            .await?;
+        Ok(receiver)
+    }
+
+    /// Connect to `config.device_address` and keep reconnecting whenever the group
+    /// goes away or negotiation fails, per `policy`; see `supervisor`.
+    pub fn supervise(
+        &self,
+        config: ConnectConfig,
+        policy: SupervisorPolicy,
+    ) -> Result<ConnectionSupervisor, P2pError> {
+        // Every attempt would fail the same way, so reject this up front.
+        if config.wps_method.requires_pin() && config.pin.is_none() {
+            return Err(P2pError::InvalidConfig(format!(
+                "{} requires a PIN",
+                config.wps_method.as_str()
+            )));
+        }
+        Ok(ConnectionSupervisor::spawn(
+            self.clone(),
+            Arc::clone(&self.executor),
+            config,
+            policy,
+        ))
+    }
+
+    async fn queue_connect(
+        &self,
+        config: ConnectConfig,
+        timeout: Option<Duration>,
+    ) -> Result<ConnectReceiver, P2pError> {
+        // Reject PIN methods that need a PIN we were not given before queueing.
+        if config.wps_method.requires_pin() && config.pin.is_none() {
+            return Err(P2pError::InvalidConfig(format!(
+                "{} requires a PIN",
+                config.wps_method.as_str()
+            )));
+        }
         // Queue a connect command; the worker does the D-Bus call.
         let (respond_to, receiver) = oneshot::channel();
-        self.send_command(ManagerCommand::Connect {
-            device_address,
+        let command = ManagerCommand::Connect { config, respond_to };
+        self.send_command_with_timeout(command, timeout).await?;
+        Ok(receiver)
+    }
+
+    pub async fn request_peers(&self) -> Result<PeersReceiver, P2pError> {
+        // Like Android's requestPeers: a snapshot of the supplicant's current peer list.
+        let (respond_to, receiver) = oneshot::channel();
+        self.send_command(ManagerCommand::RequestPeers {
+            filter: None,
             respond_to,
         })
         .await?;
         Ok(receiver)
     }
 
+    /// `request_peers`, answered with only the peers matching `filter`.
+    pub async fn request_peers_matching(
+        &self,
+        filter: PeerFilter,
+    ) -> Result<PeersReceiver, P2pError> {
+        let (respond_to, receiver) = oneshot::channel();
+        self.send_command(ManagerCommand::RequestPeers {
+            filter: Some(filter),
+            respond_to,
+        })
+        .await?;
+        Ok(receiver)
+    }
+
+    /// The groups up on this interface right now, including ones formed before the
+    /// manager started (Android's requestGroupInfo). `groups_snapshot()` only holds
+    /// groups the manager saw start.
+    pub async fn request_groups(&self) -> Result<GroupsReceiver, P2pError> {
+        let (respond_to, receiver) = oneshot::channel();
+        self.send_command(ManagerCommand::RequestGroups { respond_to })
+            .await?;
+        Ok(receiver)
+    }
+
+    /// Whether discovery is running, as the manager tracks it (Android's
+    /// requestDiscoveryState): started by `discover_peers`, stopped by
+    /// `stop_discovery` or when the supplicant ends the scan.
+    pub async fn discovery_state(&self) -> Result<DiscoveryStateReceiver, P2pError> {
+        let (respond_to, receiver) = oneshot::channel();
+        self.send_command(ManagerCommand::DiscoveryState { respond_to })
+            .await?;
+        Ok(receiver)
+    }
+
+    /// Keep the device discoverable between our own scans (Android's
+    /// startListening). Followed by `P2pEvent::ListenStateChanged`; the manager
+    /// starts listening again after the supplicant restarts.
+    pub async fn start_listening(&self) -> Result<ActionReceiver, P2pError> {
+        let (respond_to, receiver) = oneshot::channel();
+        self.send_command(ManagerCommand::StartListening { respond_to })
+            .await?;
+        Ok(receiver)
+    }
+
+    /// Listen as `policy` says: always (`start_listening`), never outside our
+    /// own scans (`stop_listening`), or in windows that bound the radio's duty
+    /// cycle, e.g. `Windowed { listen: 500ms, every: 5s }`. The manager puts the
+    /// same policy back after the supplicant restarts. Only the supplicant
+    /// backend can listen windowed.
+    pub async fn set_discoverability(
+        &self,
+        policy: DiscoverabilityPolicy,
+    ) -> Result<ActionReceiver, P2pError> {
+        let (respond_to, receiver) = oneshot::channel();
+        self.send_command(ManagerCommand::SetDiscoverability { policy, respond_to })
+            .await?;
+        Ok(receiver)
+    }
+
+    /// Leave listen state (Android's stopListening). On the supplicant, as on
+    /// Android, this also ends a running discovery scan.
+    pub async fn stop_listening(&self) -> Result<ActionReceiver, P2pError> {
+        let (respond_to, receiver) = oneshot::channel();
+        self.send_command(ManagerCommand::StopListening { respond_to })
+            .await?;
+        Ok(receiver)
+    }
+
+    /// Whether we are in listen state, as the manager tracks it (Android's
+    /// getListenState).
+    pub async fn listen_state(&self) -> Result<ListenStateReceiver, P2pError> {
+        let (respond_to, receiver) = oneshot::channel();
+        self.send_command(ManagerCommand::ListenState { respond_to })
+            .await?;
+        Ok(receiver)
+    }
+
+    /// Whether P2P is usable on the interface (Android's requestP2pState): the
+    /// backend is attached and answers, and the radio is not blocked.
+    pub async fn p2p_state(&self) -> Result<P2pStateReceiver, P2pError> {
+        let (respond_to, receiver) = oneshot::channel();
+        self.send_command(ManagerCommand::P2pState { respond_to })
+            .await?;
+        Ok(receiver)
+    }
+
+    /// Our own device as peers see it (Android's requestDeviceInfo), read from
+    /// the backend now. Changes are also reported as `P2pEvent::ThisDeviceChanged`.
+    pub async fn request_device_info(&self) -> Result<DeviceInfoReceiver, P2pError> {
+        let (respond_to, receiver) = oneshot::channel();
+        self.send_command(ManagerCommand::RequestDeviceInfo { respond_to })
+            .await?;
+        Ok(receiver)
+    }
+
+    /// Change the name peers see for us during discovery (Android's
+    /// setDeviceName), followed by `P2pEvent::ThisDeviceChanged`.
+    pub async fn set_device_name(
+        &self,
+        name: impl Into<String>,
+    ) -> Result<ActionReceiver, P2pError> {
+        let (respond_to, receiver) = oneshot::channel();
+        self.send_command(ManagerCommand::SetDeviceName {
+            name: name.into(),
+            respond_to,
+        })
+        .await?;
+        Ok(receiver)
+    }
+
+    /// Return to factory settings (Android's factoryReset): every persistent
+    /// group, local service and pending service query is dropped, peers are
+    /// forgotten, vendor elements cleared and the device name put back. Groups
+    /// stored in the `CredentialStore` are removed too; a running group stays up.
+    pub async fn factory_reset(&self) -> Result<ActionReceiver, P2pError> {
+        let (respond_to, receiver) = oneshot::channel();
+        self.send_command(ManagerCommand::FactoryReset { respond_to })
+            .await?;
+        Ok(receiver)
+    }
+
     pub async fn create_group(&self) -> Result<ActionReceiver, P2pError> {
         // Create a P2P group with default options.
         let (respond_to, receiver) = oneshot::channel();
@@ -73,11 +717,395 @@ impl WifiP2pChannel {
         Ok(receiver)
     }
 
+    /// Invite `peer`, which discovery has found, into the group we own; its answer
+    /// arrives as `P2pEvent::InvitationResult`.
+    pub async fn invite(&self, peer: MacAddress) -> Result<ActionReceiver, P2pError> {
+        let (respond_to, receiver) = oneshot::channel();
+        self.send_command(ManagerCommand::Invite { peer, respond_to })
+            .await?;
+        Ok(receiver)
+    }
+
+    /// Open a session with the P2PS service a peer advertised (see
+    /// `P2pEvent::AspServiceFound`) through P2PS provision discovery. Answers once
+    /// the request is sent; the group formed for the session is reported as
+    /// `P2pEvent::GroupStarted`.
+    pub async fn asp_provision(&self, request: AspProvision) -> Result<ActionReceiver, P2pError> {
+        let (respond_to, receiver) = oneshot::channel();
+        self.send_command(ManagerCommand::AspProvision {
+            request,
+            respond_to,
+        })
+        .await?;
+        Ok(receiver)
+    }
+
+    /// Turn down the connection request reported by `P2pEvent::ConnectionRequested`.
+    pub async fn reject_peer(&self, peer: MacAddress) -> Result<ActionReceiver, P2pError> {
+        let (respond_to, receiver) = oneshot::channel();
+        self.send_command(ManagerCommand::RejectPeer { peer, respond_to })
+            .await?;
+        Ok(receiver)
+    }
+
+    /// Let `approver` decide every GO negotiation, invitation and provision
+    /// discovery request from now on, replacing an earlier one; see `approver`.
+    pub async fn set_connection_policy(
+        &self,
+        approver: impl ConnectionApprover,
+    ) -> Result<ActionReceiver, P2pError> {
+        let (respond_to, receiver) = oneshot::channel();
+        self.send_command(ManagerCommand::SetConnectionPolicy {
+            approver: Some(Box::new(approver)),
+            respond_to,
+        })
+        .await?;
+        Ok(receiver)
+    }
+
+    /// Report incoming requests as events again, for the app to answer.
+    pub async fn clear_connection_policy(&self) -> Result<ActionReceiver, P2pError> {
+        let (respond_to, receiver) = oneshot::channel();
+        self.send_command(ManagerCommand::SetConnectionPolicy {
+            approver: None,
+            respond_to,
+        })
+        .await?;
+        Ok(receiver)
+    }
+
+    /// Leave the group we are in, or end the one we own (Android's removeGroup).
+    /// Succeeds when there is no group.
+    pub async fn remove_group(&self) -> Result<ActionReceiver, P2pError> {
+        let (respond_to, receiver) = oneshot::channel();
+        self.send_command(ManagerCommand::RemoveGroups { respond_to })
+            .await?;
+        Ok(receiver)
+    }
+
+    /// Answer service discovery queries from peers for `service`, until it is
+    /// removed or the supplicant restarts.
+    pub async fn add_local_service(
+        &self,
+        service: LocalService,
+    ) -> Result<ActionReceiver, P2pError> {
+        let (respond_to, receiver) = oneshot::channel();
+        self.send_command(ManagerCommand::AddService {
+            service,
+            respond_to,
+        })
+        .await?;
+        Ok(receiver)
+    }
+
+    pub async fn remove_local_service(
+        &self,
+        service: LocalService,
+    ) -> Result<ActionReceiver, P2pError> {
+        let (respond_to, receiver) = oneshot::channel();
+        self.send_command(ManagerCommand::RemoveService {
+            service,
+            respond_to,
+        })
+        .await?;
+        Ok(receiver)
+    }
+
+    /// Stop answering for every service added through this channel's manager
+    /// (Android's clearLocalServices), e.g. on logout.
+    pub async fn clear_local_services(&self) -> Result<ActionReceiver, P2pError> {
+        let (respond_to, receiver) = oneshot::channel();
+        self.send_command(ManagerCommand::ClearLocalServices { respond_to })
+            .await?;
+        Ok(receiver)
+    }
+
+    /// Ask every peer that discovery finds for its services; answers arrive as
+    /// `P2pEvent::ServicesFound`. Queries only go out while discovery runs.
+    pub async fn discover_services(&self) -> Result<ActionReceiver, P2pError> {
+        self.queue_service_discovery(None).await
+    }
+
+    /// Ask `peer`, which discovery has found, for its services.
+    pub async fn discover_services_from(
+        &self,
+        peer: MacAddress,
+    ) -> Result<ActionReceiver, P2pError> {
+        self.queue_service_discovery(Some(peer)).await
+    }
+
+    /// Withdraw every service query sent by `discover_services` and
+    /// `discover_services_from` that is still pending (Android's
+    /// clearServiceRequests).
+    pub async fn clear_service_requests(&self) -> Result<ActionReceiver, P2pError> {
+        let (respond_to, receiver) = oneshot::channel();
+        self.send_command(ManagerCommand::ClearServiceRequests { respond_to })
+            .await?;
+        Ok(receiver)
+    }
+
+    async fn queue_service_discovery(
+        &self,
+        peer: Option<MacAddress>,
+    ) -> Result<ActionReceiver, P2pError> {
+        let (respond_to, receiver) = oneshot::channel();
+        self.send_command(ManagerCommand::DiscoverServices { peer, respond_to })
+            .await?;
+        Ok(receiver)
+    }
+
+    pub async fn set_noa(&self, schedule: NoaSchedule) -> Result<ActionReceiver, P2pError> {
+        // Only meaningful while we are GO; the supplicant rejects it otherwise.
+        let (respond_to, receiver) = oneshot::channel();
+        self.send_command(ManagerCommand::SetNoa {
+            schedule,
+            respond_to,
+        })
+        .await?;
+        Ok(receiver)
+    }
+
+    pub async fn set_vendor_elements(
+        &self,
+        frame: VendorElementFrame,
+        elements: Vec<u8>,
+    ) -> Result<ActionReceiver, P2pError> {
+        // Elements are raw IE bytes (id, length, payload), appended to every matching frame.
+        let (respond_to, receiver) = oneshot::channel();
+        self.send_command(ManagerCommand::SetVendorElements {
+            frame,
+            elements,
+            respond_to,
+        })
+        .await?;
+        Ok(receiver)
+    }
+
+    /// Announce `info` as our WFD Device Information in P2P frames, turning Wi-Fi
+    /// Display on; `None` turns it off. Put back after the supplicant restarts.
+    pub async fn set_wfd_info(
+        &self,
+        info: Option<WfdDeviceInfo>,
+    ) -> Result<ActionReceiver, P2pError> {
+        let (respond_to, receiver) = oneshot::channel();
+        self.send_command(ManagerCommand::SetWfdInfo { info, respond_to })
+            .await?;
+        Ok(receiver)
+    }
+
+    /// The WFD Device Information set with `set_wfd_info`, if Wi-Fi Display is on.
+    pub async fn request_wfd_info(&self) -> Result<WfdInfoReceiver, P2pError> {
+        let (respond_to, receiver) = oneshot::channel();
+        self.send_command(ManagerCommand::RequestWfdInfo { respond_to })
+            .await?;
+        Ok(receiver)
+    }
+
+    pub async fn resolve_peer_ip(
+        &self,
+        device_address: MacAddress,
+    ) -> Result<PeerIpReceiver, P2pError> {
+        // Connected peers are also looked up in the background; see `P2pEvent::PeerIpResolved`.
+        let (respond_to, receiver) = oneshot::channel();
+        self.send_command(ManagerCommand::ResolvePeerIp {
+            device_address,
+            respond_to,
+        })
+        .await?;
+        Ok(receiver)
+    }
+
+    /// Wait for group formation and open a TCP connection to `peer` on `port` as
+    /// client, or a listener on `port` as group owner, giving up after 30 seconds.
+    #[cfg(feature = "tokio")]
+    pub async fn open_connection(
+        &self,
+        peer: MacAddress,
+        port: u16,
+    ) -> Result<P2pSocket, P2pError> {
+        self.open_connection_with_timeout(peer, port, OPEN_CONNECTION_TIMEOUT)
+            .await
+    }
+
+    #[cfg(feature = "tokio")]
+    pub async fn open_connection_with_timeout(
+        &self,
+        peer: MacAddress,
+        port: u16,
+        timeout: Duration,
+    ) -> Result<P2pSocket, P2pError> {
+        tokio::time::timeout(timeout, crate::net::socket::open(self, peer, port))
+            .await
+            .map_err(|_| P2pError::Timeout(timeout))?
+    }
+
+    /// Find the peer `selector` picks, connect to it and wait until it can be
+    /// reached over the group link; see `pair`.
+    #[cfg(feature = "tokio")]
+    pub async fn pair(
+        &self,
+        selector: PeerSelector,
+        options: PairOptions,
+    ) -> Result<PairedLink, P2pError> {
+        crate::pair::pair(self, selector, options).await
+    }
+
+    /// Pair with `peer` for a Wi-Fi Display session, as source or sink depending
+    /// on its WFD Device Information and ours (see `set_wfd_info`), and return
+    /// what an RTSP stack needs to run it; see `wfd`.
+    #[cfg(feature = "tokio")]
+    pub async fn start_wfd_session(&self, peer: MacAddress) -> Result<WfdSession, P2pError> {
+        crate::wfd::start_session(self, peer).await
+    }
+
+    /// Send the file at `path` to `peer`, which must be running `receive_files`.
+    /// Progress is reported as `P2pEvent::TransferProgress`; an interrupted
+    /// transfer of the same file resumes where it stopped.
+    #[cfg(feature = "transfer")]
+    pub async fn send_file(
+        &self,
+        peer: MacAddress,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<(), P2pError> {
+        self.send_file_on(peer, path, crate::transfer::DEFAULT_PORT)
+            .await
+    }
+
+    #[cfg(feature = "transfer")]
+    pub async fn send_file_on(
+        &self,
+        peer: MacAddress,
+        path: impl AsRef<std::path::Path>,
+        port: u16,
+    ) -> Result<(), P2pError> {
+        // Only reaching the peer is bounded; the transfer itself takes as long as it takes.
+        let connect = crate::net::socket::connect(self, peer, port);
+        let stream = tokio::time::timeout(OPEN_CONNECTION_TIMEOUT, connect)
+            .await
+            .map_err(|_| P2pError::Timeout(OPEN_CONNECTION_TIMEOUT))??;
+        crate::transfer::send(self, stream, path.as_ref()).await
+    }
+
+    /// Measure round trips and throughput to `peer`, which must be running
+    /// `serve_probes`; see `probe`. Takes about twice `options.duration`.
+    #[cfg(feature = "probe")]
+    pub async fn probe(
+        &self,
+        peer: MacAddress,
+        options: ProbeOptions,
+    ) -> Result<LinkReport, P2pError> {
+        self.probe_on(peer, crate::probe::DEFAULT_PORT, options)
+            .await
+    }
+
+    #[cfg(feature = "probe")]
+    pub async fn probe_on(
+        &self,
+        peer: MacAddress,
+        port: u16,
+        options: ProbeOptions,
+    ) -> Result<LinkReport, P2pError> {
+        let connect = crate::net::socket::connect(self, peer, port);
+        let stream = tokio::time::timeout(OPEN_CONNECTION_TIMEOUT, connect)
+            .await
+            .map_err(|_| P2pError::Timeout(OPEN_CONNECTION_TIMEOUT))??;
+        crate::probe::run(peer, stream, options).await
+    }
+
+    /// Answer probes from any group member until the returned future is
+    /// dropped or listening fails.
+    #[cfg(feature = "probe")]
+    pub async fn serve_probes(&self) -> Result<(), P2pError> {
+        self.serve_probes_on(crate::probe::DEFAULT_PORT).await
+    }
+
+    #[cfg(feature = "probe")]
+    pub async fn serve_probes_on(&self, port: u16) -> Result<(), P2pError> {
+        crate::probe::serve(port).await
+    }
+
+    /// Accept files from any group member into `dir` until the returned future
+    /// is dropped or listening fails.
+    #[cfg(feature = "transfer")]
+    pub async fn receive_files(&self, dir: impl AsRef<std::path::Path>) -> Result<(), P2pError> {
+        self.receive_files_on(dir, crate::transfer::DEFAULT_PORT)
+            .await
+    }
+
+    #[cfg(feature = "transfer")]
+    pub async fn receive_files_on(
+        &self,
+        dir: impl AsRef<std::path::Path>,
+        port: u16,
+    ) -> Result<(), P2pError> {
+        crate::transfer::receive(self, dir.as_ref(), port).await
+    }
+
+    pub async fn call_raw(
+        &self,
+        method: impl Into<String>,
+        options: HashMap<String, OwnedValue>,
+    ) -> Result<RawReplyReceiver, P2pError> {
+        // Escape hatch for unwrapped P2PDevice methods; no event is emitted.
+        let (respond_to, receiver) = oneshot::channel();
+        self.send_command(ManagerCommand::CallRaw {
+            method: method.into(),
+            options,
+            respond_to,
+        })
+        .await?;
+        Ok(receiver)
+    }
+
+    /// Publish an event produced on the channel side, e.g. by a file transfer.
+    pub(crate) fn emit(&self, event: P2pEvent) {
+        let _ = self.event_tx.send(event);
+    }
+
     async fn send_command(&self, command: ManagerCommand) -> Result<(), P2pError> {
+        self.send_command_with_timeout(command, None).await
+    }
+
+    async fn send_command_with_timeout(
+        &self,
+        command: ManagerCommand,
+        timeout: Option<Duration>,
+    ) -> Result<(), P2pError> {
+        #[cfg(feature = "recording")]
+        self.taps.write(|at_ms| RecordedEntry::Command {
+            at_ms,
+            command: command.name().to_string(),
+            timeout_ms: timeout.map(|timeout| timeout.as_millis() as u64),
+        });
+        let commands = if command.is_priority() {
+            &self.priority_tx
+        } else {
+            &self.command_tx
+        };
         // If the manager task is gone, convert it into a typed error.
-        self.command_tx
-            .send(command)
-            .await
-            .map_err(|_| P2pError::ChannelClosed("manager".to_string()))
+        let closed = || P2pError::ChannelClosed("manager".to_string());
+        // Wait for room first: numbering a command before it is on the queue
+        // would let another clone's later one get there ahead of it.
+        let permit = match self.overflow {
+            QueueOverflow::Wait => commands.reserve().await.map_err(|_| closed())?,
+            QueueOverflow::FailFast => commands.try_reserve().map_err(|err| match err {
+                mpsc::error::TrySendError::Full(()) => P2pError::Busy(format!(
+                    "command queue is full ({} commands waiting)",
+                    commands.max_capacity()
+                )),
+                mpsc::error::TrySendError::Closed(()) => closed(),
+            })?,
+        };
+        let mut sequence = self
+            .sequence
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        permit.send(QueuedCommand {
+            command,
+            timeout,
+            sequence: *sequence,
+        });
+        *sequence += 1;
+        Ok(())
     }
 }
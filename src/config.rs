@@ -0,0 +1,145 @@
+use std::time::Duration;
+
+use crate::mac::MacAddress;
+
+/// WPS provisioning method used when connecting to a peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
+pub enum WpsMethod {
+    /// Push button on both sides.
+    #[default]
+    Pbc,
+    /// We display a PIN that the peer enters.
+    PinDisplay,
+    /// We enter the PIN displayed by the peer.
+    PinKeypad,
+    /// We use the PIN printed on the peer's label.
+    PinLabel,
+}
+
+impl WpsMethod {
+    /// The `wps_method` string expected by wpa_supplicant's Connect.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Pbc => "pbc",
+            Self::PinDisplay => "display",
+            Self::PinKeypad => "keypad",
+            Self::PinLabel => "label",
+        }
+    }
+
+    /// Methods where the caller must supply the PIN; `PinDisplay` can let the
+    /// supplicant generate one.
+    pub fn requires_pin(self) -> bool {
+        matches!(self, Self::PinKeypad | Self::PinLabel)
+    }
+}
+
+/// Which channels a discovery scan covers, like Android 13's
+/// `discoverPeersOnSocialChannels` and `discoverPeersOnSpecificFrequency`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DiscoveryType {
+    /// Scan every supported channel first, then only the social channels.
+    #[default]
+    Full,
+    /// Only the social channels 1, 6 and 11, where every device listens; much
+    /// faster when peers are not already in a group elsewhere.
+    Social,
+    /// Social channels, visiting one other channel each round.
+    Progressive,
+    /// Only this frequency in MHz, e.g. the operating channel of a known group.
+    SingleFrequency(u32),
+}
+
+/// What `WifiP2pChannel::discover_peers_with` scans for. A `DiscoveryType`
+/// converts into options without a device type filter.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DiscoveryOptions {
+    pub discovery_type: DiscoveryType,
+    /// Only report peers with one of these primary or secondary device types,
+    /// in `P2pDevice::primary_type` format (e.g. "3-0050F204-1" for printers).
+    /// Empty reports every peer.
+    pub device_types: Vec<String>,
+    /// Only find peers advertising one of these P2PS services, by name (e.g.
+    /// `org.wi-fi.wfds.print.tx`); see `service::asp`.
+    pub seek: Vec<String>,
+}
+
+impl DiscoveryOptions {
+    /// Also report peers of `device_type`; see `device_types`.
+    pub fn device_type(mut self, device_type: impl Into<String>) -> Self {
+        self.device_types.push(device_type.into());
+        self
+    }
+
+    /// Also find advertisers of the P2PS service `name`; see `seek`.
+    pub fn seek(mut self, name: impl Into<String>) -> Self {
+        self.seek.push(name.into());
+        self
+    }
+}
+
+impl From<DiscoveryType> for DiscoveryOptions {
+    fn from(discovery_type: DiscoveryType) -> Self {
+        Self {
+            discovery_type,
+            device_types: Vec::new(),
+            seek: Vec::new(),
+        }
+    }
+}
+
+/// When peers can find us outside our own discovery scans; see
+/// `WifiP2pChannel::set_discoverability`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DiscoverabilityPolicy {
+    /// Stay in listen state, like `start_listening`.
+    Always,
+    /// Listen for `listen` out of every `every`, e.g. 500ms every 5s, to bound
+    /// the radio's duty cycle on battery.
+    Windowed { listen: Duration, every: Duration },
+    /// Only while discovering, like `stop_listening`.
+    Off,
+}
+
+/// Parameters for a connect request, similar to Android's `WifiP2pConfig`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct ConnectConfig {
+    /// P2P device address of the peer.
+    pub device_address: MacAddress,
+    /// Provisioning method for the WPS exchange.
+    pub wps_method: WpsMethod,
+    /// PIN for the PIN-based methods. With `PinDisplay` and no PIN, the
+    /// supplicant generates one and it is returned from the connect action.
+    pub pin: Option<String>,
+}
+
+impl ConnectConfig {
+    /// Push-button connect, the default used by `WifiP2pChannel::connect`.
+    pub fn new(device_address: MacAddress) -> Self {
+        Self {
+            device_address,
+            wps_method: WpsMethod::Pbc,
+            pin: None,
+        }
+    }
+
+    /// PIN-based connect with the PIN carried alongside the method.
+    pub fn with_pin(
+        device_address: MacAddress,
+        wps_method: WpsMethod,
+        pin: impl Into<String>,
+    ) -> Self {
+        Self {
+            device_address,
+            wps_method,
+            pin: Some(pin.into()),
+        }
+    }
+}
@@ -0,0 +1,210 @@
+//! Persistent group credentials that outlive the process.
+//!
+//! wpa_supplicant only keeps persistent groups across restarts when it may write
+//! its own configuration, which is often not the case. With a `CredentialStore`
+//! on the builder, the manager saves the credentials of every persistent group the
+//! supplicant reports when a group starts or finishes, and adds them back to the
+//! supplicant on startup, so `WifiP2pChannel::reinvoke` reconnects to a known peer
+//! without pairing again.
+//!
+//! `FileCredentialStore` keeps them in a file only the owner can read; with the
+//! `secret-service` feature, `SecretServiceCredentialStore` keeps them in the
+//! user's keyring instead (Linux).
+
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::P2pError;
+use crate::group::GroupRole;
+use crate::mac::MacAddress;
+
+#[cfg(all(feature = "secret-service", target_os = "linux"))]
+mod secret_service;
+
+#[cfg(all(feature = "secret-service", target_os = "linux"))]
+pub use secret_service::SecretServiceCredentialStore;
+
+/// What it takes to re-invoke a persistent group with a peer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GroupCredentials {
+    /// The supplicant's network id for the group; reassigned when the group is
+    /// added back after a restart.
+    pub network_id: u32,
+    pub ssid: String,
+    /// The WPA2 passphrase, or the raw PSK as 64 hex digits.
+    pub psk: String,
+    /// P2P device address of the other side: the group owner when we were client,
+    /// the (first) client when we owned the group.
+    pub peer: MacAddress,
+    /// Our role in the group.
+    pub role: GroupRole,
+}
+
+impl GroupCredentials {
+    /// Whether `psk` is a raw key rather than a passphrase.
+    pub fn is_raw_psk(&self) -> bool {
+        self.psk.len() == 64 && self.psk.bytes().all(|b| b.is_ascii_hexdigit())
+    }
+}
+
+/// Where the manager keeps group credentials; see `WifiP2pManagerBuilder::credential_store`.
+///
+/// `save` is handed the complete set every time, so implementations can simply
+/// replace what they stored before.
+pub trait CredentialStore: fmt::Debug + Send + Sync + 'static {
+    fn load(&self) -> Result<Vec<GroupCredentials>, P2pError>;
+    fn save(&self, credentials: &[GroupCredentials]) -> Result<(), P2pError>;
+}
+
+/// Keeps credentials in a file of wpa_supplicant-style `network={...}` blocks,
+/// readable only by the owner.
+#[derive(Debug, Clone)]
+pub struct FileCredentialStore {
+    path: PathBuf,
+}
+
+impl FileCredentialStore {
+    /// Store at `path`; the file is created on the first save.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl CredentialStore for FileCredentialStore {
+    fn load(&self) -> Result<Vec<GroupCredentials>, P2pError> {
+        let text = match fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(store_error(&self.path, err)),
+        };
+        parse(&text).map_err(|line| {
+            P2pError::Backend(format!(
+                "credential store {}: malformed line {line}",
+                self.path.display()
+            ))
+        })
+    }
+
+    fn save(&self, credentials: &[GroupCredentials]) -> Result<(), P2pError> {
+        // Write a sibling file and rename it over the old one, so a crash never
+        // leaves a truncated store behind.
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        let temporary = PathBuf::from(temporary);
+        write_private(&temporary, to_text(credentials).as_bytes())
+            .map_err(|err| store_error(&temporary, err))?;
+        fs::rename(&temporary, &self.path).map_err(|err| store_error(&self.path, err))
+    }
+}
+
+fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+fn to_text(credentials: &[GroupCredentials]) -> String {
+    let mut text = String::new();
+    for group in credentials {
+        let role = match group.role {
+            GroupRole::Owner => "owner",
+            GroupRole::Client => "client",
+        };
+        // Raw keys are unquoted, like in wpa_supplicant.conf.
+        let psk = if group.is_raw_psk() {
+            group.psk.clone()
+        } else {
+            quote(&group.psk)
+        };
+        text.push_str(&format!(
+            "network={{\n\tid={}\n\tssid={}\n\tpsk={psk}\n\tpeer={}\n\trole={role}\n}}\n",
+            group.network_id,
+            quote(&group.ssid),
+            group.peer,
+        ));
+    }
+    text
+}
+
+/// Parse the blocks written by `to_text`; on error, the offending line number.
+fn parse(text: &str) -> Result<Vec<GroupCredentials>, usize> {
+    let mut credentials = Vec::new();
+    let mut fields: Option<Vec<(&str, String)>> = None;
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        let malformed = index + 1;
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match (&mut fields, line) {
+            (None, "network={") => fields = Some(Vec::new()),
+            (Some(block), "}") => {
+                let block = std::mem::take(block);
+                fields = None;
+                credentials.push(credentials_from(&block).ok_or(malformed)?);
+            }
+            (Some(block), _) => {
+                let (key, value) = line.split_once('=').ok_or(malformed)?;
+                block.push((key, unquote(value).ok_or(malformed)?));
+            }
+            (None, _) => return Err(malformed),
+        }
+    }
+    match fields {
+        Some(_) => Err(text.lines().count()),
+        None => Ok(credentials),
+    }
+}
+
+fn credentials_from(fields: &[(&str, String)]) -> Option<GroupCredentials> {
+    let field = |name: &str| {
+        fields
+            .iter()
+            .find_map(|(key, value)| (*key == name).then_some(value.as_str()))
+    };
+    Some(GroupCredentials {
+        network_id: field("id")?.parse().ok()?,
+        ssid: field("ssid")?.to_string(),
+        psk: field("psk")?.to_string(),
+        peer: field("peer")?.parse().ok()?,
+        role: match field("role")? {
+            "owner" => GroupRole::Owner,
+            "client" => GroupRole::Client,
+            _ => return None,
+        },
+    })
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// The value of a `key=value` line: quoted strings are unescaped, anything
+/// else is taken as is.
+fn unquote(value: &str) -> Option<String> {
+    let Some(inner) = value.strip_prefix('"') else {
+        return Some(value.to_string());
+    };
+    let inner = inner.strip_suffix('"')?;
+    let mut unescaped = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        unescaped.push(if c == '\\' { chars.next()? } else { c });
+    }
+    Some(unescaped)
+}
+
+fn store_error(path: &Path, err: io::Error) -> P2pError {
+    P2pError::Backend(format!("credential store {}: {err}", path.display()))
+}
@@ -0,0 +1,291 @@
+//! `CredentialStore` on the freedesktop Secret Service (GNOME Keyring, KWallet).
+//!
+//! Each group is one item in the default collection: the PSK is the secret, and
+//! the SSID, peer, role and network id are attributes, so nothing sensitive is
+//! written to a file. A locked collection is unlocked through the service's own
+//! prompt, which may ask the user for their login password.
+
+use std::collections::HashMap;
+use std::thread;
+
+use zbus::blocking::Connection;
+use zbus::proxy;
+use zbus::zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value};
+
+use super::{CredentialStore, GroupCredentials};
+use crate::error::P2pError;
+use crate::group::GroupRole;
+
+/// Marks our items among everything else in the keyring.
+const SCHEMA: &str = "io.github.wifi_p2p_rs.GroupCredentials";
+
+/// (session, parameters, value, content type), as the Secret Service passes secrets.
+type Secret = (OwnedObjectPath, Vec<u8>, Vec<u8>, String);
+
+#[proxy(
+    interface = "org.freedesktop.Secret.Service",
+    default_service = "org.freedesktop.secrets",
+    default_path = "/org/freedesktop/secrets",
+    gen_async = false
+)]
+trait Service {
+    fn open_session(
+        &self,
+        algorithm: &str,
+        input: &Value<'_>,
+    ) -> zbus::Result<(OwnedValue, OwnedObjectPath)>;
+
+    fn search_items(
+        &self,
+        attributes: HashMap<&str, &str>,
+    ) -> zbus::Result<(Vec<OwnedObjectPath>, Vec<OwnedObjectPath>)>;
+
+    fn unlock(
+        &self,
+        objects: &[OwnedObjectPath],
+    ) -> zbus::Result<(Vec<OwnedObjectPath>, OwnedObjectPath)>;
+
+    fn get_secrets(
+        &self,
+        items: &[OwnedObjectPath],
+        session: &ObjectPath<'_>,
+    ) -> zbus::Result<HashMap<OwnedObjectPath, Secret>>;
+
+    fn read_alias(&self, name: &str) -> zbus::Result<OwnedObjectPath>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.Secret.Collection",
+    default_service = "org.freedesktop.secrets",
+    gen_async = false
+)]
+trait Collection {
+    fn create_item(
+        &self,
+        properties: HashMap<&str, Value<'_>>,
+        secret: &Secret,
+        replace: bool,
+    ) -> zbus::Result<(OwnedObjectPath, OwnedObjectPath)>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.Secret.Item",
+    default_service = "org.freedesktop.secrets",
+    gen_async = false
+)]
+trait Item {
+    fn delete(&self) -> zbus::Result<OwnedObjectPath>;
+
+    #[zbus(property)]
+    fn attributes(&self) -> zbus::Result<HashMap<String, String>>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.Secret.Prompt",
+    default_service = "org.freedesktop.secrets",
+    gen_async = false
+)]
+trait Prompt {
+    fn prompt(&self, window_id: &str) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn completed(&self, dismissed: bool, result: Value<'_>) -> zbus::Result<()>;
+}
+
+/// Keeps credentials in the user's keyring over the Secret Service API.
+///
+/// Calls are blocking and run on a short-lived thread of their own; `save` may
+/// wait for the user to answer an unlock prompt.
+#[derive(Debug, Clone, Default)]
+pub struct SecretServiceCredentialStore {
+    _private: (),
+}
+
+impl SecretServiceCredentialStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl CredentialStore for SecretServiceCredentialStore {
+    fn load(&self) -> Result<Vec<GroupCredentials>, P2pError> {
+        on_own_thread(|session| session.load())
+    }
+
+    fn save(&self, credentials: &[GroupCredentials]) -> Result<(), P2pError> {
+        on_own_thread(|session| session.save(credentials))
+    }
+}
+
+/// Run `f` on a fresh thread: the blocking zbus API must not be used on a
+/// runtime thread, and the manager calls the store from one.
+fn on_own_thread<T: Send>(
+    f: impl FnOnce(&Session) -> zbus::Result<T> + Send,
+) -> Result<T, P2pError> {
+    let result = thread::scope(|scope| {
+        scope
+            .spawn(|| Session::open().and_then(|session| f(&session)))
+            .join()
+    });
+    match result {
+        Ok(result) => result.map_err(|err| P2pError::Backend(format!("secret service: {err}"))),
+        Err(_) => Err(P2pError::Backend(
+            "secret service: worker thread panicked".to_string(),
+        )),
+    }
+}
+
+struct Session {
+    connection: Connection,
+    service: ServiceProxy<'static>,
+    path: OwnedObjectPath,
+}
+
+impl Session {
+    fn open() -> zbus::Result<Self> {
+        let connection = Connection::session()?;
+        let service = ServiceProxy::new(&connection)?;
+        // The secrets only travel over the session bus, so no transport encryption.
+        let (_, path) = service.open_session("plain", &Value::from(""))?;
+        Ok(Self {
+            connection,
+            service,
+            path,
+        })
+    }
+
+    fn load(&self) -> zbus::Result<Vec<GroupCredentials>> {
+        let (mut items, locked) = self.service.search_items(query())?;
+        if !locked.is_empty() {
+            items.extend(self.unlock(locked)?);
+        }
+        let secrets = self.service.get_secrets(&items, &self.path)?;
+        let mut credentials = Vec::new();
+        for item in &items {
+            let Some((_, _, psk, _)) = secrets.get(item) else {
+                continue;
+            };
+            let attributes = self.item(item)?.attributes()?;
+            // Items that are not ours to read are skipped rather than failing the load.
+            if let Some(group) = credentials_from(&attributes, psk) {
+                credentials.push(group);
+            }
+        }
+        Ok(credentials)
+    }
+
+    fn save(&self, credentials: &[GroupCredentials]) -> zbus::Result<()> {
+        let collection = self.service.read_alias("default")?;
+        if collection.as_str() == "/" {
+            return Err(zbus::Error::Failure("no default collection".to_string()));
+        }
+        self.unlock(vec![collection.clone()])?;
+        let collection = CollectionProxy::builder(&self.connection)
+            .path(collection)?
+            .build()?;
+        // Items are replaced when their attributes match, so a re-formed group
+        // keeps a single entry; whatever was not written this time is deleted.
+        let mut kept = Vec::new();
+        for group in credentials {
+            let attributes = attributes(group);
+            let properties = HashMap::from([
+                (
+                    "org.freedesktop.Secret.Item.Label",
+                    Value::from(format!("Wi-Fi Direct group {}", group.ssid)),
+                ),
+                (
+                    "org.freedesktop.Secret.Item.Attributes",
+                    Value::from(attributes),
+                ),
+            ]);
+            let secret = (
+                self.path.clone(),
+                Vec::new(),
+                group.psk.as_bytes().to_vec(),
+                "text/plain".to_string(),
+            );
+            let (item, prompt) = collection.create_item(properties, &secret, true)?;
+            kept.push(if item.as_str() == "/" {
+                OwnedObjectPath::try_from(self.prompt(prompt)?)?
+            } else {
+                item
+            });
+        }
+        let (unlocked, locked) = self.service.search_items(query())?;
+        for stale in unlocked
+            .iter()
+            .chain(&locked)
+            .filter(|item| !kept.contains(item))
+        {
+            let prompt = self.item(stale)?.delete()?;
+            if prompt.as_str() != "/" {
+                self.prompt(prompt)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Unlock `objects`, prompting the user if the service asks to.
+    fn unlock(&self, objects: Vec<OwnedObjectPath>) -> zbus::Result<Vec<OwnedObjectPath>> {
+        let (unlocked, prompt) = self.service.unlock(&objects)?;
+        if prompt.as_str() == "/" {
+            return Ok(unlocked);
+        }
+        Ok(Vec::try_from(self.prompt(prompt)?)?)
+    }
+
+    /// Show a prompt and wait for it; the result depends on the call that asked for it.
+    fn prompt(&self, path: OwnedObjectPath) -> zbus::Result<OwnedValue> {
+        let prompt = PromptProxy::builder(&self.connection).path(path)?.build()?;
+        // Subscribe first, so a prompt answered right away is not missed.
+        let mut completed = prompt.receive_completed()?;
+        prompt.prompt("")?;
+        let signal = completed
+            .next()
+            .ok_or_else(|| zbus::Error::Failure("prompt went away".to_string()))?;
+        let args = signal.args()?;
+        if args.dismissed {
+            return Err(zbus::Error::Failure("prompt was dismissed".to_string()));
+        }
+        Ok(args.result.try_to_owned()?)
+    }
+
+    fn item(&self, path: &OwnedObjectPath) -> zbus::Result<ItemProxy<'static>> {
+        ItemProxy::builder(&self.connection)
+            .path(path.clone())?
+            .build()
+    }
+}
+
+fn query() -> HashMap<&'static str, &'static str> {
+    HashMap::from([("xdg:schema", SCHEMA)])
+}
+
+fn attributes(group: &GroupCredentials) -> HashMap<&'static str, String> {
+    let role = match group.role {
+        GroupRole::Owner => "owner",
+        GroupRole::Client => "client",
+    };
+    HashMap::from([
+        ("xdg:schema", SCHEMA.to_string()),
+        ("ssid", group.ssid.clone()),
+        ("peer", group.peer.to_string()),
+        ("role", role.to_string()),
+        ("network-id", group.network_id.to_string()),
+    ])
+}
+
+fn credentials_from(attributes: &HashMap<String, String>, psk: &[u8]) -> Option<GroupCredentials> {
+    let field = |name: &str| attributes.get(name).map(String::as_str);
+    Some(GroupCredentials {
+        network_id: field("network-id")?.parse().ok()?,
+        ssid: field("ssid")?.to_string(),
+        psk: String::from_utf8(psk.to_vec()).ok()?,
+        peer: field("peer")?.parse().ok()?,
+        role: match field("role")? {
+            "owner" => GroupRole::Owner,
+            "client" => GroupRole::Client,
+            _ => return None,
+        },
+    })
+}
@@ -0,0 +1,342 @@
+//! `org.wifip2p.Manager1`: one manager shared by several applications over D-Bus.
+//!
+//! wpa_supplicant keeps a single P2P state per radio, so applications driving it
+//! directly stop each other's discovery and tear down each other's groups.
+//! `serve` publishes a channel as a D-Bus service instead, and the `wifip2pd`
+//! binary runs it on the system bus, where the bus policy decides who may call it.
+//!
+//! Discovery is shared: it runs while any client wants it, and only stops once
+//! every client that started it has called `StopDiscovery` or left the bus.
+//!
+//! Peers and groups are `a{sv}` dictionaries so fields can be added later.
+//! Peers carry `address`, `status` and, when known, `name`, `primary_type` and
+//! `signal` (dBm); groups carry `interface`, `role` and, once addressed, `ip`,
+//! `prefix` and `go_ip`. Errors use the standard `org.freedesktop.DBus.Error`
+//! names, which this crate decodes back into `P2pError`.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::oneshot;
+use tokio_stream::StreamExt;
+use zbus::message::Header;
+use zbus::object_server::{InterfaceRef, SignalContext};
+use zbus::zvariant::Value;
+use zbus::{Connection, fdo, interface};
+
+use crate::channel::{P2pEvent, WifiP2pChannel};
+use crate::config::{ConnectConfig, WpsMethod};
+use crate::device::P2pDevice;
+use crate::error::P2pError;
+use crate::group::P2pGroup;
+use crate::mac::MacAddress;
+
+/// Well-known bus name the service requests.
+pub const SERVICE_NAME: &str = "org.wifip2p";
+/// Object the `org.wifip2p.Manager1` interface lives on.
+pub const OBJECT_PATH: &str = "/org/wifip2p/Manager1";
+
+type Dict = HashMap<&'static str, Value<'static>>;
+
+/// Serve `channel` on `connection` as `org.wifip2p` until the manager stops.
+pub async fn serve(channel: WifiP2pChannel, connection: &Connection) -> Result<(), P2pError> {
+    let mut events = channel.subscribe_events();
+    let server = connection.object_server();
+    server
+        .at(OBJECT_PATH, ManagerService::new(channel.clone()))
+        .await?;
+    let service = server.interface::<_, ManagerService>(OBJECT_PATH).await?;
+    connection.request_name(SERVICE_NAME).await?;
+    let bus = fdo::DBusProxy::new(connection).await?;
+    let mut owners = bus.receive_name_owner_changed().await?;
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => forward(&service, event).await?,
+                Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return Ok(()),
+            },
+            () = channel.closed() => return Ok(()),
+            Some(change) = owners.next() => {
+                let args = change.args()?;
+                if args.new_owner.is_none() {
+                    service.get().await.client_left(args.name.as_str()).await;
+                }
+            }
+        }
+    }
+}
+
+async fn forward(service: &InterfaceRef<ManagerService>, event: P2pEvent) -> zbus::Result<()> {
+    let ctxt = service.signal_context();
+    let manager = service.get().await;
+    match event {
+        P2pEvent::DiscoveryStarted => manager.discovering_changed(ctxt).await,
+        P2pEvent::DiscoveryStopped => {
+            // However it stopped, a new DiscoverPeers has to start it again.
+            manager.clients().clear();
+            manager.discovering_changed(ctxt).await
+        }
+        P2pEvent::PeerFound(device) => ManagerService::peer_found(ctxt, peer_dict(&device)).await,
+        P2pEvent::PeerLost(address) => ManagerService::peer_lost(ctxt, &address.to_string()).await,
+        P2pEvent::PeersChanged { .. } | P2pEvent::PeerStatusChanged(..) => {
+            manager.peers_changed(ctxt).await
+        }
+        P2pEvent::ConnectionRequested(address) => {
+            ManagerService::connection_requested(ctxt, &address.to_string()).await
+        }
+        P2pEvent::GoNegotiationFailed {
+            peer: Some(peer),
+            status,
+        }
+        | P2pEvent::ProvisionDiscoveryFailed { peer, status } => {
+            ManagerService::connection_failed(ctxt, &peer.to_string(), &status.to_string()).await
+        }
+        P2pEvent::GroupStarted(group) => {
+            ManagerService::group_started(ctxt, group_dict(&group)).await?;
+            manager.groups_changed(ctxt).await
+        }
+        P2pEvent::GroupFinished(group) => {
+            ManagerService::group_finished(ctxt, group_dict(&group)).await?;
+            manager.groups_changed(ctxt).await
+        }
+        P2pEvent::PeerIpResolved { mac, ip } => {
+            ManagerService::peer_ip_resolved(ctxt, &mac.to_string(), &ip.to_string()).await
+        }
+        _ => Ok(()),
+    }
+}
+
+struct ManagerService {
+    channel: WifiP2pChannel,
+    /// Unique names of the clients that want discovery running.
+    clients: Arc<Mutex<HashSet<String>>>,
+}
+
+impl ManagerService {
+    fn new(channel: WifiP2pChannel) -> Self {
+        Self {
+            channel,
+            clients: Arc::default(),
+        }
+    }
+
+    fn clients(&self) -> std::sync::MutexGuard<'_, HashSet<String>> {
+        self.clients
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    async fn client_left(&self, name: &str) {
+        let last = {
+            let mut clients = self.clients();
+            clients.remove(name) && clients.is_empty()
+        };
+        if last {
+            // Nobody is left to report a failure to.
+            let _ = answer(self.channel.stop_discovery()).await;
+        }
+    }
+}
+
+#[interface(name = "org.wifip2p.Manager1")]
+impl ManagerService {
+    /// Start (or keep) peer discovery on behalf of the caller.
+    async fn discover_peers(&self, #[zbus(header)] header: Header<'_>) -> fdo::Result<()> {
+        answer(self.channel.discover_peers()).await?;
+        if let Some(sender) = header.sender() {
+            self.clients().insert(sender.to_string());
+        }
+        Ok(())
+    }
+
+    /// Drop the caller's interest in discovery; it stops when no client wants it.
+    async fn stop_discovery(&self, #[zbus(header)] header: Header<'_>) -> fdo::Result<()> {
+        let last = {
+            let mut clients = self.clients();
+            if let Some(sender) = header.sender() {
+                clients.remove(sender.as_str());
+            }
+            clients.is_empty()
+        };
+        if last {
+            answer(self.channel.stop_discovery()).await?;
+        }
+        Ok(())
+    }
+
+    /// Connect to a found peer with push-button WPS.
+    async fn connect(&self, address: &str) -> fdo::Result<()> {
+        let address = parse_address(address)?;
+        answer(self.channel.connect(address)).await?;
+        Ok(())
+    }
+
+    /// Connect with `method` ("pbc", "display", "keypad" or "label") and `pin`,
+    /// which may be empty for "display"; returns the generated PIN, or "".
+    async fn connect_with_pin(
+        &self,
+        address: &str,
+        method: &str,
+        pin: &str,
+    ) -> fdo::Result<String> {
+        let device_address = parse_address(address)?;
+        let wps_method = match method {
+            "pbc" => WpsMethod::Pbc,
+            "display" => WpsMethod::PinDisplay,
+            "keypad" => WpsMethod::PinKeypad,
+            "label" => WpsMethod::PinLabel,
+            other => {
+                return Err(fdo::Error::InvalidArgs(format!(
+                    "unknown WPS method {other}"
+                )));
+            }
+        };
+        let config = ConnectConfig {
+            device_address,
+            wps_method,
+            pin: (!pin.is_empty()).then(|| pin.to_string()),
+        };
+        let generated = answer(self.channel.connect_with_config(config)).await?;
+        Ok(generated.unwrap_or_default())
+    }
+
+    async fn create_group(&self) -> fdo::Result<()> {
+        answer(self.channel.create_group()).await
+    }
+
+    async fn remove_group(&self) -> fdo::Result<()> {
+        answer(self.channel.remove_group()).await
+    }
+
+    async fn invite(&self, address: &str) -> fdo::Result<()> {
+        answer(self.channel.invite(parse_address(address)?)).await
+    }
+
+    async fn reject_peer(&self, address: &str) -> fdo::Result<()> {
+        answer(self.channel.reject_peer(parse_address(address)?)).await
+    }
+
+    #[zbus(property)]
+    async fn peers(&self) -> Vec<Dict> {
+        self.channel
+            .peers_snapshot()
+            .iter()
+            .map(peer_dict)
+            .collect()
+    }
+
+    #[zbus(property)]
+    async fn groups(&self) -> Vec<Dict> {
+        self.channel
+            .groups_snapshot()
+            .iter()
+            .map(group_dict)
+            .collect()
+    }
+
+    /// Whether some client has discovery running.
+    #[zbus(property)]
+    async fn discovering(&self) -> bool {
+        !self.clients().is_empty()
+    }
+
+    #[zbus(signal)]
+    async fn peer_found(ctxt: &SignalContext<'_>, peer: Dict) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn peer_lost(ctxt: &SignalContext<'_>, address: &str) -> zbus::Result<()>;
+
+    /// A peer asked to connect; accept with `Connect` or refuse with `RejectPeer`.
+    #[zbus(signal)]
+    async fn connection_requested(ctxt: &SignalContext<'_>, address: &str) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn connection_failed(
+        ctxt: &SignalContext<'_>,
+        address: &str,
+        status: &str,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn group_started(ctxt: &SignalContext<'_>, group: Dict) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn group_finished(ctxt: &SignalContext<'_>, group: Dict) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn peer_ip_resolved(
+        ctxt: &SignalContext<'_>,
+        address: &str,
+        ip: &str,
+    ) -> zbus::Result<()>;
+}
+
+/// Wait for a queued command's answer, as a D-Bus error on failure.
+async fn answer<T>(
+    queued: impl Future<Output = Result<oneshot::Receiver<Result<T, P2pError>>, P2pError>>,
+) -> fdo::Result<T> {
+    let result = match queued.await {
+        Ok(receiver) => receiver
+            .await
+            .unwrap_or_else(|_| Err(P2pError::ChannelClosed("manager".to_string()))),
+        Err(err) => Err(err),
+    };
+    result.map_err(dbus_error)
+}
+
+fn dbus_error(err: P2pError) -> fdo::Error {
+    let message = err.to_string();
+    match err {
+        P2pError::InvalidArgs(_) | P2pError::InvalidMacAddress(_) | P2pError::InvalidConfig(_) => {
+            fdo::Error::InvalidArgs(message)
+        }
+        P2pError::Unsupported(_) | P2pError::UnsupportedPlatform(_) => {
+            fdo::Error::NotSupported(message)
+        }
+        P2pError::PermissionDenied(_) => fdo::Error::AccessDenied(message),
+        P2pError::Busy(_) => fdo::Error::LimitsExceeded(message),
+        P2pError::Timeout(_) => fdo::Error::TimedOut(message),
+        _ => fdo::Error::Failed(message),
+    }
+}
+
+fn parse_address(address: &str) -> fdo::Result<MacAddress> {
+    address
+        .parse()
+        .map_err(|err: P2pError| fdo::Error::InvalidArgs(err.to_string()))
+}
+
+fn peer_dict(device: &P2pDevice) -> Dict {
+    let mut dict = Dict::new();
+    dict.insert("address", Value::from(device.mac_address.to_string()));
+    let status = format!("{:?}", device.status).to_lowercase();
+    dict.insert("status", Value::from(status));
+    if let Some(name) = &device.device_name {
+        dict.insert("name", Value::from(name.clone()));
+    }
+    if let Some(primary_type) = &device.primary_type {
+        dict.insert("primary_type", Value::from(primary_type.clone()));
+    }
+    if let Some(signal) = device.signal_level {
+        dict.insert("signal", Value::from(signal));
+    }
+    dict
+}
+
+fn group_dict(group: &P2pGroup) -> Dict {
+    let mut dict = Dict::new();
+    dict.insert("interface", Value::from(group.interface.clone()));
+    let role = format!("{:?}", group.role).to_lowercase();
+    dict.insert("role", Value::from(role));
+    if let Some(allocation) = group.ip_allocation {
+        dict.insert("ip", Value::from(allocation.ip.to_string()));
+        dict.insert("prefix", Value::from(allocation.prefix_len()));
+        dict.insert("go_ip", Value::from(allocation.go_ip.to_string()));
+    }
+    if let Some(peer) = group.peer {
+        dict.insert("peer", Value::from(peer.to_string()));
+    }
+    dict
+}
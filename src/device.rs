@@ -1,9 +1,192 @@
-#[derive(Debug, Clone)]
+use crate::config::WpsMethod;
+use crate::mac::MacAddress;
+use crate::wfd::WfdDeviceInfo;
+
+/// WPS config method bits as reported in a peer's `config_method` property.
+pub mod wps_config {
+    pub const LABEL: u16 = 0x0004;
+    pub const DISPLAY: u16 = 0x0008;
+    pub const PUSH_BUTTON: u16 = 0x0080;
+    pub const KEYPAD: u16 = 0x0100;
+    /// P2PS default config method: provisioning without a PIN.
+    pub const P2PS: u16 = 0x1000;
+}
+
+/// P2P Device Capability bitmap bits.
+pub mod device_capability {
+    pub const SERVICE_DISCOVERY: u8 = 0x01;
+    pub const CLIENT_DISCOVERABILITY: u8 = 0x02;
+    pub const CONCURRENT_OPERATION: u8 = 0x04;
+    pub const INFRASTRUCTURE_MANAGED: u8 = 0x08;
+    pub const DEVICE_LIMIT: u8 = 0x10;
+    pub const INVITATION_PROCEDURE: u8 = 0x20;
+}
+
+/// P2P Group Capability bitmap bits.
+pub mod group_capability {
+    pub const GROUP_OWNER: u8 = 0x01;
+    pub const PERSISTENT_GROUP: u8 = 0x02;
+    pub const GROUP_LIMIT: u8 = 0x04;
+    pub const INTRA_BSS_DISTRIBUTION: u8 = 0x08;
+    pub const CROSS_CONNECTION: u8 = 0x10;
+    pub const PERSISTENT_RECONNECT: u8 = 0x20;
+    pub const GROUP_FORMATION: u8 = 0x40;
+}
+
+/// Connection state of a peer from our point of view, like Android's `WifiP2pDevice.status`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
+pub enum DeviceStatus {
+    /// Seen during discovery and not engaged with us.
+    #[default]
+    Available,
+    /// A connect request to the peer is in progress.
+    Invited,
+    /// Connected to us through a P2P group.
+    Connected,
+    /// The last connect attempt failed.
+    Failed,
+    /// No longer reported by the supplicant.
+    Unavailable,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
 pub struct P2pDevice {
     /// Device MAC address (e.g. "02:11:22:33:44:55").
-    pub mac_address: String,
+    pub mac_address: MacAddress,
     /// Optional device name reported by P2P.
     pub device_name: Option<String>,
     /// Optional primary device type (e.g. "1-0050F204-1").
     pub primary_type: Option<String>,
+    /// Secondary device types in the same format as `primary_type`.
+    pub secondary_types: Vec<String>,
+    /// P2P Device Capability bitmap (see `device_capability`).
+    pub device_capability: u8,
+    /// P2P Group Capability bitmap (see `group_capability`).
+    pub group_capability: u8,
+    /// Supported WPS config methods bitmap (see `wps_config`).
+    pub wps_config_methods: u16,
+    /// Manufacturer string from the WPS attributes.
+    pub manufacturer: Option<String>,
+    /// Model name from the WPS attributes.
+    pub model_name: Option<String>,
+    /// Model number from the WPS attributes.
+    pub model_number: Option<String>,
+    /// Serial number from the WPS attributes.
+    pub serial_number: Option<String>,
+    /// Signal level of the last received frame, in dBm.
+    pub signal_level: Option<i32>,
+    /// WFD Device Information, if the peer does Wi-Fi Display.
+    pub wfd: Option<WfdDeviceInfo>,
+    /// Connection state tracked by the manager.
+    pub status: DeviceStatus,
+}
+
+impl P2pDevice {
+    /// A device with only its address known; the rest is filled in from peer properties.
+    pub fn new(mac_address: MacAddress) -> Self {
+        Self {
+            mac_address,
+            device_name: None,
+            primary_type: None,
+            secondary_types: Vec::new(),
+            device_capability: 0,
+            group_capability: 0,
+            wps_config_methods: 0,
+            manufacturer: None,
+            model_name: None,
+            model_number: None,
+            serial_number: None,
+            signal_level: None,
+            wfd: None,
+            status: DeviceStatus::Available,
+        }
+    }
+
+    /// The peer is currently acting as a group owner.
+    pub fn is_group_owner(&self) -> bool {
+        self.group_capability & group_capability::GROUP_OWNER != 0
+    }
+
+    pub fn is_service_discovery_capable(&self) -> bool {
+        self.device_capability & device_capability::SERVICE_DISCOVERY != 0
+    }
+
+    pub fn is_invitation_capable(&self) -> bool {
+        self.device_capability & device_capability::INVITATION_PROCEDURE != 0
+    }
+
+    /// Whether connecting with `method` (from our side) can work with this peer.
+    /// Our `PinDisplay` needs the peer to have a keypad and vice versa.
+    pub fn supports_wps_method(&self, method: WpsMethod) -> bool {
+        let required = match method {
+            WpsMethod::Pbc => wps_config::PUSH_BUTTON,
+            WpsMethod::PinDisplay => wps_config::KEYPAD,
+            WpsMethod::PinKeypad => wps_config::DISPLAY,
+            WpsMethod::PinLabel => wps_config::LABEL,
+        };
+        self.wps_config_methods & required != 0
+    }
+
+    /// Whether `device_type` ("category-OUI-subcategory") is the peer's primary
+    /// or one of its secondary types, as the supplicant matches requested types.
+    pub fn has_device_type(&self, device_type: &str) -> bool {
+        let Some(wanted) = parse_device_type(device_type) else {
+            return false;
+        };
+        self.primary_type
+            .iter()
+            .chain(&self.secondary_types)
+            .any(|own| parse_device_type(own) == Some(wanted))
+    }
+}
+
+/// Our own P2P device as peers see it, like the `WifiP2pDevice` Android sends
+/// with `WIFI_P2P_THIS_DEVICE_CHANGED_ACTION`. Fields the platform does not
+/// report are left empty.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct LocalDeviceInfo {
+    /// P2P device address; changes when the supplicant randomizes it.
+    pub device_address: Option<MacAddress>,
+    /// Name advertised in probe responses, set with `WifiP2pChannel::set_device_name`.
+    pub device_name: Option<String>,
+    /// Primary device type in the same format as `P2pDevice::primary_type`.
+    pub primary_type: Option<String>,
+    /// WPS config methods we offer, e.g. `push_button` or `keypad`.
+    pub wps_methods: Vec<String>,
+}
+
+/// Parse "category-OUI-subcategory" back into the 8-byte WPS device type; the
+/// OUI is read as hex in either case.
+pub(crate) fn parse_device_type(text: &str) -> Option<[u8; 8]> {
+    let mut parts = text.split('-');
+    let category: u16 = parts.next()?.parse().ok()?;
+    let oui = parts.next()?;
+    let subcategory: u16 = parts.next()?.parse().ok()?;
+    if parts.next().is_some() || oui.len() != 8 {
+        return None;
+    }
+    let oui = u32::from_str_radix(oui, 16).ok()?;
+    let mut bytes = [0; 8];
+    bytes[..2].copy_from_slice(&category.to_be_bytes());
+    bytes[2..6].copy_from_slice(&oui.to_be_bytes());
+    bytes[6..].copy_from_slice(&subcategory.to_be_bytes());
+    Some(bytes)
+}
+
+/// Format a raw 8-byte WPS device type as "category-OUI-subcategory".
+#[cfg(target_os = "linux")]
+pub(crate) fn format_device_type(bytes: &[u8]) -> Option<String> {
+    let bytes: &[u8; 8] = bytes.try_into().ok()?;
+    let category = u16::from_be_bytes([bytes[0], bytes[1]]);
+    let subcategory = u16::from_be_bytes([bytes[6], bytes[7]]);
+    Some(format!(
+        "{category}-{:02X}{:02X}{:02X}{:02X}-{subcategory}",
+        bytes[2], bytes[3], bytes[4], bytes[5]
+    ))
 }
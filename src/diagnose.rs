@@ -0,0 +1,367 @@
+//! Environment checks for when nothing works: the system bus, wpa_supplicant
+//! and its D-Bus policy, the interface, rfkill and NetworkManager, each turned
+//! into a finding with what to do about it. `wifip2p doctor` prints them.
+
+use std::fs;
+use std::path::Path;
+
+use zbus::fdo::{DBusProxy, IntrospectableProxy, PropertiesProxy};
+use zbus::names::{BusName, InterfaceName};
+use zbus::zvariant::OwnedObjectPath;
+use zbus::{Connection, Proxy};
+
+use crate::error::P2pError;
+use crate::rfkill::{self, RadioState};
+
+const SUPPLICANT_SERVICE: &str = "fi.w1.wpa_supplicant1";
+const SUPPLICANT_PATH: &str = "/fi/w1/wpa_supplicant1";
+const P2P_DEVICE_INTERFACE: &str = "fi.w1.wpa_supplicant1.Interface.P2PDevice";
+const NM_SERVICE: &str = "org.freedesktop.NetworkManager";
+const NM_PATH: &str = "/org/freedesktop/NetworkManager";
+
+/// How a check came out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Severity {
+    Ok,
+    /// Works, but is likely to cause trouble.
+    Warning,
+    /// Wi-Fi Direct cannot work until this is fixed.
+    Error,
+}
+
+/// The outcome of one check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Finding {
+    /// What was checked, e.g. `"system bus"`.
+    pub check: String,
+    pub severity: Severity,
+    /// What was found.
+    pub summary: String,
+    /// What to do about it, for warnings and errors.
+    pub hint: Option<String>,
+}
+
+impl Finding {
+    fn ok(check: &str, summary: impl Into<String>) -> Self {
+        Self {
+            check: check.to_string(),
+            severity: Severity::Ok,
+            summary: summary.into(),
+            hint: None,
+        }
+    }
+
+    fn warning(check: &str, summary: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            hint: Some(hint.into()),
+            ..Self::ok(check, summary)
+        }
+    }
+
+    fn error(check: &str, summary: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            hint: Some(hint.into()),
+            ..Self::ok(check, summary)
+        }
+    }
+}
+
+/// Every finding, in the order the checks ran. Checks that depend on a failed
+/// one are left out.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Diagnosis {
+    pub findings: Vec<Finding>,
+}
+
+impl Diagnosis {
+    /// The worst severity found; `Ok` when there are no findings.
+    pub fn severity(&self) -> Severity {
+        self.findings
+            .iter()
+            .map(|finding| finding.severity)
+            .max()
+            .unwrap_or(Severity::Ok)
+    }
+
+    /// No check found an error.
+    pub fn is_healthy(&self) -> bool {
+        self.severity() < Severity::Error
+    }
+}
+
+/// Check whether Wi-Fi Direct can work on `interface_name` (e.g. "wlan0").
+/// Never fails: problems, including an unreachable bus, become findings.
+pub async fn diagnose(interface_name: &str) -> Diagnosis {
+    let mut diagnosis = Diagnosis::default();
+    let findings = &mut diagnosis.findings;
+    findings.push(check_netdev(interface_name));
+    findings.extend(check_rfkill(interface_name));
+    let connection = match Connection::system().await {
+        Ok(connection) => {
+            findings.push(Finding::ok("system bus", "reachable"));
+            connection
+        }
+        Err(err) => {
+            findings.push(Finding::error(
+                "system bus",
+                format!("cannot connect: {err}"),
+                "start dbus, or point DBUS_SYSTEM_BUS_ADDRESS at the system bus socket",
+            ));
+            return diagnosis;
+        }
+    };
+    if check_supplicant(&connection, findings).await {
+        check_supplicant_interface(&connection, interface_name, findings).await;
+    }
+    findings.push(check_network_manager(&connection, interface_name).await);
+    diagnosis
+}
+
+fn check_netdev(interface_name: &str) -> Finding {
+    if Path::new("/sys/class/net").join(interface_name).exists() {
+        return Finding::ok("interface", format!("{interface_name} exists"));
+    }
+    let available: Vec<String> = fs::read_dir("/sys/class/net")
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.path().join("wireless").exists())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect();
+    let hint = if available.is_empty() {
+        "no wireless interfaces found; check that the driver is loaded".to_string()
+    } else {
+        format!("wireless interfaces here: {}", available.join(", "))
+    };
+    Finding::error(
+        "interface",
+        format!("{interface_name} does not exist"),
+        hint,
+    )
+}
+
+/// rfkill switches of the interface's radio, or of every Wi-Fi radio when the
+/// interface cannot be tied to one.
+fn check_rfkill(interface_name: &str) -> Option<Finding> {
+    let finding = match rfkill::radio_state(interface_name)? {
+        RadioState::HardBlocked => Finding::error(
+            "rfkill",
+            "Wi-Fi is hard-blocked",
+            "turn the radio on with the hardware switch or key, or in the firmware setup",
+        ),
+        RadioState::SoftBlocked => Finding::error(
+            "rfkill",
+            "Wi-Fi is soft-blocked",
+            "run `rfkill unblock wifi`",
+        ),
+        RadioState::Unblocked => Finding::ok("rfkill", "not blocked"),
+    };
+    Some(finding)
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    fs::read_to_string(path)
+        .ok()
+        .map(|text| text.trim().to_string())
+}
+
+/// Whether wpa_supplicant is on the bus and lets us in; pushes the findings.
+async fn check_supplicant(connection: &Connection, findings: &mut Vec<Finding>) -> bool {
+    match has_owner(connection, SUPPLICANT_SERVICE).await {
+        Ok(true) => findings.push(Finding::ok(
+            "wpa_supplicant",
+            format!("{SUPPLICANT_SERVICE} is on the system bus"),
+        )),
+        Ok(false) if process_running("wpa_supplicant") => {
+            findings.push(Finding::error(
+                "wpa_supplicant",
+                "running, but without its D-Bus interface",
+                "restart it with -u (e.g. the distribution's wpa_supplicant.service)",
+            ));
+            return false;
+        }
+        Ok(false) => {
+            findings.push(Finding::error(
+                "wpa_supplicant",
+                "not running",
+                "start it with D-Bus enabled: `systemctl start wpa_supplicant` or `wpa_supplicant -u`",
+            ));
+            return false;
+        }
+        Err(err) => {
+            findings.push(Finding::error(
+                "wpa_supplicant",
+                format!("cannot ask the bus: {err}"),
+                "check the system bus with `busctl list`",
+            ));
+            return false;
+        }
+    }
+    // Reading a property is the cheapest call the default policy refuses to
+    // users outside the netdev (or wheel) group.
+    let capabilities = async {
+        let proxy = PropertiesProxy::builder(connection)
+            .destination(SUPPLICANT_SERVICE)?
+            .path(SUPPLICANT_PATH)?
+            .build()
+            .await?;
+        let interface = InterfaceName::from_static_str_unchecked(SUPPLICANT_SERVICE);
+        let value = proxy.get(interface, "Capabilities").await?;
+        Ok::<_, P2pError>(Vec::<String>::try_from(value).unwrap_or_default())
+    };
+    match capabilities.await {
+        Ok(capabilities) => {
+            findings.push(Finding::ok("D-Bus policy", "calls are permitted"));
+            if capabilities.iter().any(|capability| capability == "p2p") {
+                findings.push(Finding::ok(
+                    "P2P support",
+                    "wpa_supplicant was built with P2P",
+                ));
+            } else {
+                findings.push(Finding::error(
+                    "P2P support",
+                    "wpa_supplicant was built without P2P",
+                    "install a build with CONFIG_P2P=y (most distribution packages have it)",
+                ));
+            }
+            true
+        }
+        Err(P2pError::PermissionDenied(message)) => {
+            findings.push(Finding::error(
+                "D-Bus policy",
+                format!("calls are denied: {message}"),
+                "run as root, join the group the policy in /usr/share/dbus-1/system.d/wpa_supplicant.conf allows, or add a <policy> for fi.w1.wpa_supplicant1",
+            ));
+            false
+        }
+        Err(err) => {
+            findings.push(Finding::error(
+                "D-Bus policy",
+                format!("reading wpa_supplicant's capabilities failed: {err}"),
+                "check `journalctl -u wpa_supplicant` for errors",
+            ));
+            false
+        }
+    }
+}
+
+async fn check_supplicant_interface(
+    connection: &Connection,
+    interface_name: &str,
+    findings: &mut Vec<Finding>,
+) {
+    let path = async {
+        let proxy = Proxy::new(
+            connection,
+            SUPPLICANT_SERVICE,
+            SUPPLICANT_PATH,
+            SUPPLICANT_SERVICE,
+        )
+        .await?;
+        let path: OwnedObjectPath = proxy.call("GetInterface", &(interface_name,)).await?;
+        Ok::<_, P2pError>(path)
+    };
+    let path = match path.await {
+        Ok(path) => path,
+        Err(P2pError::InterfaceUnknown(_)) => {
+            findings.push(Finding::warning(
+                "supplicant interface",
+                format!("wpa_supplicant does not manage {interface_name} yet"),
+                format!(
+                    "the manager registers it with CreateInterface on startup; start wpa_supplicant with -i{interface_name} to keep it registered"
+                ),
+            ));
+            return;
+        }
+        Err(err) => {
+            findings.push(Finding::error(
+                "supplicant interface",
+                format!("looking up {interface_name} failed: {err}"),
+                "check `journalctl -u wpa_supplicant` for errors",
+            ));
+            return;
+        }
+    };
+    findings.push(Finding::ok(
+        "supplicant interface",
+        format!("{interface_name} is {}", path.as_str()),
+    ));
+    let xml = async {
+        let proxy = IntrospectableProxy::builder(connection)
+            .destination(SUPPLICANT_SERVICE)?
+            .path(path)?
+            .build()
+            .await?;
+        Ok::<_, P2pError>(proxy.introspect().await?)
+    };
+    match xml.await {
+        Ok(xml) if xml.contains(P2P_DEVICE_INTERFACE) => {
+            findings.push(Finding::ok("P2P device", "the interface exposes P2PDevice"));
+        }
+        Ok(_) => findings.push(Finding::error(
+            "P2P device",
+            format!("{interface_name} has no P2PDevice interface"),
+            "the driver does not support P2P on this interface, or p2p_disabled=1 is set in wpa_supplicant.conf",
+        )),
+        Err(err) => findings.push(Finding::warning(
+            "P2P device",
+            format!("could not introspect the interface: {err}"),
+            "check that the D-Bus policy allows Introspect on wpa_supplicant",
+        )),
+    }
+}
+
+async fn check_network_manager(connection: &Connection, interface_name: &str) -> Finding {
+    const CHECK: &str = "NetworkManager";
+    match has_owner(connection, NM_SERVICE).await {
+        Ok(true) => {}
+        Ok(false) => return Finding::ok(CHECK, "not running"),
+        Err(err) => return Finding::ok(CHECK, format!("unknown: {err}")),
+    }
+    let managed = async {
+        let proxy = Proxy::new(connection, NM_SERVICE, NM_PATH, NM_SERVICE).await?;
+        let device: OwnedObjectPath = proxy.call("GetDeviceByIpIface", &(interface_name,)).await?;
+        let device = Proxy::new(
+            connection,
+            NM_SERVICE,
+            device,
+            "org.freedesktop.NetworkManager.Device",
+        )
+        .await?;
+        Ok::<_, P2pError>(device.get_property::<bool>("Managed").await?)
+    };
+    match managed.await {
+        Ok(true) => Finding::warning(
+            CHECK,
+            format!("manages {interface_name} and may reset it during discovery"),
+            format!(
+                "run `nmcli device set {interface_name} managed no` while using Wi-Fi Direct, or build the manager with `unmanage_in_network_manager`"
+            ),
+        ),
+        Ok(false) => Finding::ok(CHECK, format!("running, {interface_name} is unmanaged")),
+        Err(err) => Finding::warning(
+            CHECK,
+            format!("running; could not tell whether it manages {interface_name}: {err}"),
+            "check with `nmcli device status`",
+        ),
+    }
+}
+
+async fn has_owner(connection: &Connection, name: &str) -> Result<bool, P2pError> {
+    let proxy = DBusProxy::new(connection).await?;
+    let name = BusName::try_from(name).map_err(zbus::Error::from)?;
+    Ok(proxy.name_has_owner(name).await?)
+}
+
+fn process_running(name: &str) -> bool {
+    fs::read_dir("/proc")
+        .into_iter()
+        .flatten()
+        .flatten()
+        .any(|entry| read_trimmed(&entry.path().join("comm")).as_deref() == Some(name))
+}
@@ -1,10 +1,16 @@
+use std::time::Duration;
+
 use thiserror::Error;
 
-#[derive(Debug, Error)]
+use crate::status::P2pStatusCode;
+
+#[derive(Debug, Clone, Error)]
+// Kotlin and Swift get one exception subclass per variant, carrying the message.
+#[cfg_attr(feature = "uniffi", derive(uniffi::Error), uniffi(flat_error))]
 pub enum P2pError {
-    /// A transport or method call error from the D-Bus layer.
+    /// A transport or method call error from the D-Bus layer not mapped below.
     #[error("D-Bus error: {0}")]
-    DBus(#[from] zbus::Error),
+    DBus(#[source] zbus::Error),
     /// Serialization/deserialization failures for D-Bus values.
     #[error("D-Bus serialization error: {0}")]
     ZVariant(#[from] zbus::zvariant::Error),
@@ -14,7 +20,270 @@ pub enum P2pError {
     /// Invalid or empty interface name provided by the caller.
     #[error("invalid interface name: {0}")]
     InvalidInterface(String),
+    /// A MAC address string or byte array that is not a valid 48-bit address.
+    #[error("invalid MAC address: {0}")]
+    InvalidMacAddress(String),
+    /// A connect or manager configuration is inconsistent (e.g. a PIN method without a PIN).
+    #[error("invalid config: {0}")]
+    InvalidConfig(String),
+    /// A command did not complete within its deadline; the backend call was cancelled.
+    #[error("timed out after {0:?}")]
+    Timeout(Duration),
+    /// A later `stop_discovery` or `cancel_connect` overtook the command before it ran.
+    #[error("cancelled: {0}")]
+    Cancelled(String),
+    /// The peer or supplicant reported a non-success P2P status code.
+    #[error("P2P status: {0}")]
+    Status(P2pStatusCode),
+    /// wpa_supplicant does not manage the requested interface (InterfaceUnknown).
+    #[error("interface unknown to wpa_supplicant: {0}")]
+    InterfaceUnknown(String),
+    /// The operation needs an association or group that does not exist (NotConnected).
+    #[error("not connected: {0}")]
+    NotConnected(String),
+    /// The supplicant rejected the call's arguments (InvalidArgs).
+    #[error("invalid arguments: {0}")]
+    InvalidArgs(String),
+    /// The supplicant or radio is busy with another operation; retrying later may succeed.
+    #[error("busy: {0}")]
+    Busy(String),
+    /// The D-Bus policy does not allow this caller to talk to wpa_supplicant.
+    #[error("permission denied: {0}")]
+    PermissionDenied(String),
+    /// The supplicant build or driver does not support the operation.
+    #[error("unsupported: {0}")]
+    Unsupported(String),
+    /// wpa_supplicant is not running or not exposing its D-Bus service.
+    #[error("wpa_supplicant unavailable: {0}")]
+    SupplicantUnavailable(String),
+    /// rfkill blocks the radio, so nothing can be sent until it is unblocked.
+    #[error("radio blocked: {0}")]
+    RadioBlocked(String),
+    /// A named configuration blob already exists or is unknown (Blob*).
+    #[error("blob error: {0}")]
+    Blob(String),
+    /// No Wi-Fi Direct backend exists for this target (see `NoopBackend`).
+    #[error("unsupported platform: {0}")]
+    UnsupportedPlatform(String),
     /// Other backend-specific errors not mapped above.
     #[error("backend error: {0}")]
     Backend(String),
 }
+
+impl From<zbus::Error> for P2pError {
+    fn from(err: zbus::Error) -> Self {
+        // Decode well-known error names so callers can branch and retry instead of
+        // string-matching opaque D-Bus errors.
+        match &err {
+            zbus::Error::MethodError(name, message, _) => {
+                let detail = message.clone().unwrap_or_else(|| name.to_string());
+                Self::from_error_name(name.as_str(), detail).unwrap_or(Self::DBus(err))
+            }
+            zbus::Error::FDO(fdo) => match fdo.as_ref() {
+                zbus::fdo::Error::AccessDenied(msg) | zbus::fdo::Error::AuthFailed(msg) => {
+                    Self::PermissionDenied(msg.clone())
+                }
+                zbus::fdo::Error::ServiceUnknown(msg) | zbus::fdo::Error::NameHasNoOwner(msg) => {
+                    Self::SupplicantUnavailable(msg.clone())
+                }
+                zbus::fdo::Error::NotSupported(msg)
+                | zbus::fdo::Error::UnknownMethod(msg)
+                | zbus::fdo::Error::UnknownProperty(msg)
+                | zbus::fdo::Error::UnknownInterface(msg) => Self::Unsupported(msg.clone()),
+                zbus::fdo::Error::InvalidArgs(msg) => Self::InvalidArgs(msg.clone()),
+                zbus::fdo::Error::LimitsExceeded(msg) => Self::Busy(msg.clone()),
+                _ => Self::DBus(err),
+            },
+            _ => Self::DBus(err),
+        }
+    }
+}
+
+impl From<zbus::fdo::Error> for P2pError {
+    fn from(err: zbus::fdo::Error) -> Self {
+        // Standard interfaces (ObjectManager, Properties) report fdo errors; decode alike.
+        Self::from(zbus::Error::from(err))
+    }
+}
+
+#[cfg(target_os = "windows")]
+impl From<windows::core::Error> for P2pError {
+    fn from(err: windows::core::Error) -> Self {
+        // WinRT reports HRESULTs; map the few with a matching variant.
+        const E_ACCESSDENIED: i32 = 0x8007_0005_u32 as i32;
+        const E_NOTIMPL: i32 = 0x8000_4001_u32 as i32;
+        const ERROR_DEVICE_NOT_AVAILABLE: i32 = 0x8007_10DF_u32 as i32;
+        let message = err.message();
+        match err.code().0 {
+            E_ACCESSDENIED => Self::PermissionDenied(message),
+            E_NOTIMPL => Self::Unsupported(message),
+            ERROR_DEVICE_NOT_AVAILABLE => Self::Busy(message),
+            _ => Self::Backend(message),
+        }
+    }
+}
+
+// zbus errors cannot round-trip, so errors serialize one way as `{ kind, message }`.
+#[cfg(feature = "serde")]
+impl serde::Serialize for P2pError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut state = serializer.serialize_struct("P2pError", 2)?;
+        state.serialize_field("kind", self.kind())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.end()
+    }
+}
+
+impl P2pError {
+    /// Stable name of the variant, e.g. `"Timeout"`, for logs and IPC.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::DBus(_) => "DBus",
+            Self::ZVariant(_) => "ZVariant",
+            Self::ChannelClosed(_) => "ChannelClosed",
+            Self::InvalidInterface(_) => "InvalidInterface",
+            Self::InvalidMacAddress(_) => "InvalidMacAddress",
+            Self::InvalidConfig(_) => "InvalidConfig",
+            Self::Timeout(_) => "Timeout",
+            Self::Cancelled(_) => "Cancelled",
+            Self::Status(_) => "Status",
+            Self::InterfaceUnknown(_) => "InterfaceUnknown",
+            Self::NotConnected(_) => "NotConnected",
+            Self::InvalidArgs(_) => "InvalidArgs",
+            Self::Busy(_) => "Busy",
+            Self::PermissionDenied(_) => "PermissionDenied",
+            Self::Unsupported(_) => "Unsupported",
+            Self::SupplicantUnavailable(_) => "SupplicantUnavailable",
+            Self::RadioBlocked(_) => "RadioBlocked",
+            Self::Blob(_) => "Blob",
+            Self::UnsupportedPlatform(_) => "UnsupportedPlatform",
+            Self::Backend(_) => "Backend",
+        }
+    }
+
+    fn from_error_name(name: &str, detail: String) -> Option<Self> {
+        let error = match name {
+            "fi.w1.wpa_supplicant1.InterfaceUnknown" => Self::InterfaceUnknown(detail),
+            "fi.w1.wpa_supplicant1.NotConnected" => Self::NotConnected(detail),
+            "fi.w1.wpa_supplicant1.InvalidArgs" | "org.freedesktop.DBus.Error.InvalidArgs" => {
+                Self::InvalidArgs(detail)
+            }
+            "fi.w1.wpa_supplicant1.InterfaceDisabled"
+            | "org.freedesktop.DBus.Error.LimitsExceeded" => Self::Busy(detail),
+            "fi.w1.wpa_supplicant1.BlobExists" | "fi.w1.wpa_supplicant1.BlobUnknown" => {
+                Self::Blob(detail)
+            }
+            "org.freedesktop.DBus.Error.AccessDenied" | "org.freedesktop.DBus.Error.AuthFailed" => {
+                Self::PermissionDenied(detail)
+            }
+            "org.freedesktop.DBus.Error.ServiceUnknown"
+            | "org.freedesktop.DBus.Error.NameHasNoOwner" => Self::SupplicantUnavailable(detail),
+            "org.freedesktop.DBus.Error.NotSupported"
+            | "org.freedesktop.DBus.Error.UnknownMethod"
+            | "org.freedesktop.DBus.Error.UnknownProperty"
+            | "org.freedesktop.DBus.Error.UnknownInterface" => Self::Unsupported(detail),
+            // P2P methods report transient conditions (scan in progress, channel in use)
+            // as UnknownError with a free-form message.
+            "fi.w1.wpa_supplicant1.UnknownError" if detail.to_lowercase().contains("busy") => {
+                Self::Busy(detail)
+            }
+            _ => return None,
+        };
+        Some(error)
+    }
+
+    /// Whether retrying the same operation later has a reasonable chance to succeed.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, Self::Busy(_) | Self::Timeout(_))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn supplicant_error_names_map_to_variants() {
+        let cases = [
+            (
+                "fi.w1.wpa_supplicant1.InterfaceDisabled",
+                "disabled",
+                "Busy",
+            ),
+            (
+                "org.freedesktop.DBus.Error.LimitsExceeded",
+                "limits",
+                "Busy",
+            ),
+            ("fi.w1.wpa_supplicant1.UnknownError", "Device Busy", "Busy"),
+            (
+                "fi.w1.wpa_supplicant1.InterfaceUnknown",
+                "wlan9",
+                "InterfaceUnknown",
+            ),
+            ("fi.w1.wpa_supplicant1.InvalidArgs", "bad", "InvalidArgs"),
+            (
+                "org.freedesktop.DBus.Error.AccessDenied",
+                "denied",
+                "PermissionDenied",
+            ),
+            (
+                "org.freedesktop.DBus.Error.ServiceUnknown",
+                "gone",
+                "SupplicantUnavailable",
+            ),
+            (
+                "org.freedesktop.DBus.Error.NotSupported",
+                "no",
+                "Unsupported",
+            ),
+            (
+                "org.freedesktop.DBus.Error.UnknownMethod",
+                "no",
+                "Unsupported",
+            ),
+            ("fi.w1.wpa_supplicant1.BlobUnknown", "blob", "Blob"),
+        ];
+        for (name, detail, kind) in cases {
+            let error = P2pError::from_error_name(name, detail.to_string());
+            assert_eq!(error.as_ref().map(P2pError::kind), Some(kind), "{name}");
+        }
+    }
+
+    #[test]
+    fn unknown_error_names_are_not_decoded() {
+        // Only a busy message makes an UnknownError transient.
+        let failed = "fi.w1.wpa_supplicant1.UnknownError";
+        assert!(P2pError::from_error_name(failed, "failed".to_string()).is_none());
+        let other = "org.example.Error.Whatever";
+        assert!(P2pError::from_error_name(other, "busy".to_string()).is_none());
+    }
+
+    #[test]
+    fn fdo_errors_decode_alike_or_fall_back_to_dbus() {
+        let cases = [
+            (
+                zbus::fdo::Error::AccessDenied("x".into()),
+                "PermissionDenied",
+            ),
+            (
+                zbus::fdo::Error::ServiceUnknown("x".into()),
+                "SupplicantUnavailable",
+            ),
+            (zbus::fdo::Error::NotSupported("x".into()), "Unsupported"),
+            (zbus::fdo::Error::UnknownMethod("x".into()), "Unsupported"),
+            (zbus::fdo::Error::LimitsExceeded("x".into()), "Busy"),
+            (zbus::fdo::Error::Failed("x".into()), "DBus"),
+        ];
+        for (error, kind) in cases {
+            let name = format!("{error:?}");
+            assert_eq!(P2pError::from(error).kind(), kind, "{name}");
+        }
+        let transport = P2pError::from(zbus::Error::InterfaceNotFound);
+        assert!(matches!(transport, P2pError::DBus(_)));
+        assert!(!transport.is_transient());
+        assert!(P2pError::Busy(String::new()).is_transient());
+    }
+}
@@ -29,6 +29,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         device.mac_address, device.device_name
                     );
                 }
+                P2pEvent::PeerStatusChanged(addr, status) => {
+                    println!("Peer {addr} is now {status:?}");
+                }
+                P2pEvent::PeerLost(addr) => {
+                    println!("Peer lost: {addr}");
+                }
+                other => {
+                    println!("Other event: {other:?}");
+                }
             }
         }
     });
@@ -0,0 +1,35 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+/// A boxed background future, as handed to `Executor::spawn`.
+pub type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/// The runtime primitives the manager needs: spawning its worker tasks and
+/// sleeping for command timeouts.
+///
+/// The channels used between the manager and its handles are runtime-agnostic,
+/// so implementing this trait is enough to drive the crate from async-std, smol
+/// or any other executor.
+pub trait Executor: Send + Sync + 'static {
+    /// Run `future` to completion in the background.
+    fn spawn(&self, future: BoxFuture);
+    /// A future that completes once `duration` has elapsed.
+    fn sleep(&self, duration: Duration) -> BoxFuture;
+}
+
+/// Spawns onto the ambient tokio runtime; the default executor.
+#[cfg(feature = "tokio")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioExecutor;
+
+#[cfg(feature = "tokio")]
+impl Executor for TokioExecutor {
+    fn spawn(&self, future: BoxFuture) {
+        tokio::spawn(future);
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
@@ -0,0 +1,654 @@
+//! C bindings over the blocking API, declared in `include/wifi_p2p.h`.
+//!
+//! Build the shared library with
+//! `cargo rustc --release --features ffi --crate-type cdylib`. Managers and
+//! channels are opaque handles. Every action comes in two forms: a blocking
+//! one that returns a `WIFI_P2P_*` status (details in `wifi_p2p_last_error`),
+//! and an `_async` one that returns at once and calls a `wifi_p2p_result_cb`
+//! exactly once from a runtime thread. Events reach registered callbacks as
+//! the JSON objects `wifip2p watch --json` prints.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char, c_void};
+use std::future::Future;
+use std::ptr;
+
+use tokio::sync::oneshot;
+
+use crate::blocking::{BlockingChannel, BlockingWifiP2pManager};
+use crate::channel::{EventKind, P2pEvent, WifiP2pChannel};
+use crate::config::{ConnectConfig, WpsMethod};
+use crate::error::P2pError;
+use crate::json::event_json;
+use crate::listener::{ListenerHandle, P2pEventListener};
+use crate::mac::MacAddress;
+
+pub const WIFI_P2P_OK: i32 = 0;
+pub const WIFI_P2P_ERR_DBUS: i32 = -1;
+pub const WIFI_P2P_ERR_ZVARIANT: i32 = -2;
+pub const WIFI_P2P_ERR_CHANNEL_CLOSED: i32 = -3;
+pub const WIFI_P2P_ERR_INVALID_INTERFACE: i32 = -4;
+pub const WIFI_P2P_ERR_INVALID_MAC_ADDRESS: i32 = -5;
+pub const WIFI_P2P_ERR_INVALID_CONFIG: i32 = -6;
+pub const WIFI_P2P_ERR_TIMEOUT: i32 = -7;
+pub const WIFI_P2P_ERR_CANCELLED: i32 = -8;
+pub const WIFI_P2P_ERR_STATUS: i32 = -9;
+pub const WIFI_P2P_ERR_INTERFACE_UNKNOWN: i32 = -10;
+pub const WIFI_P2P_ERR_NOT_CONNECTED: i32 = -11;
+pub const WIFI_P2P_ERR_INVALID_ARGS: i32 = -12;
+pub const WIFI_P2P_ERR_BUSY: i32 = -13;
+pub const WIFI_P2P_ERR_PERMISSION_DENIED: i32 = -14;
+pub const WIFI_P2P_ERR_UNSUPPORTED: i32 = -15;
+pub const WIFI_P2P_ERR_SUPPLICANT_UNAVAILABLE: i32 = -16;
+pub const WIFI_P2P_ERR_RADIO_BLOCKED: i32 = -17;
+pub const WIFI_P2P_ERR_BLOB: i32 = -18;
+pub const WIFI_P2P_ERR_UNSUPPORTED_PLATFORM: i32 = -19;
+pub const WIFI_P2P_ERR_BACKEND: i32 = -20;
+
+/// `wifi_p2p_result_cb(status, message, user_data)`: `message` is the error
+/// text on failure and otherwise `NULL`, except for connects where it is the
+/// PIN the supplicant generated, if any. It is only valid during the call.
+pub type ResultCallback = extern "C" fn(i32, *const c_char, *mut c_void);
+
+/// `wifi_p2p_event_cb(kind, json, user_data)`: `kind` is one `WIFI_P2P_EVENT_*`
+/// bit and `json` is only valid during the call.
+pub type EventCallback = extern "C" fn(u32, *const c_char, *mut c_void);
+
+/// Opaque `wifi_p2p_manager`.
+pub struct FfiManager {
+    inner: BlockingWifiP2pManager,
+}
+
+/// Opaque `wifi_p2p_channel`.
+pub struct FfiChannel {
+    inner: BlockingChannel,
+}
+
+/// Opaque `wifi_p2p_listener`.
+pub struct FfiListener {
+    handle: ListenerHandle,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// The C caller's `user_data`; it promises in the header that it can be used
+/// from the runtime's threads.
+#[derive(Clone, Copy)]
+struct UserData(*mut c_void);
+
+unsafe impl Send for UserData {}
+
+/// Start a runtime and the platform manager on `interface_name`, or return
+/// `NULL` and set the last error.
+///
+/// # Safety
+///
+/// `interface_name` must be `NULL` or a NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wifi_p2p_manager_new(interface_name: *const c_char) -> *mut FfiManager {
+    let manager =
+        unsafe { text(interface_name, "interface_name") }.and_then(BlockingWifiP2pManager::new);
+    match manager {
+        Ok(inner) => Box::into_raw(Box::new(FfiManager { inner })),
+        Err(err) => {
+            fail(err);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Shut the manager down (see `WifiP2pManager::shutdown`) and free it.
+/// Channels taken from it stop working but must still be freed.
+///
+/// # Safety
+///
+/// `manager` must be `NULL` or come from `wifi_p2p_manager_new`, and not be
+/// used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wifi_p2p_manager_free(manager: *mut FfiManager) {
+    if !manager.is_null() {
+        drop(unsafe { Box::from_raw(manager) });
+    }
+}
+
+/// Start the manager's command loop and return a channel to it, or `NULL`
+/// when `manager` is `NULL`. Each call returns a new handle to the same loop.
+///
+/// # Safety
+///
+/// `manager` must be `NULL` or a live handle from `wifi_p2p_manager_new`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wifi_p2p_manager_initialize(
+    manager: *const FfiManager,
+) -> *mut FfiChannel {
+    match unsafe { manager.as_ref() } {
+        Some(manager) => Box::into_raw(Box::new(FfiChannel {
+            inner: manager.inner.initialize(),
+        })),
+        None => {
+            fail(P2pError::InvalidArgs("manager is NULL".to_string()));
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Free a channel handle; commands it queued still run.
+///
+/// # Safety
+///
+/// `channel` must be `NULL` or come from `wifi_p2p_manager_initialize`, and
+/// not be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wifi_p2p_channel_free(channel: *mut FfiChannel) {
+    if !channel.is_null() {
+        drop(unsafe { Box::from_raw(channel) });
+    }
+}
+
+/// The message of the last error on this thread, or `NULL`. Valid until the
+/// next failing call on the same thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn wifi_p2p_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |err| err.as_ptr())
+    })
+}
+
+/// Free a string returned by this library.
+///
+/// # Safety
+///
+/// `string` must be `NULL` or a string this library handed out, freed once.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wifi_p2p_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(unsafe { CString::from_raw(string) });
+    }
+}
+
+/// # Safety
+///
+/// `channel` must be `NULL` or a live channel handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wifi_p2p_discover_peers(channel: *const FfiChannel) -> i32 {
+    status(unsafe { channel_ref(channel) }.and_then(|channel| channel.inner.discover_peers()))
+}
+
+/// # Safety
+///
+/// `channel` must be `NULL` or a live channel handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wifi_p2p_stop_discovery(channel: *const FfiChannel) -> i32 {
+    status(unsafe { channel_ref(channel) }.and_then(|channel| channel.inner.stop_discovery()))
+}
+
+/// Connect to `device_address` with a `WIFI_P2P_WPS_*` method. `pin` may be
+/// `NULL`; with `WIFI_P2P_WPS_DISPLAY` and no PIN the supplicant generates one,
+/// stored in `*generated_pin` (free it with `wifi_p2p_string_free`) when
+/// `generated_pin` is not `NULL`, and `NULL` is stored otherwise.
+///
+/// # Safety
+///
+/// `channel` must be `NULL` or a live channel handle, the strings `NULL` or
+/// NUL-terminated, and `generated_pin` `NULL` or writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wifi_p2p_connect(
+    channel: *const FfiChannel,
+    device_address: *const c_char,
+    wps_method: i32,
+    pin: *const c_char,
+    generated_pin: *mut *mut c_char,
+) -> i32 {
+    let result = unsafe { channel_ref(channel) }.and_then(|channel| {
+        let config = unsafe { connect_config(device_address, wps_method, pin) }?;
+        channel.inner.connect_with_config(config)
+    });
+    if !generated_pin.is_null() {
+        let pin = result.as_ref().ok().cloned().flatten();
+        unsafe { *generated_pin = pin.map_or(ptr::null_mut(), into_c_string) };
+    }
+    status(result)
+}
+
+/// # Safety
+///
+/// `channel` must be `NULL` or a live channel handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wifi_p2p_cancel_connect(channel: *const FfiChannel) -> i32 {
+    status(unsafe { channel_ref(channel) }.and_then(|channel| channel.inner.cancel_connect()))
+}
+
+/// Store the known peers as a JSON array of devices in `*peers`; free it with
+/// `wifi_p2p_string_free`.
+///
+/// # Safety
+///
+/// `channel` must be `NULL` or a live channel handle and `peers` writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wifi_p2p_request_peers(
+    channel: *const FfiChannel,
+    peers: *mut *mut c_char,
+) -> i32 {
+    let result = unsafe { channel_ref(channel) }
+        .and_then(|channel| channel.inner.request_peers())
+        .and_then(|peers| to_json(&peers));
+    unsafe { store_string(peers, result) }
+}
+
+/// Store the active groups as a JSON array in `*groups`; free it with
+/// `wifi_p2p_string_free`.
+///
+/// # Safety
+///
+/// `channel` must be `NULL` or a live channel handle and `groups` writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wifi_p2p_request_groups(
+    channel: *const FfiChannel,
+    groups: *mut *mut c_char,
+) -> i32 {
+    let result = unsafe { channel_ref(channel) }
+        .and_then(|channel| channel.inner.request_groups())
+        .and_then(|groups| to_json(&groups));
+    unsafe { store_string(groups, result) }
+}
+
+/// # Safety
+///
+/// `channel` must be `NULL` or a live channel handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wifi_p2p_create_group(channel: *const FfiChannel) -> i32 {
+    status(unsafe { channel_ref(channel) }.and_then(|channel| channel.inner.create_group()))
+}
+
+/// # Safety
+///
+/// `channel` must be `NULL` or a live channel handle and `peer` `NULL` or
+/// NUL-terminated.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wifi_p2p_invite(channel: *const FfiChannel, peer: *const c_char) -> i32 {
+    status(unsafe { channel_ref(channel) }.and_then(|channel| {
+        let peer = unsafe { mac(peer, "peer") }?;
+        channel.inner.invite(peer)
+    }))
+}
+
+/// # Safety
+///
+/// `channel` must be `NULL` or a live channel handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wifi_p2p_remove_group(channel: *const FfiChannel) -> i32 {
+    status(unsafe { channel_ref(channel) }.and_then(|channel| channel.inner.remove_group()))
+}
+
+/// # Safety
+///
+/// `channel` must be `NULL` or a live channel handle; see the header for
+/// `callback` and `user_data`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wifi_p2p_discover_peers_async(
+    channel: *const FfiChannel,
+    callback: ResultCallback,
+    user_data: *mut c_void,
+) -> i32 {
+    unsafe {
+        spawn_action(channel, callback, user_data, |channel| async move {
+            channel.discover_peers().await
+        })
+    }
+}
+
+/// # Safety
+///
+/// `channel` must be `NULL` or a live channel handle; see the header for
+/// `callback` and `user_data`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wifi_p2p_stop_discovery_async(
+    channel: *const FfiChannel,
+    callback: ResultCallback,
+    user_data: *mut c_void,
+) -> i32 {
+    unsafe {
+        spawn_action(channel, callback, user_data, |channel| async move {
+            channel.stop_discovery().await
+        })
+    }
+}
+
+/// Like `wifi_p2p_connect`; the callback gets the generated PIN as `message`.
+///
+/// # Safety
+///
+/// As for `wifi_p2p_connect`; see the header for `callback` and `user_data`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wifi_p2p_connect_async(
+    channel: *const FfiChannel,
+    device_address: *const c_char,
+    wps_method: i32,
+    pin: *const c_char,
+    callback: ResultCallback,
+    user_data: *mut c_void,
+) -> i32 {
+    let config = match unsafe { connect_config(device_address, wps_method, pin) } {
+        Ok(config) => config,
+        Err(err) => return fail(err),
+    };
+    unsafe {
+        spawn_action(channel, callback, user_data, move |channel| async move {
+            channel.connect_with_config(config).await
+        })
+    }
+}
+
+/// # Safety
+///
+/// `channel` must be `NULL` or a live channel handle; see the header for
+/// `callback` and `user_data`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wifi_p2p_cancel_connect_async(
+    channel: *const FfiChannel,
+    callback: ResultCallback,
+    user_data: *mut c_void,
+) -> i32 {
+    unsafe {
+        spawn_action(channel, callback, user_data, |channel| async move {
+            channel.cancel_connect().await
+        })
+    }
+}
+
+/// # Safety
+///
+/// `channel` must be `NULL` or a live channel handle; see the header for
+/// `callback` and `user_data`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wifi_p2p_create_group_async(
+    channel: *const FfiChannel,
+    callback: ResultCallback,
+    user_data: *mut c_void,
+) -> i32 {
+    unsafe {
+        spawn_action(channel, callback, user_data, |channel| async move {
+            channel.create_group().await
+        })
+    }
+}
+
+/// # Safety
+///
+/// As for `wifi_p2p_invite`; see the header for `callback` and `user_data`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wifi_p2p_invite_async(
+    channel: *const FfiChannel,
+    peer: *const c_char,
+    callback: ResultCallback,
+    user_data: *mut c_void,
+) -> i32 {
+    let peer = match unsafe { mac(peer, "peer") } {
+        Ok(peer) => peer,
+        Err(err) => return fail(err),
+    };
+    unsafe {
+        spawn_action(channel, callback, user_data, move |channel| async move {
+            channel.invite(peer).await
+        })
+    }
+}
+
+/// # Safety
+///
+/// `channel` must be `NULL` or a live channel handle; see the header for
+/// `callback` and `user_data`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wifi_p2p_remove_group_async(
+    channel: *const FfiChannel,
+    callback: ResultCallback,
+    user_data: *mut c_void,
+) -> i32 {
+    unsafe {
+        spawn_action(channel, callback, user_data, |channel| async move {
+            channel.remove_group().await
+        })
+    }
+}
+
+/// Call `callback` for every event whose kind is in `kinds`, a mask of
+/// `WIFI_P2P_EVENT_*` bits (0 for all), until `wifi_p2p_unregister_events`.
+/// Returns `NULL` when `channel` is `NULL`.
+///
+/// # Safety
+///
+/// `channel` must be `NULL` or a live channel handle; see the header for
+/// `callback` and `user_data`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wifi_p2p_register_events(
+    channel: *const FfiChannel,
+    kinds: u32,
+    callback: EventCallback,
+    user_data: *mut c_void,
+) -> *mut FfiListener {
+    let channel = match unsafe { channel_ref(channel) } {
+        Ok(channel) => channel,
+        Err(err) => {
+            fail(err);
+            return ptr::null_mut();
+        }
+    };
+    let listener = CallbackListener {
+        kinds,
+        callback,
+        user_data: UserData(user_data),
+    };
+    let handle = channel.inner.as_async().register_listener(listener);
+    Box::into_raw(Box::new(FfiListener { handle }))
+}
+
+/// Stop the callback and free the listener. An event being delivered may
+/// still finish after this returns.
+///
+/// # Safety
+///
+/// `listener` must be `NULL` or come from `wifi_p2p_register_events`, and not
+/// be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wifi_p2p_unregister_events(listener: *mut FfiListener) {
+    if !listener.is_null() {
+        unsafe { Box::from_raw(listener) }.handle.unregister();
+    }
+}
+
+struct CallbackListener {
+    kinds: u32,
+    callback: EventCallback,
+    user_data: UserData,
+}
+
+impl P2pEventListener for CallbackListener {
+    fn on_event(&mut self, event: &P2pEvent) {
+        let kind = kind_bit(event.kind());
+        if self.kinds != 0 && self.kinds & kind == 0 {
+            return;
+        }
+        let Ok(json) = CString::new(event_json(event).to_string()) else {
+            return;
+        };
+        (self.callback)(kind, json.as_ptr(), self.user_data.0);
+    }
+}
+
+/// The `WIFI_P2P_EVENT_*` bit for `kind`.
+fn kind_bit(kind: EventKind) -> u32 {
+    match kind {
+        EventKind::Discovery => 1 << 0,
+        EventKind::Peer => 1 << 1,
+        EventKind::Connection => 1 << 2,
+        EventKind::Group => 1 << 3,
+        EventKind::Transfer => 1 << 4,
+        EventKind::Stats => 1 << 5,
+        EventKind::Backend => 1 << 6,
+    }
+}
+
+/// The `WIFI_P2P_ERR_*` code for `err`.
+pub fn error_code(err: &P2pError) -> i32 {
+    match err {
+        P2pError::DBus(_) => WIFI_P2P_ERR_DBUS,
+        P2pError::ZVariant(_) => WIFI_P2P_ERR_ZVARIANT,
+        P2pError::ChannelClosed(_) => WIFI_P2P_ERR_CHANNEL_CLOSED,
+        P2pError::InvalidInterface(_) => WIFI_P2P_ERR_INVALID_INTERFACE,
+        P2pError::InvalidMacAddress(_) => WIFI_P2P_ERR_INVALID_MAC_ADDRESS,
+        P2pError::InvalidConfig(_) => WIFI_P2P_ERR_INVALID_CONFIG,
+        P2pError::Timeout(_) => WIFI_P2P_ERR_TIMEOUT,
+        P2pError::Cancelled(_) => WIFI_P2P_ERR_CANCELLED,
+        P2pError::Status(_) => WIFI_P2P_ERR_STATUS,
+        P2pError::InterfaceUnknown(_) => WIFI_P2P_ERR_INTERFACE_UNKNOWN,
+        P2pError::NotConnected(_) => WIFI_P2P_ERR_NOT_CONNECTED,
+        P2pError::InvalidArgs(_) => WIFI_P2P_ERR_INVALID_ARGS,
+        P2pError::Busy(_) => WIFI_P2P_ERR_BUSY,
+        P2pError::PermissionDenied(_) => WIFI_P2P_ERR_PERMISSION_DENIED,
+        P2pError::Unsupported(_) => WIFI_P2P_ERR_UNSUPPORTED,
+        P2pError::SupplicantUnavailable(_) => WIFI_P2P_ERR_SUPPLICANT_UNAVAILABLE,
+        P2pError::RadioBlocked(_) => WIFI_P2P_ERR_RADIO_BLOCKED,
+        P2pError::Blob(_) => WIFI_P2P_ERR_BLOB,
+        P2pError::UnsupportedPlatform(_) => WIFI_P2P_ERR_UNSUPPORTED_PLATFORM,
+        P2pError::Backend(_) => WIFI_P2P_ERR_BACKEND,
+    }
+}
+
+/// Queue an action on the channel's runtime and report its result to
+/// `callback`. Returns an error without calling `callback` only for a `NULL`
+/// channel.
+unsafe fn spawn_action<T, F, Q>(
+    channel: *const FfiChannel,
+    callback: ResultCallback,
+    user_data: *mut c_void,
+    queue: F,
+) -> i32
+where
+    T: SuccessMessage + Send + 'static,
+    F: FnOnce(WifiP2pChannel) -> Q,
+    Q: Future<Output = Result<oneshot::Receiver<Result<T, P2pError>>, P2pError>> + Send + 'static,
+{
+    let channel = match unsafe { channel_ref(channel) } {
+        Ok(channel) => channel,
+        Err(err) => return fail(err),
+    };
+    let queued = queue(channel.inner.as_async().clone());
+    let user_data = UserData(user_data);
+    channel.inner.spawn(async move {
+        let user_data = user_data;
+        let result = match queued.await {
+            Ok(receiver) => receiver
+                .await
+                .unwrap_or_else(|_| Err(P2pError::ChannelClosed("manager".to_string()))),
+            Err(err) => Err(err),
+        };
+        let (code, message) = match result {
+            Ok(value) => (WIFI_P2P_OK, value.message()),
+            Err(err) => (error_code(&err), Some(err.to_string())),
+        };
+        let message = message.and_then(|message| CString::new(message).ok());
+        let message = message
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr());
+        callback(code, message, user_data.0);
+    });
+    WIFI_P2P_OK
+}
+
+/// What a successful action passes to its callback as `message`.
+trait SuccessMessage {
+    fn message(self) -> Option<String>;
+}
+
+impl SuccessMessage for () {
+    fn message(self) -> Option<String> {
+        None
+    }
+}
+
+/// A connect's generated PIN.
+impl SuccessMessage for Option<String> {
+    fn message(self) -> Option<String> {
+        self
+    }
+}
+
+unsafe fn channel_ref<'a>(channel: *const FfiChannel) -> Result<&'a FfiChannel, P2pError> {
+    unsafe { channel.as_ref() }.ok_or_else(|| P2pError::InvalidArgs("channel is NULL".to_string()))
+}
+
+unsafe fn text<'a>(string: *const c_char, what: &str) -> Result<&'a str, P2pError> {
+    if string.is_null() {
+        return Err(P2pError::InvalidArgs(format!("{what} is NULL")));
+    }
+    unsafe { CStr::from_ptr(string) }
+        .to_str()
+        .map_err(|_| P2pError::InvalidArgs(format!("{what} is not UTF-8")))
+}
+
+unsafe fn mac(string: *const c_char, what: &str) -> Result<MacAddress, P2pError> {
+    unsafe { text(string, what) }?.parse()
+}
+
+unsafe fn connect_config(
+    device_address: *const c_char,
+    wps_method: i32,
+    pin: *const c_char,
+) -> Result<ConnectConfig, P2pError> {
+    let device_address = unsafe { mac(device_address, "device_address") }?;
+    let wps_method = match wps_method {
+        0 => WpsMethod::Pbc,
+        1 => WpsMethod::PinDisplay,
+        2 => WpsMethod::PinKeypad,
+        3 => WpsMethod::PinLabel,
+        other => return Err(P2pError::InvalidArgs(format!("unknown WPS method {other}"))),
+    };
+    let pin = if pin.is_null() {
+        None
+    } else {
+        Some(unsafe { text(pin, "pin") }?.to_string())
+    };
+    Ok(ConnectConfig {
+        device_address,
+        wps_method,
+        pin,
+    })
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> Result<String, P2pError> {
+    serde_json::to_string(value).map_err(|err| P2pError::Backend(err.to_string()))
+}
+
+/// Store `result`'s string in `*out` (or `NULL`) and return its status.
+unsafe fn store_string(out: *mut *mut c_char, result: Result<String, P2pError>) -> i32 {
+    if out.is_null() {
+        return fail(P2pError::InvalidArgs("output pointer is NULL".to_string()));
+    }
+    let string = result.as_ref().ok().cloned();
+    unsafe { *out = string.map_or(ptr::null_mut(), into_c_string) };
+    status(result)
+}
+
+fn into_c_string(string: String) -> *mut c_char {
+    // Strings from the supplicant and serde_json carry no NUL bytes.
+    CString::new(string).map_or(ptr::null_mut(), CString::into_raw)
+}
+
+fn status<T>(result: Result<T, P2pError>) -> i32 {
+    match result {
+        Ok(_) => WIFI_P2P_OK,
+        Err(err) => fail(err),
+    }
+}
+
+/// Record `err` as this thread's last error and return its code.
+fn fail(err: P2pError) -> i32 {
+    let code = error_code(&err);
+    let message = CString::new(err.to_string()).ok();
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+    code
+}
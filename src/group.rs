@@ -0,0 +1,149 @@
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use crate::error::P2pError;
+use crate::mac::MacAddress;
+
+/// Notice of Absence schedule advertised by a group owner (maps to `p2p_set noa`).
+///
+/// `p2p_set noa` takes only count, start and duration: the supplicant repeats
+/// the absence once per beacon interval, so `interval` has to be
+/// `BEACON_INTERVAL` for the schedule to be applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NoaSchedule {
+    /// Number of absence periods; 255 repeats the schedule until it is replaced.
+    pub count: u8,
+    /// Offset from the next beacon (TBTT) at which the first absence starts.
+    pub start: Duration,
+    /// Length of each absence period.
+    pub duration: Duration,
+    /// Time from the start of one absence period to the start of the next.
+    #[cfg_attr(feature = "serde", serde(default = "beacon_interval"))]
+    pub interval: Duration,
+}
+
+impl NoaSchedule {
+    /// Beacon interval of the groups the supplicant owns, 100 TU unless its
+    /// `beacon_int` is changed.
+    pub const BEACON_INTERVAL: Duration = Duration::from_micros(102_400);
+
+    /// A schedule that clears any active absence periods.
+    pub const DISABLED: Self = Self {
+        count: 0,
+        start: Duration::ZERO,
+        duration: Duration::ZERO,
+        interval: Self::BEACON_INTERVAL,
+    };
+
+    /// `InvalidConfig` unless the absences repeat every beacon interval, the
+    /// only period the supplicant offers. A disabled schedule has no period.
+    pub(crate) fn check_interval(&self) -> Result<(), P2pError> {
+        if self.count == 0 || self.interval == Self::BEACON_INTERVAL {
+            return Ok(());
+        }
+        Err(P2pError::InvalidConfig(format!(
+            "absences repeat every beacon interval ({:?}), not every {:?}",
+            Self::BEACON_INTERVAL,
+            self.interval
+        )))
+    }
+}
+
+#[cfg(feature = "serde")]
+fn beacon_interval() -> Duration {
+    NoaSchedule::BEACON_INTERVAL
+}
+
+/// Which side of a P2P group this device is on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
+pub enum GroupRole {
+    /// We own the group; members associate with us as with an access point.
+    Owner,
+    /// We joined a group owned by a peer.
+    Client,
+}
+
+/// A P2P group this device is a member of.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct P2pGroup {
+    /// Network interface carrying the group's traffic (e.g. "p2p-wlan0-0").
+    pub interface: String,
+    pub role: GroupRole,
+    /// Addressing handed out during the 4-way handshake (P2P IP allocation), when
+    /// the group owner offers it. Our own address is `ip` in either role.
+    pub ip_allocation: Option<IpAllocation>,
+    /// P2P device address of the peer the group was formed with: the group owner
+    /// when we are a client, the peer GO negotiation or a persistent group
+    /// re-invocation was with when we own it. `None` for groups we started on
+    /// our own and where the backend cannot tell.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub peer: Option<MacAddress>,
+}
+
+/// IPv4 addressing from P2P IP allocation in EAPOL-Key frames (Wi-Fi P2P 4.2.8).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct IpAllocation {
+    /// Our address on the group interface.
+    pub ip: Ipv4Addr,
+    pub mask: Ipv4Addr,
+    /// The group owner's address.
+    pub go_ip: Ipv4Addr,
+}
+
+impl IpAllocation {
+    pub fn prefix_len(&self) -> u8 {
+        u32::from(self.mask).count_ones() as u8
+    }
+}
+
+/// Addresses a group owner hands out through P2P IP allocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IpAllocationPool {
+    /// Our address as group owner.
+    pub go_ip: Ipv4Addr,
+    pub mask: Ipv4Addr,
+    /// First address handed out to clients.
+    pub start: Ipv4Addr,
+    /// Last address handed out to clients (inclusive).
+    pub end: Ipv4Addr,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_beacon_interval_is_honored() {
+        let schedule = NoaSchedule {
+            count: 255,
+            start: Duration::from_millis(10),
+            duration: Duration::from_millis(40),
+            interval: NoaSchedule::BEACON_INTERVAL,
+        };
+        assert!(schedule.check_interval().is_ok());
+        for interval in [Duration::ZERO, Duration::from_millis(100)] {
+            let schedule = NoaSchedule {
+                interval,
+                ..schedule
+            };
+            assert!(matches!(
+                schedule.check_interval(),
+                Err(P2pError::InvalidConfig(_))
+            ));
+        }
+        // Clearing the schedule does not depend on it.
+        let cleared = NoaSchedule {
+            interval: Duration::ZERO,
+            ..NoaSchedule::DISABLED
+        };
+        assert!(cleared.check_interval().is_ok());
+    }
+}
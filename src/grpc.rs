@@ -0,0 +1,306 @@
+//! gRPC control API, so services written in other languages on the same device
+//! can drive Wi-Fi Direct through this crate.
+//!
+//! The service is defined in `proto/wifip2p.proto`; generate a client from it
+//! with the usual tooling for Go, Python or Node. `serve` runs a standalone
+//! server, and `service` returns the tonic service to mount on an existing
+//! `tonic::transport::Server` next to your own. There is no authentication:
+//! bind to loopback or put the server behind something that checks callers.
+
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tokio::sync::{mpsc, oneshot};
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tokio_stream::{Stream, StreamExt};
+use tonic::{Code, Request, Response, Status};
+
+use crate::channel::{P2pEvent, WifiP2pChannel};
+use crate::config::{ConnectConfig, WpsMethod};
+use crate::device::{DeviceStatus, P2pDevice};
+use crate::error::P2pError;
+use crate::group::{GroupRole, P2pGroup};
+use crate::mac::MacAddress;
+
+/// Messages and the server trait generated from `proto/wifip2p.proto`.
+pub mod proto {
+    tonic::include_proto!("wifip2p.v1");
+}
+
+use proto::wifi_p2p_server::{WifiP2p, WifiP2pServer};
+
+/// Serve the API for `channel` on `addr` until the manager stops or serving fails.
+pub async fn serve(channel: WifiP2pChannel, addr: SocketAddr) -> Result<(), P2pError> {
+    let listener = std::net::TcpListener::bind(addr).map_err(grpc_error)?;
+    serve_listener(channel, listener).await
+}
+
+/// Like `serve`, on a socket that is already listening, e.g. one from
+/// `systemd::listen_fds`.
+pub async fn serve_listener(
+    channel: WifiP2pChannel,
+    listener: std::net::TcpListener,
+) -> Result<(), P2pError> {
+    listener.set_nonblocking(true).map_err(grpc_error)?;
+    let listener = tokio::net::TcpListener::from_std(listener).map_err(grpc_error)?;
+    let stopped = channel.clone();
+    tonic::transport::Server::builder()
+        .add_service(service(channel))
+        .serve_with_incoming_shutdown(TcpListenerStream::new(listener), async move {
+            stopped.closed().await
+        })
+        .await
+        .map_err(grpc_error)
+}
+
+fn grpc_error(err: impl std::fmt::Display) -> P2pError {
+    P2pError::Backend(format!("grpc: {err}"))
+}
+
+/// The API for `channel` as a tonic service, for a server you build yourself.
+pub fn service(channel: WifiP2pChannel) -> WifiP2pServer<GrpcService> {
+    WifiP2pServer::new(GrpcService { channel })
+}
+
+/// Implementation of the `wifip2p.v1.WifiP2p` service over a channel.
+pub struct GrpcService {
+    channel: WifiP2pChannel,
+}
+
+type EventStream = Pin<Box<dyn Stream<Item = Result<proto::Event, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl WifiP2p for GrpcService {
+    async fn discover_peers(
+        &self,
+        _request: Request<proto::Empty>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        answer(self.channel.discover_peers()).await?;
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    async fn stop_discovery(
+        &self,
+        _request: Request<proto::Empty>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        answer(self.channel.stop_discovery()).await?;
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    async fn list_peers(
+        &self,
+        _request: Request<proto::Empty>,
+    ) -> Result<Response<proto::PeerList>, Status> {
+        let peers = self.channel.peers_snapshot().iter().map(peer).collect();
+        Ok(Response::new(proto::PeerList { peers }))
+    }
+
+    async fn connect(
+        &self,
+        request: Request<proto::ConnectRequest>,
+    ) -> Result<Response<proto::ConnectReply>, Status> {
+        let request = request.into_inner();
+        let wps_method = match request.method() {
+            proto::WpsMethod::Pbc => WpsMethod::Pbc,
+            proto::WpsMethod::Display => WpsMethod::PinDisplay,
+            proto::WpsMethod::Keypad => WpsMethod::PinKeypad,
+            proto::WpsMethod::Label => WpsMethod::PinLabel,
+        };
+        let config = ConnectConfig {
+            device_address: request.address.parse().map_err(status)?,
+            wps_method,
+            pin: (!request.pin.is_empty()).then_some(request.pin),
+        };
+        let pin = answer(self.channel.connect_with_config(config)).await?;
+        Ok(Response::new(proto::ConnectReply {
+            pin: pin.unwrap_or_default(),
+        }))
+    }
+
+    async fn create_group(
+        &self,
+        _request: Request<proto::Empty>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        answer(self.channel.create_group()).await?;
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    async fn remove_group(
+        &self,
+        _request: Request<proto::Empty>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        answer(self.channel.remove_group()).await?;
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    async fn list_groups(
+        &self,
+        _request: Request<proto::Empty>,
+    ) -> Result<Response<proto::GroupList>, Status> {
+        let groups = self.channel.groups_snapshot().iter().map(group).collect();
+        Ok(Response::new(proto::GroupList { groups }))
+    }
+
+    async fn invite(
+        &self,
+        request: Request<proto::PeerRequest>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        let peer: MacAddress = request.into_inner().address.parse().map_err(status)?;
+        answer(self.channel.invite(peer)).await?;
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    async fn reject_peer(
+        &self,
+        request: Request<proto::PeerRequest>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        let peer: MacAddress = request.into_inner().address.parse().map_err(status)?;
+        answer(self.channel.reject_peer(peer)).await?;
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    type StreamEventsStream = EventStream;
+
+    async fn stream_events(
+        &self,
+        _request: Request<proto::Empty>,
+    ) -> Result<Response<EventStream>, Status> {
+        // Forward from a task so the stream also ends when the manager stops,
+        // rather than holding up a graceful shutdown.
+        let (tx, rx) = mpsc::channel(16);
+        let channel = self.channel.clone();
+        tokio::spawn(async move {
+            let mut events = channel.event_stream();
+            loop {
+                tokio::select! {
+                    event = events.next() => {
+                        let Some(event) = event else { break };
+                        if tx.send(Ok(event_message(&event))).await.is_err() {
+                            break;
+                        }
+                    }
+                    () = channel.closed() => break,
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+}
+
+/// Wait for a queued command's answer, as a gRPC status on failure.
+async fn answer<T>(
+    queued: impl Future<Output = Result<oneshot::Receiver<Result<T, P2pError>>, P2pError>>,
+) -> Result<T, Status> {
+    let result = match queued.await {
+        Ok(receiver) => receiver
+            .await
+            .unwrap_or_else(|_| Err(P2pError::ChannelClosed("manager".to_string()))),
+        Err(err) => Err(err),
+    };
+    result.map_err(status)
+}
+
+fn status(err: P2pError) -> Status {
+    let code = match err {
+        P2pError::InvalidArgs(_) | P2pError::InvalidMacAddress(_) | P2pError::InvalidConfig(_) => {
+            Code::InvalidArgument
+        }
+        P2pError::Unsupported(_) | P2pError::UnsupportedPlatform(_) => Code::Unimplemented,
+        P2pError::PermissionDenied(_) => Code::PermissionDenied,
+        P2pError::Timeout(_) => Code::DeadlineExceeded,
+        P2pError::Cancelled(_) => Code::Cancelled,
+        P2pError::NotConnected(_) => Code::FailedPrecondition,
+        P2pError::Busy(_) | P2pError::SupplicantUnavailable(_) | P2pError::RadioBlocked(_) => {
+            Code::Unavailable
+        }
+        P2pError::ChannelClosed(_) => Code::Internal,
+        _ => Code::Unknown,
+    };
+    Status::new(code, err.to_string())
+}
+
+fn peer(device: &P2pDevice) -> proto::Peer {
+    let status = match device.status {
+        DeviceStatus::Available => proto::PeerStatus::Available,
+        DeviceStatus::Invited => proto::PeerStatus::Invited,
+        DeviceStatus::Connected => proto::PeerStatus::Connected,
+        DeviceStatus::Failed => proto::PeerStatus::Failed,
+        DeviceStatus::Unavailable => proto::PeerStatus::Unavailable,
+    };
+    proto::Peer {
+        address: device.mac_address.to_string(),
+        name: device.device_name.clone(),
+        primary_type: device.primary_type.clone(),
+        signal: device.signal_level,
+        status: status.into(),
+    }
+}
+
+fn group(group: &P2pGroup) -> proto::Group {
+    let role = match group.role {
+        GroupRole::Owner => proto::GroupRole::Owner,
+        GroupRole::Client => proto::GroupRole::Client,
+    };
+    let allocation = group.ip_allocation;
+    proto::Group {
+        interface: group.interface.clone(),
+        role: role.into(),
+        ip: allocation.map(|allocation| allocation.ip.to_string()),
+        prefix: allocation.map(|allocation| allocation.prefix_len().into()),
+        go_ip: allocation.map(|allocation| allocation.go_ip.to_string()),
+    }
+}
+
+fn event_message(event: &P2pEvent) -> proto::Event {
+    use proto::event::Payload;
+
+    let debug = format!("{event:?}");
+    let kind: String = debug
+        .chars()
+        .take_while(char::is_ascii_alphanumeric)
+        .collect();
+    let payload = match event {
+        P2pEvent::DiscoveryStarted
+        | P2pEvent::DiscoveryStopped
+        | P2pEvent::GroupCreated
+        | P2pEvent::BackendRestored => None,
+        P2pEvent::PeerFound(device) => Some(Payload::Peer(peer(device))),
+        P2pEvent::PeerStatusChanged(address, status) => {
+            let mut device = P2pDevice::new(*address);
+            device.status = *status;
+            Some(Payload::Peer(peer(&device)))
+        }
+        P2pEvent::PeerLost(address)
+        | P2pEvent::Connected(address)
+        | P2pEvent::ConnectionRequested(address)
+        | P2pEvent::LinkRecovered { peer: address } => Some(Payload::Address(address.to_string())),
+        P2pEvent::GroupStarted(started) => Some(Payload::Group(group(started))),
+        P2pEvent::GroupFinished(finished) => Some(Payload::Group(group(finished))),
+        P2pEvent::PeerIpResolved { mac, ip } => Some(Payload::PeerIp(proto::PeerIp {
+            address: mac.to_string(),
+            ip: ip.to_string(),
+        })),
+        P2pEvent::GoNegotiationFailed { peer, status } => {
+            Some(Payload::Failure(proto::PeerFailure {
+                address: peer.map(|peer| peer.to_string()).unwrap_or_default(),
+                status: status.to_string(),
+            }))
+        }
+        P2pEvent::ProvisionDiscoveryFailed { peer, status } => {
+            Some(Payload::Failure(proto::PeerFailure {
+                address: peer.to_string(),
+                status: status.to_string(),
+            }))
+        }
+        _ => Some(Payload::Detail(debug)),
+    };
+    let time_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as u64);
+    proto::Event {
+        time_ms,
+        r#type: kind,
+        payload,
+    }
+}
@@ -0,0 +1,174 @@
+//! Liveness of the manager and its backend, for probes in daemons.
+//!
+//! `WifiP2pManager::health` pings the backend directly (not through the command
+//! queue, so a long-running command does not fail the probe) and adds what the
+//! manager task has been doing: when each command last succeeded and how full
+//! the queue is. `WifiP2pChannel::queue_status` answers the narrower question of
+//! what the task is working on right now, to spot a command that never returns.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime};
+
+use tokio::sync::mpsc;
+
+use crate::error::P2pError;
+use crate::manager::QueuedCommand;
+
+/// What `WifiP2pManager::health` found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HealthReport {
+    /// Whether the backend answered a cheap request (a property read on Linux) in time.
+    pub responsive: bool,
+    /// How long the request took, up to the deadline.
+    pub latency: Duration,
+    /// Why the backend did not answer.
+    pub error: Option<String>,
+    /// Whether a manager task is running and taking commands.
+    pub running: bool,
+    /// Commands waiting for the manager task.
+    pub queued_commands: usize,
+    /// How many commands fit in the queue (`command_queue_depth`).
+    pub queue_capacity: usize,
+    /// When each operation last succeeded, by name: the commands (e.g. `"discover"`,
+    /// `"connect"`) and `"ping"` for the health check itself.
+    pub last_success: BTreeMap<String, SystemTime>,
+}
+
+impl HealthReport {
+    /// Responsive and running: what a liveness probe wants to know.
+    pub fn is_healthy(&self) -> bool {
+        self.responsive && self.running
+    }
+}
+
+/// What the manager task is working through, from `WifiP2pChannel::queue_status`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QueueStatus {
+    /// Commands sent but not started: in the queues, or parked until the radio
+    /// command ahead of them finishes.
+    pub pending: usize,
+    /// The command the manager loop is running.
+    pub current: Option<RunningCommand>,
+    /// The radio command (`connect`, `invite`, ...) running beside the loop.
+    pub radio: Option<RunningCommand>,
+}
+
+impl QueueStatus {
+    /// The longer-running of `current` and `radio`. A command past its deadline
+    /// (`command_timeout`, or the one it was sent with) points at a wedged backend.
+    pub fn longest_running(&self) -> Option<&RunningCommand> {
+        [&self.current, &self.radio]
+            .into_iter()
+            .flatten()
+            .max_by_key(|running| running.elapsed)
+    }
+}
+
+/// A command that has started and not yet returned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RunningCommand {
+    /// Command name, e.g. `"discover"` or `"connect"`.
+    pub name: String,
+    /// Time since it started.
+    pub elapsed: Duration,
+}
+
+/// Which commands a manager task is running, shared with its channels.
+#[derive(Default)]
+pub(crate) struct QueueTracker {
+    current: Mutex<Option<(&'static str, Instant)>>,
+    radio: Mutex<Option<(&'static str, Instant)>>,
+    parked: AtomicUsize,
+}
+
+impl QueueTracker {
+    pub(crate) fn started(&self, operation: &'static str) {
+        *lock(&self.current) = Some((operation, Instant::now()));
+    }
+
+    pub(crate) fn finished(&self) {
+        *lock(&self.current) = None;
+    }
+
+    pub(crate) fn radio_started(&self, operation: &'static str) {
+        *lock(&self.radio) = Some((operation, Instant::now()));
+    }
+
+    pub(crate) fn radio_finished(&self) {
+        *lock(&self.radio) = None;
+    }
+
+    pub(crate) fn set_parked(&self, parked: usize) {
+        self.parked.store(parked, Ordering::Relaxed);
+    }
+
+    /// `queued` is what sits in the channels' queues.
+    pub(crate) fn status(&self, queued: usize) -> QueueStatus {
+        let running = |slot: &Mutex<Option<(&'static str, Instant)>>| {
+            lock(slot).map(|(name, started)| RunningCommand {
+                name: name.to_string(),
+                elapsed: started.elapsed(),
+            })
+        };
+        QueueStatus {
+            pending: queued + self.parked.load(Ordering::Relaxed),
+            current: running(&self.current),
+            radio: running(&self.radio),
+        }
+    }
+}
+
+/// Shared between the manager and its task.
+#[derive(Default)]
+pub(crate) struct HealthState {
+    last_success: Mutex<HashMap<&'static str, SystemTime>>,
+    // Weak, so the queue still closes when every channel is dropped.
+    commands: Mutex<Option<mpsc::WeakSender<QueuedCommand>>>,
+}
+
+impl HealthState {
+    pub(crate) fn succeeded(&self, operation: &'static str) {
+        lock(&self.last_success).insert(operation, SystemTime::now());
+    }
+
+    pub(crate) fn track_queue(&self, commands: &mpsc::Sender<QueuedCommand>) {
+        *lock(&self.commands) = Some(commands.downgrade());
+    }
+
+    pub(crate) fn report(
+        &self,
+        ping: Result<(), P2pError>,
+        latency: Duration,
+        queue_capacity: usize,
+    ) -> HealthReport {
+        let commands = lock(&self.commands)
+            .as_ref()
+            .and_then(mpsc::WeakSender::upgrade)
+            .filter(|commands| !commands.is_closed());
+        HealthReport {
+            responsive: ping.is_ok(),
+            latency,
+            error: ping.err().map(|err| err.to_string()),
+            running: commands.is_some(),
+            queued_commands: commands
+                .map_or(0, |commands| commands.max_capacity() - commands.capacity()),
+            queue_capacity,
+            last_success: lock(&self.last_success)
+                .iter()
+                .map(|(operation, at)| (operation.to_string(), *at))
+                .collect(),
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // Each update is a single insert, so a panicking holder leaves nothing half-done.
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
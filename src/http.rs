@@ -0,0 +1,225 @@
+//! Embedded HTTP API for local web dashboards: REST endpoints for peers,
+//! groups and connecting, and a WebSocket streaming every `P2pEvent` as JSON.
+//!
+//! | Method and path                  | Does                                            |
+//! |----------------------------------|-------------------------------------------------|
+//! | `GET /peers`                     | peers the manager has seen                      |
+//! | `POST /discovery`                | start peer discovery                            |
+//! | `DELETE /discovery`              | stop peer discovery                             |
+//! | `POST /peers/{address}/connect`  | connect to the peer                             |
+//! | `POST /peers/{address}/invite`   | invite the peer into the group we own           |
+//! | `POST /peers/{address}/reject`   | turn down the peer's connection request         |
+//! | `GET /groups`                    | groups we are a member of                       |
+//! | `POST /groups`                   | create a group as owner                         |
+//! | `DELETE /groups`                 | leave or end every group                        |
+//! | `GET /events`                    | WebSocket, one text message per event           |
+//! | `GET /metrics`                   | Prometheus metrics (with the `metrics` feature) |
+//!
+//! Peers and groups use the crate's serde form. `connect` takes an optional
+//! body `{"method": "pbc" | "display" | "keypad" | "label", "pin": "..."}`,
+//! push button without one, and answers `{"pin": ...}` with the generated PIN
+//! for "display" and `null` otherwise; the other actions answer 204 once the
+//! supplicant has accepted them. Events are
+//! `{"time": <unix seconds>, "type": "<variant>", "data": ...}`, as printed by
+//! `wifip2p watch --json`. Errors are `{"error": {"kind": ..., "message": ...}}`
+//! with a matching status code. There is no authentication: bind to loopback,
+//! or put the router behind your own middleware.
+
+use std::net::SocketAddr;
+
+use axum::body::Bytes;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+#[cfg(feature = "metrics")]
+use axum::http::header::CONTENT_TYPE;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde_json::{Value, json};
+use tokio::sync::oneshot;
+use tokio_stream::StreamExt;
+
+use crate::channel::WifiP2pChannel;
+use crate::device::P2pDevice;
+use crate::error::P2pError;
+use crate::group::P2pGroup;
+use crate::json::{ConnectOptions, event_json};
+use crate::mac::MacAddress;
+
+/// Serve the API for `channel` on `addr` until the manager stops or serving fails.
+pub async fn serve(channel: WifiP2pChannel, addr: SocketAddr) -> Result<(), P2pError> {
+    let listener = std::net::TcpListener::bind(addr).map_err(http_error)?;
+    serve_listener(channel, listener).await
+}
+
+/// Like `serve`, on a socket that is already listening, e.g. one from
+/// `systemd::listen_fds`.
+pub async fn serve_listener(
+    channel: WifiP2pChannel,
+    listener: std::net::TcpListener,
+) -> Result<(), P2pError> {
+    listener.set_nonblocking(true).map_err(http_error)?;
+    let listener = tokio::net::TcpListener::from_std(listener).map_err(http_error)?;
+    let stopped = channel.clone();
+    axum::serve(listener, router(channel))
+        .with_graceful_shutdown(async move { stopped.closed().await })
+        .await
+        .map_err(http_error)
+}
+
+fn http_error(err: std::io::Error) -> P2pError {
+    P2pError::Backend(format!("http: {err}"))
+}
+
+/// The API for `channel` as a router, to nest in your own application.
+pub fn router(channel: WifiP2pChannel) -> Router {
+    let router = Router::new()
+        .route("/peers", get(peers))
+        .route("/discovery", post(discover).delete(stop_discovery))
+        .route("/peers/:address/connect", post(connect))
+        .route("/peers/:address/invite", post(invite))
+        .route("/peers/:address/reject", post(reject))
+        .route(
+            "/groups",
+            get(groups).post(create_group).delete(remove_group),
+        )
+        .route("/events", get(events));
+    #[cfg(feature = "metrics")]
+    let router = router.route("/metrics", get(metrics));
+    router.with_state(channel)
+}
+
+type ApiResult<T> = Result<T, ApiError>;
+
+/// A `P2pError` answered with a status code and its serde form.
+struct ApiError(P2pError);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = match self.0 {
+            P2pError::InvalidArgs(_)
+            | P2pError::InvalidMacAddress(_)
+            | P2pError::InvalidConfig(_) => StatusCode::BAD_REQUEST,
+            P2pError::Unsupported(_) | P2pError::UnsupportedPlatform(_) => {
+                StatusCode::NOT_IMPLEMENTED
+            }
+            P2pError::PermissionDenied(_) => StatusCode::FORBIDDEN,
+            P2pError::NotConnected(_) | P2pError::Cancelled(_) => StatusCode::CONFLICT,
+            P2pError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            P2pError::Busy(_) | P2pError::SupplicantUnavailable(_) | P2pError::RadioBlocked(_) => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        (status, Json(json!({ "error": self.0 }))).into_response()
+    }
+}
+
+/// Wait for a queued command's answer.
+async fn answer<T>(
+    queued: impl Future<Output = Result<oneshot::Receiver<Result<T, P2pError>>, P2pError>>,
+) -> ApiResult<T> {
+    let receiver = queued.await.map_err(ApiError)?;
+    receiver
+        .await
+        .unwrap_or_else(|_| Err(P2pError::ChannelClosed("manager".to_string())))
+        .map_err(ApiError)
+}
+
+fn parse_address(address: &str) -> ApiResult<MacAddress> {
+    address.parse().map_err(ApiError)
+}
+
+async fn peers(State(channel): State<WifiP2pChannel>) -> Json<Vec<P2pDevice>> {
+    Json(channel.peers_snapshot())
+}
+
+async fn groups(State(channel): State<WifiP2pChannel>) -> Json<Vec<P2pGroup>> {
+    Json(channel.groups_snapshot())
+}
+
+#[cfg(feature = "metrics")]
+async fn metrics(State(channel): State<WifiP2pChannel>) -> impl IntoResponse {
+    (
+        [(CONTENT_TYPE, "text/plain; version=0.0.4")],
+        channel.metrics().render(),
+    )
+}
+
+async fn discover(State(channel): State<WifiP2pChannel>) -> ApiResult<StatusCode> {
+    answer(channel.discover_peers()).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn stop_discovery(State(channel): State<WifiP2pChannel>) -> ApiResult<StatusCode> {
+    answer(channel.stop_discovery()).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn connect(
+    State(channel): State<WifiP2pChannel>,
+    Path(address): Path<String>,
+    body: Bytes,
+) -> ApiResult<Json<Value>> {
+    let body: ConnectOptions = if body.is_empty() {
+        ConnectOptions::default()
+    } else {
+        serde_json::from_slice(&body)
+            .map_err(|err| ApiError(P2pError::InvalidArgs(format!("connect body: {err}"))))?
+    };
+    let config = body.config(parse_address(&address)?);
+    let pin = answer(channel.connect_with_config(config)).await?;
+    Ok(Json(json!({ "pin": pin })))
+}
+
+async fn invite(
+    State(channel): State<WifiP2pChannel>,
+    Path(address): Path<String>,
+) -> ApiResult<StatusCode> {
+    answer(channel.invite(parse_address(&address)?)).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn reject(
+    State(channel): State<WifiP2pChannel>,
+    Path(address): Path<String>,
+) -> ApiResult<StatusCode> {
+    answer(channel.reject_peer(parse_address(&address)?)).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn create_group(State(channel): State<WifiP2pChannel>) -> ApiResult<StatusCode> {
+    answer(channel.create_group()).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn remove_group(State(channel): State<WifiP2pChannel>) -> ApiResult<StatusCode> {
+    answer(channel.remove_group()).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn events(State(channel): State<WifiP2pChannel>, upgrade: WebSocketUpgrade) -> Response {
+    upgrade.on_upgrade(move |socket| stream_events(channel, socket))
+}
+
+async fn stream_events(channel: WifiP2pChannel, mut socket: WebSocket) {
+    let mut events = channel.event_stream();
+    loop {
+        tokio::select! {
+            event = events.next() => {
+                let Some(event) = event else { break };
+                let text = event_json(&event).to_string();
+                if socket.send(Message::Text(text)).await.is_err() {
+                    break;
+                }
+            }
+            // Anything the client sends is ignored; only a close or error ends the stream.
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+            () = channel.closed() => break,
+        }
+    }
+}
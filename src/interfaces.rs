@@ -0,0 +1,170 @@
+//! Find the wireless interfaces that can do Wi-Fi Direct.
+//!
+//! Dumps the radios and interfaces nl80211 knows: a radio supports P2P when it
+//! lists the P2P client and group owner interface types. Driver and up/down
+//! state come from sysfs. Group interfaces wpa_supplicant creates are left out.
+//! `WifiP2pManager::discover_interfaces` and `WifiP2pManager::auto_interface`
+//! wrap this.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::error::P2pError;
+use crate::mac::MacAddress;
+use crate::net::netlink::{self, attributes, exchange, family_id, generic_message, u32_at};
+
+const NL80211_CMD_GET_WIPHY: u8 = 1;
+const NL80211_CMD_GET_INTERFACE: u8 = 5;
+const NL80211_ATTR_WIPHY: u16 = 1;
+const NL80211_ATTR_WIPHY_NAME: u16 = 2;
+const NL80211_ATTR_IFNAME: u16 = 4;
+const NL80211_ATTR_IFTYPE: u16 = 5;
+const NL80211_ATTR_MAC: u16 = 6;
+const NL80211_ATTR_SUPPORTED_IFTYPES: u16 = 32;
+const NL80211_IFTYPE_P2P_CLIENT: u16 = 8;
+const NL80211_IFTYPE_P2P_GO: u16 = 9;
+const NL80211_IFTYPE_P2P_DEVICE: u16 = 10;
+const P2P_IFTYPES: [u16; 3] = [
+    NL80211_IFTYPE_P2P_CLIENT,
+    NL80211_IFTYPE_P2P_GO,
+    NL80211_IFTYPE_P2P_DEVICE,
+];
+// IFF_UP in /sys/class/net/<interface>/flags.
+const IFF_UP: u32 = 0x1;
+
+/// A wireless network interface and what its radio supports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WirelessInterface {
+    /// Interface name, e.g. `"wlan0"`.
+    pub name: String,
+    /// The radio it belongs to, e.g. `"phy0"`.
+    pub phy: String,
+    pub mac_address: Option<MacAddress>,
+    /// Kernel driver, e.g. `"iwlwifi"`.
+    pub driver: Option<String>,
+    /// Whether the interface is administratively up.
+    pub up: bool,
+    /// Whether the radio can run P2P client and group owner interfaces.
+    pub supports_p2p: bool,
+    /// Whether the radio has a dedicated P2P device, so discovery can run next
+    /// to a station connection.
+    pub p2p_device: bool,
+}
+
+#[derive(Default)]
+struct Phy {
+    name: String,
+    iftypes: Vec<u16>,
+}
+
+/// Every wireless interface, sorted by name. Empty when nl80211 is not there
+/// (no wireless drivers loaded).
+pub fn discover_interfaces() -> Result<Vec<WirelessInterface>, P2pError> {
+    discover().map_err(|err| P2pError::Backend(format!("nl80211: {err}")))
+}
+
+/// The interface most likely meant for Wi-Fi Direct: one whose radio supports
+/// P2P, preferring interfaces that are up, then radios with a P2P device.
+pub fn auto_interface() -> Result<String, P2pError> {
+    discover_interfaces()?
+        .into_iter()
+        .filter(|interface| interface.supports_p2p)
+        .max_by_key(|interface| (interface.up, interface.p2p_device))
+        .map(|interface| interface.name)
+        .ok_or_else(|| {
+            P2pError::Unsupported("no wireless interface supports Wi-Fi Direct".to_string())
+        })
+}
+
+fn discover() -> io::Result<Vec<WirelessInterface>> {
+    let socket = netlink::open(libc::NETLINK_GENERIC, 0, 0)?;
+    let family = match family_id(&socket, "nl80211") {
+        Ok(family) => family,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    let mut phys: HashMap<u32, Phy> = HashMap::new();
+    let request = generic_message(family, libc::NLM_F_DUMP, NL80211_CMD_GET_WIPHY, &[]);
+    exchange(&socket, &request, |payload| {
+        let mut index = None;
+        let mut phy = Phy::default();
+        for (kind, value) in attributes(payload) {
+            match kind {
+                NL80211_ATTR_WIPHY if value.len() == 4 => index = Some(u32_at(value, 0)),
+                NL80211_ATTR_WIPHY_NAME => phy.name = c_string(value),
+                NL80211_ATTR_SUPPORTED_IFTYPES => {
+                    phy.iftypes = attributes(value).map(|(iftype, _)| iftype).collect();
+                }
+                _ => {}
+            }
+        }
+        // Large radios may be split over several messages; keep what each adds.
+        if let Some(index) = index {
+            let known = phys.entry(index).or_default();
+            if !phy.name.is_empty() {
+                known.name = phy.name;
+            }
+            known.iftypes.extend(phy.iftypes);
+        }
+    })?;
+
+    let mut interfaces = Vec::new();
+    let request = generic_message(family, libc::NLM_F_DUMP, NL80211_CMD_GET_INTERFACE, &[]);
+    exchange(&socket, &request, |payload| {
+        let mut name = None;
+        let mut wiphy = None;
+        let mut iftype = None;
+        let mut mac_address = None;
+        for (kind, value) in attributes(payload) {
+            match kind {
+                NL80211_ATTR_IFNAME => name = Some(c_string(value)),
+                NL80211_ATTR_WIPHY if value.len() == 4 => wiphy = Some(u32_at(value, 0)),
+                NL80211_ATTR_IFTYPE if value.len() == 4 => {
+                    iftype = u16::try_from(u32_at(value, 0)).ok();
+                }
+                NL80211_ATTR_MAC => mac_address = MacAddress::try_from(value).ok(),
+                _ => {}
+            }
+        }
+        let (Some(name), Some(wiphy)) = (name, wiphy) else {
+            return;
+        };
+        // P2P devices have no netdev, and group interfaces come and go with groups.
+        if iftype.is_some_and(|iftype| P2P_IFTYPES.contains(&iftype)) {
+            return;
+        }
+        let phy = phys.get(&wiphy);
+        let supports = |iftype: u16| phy.is_some_and(|phy| phy.iftypes.contains(&iftype));
+        let sysfs = Path::new("/sys/class/net").join(&name);
+        interfaces.push(WirelessInterface {
+            phy: phy.map_or_else(|| format!("phy{wiphy}"), |phy| phy.name.clone()),
+            mac_address,
+            driver: fs::read_link(sysfs.join("device/driver"))
+                .ok()
+                .and_then(|driver| Some(driver.file_name()?.to_string_lossy().into_owned())),
+            up: fs::read_to_string(sysfs.join("flags"))
+                .ok()
+                .and_then(|flags| {
+                    u32::from_str_radix(flags.trim().trim_start_matches("0x"), 16).ok()
+                })
+                .is_some_and(|flags| flags & IFF_UP != 0),
+            supports_p2p: supports(NL80211_IFTYPE_P2P_CLIENT) && supports(NL80211_IFTYPE_P2P_GO),
+            p2p_device: supports(NL80211_IFTYPE_P2P_DEVICE),
+            name,
+        });
+    })?;
+    interfaces.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(interfaces)
+}
+
+/// A NUL-terminated string attribute.
+fn c_string(value: &[u8]) -> String {
+    let end = value
+        .iter()
+        .position(|byte| *byte == 0)
+        .unwrap_or(value.len());
+    String::from_utf8_lossy(&value[..end]).into_owned()
+}
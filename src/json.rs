@@ -0,0 +1,81 @@
+//! JSON shapes shared by the HTTP, JSON-RPC and C APIs, the MQTT bridge and the
+//! language bindings.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(any(feature = "http", feature = "mqtt", feature = "jsonrpc"))]
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+
+use crate::channel::P2pEvent;
+#[cfg(any(feature = "http", feature = "mqtt", feature = "jsonrpc"))]
+use crate::config::{ConnectConfig, WpsMethod};
+#[cfg(any(feature = "http", feature = "mqtt", feature = "jsonrpc"))]
+use crate::mac::MacAddress;
+
+/// `{"method": "pbc" | "display" | "keypad" | "label", "pin": "..."}`, both optional.
+#[cfg(any(feature = "http", feature = "mqtt", feature = "jsonrpc"))]
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct ConnectOptions {
+    #[serde(default)]
+    method: Method,
+    pin: Option<String>,
+}
+
+#[cfg(any(feature = "http", feature = "mqtt", feature = "jsonrpc"))]
+impl ConnectOptions {
+    pub(crate) fn config(self, device_address: MacAddress) -> ConnectConfig {
+        let wps_method = match self.method {
+            Method::Pbc => WpsMethod::Pbc,
+            Method::Display => WpsMethod::PinDisplay,
+            Method::Keypad => WpsMethod::PinKeypad,
+            Method::Label => WpsMethod::PinLabel,
+        };
+        ConnectConfig {
+            device_address,
+            wps_method,
+            pin: self.pin,
+        }
+    }
+}
+
+/// The names wpa_supplicant uses, rather than `WpsMethod`'s serde form.
+#[cfg(any(feature = "http", feature = "mqtt", feature = "jsonrpc"))]
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Method {
+    #[default]
+    Pbc,
+    Display,
+    Keypad,
+    Label,
+}
+
+/// `{"time": <unix seconds>, "type": "<variant>", "data": ...}`, with `data`
+/// left out for variants without fields, as `wifip2p watch --json` prints.
+pub(crate) fn event_json(event: &P2pEvent) -> Value {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |since| since.as_secs_f64());
+    let mut line = Map::new();
+    line.insert("time".to_string(), json!(time));
+    // serde tags unit variants as a bare string and the rest as {"Variant": data}.
+    match serde_json::to_value(event) {
+        Ok(Value::String(kind)) => {
+            line.insert("type".to_string(), Value::String(kind));
+        }
+        Ok(Value::Object(tagged)) => {
+            for (kind, data) in tagged {
+                line.insert("type".to_string(), Value::String(kind));
+                line.insert("data".to_string(), data);
+            }
+        }
+        Ok(other) => {
+            line.insert("data".to_string(), other);
+        }
+        Err(err) => {
+            line.insert("error".to_string(), Value::String(err.to_string()));
+        }
+    }
+    Value::Object(line)
+}
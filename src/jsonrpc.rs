@@ -0,0 +1,448 @@
+//! JSON-RPC 2.0 over a Unix socket, one JSON object per line, for shell
+//! scripts and languages without a gRPC or HTTP stack:
+//!
+//! ```sh
+//! echo '{"jsonrpc": "2.0", "id": 1, "method": "request_peers"}' | socat - UNIX:/run/wifip2p.sock
+//! ```
+//!
+//! | Method                 | Params                                         | Result           |
+//! |------------------------|------------------------------------------------|------------------|
+//! | `discover_peers`       |                                                | `null`           |
+//! | `stop_discovery`       |                                                | `null`           |
+//! | `connect`              | `address`, `method`, `pin`, `timeout_ms`       | `{"pin": ...}`   |
+//! | `cancel_connect`       |                                                | `null`           |
+//! | `reinvoke`             | `address`                                      | `null`           |
+//! | `request_peers`        |                                                | devices          |
+//! | `request_groups`       |                                                | groups           |
+//! | `peers`                |                                                | devices          |
+//! | `groups`               |                                                | groups           |
+//! | `queue_status`         |                                                | `QueueStatus`    |
+//! | `create_group`         |                                                | `null`           |
+//! | `invite`               | `address`                                      | `null`           |
+//! | `reject_peer`          | `address`                                      | `null`           |
+//! | `remove_group`         |                                                | `null`           |
+//! | `add_local_service`    | `service`                                      | `null`           |
+//! | `remove_local_service` | `service`                                      | `null`           |
+//! | `discover_services`    | `address` (optional)                           | `null`           |
+//! | `set_noa`              | `schedule`                                     | `null`           |
+//! | `set_vendor_elements`  | `frame`, `elements` (bytes)                    | `null`           |
+//! | `resolve_peer_ip`      | `address`                                      | address or `null`|
+//! | `watch_link_quality`   | `address`, `thresholds`                        | `null`           |
+//! | `unwatch_link_quality` | `address`                                      | `null`           |
+//! | `subscribe`            | `kinds` (optional `EventKind`s)                | `null`           |
+//! | `unsubscribe`          |                                                | `null`           |
+//!
+//! Params are a JSON object, and values use the crate's serde form; `connect`'s
+//! `method` is "pbc" (the default), "display", "keypad" or "label". After
+//! `subscribe`, events arrive on the same connection as `event` notifications
+//! whose params are `{"time": <unix seconds>, "type": "<variant>", "data": ...}`;
+//! subscribing again replaces the filter. Requests run concurrently and are
+//! answered in the order they finish. A failed action is error code -32000
+//! with `{"kind": ..., "message": ...}` as its data. Batches are not supported.
+//! Anyone who can open the socket can drive the radio, so set its permissions.
+
+use std::os::unix::net::UnixListener as StdUnixListener;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value, json};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio_stream::StreamExt;
+
+use crate::channel::{EventKind, WifiP2pChannel};
+use crate::error::P2pError;
+use crate::group::NoaSchedule;
+use crate::json::{ConnectOptions, event_json};
+use crate::mac::MacAddress;
+use crate::service::LocalService;
+use crate::stats::LinkThresholds;
+use crate::stream::EventFilter;
+use crate::vendor::VendorElementFrame;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// A `P2pError` from the manager, in the range JSON-RPC leaves to servers.
+const ACTION_FAILED: i64 = -32000;
+
+/// Lines waiting to be written to one client before its requests hold back.
+const OUTGOING_LINES: usize = 64;
+
+/// Serve `channel` on a Unix socket at `path` until the manager stops. A file
+/// left at `path`, e.g. by an earlier run, is replaced.
+pub async fn serve(channel: WifiP2pChannel, path: impl AsRef<Path>) -> Result<(), P2pError> {
+    let path = path.as_ref();
+    match std::fs::remove_file(path) {
+        Ok(()) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => return Err(jsonrpc_error(err)),
+    }
+    let listener = StdUnixListener::bind(path).map_err(jsonrpc_error)?;
+    serve_listener(channel, listener).await
+}
+
+/// Like `serve`, on a socket that is already listening, e.g. one from
+/// `systemd::listen_fds`.
+pub async fn serve_listener(
+    channel: WifiP2pChannel,
+    listener: StdUnixListener,
+) -> Result<(), P2pError> {
+    listener.set_nonblocking(true).map_err(jsonrpc_error)?;
+    let listener = UnixListener::from_std(listener).map_err(jsonrpc_error)?;
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (stream, _) = accepted.map_err(jsonrpc_error)?;
+                tokio::spawn(session(channel.clone(), stream));
+            }
+            () = channel.closed() => return Ok(()),
+        }
+    }
+}
+
+fn jsonrpc_error(err: std::io::Error) -> P2pError {
+    P2pError::Backend(format!("jsonrpc: {err}"))
+}
+
+/// One client connection, until it hangs up or the manager stops.
+async fn session(channel: WifiP2pChannel, stream: UnixStream) {
+    let (reader, mut writer) = stream.into_split();
+    let (outgoing, mut lines_out) = mpsc::channel::<String>(OUTGOING_LINES);
+    let writing = tokio::spawn(async move {
+        while let Some(mut line) = lines_out.recv().await {
+            line.push('\n');
+            if writer.write_all(line.as_bytes()).await.is_err() {
+                break;
+            }
+        }
+    });
+    let mut lines = BufReader::new(reader).lines();
+    let mut subscription: Option<JoinHandle<()>> = None;
+    loop {
+        let line = tokio::select! {
+            line = lines.next_line() => match line {
+                Ok(Some(line)) => line,
+                Ok(None) | Err(_) => break,
+            },
+            () = channel.closed() => break,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request = match Request::parse(&line) {
+            Ok(request) => request,
+            Err((id, error)) => {
+                let _ = outgoing.send(response(id, Err(error))).await;
+                continue;
+            }
+        };
+        // Subscriptions belong to the connection, so they are handled here.
+        let result = match request.method.as_str() {
+            "subscribe" => params::<Subscribe>(request.params).map(|Subscribe { kinds }| {
+                if let Some(previous) = subscription.take() {
+                    previous.abort();
+                }
+                let filter = kinds
+                    .into_iter()
+                    .fold(EventFilter::all(), EventFilter::with);
+                subscription = Some(tokio::spawn(forward_events(
+                    channel.clone(),
+                    filter,
+                    outgoing.clone(),
+                )));
+                Value::Null
+            }),
+            "unsubscribe" => {
+                if let Some(previous) = subscription.take() {
+                    previous.abort();
+                }
+                Ok(Value::Null)
+            }
+            _ => {
+                let channel = channel.clone();
+                let outgoing = outgoing.clone();
+                tokio::spawn(async move {
+                    let result = call(&channel, &request.method, request.params).await;
+                    if let Some(id) = request.id {
+                        let _ = outgoing.send(response(id, result)).await;
+                    }
+                });
+                continue;
+            }
+        };
+        if let Some(id) = request.id {
+            let _ = outgoing.send(response(id, result)).await;
+        }
+    }
+    if let Some(subscription) = subscription {
+        subscription.abort();
+    }
+    drop(outgoing);
+    let _ = writing.await;
+}
+
+async fn forward_events(
+    channel: WifiP2pChannel,
+    filter: EventFilter,
+    outgoing: mpsc::Sender<String>,
+) {
+    let mut events = channel.subscribe_filtered(filter);
+    while let Some(event) = events.next().await {
+        let notification = json!({
+            "jsonrpc": "2.0",
+            "method": "event",
+            "params": event_json(&event),
+        });
+        if outgoing.send(notification.to_string()).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// A parsed request; `id` is `None` for notifications, which get no response.
+struct Request {
+    id: Option<Value>,
+    method: String,
+    params: Value,
+}
+
+impl Request {
+    /// The request in `line`, or the id to answer with and why it was refused.
+    fn parse(line: &str) -> Result<Self, (Value, RpcError)> {
+        let value: Value = serde_json::from_str(line)
+            .map_err(|err| (Value::Null, RpcError::new(PARSE_ERROR, err.to_string())))?;
+        let Value::Object(mut request) = value else {
+            let message = "expected a request object; batches are not supported";
+            return Err((Value::Null, RpcError::new(INVALID_REQUEST, message)));
+        };
+        let id = request.remove("id");
+        let refuse = |message: &str| {
+            let id = id.clone().unwrap_or(Value::Null);
+            (id, RpcError::new(INVALID_REQUEST, message))
+        };
+        if request.get("jsonrpc") != Some(&Value::String("2.0".to_string())) {
+            return Err(refuse("jsonrpc must be \"2.0\""));
+        }
+        let method = match request.remove("method") {
+            Some(Value::String(method)) => method,
+            _ => return Err(refuse("method must be a string")),
+        };
+        let params = match request.remove("params") {
+            None | Some(Value::Null) => Value::Object(Map::new()),
+            Some(params @ Value::Object(_)) => params,
+            Some(_) => return Err(refuse("params must be an object")),
+        };
+        Ok(Self { id, method, params })
+    }
+}
+
+struct RpcError {
+    code: i64,
+    message: String,
+    data: Option<Value>,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+}
+
+impl From<P2pError> for RpcError {
+    fn from(err: P2pError) -> Self {
+        let code = match err {
+            P2pError::InvalidArgs(_) | P2pError::InvalidMacAddress(_) => INVALID_PARAMS,
+            _ => ACTION_FAILED,
+        };
+        Self {
+            code,
+            message: err.to_string(),
+            data: Some(json!(err)),
+        }
+    }
+}
+
+fn response(id: Value, result: Result<Value, RpcError>) -> String {
+    let response = match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => {
+            let mut body = Map::new();
+            body.insert("code".to_string(), json!(error.code));
+            body.insert("message".to_string(), json!(error.message));
+            if let Some(data) = error.data {
+                body.insert("data".to_string(), data);
+            }
+            json!({ "jsonrpc": "2.0", "id": id, "error": body })
+        }
+    };
+    response.to_string()
+}
+
+/// No `kinds`, or none at all, subscribes to every event.
+#[derive(Deserialize)]
+struct Subscribe {
+    #[serde(default)]
+    kinds: Vec<EventKind>,
+}
+
+#[derive(Deserialize)]
+struct Address {
+    address: MacAddress,
+}
+
+#[derive(Deserialize)]
+struct OptionalAddress {
+    address: Option<MacAddress>,
+}
+
+#[derive(Deserialize)]
+struct ConnectParams {
+    address: MacAddress,
+    timeout_ms: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct Service {
+    service: LocalService,
+}
+
+#[derive(Deserialize)]
+struct Noa {
+    schedule: NoaSchedule,
+}
+
+#[derive(Deserialize)]
+struct VendorElements {
+    frame: VendorElementFrame,
+    elements: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+struct Watch {
+    address: MacAddress,
+    thresholds: LinkThresholds,
+}
+
+/// Run `method` on the channel, returning its result as JSON.
+async fn call(channel: &WifiP2pChannel, method: &str, args: Value) -> Result<Value, RpcError> {
+    let done = |()| Value::Null;
+    let result = match method {
+        "discover_peers" => answer(channel.discover_peers()).await.map(done),
+        "stop_discovery" => answer(channel.stop_discovery()).await.map(done),
+        "connect" => {
+            let target: ConnectParams = params(args.clone())?;
+            let options: ConnectOptions = params(args)?;
+            let config = options.config(target.address);
+            let pin = match target.timeout_ms {
+                Some(ms) => {
+                    let timeout = Duration::from_millis(ms);
+                    answer(channel.connect_with_timeout(config, timeout)).await
+                }
+                None => answer(channel.connect_with_config(config)).await,
+            };
+            pin.map(|pin| json!({ "pin": pin }))
+        }
+        "cancel_connect" => answer(channel.cancel_connect()).await.map(done),
+        "reinvoke" => {
+            let Address { address } = params(args)?;
+            answer(channel.reinvoke(address)).await.map(done)
+        }
+        "request_peers" => answer(channel.request_peers())
+            .await
+            .map(|peers| json!(peers)),
+        "request_groups" => answer(channel.request_groups())
+            .await
+            .map(|groups| json!(groups)),
+        "peers" => Ok(json!(channel.peers_snapshot())),
+        "groups" => Ok(json!(channel.groups_snapshot())),
+        "queue_status" => Ok(json!(channel.queue_status())),
+        "create_group" => answer(channel.create_group()).await.map(done),
+        "invite" => {
+            let Address { address } = params(args)?;
+            answer(channel.invite(address)).await.map(done)
+        }
+        "reject_peer" => {
+            let Address { address } = params(args)?;
+            answer(channel.reject_peer(address)).await.map(done)
+        }
+        "remove_group" => answer(channel.remove_group()).await.map(done),
+        "add_local_service" => {
+            let Service { service } = params(args)?;
+            answer(channel.add_local_service(service)).await.map(done)
+        }
+        "remove_local_service" => {
+            let Service { service } = params(args)?;
+            answer(channel.remove_local_service(service))
+                .await
+                .map(done)
+        }
+        "discover_services" => {
+            let OptionalAddress { address } = params(args)?;
+            match address {
+                Some(address) => answer(channel.discover_services_from(address)).await,
+                None => answer(channel.discover_services()).await,
+            }
+            .map(done)
+        }
+        "set_noa" => {
+            let Noa { schedule } = params(args)?;
+            answer(channel.set_noa(schedule)).await.map(done)
+        }
+        "set_vendor_elements" => {
+            let VendorElements { frame, elements } = params(args)?;
+            answer(channel.set_vendor_elements(frame, elements))
+                .await
+                .map(done)
+        }
+        "resolve_peer_ip" => {
+            let Address { address } = params(args)?;
+            answer(channel.resolve_peer_ip(address))
+                .await
+                .map(|ip| json!(ip))
+        }
+        "watch_link_quality" => {
+            let Watch {
+                address,
+                thresholds,
+            } = params(args)?;
+            answer(channel.watch_link_quality(address, thresholds))
+                .await
+                .map(done)
+        }
+        "unwatch_link_quality" => {
+            let Address { address } = params(args)?;
+            answer(channel.unwatch_link_quality(address))
+                .await
+                .map(done)
+        }
+        other => {
+            let message = format!("unknown method {other}");
+            return Err(RpcError::new(METHOD_NOT_FOUND, message));
+        }
+    };
+    result.map_err(RpcError::from)
+}
+
+fn params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|err| RpcError::new(INVALID_PARAMS, err.to_string()))
+}
+
+/// Wait for a queued command's answer.
+async fn answer<T>(
+    queued: impl Future<Output = Result<oneshot::Receiver<Result<T, P2pError>>, P2pError>>,
+) -> Result<T, P2pError> {
+    queued
+        .await?
+        .await
+        .unwrap_or_else(|_| Err(P2pError::ChannelClosed("manager".to_string())))
+}
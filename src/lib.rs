@@ -1,11 +1,142 @@
+#[cfg(feature = "tokio")]
+pub mod android_compat;
+pub mod approver;
 pub mod backend;
+#[cfg(feature = "uniffi")]
+mod bindings;
+#[cfg(feature = "tokio")]
+pub mod blocking;
+pub mod builder;
+pub mod capabilities;
 pub mod channel;
+pub mod config;
+pub mod credentials;
+#[cfg(feature = "daemon")]
+pub mod daemon;
 pub mod device;
+#[cfg(target_os = "linux")]
+pub mod diagnose;
 pub mod error;
+pub mod executor;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod group;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod health;
+#[cfg(feature = "http")]
+pub mod http;
+#[cfg(all(feature = "interfaces", target_os = "linux"))]
+pub mod interfaces;
+#[cfg(any(
+    feature = "http",
+    feature = "mqtt",
+    feature = "jsonrpc",
+    feature = "ffi",
+    feature = "node",
+    feature = "python"
+))]
+mod json;
+#[cfg(all(feature = "jsonrpc", unix))]
+pub mod jsonrpc;
+pub mod listener;
+pub mod mac;
 pub mod manager;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+pub mod net;
+#[cfg(feature = "node")]
+mod node;
+#[cfg(feature = "tokio")]
+pub mod pair;
+pub mod pairing;
+pub mod peer_filter;
+pub mod probe;
+#[cfg(feature = "python")]
+mod python;
+#[cfg(target_os = "linux")]
+pub mod rfkill;
+pub mod service;
+pub mod state;
+pub mod stats;
+pub mod status;
+pub mod stream;
+pub mod supervisor;
+#[cfg(all(feature = "systemd", unix))]
+pub mod systemd;
+#[cfg(feature = "hwsim")]
+pub mod testing;
+pub mod transfer;
+pub mod vendor;
+pub mod wfd;
 
+pub use approver::{ConnectionApprover, ConnectionRequest, Decision, RequestKind};
+#[cfg(all(feature = "android", target_os = "android"))]
+pub use backend::AndroidBackend;
+#[cfg(target_os = "linux")]
+pub use backend::InterfaceConfig;
+#[cfg(target_os = "linux")]
+pub use backend::IwdBackend;
+#[cfg(any(feature = "noop", not(any(target_os = "linux", target_os = "windows"))))]
+pub use backend::NoopBackend;
+#[cfg(target_os = "windows")]
+pub use backend::WindowsBackend;
 pub use backend::{P2pBackend, P2pBackendImpl};
-pub use channel::{P2pEvent, WifiP2pChannel};
-pub use device::P2pDevice;
+#[cfg(feature = "tokio")]
+pub use blocking::{BlockingChannel, BlockingWifiP2pManager};
+pub use builder::{QueueOverflow, ReconnectPolicy, WifiP2pManagerBuilder};
+pub use capabilities::P2pCapabilities;
+pub use channel::{EventKind, P2pEvent, WifiP2pChannel};
+pub use config::{
+    ConnectConfig, DiscoverabilityPolicy, DiscoveryOptions, DiscoveryType, WpsMethod,
+};
+#[cfg(all(feature = "secret-service", target_os = "linux"))]
+pub use credentials::SecretServiceCredentialStore;
+pub use credentials::{CredentialStore, FileCredentialStore, GroupCredentials};
+pub use device::{DeviceStatus, LocalDeviceInfo, P2pDevice};
+#[cfg(target_os = "linux")]
+pub use diagnose::{Diagnosis, Finding, Severity, diagnose};
 pub use error::P2pError;
+pub use executor::Executor;
+#[cfg(feature = "tokio")]
+pub use executor::TokioExecutor;
+pub use group::{GroupRole, IpAllocation, IpAllocationPool, NoaSchedule, P2pGroup};
+pub use health::{HealthReport, QueueStatus, RunningCommand};
+#[cfg(all(feature = "interfaces", target_os = "linux"))]
+pub use interfaces::WirelessInterface;
+pub use listener::{ListenerHandle, P2pEventListener};
+pub use mac::MacAddress;
 pub use manager::WifiP2pManager;
+#[cfg(feature = "metrics")]
+pub use metrics::Metrics;
+#[cfg(feature = "tokio")]
+pub use net::socket::P2pSocket;
+#[cfg(feature = "tokio")]
+pub use pair::{PairOptions, PairedLink, PeerSelector, Provisioning};
+pub use pairing::qr::{DppUri, QrPayload};
+pub use peer_filter::PeerFilter;
+pub use probe::{LinkReport, ProbeOptions};
+#[cfg(target_os = "linux")]
+pub use rfkill::RadioState;
+#[cfg(all(feature = "rfkill", target_os = "linux"))]
+pub use rfkill::unblock_radio;
+pub use service::{
+    AspAdvertisement, AspProvision, AspService, DnsSdRecord, DnsSdServiceInfo,
+    DnsSdServiceResponse, DnsSdTxtRecord, LocalService, ServiceProtocol, ServiceResponse,
+    ServiceTlv, UpnpResponse, UpnpServiceInfo,
+};
+pub use state::{DiscoveryState, ListenState, P2pState};
+pub use stats::{LinkIssue, LinkStats, LinkThresholds};
+pub use status::P2pStatusCode;
+pub use stream::{EventFilter, P2pEventStream};
+pub use supervisor::{ConnectionSupervisor, SupervisorPolicy, SupervisorState};
+pub use transfer::{TransferDirection, TransferProgress};
+pub use vendor::VendorElementFrame;
+#[cfg(feature = "tokio")]
+pub use wfd::WfdSession;
+pub use wfd::{WfdDeviceInfo, WfdDeviceType, WfdRole};
+
+#[cfg(feature = "uniffi")]
+uniffi::setup_scaffolding!();
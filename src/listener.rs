@@ -0,0 +1,222 @@
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use tokio::sync::Notify;
+use tokio_stream::StreamExt;
+
+use crate::approver::ConnectionRequest;
+use crate::channel::P2pEvent;
+use crate::device::{DeviceStatus, LocalDeviceInfo, P2pDevice};
+use crate::executor::Executor;
+use crate::group::{NoaSchedule, P2pGroup};
+use crate::mac::MacAddress;
+use crate::service::{AspAdvertisement, DnsSdServiceResponse, DnsSdTxtRecord, ServiceResponse};
+use crate::state::ListenState;
+use crate::stats::{LinkIssue, LinkStats};
+use crate::status::P2pStatusCode;
+use crate::stream::P2pEventStream;
+use crate::supervisor::SupervisorState;
+use crate::transfer::{TransferDirection, TransferProgress};
+
+/// Callback-style event listener, similar to registering a BroadcastReceiver on Android.
+///
+/// Every method has an empty default, so implementors only override what they need.
+/// Callbacks run on a dedicated dispatch task, one event at a time.
+pub trait P2pEventListener: Send + 'static {
+    /// Called for every event before the specific callback below.
+    fn on_event(&mut self, _event: &P2pEvent) {}
+    /// Discovery became active (`true`) or stopped (`false`).
+    fn on_discovery_changed(&mut self, _active: bool) {}
+    /// We became discoverable (`true`) or stopped listening (`false`).
+    fn on_listen_changed(&mut self, _listening: bool) {}
+    fn on_peer_found(&mut self, _device: &P2pDevice) {}
+    fn on_peer_lost(&mut self, _address: MacAddress) {}
+    /// The manager's peer table changed (see `P2pEvent::PeersChanged`).
+    fn on_peers_changed(
+        &mut self,
+        _added: &[Arc<P2pDevice>],
+        _removed: &[MacAddress],
+        _updated: &[Arc<P2pDevice>],
+    ) {
+    }
+    fn on_peer_status_changed(&mut self, _address: MacAddress, _status: DeviceStatus) {}
+    /// Our own device's name, address or WPS settings changed.
+    fn on_this_device_changed(&mut self, _device: &LocalDeviceInfo) {}
+    /// A peer answered a service discovery query.
+    fn on_services_found(&mut self, _response: &ServiceResponse) {}
+    /// A peer offers a Bonjour service instance.
+    fn on_dns_sd_service_available(&mut self, _response: &DnsSdServiceResponse) {}
+    /// A peer sent a Bonjour TXT record.
+    fn on_dns_sd_txt_record_available(&mut self, _record: &DnsSdTxtRecord) {}
+    /// A peer advertises a P2PS service.
+    fn on_asp_service_found(&mut self, _peer: MacAddress, _service: &AspAdvertisement) {}
+    /// A peer asked to connect to us.
+    fn on_connection_requested(&mut self, _address: MacAddress) {}
+    /// A peer's request no `ConnectionApprover` decided.
+    fn on_incoming_request(&mut self, _request: &ConnectionRequest) {}
+    /// An approved request needs `pin` shown to the user.
+    fn on_pin_to_display(&mut self, _peer: MacAddress, _pin: &str) {}
+    /// A connect request to `address` succeeded.
+    fn on_connection_changed(&mut self, _address: MacAddress) {}
+    /// Negotiation, provisioning or invitation with a peer failed.
+    fn on_connection_failed(&mut self, _peer: Option<MacAddress>, _status: P2pStatusCode) {}
+    fn on_group_created(&mut self) {}
+    fn on_noa_updated(&mut self, _schedule: NoaSchedule) {}
+    fn on_group_started(&mut self, _group: &P2pGroup) {}
+    fn on_group_finished(&mut self, _group: &P2pGroup) {}
+    /// A connected peer's address on the group interface became known.
+    fn on_peer_ip_resolved(&mut self, _address: MacAddress, _ip: IpAddr) {}
+    fn on_network_setup_failed(&mut self, _interface: &str, _reason: &str) {}
+    fn on_ip_configured(&mut self, _ip: IpAddr) {}
+    fn on_group_interface_changed(&mut self, _ifname: &str, _up: bool) {}
+    fn on_group_address_changed(&mut self, _ifname: &str, _ip: IpAddr, _added: bool) {}
+    fn on_transfer_progress(&mut self, _progress: &TransferProgress) {}
+    fn on_transfer_completed(&mut self, _direction: TransferDirection, _file_name: &str) {}
+    fn on_transfer_failed(
+        &mut self,
+        _direction: TransferDirection,
+        _file_name: &str,
+        _reason: &str,
+    ) {
+    }
+    fn on_link_stats(&mut self, _stats: &LinkStats) {}
+    fn on_link_degraded(&mut self, _peer: MacAddress, _issue: LinkIssue) {}
+    fn on_link_recovered(&mut self, _peer: MacAddress) {}
+    fn on_credential_store_failed(&mut self, _reason: &str) {}
+    fn on_supervisor_state_changed(&mut self, _peer: MacAddress, _state: SupervisorState) {}
+    /// The supplicant went away (`available == false`) or the manager re-attached.
+    fn on_backend_changed(&mut self, _available: bool) {}
+    /// The interface was unplugged (`present == false`) or appeared and was attached.
+    fn on_adapter_changed(&mut self, _interface: &str, _present: bool) {}
+}
+
+/// Handle returned by `WifiP2pChannel::register_listener`.
+///
+/// Dropping the handle leaves the listener registered; call `unregister` to stop it.
+pub struct ListenerHandle {
+    stop: Arc<Notify>,
+    finished: Arc<AtomicBool>,
+}
+
+impl ListenerHandle {
+    pub(crate) fn spawn(
+        mut listener: impl P2pEventListener,
+        mut events: P2pEventStream,
+        executor: &dyn Executor,
+    ) -> Self {
+        let stop = Arc::new(Notify::new());
+        let finished = Arc::new(AtomicBool::new(false));
+        let (stop_task, finished_task) = (Arc::clone(&stop), Arc::clone(&finished));
+        executor.spawn(Box::pin(async move {
+            loop {
+                tokio::select! {
+                    biased;
+                    () = stop_task.notified() => break,
+                    event = events.next() => match event {
+                        Some(event) => dispatch(&mut listener, &event),
+                        None => break,
+                    },
+                }
+            }
+            finished_task.store(true, Ordering::Release);
+        }));
+        Self { stop, finished }
+    }
+
+    /// Stop delivering events to the listener and drop it.
+    pub fn unregister(self) {
+        // notify_one stores a permit, so this works even between two events.
+        self.stop.notify_one();
+    }
+
+    /// Whether the dispatch task is still running.
+    pub fn is_registered(&self) -> bool {
+        !self.finished.load(Ordering::Acquire)
+    }
+}
+
+fn dispatch(listener: &mut impl P2pEventListener, event: &P2pEvent) {
+    listener.on_event(event);
+    match event {
+        P2pEvent::DiscoveryStarted => listener.on_discovery_changed(true),
+        P2pEvent::DiscoveryStopped => listener.on_discovery_changed(false),
+        P2pEvent::ListenStateChanged(state) => {
+            listener.on_listen_changed(*state == ListenState::Started)
+        }
+        P2pEvent::GroupCreated => listener.on_group_created(),
+        P2pEvent::Connected(address) => listener.on_connection_changed(*address),
+        P2pEvent::PeerFound(device) => listener.on_peer_found(device),
+        P2pEvent::PeerLost(address) => listener.on_peer_lost(*address),
+        P2pEvent::PeersChanged {
+            added,
+            removed,
+            updated,
+        } => listener.on_peers_changed(added, removed, updated),
+        P2pEvent::GoNegotiationFailed { peer, status } => {
+            listener.on_connection_failed(*peer, *status)
+        }
+        P2pEvent::ProvisionDiscoveryFailed { peer, status } => {
+            listener.on_connection_failed(Some(*peer), *status)
+        }
+        P2pEvent::ConnectionRequested(address) => listener.on_connection_requested(*address),
+        P2pEvent::IncomingRequest(request) => listener.on_incoming_request(request),
+        P2pEvent::PinToDisplay { peer, pin } => listener.on_pin_to_display(*peer, pin),
+        P2pEvent::InvitationResult { status } => {
+            if !status.is_success() {
+                listener.on_connection_failed(None, *status)
+            }
+        }
+        P2pEvent::PeerStatusChanged(address, status) => {
+            listener.on_peer_status_changed(*address, *status)
+        }
+        P2pEvent::ThisDeviceChanged(device) => listener.on_this_device_changed(device),
+        P2pEvent::ServicesFound(response) => listener.on_services_found(response),
+        P2pEvent::DnsSdServiceAvailable(response) => listener.on_dns_sd_service_available(response),
+        P2pEvent::DnsSdTxtRecordAvailable(record) => {
+            listener.on_dns_sd_txt_record_available(record)
+        }
+        P2pEvent::AspServiceFound { peer, service } => {
+            listener.on_asp_service_found(*peer, service)
+        }
+        P2pEvent::NoaUpdated(schedule) => listener.on_noa_updated(*schedule),
+        P2pEvent::GroupStarted(group) => listener.on_group_started(group),
+        P2pEvent::GroupFinished(group) => listener.on_group_finished(group),
+        P2pEvent::PeerIpResolved { mac, ip } => listener.on_peer_ip_resolved(*mac, *ip),
+        P2pEvent::NetworkSetupFailed { interface, reason } => {
+            listener.on_network_setup_failed(interface, reason)
+        }
+        P2pEvent::IpConfigured(ip) => listener.on_ip_configured(*ip),
+        P2pEvent::GroupInterfaceUp { ifname } => listener.on_group_interface_changed(ifname, true),
+        P2pEvent::GroupInterfaceDown { ifname } => {
+            listener.on_group_interface_changed(ifname, false)
+        }
+        P2pEvent::GroupAddressAdded { ifname, ip } => {
+            listener.on_group_address_changed(ifname, *ip, true)
+        }
+        P2pEvent::GroupAddressRemoved { ifname, ip } => {
+            listener.on_group_address_changed(ifname, *ip, false)
+        }
+        P2pEvent::TransferProgress(progress) => listener.on_transfer_progress(progress),
+        P2pEvent::TransferCompleted {
+            direction,
+            file_name,
+        } => listener.on_transfer_completed(*direction, file_name),
+        P2pEvent::TransferFailed {
+            direction,
+            file_name,
+            reason,
+        } => listener.on_transfer_failed(*direction, file_name, reason),
+        P2pEvent::LinkStats(stats) => listener.on_link_stats(stats),
+        P2pEvent::LinkDegraded { peer, issue } => listener.on_link_degraded(*peer, *issue),
+        P2pEvent::LinkRecovered { peer } => listener.on_link_recovered(*peer),
+        P2pEvent::CredentialStoreFailed { reason } => listener.on_credential_store_failed(reason),
+        P2pEvent::SupervisorStateChanged { peer, state } => {
+            listener.on_supervisor_state_changed(*peer, *state)
+        }
+        P2pEvent::BackendLost { .. } => listener.on_backend_changed(false),
+        P2pEvent::BackendRestored => listener.on_backend_changed(true),
+        P2pEvent::AdapterRemoved { interface } => listener.on_adapter_changed(interface, false),
+        P2pEvent::AdapterAdded { interface } => listener.on_adapter_changed(interface, true),
+    }
+}
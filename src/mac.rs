@@ -0,0 +1,148 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::P2pError;
+
+/// A 48-bit IEEE MAC address, e.g. a P2P device or interface address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MacAddress([u8; 6]);
+
+impl MacAddress {
+    pub const fn new(bytes: [u8; 6]) -> Self {
+        Self(bytes)
+    }
+
+    pub const fn octets(&self) -> [u8; 6] {
+        self.0
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 6] {
+        &self.0
+    }
+
+    /// Lower-case hex without separators, as used in wpa_supplicant peer object paths.
+    pub fn to_hex(&self) -> String {
+        self.0.iter().map(|b| format!("{b:02x}")).collect()
+    }
+}
+
+impl fmt::Display for MacAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(f, "{a:02x}:{b:02x}:{c:02x}:{d:02x}:{e:02x}:{g:02x}")
+    }
+}
+
+impl FromStr for MacAddress {
+    type Err = P2pError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Accept "aa:bb:cc:dd:ee:ff" and "aa-bb-cc-dd-ee-ff", with the same separator
+        // throughout; anything else is rejected here rather than surfacing later as
+        // an opaque D-Bus error.
+        let invalid = || P2pError::InvalidMacAddress(s.to_string());
+        let separator = match s.as_bytes().get(2) {
+            Some(b':') => ':',
+            Some(b'-') => '-',
+            _ => return Err(invalid()),
+        };
+        let mut bytes = [0u8; 6];
+        let mut parts = s.split(separator);
+        for byte in bytes.iter_mut() {
+            let part = parts.next().ok_or_else(invalid)?;
+            if part.len() != 2 || !part.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(invalid());
+            }
+            *byte = u8::from_str_radix(part, 16).map_err(|_| invalid())?;
+        }
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+        Ok(Self(bytes))
+    }
+}
+
+impl TryFrom<&[u8]> for MacAddress {
+    type Error = P2pError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        // D-Bus properties such as DeviceAddress are exposed as raw `ay`.
+        <[u8; 6]>::try_from(bytes)
+            .map(Self)
+            .map_err(|_| P2pError::InvalidMacAddress(format!("{bytes:02x?}")))
+    }
+}
+
+impl From<[u8; 6]> for MacAddress {
+    fn from(bytes: [u8; 6]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<MacAddress> for [u8; 6] {
+    fn from(address: MacAddress) -> Self {
+        address.0
+    }
+}
+
+// Serialized as the colon-separated string form so it reads naturally in JSON and config files.
+#[cfg(feature = "serde")]
+impl serde::Serialize for MacAddress {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for MacAddress {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = <std::borrow::Cow<'de, str>>::deserialize(deserializer)?;
+        text.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDRESS: MacAddress = MacAddress::new([0x02, 0x1a, 0x2b, 0x3c, 0x4d, 0xfe]);
+
+    #[test]
+    fn display_round_trips() {
+        assert_eq!(ADDRESS.to_string(), "02:1a:2b:3c:4d:fe");
+        assert_eq!(ADDRESS.to_string().parse::<MacAddress>().unwrap(), ADDRESS);
+    }
+
+    #[test]
+    fn dashes_and_upper_case_parse() {
+        assert_eq!("02-1A-2B-3C-4D-FE".parse::<MacAddress>().unwrap(), ADDRESS);
+    }
+
+    #[test]
+    fn malformed_addresses_are_rejected() {
+        for s in [
+            "",
+            "02:1a:2b-3c:4d:fe",
+            "02-1a-2b-3c-4d:fe",
+            "02:1a:2b:3c:4d",
+            "02:1a:2b:3c:4d:fe:00",
+            "02:1a:2b:3c:4d:f",
+            "021a:2b:3c:4d:fe",
+            "02:1a:2b:3c:4d:+f",
+            "02:1a:2b:3c:4d:fg",
+            "02 1a 2b 3c 4d fe",
+        ] {
+            assert!(s.parse::<MacAddress>().is_err(), "{s:?} parsed");
+        }
+    }
+
+    #[test]
+    fn raw_bytes_need_six_octets() {
+        assert_eq!(
+            MacAddress::try_from(&ADDRESS.octets()[..]).unwrap(),
+            ADDRESS
+        );
+        assert!(MacAddress::try_from(&[0u8; 5][..]).is_err());
+        assert!(MacAddress::try_from(&[0u8; 7][..]).is_err());
+    }
+}
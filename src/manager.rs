@@ -1,105 +1,2788 @@
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::net::IpAddr;
+use std::pin::Pin;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
+use tracing::Instrument;
+#[cfg(target_os = "linux")]
 use zbus::Connection;
+use zbus::zvariant::OwnedValue;
 
+use crate::approver::{ConnectionApprover, ConnectionRequest, Decision, RequestKind};
+#[cfg(feature = "recording")]
+use crate::backend::recording::{RecordingBackend, Taps};
 use crate::backend::{P2pBackend, P2pBackendImpl};
+use crate::builder::{ManagerOptions, ReconnectPolicy, WifiP2pManagerBuilder};
+use crate::capabilities::P2pCapabilities;
 use crate::channel::{P2pEvent, WifiP2pChannel};
+use crate::config::{
+    ConnectConfig, DiscoverabilityPolicy, DiscoveryOptions, DiscoveryType, WpsMethod,
+};
+use crate::credentials::{CredentialStore, GroupCredentials};
+use crate::device::{DeviceStatus, LocalDeviceInfo, P2pDevice, parse_device_type};
 use crate::error::P2pError;
+use crate::executor::Executor;
+#[cfg(feature = "tokio")]
+use crate::executor::TokioExecutor;
+use crate::group::{NoaSchedule, P2pGroup};
+use crate::health::{HealthReport, HealthState, QueueTracker};
+use crate::mac::MacAddress;
+#[cfg(feature = "metrics")]
+use crate::metrics::Metrics;
+use crate::net::GroupNetwork;
+use crate::peer_filter::PeerFilter;
+use crate::service::{AspAdvertisement, AspProvision, DnsSdRecord, LocalService, ServiceTlv};
+use crate::state::{DiscoveryState, ListenState, P2pState};
+use crate::stats::{LinkThresholds, LinkWatch};
+use crate::vendor::VendorElementFrame;
+use crate::wfd::WfdDeviceInfo;
 
-pub struct WifiP2pManager {
-    connection: Connection,
-    backend: Arc<dyn P2pBackend>,
+/// Deadline applied to each backend call unless a command overrides it.
+pub const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often the address of a freshly connected peer is looked up, and how many
+/// times before giving up (the peer may never configure an address).
+const PEER_IP_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const PEER_IP_LOOKUPS: u32 = 30;
+/// How long `health` waits for the backend.
+const HEALTH_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub struct WifiP2pManager<B: P2pBackend = P2pBackendImpl> {
+    backend: Arc<B>,
+    executor: Arc<dyn Executor>,
+    options: ManagerOptions,
+    shutdown_tx: watch::Sender<bool>,
+    shut_down: bool,
+    health: Arc<HealthState>,
 }
 
-impl WifiP2pManager {
-    /// Build the manager and its Linux backend by opening the system bus
-    /// and resolving the wpa_supplicant interface object path.
+impl WifiP2pManager<P2pBackendImpl> {
+    /// Build the manager and its platform backend: on Linux by opening the system
+    /// bus and resolving the wpa_supplicant interface object path, registering
+    /// the interface with wpa_supplicant if nothing has.
+    #[cfg(feature = "tokio")]
     pub async fn new(interface_name: &str) -> Result<Self, P2pError> {
-        let connection = Connection::system().await?;
-        let backend = P2pBackendImpl::new(&connection, interface_name).await?;
-        Ok(Self {
-            connection,
-            backend: Arc::new(backend),
-        })
+        Self::with_executor(interface_name, TokioExecutor).await
+    }
+
+    /// Like `new`, but spawns the worker tasks and timers on `executor`, for
+    /// embedding in applications that do not run tokio.
+    pub async fn with_executor(
+        interface_name: &str,
+        executor: impl Executor,
+    ) -> Result<Self, P2pError> {
+        Self::builder()
+            .executor(executor)
+            .build(interface_name)
+            .await
+    }
+
+    /// Configure queue sizes, timeouts and behavior flags before building.
+    pub fn builder() -> WifiP2pManagerBuilder {
+        WifiP2pManagerBuilder::new()
+    }
+
+    /// List the wireless interfaces and whether their radios support Wi-Fi
+    /// Direct; see `interfaces`.
+    #[cfg(all(feature = "interfaces", target_os = "linux"))]
+    pub fn discover_interfaces() -> Result<Vec<crate::interfaces::WirelessInterface>, P2pError> {
+        crate::interfaces::discover_interfaces()
+    }
+
+    /// Pick an interface for `new` instead of guessing `"wlan0"`: one whose radio
+    /// supports Wi-Fi Direct, preferring interfaces that are up.
+    #[cfg(all(feature = "interfaces", target_os = "linux"))]
+    pub fn auto_interface() -> Result<String, P2pError> {
+        crate::interfaces::auto_interface()
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn connection(&self) -> &Connection {
+        // Expose the raw connection for advanced consumers (signals, extra interfaces).
+        self.backend.connection()
+    }
+}
+
+/// Open the default backend for this platform on `interface_name`.
+#[cfg(target_os = "linux")]
+pub(crate) async fn open_platform_backend(
+    interface_name: &str,
+    options: &ManagerOptions,
+) -> Result<P2pBackendImpl, P2pError> {
+    // Every call would fail with unrelated-looking errors on a blocked radio.
+    crate::rfkill::check(interface_name)?;
+    let connection = Connection::system().await?;
+    P2pBackendImpl::open(&connection, interface_name, &options.interface_config).await
+}
+
+/// Open the default backend for this platform on `interface_name`.
+#[cfg(target_os = "windows")]
+pub(crate) async fn open_platform_backend(
+    interface_name: &str,
+    _options: &ManagerOptions,
+) -> Result<P2pBackendImpl, P2pError> {
+    P2pBackendImpl::new(interface_name).await
+}
+
+/// Open the default backend for this platform on `interface_name`.
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+pub(crate) async fn open_platform_backend(
+    interface_name: &str,
+    _options: &ManagerOptions,
+) -> Result<P2pBackendImpl, P2pError> {
+    P2pBackendImpl::new(interface_name).await
+}
+
+impl<B: P2pBackend> WifiP2pManager<B> {
+    /// Drive a caller-provided backend instead of the platform one, e.g. a mock in
+    /// tests or an alternative implementation for another platform.
+    #[cfg(feature = "tokio")]
+    pub fn with_backend(backend: Arc<B>) -> Self {
+        Self::with_backend_and_executor(backend, TokioExecutor)
+    }
+
+    pub fn with_backend_and_executor(backend: Arc<B>, executor: impl Executor) -> Self {
+        Self::from_parts(backend, Arc::new(executor), ManagerOptions::default())
+    }
+
+    pub(crate) fn from_parts(
+        backend: Arc<B>,
+        executor: Arc<dyn Executor>,
+        options: ManagerOptions,
+    ) -> Self {
+        Self {
+            backend,
+            executor,
+            options,
+            shutdown_tx: watch::Sender::new(false),
+            shut_down: false,
+            health: Arc::default(),
+        }
+    }
+
+    /// The backend this manager drives.
+    pub fn backend(&self) -> &Arc<B> {
+        &self.backend
+    }
+
+    /// Counters for this manager and every channel it initializes, for a
+    /// Prometheus scrape (`Metrics::render`).
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> &Metrics {
+        &self.options.metrics
+    }
+
+    /// Change the default per-command deadline for channels initialized afterwards.
+    /// A D-Bus call that exceeds it is cancelled and resolves with `P2pError::Timeout`.
+    pub fn set_command_timeout(&mut self, timeout: Duration) {
+        self.options.command_timeout = timeout;
     }
 
     pub fn initialize(&self) -> WifiP2pChannel {
         // The channel owns the command sender; a background task consumes commands
         // and executes D-Bus calls on the backend.
-        let (command_tx, command_rx) = mpsc::channel(32);
-        let (event_tx, _event_rx) = broadcast::channel(64);
+        let (command_tx, command_rx) = mpsc::channel(self.options.command_queue_depth);
+        let (priority_tx, priority_rx) = mpsc::channel(self.options.command_queue_depth);
+        self.health.track_queue(&command_tx);
+        let queue = Arc::new(QueueTracker::default());
+        let (event_tx, _event_rx) = broadcast::channel(self.options.event_buffer);
+        let (backend_tx, backend_rx) = mpsc::channel(64);
+        let (peers_tx, peers_rx) = watch::channel(Vec::new());
+        let (groups_tx, groups_rx) = watch::channel(Vec::new());
+        #[cfg(all(feature = "rtnetlink", target_os = "linux"))]
+        {
+            let event_tx = event_tx.clone();
+            let groups_rx = groups_rx.clone();
+            let mut shutdown = self.shutdown_tx.subscribe();
+            self.executor.spawn(Box::pin(async move {
+                // Without the socket there is just no interface state; everything else works.
+                tokio::select! {
+                    _ = crate::net::rtnetlink::monitor(event_tx, groups_rx) => {}
+                    () = shutdown_requested(&mut shutdown) => {}
+                }
+            }));
+        }
         let event_tx_for_task = event_tx.clone();
+        // Calls and backend events pass through a recording layer that stays idle
+        // until a `Recorder` attaches to one of the channels.
+        #[cfg(feature = "recording")]
+        let taps = Taps::default();
+        #[cfg(feature = "recording")]
+        let backend = Arc::new(RecordingBackend::tapped(
+            Arc::clone(&self.backend),
+            taps.clone(),
+        ));
+        #[cfg(not(feature = "recording"))]
         let backend = Arc::clone(&self.backend);
-        tokio::spawn(async move {
-            run_manager(backend, command_rx, event_tx_for_task).await;
-        });
-        WifiP2pChannel::new(command_tx, event_tx)
+        let executor = Arc::clone(&self.executor);
+        let options = self.options.clone();
+        let health = Arc::clone(&self.health);
+        let tracker = Arc::clone(&queue);
+        let watcher = Arc::clone(&backend);
+        let watcher_executor = Arc::clone(&self.executor);
+        let mut watcher_shutdown = self.shutdown_tx.subscribe();
+        let manager_shutdown = self.shutdown_tx.subscribe();
+        self.executor.spawn(Box::pin(async move {
+            // Signal subscription failures leave the command path usable. Dropping the
+            // watch future on shutdown unsubscribes from the supplicant's signals.
+            tokio::select! {
+                () = watch_backend(
+                    &*watcher,
+                    &*watcher_executor,
+                    options.reconnect_policy,
+                    backend_tx,
+                ) => {}
+                () = shutdown_requested(&mut watcher_shutdown) => {}
+            }
+        }));
+        self.executor.spawn(Box::pin(async move {
+            run_manager(
+                backend,
+                executor,
+                options,
+                health,
+                tracker,
+                peers_tx,
+                groups_tx,
+                command_rx,
+                priority_rx,
+                backend_rx,
+                event_tx_for_task,
+                manager_shutdown,
+            )
+            .await;
+        }));
+        let channel = WifiP2pChannel::new(
+            command_tx,
+            priority_tx,
+            event_tx,
+            peers_rx,
+            groups_rx,
+            Arc::clone(&self.executor),
+            queue,
+        )
+        .with_overflow(self.options.queue_overflow);
+        #[cfg(feature = "metrics")]
+        let channel = channel.with_metrics(self.options.metrics.clone());
+        #[cfg(feature = "recording")]
+        let channel = channel.with_taps(taps);
+        channel
     }
 
-    pub fn connection(&self) -> &Connection {
-        // Expose the raw connection for advanced consumers (signals, extra interfaces).
-        &self.connection
+    /// Stop discovery, remove any P2P groups and terminate the worker tasks, so the
+    /// adapter is not left in find or group owner state (see `cleanup_on_shutdown`).
+    /// Channels created from this manager report `P2pError::ChannelClosed`
+    /// afterwards. Calling it again is a no-op.
+    pub async fn shutdown(&mut self) -> Result<(), P2pError> {
+        if self.shut_down {
+            return Ok(());
+        }
+        self.shut_down = true;
+        self.shutdown_tx.send_replace(true);
+        let cleaned = if self.options.cleanup_on_shutdown {
+            cleanup(
+                &*self.backend,
+                &*self.executor,
+                self.options.command_timeout,
+            )
+            .await
+        } else {
+            Ok(())
+        };
+        #[cfg(target_os = "linux")]
+        let cleaned = match &self.options.created_interface {
+            // After the cleanup, which needs the interface's objects.
+            Some(backend) if backend.created_interface() => {
+                cleaned.and(backend.remove_interface().await)
+            }
+            _ => cleaned,
+        };
+        // Only once we are done with the radio, so NetworkManager and ConnMan do not race the cleanup.
+        #[cfg(all(feature = "network-manager", target_os = "linux"))]
+        if let Some(guard) = &self.options.network_manager {
+            guard.restore().await?;
+        }
+        #[cfg(all(feature = "connman", target_os = "linux"))]
+        if let Some(guard) = &self.options.connman {
+            guard.restore().await?;
+        }
+        cleaned
+    }
+
+    /// Query what the supplicant and driver support, so callers can degrade
+    /// gracefully before attempting operations that would fail.
+    pub async fn capabilities(&self) -> Result<P2pCapabilities, P2pError> {
+        let limit = self.options.command_timeout;
+        with_deadline(&*self.executor, limit, self.backend.capabilities()).await
+    }
+
+    /// Check that the backend answers within 2 s and report when each command
+    /// last succeeded and how full the command queue is. Bypasses the queue, so
+    /// it is cheap enough for a liveness probe.
+    pub async fn health(&self) -> HealthReport {
+        self.health_with_timeout(HEALTH_TIMEOUT).await
+    }
+
+    /// Like `health`, waiting up to `timeout` for the backend.
+    pub async fn health_with_timeout(&self, timeout: Duration) -> HealthReport {
+        let started = Instant::now();
+        let ping = with_deadline(&*self.executor, timeout, self.backend.ping()).await;
+        let latency = started.elapsed();
+        if ping.is_ok() {
+            self.health.succeeded("ping");
+        }
+        let capacity = self.options.command_queue_depth;
+        self.health.report(ping, latency, capacity)
+    }
+}
+
+impl<B: P2pBackend> Drop for WifiP2pManager<B> {
+    fn drop(&mut self) {
+        // Best effort: the worker tasks stop once the shutdown sender is gone, and the
+        // adapter cleanup runs in the background because Drop cannot await. Prefer
+        // `shutdown()` when the process is about to exit.
+        if !self.shut_down {
+            self.shutdown_tx.send_replace(true);
+            let backend = Arc::clone(&self.backend);
+            let executor = Arc::clone(&self.executor);
+            let limit = self.options.command_timeout;
+            let cleanup_on_shutdown = self.options.cleanup_on_shutdown;
+            #[cfg(target_os = "linux")]
+            let created_interface = self.options.created_interface.clone();
+            #[cfg(all(feature = "network-manager", target_os = "linux"))]
+            let network_manager = self.options.network_manager.clone();
+            #[cfg(all(feature = "connman", target_os = "linux"))]
+            let connman = self.options.connman.clone();
+            self.executor.spawn(Box::pin(async move {
+                if cleanup_on_shutdown {
+                    let _ = cleanup(&*backend, &*executor, limit).await;
+                }
+                #[cfg(target_os = "linux")]
+                if let Some(created) = created_interface
+                    && created.created_interface()
+                {
+                    let _ = created.remove_interface().await;
+                }
+                #[cfg(all(feature = "network-manager", target_os = "linux"))]
+                if let Some(guard) = network_manager {
+                    let _ = guard.restore().await;
+                }
+                #[cfg(all(feature = "connman", target_os = "linux"))]
+                if let Some(guard) = connman {
+                    let _ = guard.restore().await;
+                }
+            }));
+        }
     }
 }
 
+/// A command plus its per-call deadline override, as sent by the channel.
+pub(crate) struct QueuedCommand {
+    pub(crate) command: ManagerCommand,
+    pub(crate) timeout: Option<Duration>,
+    /// Position in the order the channel's clones sent commands.
+    pub(crate) sequence: u64,
+}
+
 pub(crate) enum ManagerCommand {
     Discover {
+        options: DiscoveryOptions,
         respond_to: oneshot::Sender<Result<(), P2pError>>,
     },
     StopDiscovery {
         respond_to: oneshot::Sender<Result<(), P2pError>>,
     },
+    StartContinuousDiscovery {
+        interval: Duration,
+        duty_cycle: f32,
+        respond_to: oneshot::Sender<Result<(), P2pError>>,
+    },
     Connect {
-        device_address: String,
+        config: ConnectConfig,
+        respond_to: oneshot::Sender<Result<Option<String>, P2pError>>,
+    },
+    CancelConnect {
         respond_to: oneshot::Sender<Result<(), P2pError>>,
     },
+    RequestPeers {
+        filter: Option<PeerFilter>,
+        respond_to: oneshot::Sender<Result<Vec<P2pDevice>, P2pError>>,
+    },
+    RequestGroups {
+        respond_to: oneshot::Sender<Result<Vec<P2pGroup>, P2pError>>,
+    },
     CreateGroup {
         respond_to: oneshot::Sender<Result<(), P2pError>>,
     },
+    Invite {
+        peer: MacAddress,
+        respond_to: oneshot::Sender<Result<(), P2pError>>,
+    },
+    AspProvision {
+        request: AspProvision,
+        respond_to: oneshot::Sender<Result<(), P2pError>>,
+    },
+    RejectPeer {
+        peer: MacAddress,
+        respond_to: oneshot::Sender<Result<(), P2pError>>,
+    },
+    RemoveGroups {
+        respond_to: oneshot::Sender<Result<(), P2pError>>,
+    },
+    AddService {
+        service: LocalService,
+        respond_to: oneshot::Sender<Result<(), P2pError>>,
+    },
+    RemoveService {
+        service: LocalService,
+        respond_to: oneshot::Sender<Result<(), P2pError>>,
+    },
+    DiscoverServices {
+        peer: Option<MacAddress>,
+        respond_to: oneshot::Sender<Result<(), P2pError>>,
+    },
+    SetNoa {
+        schedule: NoaSchedule,
+        respond_to: oneshot::Sender<Result<(), P2pError>>,
+    },
+    SetVendorElements {
+        frame: VendorElementFrame,
+        elements: Vec<u8>,
+        respond_to: oneshot::Sender<Result<(), P2pError>>,
+    },
+    ResolvePeerIp {
+        device_address: MacAddress,
+        respond_to: oneshot::Sender<Result<Option<IpAddr>, P2pError>>,
+    },
+    SetWfdInfo {
+        info: Option<WfdDeviceInfo>,
+        respond_to: oneshot::Sender<Result<(), P2pError>>,
+    },
+    RequestWfdInfo {
+        respond_to: oneshot::Sender<Result<Option<WfdDeviceInfo>, P2pError>>,
+    },
+    Reinvoke {
+        peer: MacAddress,
+        respond_to: oneshot::Sender<Result<(), P2pError>>,
+    },
+    /// `None` stops watching.
+    WatchLinkQuality {
+        peer: MacAddress,
+        thresholds: Option<LinkThresholds>,
+        respond_to: oneshot::Sender<Result<(), P2pError>>,
+    },
+    CallRaw {
+        method: String,
+        options: HashMap<String, OwnedValue>,
+        respond_to: oneshot::Sender<Result<Vec<OwnedValue>, P2pError>>,
+    },
+    DiscoveryState {
+        respond_to: oneshot::Sender<Result<DiscoveryState, P2pError>>,
+    },
+    StartListening {
+        respond_to: oneshot::Sender<Result<(), P2pError>>,
+    },
+    SetDiscoverability {
+        policy: DiscoverabilityPolicy,
+        respond_to: oneshot::Sender<Result<(), P2pError>>,
+    },
+    StopListening {
+        respond_to: oneshot::Sender<Result<(), P2pError>>,
+    },
+    ListenState {
+        respond_to: oneshot::Sender<Result<ListenState, P2pError>>,
+    },
+    P2pState {
+        respond_to: oneshot::Sender<Result<P2pState, P2pError>>,
+    },
+    /// `None` reports requests as events again.
+    SetConnectionPolicy {
+        approver: Option<Box<dyn ConnectionApprover>>,
+        respond_to: oneshot::Sender<Result<(), P2pError>>,
+    },
+    FactoryReset {
+        respond_to: oneshot::Sender<Result<(), P2pError>>,
+    },
+    ClearLocalServices {
+        respond_to: oneshot::Sender<Result<(), P2pError>>,
+    },
+    ClearServiceRequests {
+        respond_to: oneshot::Sender<Result<(), P2pError>>,
+    },
+    RequestDeviceInfo {
+        respond_to: oneshot::Sender<Result<LocalDeviceInfo, P2pError>>,
+    },
+    SetDeviceName {
+        name: String,
+        respond_to: oneshot::Sender<Result<(), P2pError>>,
+    },
+}
+
+impl ManagerCommand {
+    /// Whether the command goes ahead of the regular queue: stopping and
+    /// cancelling should not wait behind what they stop.
+    pub(crate) fn is_priority(&self) -> bool {
+        matches!(
+            self,
+            Self::StopDiscovery { .. } | Self::CancelConnect { .. }
+        )
+    }
+
+    /// Whether the command holds the radio until the peer answers; see `RadioLane`.
+    fn uses_radio(&self) -> bool {
+        matches!(
+            self,
+            Self::Connect { .. }
+                | Self::CreateGroup { .. }
+                | Self::Invite { .. }
+                | Self::AspProvision { .. }
+                | Self::Reinvoke { .. }
+        )
+    }
+
+    /// The command's name in `Recorder` captures and `HealthReport::last_success`.
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Self::Discover { .. } => "discover",
+            Self::StopDiscovery { .. } => "stop_discovery",
+            Self::StartContinuousDiscovery { .. } => "start_continuous_discovery",
+            Self::Connect { .. } => "connect",
+            Self::CancelConnect { .. } => "cancel_connect",
+            Self::RequestPeers { .. } => "request_peers",
+            Self::RequestGroups { .. } => "request_groups",
+            Self::CreateGroup { .. } => "create_group",
+            Self::Invite { .. } => "invite",
+            Self::AspProvision { .. } => "asp_provision",
+            Self::RejectPeer { .. } => "reject_peer",
+            Self::RemoveGroups { .. } => "remove_groups",
+            Self::AddService { .. } => "add_service",
+            Self::RemoveService { .. } => "remove_service",
+            Self::DiscoverServices { .. } => "discover_services",
+            Self::SetNoa { .. } => "set_noa",
+            Self::SetVendorElements { .. } => "set_vendor_elements",
+            Self::SetWfdInfo { .. } => "set_wfd_info",
+            Self::RequestWfdInfo { .. } => "request_wfd_info",
+            Self::ResolvePeerIp { .. } => "resolve_peer_ip",
+            Self::Reinvoke { .. } => "reinvoke",
+            Self::WatchLinkQuality { .. } => "watch_link_quality",
+            Self::CallRaw { .. } => "call_raw",
+            Self::DiscoveryState { .. } => "discovery_state",
+            Self::StartListening { .. } => "start_listening",
+            Self::SetDiscoverability { .. } => "set_discoverability",
+            Self::StopListening { .. } => "stop_listening",
+            Self::ListenState { .. } => "listen_state",
+            Self::P2pState { .. } => "p2p_state",
+            Self::SetConnectionPolicy { .. } => "set_connection_policy",
+            Self::FactoryReset { .. } => "factory_reset",
+            Self::ClearLocalServices { .. } => "clear_local_services",
+            Self::ClearServiceRequests { .. } => "clear_service_requests",
+            Self::RequestDeviceInfo { .. } => "request_device_info",
+            Self::SetDeviceName { .. } => "set_device_name",
+        }
+    }
+
+    /// The peer the command is about, for its `tracing` span.
+    fn peer(&self) -> Option<MacAddress> {
+        match self {
+            Self::Connect { config, .. } => Some(config.device_address),
+            Self::Invite { peer, .. }
+            | Self::RejectPeer { peer, .. }
+            | Self::Reinvoke { peer, .. }
+            | Self::WatchLinkQuality { peer, .. } => Some(*peer),
+            Self::AspProvision { request, .. } => Some(request.peer),
+            Self::ResolvePeerIp { device_address, .. } => Some(*device_address),
+            Self::DiscoverServices { peer, .. } => *peer,
+            _ => None,
+        }
+    }
+
+    /// Whether `other` asks for exactly what this does, so that one backend call
+    /// can answer both; see `take_duplicates`.
+    fn coalesces_with(&self, other: &ManagerCommand) -> bool {
+        match (self, other) {
+            (Self::StopDiscovery { .. }, Self::StopDiscovery { .. })
+            | (Self::RequestPeers { filter: None, .. }, Self::RequestPeers { filter: None, .. })
+            | (Self::RequestGroups { .. }, Self::RequestGroups { .. })
+            | (Self::RemoveGroups { .. }, Self::RemoveGroups { .. }) => true,
+            (Self::Discover { options, .. }, Self::Discover { options: other, .. }) => {
+                options == other
+            }
+            (Self::DiscoverServices { peer, .. }, Self::DiscoverServices { peer: other, .. }) => {
+                peer == other
+            }
+            _ => false,
+        }
+    }
+
+    /// Take over the callers of `duplicates`, which coalesce with this command:
+    /// the returned closure answers them with a copy of this command's answer,
+    /// once it has been sent.
+    fn adopt(&mut self, duplicates: Vec<ManagerCommand>) -> Box<dyn FnOnce() + Send> {
+        if duplicates.is_empty() {
+            return Box::new(|| {});
+        }
+        match self {
+            Self::Discover { respond_to, .. }
+            | Self::StopDiscovery { respond_to }
+            | Self::RemoveGroups { respond_to }
+            | Self::DiscoverServices { respond_to, .. } => {
+                let waiters = duplicates
+                    .into_iter()
+                    .filter_map(|duplicate| match duplicate {
+                        Self::Discover { respond_to, .. }
+                        | Self::StopDiscovery { respond_to }
+                        | Self::RemoveGroups { respond_to }
+                        | Self::DiscoverServices { respond_to, .. } => Some(respond_to),
+                        _ => None,
+                    });
+                fan_out(respond_to, waiters.collect())
+            }
+            Self::RequestPeers { respond_to, .. } => {
+                let waiters = duplicates
+                    .into_iter()
+                    .filter_map(|duplicate| match duplicate {
+                        Self::RequestPeers { respond_to, .. } => Some(respond_to),
+                        _ => None,
+                    });
+                fan_out(respond_to, waiters.collect())
+            }
+            Self::RequestGroups { respond_to } => {
+                let waiters = duplicates
+                    .into_iter()
+                    .filter_map(|duplicate| match duplicate {
+                        Self::RequestGroups { respond_to } => Some(respond_to),
+                        _ => None,
+                    });
+                fan_out(respond_to, waiters.collect())
+            }
+            _ => Box::new(|| {}),
+        }
+    }
+}
+
+/// Stand in for `respond_to`, returning what forwards its answer to it and to
+/// `waiters`. Nothing is sent if the command dropped its sender unanswered.
+fn fan_out<T: Clone + Send + 'static>(
+    respond_to: &mut oneshot::Sender<Result<T, P2pError>>,
+    mut waiters: Vec<oneshot::Sender<Result<T, P2pError>>>,
+) -> Box<dyn FnOnce() + Send> {
+    let (stand_in, mut answer) = oneshot::channel();
+    waiters.push(std::mem::replace(respond_to, stand_in));
+    Box::new(move || {
+        if let Ok(result) = answer.try_recv() {
+            for waiter in waiters {
+                let _ = waiter.send(result.clone());
+            }
+        }
+    })
+}
+
+/// Take the commands queued right behind `queued` that coalesce with it, so
+/// concurrent callers asking the same thing share one backend call. The first
+/// command that does not is left in `next`, to run before anything else from
+/// `commands`.
+fn take_duplicates(
+    queued: &QueuedCommand,
+    commands: &mut mpsc::Receiver<QueuedCommand>,
+    next: &mut Option<QueuedCommand>,
+    state: &ManagerState,
+) -> Vec<ManagerCommand> {
+    let mut duplicates = Vec::new();
+    if queued.command.uses_radio() {
+        return duplicates;
+    }
+    // A discover sent after a stop_discovery has to run even if one sent before
+    // it was overtaken.
+    let stopped = |sequence| sequence < state.discovery_stopped_at;
+    while let Ok(candidate) = commands.try_recv() {
+        if !queued.command.coalesces_with(&candidate.command)
+            || stopped(queued.sequence) != stopped(candidate.sequence)
+        {
+            *next = Some(candidate);
+            break;
+        }
+        duplicates.push(candidate.command);
+    }
+    duplicates
+}
+
+/// A command's `tracing` span, and when it started.
+struct CommandTrace {
+    kind: &'static str,
+    span: tracing::Span,
+    started: Instant,
+}
+
+impl CommandTrace {
+    fn new(command: &ManagerCommand) -> Self {
+        let peer = command.peer().map(tracing::field::display);
+        Self {
+            kind: command.name(),
+            span: tracing::info_span!("command", kind = command.name(), peer),
+            started: Instant::now(),
+        }
+    }
+
+    /// Carry on in the span the caller runs in: a radio call handed over by
+    /// `handle_command`.
+    fn current(kind: &'static str) -> Self {
+        Self {
+            kind,
+            span: tracing::Span::current(),
+            started: Instant::now(),
+        }
+    }
+
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+    fn finished(&self, state: &ManagerState, succeeded: bool) {
+        let elapsed = self.started.elapsed();
+        let elapsed_ms = elapsed.as_millis() as u64;
+        let kind = self.kind;
+        self.span
+            .in_scope(|| tracing::debug!(elapsed_ms, succeeded, "{kind} finished"));
+        #[cfg(feature = "metrics")]
+        state.metrics.command_finished(kind, elapsed);
+    }
+}
+
+/// Applies a radio command's outcome to the manager state and answers the
+/// caller, telling whether it succeeded.
+type Finish = Box<dyn FnOnce(&mut ManagerState, &broadcast::Sender<P2pEvent>) -> bool + Send>;
+type RadioCall = Pin<Box<dyn Future<Output = (&'static str, Finish)> + Send>>;
+
+fn finish(
+    apply: impl FnOnce(&mut ManagerState, &broadcast::Sender<P2pEvent>) -> bool + Send + 'static,
+) -> Finish {
+    Box::new(apply)
+}
+
+/// Commands that hold the radio for seconds (GO negotiation, invitations, group
+/// formation) run here, one at a time, while the manager loop keeps taking the
+/// rest: a slow `connect` does not hold up `stop_discovery` or `remove_groups`.
+struct RadioLane<B: P2pBackend> {
+    backend: Arc<B>,
+    executor: Arc<dyn Executor>,
+    running: Option<RadioCall>,
+    trace: Option<CommandTrace>,
+    // Radio commands that arrived while one was running, in order.
+    waiting: VecDeque<QueuedCommand>,
+    queue: Arc<QueueTracker>,
+}
+
+impl<B: P2pBackend> RadioLane<B> {
+    fn new(backend: Arc<B>, executor: Arc<dyn Executor>, queue: Arc<QueueTracker>) -> Self {
+        Self {
+            backend,
+            executor,
+            running: None,
+            trace: None,
+            waiting: VecDeque::new(),
+            queue,
+        }
+    }
+
+    fn is_busy(&self) -> bool {
+        self.running.is_some()
+    }
+
+    /// The backend and executor, for a call that outlives the command handler.
+    fn handles(&self) -> (Arc<B>, Arc<dyn Executor>) {
+        (Arc::clone(&self.backend), Arc::clone(&self.executor))
+    }
+
+    fn run(&mut self, name: &'static str, call: impl Future<Output = Finish> + Send + 'static) {
+        self.queue.radio_started(name);
+        let trace = CommandTrace::current(name);
+        let call = call.instrument(trace.span.clone());
+        self.trace = Some(trace);
+        self.running = Some(Box::pin(async move { (name, call.await) }));
+    }
+
+    /// The running command once its call returns; pending while idle. Dropping
+    /// this future (another branch of the loop won) leaves the call running.
+    async fn finished(&mut self) -> (&'static str, Finish) {
+        let Some(running) = &mut self.running else {
+            return std::future::pending().await;
+        };
+        let done = running.await;
+        self.running = None;
+        self.queue.radio_finished();
+        done
+    }
+
+    fn park(&mut self, queued: QueuedCommand) {
+        self.waiting.push_back(queued);
+        self.queue.set_parked(self.waiting.len());
+    }
+
+    fn unpark(&mut self) -> Option<QueuedCommand> {
+        let queued = self.waiting.pop_front();
+        self.queue.set_parked(self.waiting.len());
+        queued
+    }
+}
+
+/// `start_continuous_discovery`'s schedule: scan for `duty_cycle` of every
+/// `interval`, counted from `started`.
+struct ContinuousDiscovery {
+    started: Instant,
+    interval: Duration,
+    duty_cycle: f32,
+}
+
+impl ContinuousDiscovery {
+    /// `None` unless `duty_cycle` is in (0, 1] and leaves a scan of some length
+    /// in every `interval`; `phase` relies on both.
+    fn new(interval: Duration, duty_cycle: f32) -> Option<Self> {
+        let valid =
+            duty_cycle > 0.0 && duty_cycle <= 1.0 && !interval.mul_f32(duty_cycle).is_zero();
+        valid.then(|| Self {
+            started: Instant::now(),
+            interval,
+            duty_cycle,
+        })
+    }
+
+    /// Whether a scan should run at `now`, and how long until that changes.
+    fn phase(&self, now: Instant) -> (bool, Duration) {
+        let elapsed = now.duration_since(self.started).as_nanos();
+        let position = Duration::from_nanos((elapsed % self.interval.as_nanos()) as u64);
+        let scan = self.interval.mul_f32(self.duty_cycle);
+        if position < scan {
+            (true, scan - position)
+        } else {
+            (false, self.interval - position)
+        }
+    }
+}
+
+/// Per-peer state owned by the manager task.
+struct ManagerState {
+    statuses: HashMap<MacAddress, DeviceStatus>,
+    peers: HashMap<MacAddress, P2pDevice>,
+    // When discovery last reported each peer, for `peer_ttl`.
+    last_seen: HashMap<MacAddress, Instant>,
+    peers_tx: watch::Sender<Vec<P2pDevice>>,
+    groups_tx: watch::Sender<Vec<P2pGroup>>,
+    discovering: bool,
+    // What the last scan started looked for: scanned again after a restart,
+    // and its device types filter peers while discovery runs.
+    discovery: DiscoveryOptions,
+    // Until stop_discovery, and whether it has to look at the scan again
+    // (it stopped, or a connection started or ended) before its next turn.
+    continuous: Option<ContinuousDiscovery>,
+    rearm_due: bool,
+    listening: bool,
+    // Listen and interval of a `DiscoverabilityPolicy::Windowed` listen.
+    listen_window: Option<(Duration, Duration)>,
+    // Cleared while the supplicant or the interface is gone.
+    attached: bool,
+    peer_ips: HashMap<MacAddress, IpAddr>,
+    // Connected peers whose address is still unknown, with the lookups left.
+    ip_lookups: HashMap<MacAddress, u32>,
+    // Cleared for good once the backend says it has no link statistics.
+    link_stats: bool,
+    // Decides incoming requests instead of the app, if set.
+    approver: Option<Box<dyn ConnectionApprover>>,
+    // Our own device as last read, and whether the backend can report it.
+    local_device: Option<LocalDeviceInfo>,
+    reads_local_device: bool,
+    link_watches: HashMap<MacAddress, LinkWatch>,
+    // What the credential store holds, if one is configured.
+    credentials: Vec<GroupCredentials>,
+    network: GroupNetwork,
+    // Applied to the supplicant so far; put back after it restarts.
+    services: Vec<LocalService>,
+    vendor_elements: HashMap<VendorElementFrame, Vec<u8>>,
+    wfd: Option<WfdDeviceInfo>,
+    // Service queries sent and not withdrawn, by peer (`None` for every peer).
+    service_requests: Vec<Option<MacAddress>>,
+    device_name: Option<String>,
+    default_device_name: Option<String>,
+    // Whether discovery ran (and we listened) when the supplicant went away.
+    resume_discovery: bool,
+    resume_listening: bool,
+    // Sequence numbers of the last stop_discovery and cancel_connect: commands
+    // sent before them that have not run yet are answered `Cancelled`.
+    discovery_stopped_at: u64,
+    connect_cancelled_at: u64,
+    #[cfg(feature = "metrics")]
+    metrics: Metrics,
+}
+
+/// Peer table changes collected while handling one signal or peer list.
+#[derive(Default)]
+struct PeerChanges {
+    added: Vec<Arc<P2pDevice>>,
+    removed: Vec<MacAddress>,
+    updated: Vec<Arc<P2pDevice>>,
+}
+
+impl ManagerState {
+    fn new(
+        peers_tx: watch::Sender<Vec<P2pDevice>>,
+        groups_tx: watch::Sender<Vec<P2pGroup>>,
+        options: &ManagerOptions,
+    ) -> Self {
+        Self {
+            statuses: HashMap::new(),
+            peers: HashMap::new(),
+            last_seen: HashMap::new(),
+            peers_tx,
+            groups_tx,
+            discovering: false,
+            discovery: DiscoveryOptions::default(),
+            continuous: None,
+            rearm_due: false,
+            listening: false,
+            listen_window: None,
+            attached: true,
+            peer_ips: HashMap::new(),
+            ip_lookups: HashMap::new(),
+            link_stats: options.link_stats_interval.is_some(),
+            approver: None,
+            local_device: None,
+            reads_local_device: true,
+            link_watches: HashMap::new(),
+            credentials: Vec::new(),
+            network: GroupNetwork::new(options),
+            services: Vec::new(),
+            vendor_elements: HashMap::new(),
+            wfd: None,
+            service_requests: Vec::new(),
+            device_name: None,
+            default_device_name: None,
+            resume_discovery: false,
+            resume_listening: false,
+            discovery_stopped_at: 0,
+            connect_cancelled_at: 0,
+            #[cfg(feature = "metrics")]
+            metrics: options.metrics.clone(),
+        }
+    }
+
+    /// The command carrying out the approver's decision on `request`, or the
+    /// request back when there is no approver or it deferred. An accepted
+    /// invitation, or a PIN method accepted without the PIN, counts as deferred.
+    fn decide(
+        &mut self,
+        request: ConnectionRequest,
+        executor: &dyn Executor,
+        event_tx: &broadcast::Sender<P2pEvent>,
+    ) -> Result<ManagerCommand, ConnectionRequest> {
+        let Some(approver) = self.approver.as_mut() else {
+            return Err(request);
+        };
+        // Nobody waits for the answers; the outcome is reported as events.
+        let pin = match approver.approve(&request) {
+            Decision::Defer => return Err(request),
+            Decision::Reject => {
+                let (respond_to, _) = oneshot::channel();
+                return Ok(ManagerCommand::RejectPeer {
+                    peer: request.peer,
+                    respond_to,
+                });
+            }
+            // Connecting would start a new GO negotiation, not join the group.
+            Decision::Accept | Decision::AcceptWithPin(_)
+                if request.kind == RequestKind::Invitation =>
+            {
+                return Err(request);
+            }
+            Decision::Accept => request.pin.clone(),
+            Decision::AcceptWithPin(pin) => Some(pin),
+        };
+        if request.method.requires_pin() && pin.is_none() {
+            return Err(request);
+        }
+        let (respond_to, generated) = oneshot::channel::<Result<Option<String>, P2pError>>();
+        if request.method == WpsMethod::PinDisplay {
+            // The peer can only finish WPS once the user sees the PIN.
+            let peer = request.peer;
+            let given = pin.clone();
+            let event_tx = event_tx.clone();
+            executor.spawn(Box::pin(async move {
+                if let Ok(Ok(generated)) = generated.await
+                    && let Some(pin) = generated.or(given)
+                {
+                    let _ = event_tx.send(P2pEvent::PinToDisplay { peer, pin });
+                }
+            }));
+        }
+        Ok(ManagerCommand::Connect {
+            config: ConnectConfig {
+                device_address: request.peer,
+                wps_method: request.method,
+                pin,
+            },
+            respond_to,
+        })
+    }
+
+    /// Report every known peer gone, as after the supplicant's peer table was emptied.
+    fn forget_peers(&mut self, event_tx: &broadcast::Sender<P2pEvent>) {
+        let mut changes = PeerChanges::default();
+        let lost: Vec<MacAddress> = self.peers.keys().copied().collect();
+        for address in lost {
+            self.set_status(address, DeviceStatus::Unavailable, event_tx);
+            self.remove_peer(address, &mut changes);
+        }
+        self.finish_changes(changes, event_tx);
+    }
+
+    fn set_local_device(&mut self, info: LocalDeviceInfo, event_tx: &broadcast::Sender<P2pEvent>) {
+        // The first name read is the one `factory_reset` puts back.
+        if self.local_device.is_none() && self.device_name.is_none() {
+            self.default_device_name = info.device_name.clone();
+        }
+        if self.local_device.as_ref() != Some(&info) {
+            self.local_device = Some(info.clone());
+            let _ = event_tx.send(P2pEvent::ThisDeviceChanged(info));
+        }
+    }
+
+    fn set_status(
+        &mut self,
+        address: MacAddress,
+        status: DeviceStatus,
+        event_tx: &broadcast::Sender<P2pEvent>,
+    ) {
+        if self.statuses.insert(address, status) != Some(status) {
+            self.rearm_due = true;
+            if let Some(device) = self.peers.get_mut(&address) {
+                device.status = status;
+                self.publish_peers();
+            }
+            let _ = event_tx.send(P2pEvent::PeerStatusChanged(address, status));
+        }
+    }
+
+    fn mark_seen(&mut self, device: &mut P2pDevice, event_tx: &broadcast::Sender<P2pEvent>) {
+        // New or returning peers start out available; engaged peers keep their status.
+        let status = match self.statuses.get(&device.mac_address) {
+            Some(DeviceStatus::Unavailable) | None => DeviceStatus::Available,
+            Some(status) => *status,
+        };
+        self.set_status(device.mac_address, status, event_tx);
+        device.status = status;
+    }
+
+    fn upsert_peer(&mut self, device: &Arc<P2pDevice>, changes: &mut PeerChanges) {
+        // Listing a peer does not count as hearing it again; finding it does.
+        self.last_seen
+            .entry(device.mac_address)
+            .or_insert_with(Instant::now);
+        match self.peers.insert(device.mac_address, (**device).clone()) {
+            None => changes.added.push(Arc::clone(device)),
+            Some(previous) if previous != **device => changes.updated.push(Arc::clone(device)),
+            Some(_) => {}
+        }
+    }
+
+    fn remove_peer(&mut self, address: MacAddress, changes: &mut PeerChanges) {
+        self.last_seen.remove(&address);
+        if self.peers.remove(&address).is_some() {
+            changes.removed.push(address);
+        }
+    }
+
+    fn finish_changes(&mut self, changes: PeerChanges, event_tx: &broadcast::Sender<P2pEvent>) {
+        if changes.added.is_empty() && changes.removed.is_empty() && changes.updated.is_empty() {
+            return;
+        }
+        self.publish_peers();
+        let _ = event_tx.send(P2pEvent::PeersChanged {
+            added: changes.added,
+            removed: changes.removed,
+            updated: changes.updated,
+        });
+    }
+
+    fn publish_peers(&self) {
+        // Snapshots are sorted by address so consumers see a stable order.
+        let mut peers: Vec<P2pDevice> = self.peers.values().cloned().collect();
+        peers.sort_by_key(|peer| peer.mac_address);
+        self.peers_tx.send_replace(peers);
+    }
+
+    fn set_discovering(&mut self, discovering: bool, event_tx: &broadcast::Sender<P2pEvent>) {
+        // Our own stop and the supplicant's FindStopped both land here; report once.
+        if self.discovering != discovering {
+            self.discovering = discovering;
+            if discovering {
+                // Peers get a full TTL to show up in the new scan.
+                let now = Instant::now();
+                self.last_seen.values_mut().for_each(|seen| *seen = now);
+            } else {
+                self.rearm_due = true;
+            }
+            let event = if discovering {
+                P2pEvent::DiscoveryStarted
+            } else {
+                P2pEvent::DiscoveryStopped
+            };
+            let _ = event_tx.send(event);
+        }
+    }
+
+    /// Whether a found peer matches the device types discovery asked for.
+    fn wants_peer(&self, device: &P2pDevice) -> bool {
+        let device_types = &self.discovery.device_types;
+        !self.discovering
+            || device_types.is_empty()
+            || device_types
+                .iter()
+                .any(|device_type| device.has_device_type(device_type))
+    }
+
+    /// Report peers discovery has not found again within `ttl` lost, as if the
+    /// backend had said so.
+    fn expire_peers(&mut self, ttl: Duration, event_tx: &broadcast::Sender<P2pEvent>) {
+        let now = Instant::now();
+        let stale: Vec<MacAddress> = self
+            .last_seen
+            .iter()
+            .filter(|(address, seen)| {
+                now.duration_since(**seen) >= ttl
+                    && !matches!(
+                        self.statuses.get(address),
+                        Some(DeviceStatus::Connected | DeviceStatus::Invited)
+                    )
+            })
+            .map(|(address, _)| *address)
+            .collect();
+        for address in stale {
+            tracing::debug!(%address, "peer expired");
+            self.handle_backend_event(P2pEvent::PeerLost(address), event_tx);
+        }
+    }
+
+    /// A connection is being set up or a group runs: continuous discovery
+    /// leaves the scan alone until it is over.
+    fn connection_active(&self) -> bool {
+        !self.groups_tx.borrow().is_empty()
+            || self
+                .statuses
+                .values()
+                .any(|status| *status == DeviceStatus::Invited)
+    }
+
+    fn set_listening(&mut self, listening: bool, event_tx: &broadcast::Sender<P2pEvent>) {
+        if self.listening != listening {
+            self.listening = listening;
+            let state = if listening {
+                ListenState::Started
+            } else {
+                ListenState::Stopped
+            };
+            let _ = event_tx.send(P2pEvent::ListenStateChanged(state));
+        }
+    }
+
+    fn record_peer_ip(
+        &mut self,
+        address: MacAddress,
+        ip: IpAddr,
+        event_tx: &broadcast::Sender<P2pEvent>,
+    ) {
+        self.ip_lookups.remove(&address);
+        if self.peer_ips.insert(address, ip) != Some(ip) {
+            let _ = event_tx.send(P2pEvent::PeerIpResolved { mac: address, ip });
+        }
+    }
+
+    fn polls_link_stats(&self) -> bool {
+        self.link_stats && !self.groups_tx.borrow().is_empty()
+    }
+
+    fn handle_backend_event(&mut self, event: P2pEvent, event_tx: &broadcast::Sender<P2pEvent>) {
+        match event {
+            P2pEvent::PeerFound(mut device) => {
+                if !self.wants_peer(&device) {
+                    return;
+                }
+                let mut changes = PeerChanges::default();
+                self.last_seen.insert(device.mac_address, Instant::now());
+                self.mark_seen(Arc::make_mut(&mut device), event_tx);
+                self.upsert_peer(&device, &mut changes);
+                #[cfg(feature = "metrics")]
+                self.metrics.peer_found();
+                let _ = event_tx.send(P2pEvent::PeerFound(device));
+                self.finish_changes(changes, event_tx);
+            }
+            P2pEvent::PeerLost(address) => {
+                let mut changes = PeerChanges::default();
+                self.set_status(address, DeviceStatus::Unavailable, event_tx);
+                self.remove_peer(address, &mut changes);
+                let _ = event_tx.send(P2pEvent::PeerLost(address));
+                self.finish_changes(changes, event_tx);
+            }
+            P2pEvent::DiscoveryStopped => self.set_discovering(false, event_tx),
+            P2pEvent::ListenStateChanged(listen) => {
+                self.set_listening(listen == ListenState::Started, event_tx)
+            }
+            P2pEvent::GoNegotiationFailed { peer, status } => {
+                if let Some(address) = peer {
+                    self.set_status(address, DeviceStatus::Failed, event_tx);
+                }
+                #[cfg(feature = "metrics")]
+                self.metrics.negotiation_failed(status);
+                let _ = event_tx.send(P2pEvent::GoNegotiationFailed { peer, status });
+            }
+            P2pEvent::ProvisionDiscoveryFailed { peer, status } => {
+                self.set_status(peer, DeviceStatus::Failed, event_tx);
+                #[cfg(feature = "metrics")]
+                self.metrics.negotiation_failed(status);
+                let _ = event_tx.send(P2pEvent::ProvisionDiscoveryFailed { peer, status });
+            }
+            P2pEvent::GroupStarted(group) => {
+                self.rearm_due = true;
+                let setup = self.network.group_started(&group, event_tx);
+                self.groups_tx.send_modify(|groups| {
+                    groups.retain(|active| active.interface != group.interface);
+                    groups.push((*group).clone());
+                });
+                let _ = event_tx.send(P2pEvent::GroupStarted(group.clone()));
+                if let Err(err) = setup {
+                    let _ = event_tx.send(P2pEvent::NetworkSetupFailed {
+                        interface: group.interface.clone(),
+                        reason: err.to_string(),
+                    });
+                }
+            }
+            P2pEvent::GroupFinished(group) => {
+                self.rearm_due = true;
+                // Addresses are only valid for the group that assigned them; a new
+                // group resolves (and reports) them again.
+                self.peer_ips.clear();
+                self.network.group_finished(&group);
+                self.groups_tx.send_modify(|groups| {
+                    groups.retain(|active| active.interface != group.interface)
+                });
+                let _ = event_tx.send(P2pEvent::GroupFinished(group));
+            }
+            P2pEvent::ServicesFound(response) => {
+                let source = response.peer;
+                let records: Vec<DnsSdRecord> = response
+                    .tlvs
+                    .iter()
+                    .filter_map(ServiceTlv::dns_sd)
+                    .collect();
+                let advertisements: Vec<AspAdvertisement> = response
+                    .tlvs
+                    .iter()
+                    .filter_map(ServiceTlv::asp)
+                    .flatten()
+                    .collect();
+                let _ = event_tx.send(P2pEvent::ServicesFound(response));
+                for record in records {
+                    let _ = event_tx.send(record.into_event(source));
+                }
+                for service in advertisements {
+                    let _ = event_tx.send(P2pEvent::AspServiceFound {
+                        peer: source,
+                        service,
+                    });
+                }
+            }
+            lost @ (P2pEvent::BackendLost { .. } | P2pEvent::AdapterRemoved { .. }) => {
+                self.attached = false;
+                let _ = event_tx.send(lost);
+                // The supplicant took its scan, peer table and groups with it.
+                self.resume_discovery = self.discovering;
+                self.resume_listening = self.listening;
+                self.set_discovering(false, event_tx);
+                self.set_listening(false, event_tx);
+                self.forget_peers(event_tx);
+                self.ip_lookups.clear();
+                self.service_requests.clear();
+                let groups = self.groups_tx.borrow().clone();
+                for group in groups {
+                    self.handle_backend_event(P2pEvent::GroupFinished(Arc::new(group)), event_tx);
+                }
+            }
+            P2pEvent::IncomingRequest(request) => {
+                let go_negotiation = request.kind == RequestKind::GoNegotiation;
+                let peer = request.peer;
+                let _ = event_tx.send(P2pEvent::IncomingRequest(request));
+                if go_negotiation {
+                    let _ = event_tx.send(P2pEvent::ConnectionRequested(peer));
+                }
+            }
+            restored @ (P2pEvent::BackendRestored | P2pEvent::AdapterAdded { .. }) => {
+                self.attached = true;
+                self.rearm_due = true;
+                let _ = event_tx.send(restored);
+            }
+            other => {
+                let _ = event_tx.send(other);
+            }
+        }
+    }
+
+    fn apply_peers(&mut self, peers: &mut [P2pDevice], event_tx: &broadcast::Sender<P2pEvent>) {
+        // Peers that dropped out of the supplicant's list become unavailable; everyone
+        // else keeps their tracked status (new peers start out available).
+        let mut changes = PeerChanges::default();
+        let missing: Vec<MacAddress> = self
+            .statuses
+            .iter()
+            .filter(|(address, status)| {
+                **status != DeviceStatus::Unavailable
+                    && !peers.iter().any(|peer| peer.mac_address == **address)
+            })
+            .map(|(address, _)| *address)
+            .collect();
+        for address in missing {
+            self.set_status(address, DeviceStatus::Unavailable, event_tx);
+            self.remove_peer(address, &mut changes);
+        }
+        for peer in peers.iter_mut() {
+            self.mark_seen(peer, event_tx);
+            self.upsert_peer(&Arc::new(peer.clone()), &mut changes);
+        }
+        self.finish_changes(changes, event_tx);
+    }
 }
 
-async fn run_manager(
-    backend: Arc<dyn P2pBackend>,
-    mut command_rx: mpsc::Receiver<ManagerCommand>,
+#[allow(clippy::too_many_arguments)]
+async fn run_manager<B: P2pBackend>(
+    backend: Arc<B>,
+    executor: Arc<dyn Executor>,
+    options: ManagerOptions,
+    health: Arc<HealthState>,
+    queue: Arc<QueueTracker>,
+    peers_tx: watch::Sender<Vec<P2pDevice>>,
+    groups_tx: watch::Sender<Vec<P2pGroup>>,
+    mut command_rx: mpsc::Receiver<QueuedCommand>,
+    mut priority_rx: mpsc::Receiver<QueuedCommand>,
+    mut backend_rx: mpsc::Receiver<P2pEvent>,
     event_tx: broadcast::Sender<P2pEvent>,
+    mut shutdown_rx: watch::Receiver<bool>,
 ) {
     // Single consumer loop that serializes backend operations to avoid
-    // overlapping D-Bus requests unless explicitly desired.
-    while let Some(command) = command_rx.recv().await {
-        match command {
-            ManagerCommand::Discover { respond_to } => {
-                let result = backend.discover_peers().await;
-                if result.is_ok() {
-                    let _ = event_tx.send(P2pEvent::DiscoveryStarted);
+    // overlapping D-Bus requests unless explicitly desired. Backend signals are
+    // folded into the same loop so the peer state stays consistent. Radio
+    // commands run beside it in `RadioLane`, one at a time.
+    let mut state = ManagerState::new(peers_tx, groups_tx, &options);
+    let mut ip_poll = executor.sleep(PEER_IP_POLL_INTERVAL);
+    let stats_interval = options.link_stats_interval.unwrap_or(PEER_IP_POLL_INTERVAL);
+    let mut stats_poll = executor.sleep(stats_interval);
+    let watchdog_interval = options.watchdog_interval.unwrap_or(PEER_IP_POLL_INTERVAL);
+    let mut watchdog = executor.sleep(watchdog_interval);
+    // Sweeping at a quarter of the TTL expires a peer at most a quarter late.
+    let expiry_interval = options
+        .peer_ttl
+        .map_or(PEER_IP_POLL_INTERVAL, |ttl| ttl / 4);
+    let mut expiry_sweep = executor.sleep(expiry_interval);
+    let mut rearm = executor.sleep(PEER_IP_POLL_INTERVAL);
+    #[cfg(all(feature = "network-manager", target_os = "linux"))]
+    if let (Some(guard), Some(interface)) = (&options.network_manager, &options.interface_name) {
+        unmanage(guard, interface, &event_tx).await;
+    }
+    #[cfg(all(feature = "connman", target_os = "linux"))]
+    if let Some(guard) = &options.connman
+        && let Err(err) = guard.release().await
+    {
+        let _ = event_tx.send(P2pEvent::NetworkSetupFailed {
+            interface: options.interface_name.clone().unwrap_or_default(),
+            reason: err.to_string(),
+        });
+    }
+    if let Some(store) = &options.credential_store {
+        let limit = options.command_timeout;
+        restore_credentials(
+            &*backend, &*executor, &mut state, &**store, limit, &event_tx,
+        )
+        .await;
+    }
+    let limit = options.command_timeout;
+    let _ = refresh_local_device(&*backend, &*executor, &mut state, limit, &event_tx).await;
+    let mut radio = RadioLane::new(Arc::clone(&backend), Arc::clone(&executor), queue);
+    let mut commands_open = true;
+    // Commands taken while looking for duplicates, run before their queue's next.
+    let mut next_priority = None;
+    let mut next_command = None;
+    // Once every channel is gone, radio commands already taken still finish.
+    while commands_open || radio.is_busy() || next_command.is_some() {
+        // Stop and cancel commands go first, whatever else is ready.
+        while let Some(queued) = next_priority.take().or_else(|| priority_rx.try_recv().ok()) {
+            let duplicates = take_duplicates(&queued, &mut priority_rx, &mut next_priority, &state);
+            dispatch(
+                &mut state, &mut radio, queued, duplicates, &options, &event_tx, &health,
+            )
+            .await;
+        }
+        if let Some(queued) = next_command.take() {
+            let duplicates = take_duplicates(&queued, &mut command_rx, &mut next_command, &state);
+            dispatch(
+                &mut state, &mut radio, queued, duplicates, &options, &event_tx, &health,
+            )
+            .await;
+            continue;
+        }
+        if std::mem::take(&mut state.rearm_due) && state.continuous.is_some() {
+            rearm = executor.sleep(Duration::ZERO);
+        }
+        tokio::select! {
+            Some(queued) = priority_rx.recv() => {
+                let duplicates =
+                    take_duplicates(&queued, &mut priority_rx, &mut next_priority, &state);
+                dispatch(&mut state, &mut radio, queued, duplicates, &options, &event_tx, &health)
+                    .await;
+            }
+            queued = command_rx.recv(), if commands_open => match queued {
+                Some(queued) => {
+                    let duplicates =
+                        take_duplicates(&queued, &mut command_rx, &mut next_command, &state);
+                    dispatch(&mut state, &mut radio, queued, duplicates, &options, &event_tx, &health)
+                        .await;
+                }
+                None => commands_open = false,
+            },
+            (name, finish) = radio.finished() => {
+                let succeeded = finish(&mut state, &event_tx);
+                if let Some(trace) = radio.trace.take() {
+                    trace.finished(&state, succeeded);
+                }
+                if succeeded {
+                    health.succeeded(name);
+                }
+                if let Some(queued) = radio.unpark() {
+                    dispatch(&mut state, &mut radio, queued, Vec::new(), &options, &event_tx, &health)
+                        .await;
+                }
+            }
+            Some(event) = backend_rx.recv() => {
+                let event = match event {
+                    P2pEvent::IncomingRequest(request) => match state.decide(request, &*executor, &event_tx) {
+                        Ok(command) => {
+                            // Sent after everything queued so far: nothing overtakes it.
+                            let queued = QueuedCommand {
+                                command,
+                                timeout: None,
+                                sequence: u64::MAX,
+                            };
+                            dispatch(&mut state, &mut radio, queued, Vec::new(), &options, &event_tx, &health)
+                                .await;
+                            continue;
+                        }
+                        Err(request) => P2pEvent::IncomingRequest(request),
+                    },
+                    event => event,
+                };
+                // The supplicant stores a persistent group when it forms and adds
+                // the GO's clients while it runs.
+                let group_changed =
+                    matches!(event, P2pEvent::GroupStarted(_) | P2pEvent::GroupFinished(_));
+                // The supplicant may pick a new random device address for either.
+                let reattached =
+                    matches!(event, P2pEvent::BackendRestored | P2pEvent::AdapterAdded { .. });
+                #[cfg(all(feature = "network-manager", target_os = "linux"))]
+                let group_event = group_changed.then(|| event.clone());
+                #[cfg(all(feature = "network-manager", target_os = "linux"))]
+                let lost_groups = match &event {
+                    P2pEvent::BackendLost { .. } | P2pEvent::AdapterRemoved { .. } => {
+                        state.groups_tx.borrow().clone()
+                    }
+                    _ => Vec::new(),
+                };
+                if reattached {
+                    let limit = options.command_timeout;
+                    replay_state(&*backend, &*executor, &mut state, &options, limit, &event_tx)
+                        .await;
+                }
+                state.handle_backend_event(event, &event_tx);
+                if (group_changed || reattached) && state.reads_local_device {
+                    let limit = options.command_timeout;
+                    let _ = refresh_local_device(&*backend, &*executor, &mut state, limit, &event_tx)
+                        .await;
+                }
+                #[cfg(all(feature = "network-manager", target_os = "linux"))]
+                if let Some(guard) = &options.network_manager {
+                    for group in lost_groups {
+                        guard.forget(&group.interface).await;
+                    }
+                }
+                #[cfg(all(feature = "network-manager", target_os = "linux"))]
+                if let (Some(guard), Some(event)) = (&options.network_manager, group_event) {
+                    match event {
+                        P2pEvent::GroupStarted(group) => {
+                            unmanage(guard, &group.interface, &event_tx).await;
+                        }
+                        P2pEvent::GroupFinished(group) => guard.forget(&group.interface).await,
+                        _ => {}
+                    }
+                }
+                if let (true, Some(store)) = (group_changed, &options.credential_store) {
+                    let limit = options.command_timeout;
+                    save_credentials(&*backend, &*executor, &mut state, &**store, limit, &event_tx)
+                        .await;
+                }
+            }
+            () = &mut ip_poll, if !state.ip_lookups.is_empty() => {
+                let limit = options.command_timeout;
+                resolve_pending_ips(&*backend, &*executor, &mut state, limit, &event_tx).await;
+                ip_poll = executor.sleep(PEER_IP_POLL_INTERVAL);
+            }
+            () = &mut stats_poll, if state.polls_link_stats() => {
+                let limit = options.command_timeout;
+                poll_link_stats(&*backend, &*executor, &mut state, limit, &event_tx).await;
+                stats_poll = executor.sleep(stats_interval);
+            }
+            () = &mut rearm, if state.continuous.is_some() => {
+                let limit = options.command_timeout;
+                let next = rearm_discovery(&*backend, &*executor, &mut state, limit, &event_tx).await;
+                rearm = executor.sleep(next);
+            }
+            () = &mut expiry_sweep, if options.peer_ttl.is_some() && state.discovering => {
+                if let Some(ttl) = options.peer_ttl {
+                    state.expire_peers(ttl, &event_tx);
+                }
+                expiry_sweep = executor.sleep(expiry_interval);
+            }
+            () = &mut watchdog, if options.watchdog_interval.is_some() => {
+                ping_watchdog();
+                watchdog = executor.sleep(watchdog_interval);
+            }
+            () = shutdown_requested(&mut shutdown_rx) => break,
+        }
+    }
+}
+
+/// Run a queued command, answering `duplicates` alike (see `take_duplicates`),
+/// or park it while another radio command is running.
+#[allow(clippy::too_many_arguments)]
+async fn dispatch<B: P2pBackend>(
+    state: &mut ManagerState,
+    radio: &mut RadioLane<B>,
+    queued: QueuedCommand,
+    duplicates: Vec<ManagerCommand>,
+    options: &ManagerOptions,
+    event_tx: &broadcast::Sender<P2pEvent>,
+    health: &HealthState,
+) {
+    if queued.command.uses_radio() && radio.is_busy() {
+        radio.park(queued);
+        return;
+    }
+    let QueuedCommand {
+        mut command,
+        timeout,
+        sequence,
+    } = queued;
+    let limit = timeout.unwrap_or(options.command_timeout);
+    let name = command.name();
+    let radio_call = command.uses_radio();
+    let trace = CommandTrace::new(&command);
+    if !duplicates.is_empty() {
+        let _span = trace.span.enter();
+        tracing::debug!(
+            duplicates = duplicates.len(),
+            "coalesced identical commands"
+        );
+    }
+    let answer_duplicates = command.adopt(duplicates);
+    radio.queue.started(name);
+    let succeeded = handle_command(state, radio, command, sequence, limit, options, event_tx)
+        .instrument(trace.span.clone())
+        .await;
+    radio.queue.finished();
+    answer_duplicates();
+    // A radio command handed to the lane is traced until it finishes there.
+    if !(radio_call && radio.is_busy()) {
+        trace.finished(state, succeeded);
+    }
+    if succeeded {
+        health.succeeded(name);
+    }
+}
+
+/// Run a command, telling whether it succeeded. Radio commands are handed to
+/// `radio` and report that when they finish instead.
+async fn handle_command<B: P2pBackend>(
+    state: &mut ManagerState,
+    radio: &mut RadioLane<B>,
+    command: ManagerCommand,
+    sequence: u64,
+    limit: Duration,
+    options: &ManagerOptions,
+    event_tx: &broadcast::Sender<P2pEvent>,
+) -> bool {
+    let (shared_backend, shared_executor) = radio.handles();
+    let (backend, executor) = (&*shared_backend, &*shared_executor);
+    match command {
+        ManagerCommand::Discover {
+            options: discovery,
+            respond_to,
+        } => {
+            if sequence < state.discovery_stopped_at {
+                return respond(respond_to, Err(overtaken("stop_discovery")));
+            }
+            if discovery.discovery_type == DiscoveryType::SingleFrequency(0) {
+                return respond(
+                    respond_to,
+                    Err(P2pError::InvalidArgs("frequency must not be 0".to_string())),
+                );
+            }
+            let invalid = discovery
+                .device_types
+                .iter()
+                .find(|device_type| parse_device_type(device_type).is_none());
+            if let Some(device_type) = invalid {
+                return respond(
+                    respond_to,
+                    Err(P2pError::InvalidArgs(format!(
+                        "device type {device_type:?} is not category-OUI-subcategory"
+                    ))),
+                );
+            }
+            let invalid = discovery.seek.iter().find(|name| {
+                name.is_empty() || name.len() > 255 || name.contains(char::is_whitespace)
+            });
+            if let Some(name) = invalid {
+                return respond(
+                    respond_to,
+                    Err(P2pError::InvalidArgs(format!(
+                        "service name {name:?} must be one word of at most 255 bytes"
+                    ))),
+                );
+            }
+            let call = backend.discover_peers(discovery.clone());
+            let result = with_deadline(executor, limit, call).await;
+            if result.is_ok() {
+                state.discovery = discovery;
+                #[cfg(feature = "metrics")]
+                state.metrics.discovery_started();
+                state.set_discovering(true, event_tx);
+            }
+            respond(respond_to, result)
+        }
+        ManagerCommand::StopDiscovery { respond_to } => {
+            // Also while the supplicant is away: it should not come back scanning.
+            state.resume_discovery = false;
+            state.continuous = None;
+            state.discovery_stopped_at = state.discovery_stopped_at.max(sequence);
+            let result = with_deadline(executor, limit, backend.stop_discovery()).await;
+            if result.is_ok() {
+                state.set_discovering(false, event_tx);
+            }
+            respond(respond_to, result)
+        }
+        ManagerCommand::StartContinuousDiscovery {
+            interval,
+            duty_cycle,
+            respond_to,
+        } => {
+            if sequence < state.discovery_stopped_at {
+                return respond(respond_to, Err(overtaken("stop_discovery")));
+            }
+            let Some(schedule) = ContinuousDiscovery::new(interval, duty_cycle) else {
+                return respond(
+                    respond_to,
+                    Err(P2pError::InvalidArgs(
+                        "duty cycle must be in (0, 1] and leave a non-zero scan in the interval"
+                            .to_string(),
+                    )),
+                );
+            };
+            let discovery = DiscoveryOptions::default();
+            let result =
+                with_deadline(executor, limit, backend.discover_peers(discovery.clone())).await;
+            if result.is_ok() {
+                state.discovery = discovery;
+                // The schedule counts from the scan actually starting.
+                state.continuous = Some(ContinuousDiscovery {
+                    started: Instant::now(),
+                    ..schedule
+                });
+                state.rearm_due = true;
+                #[cfg(feature = "metrics")]
+                state.metrics.discovery_started();
+                state.set_discovering(true, event_tx);
+            }
+            respond(respond_to, result)
+        }
+        ManagerCommand::Connect { config, respond_to } => {
+            if sequence < state.connect_cancelled_at {
+                return respond(respond_to, Err(overtaken("cancel_connect")));
+            }
+            let device_address = config.device_address;
+            if options.stop_discovery_on_connect
+                && state.discovering
+                && with_deadline(executor, limit, backend.stop_discovery())
+                    .await
+                    .is_ok()
+            {
+                // An active scan competes with GO negotiation for the radio.
+                state.set_discovering(false, event_tx);
+            }
+            state.set_status(device_address, DeviceStatus::Invited, event_tx);
+            #[cfg(feature = "metrics")]
+            state.metrics.connect_attempted();
+            let (backend, executor) = radio.handles();
+            radio.run("connect", async move {
+                let result = with_deadline(&*executor, limit, backend.connect(config)).await;
+                finish(move |state, event_tx| {
+                    #[cfg(feature = "metrics")]
+                    match &result {
+                        Ok(_) => state.metrics.connect_succeeded(),
+                        Err(err) => state.metrics.connect_failed(err),
+                    }
+                    if result.is_ok() {
+                        state.set_status(device_address, DeviceStatus::Connected, event_tx);
+                        state.ip_lookups.insert(device_address, PEER_IP_LOOKUPS);
+                        let _ = event_tx.send(P2pEvent::Connected(device_address));
+                    } else {
+                        state.set_status(device_address, DeviceStatus::Failed, event_tx);
+                    }
+                    respond(respond_to, result)
+                })
+            });
+            false
+        }
+        ManagerCommand::CancelConnect { respond_to } => {
+            state.connect_cancelled_at = state.connect_cancelled_at.max(sequence);
+            let result = with_deadline(executor, limit, backend.cancel_connect()).await;
+            respond(respond_to, result)
+        }
+        ManagerCommand::RequestPeers { filter, respond_to } => {
+            let mut result = with_deadline(executor, limit, backend.request_peers()).await;
+            if let Ok(peers) = &mut result {
+                state.apply_peers(peers, event_tx);
+                // The peer table keeps every peer; only the answer is narrowed.
+                if let Some(filter) = filter {
+                    peers.retain(|device| filter.matches(device));
+                }
+            }
+            respond(respond_to, result)
+        }
+        ManagerCommand::RequestGroups { respond_to } => {
+            let mut result = with_deadline(executor, limit, backend.request_groups()).await;
+            if let Ok(groups) = &mut result {
+                // Prefer what GroupStarted told us: it carries the IP allocation.
+                let tracked = state.groups_tx.borrow();
+                for group in groups.iter_mut() {
+                    if let Some(known) = tracked
+                        .iter()
+                        .find(|known| known.interface == group.interface)
+                    {
+                        *group = known.clone();
+                    }
+                }
+            }
+            respond(respond_to, result)
+        }
+        ManagerCommand::CreateGroup { respond_to } => {
+            let (backend, executor) = radio.handles();
+            radio.run("create_group", async move {
+                let result = with_deadline(&*executor, limit, backend.create_group()).await;
+                finish(move |_, event_tx| {
+                    if result.is_ok() {
+                        let _ = event_tx.send(P2pEvent::GroupCreated);
+                    }
+                    respond(respond_to, result)
+                })
+            });
+            false
+        }
+        ManagerCommand::Invite { peer, respond_to } => {
+            let (backend, executor) = radio.handles();
+            radio.run("invite", async move {
+                let result = with_deadline(&*executor, limit, backend.invite(peer)).await;
+                finish(move |_, _| respond(respond_to, result))
+            });
+            false
+        }
+        ManagerCommand::AspProvision {
+            request,
+            respond_to,
+        } => {
+            let (backend, executor) = radio.handles();
+            radio.run("asp_provision", async move {
+                let result = with_deadline(&*executor, limit, backend.asp_provision(request)).await;
+                finish(move |_, _| respond(respond_to, result))
+            });
+            false
+        }
+        ManagerCommand::RejectPeer { peer, respond_to } => {
+            let result = with_deadline(executor, limit, backend.reject_peer(peer)).await;
+            respond(respond_to, result)
+        }
+        ManagerCommand::RemoveGroups { respond_to } => {
+            // GroupFinished arrives from the backend for every group it removed.
+            let result = with_deadline(executor, limit, backend.remove_groups()).await;
+            respond(respond_to, result)
+        }
+        ManagerCommand::AddService {
+            service,
+            respond_to,
+        } => {
+            let call = backend.add_service(service.clone());
+            let result = with_deadline(executor, limit, call).await;
+            if result.is_ok() && !state.services.contains(&service) {
+                state.services.push(service);
+            }
+            respond(respond_to, result)
+        }
+        ManagerCommand::RemoveService {
+            service,
+            respond_to,
+        } => {
+            let call = backend.remove_service(service.clone());
+            let result = with_deadline(executor, limit, call).await;
+            if result.is_ok() {
+                state.services.retain(|known| *known != service);
+            }
+            respond(respond_to, result)
+        }
+        ManagerCommand::DiscoverServices { peer, respond_to } => {
+            let result = with_deadline(executor, limit, backend.discover_services(peer)).await;
+            if result.is_ok() && !state.service_requests.contains(&peer) {
+                state.service_requests.push(peer);
+            }
+            respond(respond_to, result)
+        }
+        ManagerCommand::ClearLocalServices { respond_to } => {
+            // Services the backend would not remove stay listed, so a later clear
+            // tries them again; the first failure is reported.
+            let mut result = Ok(());
+            for service in std::mem::take(&mut state.services) {
+                let call = backend.remove_service(service.clone());
+                if let Err(err) = with_deadline(executor, limit, call).await {
+                    state.services.push(service);
+                    result = result.and(Err(err));
+                }
+            }
+            respond(respond_to, result)
+        }
+        ManagerCommand::ClearServiceRequests { respond_to } => {
+            let mut result = Ok(());
+            for peer in std::mem::take(&mut state.service_requests) {
+                let call = backend.cancel_service_discovery(peer);
+                if let Err(err) = with_deadline(executor, limit, call).await {
+                    state.service_requests.push(peer);
+                    result = result.and(Err(err));
+                }
+            }
+            respond(respond_to, result)
+        }
+        ManagerCommand::SetNoa {
+            schedule,
+            respond_to,
+        } => {
+            let result = with_deadline(executor, limit, backend.set_noa(schedule)).await;
+            if result.is_ok() {
+                let _ = event_tx.send(P2pEvent::NoaUpdated(schedule));
+            }
+            respond(respond_to, result)
+        }
+        ManagerCommand::SetVendorElements {
+            frame,
+            elements,
+            respond_to,
+        } => {
+            let call = backend.set_vendor_elements(frame, elements.clone());
+            let result = with_deadline(executor, limit, call).await;
+            if result.is_ok() {
+                if elements.is_empty() {
+                    state.vendor_elements.remove(&frame);
+                } else {
+                    state.vendor_elements.insert(frame, elements);
                 }
-                let _ = respond_to.send(result);
             }
-            ManagerCommand::StopDiscovery { respond_to } => {
-                let result = backend.stop_discovery().await;
-                if result.is_ok() {
-                    let _ = event_tx.send(P2pEvent::DiscoveryStopped);
+            respond(respond_to, result)
+        }
+        ManagerCommand::SetWfdInfo { info, respond_to } => {
+            if info.is_some_and(|info| info.rtsp_port == 0) {
+                return respond(
+                    respond_to,
+                    Err(P2pError::InvalidArgs("RTSP port 0".to_string())),
+                );
+            }
+            let result = with_deadline(executor, limit, backend.set_wfd_info(info)).await;
+            if result.is_ok() {
+                state.wfd = info;
+            }
+            respond(respond_to, result)
+        }
+        ManagerCommand::RequestWfdInfo { respond_to } => respond(respond_to, Ok(state.wfd)),
+        ManagerCommand::ResolvePeerIp {
+            device_address,
+            respond_to,
+        } => {
+            let call = backend.resolve_peer_ip(device_address);
+            let result = with_deadline(executor, limit, call).await;
+            if let Ok(Some(ip)) = result {
+                state.record_peer_ip(device_address, ip, event_tx);
+            }
+            respond(respond_to, result)
+        }
+        ManagerCommand::Reinvoke { peer, respond_to } => {
+            if sequence < state.connect_cancelled_at {
+                return respond(respond_to, Err(overtaken("cancel_connect")));
+            }
+            let (backend, executor) = radio.handles();
+            radio.run("reinvoke", async move {
+                let result = reinvoke(&*backend, &*executor, peer, limit).await;
+                finish(move |state, event_tx| {
+                    if result.is_ok() {
+                        state.set_status(peer, DeviceStatus::Connected, event_tx);
+                        state.ip_lookups.insert(peer, PEER_IP_LOOKUPS);
+                        let _ = event_tx.send(P2pEvent::Connected(peer));
+                    }
+                    respond(respond_to, result)
+                })
+            });
+            false
+        }
+        ManagerCommand::WatchLinkQuality {
+            peer,
+            thresholds,
+            respond_to,
+        } => {
+            let result = match thresholds {
+                None => {
+                    state.link_watches.remove(&peer);
+                    Ok(())
                 }
-                let _ = respond_to.send(result);
+                Some(_) if options.link_stats_interval.is_none() => Err(P2pError::InvalidConfig(
+                    "link quality needs link_stats_interval".to_string(),
+                )),
+                Some(_) if !state.link_stats => Err(P2pError::Unsupported(
+                    "the backend reports no link statistics".to_string(),
+                )),
+                Some(thresholds) => {
+                    state.link_watches.insert(peer, LinkWatch::new(thresholds));
+                    Ok(())
+                }
+            };
+            respond(respond_to, result)
+        }
+        ManagerCommand::CallRaw {
+            method,
+            options,
+            respond_to,
+        } => {
+            let result = with_deadline(executor, limit, backend.call_raw(method, options)).await;
+            respond(respond_to, result)
+        }
+        ManagerCommand::StartListening { respond_to } => {
+            let result = with_deadline(executor, limit, backend.start_listening()).await;
+            if result.is_ok() {
+                state.listen_window = None;
+                state.set_listening(true, event_tx);
+            }
+            respond(respond_to, result)
+        }
+        ManagerCommand::StopListening { respond_to } => {
+            state.resume_listening = false;
+            let result = with_deadline(executor, limit, backend.stop_listening()).await;
+            if result.is_ok() {
+                state.listen_window = None;
+                state.set_listening(false, event_tx);
             }
-            ManagerCommand::Connect {
-                device_address,
-                respond_to,
-            } => {
-                let event_address = device_address.clone();
-                let result = backend.connect(device_address).await;
-                if result.is_ok() {
-                    let _ = event_tx.send(P2pEvent::Connected(event_address));
+            respond(respond_to, result)
+        }
+        ManagerCommand::SetDiscoverability { policy, respond_to } => {
+            let result = match policy {
+                DiscoverabilityPolicy::Always => {
+                    with_deadline(executor, limit, backend.start_listening()).await
                 }
-                let _ = respond_to.send(result);
+                DiscoverabilityPolicy::Windowed { listen, every } => {
+                    if listen.is_zero() || listen > every {
+                        return respond(
+                            respond_to,
+                            Err(P2pError::InvalidArgs(
+                                "listen window must be non-zero and at most its interval"
+                                    .to_string(),
+                            )),
+                        );
+                    }
+                    let call = backend.start_listening_windowed(listen, every);
+                    with_deadline(executor, limit, call).await
+                }
+                DiscoverabilityPolicy::Off => {
+                    state.resume_listening = false;
+                    with_deadline(executor, limit, backend.stop_listening()).await
+                }
+            };
+            if result.is_ok() {
+                state.listen_window = match policy {
+                    DiscoverabilityPolicy::Windowed { listen, every } => Some((listen, every)),
+                    _ => None,
+                };
+                state.set_listening(policy != DiscoverabilityPolicy::Off, event_tx);
+            }
+            respond(respond_to, result)
+        }
+        ManagerCommand::ListenState { respond_to } => {
+            let listen = if state.listening {
+                ListenState::Started
+            } else {
+                ListenState::Stopped
+            };
+            respond(respond_to, Ok(listen))
+        }
+        ManagerCommand::DiscoveryState { respond_to } => {
+            let discovery = if state.discovering {
+                DiscoveryState::Started
+            } else {
+                DiscoveryState::Stopped
+            };
+            respond(respond_to, Ok(discovery))
+        }
+        ManagerCommand::P2pState { respond_to } => {
+            let enabled = state.attached
+                && radio_unblocked(options)
+                && with_deadline(executor, limit, backend.ping()).await.is_ok();
+            let p2p = if enabled {
+                P2pState::Enabled
+            } else {
+                P2pState::Disabled
+            };
+            respond(respond_to, Ok(p2p))
+        }
+        ManagerCommand::SetConnectionPolicy {
+            approver,
+            respond_to,
+        } => {
+            state.approver = approver;
+            respond(respond_to, Ok(()))
+        }
+        ManagerCommand::FactoryReset { respond_to } => {
+            let result = with_deadline(executor, limit, backend.factory_reset()).await;
+            if result.is_ok() {
+                factory_reset(backend, executor, state, options, limit, event_tx).await;
             }
-            ManagerCommand::CreateGroup { respond_to } => {
-                let result = backend.create_group().await;
-                if result.is_ok() {
-                    let _ = event_tx.send(P2pEvent::GroupCreated);
+            respond(respond_to, result)
+        }
+        ManagerCommand::RequestDeviceInfo { respond_to } => {
+            let result = refresh_local_device(backend, executor, state, limit, event_tx).await;
+            respond(respond_to, result)
+        }
+        ManagerCommand::SetDeviceName { name, respond_to } => {
+            // The Device Name attribute holds at most 32 bytes.
+            if name.is_empty() || name.len() > 32 {
+                return respond(
+                    respond_to,
+                    Err(P2pError::InvalidArgs(format!(
+                        "device name {name:?} must be 1 to 32 bytes"
+                    ))),
+                );
+            }
+            let call = backend.set_device_name(name.clone());
+            let result = with_deadline(executor, limit, call).await;
+            if result.is_ok() {
+                state.device_name = Some(name);
+                if state.reads_local_device {
+                    let _ = refresh_local_device(backend, executor, state, limit, event_tx).await;
                 }
-                let _ = respond_to.send(result);
             }
+            respond(respond_to, result)
+        }
+    }
+}
+
+/// Whether rfkill lets the interface's radio transmit; an unknown interface or
+/// one without a switch counts as unblocked.
+#[cfg(target_os = "linux")]
+fn radio_unblocked(options: &ManagerOptions) -> bool {
+    options
+        .interface_name
+        .as_deref()
+        .and_then(crate::rfkill::radio_state)
+        .is_none_or(|state| state == crate::rfkill::RadioState::Unblocked)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn radio_unblocked(_options: &ManagerOptions) -> bool {
+    true
+}
+
+fn overtaken(by: &str) -> P2pError {
+    P2pError::Cancelled(format!("{by} was sent after it"))
+}
+
+/// Answer a command, telling whether it succeeded.
+fn respond<T>(
+    respond_to: oneshot::Sender<Result<T, P2pError>>,
+    result: Result<T, P2pError>,
+) -> bool {
+    let succeeded = result.is_ok();
+    let _ = respond_to.send(result);
+    succeeded
+}
+
+/// Put back what the manager had applied before the supplicant restarted. A
+/// failed step is left for the caller to redo; the rest still runs.
+async fn replay_state<B: P2pBackend>(
+    backend: &B,
+    executor: &dyn Executor,
+    state: &mut ManagerState,
+    options: &ManagerOptions,
+    limit: Duration,
+    event_tx: &broadcast::Sender<P2pEvent>,
+) {
+    for service in state.services.clone() {
+        let _ = with_deadline(executor, limit, backend.add_service(service)).await;
+    }
+    for (frame, elements) in state.vendor_elements.clone() {
+        let call = backend.set_vendor_elements(frame, elements);
+        let _ = with_deadline(executor, limit, call).await;
+    }
+    if let Some(info) = state.wfd {
+        let _ = with_deadline(executor, limit, backend.set_wfd_info(Some(info))).await;
+    }
+    if let Some(name) = state.device_name.clone() {
+        let _ = with_deadline(executor, limit, backend.set_device_name(name)).await;
+    }
+    if let Some(store) = &options.credential_store {
+        restore_credentials(backend, executor, state, &**store, limit, event_tx).await;
+    }
+    if std::mem::take(&mut state.resume_listening) {
+        // In the same window as before.
+        let result = match state.listen_window {
+            Some((listen, every)) => {
+                let call = backend.start_listening_windowed(listen, every);
+                with_deadline(executor, limit, call).await
+            }
+            None => with_deadline(executor, limit, backend.start_listening()).await,
+        };
+        if result.is_ok() {
+            state.set_listening(true, event_tx);
+        }
+    }
+    if std::mem::take(&mut state.resume_discovery)
+        && with_deadline(
+            executor,
+            limit,
+            backend.discover_peers(state.discovery.clone()),
+        )
+        .await
+        .is_ok()
+    {
+        state.set_discovering(true, event_tx);
+    }
+}
+
+/// Forget what the manager would put back after a restart and undo our device
+/// settings, once the backend dropped its groups, services and peers.
+async fn factory_reset<B: P2pBackend>(
+    backend: &B,
+    executor: &dyn Executor,
+    state: &mut ManagerState,
+    options: &ManagerOptions,
+    limit: Duration,
+    event_tx: &broadcast::Sender<P2pEvent>,
+) {
+    state.services.clear();
+    state.service_requests.clear();
+    state.resume_discovery = false;
+    state.continuous = None;
+    state.set_discovering(false, event_tx);
+    state.forget_peers(event_tx);
+    for (frame, _) in std::mem::take(&mut state.vendor_elements) {
+        let call = backend.set_vendor_elements(frame, Vec::new());
+        let _ = with_deadline(executor, limit, call).await;
+    }
+    if state.wfd.take().is_some() {
+        let _ = with_deadline(executor, limit, backend.set_wfd_info(None)).await;
+    }
+    if state.device_name.take().is_some()
+        && let Some(name) = state.default_device_name.clone()
+    {
+        let _ = with_deadline(executor, limit, backend.set_device_name(name)).await;
+    }
+    // Otherwise the store would add the groups back on the next start.
+    if let Some(store) = &options.credential_store {
+        state.credentials.clear();
+        if let Err(err) = store.save(&[]) {
+            let _ = event_tx.send(P2pEvent::CredentialStoreFailed {
+                reason: err.to_string(),
+            });
+        }
+    }
+    if state.reads_local_device {
+        let _ = refresh_local_device(backend, executor, state, limit, event_tx).await;
+    }
+}
+
+/// Read our own device and report it when it changed. A backend that cannot
+/// tell is not asked again unless a caller requests the device info.
+async fn refresh_local_device<B: P2pBackend>(
+    backend: &B,
+    executor: &dyn Executor,
+    state: &mut ManagerState,
+    limit: Duration,
+    event_tx: &broadcast::Sender<P2pEvent>,
+) -> Result<LocalDeviceInfo, P2pError> {
+    let result = with_deadline(executor, limit, backend.local_device()).await;
+    match &result {
+        Ok(info) => state.set_local_device(info.clone(), event_tx),
+        Err(P2pError::Unsupported(_) | P2pError::UnsupportedPlatform(_)) => {
+            state.reads_local_device = false;
+        }
+        Err(_) => {}
+    }
+    result
+}
+
+async fn resolve_pending_ips<B: P2pBackend>(
+    backend: &B,
+    executor: &dyn Executor,
+    state: &mut ManagerState,
+    limit: Duration,
+    event_tx: &broadcast::Sender<P2pEvent>,
+) {
+    // The peer usually needs a moment after group formation to get (or announce)
+    // its address, so keep asking until it shows up or the lookups run out.
+    let pending: Vec<MacAddress> = state.ip_lookups.keys().copied().collect();
+    for address in pending {
+        match with_deadline(executor, limit, backend.resolve_peer_ip(address)).await {
+            Ok(Some(ip)) => state.record_peer_ip(address, ip, event_tx),
+            Err(P2pError::Unsupported(_) | P2pError::UnsupportedPlatform(_)) => {
+                state.ip_lookups.remove(&address);
+            }
+            Ok(None) | Err(_) => {
+                if let Some(left) = state.ip_lookups.get_mut(&address) {
+                    *left -= 1;
+                    if *left == 0 {
+                        state.ip_lookups.remove(&address);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Start or stop the scan as continuous discovery's schedule says, telling when
+/// to look again. A scan that stopped early is started again right away.
+async fn rearm_discovery<B: P2pBackend>(
+    backend: &B,
+    executor: &dyn Executor,
+    state: &mut ManagerState,
+    limit: Duration,
+    event_tx: &broadcast::Sender<P2pEvent>,
+) -> Duration {
+    let Some(continuous) = &state.continuous else {
+        return PEER_IP_POLL_INTERVAL;
+    };
+    let (scan, next) = continuous.phase(Instant::now());
+    // Connecting stops the supplicant's scan; stopping it ourselves in the
+    // middle of GO negotiation could break the negotiation.
+    if !state.attached || state.connection_active() || scan == state.discovering {
+        return next;
+    }
+    if scan {
+        let call = backend.discover_peers(state.discovery.clone());
+        match with_deadline(executor, limit, call).await {
+            Ok(()) => state.set_discovering(true, event_tx),
+            Err(err) => tracing::debug!(%err, "restarting discovery failed"),
+        }
+    } else if with_deadline(executor, limit, backend.stop_discovery())
+        .await
+        .is_ok()
+    {
+        state.set_discovering(false, event_tx);
+    }
+    next
+}
+
+async fn poll_link_stats<B: P2pBackend>(
+    backend: &B,
+    executor: &dyn Executor,
+    state: &mut ManagerState,
+    limit: Duration,
+    event_tx: &broadcast::Sender<P2pEvent>,
+) {
+    match with_deadline(executor, limit, backend.link_stats()).await {
+        Ok(stations) => {
+            for stats in stations {
+                let crossing = state
+                    .link_watches
+                    .get_mut(&stats.peer)
+                    .and_then(|watch| watch.sample(&stats));
+                let _ = event_tx.send(P2pEvent::LinkStats(stats));
+                if let Some(crossing) = crossing {
+                    let _ = event_tx.send(crossing);
+                }
+            }
+        }
+        Err(P2pError::Unsupported(_) | P2pError::UnsupportedPlatform(_)) => {
+            state.link_stats = false;
+        }
+        // A group that just went away or a busy supplicant; the next sample may work.
+        Err(_) => {}
+    }
+}
+
+async fn reinvoke<B: P2pBackend>(
+    backend: &B,
+    executor: &dyn Executor,
+    peer: MacAddress,
+    limit: Duration,
+) -> Result<(), P2pError> {
+    let groups = with_deadline(executor, limit, backend.persistent_groups()).await?;
+    let Some(group) = groups.iter().find(|group| group.peer == peer) else {
+        return Err(P2pError::InvalidArgs(format!(
+            "no persistent group with {peer}"
+        )));
+    };
+    let call = backend.invoke_persistent_group(group.network_id, peer);
+    with_deadline(executor, limit, call).await
+}
+
+async fn restore_credentials<B: P2pBackend>(
+    backend: &B,
+    executor: &dyn Executor,
+    state: &mut ManagerState,
+    store: &dyn CredentialStore,
+    limit: Duration,
+    event_tx: &broadcast::Sender<P2pEvent>,
+) {
+    let failed = |err: P2pError| {
+        let _ = event_tx.send(P2pEvent::CredentialStoreFailed {
+            reason: err.to_string(),
+        });
+    };
+    let mut stored = match store.load() {
+        Ok(stored) => stored,
+        Err(err) => return failed(err),
+    };
+    let known = match with_deadline(executor, limit, backend.persistent_groups()).await {
+        Ok(known) => known,
+        // Nothing to restore into, but the store stays as it is.
+        Err(P2pError::Unsupported(_) | P2pError::UnsupportedPlatform(_)) => {
+            state.credentials = stored;
+            return;
+        }
+        Err(err) => return failed(err),
+    };
+    // Groups the supplicant still has keep their id; the rest are added back.
+    for credentials in &mut stored {
+        if let Some(group) = known.iter().find(|group| group.ssid == credentials.ssid) {
+            credentials.network_id = group.network_id;
+            continue;
+        }
+        let call = backend.add_persistent_group(credentials.clone());
+        match with_deadline(executor, limit, call).await {
+            Ok(network_id) => credentials.network_id = network_id,
+            Err(err) => failed(err),
+        }
+    }
+    state.credentials = stored;
+    if let Err(err) = store.save(&state.credentials) {
+        failed(err);
+    }
+}
+
+async fn save_credentials<B: P2pBackend>(
+    backend: &B,
+    executor: &dyn Executor,
+    state: &mut ManagerState,
+    store: &dyn CredentialStore,
+    limit: Duration,
+    event_tx: &broadcast::Sender<P2pEvent>,
+) {
+    let Ok(groups) = with_deadline(executor, limit, backend.persistent_groups()).await else {
+        return;
+    };
+    // Keyed by SSID: a re-formed group replaces what was stored for it.
+    let mut changed = false;
+    for group in groups {
+        match state
+            .credentials
+            .iter_mut()
+            .find(|stored| stored.ssid == group.ssid)
+        {
+            Some(stored) if *stored == group => {}
+            Some(stored) => {
+                *stored = group;
+                changed = true;
+            }
+            None => {
+                state.credentials.push(group);
+                changed = true;
+            }
+        }
+    }
+    if changed && let Err(err) = store.save(&state.credentials) {
+        let _ = event_tx.send(P2pEvent::CredentialStoreFailed {
+            reason: err.to_string(),
+        });
+    }
+}
+
+async fn watch_backend<B: P2pBackend>(
+    backend: &B,
+    executor: &dyn Executor,
+    policy: ReconnectPolicy,
+    events: mpsc::Sender<P2pEvent>,
+) {
+    let mut attempt = 0;
+    loop {
+        let _ = backend.watch_events(events.clone()).await;
+        // The manager task is gone; nobody is left to re-subscribe for.
+        if events.is_closed() {
+            return;
+        }
+        match policy.delay_for(attempt) {
+            Some(delay) => executor.sleep(delay).await,
+            None => return,
+        }
+        attempt += 1;
+    }
+}
+
+/// Mark `interface` unmanaged in NetworkManager, reporting a failure like other
+/// network setup.
+#[cfg(all(feature = "network-manager", target_os = "linux"))]
+async fn unmanage(
+    guard: &crate::net::network_manager::NetworkManagerGuard,
+    interface: &str,
+    event_tx: &broadcast::Sender<P2pEvent>,
+) {
+    if let Err(err) = guard.unmanage(interface).await {
+        let _ = event_tx.send(P2pEvent::NetworkSetupFailed {
+            interface: interface.to_string(),
+            reason: err.to_string(),
+        });
+    }
+}
+
+async fn shutdown_requested(shutdown_rx: &mut watch::Receiver<bool>) {
+    // A dropped sender (the manager went away) counts as a shutdown request too.
+    let _ = shutdown_rx.wait_for(|stop| *stop).await;
+}
+
+/// Tell systemd the loop is alive; a lost ping is left for the watchdog to judge.
+fn ping_watchdog() {
+    #[cfg(all(feature = "systemd", unix))]
+    let _ = crate::systemd::notify("WATCHDOG=1");
+}
+
+async fn cleanup<B: P2pBackend>(
+    backend: &B,
+    executor: &dyn Executor,
+    limit: Duration,
+) -> Result<(), P2pError> {
+    // Attempt both steps even if the first fails; report the first error.
+    let stopped = with_deadline(executor, limit, backend.stop_discovery()).await;
+    let removed = with_deadline(executor, limit, backend.remove_groups()).await;
+    stopped.and(removed)
+}
+
+async fn with_deadline<T>(
+    executor: &dyn Executor,
+    limit: Duration,
+    call: impl Future<Output = Result<T, P2pError>>,
+) -> Result<T, P2pError> {
+    // Dropping the call on expiry cancels the in-flight D-Bus request, so a hung
+    // supplicant cannot wedge the manager loop.
+    tokio::select! {
+        biased;
+        result = call => result,
+        () = executor.sleep(limit) => Err(P2pError::Timeout(limit)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule(interval_ms: u64, duty_cycle: f32) -> Option<ContinuousDiscovery> {
+        ContinuousDiscovery::new(Duration::from_millis(interval_ms), duty_cycle)
+    }
+
+    #[test]
+    fn continuous_discovery_phase_boundaries() {
+        let schedule = schedule(1000, 0.25).unwrap();
+        let at = |ms| schedule.started + Duration::from_millis(ms);
+        let ms = Duration::from_millis;
+        assert_eq!(schedule.phase(at(0)), (true, ms(250)));
+        assert_eq!(schedule.phase(at(249)), (true, ms(1)));
+        assert_eq!(schedule.phase(at(250)), (false, ms(750)));
+        assert_eq!(schedule.phase(at(999)), (false, ms(1)));
+        // Every interval repeats the first.
+        assert_eq!(schedule.phase(at(1000)), (true, ms(250)));
+        assert_eq!(schedule.phase(at(3250)), (false, ms(750)));
+    }
+
+    #[test]
+    fn full_duty_cycle_never_pauses() {
+        let schedule = schedule(1000, 1.0).unwrap();
+        let at = |ms| schedule.started + Duration::from_millis(ms);
+        assert_eq!(schedule.phase(at(0)), (true, Duration::from_secs(1)));
+        assert_eq!(schedule.phase(at(999)), (true, Duration::from_millis(1)));
+        assert_eq!(schedule.phase(at(1000)), (true, Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn degenerate_windows_are_rejected() {
+        assert!(schedule(0, 0.5).is_none());
+        assert!(schedule(1000, 0.0).is_none());
+        assert!(schedule(1000, -0.5).is_none());
+        assert!(schedule(1000, 1.5).is_none());
+        assert!(schedule(1000, f32::NAN).is_none());
+        // A scan shorter than a nanosecond would never run.
+        let tiny = ContinuousDiscovery::new(Duration::from_nanos(1), 0.5);
+        assert!(tiny.is_none());
+        assert!(ContinuousDiscovery::new(Duration::from_nanos(1), 1.0).is_some());
+    }
+
+    #[cfg(feature = "tokio")]
+    mod sim {
+        use super::*;
+        use crate::backend::mock::{ConnectBehavior, SimBackend, VirtualPeer};
+
+        const FIRST: MacAddress = MacAddress::new([0x02, 0, 0, 0, 0, 0x01]);
+        const SECOND: MacAddress = MacAddress::new([0x02, 0, 0, 0, 0, 0x02]);
+        const THIRD: MacAddress = MacAddress::new([0x02, 0, 0, 0, 0, 0x03]);
+
+        async fn wait_for<T>(
+            events: &mut broadcast::Receiver<P2pEvent>,
+            mut pick: impl FnMut(P2pEvent) -> Option<T>,
+        ) -> T {
+            let found = async {
+                loop {
+                    if let Ok(event) = events.recv().await
+                        && let Some(found) = pick(event)
+                    {
+                        return found;
+                    }
+                }
+            };
+            tokio::time::timeout(Duration::from_secs(5), found)
+                .await
+                .expect("event did not arrive")
+        }
+
+        type PeerDiff = (Vec<Arc<P2pDevice>>, Vec<MacAddress>, Vec<Arc<P2pDevice>>);
+
+        async fn next_diff(events: &mut broadcast::Receiver<P2pEvent>) -> PeerDiff {
+            wait_for(events, |event| match event {
+                P2pEvent::PeersChanged {
+                    added,
+                    removed,
+                    updated,
+                } => Some((added, removed, updated)),
+                _ => None,
+            })
+            .await
+        }
+
+        async fn next_discovery_change(events: &mut broadcast::Receiver<P2pEvent>) -> bool {
+            wait_for(events, |event| match event {
+                P2pEvent::DiscoveryStarted => Some(true),
+                P2pEvent::DiscoveryStopped => Some(false),
+                _ => None,
+            })
+            .await
+        }
+
+        #[tokio::test]
+        async fn peer_table_changes_are_reported_as_a_diff() {
+            let sim = SimBackend::new();
+            sim.add_peer(VirtualPeer::new(FIRST));
+            sim.add_peer(VirtualPeer::new(SECOND));
+            let manager = WifiP2pManager::with_backend(Arc::new(sim.clone()));
+            let channel = manager.initialize();
+            let mut events = channel.subscribe_events();
+            channel
+                .discover_peers()
+                .await
+                .unwrap()
+                .await
+                .unwrap()
+                .unwrap();
+            let mut found = Vec::new();
+            while found.len() < 2 {
+                let (added, removed, updated) = next_diff(&mut events).await;
+                assert!(removed.is_empty() && updated.is_empty());
+                found.extend(added.iter().map(|device| device.mac_address));
+            }
+            found.sort();
+            assert_eq!(found, [FIRST, SECOND]);
+
+            // Listing the same peers again changes nothing, so the next diff is
+            // the rename.
+            let listed = channel.request_peers().await.unwrap().await.unwrap();
+            assert_eq!(listed.unwrap().len(), 2);
+            sim.add_peer(VirtualPeer::new(SECOND).named("renamed"));
+            let (added, removed, updated) = next_diff(&mut events).await;
+            assert!(added.is_empty() && removed.is_empty());
+            assert_eq!(updated.len(), 1);
+            assert_eq!(updated[0].device_name.as_deref(), Some("renamed"));
+
+            sim.lose_peer(FIRST);
+            let (added, removed, updated) = next_diff(&mut events).await;
+            assert!(added.is_empty() && updated.is_empty());
+            assert_eq!(removed, [FIRST]);
+            let left: Vec<_> = channel
+                .peers_snapshot()
+                .iter()
+                .map(|device| device.mac_address)
+                .collect();
+            assert_eq!(left, [SECOND]);
+        }
+
+        // Nothing runs on the test's single thread until it waits, so every
+        // command below is queued before the manager takes the first one.
+
+        #[tokio::test]
+        async fn stop_discovery_overtakes_a_queued_discover() {
+            let sim = SimBackend::new();
+            let manager = WifiP2pManager::with_backend(Arc::new(sim.clone()));
+            let channel = manager.initialize();
+            let overtaken = channel.discover_peers().await.unwrap();
+            let stop = channel.stop_discovery().await.unwrap();
+            let after = channel.discover_peers().await.unwrap();
+            assert!(matches!(
+                overtaken.await.unwrap(),
+                Err(P2pError::Cancelled(_))
+            ));
+            stop.await.unwrap().unwrap();
+            // Sent after the stop, so neither overtaken nor answered with the
+            // first one.
+            after.await.unwrap().unwrap();
+            assert!(sim.is_discovering());
+            assert_eq!(sim.discovery_rounds(), 1);
+        }
+
+        #[tokio::test]
+        async fn cancel_connect_overtakes_a_queued_connect() {
+            let sim = SimBackend::new();
+            let manager = WifiP2pManager::with_backend(Arc::new(sim.clone()));
+            let channel = manager.initialize();
+            // The simulator does not know the peer: a connect that reached it
+            // would fail with `InvalidArgs`.
+            let connect = channel.connect(FIRST).await.unwrap();
+            let cancel = channel.cancel_connect().await.unwrap();
+            assert!(matches!(
+                connect.await.unwrap(),
+                Err(P2pError::Cancelled(_))
+            ));
+            cancel.await.unwrap().unwrap();
+            assert!(sim.connected_peers().is_empty());
+        }
+
+        #[tokio::test]
+        async fn identical_queued_commands_share_one_call() {
+            let sim = SimBackend::new();
+            let manager = WifiP2pManager::with_backend(Arc::new(sim.clone()));
+            let channel = manager.initialize();
+            let first = channel.discover_peers().await.unwrap();
+            let second = channel.discover_peers().await.unwrap();
+            let social = channel
+                .discover_peers_with(DiscoveryType::Social)
+                .await
+                .unwrap();
+            let third = channel.discover_peers().await.unwrap();
+            for answer in [first, second, social, third] {
+                answer.await.unwrap().unwrap();
+            }
+            // The first two share a call; the social scan in between keeps the
+            // third from joining them.
+            assert_eq!(sim.discovery_rounds(), 3);
+        }
+
+        #[tokio::test]
+        async fn expired_peers_are_lost_unless_engaged() {
+            let sim = SimBackend::new();
+            sim.add_peer(VirtualPeer::new(FIRST));
+            sim.add_peer(VirtualPeer::new(SECOND));
+            sim.add_peer(VirtualPeer::new(THIRD).on_connect(ConnectBehavior::Ignore));
+            let manager = WifiP2pManager::builder()
+                .peer_ttl(Duration::from_millis(400))
+                .build_with_backend(Arc::new(sim.clone()))
+                .unwrap();
+            let channel = manager.initialize();
+            let mut events = channel.subscribe_events();
+            channel
+                .discover_peers()
+                .await
+                .unwrap()
+                .await
+                .unwrap()
+                .unwrap();
+            let mut found = 0;
+            while found < 3 {
+                found += next_diff(&mut events).await.0.len();
+            }
+            // Connected to the second, still inviting the third.
+            channel
+                .connect(SECOND)
+                .await
+                .unwrap()
+                .await
+                .unwrap()
+                .unwrap();
+            let _invite = channel.connect(THIRD).await.unwrap();
+
+            // The simulator reports a peer once per scan, so all three go stale.
+            let lost = wait_for(&mut events, |event| match event {
+                P2pEvent::PeerLost(address) => Some(address),
+                _ => None,
+            })
+            .await;
+            assert_eq!(lost, FIRST);
+            tokio::time::sleep(Duration::from_millis(600)).await;
+            let left: Vec<_> = channel
+                .peers_snapshot()
+                .iter()
+                .map(|device| (device.mac_address, device.status))
+                .collect();
+            assert_eq!(
+                left,
+                [
+                    (SECOND, DeviceStatus::Connected),
+                    (THIRD, DeviceStatus::Invited)
+                ]
+            );
+        }
+
+        #[tokio::test]
+        async fn continuous_discovery_follows_its_duty_cycle() {
+            let sim = SimBackend::new();
+            let manager = WifiP2pManager::with_backend(Arc::new(sim.clone()));
+            let channel = manager.initialize();
+            let mut events = channel.subscribe_events();
+            let started = Instant::now();
+            channel
+                .start_continuous_discovery(Duration::from_millis(400), 0.5)
+                .await
+                .unwrap()
+                .await
+                .unwrap()
+                .unwrap();
+            assert!(next_discovery_change(&mut events).await);
+            // Paused for the second half of the interval, then scanning again.
+            assert!(!next_discovery_change(&mut events).await);
+            assert!(started.elapsed() >= Duration::from_millis(200));
+            assert!(next_discovery_change(&mut events).await);
+            assert!(started.elapsed() >= Duration::from_millis(400));
+            assert_eq!(sim.discovery_rounds(), 2);
+
+            // A scan the supplicant ends early is started again right away.
+            sim.end_discovery();
+            assert!(!next_discovery_change(&mut events).await);
+            assert!(next_discovery_change(&mut events).await);
+            assert_eq!(sim.discovery_rounds(), 3);
+
+            channel
+                .stop_discovery()
+                .await
+                .unwrap()
+                .await
+                .unwrap()
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            assert!(!sim.is_discovering());
+            assert_eq!(sim.discovery_rounds(), 3);
         }
     }
 }
@@ -0,0 +1,219 @@
+//! Counters and histograms in the Prometheus text format.
+//!
+//! `WifiP2pManager::metrics` (or `WifiP2pChannel::metrics`) hands out the
+//! manager's `Metrics`; `render` formats them for a scrape, and the HTTP API
+//! serves them at `GET /metrics`. Clones share the same values.
+//!
+//! | Metric                                  | Type      | Labels    |
+//! |-----------------------------------------|-----------|-----------|
+//! | `wifip2p_discoveries_started_total`     | counter   |           |
+//! | `wifip2p_peers_found_total`             | counter   |           |
+//! | `wifip2p_connect_attempts_total`        | counter   |           |
+//! | `wifip2p_connect_successes_total`       | counter   |           |
+//! | `wifip2p_connect_failures_total`        | counter   | `reason`  |
+//! | `wifip2p_negotiation_failures_total`    | counter   | `status`  |
+//! | `wifip2p_command_duration_seconds`      | histogram | `command` |
+//! | `wifip2p_events_lagged_total`           | counter   |           |
+//!
+//! Every connect attempt ends up in the successes or, by `reason`, the
+//! failures: the P2P status code when the peer sent one (e.g. `"7"`, no common
+//! channels), otherwise the `P2pError` kind (`"Timeout"`). A connect the backend
+//! accepted can still fail later; those GO negotiation and provision discovery
+//! failures are counted apart, by status code.
+//! Command durations cover the backend calls a command makes, which on Linux
+//! are D-Bus calls to wpa_supplicant. Lagged events are those `P2pEventStream`s
+//! skipped because their consumer fell behind `event_buffer`.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::error::P2pError;
+use crate::status::P2pStatusCode;
+
+/// Upper bounds of the duration buckets, in seconds; the last bucket is `+Inf`.
+const BUCKETS: [f64; 12] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+];
+
+/// The manager's counters; see the module docs for what each one counts.
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    discoveries_started: AtomicU64,
+    peers_found: AtomicU64,
+    connect_attempts: AtomicU64,
+    connect_successes: AtomicU64,
+    events_lagged: AtomicU64,
+    connect_failures: Mutex<BTreeMap<String, u64>>,
+    negotiation_failures: Mutex<BTreeMap<i32, u64>>,
+    command_durations: Mutex<BTreeMap<&'static str, Histogram>>,
+}
+
+#[derive(Debug, Default)]
+struct Histogram {
+    // Per bucket, not cumulative; `render` adds them up.
+    buckets: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn discovery_started(&self) {
+        self.inner
+            .discoveries_started
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn peer_found(&self) {
+        self.inner.peers_found.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn connect_attempted(&self) {
+        self.inner.connect_attempts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn connect_succeeded(&self) {
+        self.inner.connect_successes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn connect_failed(&self, err: &P2pError) {
+        let reason = match err {
+            P2pError::Status(status) => status.code().to_string(),
+            other => other.kind().to_string(),
+        };
+        *lock(&self.inner.connect_failures)
+            .entry(reason)
+            .or_default() += 1;
+    }
+
+    pub(crate) fn negotiation_failed(&self, status: P2pStatusCode) {
+        *lock(&self.inner.negotiation_failures)
+            .entry(status.code())
+            .or_default() += 1;
+    }
+
+    pub(crate) fn command_finished(&self, command: &'static str, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        let mut durations = lock(&self.inner.command_durations);
+        let histogram = durations.entry(command).or_default();
+        if let Some(bucket) = BUCKETS.iter().position(|bound| seconds <= *bound) {
+            histogram.buckets[bucket] += 1;
+        }
+        histogram.sum += seconds;
+        histogram.count += 1;
+    }
+
+    pub(crate) fn events_lagged(&self, missed: u64) {
+        self.inner
+            .events_lagged
+            .fetch_add(missed, Ordering::Relaxed);
+    }
+
+    /// Every metric in the Prometheus text exposition format (version 0.0.4).
+    pub fn render(&self) -> String {
+        let inner = &*self.inner;
+        let mut out = String::new();
+        let counters = [
+            (
+                "wifip2p_discoveries_started_total",
+                "Peer discoveries started.",
+                &inner.discoveries_started,
+            ),
+            (
+                "wifip2p_peers_found_total",
+                "Peers reported found by the backend.",
+                &inner.peers_found,
+            ),
+            (
+                "wifip2p_connect_attempts_total",
+                "Connect commands sent to the backend.",
+                &inner.connect_attempts,
+            ),
+            (
+                "wifip2p_connect_successes_total",
+                "Connect commands the backend accepted.",
+                &inner.connect_successes,
+            ),
+            (
+                "wifip2p_events_lagged_total",
+                "Events skipped by event streams that fell behind.",
+                &inner.events_lagged,
+            ),
+        ];
+        for (name, help, value) in counters {
+            header(&mut out, name, help, "counter");
+            let _ = writeln!(out, "{name} {}", value.load(Ordering::Relaxed));
+        }
+
+        let name = "wifip2p_connect_failures_total";
+        header(
+            &mut out,
+            name,
+            "Failed connects by status code or error kind.",
+            "counter",
+        );
+        for (reason, count) in lock(&inner.connect_failures).iter() {
+            let _ = writeln!(out, "{name}{{reason=\"{reason}\"}} {count}");
+        }
+
+        let name = "wifip2p_negotiation_failures_total";
+        header(
+            &mut out,
+            name,
+            "GO negotiation and provision discovery failures by status code.",
+            "counter",
+        );
+        for (status, count) in lock(&inner.negotiation_failures).iter() {
+            let _ = writeln!(out, "{name}{{status=\"{status}\"}} {count}");
+        }
+
+        let name = "wifip2p_command_duration_seconds";
+        header(
+            &mut out,
+            name,
+            "Time commands spent in the backend.",
+            "histogram",
+        );
+        for (command, histogram) in lock(&inner.command_durations).iter() {
+            let mut cumulative = 0;
+            for (bound, count) in BUCKETS.iter().zip(histogram.buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "{name}_bucket{{command=\"{command}\",le=\"{bound}\"}} {cumulative}"
+                );
+            }
+            let count = histogram.count;
+            let _ = writeln!(
+                out,
+                "{name}_bucket{{command=\"{command}\",le=\"+Inf\"}} {count}"
+            );
+            let _ = writeln!(out, "{name}_sum{{command=\"{command}\"}} {}", histogram.sum);
+            let _ = writeln!(out, "{name}_count{{command=\"{command}\"}} {count}");
+        }
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // Each update is a single increment, so a panicking holder leaves nothing half-done.
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
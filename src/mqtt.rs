@@ -0,0 +1,246 @@
+//! MQTT bridge, so fleets of headless devices can be watched and driven from a
+//! central broker.
+//!
+//! Under a per-device `prefix` (e.g. `wifip2p/kiosk-17`) the bridge publishes:
+//!
+//! - `<prefix>/events/<variant>`: every `P2pEvent`, as
+//!   `{"time": <unix seconds>, "type": "<variant>", "data": ...}` (QoS 0)
+//! - `<prefix>/peers` and `<prefix>/groups`: the peer and group tables in the
+//!   crate's serde form, retained and republished when they change
+//! - `<prefix>/status`: `online`, retained, with `offline` as the last will
+//! - `<prefix>/result`: the outcome of each command (QoS 1)
+//!
+//! and takes commands on `<prefix>/command/<name>`, with a JSON object payload
+//! (or none): `discover`, `stop_discovery`, `create_group`, `remove_group`,
+//! `connect` (`address`, optional `method` and `pin`), `invite` and `reject`
+//! (`address`). Results are `{"command": ..., "id": ..., "ok": true}`, with
+//! `pin` after a connect, or `"ok": false` and an `error` of the form
+//! `{"kind": ..., "message": ...}`. `id` is copied from the command so callers
+//! can match answers to requests.
+//!
+//! Commands are accepted from anyone who can publish to the topic; restrict
+//! that with the broker's ACLs. rumqttc is built without TLS here: enable its
+//! `use-rustls` feature in your own manifest to reach a broker over TLS.
+
+use std::time::Duration;
+
+use rumqttc::{AsyncClient, Event, LastWill, MqttOptions, Packet, QoS};
+use serde_json::{Map, Value, json};
+use tokio::sync::oneshot;
+use tokio_stream::StreamExt;
+
+use crate::channel::{P2pEvent, WifiP2pChannel};
+use crate::error::P2pError;
+use crate::json::{ConnectOptions, event_json};
+use crate::mac::MacAddress;
+
+/// How long to wait before reconnecting after the broker connection fails.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Bridge `channel` to the broker in `options` under `prefix` until the manager
+/// stops. Connection failures are retried every few seconds rather than returned.
+pub async fn bridge(
+    channel: WifiP2pChannel,
+    mut options: MqttOptions,
+    prefix: &str,
+) -> Result<(), P2pError> {
+    let topics = Topics::new(prefix);
+    options.set_last_will(LastWill::new(
+        &topics.status,
+        "offline",
+        QoS::AtLeastOnce,
+        true,
+    ));
+    let (client, mut eventloop) = AsyncClient::new(options, 64);
+    let mut events = channel.event_stream();
+    loop {
+        tokio::select! {
+            event = events.next() => match event {
+                Some(event) => publish_event(&client, &topics, &channel, &event),
+                None => break,
+            },
+            () = channel.closed() => break,
+            polled = eventloop.poll() => match polled {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    // The broker may have forgotten us; subscribe and publish state again.
+                    let commands = format!("{}#", topics.command);
+                    let _ = client.try_subscribe(commands, QoS::AtLeastOnce);
+                    retain(&client, &topics.status, "online".into());
+                    publish_peers(&client, &topics, &channel);
+                    publish_groups(&client, &topics, &channel);
+                }
+                Ok(Event::Incoming(Packet::Publish(publish))) => {
+                    if let Some(name) = publish.topic.strip_prefix(&topics.command) {
+                        let command = Command::parse(name, &publish.payload);
+                        let result = topics.result.clone();
+                        tokio::spawn(command.run(channel.clone(), client.clone(), result));
+                    }
+                }
+                Ok(_) => {}
+                // rumqttc reconnects on the next poll.
+                Err(_) => tokio::time::sleep(RECONNECT_DELAY).await,
+            },
+        }
+    }
+    // Say goodbye ourselves rather than leave it to the last will.
+    retain(&client, &topics.status, "offline".into());
+    let _ = client.try_disconnect();
+    while let Ok(event) = eventloop.poll().await {
+        if let Event::Outgoing(rumqttc::Outgoing::Disconnect) = event {
+            break;
+        }
+    }
+    Ok(())
+}
+
+struct Topics {
+    status: String,
+    peers: String,
+    groups: String,
+    events: String,
+    command: String,
+    result: String,
+}
+
+impl Topics {
+    fn new(prefix: &str) -> Self {
+        let prefix = prefix.trim_end_matches('/');
+        Self {
+            status: format!("{prefix}/status"),
+            peers: format!("{prefix}/peers"),
+            groups: format!("{prefix}/groups"),
+            events: format!("{prefix}/events/"),
+            command: format!("{prefix}/command/"),
+            result: format!("{prefix}/result"),
+        }
+    }
+}
+
+// The main loop never waits on the client: a full request queue would stall the
+// event loop that drains it, so publishes are dropped instead.
+fn publish_event(
+    client: &AsyncClient,
+    topics: &Topics,
+    channel: &WifiP2pChannel,
+    event: &P2pEvent,
+) {
+    let line = event_json(event);
+    let kind = line["type"].as_str().unwrap_or("Unknown");
+    let topic = format!("{}{kind}", topics.events);
+    let _ = client.try_publish(topic, QoS::AtMostOnce, false, line.to_string());
+    match event {
+        P2pEvent::PeersChanged { .. } | P2pEvent::PeerStatusChanged(..) => {
+            publish_peers(client, topics, channel);
+        }
+        P2pEvent::GroupStarted(_) | P2pEvent::GroupFinished(_) => {
+            publish_groups(client, topics, channel);
+        }
+        _ => {}
+    }
+}
+
+fn publish_peers(client: &AsyncClient, topics: &Topics, channel: &WifiP2pChannel) {
+    retain(
+        client,
+        &topics.peers,
+        json!(channel.peers_snapshot()).to_string(),
+    );
+}
+
+fn publish_groups(client: &AsyncClient, topics: &Topics, channel: &WifiP2pChannel) {
+    retain(
+        client,
+        &topics.groups,
+        json!(channel.groups_snapshot()).to_string(),
+    );
+}
+
+fn retain(client: &AsyncClient, topic: &str, payload: String) {
+    let _ = client.try_publish(topic, QoS::AtLeastOnce, true, payload);
+}
+
+/// A command received on `<prefix>/command/<name>`.
+struct Command {
+    name: String,
+    args: Map<String, Value>,
+}
+
+impl Command {
+    fn parse(name: &str, payload: &[u8]) -> Self {
+        let args = match serde_json::from_slice(payload) {
+            Ok(Value::Object(args)) => args,
+            _ => Map::new(),
+        };
+        Self {
+            name: name.to_string(),
+            args,
+        }
+    }
+
+    async fn run(self, channel: WifiP2pChannel, client: AsyncClient, topic: String) {
+        let mut result = Map::new();
+        result.insert("command".to_string(), Value::String(self.name.clone()));
+        result.insert(
+            "id".to_string(),
+            self.args.get("id").cloned().unwrap_or(Value::Null),
+        );
+        match self.execute(&channel).await {
+            Ok(pin) => {
+                result.insert("ok".to_string(), Value::Bool(true));
+                if let Some(pin) = pin {
+                    result.insert("pin".to_string(), Value::String(pin));
+                }
+            }
+            Err(err) => {
+                result.insert("ok".to_string(), Value::Bool(false));
+                result.insert("error".to_string(), json!(err));
+            }
+        }
+        let payload = Value::Object(result).to_string();
+        let _ = client
+            .publish(topic, QoS::AtLeastOnce, false, payload)
+            .await;
+    }
+
+    /// Carry out the command, returning the PIN a connect generated.
+    async fn execute(self, channel: &WifiP2pChannel) -> Result<Option<String>, P2pError> {
+        match self.name.as_str() {
+            "discover" => answer(channel.discover_peers()).await?,
+            "stop_discovery" => answer(channel.stop_discovery()).await?,
+            "create_group" => answer(channel.create_group()).await?,
+            "remove_group" => answer(channel.remove_group()).await?,
+            "invite" => answer(channel.invite(self.address()?)).await?,
+            "reject" => answer(channel.reject_peer(self.address()?)).await?,
+            "connect" => {
+                let address = self.address()?;
+                let options: ConnectOptions = serde_json::from_value(Value::Object(self.args))
+                    .map_err(|err| P2pError::InvalidArgs(format!("connect: {err}")))?;
+                return answer(channel.connect_with_config(options.config(address))).await;
+            }
+            other => {
+                return Err(P2pError::InvalidArgs(format!("unknown command {other}")));
+            }
+        }
+        Ok(None)
+    }
+
+    fn address(&self) -> Result<MacAddress, P2pError> {
+        match self.args.get("address") {
+            Some(Value::String(address)) => address.parse(),
+            _ => Err(P2pError::InvalidArgs(format!(
+                "{} needs an address",
+                self.name
+            ))),
+        }
+    }
+}
+
+/// Wait for a queued command's answer.
+async fn answer<T>(
+    queued: impl Future<Output = Result<oneshot::Receiver<Result<T, P2pError>>, P2pError>>,
+) -> Result<T, P2pError> {
+    queued
+        .await?
+        .await
+        .unwrap_or_else(|_| Err(P2pError::ChannelClosed("manager".to_string())))
+}
@@ -0,0 +1,138 @@
+//! Keep ConnMan from competing for the radio on ConnMan-based systems (AGL and
+//! many IVI stacks).
+//!
+//! ConnMan drives wpa_supplicant itself: its own `p2p` technology runs peer
+//! discovery and groups on the same device, and tethering turns the interface
+//! into an access point. `ConnmanGuard` switches both off through ConnMan's
+//! technology API and puts back what it changed with `restore`. ConnMan has no
+//! per-interface switch over D-Bus; to keep it off an interface altogether,
+//! list it in `NetworkInterfaceBlacklist` in `/etc/connman/main.conf`. The
+//! manager does this with `WifiP2pManagerBuilder::release_connman`.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use tokio::sync::{Mutex, OnceCell};
+use zbus::fdo::DBusProxy;
+use zbus::names::BusName;
+use zbus::zvariant::{OwnedObjectPath, OwnedValue, Value};
+use zbus::{Connection, Proxy};
+
+use crate::error::P2pError;
+
+const CONNMAN_SERVICE: &str = "net.connman";
+const MANAGER_INTERFACE: &str = "net.connman.Manager";
+const TECHNOLOGY_INTERFACE: &str = "net.connman.Technology";
+
+/// Technology properties switched off, by technology type, while we own the radio.
+const RELEASED: [(&str, &str); 2] = [("p2p", "Powered"), ("wifi", "Tethering")];
+
+/// ConnMan settings changed by this guard, to be put back. Clones share them.
+#[derive(Debug, Clone, Default)]
+pub struct ConnmanGuard {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    connection: OnceCell<Connection>,
+    // Held across `restore`, so a concurrent call waits for it to finish.
+    changed: Mutex<Vec<(OwnedObjectPath, &'static str)>>,
+}
+
+impl ConnmanGuard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Turn off ConnMan's P2P technology and Wi-Fi tethering where they are on.
+    /// Returns `Ok(false)` when ConnMan is not running.
+    pub async fn release(&self) -> Result<bool, P2pError> {
+        let connection = self.connection().await?;
+        let dbus = DBusProxy::new(connection).await?;
+        let name = BusName::try_from(CONNMAN_SERVICE).map_err(zbus::Error::from)?;
+        if !dbus.name_has_owner(name).await? {
+            return Ok(false);
+        }
+        let manager = Proxy::new(connection, CONNMAN_SERVICE, "/", MANAGER_INTERFACE).await?;
+        let technologies: Vec<(OwnedObjectPath, HashMap<String, OwnedValue>)> = manager
+            .call("GetTechnologies", &())
+            .await
+            .map_err(connman_error)?;
+        let mut changed = self.inner.changed.lock().await;
+        for (path, properties) in technologies {
+            let kind = properties
+                .get("Type")
+                .and_then(|kind| <&str>::try_from(kind).ok());
+            for (technology, property) in RELEASED {
+                let on = properties
+                    .get(property)
+                    .and_then(|value| bool::try_from(value).ok())
+                    .unwrap_or(false);
+                let known = changed
+                    .iter()
+                    .any(|(known, name)| *known == path && *name == property);
+                if kind != Some(technology) || !on || known {
+                    continue;
+                }
+                set_property(connection, &path, property, false).await?;
+                changed.push((path.clone(), property));
+            }
+        }
+        Ok(true)
+    }
+
+    /// Switch back on what `release` turned off. Calling it again is a no-op.
+    pub async fn restore(&self) -> Result<(), P2pError> {
+        let mut changed = self.inner.changed.lock().await;
+        let Some(connection) = self.inner.connection.get() else {
+            return Ok(());
+        };
+        let mut result = Ok(());
+        for (path, property) in changed.drain(..) {
+            // Keep going, so one failure does not leave the rest switched off.
+            if let Err(err) = set_property(connection, &path, property, true).await {
+                result = Err(err);
+            }
+        }
+        result
+    }
+
+    async fn connection(&self) -> Result<&Connection, P2pError> {
+        self.inner
+            .connection
+            .get_or_try_init(Connection::system)
+            .await
+            .map_err(connman_error)
+    }
+}
+
+async fn set_property(
+    connection: &Connection,
+    technology: &OwnedObjectPath,
+    property: &str,
+    value: bool,
+) -> Result<(), P2pError> {
+    let proxy = Proxy::new(
+        connection,
+        CONNMAN_SERVICE,
+        technology,
+        TECHNOLOGY_INTERFACE,
+    )
+    .await?;
+    proxy
+        .call::<_, _, ()>("SetProperty", &(property, Value::from(value)))
+        .await
+        .map_err(connman_error)
+}
+
+fn connman_error(err: zbus::Error) -> P2pError {
+    match P2pError::from(err) {
+        P2pError::DBus(err) => P2pError::Backend(format!("ConnMan: {err}")),
+        // Not running means nothing to coordinate with, not a missing supplicant.
+        P2pError::SupplicantUnavailable(message) => {
+            P2pError::Backend(format!("ConnMan: {message}"))
+        }
+        other => other,
+    }
+}
@@ -0,0 +1,247 @@
+//! DHCPv4 wire format and socket setup shared by the built-in server and client.
+
+use std::io;
+use std::net::{Ipv4Addr, SocketAddrV4};
+
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::UdpSocket;
+
+use crate::mac::MacAddress;
+
+pub(crate) const SERVER_PORT: u16 = 67;
+pub(crate) const CLIENT_PORT: u16 = 68;
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+// Fixed BOOTP header up to and including the magic cookie.
+const HEADER_LEN: usize = 240;
+
+pub(crate) const BOOT_REQUEST: u8 = 1;
+pub(crate) const BOOT_REPLY: u8 = 2;
+const HTYPE_ETHERNET: u8 = 1;
+/// Asks the server to broadcast its reply; set by clients without an address.
+#[cfg_attr(not(feature = "dhcp-client"), allow(dead_code))]
+pub(crate) const FLAG_BROADCAST: u16 = 0x8000;
+
+const OPTION_PAD: u8 = 0;
+const OPTION_SUBNET_MASK: u8 = 1;
+const OPTION_REQUESTED_IP: u8 = 50;
+const OPTION_LEASE_TIME: u8 = 51;
+const OPTION_MESSAGE_TYPE: u8 = 53;
+const OPTION_SERVER_ID: u8 = 54;
+const OPTION_END: u8 = 255;
+
+pub(crate) const DHCP_DISCOVER: u8 = 1;
+pub(crate) const DHCP_OFFER: u8 = 2;
+pub(crate) const DHCP_REQUEST: u8 = 3;
+#[cfg_attr(not(feature = "dhcp-server"), allow(dead_code))]
+pub(crate) const DHCP_DECLINE: u8 = 4;
+pub(crate) const DHCP_ACK: u8 = 5;
+pub(crate) const DHCP_NAK: u8 = 6;
+#[cfg_attr(not(feature = "dhcp-server"), allow(dead_code))]
+pub(crate) const DHCP_RELEASE: u8 = 7;
+
+/// A DHCP message, reduced to the fields and options both sides use.
+#[derive(Debug, Clone)]
+pub(crate) struct Message {
+    pub(crate) op: u8,
+    pub(crate) xid: u32,
+    pub(crate) flags: u16,
+    pub(crate) ciaddr: Ipv4Addr,
+    pub(crate) yiaddr: Ipv4Addr,
+    pub(crate) chaddr: MacAddress,
+    pub(crate) message_type: u8,
+    pub(crate) requested_ip: Option<Ipv4Addr>,
+    pub(crate) server_id: Option<Ipv4Addr>,
+    pub(crate) subnet_mask: Option<Ipv4Addr>,
+    pub(crate) lease_secs: Option<u32>,
+}
+
+impl Message {
+    pub(crate) fn new(op: u8, message_type: u8, xid: u32, chaddr: MacAddress) -> Self {
+        Self {
+            op,
+            xid,
+            flags: 0,
+            ciaddr: Ipv4Addr::UNSPECIFIED,
+            yiaddr: Ipv4Addr::UNSPECIFIED,
+            chaddr,
+            message_type,
+            requested_ip: None,
+            server_id: None,
+            subnet_mask: None,
+            lease_secs: None,
+        }
+    }
+
+    /// Decode an Ethernet DHCP message; anything else (BOOTP, other hardware) is `None`.
+    pub(crate) fn parse(packet: &[u8]) -> Option<Self> {
+        if packet.len() < HEADER_LEN
+            || packet[1] != HTYPE_ETHERNET
+            || packet[2] != 6
+            || packet[236..240] != MAGIC_COOKIE
+        {
+            return None;
+        }
+        let ip = |bytes: &[u8]| <[u8; 4]>::try_from(bytes).ok().map(Ipv4Addr::from);
+        let mut message = Self {
+            op: packet[0],
+            xid: u32::from_be_bytes(packet[4..8].try_into().ok()?),
+            flags: u16::from_be_bytes(packet[10..12].try_into().ok()?),
+            ciaddr: ip(&packet[12..16])?,
+            yiaddr: ip(&packet[16..20])?,
+            chaddr: MacAddress::try_from(&packet[28..34]).ok()?,
+            message_type: 0,
+            requested_ip: None,
+            server_id: None,
+            subnet_mask: None,
+            lease_secs: None,
+        };
+        let mut options = &packet[HEADER_LEN..];
+        while let [code, rest @ ..] = options {
+            match *code {
+                OPTION_PAD => {
+                    options = rest;
+                    continue;
+                }
+                OPTION_END => break,
+                _ => {}
+            }
+            let (&len, rest) = rest.split_first()?;
+            let value = rest.get(..usize::from(len))?;
+            match *code {
+                OPTION_MESSAGE_TYPE => message.message_type = *value.first()?,
+                OPTION_REQUESTED_IP => message.requested_ip = ip(value),
+                OPTION_SERVER_ID => message.server_id = ip(value),
+                OPTION_SUBNET_MASK => message.subnet_mask = ip(value),
+                OPTION_LEASE_TIME => {
+                    message.lease_secs = <[u8; 4]>::try_from(value).ok().map(u32::from_be_bytes)
+                }
+                _ => {}
+            }
+            options = &rest[usize::from(len)..];
+        }
+        (message.message_type != 0).then_some(message)
+    }
+
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut packet = vec![0u8; HEADER_LEN];
+        packet[0] = self.op;
+        packet[1] = HTYPE_ETHERNET;
+        packet[2] = 6;
+        packet[4..8].copy_from_slice(&self.xid.to_be_bytes());
+        packet[10..12].copy_from_slice(&self.flags.to_be_bytes());
+        packet[12..16].copy_from_slice(&self.ciaddr.octets());
+        packet[16..20].copy_from_slice(&self.yiaddr.octets());
+        packet[28..34].copy_from_slice(self.chaddr.as_bytes());
+        packet[236..240].copy_from_slice(&MAGIC_COOKIE);
+
+        let mut option = |code: u8, value: &[u8]| {
+            packet.push(code);
+            packet.push(value.len() as u8);
+            packet.extend_from_slice(value);
+        };
+        option(OPTION_MESSAGE_TYPE, &[self.message_type]);
+        if let Some(ip) = self.requested_ip {
+            option(OPTION_REQUESTED_IP, &ip.octets());
+        }
+        if let Some(ip) = self.server_id {
+            option(OPTION_SERVER_ID, &ip.octets());
+        }
+        if let Some(secs) = self.lease_secs {
+            option(OPTION_LEASE_TIME, &secs.to_be_bytes());
+        }
+        if let Some(mask) = self.subnet_mask {
+            option(OPTION_SUBNET_MASK, &mask.octets());
+        }
+        packet.push(OPTION_END);
+        packet
+    }
+}
+
+/// A broadcast-capable UDP socket on `port`, bound to `interface` so other DHCP
+/// servers and clients on the host are unaffected.
+pub(crate) fn bind(interface: &str, port: u16) -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    socket.set_broadcast(true)?;
+    socket.bind_device(Some(interface.as_bytes()))?;
+    socket.bind(&SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port).into())?;
+    socket.set_nonblocking(true)?;
+    UdpSocket::from_std(socket.into())
+}
+
+/// Put `ip/prefix_len` on `interface`; "replace" keeps this idempotent when a
+/// group interface is reused or a lease is renewed.
+pub(crate) fn assign_address(interface: &str, ip: Ipv4Addr, prefix_len: u8) -> Result<(), String> {
+    let address = format!("{ip}/{prefix_len}");
+    super::run_ip(&["-4", "address", "replace", &address, "dev", interface])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLIENT: MacAddress = MacAddress::new([0x02, 0x00, 0x00, 0x00, 0x00, 0x01]);
+
+    fn ack() -> Message {
+        let mut message = Message::new(BOOT_REPLY, DHCP_ACK, 0x1234_5678, CLIENT);
+        message.flags = FLAG_BROADCAST;
+        message.ciaddr = Ipv4Addr::new(192, 168, 49, 7);
+        message.yiaddr = Ipv4Addr::new(192, 168, 49, 20);
+        message.requested_ip = Some(Ipv4Addr::new(192, 168, 49, 20));
+        message.server_id = Some(Ipv4Addr::new(192, 168, 49, 1));
+        message.subnet_mask = Some(Ipv4Addr::new(255, 255, 255, 0));
+        message.lease_secs = Some(3600);
+        message
+    }
+
+    #[test]
+    fn encoded_message_parses_back() {
+        let sent = ack();
+        let parsed = Message::parse(&sent.encode()).unwrap();
+        assert_eq!(parsed.op, sent.op);
+        assert_eq!(parsed.xid, sent.xid);
+        assert_eq!(parsed.flags, sent.flags);
+        assert_eq!(parsed.ciaddr, sent.ciaddr);
+        assert_eq!(parsed.yiaddr, sent.yiaddr);
+        assert_eq!(parsed.chaddr, sent.chaddr);
+        assert_eq!(parsed.message_type, sent.message_type);
+        assert_eq!(parsed.requested_ip, sent.requested_ip);
+        assert_eq!(parsed.server_id, sent.server_id);
+        assert_eq!(parsed.subnet_mask, sent.subnet_mask);
+        assert_eq!(parsed.lease_secs, sent.lease_secs);
+    }
+
+    #[test]
+    fn pads_and_unknown_options_are_skipped() {
+        let mut packet = Message::new(BOOT_REQUEST, DHCP_DISCOVER, 1, CLIENT).encode();
+        packet.truncate(HEADER_LEN);
+        // Pad, a hostname option, then the message type.
+        packet.extend_from_slice(&[OPTION_PAD, 12, 3, b'p', b'c', b'1']);
+        packet.extend_from_slice(&[OPTION_MESSAGE_TYPE, 1, DHCP_REQUEST, OPTION_END]);
+        let parsed = Message::parse(&packet).unwrap();
+        assert_eq!(parsed.message_type, DHCP_REQUEST);
+        assert_eq!(parsed.requested_ip, None);
+    }
+
+    #[test]
+    fn malformed_packets_are_rejected() {
+        let packet = ack().encode();
+        // Shorter than the fixed header.
+        assert!(Message::parse(&packet[..HEADER_LEN - 1]).is_none());
+        // An option running past the end of the packet.
+        let mut truncated = packet[..HEADER_LEN].to_vec();
+        truncated.extend_from_slice(&[OPTION_MESSAGE_TYPE, 1, DHCP_ACK, OPTION_LEASE_TIME, 4, 0]);
+        assert!(Message::parse(&truncated).is_none());
+        // No message type: plain BOOTP.
+        let mut bootp = packet[..HEADER_LEN].to_vec();
+        bootp.push(OPTION_END);
+        assert!(Message::parse(&bootp).is_none());
+        // Wrong magic cookie or hardware type.
+        let mut cookie = packet.clone();
+        cookie[236] = 0;
+        assert!(Message::parse(&cookie).is_none());
+        let mut hardware = packet;
+        hardware[1] = 6;
+        assert!(Message::parse(&hardware).is_none());
+    }
+}
@@ -0,0 +1,279 @@
+//! DHCP client handling for the group interface while we are a P2P client.
+//!
+//! `DhcpClient` either runs the built-in DHCPv4 client or supervises one of the
+//! usual system clients in the foreground, and publishes the IPv4 address the
+//! interface ends up with. Dropping it stops the client; the address goes away
+//! with the group interface.
+
+use std::hash::{BuildHasher, RandomState};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::net::UdpSocket;
+use tokio::process::Command;
+use tokio::sync::{Notify, watch};
+
+use crate::error::P2pError;
+use crate::mac::MacAddress;
+
+use super::dhcp::{
+    self, BOOT_REPLY, BOOT_REQUEST, CLIENT_PORT, DHCP_ACK, DHCP_DISCOVER, DHCP_NAK, DHCP_OFFER,
+    DHCP_REQUEST, FLAG_BROADCAST, Message, SERVER_PORT,
+};
+
+/// How often a supervised client's interface is checked for a new address.
+const ADDRESS_POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// Wait for a server reply before retransmitting, and how many times to send.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(3);
+const ATTEMPTS: u32 = 4;
+/// Pause before starting over after the built-in client failed to get a lease.
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Which DHCP client configures the group interface.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DhcpClientKind {
+    /// The built-in DHCPv4 client; assigns the leased address with `ip address`.
+    #[default]
+    BuiltIn,
+    /// ISC dhclient (`dhclient -d -4 <interface>`).
+    Dhclient,
+    /// dhcpcd (`dhcpcd -B -4 <interface>`).
+    Dhcpcd,
+    /// BusyBox udhcpc (`udhcpc -f -i <interface>`); needs its default script.
+    Udhcpc,
+    /// Any other client, kept in the foreground; `{interface}` in `args` is
+    /// replaced by the group interface.
+    Command { program: String, args: Vec<String> },
+}
+
+impl DhcpClientKind {
+    /// The program and arguments to run on `interface`; `None` for the built-in client.
+    fn command(&self, interface: &str) -> Option<(String, Vec<String>)> {
+        let (program, args): (&str, Vec<&str>) = match self {
+            Self::BuiltIn => return None,
+            Self::Dhclient => ("dhclient", vec!["-d", "-4", "{interface}"]),
+            Self::Dhcpcd => ("dhcpcd", vec!["-B", "-4", "{interface}"]),
+            Self::Udhcpc => ("udhcpc", vec!["-f", "-i", "{interface}"]),
+            Self::Command { program, args } => (program, args.iter().map(String::as_str).collect()),
+        };
+        let args = args
+            .iter()
+            .map(|arg| arg.replace("{interface}", interface))
+            .collect();
+        Some((program.to_string(), args))
+    }
+}
+
+/// A DHCP client running on one group interface.
+pub struct DhcpClient {
+    interface: String,
+    address: watch::Receiver<Option<Ipv4Addr>>,
+    stop: Arc<Notify>,
+}
+
+impl DhcpClient {
+    /// Start `kind` on `interface`. The built-in client needs CAP_NET_ADMIN and
+    /// CAP_NET_BIND_SERVICE; external clients need whatever they usually need.
+    pub fn start(interface: &str, kind: &DhcpClientKind) -> Result<Self, P2pError> {
+        let (address_tx, address) = watch::channel(None);
+        let stop = Arc::new(Notify::new());
+        let task_stop = Arc::clone(&stop);
+        match kind.command(interface) {
+            None => {
+                let socket = dhcp::bind(interface, CLIENT_PORT)
+                    .map_err(|err| client_error(&err.to_string()))?;
+                let mac = super::interface_mac(interface)?;
+                let interface = interface.to_string();
+                tokio::spawn(async move {
+                    tokio::select! {
+                        () = task_stop.notified() => {}
+                        () = run_builtin(&socket, &interface, mac, &address_tx) => {}
+                    }
+                });
+            }
+            Some((program, args)) => {
+                let child = Command::new(&program)
+                    .args(&args)
+                    .kill_on_drop(true)
+                    .spawn()
+                    .map_err(|err| client_error(&format!("{program}: {err}")))?;
+                let interface = interface.to_string();
+                tokio::spawn(async move {
+                    // The child is killed when this task ends and drops it.
+                    let _child = child;
+                    tokio::select! {
+                        () = task_stop.notified() => {}
+                        () = watch_address(&interface, &address_tx) => {}
+                    }
+                });
+            }
+        }
+        Ok(Self {
+            interface: interface.to_string(),
+            address,
+            stop,
+        })
+    }
+
+    pub fn interface(&self) -> &str {
+        &self.interface
+    }
+
+    /// The address currently configured by the client, if any.
+    pub fn address(&self) -> Option<Ipv4Addr> {
+        *self.address.borrow()
+    }
+
+    /// Follow address changes, e.g. to wait for the first lease.
+    pub fn subscribe(&self) -> watch::Receiver<Option<Ipv4Addr>> {
+        self.address.clone()
+    }
+}
+
+impl Drop for DhcpClient {
+    fn drop(&mut self) {
+        self.stop.notify_one();
+    }
+}
+
+fn client_error(message: &str) -> P2pError {
+    P2pError::Backend(format!("dhcp client: {message}"))
+}
+
+async fn watch_address(interface: &str, address_tx: &watch::Sender<Option<Ipv4Addr>>) {
+    loop {
+        let current = interface_address(interface).await;
+        address_tx.send_if_modified(|address| {
+            let changed = *address != current;
+            *address = current;
+            changed
+        });
+        tokio::time::sleep(ADDRESS_POLL_INTERVAL).await;
+    }
+}
+
+async fn interface_address(interface: &str) -> Option<Ipv4Addr> {
+    // One line per address: "3: p2p-wlan0-0    inet 192.168.49.23/24 brd ... scope global ...".
+    let output = Command::new("ip")
+        .args(["-4", "-o", "address", "show", "dev", interface])
+        .output()
+        .await
+        .ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let mut words = text.split_whitespace();
+    words.find(|word| *word == "inet")?;
+    words.next()?.split('/').next()?.parse().ok()
+}
+
+struct Lease {
+    ip: Ipv4Addr,
+    prefix_len: u8,
+    server: Ipv4Addr,
+    duration: Duration,
+}
+
+async fn run_builtin(
+    socket: &UdpSocket,
+    interface: &str,
+    mac: MacAddress,
+    address_tx: &watch::Sender<Option<Ipv4Addr>>,
+) {
+    loop {
+        let Some(mut lease) = acquire(socket, mac).await else {
+            tokio::time::sleep(RETRY_DELAY).await;
+            continue;
+        };
+        loop {
+            if dhcp::assign_address(interface, lease.ip, lease.prefix_len).is_err() {
+                tokio::time::sleep(RETRY_DELAY).await;
+                break;
+            }
+            // Renewals usually keep the address; only report actual changes.
+            address_tx.send_if_modified(|address| address.replace(lease.ip) != Some(lease.ip));
+            // Renew at T1, half-way through the lease.
+            tokio::time::sleep(lease.duration / 2).await;
+            match renew(socket, mac, &lease).await {
+                Some(renewed) => lease = renewed,
+                None => break,
+            }
+        }
+        address_tx.send_if_modified(|address| address.take().is_some());
+    }
+}
+
+async fn acquire(socket: &UdpSocket, mac: MacAddress) -> Option<Lease> {
+    let broadcast = SocketAddrV4::new(Ipv4Addr::BROADCAST, SERVER_PORT).into();
+    let xid = transaction_id();
+    let mut discover = Message::new(BOOT_REQUEST, DHCP_DISCOVER, xid, mac);
+    discover.flags = FLAG_BROADCAST;
+    let offer = exchange(socket, &discover, broadcast).await?;
+    if offer.message_type != DHCP_OFFER {
+        return None;
+    }
+    let mut request = Message::new(BOOT_REQUEST, DHCP_REQUEST, xid, mac);
+    request.flags = FLAG_BROADCAST;
+    request.requested_ip = Some(offer.yiaddr);
+    request.server_id = offer.server_id;
+    let ack = exchange(socket, &request, broadcast).await?;
+    lease_from(&ack)
+}
+
+async fn renew(socket: &UdpSocket, mac: MacAddress, lease: &Lease) -> Option<Lease> {
+    // RENEWING: unicast to the server that granted the lease, address in ciaddr.
+    let server = SocketAddrV4::new(lease.server, SERVER_PORT).into();
+    let mut request = Message::new(BOOT_REQUEST, DHCP_REQUEST, transaction_id(), mac);
+    request.ciaddr = lease.ip;
+    let ack = exchange(socket, &request, server).await?;
+    lease_from(&ack)
+}
+
+fn lease_from(ack: &Message) -> Option<Lease> {
+    if ack.message_type != DHCP_ACK {
+        return None;
+    }
+    Some(Lease {
+        ip: ack.yiaddr,
+        prefix_len: ack
+            .subnet_mask
+            .map_or(24, |mask| u32::from(mask).count_ones() as u8),
+        server: ack.server_id?,
+        duration: Duration::from_secs(u64::from(ack.lease_secs.unwrap_or(3600))),
+    })
+}
+
+async fn exchange(socket: &UdpSocket, request: &Message, to: SocketAddr) -> Option<Message> {
+    let packet = request.encode();
+    let mut buf = [0u8; 1500];
+    for _ in 0..ATTEMPTS {
+        socket.send_to(&packet, to).await.ok()?;
+        let reply = tokio::time::timeout(REPLY_TIMEOUT, async {
+            loop {
+                let (len, _) = socket.recv_from(&mut buf).await.ok()?;
+                let reply = Message::parse(&buf[..len]);
+                // Replies to other clients' transactions share the broadcast address.
+                if let Some(reply) = reply.filter(|reply| {
+                    reply.op == BOOT_REPLY
+                        && reply.xid == request.xid
+                        && reply.chaddr == request.chaddr
+                }) {
+                    return Some(reply);
+                }
+            }
+        })
+        .await;
+        match reply {
+            Ok(Some(reply)) if reply.message_type == DHCP_NAK => return None,
+            Ok(Some(reply)) => return Some(reply),
+            Ok(None) => return None,
+            Err(_) => continue,
+        }
+    }
+    None
+}
+
+fn transaction_id() -> u32 {
+    // Only needs to differ between clients on the same link, not be unpredictable.
+    RandomState::new().hash_one(std::time::SystemTime::now()) as u32
+}
@@ -0,0 +1,380 @@
+//! Minimal DHCPv4 server for the group interface while we are group owner.
+//!
+//! wpa_supplicant brings the group owner's interface up without any addressing,
+//! so clients never get connectivity unless something hands out addresses.
+//! `DhcpServer` puts the server address on the interface and answers DISCOVER,
+//! REQUEST, RELEASE and DECLINE from a single pool; an address a client declined
+//! (another host answered ARP for it) is not handed out again for
+//! `DECLINE_HOLD`. Replies carry no router or
+//! DNS options, so clients keep their default route. There is no relay support,
+//! no static leases and leases are not persisted.
+
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use tokio::net::UdpSocket;
+use tokio::sync::Notify;
+
+use crate::error::P2pError;
+use crate::mac::MacAddress;
+
+use super::dhcp::{
+    self, BOOT_REPLY, BOOT_REQUEST, CLIENT_PORT, DHCP_ACK, DHCP_DECLINE, DHCP_DISCOVER, DHCP_NAK,
+    DHCP_OFFER, DHCP_RELEASE, DHCP_REQUEST, Message, SERVER_PORT,
+};
+
+/// Addressing handed out by `DhcpServer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DhcpServerConfig {
+    /// Our address on the group interface; also the DHCP server identifier.
+    pub server_ip: Ipv4Addr,
+    /// Prefix length of the group subnet.
+    pub prefix_len: u8,
+    /// First address handed out to clients.
+    pub pool_start: Ipv4Addr,
+    /// Last address handed out to clients (inclusive).
+    pub pool_end: Ipv4Addr,
+    pub lease_time: Duration,
+}
+
+impl Default for DhcpServerConfig {
+    /// 192.168.49.0/24 with the group owner on .1, as Android uses.
+    fn default() -> Self {
+        Self {
+            server_ip: Ipv4Addr::new(192, 168, 49, 1),
+            prefix_len: 24,
+            pool_start: Ipv4Addr::new(192, 168, 49, 2),
+            pool_end: Ipv4Addr::new(192, 168, 49, 254),
+            lease_time: Duration::from_secs(3600),
+        }
+    }
+}
+
+impl DhcpServerConfig {
+    /// Serve `network/prefix_len`, with the group owner on the first host address
+    /// and every other host address in the pool.
+    pub fn subnet(network: Ipv4Addr, prefix_len: u8) -> Result<Self, P2pError> {
+        if !(1..=30).contains(&prefix_len) {
+            return Err(P2pError::InvalidConfig(format!(
+                "DHCP subnet prefix /{prefix_len} leaves no room for clients"
+            )));
+        }
+        let mask = u32::MAX << (32 - prefix_len);
+        let network = u32::from(network) & mask;
+        let broadcast = network | !mask;
+        Ok(Self {
+            server_ip: Ipv4Addr::from(network + 1),
+            prefix_len,
+            pool_start: Ipv4Addr::from(network + 2),
+            pool_end: Ipv4Addr::from(broadcast - 1),
+            ..Self::default()
+        })
+    }
+
+    fn mask(&self) -> u32 {
+        u32::MAX << (32 - u32::from(self.prefix_len))
+    }
+
+    fn in_pool(&self, ip: Ipv4Addr) -> bool {
+        (u32::from(self.pool_start)..=u32::from(self.pool_end)).contains(&u32::from(ip))
+    }
+
+    fn validate(&self) -> Result<(), P2pError> {
+        if !(1..=30).contains(&self.prefix_len) {
+            return Err(P2pError::InvalidConfig(format!(
+                "DHCP subnet prefix /{} leaves no room for clients",
+                self.prefix_len
+            )));
+        }
+        let mask = self.mask();
+        let network = u32::from(self.server_ip) & mask;
+        let same_subnet = |ip: Ipv4Addr| u32::from(ip) & mask == network;
+        if !same_subnet(self.pool_start)
+            || !same_subnet(self.pool_end)
+            || self.pool_start > self.pool_end
+            || self.in_pool(self.server_ip)
+        {
+            return Err(P2pError::InvalidConfig(format!(
+                "DHCP pool {}-{} must lie in {}/{} and exclude the server address",
+                self.pool_start, self.pool_end, self.server_ip, self.prefix_len
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// An address currently leased to a group member.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DhcpLease {
+    /// The client's hardware address, i.e. its P2P interface address.
+    pub mac: MacAddress,
+    pub ip: Ipv4Addr,
+}
+
+/// How long an address a client declined stays out of the pool.
+const DECLINE_HOLD: Duration = Duration::from_secs(600);
+
+#[derive(Default)]
+struct Leases {
+    by_mac: HashMap<MacAddress, (Ipv4Addr, Instant)>,
+    // Addresses found in use by someone else, until when to skip them.
+    declined: HashMap<Ipv4Addr, Instant>,
+}
+
+impl Leases {
+    fn is_free(&self, ip: Ipv4Addr, mac: MacAddress, now: Instant) -> bool {
+        self.declined.get(&ip).is_none_or(|until| *until <= now)
+            && !self
+                .by_mac
+                .iter()
+                .any(|(owner, (leased, expires))| *owner != mac && *leased == ip && *expires > now)
+    }
+
+    fn decline(&mut self, ip: Ipv4Addr, now: Instant) {
+        self.declined.retain(|_, until| *until > now);
+        self.declined.insert(ip, now + DECLINE_HOLD);
+    }
+
+    fn offer(
+        &self,
+        mac: MacAddress,
+        requested: Option<Ipv4Addr>,
+        config: &DhcpServerConfig,
+        now: Instant,
+    ) -> Option<Ipv4Addr> {
+        // Prefer the client's previous address, then the one it asks for.
+        if let Some((ip, _)) = self.by_mac.get(&mac) {
+            return Some(*ip);
+        }
+        if let Some(ip) = requested.filter(|ip| config.in_pool(*ip) && self.is_free(*ip, mac, now))
+        {
+            return Some(ip);
+        }
+        (u32::from(config.pool_start)..=u32::from(config.pool_end))
+            .map(Ipv4Addr::from)
+            .find(|ip| self.is_free(*ip, mac, now))
+    }
+}
+
+/// A running DHCP server bound to one group interface; dropping it stops the
+/// server (the interface address is left in place and goes away with the group).
+pub struct DhcpServer {
+    interface: String,
+    leases: Arc<Mutex<Leases>>,
+    stop: Arc<Notify>,
+}
+
+impl DhcpServer {
+    /// Assign `config.server_ip` to `interface` and start serving on it.
+    /// Needs CAP_NET_ADMIN (for the address) and CAP_NET_BIND_SERVICE (port 67).
+    pub fn start(interface: &str, config: DhcpServerConfig) -> Result<Self, P2pError> {
+        config.validate()?;
+        dhcp::assign_address(interface, config.server_ip, config.prefix_len)
+            .map_err(|err| server_error(&err))?;
+        let socket =
+            dhcp::bind(interface, SERVER_PORT).map_err(|err| server_error(&err.to_string()))?;
+        let leases = Arc::new(Mutex::new(Leases::default()));
+        let stop = Arc::new(Notify::new());
+        tokio::spawn(serve(
+            socket,
+            config,
+            Arc::clone(&leases),
+            Arc::clone(&stop),
+        ));
+        Ok(Self {
+            interface: interface.to_string(),
+            leases,
+            stop,
+        })
+    }
+
+    pub fn interface(&self) -> &str {
+        &self.interface
+    }
+
+    /// Leases that have not expired yet.
+    pub fn leases(&self) -> Vec<DhcpLease> {
+        let now = Instant::now();
+        let mut leases: Vec<DhcpLease> = lock(&self.leases)
+            .by_mac
+            .iter()
+            .filter(|(_, (_, expires))| *expires > now)
+            .map(|(mac, (ip, _))| DhcpLease { mac: *mac, ip: *ip })
+            .collect();
+        leases.sort_by_key(|lease| lease.ip);
+        leases
+    }
+}
+
+impl Drop for DhcpServer {
+    fn drop(&mut self) {
+        // notify_one stores a permit, so the task stops even if it is mid-reply.
+        self.stop.notify_one();
+    }
+}
+
+fn lock(leases: &Mutex<Leases>) -> MutexGuard<'_, Leases> {
+    leases
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn server_error(message: &str) -> P2pError {
+    P2pError::Backend(format!("dhcp server: {message}"))
+}
+
+async fn serve(
+    socket: UdpSocket,
+    config: DhcpServerConfig,
+    leases: Arc<Mutex<Leases>>,
+    stop: Arc<Notify>,
+) {
+    let mut buf = [0u8; 1500];
+    loop {
+        let len = tokio::select! {
+            () = stop.notified() => return,
+            received = socket.recv_from(&mut buf) => match received {
+                Ok((len, _)) => len,
+                // The group interface went away underneath us.
+                Err(_) => return,
+            },
+        };
+        let Some(request) = Message::parse(&buf[..len]).filter(|m| m.op == BOOT_REQUEST) else {
+            continue;
+        };
+        let reply = respond(&request, &config, &mut lock(&leases), Instant::now());
+        if let Some((reply, to)) = reply {
+            let _ = socket.send_to(&reply.encode(), to).await;
+        }
+    }
+}
+
+fn respond(
+    request: &Message,
+    config: &DhcpServerConfig,
+    leases: &mut Leases,
+    now: Instant,
+) -> Option<(Message, SocketAddr)> {
+    let mac = request.chaddr;
+    let (message_type, yiaddr) = match request.message_type {
+        DHCP_DISCOVER => {
+            let ip = leases.offer(mac, request.requested_ip, config, now)?;
+            (DHCP_OFFER, ip)
+        }
+        DHCP_REQUEST => {
+            // A server id naming someone else means the client picked another offer.
+            if request.server_id.is_some_and(|id| id != config.server_ip) {
+                return None;
+            }
+            // SELECTING/INIT-REBOOT clients name the address; renewing ones use ciaddr.
+            let requested = request
+                .requested_ip
+                .or((!request.ciaddr.is_unspecified()).then_some(request.ciaddr))?;
+            if config.in_pool(requested) && leases.is_free(requested, mac, now) {
+                leases
+                    .by_mac
+                    .insert(mac, (requested, now + config.lease_time));
+                (DHCP_ACK, requested)
+            } else {
+                leases.by_mac.remove(&mac);
+                (DHCP_NAK, Ipv4Addr::UNSPECIFIED)
+            }
+        }
+        DHCP_RELEASE => {
+            leases.by_mac.remove(&mac);
+            return None;
+        }
+        DHCP_DECLINE => {
+            // The declined address comes in option 50; fall back to the lease.
+            let leased = leases.by_mac.remove(&mac).map(|(ip, _)| ip);
+            if let Some(ip) = request.requested_ip.or(leased) {
+                leases.decline(ip, now);
+            }
+            return None;
+        }
+        _ => return None,
+    };
+    let mut reply = Message::new(BOOT_REPLY, message_type, request.xid, mac);
+    reply.flags = request.flags;
+    reply.ciaddr = request.ciaddr;
+    reply.yiaddr = yiaddr;
+    reply.server_id = Some(config.server_ip);
+    if message_type != DHCP_NAK {
+        reply.lease_secs = Some(u32::try_from(config.lease_time.as_secs()).unwrap_or(u32::MAX));
+        reply.subnet_mask = Some(Ipv4Addr::from(config.mask()));
+    }
+    // Clients without an address yet cannot receive unicast before ARP works.
+    let to = if message_type != DHCP_NAK && !request.ciaddr.is_unspecified() {
+        request.ciaddr
+    } else {
+        Ipv4Addr::BROADCAST
+    };
+    Some((reply, SocketAddrV4::new(to, CLIENT_PORT).into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLIENT: MacAddress = MacAddress::new([0x02, 0, 0, 0, 0, 0x01]);
+    const OTHER: MacAddress = MacAddress::new([0x02, 0, 0, 0, 0, 0x02]);
+
+    fn request(message_type: u8, mac: MacAddress, requested: Option<Ipv4Addr>) -> Message {
+        let mut message = Message::new(BOOT_REQUEST, message_type, 7, mac);
+        message.requested_ip = requested;
+        message
+    }
+
+    fn answer(leases: &mut Leases, message: Message, now: Instant) -> Option<(u8, Ipv4Addr)> {
+        let config = DhcpServerConfig::default();
+        respond(&message, &config, leases, now).map(|(reply, _)| (reply.message_type, reply.yiaddr))
+    }
+
+    #[test]
+    fn declined_address_is_held_back() {
+        let mut leases = Leases::default();
+        let now = Instant::now();
+        let first = Ipv4Addr::new(192, 168, 49, 2);
+        let offer = answer(&mut leases, request(DHCP_DISCOVER, CLIENT, None), now);
+        assert_eq!(offer, Some((DHCP_OFFER, first)));
+        let ack = answer(&mut leases, request(DHCP_REQUEST, CLIENT, Some(first)), now);
+        assert_eq!(ack, Some((DHCP_ACK, first)));
+
+        assert_eq!(
+            answer(&mut leases, request(DHCP_DECLINE, CLIENT, Some(first)), now),
+            None
+        );
+        let offer = answer(
+            &mut leases,
+            request(DHCP_DISCOVER, CLIENT, Some(first)),
+            now,
+        );
+        assert_eq!(offer, Some((DHCP_OFFER, Ipv4Addr::new(192, 168, 49, 3))));
+        let nak = answer(&mut leases, request(DHCP_REQUEST, CLIENT, Some(first)), now);
+        assert_eq!(nak, Some((DHCP_NAK, Ipv4Addr::UNSPECIFIED)));
+
+        let later = now + DECLINE_HOLD;
+        let offer = answer(&mut leases, request(DHCP_DISCOVER, OTHER, None), later);
+        assert_eq!(offer, Some((DHCP_OFFER, first)));
+    }
+
+    #[test]
+    fn released_address_is_free_again() {
+        let mut leases = Leases::default();
+        let now = Instant::now();
+        let first = Ipv4Addr::new(192, 168, 49, 2);
+        answer(&mut leases, request(DHCP_REQUEST, CLIENT, Some(first)), now);
+        let offer = answer(&mut leases, request(DHCP_DISCOVER, OTHER, None), now);
+        assert_eq!(offer, Some((DHCP_OFFER, Ipv4Addr::new(192, 168, 49, 3))));
+
+        assert_eq!(
+            answer(&mut leases, request(DHCP_RELEASE, CLIENT, None), now),
+            None
+        );
+        let offer = answer(&mut leases, request(DHCP_DISCOVER, OTHER, None), now);
+        assert_eq!(offer, Some((DHCP_OFFER, first)));
+    }
+}
@@ -0,0 +1,266 @@
+//! Let DHCP and chosen ports through the firewall on group interfaces.
+//!
+//! Group interfaces are created on the fly (`p2p-wlan0-0`), so no firewall zone
+//! or rule covers them and a default-deny host drops DHCP and every connection
+//! from the peer. `FirewallRules` accepts DHCP (and DHCPv6) plus the configured
+//! ports on one interface and takes it all back on drop; the manager does this
+//! for every group with `WifiP2pManagerBuilder::open_firewall`.
+//!
+//! With firewalld running, the interface is added to a zone and the ports to
+//! that zone, at runtime only; otherwise rules go into a table of their own with
+//! nftables, or are inserted at the top of the INPUT chain with iptables. An
+//! nftables table cannot override a drop in another table, which is why
+//! firewalld is handled through its own interface. Needs `CAP_NET_ADMIN`.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use crate::error::P2pError;
+
+/// DHCP server and client, DHCPv6 client and server.
+const DHCP_PORTS: [u16; 4] = [67, 68, 546, 547];
+
+/// Which firewall gets the rules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FirewallFrontend {
+    /// firewalld when it is running, else nftables when `nft` is installed,
+    /// else iptables.
+    #[default]
+    Auto,
+    Firewalld,
+    Nftables,
+    Iptables,
+}
+
+/// What to let in on group interfaces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FirewallConfig {
+    pub tcp_ports: Vec<u16>,
+    pub udp_ports: Vec<u16>,
+    pub frontend: FirewallFrontend,
+    /// firewalld zone the interface joins (default "internal"). Ports are
+    /// opened for the whole zone, and only those not already open are closed
+    /// again.
+    pub zone: String,
+}
+
+impl FirewallConfig {
+    /// DHCP plus these TCP ports, e.g. `transfer::DEFAULT_PORT`.
+    pub fn tcp(ports: impl IntoIterator<Item = u16>) -> Self {
+        Self {
+            tcp_ports: ports.into_iter().collect(),
+            ..Self::default()
+        }
+    }
+}
+
+impl Default for FirewallConfig {
+    fn default() -> Self {
+        Self {
+            tcp_ports: Vec::new(),
+            udp_ports: Vec::new(),
+            frontend: FirewallFrontend::Auto,
+            zone: "internal".to_string(),
+        }
+    }
+}
+
+/// Rules accepting traffic on one group interface; removed on drop.
+#[derive(Debug)]
+pub struct FirewallRules {
+    interface: String,
+    frontend: FirewallFrontend,
+    // The zone, and the firewall-cmd arguments that undo what we changed there.
+    firewalld_zone: String,
+    firewalld_undo: Vec<String>,
+    // (protocol, port) pairs behind the iptables rules.
+    iptables_ports: Vec<(&'static str, u16)>,
+}
+
+impl FirewallRules {
+    /// Accept DHCP and `config`'s ports on `interface`.
+    pub fn open(interface: &str, config: &FirewallConfig) -> Result<Self, P2pError> {
+        let frontend = match config.frontend {
+            FirewallFrontend::Auto if firewalld_running() => FirewallFrontend::Firewalld,
+            FirewallFrontend::Auto if has_nft() => FirewallFrontend::Nftables,
+            FirewallFrontend::Auto => FirewallFrontend::Iptables,
+            frontend => frontend,
+        };
+        let mut ports: Vec<(&'static str, u16)> =
+            DHCP_PORTS.iter().map(|port| ("udp", *port)).collect();
+        ports.extend(config.tcp_ports.iter().map(|port| ("tcp", *port)));
+        ports.extend(config.udp_ports.iter().map(|port| ("udp", *port)));
+        // From here on, drop undoes whatever was added.
+        let mut rules = Self {
+            interface: interface.to_string(),
+            frontend,
+            firewalld_zone: format!("--zone={}", config.zone),
+            firewalld_undo: Vec::new(),
+            iptables_ports: Vec::new(),
+        };
+        match frontend {
+            FirewallFrontend::Firewalld => rules.open_firewalld(&ports)?,
+            FirewallFrontend::Nftables => rules.add_nft_table(&ports)?,
+            _ => {
+                for (protocol, port) in ports {
+                    run("iptables", &rules.iptables_rule("-I", protocol, port))?;
+                    rules.iptables_ports.push((protocol, port));
+                }
+            }
+        }
+        Ok(rules)
+    }
+
+    pub fn interface(&self) -> &str {
+        &self.interface
+    }
+
+    /// The frontend the rules went to, with `Auto` resolved.
+    pub fn frontend(&self) -> FirewallFrontend {
+        self.frontend
+    }
+
+    fn open_firewalld(&mut self, ports: &[(&str, u16)]) -> Result<(), P2pError> {
+        let zone = self.firewalld_zone.clone();
+        run(
+            "firewall-cmd",
+            &[&zone, &format!("--add-interface={}", self.interface)],
+        )?;
+        self.firewalld_undo
+            .push(format!("--remove-interface={}", self.interface));
+        for (protocol, port) in ports {
+            let port = format!("{port}/{protocol}");
+            // Leave ports the zone had open before alone, now and on drop.
+            if run("firewall-cmd", &[&zone, &format!("--query-port={port}")]).is_ok() {
+                continue;
+            }
+            run("firewall-cmd", &[&zone, &format!("--add-port={port}")])?;
+            self.firewalld_undo.push(format!("--remove-port={port}"));
+        }
+        Ok(())
+    }
+
+    fn table(&self) -> String {
+        // Table names only allow letters, digits and underscores.
+        let name: String = self
+            .interface
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        format!("wifi_p2p_input_{name}")
+    }
+
+    fn add_nft_table(&self, ports: &[(&str, u16)]) -> Result<(), P2pError> {
+        let interface = &self.interface;
+        let rules: String = ports
+            .iter()
+            .map(|(protocol, port)| {
+                format!("    iifname \"{interface}\" {protocol} dport {port} accept\n")
+            })
+            .collect();
+        let script = format!(
+            "table inet {table} {{
+  chain input {{
+    type filter hook input priority filter - 1; policy accept;
+{rules}  }}
+}}
+",
+            table = self.table()
+        );
+        let mut child = Command::new("nft")
+            .args(["-f", "-"])
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|err| firewall_error(&format!("nft: {err}")))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(script.as_bytes())
+                .map_err(|err| firewall_error(&format!("nft: {err}")))?;
+        }
+        let status = child
+            .wait()
+            .map_err(|err| firewall_error(&format!("nft: {err}")))?;
+        if !status.success() {
+            return Err(firewall_error(&format!("nft -f failed: {status}")));
+        }
+        Ok(())
+    }
+
+    fn iptables_rule(&self, action: &str, protocol: &str, port: u16) -> Vec<String> {
+        [
+            action,
+            "INPUT",
+            "-i",
+            &self.interface,
+            "-p",
+            protocol,
+            "--dport",
+            &port.to_string(),
+            "-j",
+            "ACCEPT",
+        ]
+        .map(str::to_string)
+        .to_vec()
+    }
+}
+
+impl Drop for FirewallRules {
+    fn drop(&mut self) {
+        // Best effort: the rules may already be gone (e.g. a firewall reload).
+        match self.frontend {
+            FirewallFrontend::Firewalld => {
+                for undo in self.firewalld_undo.iter().rev() {
+                    let _ = run("firewall-cmd", &[&self.firewalld_zone, undo]);
+                }
+            }
+            FirewallFrontend::Nftables => {
+                let _ = run("nft", &["delete", "table", "inet", &self.table()]);
+            }
+            _ => {
+                for (protocol, port) in &self.iptables_ports {
+                    let _ = run("iptables", &self.iptables_rule("-D", protocol, *port));
+                }
+            }
+        }
+    }
+}
+
+fn firewalld_running() -> bool {
+    Command::new("firewall-cmd")
+        .arg("--state")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+fn has_nft() -> bool {
+    Command::new("nft")
+        .arg("--version")
+        .stdout(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+fn run(program: &str, args: &[impl AsRef<str>]) -> Result<(), P2pError> {
+    let args: Vec<&str> = args.iter().map(AsRef::as_ref).collect();
+    let status = Command::new(program)
+        .args(&args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map_err(|err| firewall_error(&format!("{program}: {err}")))?;
+    if !status.success() {
+        return Err(firewall_error(&format!(
+            "{program} {} failed: {status}",
+            args.join(" ")
+        )));
+    }
+    Ok(())
+}
+
+fn firewall_error(message: &str) -> P2pError {
+    P2pError::Backend(format!("firewall: {message}"))
+}
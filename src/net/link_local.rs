@@ -0,0 +1,146 @@
+//! IPv6 link-local addressing on the group interface, with no DHCP at all.
+//!
+//! Every IPv6 interface has a link-local address, so two devices only need the
+//! group interface up to talk to each other. `configure` brings it up with the
+//! EUI-64 address derived from its MAC, which lets a peer compute our address
+//! from our interface address alone. `NeighborProbe` pings all nodes on the link
+//! so the peers' own link-local addresses land in the neighbour table, where the
+//! Linux backend's `resolve_peer_ip` picks them up when no IPv4 address is known.
+//!
+//! Link-local addresses are only meaningful together with the interface they
+//! belong to; use `scoped` to build a socket address for one.
+
+use std::net::Ipv6Addr;
+#[cfg(target_os = "linux")]
+use std::net::SocketAddrV6;
+#[cfg(target_os = "linux")]
+use std::sync::Arc;
+#[cfg(target_os = "linux")]
+use std::time::Duration;
+
+#[cfg(target_os = "linux")]
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+#[cfg(target_os = "linux")]
+use tokio::sync::Notify;
+
+#[cfg(target_os = "linux")]
+use crate::error::P2pError;
+use crate::mac::MacAddress;
+
+/// How often the all-nodes probe is repeated while the group is up; peers may
+/// bring their side of the group up after us.
+#[cfg(target_os = "linux")]
+const PROBE_INTERVAL: Duration = Duration::from_secs(2);
+#[cfg(target_os = "linux")]
+const ICMPV6_ECHO_REQUEST: u8 = 128;
+
+/// The link-local address with an EUI-64 interface identifier derived from `mac`
+/// (RFC 4291 appendix A): `fe80::` followed by the MAC with `ff:fe` in the middle
+/// and the universal/local bit flipped.
+pub fn eui64_address(mac: MacAddress) -> Ipv6Addr {
+    let [a, b, c, d, e, f] = *mac.as_bytes();
+    let segment = |hi: u8, lo: u8| u16::from_be_bytes([hi, lo]);
+    Ipv6Addr::new(
+        0xfe80,
+        0,
+        0,
+        0,
+        segment(a ^ 0x02, b),
+        segment(c, 0xff),
+        segment(0xfe, d),
+        segment(e, f),
+    )
+}
+
+/// `ip` and `port` scoped to `interface`, ready for `TcpStream::connect` and friends.
+#[cfg(target_os = "linux")]
+pub fn scoped(interface: &str, ip: Ipv6Addr, port: u16) -> Result<SocketAddrV6, P2pError> {
+    Ok(SocketAddrV6::new(ip, port, 0, interface_index(interface)?))
+}
+
+#[cfg(target_os = "linux")]
+fn interface_index(interface: &str) -> Result<u32, P2pError> {
+    let path = format!("/sys/class/net/{interface}/ifindex");
+    std::fs::read_to_string(&path)
+        .map_err(|err| P2pError::Backend(format!("{path}: {err}")))?
+        .trim()
+        .parse()
+        .map_err(|_| P2pError::Backend(format!("{path}: not an interface index")))
+}
+
+#[cfg(target_os = "linux")]
+fn link_local_error(message: &str) -> P2pError {
+    P2pError::Backend(format!("ipv6 link-local: {message}"))
+}
+
+/// Enable IPv6 on `interface`, bring it up and add its EUI-64 link-local address.
+/// Needs CAP_NET_ADMIN.
+#[cfg(target_os = "linux")]
+pub(crate) fn configure(interface: &str) -> Result<(), P2pError> {
+    // Distributions and NetworkManager often disable IPv6 on unmanaged interfaces.
+    let sysctl = format!("/proc/sys/net/ipv6/conf/{interface}/disable_ipv6");
+    std::fs::write(&sysctl, "0").map_err(|err| link_local_error(&format!("{sysctl}: {err}")))?;
+    super::run_ip(&["link", "set", "dev", interface, "up"])
+        .map_err(|err| link_local_error(&err))?;
+    // The kernel may already have added the same address, or a stable-privacy one
+    // alongside it; "replace" keeps this idempotent either way.
+    let address = format!("{}/64", eui64_address(super::interface_mac(interface)?));
+    super::run_ip(&["-6", "address", "replace", &address, "dev", interface])
+        .map_err(|err| link_local_error(&err))
+}
+
+/// Periodically pings ff02::1 on one group interface until dropped. Peers answer
+/// from their link-local address, which leaves them in the neighbour table.
+#[cfg(target_os = "linux")]
+pub(crate) struct NeighborProbe {
+    stop: Arc<Notify>,
+}
+
+#[cfg(target_os = "linux")]
+impl NeighborProbe {
+    /// Needs CAP_NET_RAW for the ICMPv6 socket.
+    pub(crate) fn start(interface: &str) -> Result<Self, P2pError> {
+        let socket = Socket::new(Domain::IPV6, Type::RAW, Some(Protocol::ICMPV6))
+            .and_then(|socket| {
+                socket.bind_device(Some(interface.as_bytes()))?;
+                socket.set_nonblocking(true)?;
+                Ok(socket)
+            })
+            .map_err(|err| link_local_error(&err.to_string()))?;
+        let all_nodes = SocketAddrV6::new(
+            Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1),
+            0,
+            0,
+            interface_index(interface)?,
+        );
+        let stop = Arc::new(Notify::new());
+        let task_stop = Arc::clone(&stop);
+        tokio::spawn(async move {
+            let all_nodes = SockAddr::from(all_nodes);
+            let mut sequence: u16 = 0;
+            loop {
+                // Type, code, checksum (filled in by the kernel), identifier, sequence.
+                let [seq_hi, seq_lo] = sequence.to_be_bytes();
+                let echo = [ICMPV6_ECHO_REQUEST, 0, 0, 0, 0x50, 0x32, seq_hi, seq_lo];
+                let _ = socket.send_to(&echo, &all_nodes);
+                sequence = sequence.wrapping_add(1);
+                // The socket also receives every ICMPv6 message on the link; the
+                // replies are not needed, so keep its buffer from filling up.
+                let mut buf = [std::mem::MaybeUninit::uninit(); 1500];
+                while socket.recv(&mut buf).is_ok() {}
+                tokio::select! {
+                    () = task_stop.notified() => return,
+                    () = tokio::time::sleep(PROBE_INTERVAL) => {}
+                }
+            }
+        });
+        Ok(Self { stop })
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for NeighborProbe {
+    fn drop(&mut self) {
+        self.stop.notify_one();
+    }
+}
@@ -0,0 +1,247 @@
+//! IP addressing on group interfaces, which wpa_supplicant leaves unconfigured.
+//!
+//! The manager drives these from `GroupStarted`/`GroupFinished`; each subsystem
+//! is behind its own feature, except applying the P2P IP allocation negotiated by
+//! the supplicant itself.
+
+#[cfg(all(
+    any(
+        feature = "dhcp-server",
+        feature = "dhcp-client",
+        feature = "ipv6-link-local",
+        feature = "sharing",
+        feature = "firewall"
+    ),
+    target_os = "linux"
+))]
+use std::collections::HashMap;
+
+use tokio::sync::broadcast;
+
+use crate::builder::ManagerOptions;
+use crate::channel::P2pEvent;
+use crate::error::P2pError;
+#[cfg(all(
+    any(feature = "dhcp-server", feature = "dhcp-client", feature = "sharing"),
+    target_os = "linux"
+))]
+use crate::group::GroupRole;
+use crate::group::P2pGroup;
+#[cfg(all(
+    any(feature = "dhcp-client", feature = "ipv6-link-local"),
+    target_os = "linux"
+))]
+use crate::mac::MacAddress;
+
+#[cfg(all(feature = "connman", target_os = "linux"))]
+pub mod connman;
+#[cfg(all(
+    any(feature = "dhcp-server", feature = "dhcp-client"),
+    target_os = "linux"
+))]
+mod dhcp;
+#[cfg(all(feature = "dhcp-client", target_os = "linux"))]
+pub mod dhcp_client;
+#[cfg(all(feature = "dhcp-server", target_os = "linux"))]
+pub mod dhcp_server;
+#[cfg(all(feature = "firewall", target_os = "linux"))]
+pub mod firewall;
+#[cfg(feature = "ipv6-link-local")]
+pub mod link_local;
+#[cfg(all(
+    any(feature = "rtnetlink", feature = "stats", feature = "interfaces"),
+    target_os = "linux"
+))]
+pub(crate) mod netlink;
+#[cfg(all(feature = "network-manager", target_os = "linux"))]
+pub mod network_manager;
+#[cfg(feature = "qos")]
+pub mod qos;
+#[cfg(all(feature = "rtnetlink", target_os = "linux"))]
+pub(crate) mod rtnetlink;
+#[cfg(all(feature = "sharing", target_os = "linux"))]
+pub mod sharing;
+#[cfg(feature = "tokio")]
+pub mod socket;
+
+#[cfg(all(feature = "dhcp-client", target_os = "linux"))]
+use dhcp_client::{DhcpClient, DhcpClientKind};
+#[cfg(all(feature = "dhcp-server", target_os = "linux"))]
+use dhcp_server::{DhcpServer, DhcpServerConfig};
+#[cfg(all(feature = "firewall", target_os = "linux"))]
+use firewall::{FirewallConfig, FirewallRules};
+#[cfg(all(feature = "ipv6-link-local", target_os = "linux"))]
+use link_local::NeighborProbe;
+#[cfg(all(feature = "sharing", target_os = "linux"))]
+use sharing::{InternetSharing, SharingConfig};
+
+/// Per-group addressing the manager set up, torn down with the group.
+pub(crate) struct GroupNetwork {
+    #[cfg(all(feature = "dhcp-server", target_os = "linux"))]
+    dhcp_server: Option<DhcpServerConfig>,
+    #[cfg(all(feature = "dhcp-server", target_os = "linux"))]
+    servers: HashMap<String, DhcpServer>,
+    #[cfg(all(feature = "dhcp-client", target_os = "linux"))]
+    dhcp_client: Option<DhcpClientKind>,
+    #[cfg(all(feature = "dhcp-client", target_os = "linux"))]
+    clients: HashMap<String, DhcpClient>,
+    #[cfg(all(feature = "ipv6-link-local", target_os = "linux"))]
+    ipv6_link_local: bool,
+    #[cfg(all(feature = "ipv6-link-local", target_os = "linux"))]
+    probes: HashMap<String, NeighborProbe>,
+    #[cfg(target_os = "linux")]
+    apply_ip_allocation: bool,
+    #[cfg(all(feature = "sharing", target_os = "linux"))]
+    sharing: Option<SharingConfig>,
+    #[cfg(all(feature = "sharing", target_os = "linux"))]
+    shared: HashMap<String, InternetSharing>,
+    #[cfg(all(feature = "firewall", target_os = "linux"))]
+    firewall: Option<FirewallConfig>,
+    #[cfg(all(feature = "firewall", target_os = "linux"))]
+    opened: HashMap<String, FirewallRules>,
+}
+
+impl GroupNetwork {
+    #[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
+    pub(crate) fn new(options: &ManagerOptions) -> Self {
+        Self {
+            #[cfg(all(feature = "dhcp-server", target_os = "linux"))]
+            dhcp_server: options.dhcp_server,
+            #[cfg(all(feature = "dhcp-server", target_os = "linux"))]
+            servers: HashMap::new(),
+            #[cfg(all(feature = "dhcp-client", target_os = "linux"))]
+            dhcp_client: options.dhcp_client.clone(),
+            #[cfg(all(feature = "dhcp-client", target_os = "linux"))]
+            clients: HashMap::new(),
+            #[cfg(all(feature = "ipv6-link-local", target_os = "linux"))]
+            ipv6_link_local: options.ipv6_link_local,
+            #[cfg(all(feature = "ipv6-link-local", target_os = "linux"))]
+            probes: HashMap::new(),
+            #[cfg(target_os = "linux")]
+            apply_ip_allocation: options.apply_ip_allocation,
+            #[cfg(all(feature = "sharing", target_os = "linux"))]
+            sharing: options.sharing.clone(),
+            #[cfg(all(feature = "sharing", target_os = "linux"))]
+            shared: HashMap::new(),
+            #[cfg(all(feature = "firewall", target_os = "linux"))]
+            firewall: options.firewall.clone(),
+            #[cfg(all(feature = "firewall", target_os = "linux"))]
+            opened: HashMap::new(),
+        }
+    }
+
+    /// Bring up whatever addressing is configured for our role in `group`.
+    #[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
+    pub(crate) fn group_started(
+        &mut self,
+        group: &P2pGroup,
+        event_tx: &broadcast::Sender<P2pEvent>,
+    ) -> Result<(), P2pError> {
+        // First, so nothing below (DHCP in particular) is dropped on arrival.
+        #[cfg(all(feature = "firewall", target_os = "linux"))]
+        if let Some(config) = &self.firewall {
+            let rules = FirewallRules::open(&group.interface, config)?;
+            self.opened.insert(group.interface.clone(), rules);
+        }
+        #[cfg(target_os = "linux")]
+        if let (true, Some(allocation)) = (self.apply_ip_allocation, group.ip_allocation) {
+            let address = format!("{}/{}", allocation.ip, allocation.prefix_len());
+            run_ip(&[
+                "-4",
+                "address",
+                "replace",
+                &address,
+                "dev",
+                &group.interface,
+            ])
+            .map_err(|err| P2pError::Backend(format!("ip allocation: {err}")))?;
+            let _ = event_tx.send(P2pEvent::IpConfigured(allocation.ip.into()));
+        }
+        #[cfg(all(feature = "ipv6-link-local", target_os = "linux"))]
+        if self.ipv6_link_local {
+            link_local::configure(&group.interface)?;
+            let probe = NeighborProbe::start(&group.interface)?;
+            self.probes.insert(group.interface.clone(), probe);
+        }
+        #[cfg(all(feature = "dhcp-server", target_os = "linux"))]
+        if let (GroupRole::Owner, Some(config)) = (group.role, self.dhcp_server) {
+            let server = DhcpServer::start(&group.interface, config)?;
+            self.servers.insert(group.interface.clone(), server);
+        }
+        // After the addressing above, which the shared subnet is read from.
+        #[cfg(all(feature = "sharing", target_os = "linux"))]
+        if let (GroupRole::Owner, Some(config)) = (group.role, &self.sharing) {
+            let sharing = InternetSharing::start(&group.interface, config)?;
+            self.shared.insert(group.interface.clone(), sharing);
+        }
+        #[cfg(all(feature = "dhcp-client", target_os = "linux"))]
+        if let (GroupRole::Client, Some(kind)) = (group.role, &self.dhcp_client) {
+            let client = DhcpClient::start(&group.interface, kind)?;
+            let mut address = client.subscribe();
+            let event_tx = event_tx.clone();
+            // Ends once the client is dropped with the group.
+            tokio::spawn(async move {
+                while address.changed().await.is_ok() {
+                    let ip = *address.borrow_and_update();
+                    if let Some(ip) = ip {
+                        let _ = event_tx.send(P2pEvent::IpConfigured(ip.into()));
+                    }
+                }
+            });
+            self.clients.insert(group.interface.clone(), client);
+        }
+        Ok(())
+    }
+
+    #[cfg_attr(
+        not(all(
+            any(
+                feature = "dhcp-server",
+                feature = "dhcp-client",
+                feature = "ipv6-link-local",
+                feature = "sharing",
+                feature = "firewall"
+            ),
+            target_os = "linux"
+        )),
+        allow(unused_variables)
+    )]
+    pub(crate) fn group_finished(&mut self, group: &P2pGroup) {
+        #[cfg(all(feature = "dhcp-server", target_os = "linux"))]
+        self.servers.remove(&group.interface);
+        #[cfg(all(feature = "dhcp-client", target_os = "linux"))]
+        self.clients.remove(&group.interface);
+        #[cfg(all(feature = "ipv6-link-local", target_os = "linux"))]
+        self.probes.remove(&group.interface);
+        #[cfg(all(feature = "sharing", target_os = "linux"))]
+        self.shared.remove(&group.interface);
+        // Last, once nothing is listening on the interface any more.
+        #[cfg(all(feature = "firewall", target_os = "linux"))]
+        self.opened.remove(&group.interface);
+    }
+}
+
+/// The hardware address of `interface`, i.e. our P2P interface address on it.
+#[cfg(all(
+    any(feature = "dhcp-client", feature = "ipv6-link-local"),
+    target_os = "linux"
+))]
+fn interface_mac(interface: &str) -> Result<MacAddress, P2pError> {
+    let path = format!("/sys/class/net/{interface}/address");
+    let address = std::fs::read_to_string(&path)
+        .map_err(|err| P2pError::Backend(format!("{path}: {err}")))?;
+    address.trim().parse()
+}
+
+/// Run `ip` with `args`, turning a failure into a readable message.
+#[cfg(target_os = "linux")]
+fn run_ip(args: &[&str]) -> Result<(), String> {
+    let status = std::process::Command::new("ip")
+        .args(args)
+        .status()
+        .map_err(|err| format!("ip: {err}"))?;
+    if !status.success() {
+        return Err(format!("ip {} failed: {status}", args.join(" ")));
+    }
+    Ok(())
+}
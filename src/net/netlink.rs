@@ -0,0 +1,198 @@
+//! Raw netlink plumbing shared by `net::rtnetlink`, `stats::nl80211` and
+//! `interfaces`, plus generic netlink requests for the nl80211 family.
+
+use std::io;
+use std::mem::size_of;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+
+pub(crate) const NLMSGHDR_LEN: usize = 16;
+#[cfg(any(feature = "stats", feature = "interfaces"))]
+const GENLMSGHDR_LEN: usize = 4;
+// Nested and byte-order flags live in the top bits of an attribute's type.
+const NLA_TYPE_MASK: u16 = 0x3fff;
+
+/// A netlink socket for `protocol`, bound to the multicast `groups`. `flags` is
+/// or-ed into the socket type (e.g. `SOCK_NONBLOCK`).
+pub(crate) fn open(protocol: i32, groups: u32, flags: i32) -> io::Result<OwnedFd> {
+    // SAFETY: plain socket(2)/bind(2) calls; the descriptor is owned right away and
+    // the sockaddr is a zeroed sockaddr_nl with only family and groups set.
+    unsafe {
+        let fd = libc::socket(
+            libc::AF_NETLINK,
+            libc::SOCK_RAW | libc::SOCK_CLOEXEC | flags,
+            protocol,
+        );
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = OwnedFd::from_raw_fd(fd);
+        let mut address: libc::sockaddr_nl = std::mem::zeroed();
+        address.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        address.nl_groups = groups;
+        let bound = libc::bind(
+            fd.as_raw_fd(),
+            (&raw const address).cast(),
+            size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+        );
+        if bound < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(fd)
+    }
+}
+
+pub(crate) fn receive(fd: RawFd, buf: &mut [u8]) -> io::Result<usize> {
+    // SAFETY: recv(2) writes at most buf.len() bytes into buf.
+    let len = unsafe { libc::recv(fd, buf.as_mut_ptr().cast(), buf.len(), 0) };
+    if len < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(len as usize)
+}
+
+#[cfg_attr(not(any(feature = "stats", feature = "interfaces")), allow(dead_code))]
+pub(crate) fn send(fd: RawFd, message: &[u8]) -> io::Result<()> {
+    // SAFETY: send(2) only reads message.len() bytes from message.
+    let len = unsafe { libc::send(fd, message.as_ptr().cast(), message.len(), 0) };
+    if len < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// The (type, body) pairs of the messages in one datagram.
+pub(crate) fn messages(mut data: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    std::iter::from_fn(move || {
+        if data.len() < NLMSGHDR_LEN {
+            return None;
+        }
+        let len = u32_at(data, 0) as usize;
+        if len < NLMSGHDR_LEN || len > data.len() {
+            return None;
+        }
+        let message = (u16_at(data, 4), &data[NLMSGHDR_LEN..len]);
+        data = &data[align(len).min(data.len())..];
+        Some(message)
+    })
+}
+
+/// The (type, payload) pairs of a run of struct rtattr/nlattr.
+pub(crate) fn attributes(mut data: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    std::iter::from_fn(move || {
+        if data.len() < 4 {
+            return None;
+        }
+        let len = usize::from(u16_at(data, 0));
+        if len < 4 || len > data.len() {
+            return None;
+        }
+        let attribute = (u16_at(data, 2) & NLA_TYPE_MASK, &data[4..len]);
+        data = &data[align(len).min(data.len())..];
+        Some(attribute)
+    })
+}
+
+/// Append an attribute to a message under construction, padded to 4 bytes.
+#[cfg_attr(not(any(feature = "stats", feature = "interfaces")), allow(dead_code))]
+pub(crate) fn push_attribute(message: &mut Vec<u8>, kind: u16, value: &[u8]) {
+    message.extend_from_slice(&((4 + value.len()) as u16).to_ne_bytes());
+    message.extend_from_slice(&kind.to_ne_bytes());
+    message.extend_from_slice(value);
+    message.resize(align(message.len()), 0);
+}
+
+#[cfg(any(feature = "stats", feature = "interfaces"))]
+pub(crate) fn family_id(socket: &OwnedFd, name: &str) -> io::Result<u16> {
+    let mut family_name = name.as_bytes().to_vec();
+    family_name.push(0);
+    let request = generic_message(
+        libc::GENL_ID_CTRL as u16,
+        libc::NLM_F_ACK,
+        libc::CTRL_CMD_GETFAMILY as u8,
+        &[(libc::CTRL_ATTR_FAMILY_NAME as u16, &family_name)],
+    );
+    let mut family = None;
+    let missing = || io::Error::new(io::ErrorKind::NotFound, format!("no {name} family"));
+    exchange(socket, &request, |payload| {
+        family = attributes(payload)
+            .find(|(kind, value)| *kind == libc::CTRL_ATTR_FAMILY_ID as u16 && value.len() == 2)
+            .map(|(_, value)| u16_at(value, 0));
+    })
+    .map_err(|err| match err.kind() {
+        io::ErrorKind::NotFound => missing(),
+        _ => err,
+    })?;
+    family.ok_or_else(missing)
+}
+
+/// A generic netlink request: header, genlmsghdr and attributes.
+#[cfg(any(feature = "stats", feature = "interfaces"))]
+pub(crate) fn generic_message(
+    family: u16,
+    flags: i32,
+    command: u8,
+    attributes: &[(u16, &[u8])],
+) -> Vec<u8> {
+    let mut message = vec![0u8; NLMSGHDR_LEN];
+    message.extend_from_slice(&[command, 1, 0, 0]);
+    for (kind, value) in attributes {
+        push_attribute(&mut message, *kind, value);
+    }
+    let len = message.len() as u32;
+    let flags = (libc::NLM_F_REQUEST | flags) as u16;
+    message[0..4].copy_from_slice(&len.to_ne_bytes());
+    message[4..6].copy_from_slice(&family.to_ne_bytes());
+    message[6..8].copy_from_slice(&flags.to_ne_bytes());
+    message[8..12].copy_from_slice(&1u32.to_ne_bytes());
+    message
+}
+
+/// Send `request` and hand each reply's attributes to `reply`, until the kernel
+/// acknowledges the request or finishes the dump.
+#[cfg(any(feature = "stats", feature = "interfaces"))]
+pub(crate) fn exchange(
+    socket: &OwnedFd,
+    request: &[u8],
+    mut reply: impl FnMut(&[u8]),
+) -> io::Result<()> {
+    send(socket.as_raw_fd(), request)?;
+    let mut buf = vec![0u8; 32 * 1024];
+    loop {
+        let len = receive(socket.as_raw_fd(), &mut buf)?;
+        if len == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        for (kind, body) in messages(&buf[..len]) {
+            match i32::from(kind) {
+                libc::NLMSG_DONE => return Ok(()),
+                // An error code of 0 is the acknowledgement.
+                libc::NLMSG_ERROR if body.len() >= 4 => {
+                    return match u32_at(body, 0) as i32 {
+                        0 => Ok(()),
+                        code => Err(io::Error::from_raw_os_error(-code)),
+                    };
+                }
+                _ if body.len() >= GENLMSGHDR_LEN => reply(&body[GENLMSGHDR_LEN..]),
+                _ => {}
+            }
+        }
+    }
+}
+
+pub(crate) fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+// Netlink uses host byte order.
+pub(crate) fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_ne_bytes([data[offset], data[offset + 1]])
+}
+
+pub(crate) fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_ne_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
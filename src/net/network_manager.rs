@@ -0,0 +1,139 @@
+//! Keep NetworkManager away from the interfaces Wi-Fi Direct uses.
+//!
+//! NetworkManager scans, reconnects and resets interfaces it manages, which
+//! cancels discovery and tears down group formation half-way. `NetworkManagerGuard`
+//! marks interfaces unmanaged through NetworkManager's D-Bus API and hands them
+//! back with `restore`. The `Managed` property is runtime state, so a crash
+//! leaves the interface unmanaged only until NetworkManager restarts. The
+//! manager does this for its interface and every group interface with
+//! `WifiP2pManagerBuilder::unmanage_in_network_manager`. Needs the polkit
+//! permission `org.freedesktop.NetworkManager.network-control` (root has it).
+
+use std::sync::Arc;
+
+use tokio::sync::{Mutex, OnceCell};
+use zbus::fdo::DBusProxy;
+use zbus::names::BusName;
+use zbus::zvariant::OwnedObjectPath;
+use zbus::{Connection, Proxy};
+
+use crate::error::P2pError;
+
+const NM_SERVICE: &str = "org.freedesktop.NetworkManager";
+const NM_PATH: &str = "/org/freedesktop/NetworkManager";
+const NM_DEVICE_INTERFACE: &str = "org.freedesktop.NetworkManager.Device";
+
+/// Interfaces marked unmanaged by this guard, to be handed back. Clones share
+/// the same set.
+#[derive(Debug, Clone, Default)]
+pub struct NetworkManagerGuard {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    connection: OnceCell<Connection>,
+    // Held across `restore`, so a concurrent call waits for it to finish.
+    unmanaged: Mutex<Vec<(String, OwnedObjectPath)>>,
+}
+
+impl NetworkManagerGuard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mark `interface` unmanaged until `restore`. Returns `Ok(false)` when
+    /// there is nothing to do: NetworkManager is not running, does not know the
+    /// interface or already leaves it alone.
+    pub async fn unmanage(&self, interface: &str) -> Result<bool, P2pError> {
+        let connection = self.connection().await?;
+        let dbus = DBusProxy::new(connection).await?;
+        let name = BusName::try_from(NM_SERVICE).map_err(zbus::Error::from)?;
+        if !dbus.name_has_owner(name).await? {
+            return Ok(false);
+        }
+        let manager = Proxy::new(connection, NM_SERVICE, NM_PATH, NM_SERVICE).await?;
+        let device: OwnedObjectPath = match manager.call("GetDeviceByIpIface", &(interface,)).await
+        {
+            Ok(device) => device,
+            Err(zbus::Error::MethodError(name, _, _))
+                if name.as_str() == "org.freedesktop.NetworkManager.UnknownDevice" =>
+            {
+                return Ok(false);
+            }
+            Err(err) => return Err(network_manager_error(err)),
+        };
+        let mut unmanaged = self.inner.unmanaged.lock().await;
+        if unmanaged.iter().any(|(known, _)| known == interface) {
+            return Ok(true);
+        }
+        if !managed(connection, &device).await? {
+            return Ok(false);
+        }
+        set_managed(connection, &device, false).await?;
+        unmanaged.push((interface.to_string(), device));
+        Ok(true)
+    }
+
+    /// Stop tracking `interface` without touching it, e.g. once a group
+    /// interface is gone.
+    pub async fn forget(&self, interface: &str) {
+        let mut unmanaged = self.inner.unmanaged.lock().await;
+        unmanaged.retain(|(known, _)| known != interface);
+    }
+
+    /// Hand every interface back to NetworkManager. Calling it again is a no-op.
+    pub async fn restore(&self) -> Result<(), P2pError> {
+        let mut unmanaged = self.inner.unmanaged.lock().await;
+        let Some(connection) = self.inner.connection.get() else {
+            return Ok(());
+        };
+        let mut result = Ok(());
+        for (_, device) in unmanaged.drain(..) {
+            // Keep going, so one failure does not leave the others unmanaged.
+            if let Err(err) = set_managed(connection, &device, true).await {
+                result = Err(err);
+            }
+        }
+        result
+    }
+
+    async fn connection(&self) -> Result<&Connection, P2pError> {
+        self.inner
+            .connection
+            .get_or_try_init(Connection::system)
+            .await
+            .map_err(network_manager_error)
+    }
+}
+
+async fn managed(connection: &Connection, device: &OwnedObjectPath) -> Result<bool, P2pError> {
+    let proxy = Proxy::new(connection, NM_SERVICE, device, NM_DEVICE_INTERFACE).await?;
+    proxy
+        .get_property("Managed")
+        .await
+        .map_err(network_manager_error)
+}
+
+async fn set_managed(
+    connection: &Connection,
+    device: &OwnedObjectPath,
+    managed: bool,
+) -> Result<(), P2pError> {
+    let proxy = Proxy::new(connection, NM_SERVICE, device, NM_DEVICE_INTERFACE).await?;
+    proxy
+        .set_property("Managed", managed)
+        .await
+        .map_err(|err| network_manager_error(err.into()))
+}
+
+fn network_manager_error(err: zbus::Error) -> P2pError {
+    match P2pError::from(err) {
+        P2pError::DBus(err) => P2pError::Backend(format!("NetworkManager: {err}")),
+        // Not running means nothing to coordinate with, not a missing supplicant.
+        P2pError::SupplicantUnavailable(message) => {
+            P2pError::Backend(format!("NetworkManager: {message}"))
+        }
+        other => other,
+    }
+}
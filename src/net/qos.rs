@@ -0,0 +1,102 @@
+//! WMM access categories for traffic on the group link.
+//!
+//! Every P2P group runs WMM (it is mandatory for HT and later), so the radio
+//! already queues frames in four access categories; what decides the category
+//! of a frame is the DSCP of the packet. Tagging a socket with
+//! `set_access_category` lets latency-sensitive traffic such as game state jump
+//! ahead of bulk data, which is why the `transfer` sockets are tagged
+//! `Background` when this feature is on.
+//!
+//! DSCP values follow RFC 8325, which Linux (mac80211) uses to pick the user
+//! priority of a frame. Windows ignores `IP_TOS` unless a QoS policy allows it,
+//! and other stacks may not map DSCP at all; the tag still reaches the peer.
+
+use socket2::SockRef;
+
+use crate::error::P2pError;
+
+/// A WMM access category, from lowest to highest priority.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AccessCategory {
+    /// Bulk data that should yield to everything else (AC_BK).
+    Background,
+    /// Untagged traffic (AC_BE).
+    #[default]
+    BestEffort,
+    /// Streaming media (AC_VI).
+    Video,
+    /// Small, latency-critical packets such as voice or game state (AC_VO).
+    Voice,
+}
+
+impl AccessCategory {
+    /// The DSCP to tag packets with: CS1, default, AF41 and EF.
+    pub fn dscp(self) -> u8 {
+        match self {
+            Self::Background => 8,
+            Self::BestEffort => 0,
+            Self::Video => 34,
+            Self::Voice => 46,
+        }
+    }
+
+    /// The 802.1D user priority a DSCP-mapping stack gives the frames.
+    pub fn user_priority(self) -> u8 {
+        match self {
+            Self::Background => 1,
+            Self::BestEffort => 0,
+            Self::Video => 4,
+            Self::Voice => 6,
+        }
+    }
+}
+
+/// Tag packets sent on `socket` for `category`; see `set_dscp`.
+pub fn set_access_category<S>(socket: &S, category: AccessCategory) -> Result<(), P2pError>
+where
+    for<'s> SockRef<'s>: From<&'s S>,
+{
+    set_dscp(socket, category.dscp())
+}
+
+/// Tag packets sent on `socket` with `dscp` (0-63), for any TCP or UDP socket,
+/// e.g. one from `WifiP2pChannel::open_connection`. On a listener, Linux passes
+/// the tag on to accepted connections.
+pub fn set_dscp<S>(socket: &S, dscp: u8) -> Result<(), P2pError>
+where
+    for<'s> SockRef<'s>: From<&'s S>,
+{
+    if dscp > 63 {
+        return Err(P2pError::InvalidArgs(format!(
+            "DSCP {dscp} is out of range"
+        )));
+    }
+    let socket = SockRef::from(socket);
+    // DSCP is the upper six bits of the TOS/traffic class byte.
+    let tos = u32::from(dscp) << 2;
+    let local = socket.local_addr().map_err(qos_error)?;
+    if local.is_ipv4() {
+        return socket.set_tos(tos).map_err(qos_error);
+    }
+    set_traffic_class(&socket, tos)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+fn set_traffic_class(socket: &SockRef<'_>, tos: u32) -> Result<(), P2pError> {
+    socket.set_tclass_v6(tos).map_err(qos_error)?;
+    // Dual-stack sockets talking to an IPv4 peer use the IPv4 option instead.
+    let _ = socket.set_tos(tos);
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+fn set_traffic_class(_socket: &SockRef<'_>, _tos: u32) -> Result<(), P2pError> {
+    Err(P2pError::Unsupported(
+        "DSCP on IPv6 sockets on this platform".to_string(),
+    ))
+}
+
+fn qos_error(err: std::io::Error) -> P2pError {
+    P2pError::Backend(format!("qos: {err}"))
+}
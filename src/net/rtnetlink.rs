@@ -0,0 +1,194 @@
+//! Kernel view of the group interfaces over rtnetlink.
+//!
+//! `GroupStarted` only says the supplicant formed the group; whether sockets can
+//! be opened depends on the interface being up with a carrier and an address.
+//! The monitor subscribes to link and address notifications and reports those
+//! transitions for P2P group interfaces as `GroupInterfaceUp`/`GroupInterfaceDown`
+//! and `GroupAddressAdded`/`GroupAddressRemoved`. With the `hotplug` feature,
+//! the backend also waits here for its own interface to appear or go away.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::os::fd::AsRawFd;
+
+use tokio::io::Interest;
+use tokio::io::unix::AsyncFd;
+use tokio::sync::{broadcast, watch};
+
+use super::netlink::{self, attributes, u32_at};
+use crate::channel::P2pEvent;
+use crate::group::P2pGroup;
+
+// Interfaces wpa_supplicant creates for groups are named "p2p-<parent>-<n>"; they
+// usually come up before the GroupStarted signal arrives.
+const GROUP_INTERFACE_PREFIX: &str = "p2p-";
+
+const IFLA_IFNAME: u16 = 3;
+const IFA_ADDRESS: u16 = 1;
+const IFA_LOCAL: u16 = 2;
+// struct ifinfomsg and struct ifaddrmsg, before their attributes.
+const IFINFOMSG_LEN: usize = 16;
+const IFADDRMSG_LEN: usize = 8;
+
+/// Forward link and address changes of group interfaces until the socket fails.
+pub(crate) async fn monitor(
+    event_tx: broadcast::Sender<P2pEvent>,
+    groups_rx: watch::Receiver<Vec<P2pGroup>>,
+) -> io::Result<()> {
+    let groups = libc::RTMGRP_LINK | libc::RTMGRP_IPV4_IFADDR | libc::RTMGRP_IPV6_IFADDR;
+    let fd = netlink::open(libc::NETLINK_ROUTE, groups as u32, libc::SOCK_NONBLOCK)?;
+    let socket = AsyncFd::with_interest(fd, Interest::READABLE)?;
+    let mut monitor = Monitor {
+        event_tx,
+        groups_rx,
+        names: HashMap::new(),
+        up: HashMap::new(),
+    };
+    let mut buf = vec![0u8; 16 * 1024];
+    loop {
+        let len = socket
+            .async_io(Interest::READABLE, |fd| {
+                netlink::receive(fd.as_raw_fd(), &mut buf)
+            })
+            .await?;
+        monitor.handle(&buf[..len]);
+    }
+}
+
+/// Wait until a network interface called `name` exists.
+#[cfg(feature = "hotplug")]
+pub(crate) async fn wait_for_link(name: &str) -> io::Result<()> {
+    wait_for_link_state(name, true).await
+}
+
+/// Wait until the network interface `name` is gone (unplugged or renamed).
+#[cfg(feature = "hotplug")]
+pub(crate) async fn wait_for_link_removal(name: &str) -> io::Result<()> {
+    wait_for_link_state(name, false).await
+}
+
+#[cfg(feature = "hotplug")]
+async fn wait_for_link_state(name: &str, exists: bool) -> io::Result<()> {
+    // Subscribe before looking, so a change in between is not missed. Any link
+    // message triggers a fresh look, which also covers renames.
+    let fd = netlink::open(
+        libc::NETLINK_ROUTE,
+        libc::RTMGRP_LINK as u32,
+        libc::SOCK_NONBLOCK,
+    )?;
+    let socket = AsyncFd::with_interest(fd, Interest::READABLE)?;
+    let path = std::path::Path::new("/sys/class/net").join(name);
+    let mut buf = vec![0u8; 16 * 1024];
+    while path.exists() != exists {
+        socket
+            .async_io(Interest::READABLE, |fd| {
+                netlink::receive(fd.as_raw_fd(), &mut buf)
+            })
+            .await?;
+    }
+    Ok(())
+}
+
+struct Monitor {
+    event_tx: broadcast::Sender<P2pEvent>,
+    groups_rx: watch::Receiver<Vec<P2pGroup>>,
+    // Interface names by index; address messages only carry the index.
+    names: HashMap<u32, String>,
+    up: HashMap<u32, bool>,
+}
+
+impl Monitor {
+    fn handle(&mut self, datagram: &[u8]) {
+        for (kind, body) in netlink::messages(datagram) {
+            match kind {
+                libc::RTM_NEWLINK => self.link(body, true),
+                libc::RTM_DELLINK => self.link(body, false),
+                libc::RTM_NEWADDR => self.address(body, true),
+                libc::RTM_DELADDR => self.address(body, false),
+                _ => {}
+            }
+        }
+    }
+
+    fn link(&mut self, body: &[u8], exists: bool) {
+        if body.len() < IFINFOMSG_LEN {
+            return;
+        }
+        let index = u32_at(body, 4);
+        let flags = u32_at(body, 8);
+        let Some(name) = attributes(&body[IFINFOMSG_LEN..])
+            .find(|(kind, _)| *kind == IFLA_IFNAME)
+            .and_then(|(_, value)| std::str::from_utf8(value).ok())
+            .map(|name| name.trim_end_matches('\0').to_string())
+        else {
+            return;
+        };
+        if !self.is_group_interface(&name) {
+            return;
+        }
+        // Usable means administratively up with a carrier, i.e. associated.
+        let lower_up = libc::IFF_LOWER_UP as u32;
+        let up = exists && flags & libc::IFF_UP as u32 != 0 && flags & lower_up != 0;
+        let was_up = self.up.insert(index, up).unwrap_or(false);
+        if exists {
+            self.names.insert(index, name.clone());
+        } else {
+            self.names.remove(&index);
+            self.up.remove(&index);
+        }
+        let event = match (was_up, up) {
+            (false, true) => P2pEvent::GroupInterfaceUp { ifname: name },
+            (true, false) => P2pEvent::GroupInterfaceDown { ifname: name },
+            _ => return,
+        };
+        let _ = self.event_tx.send(event);
+    }
+
+    fn address(&mut self, body: &[u8], added: bool) {
+        if body.len() < IFADDRMSG_LEN {
+            return;
+        }
+        let family = i32::from(body[0]);
+        let index = u32_at(body, 4);
+        let Some(ifname) = self.names.get(&index).cloned() else {
+            return;
+        };
+        // IFA_LOCAL is our address on point-to-point links, IFA_ADDRESS otherwise.
+        let mut ip = None;
+        for (kind, value) in attributes(&body[IFADDRMSG_LEN..]) {
+            let parsed = match (family, value.len()) {
+                (libc::AF_INET, 4) => <[u8; 4]>::try_from(value)
+                    .ok()
+                    .map(|octets| IpAddr::V4(Ipv4Addr::from(octets))),
+                (libc::AF_INET6, 16) => <[u8; 16]>::try_from(value)
+                    .ok()
+                    .map(|octets| IpAddr::V6(Ipv6Addr::from(octets))),
+                _ => None,
+            };
+            match kind {
+                IFA_LOCAL => ip = parsed.or(ip),
+                IFA_ADDRESS if ip.is_none() => ip = parsed,
+                _ => {}
+            }
+        }
+        let Some(ip) = ip else {
+            return;
+        };
+        let event = if added {
+            P2pEvent::GroupAddressAdded { ifname, ip }
+        } else {
+            P2pEvent::GroupAddressRemoved { ifname, ip }
+        };
+        let _ = self.event_tx.send(event);
+    }
+
+    fn is_group_interface(&self, name: &str) -> bool {
+        name.starts_with(GROUP_INTERFACE_PREFIX)
+            || self
+                .groups_rx
+                .borrow()
+                .iter()
+                .any(|group| group.interface == name)
+    }
+}
@@ -0,0 +1,278 @@
+//! Internet sharing for groups we own: forward the group subnet to an uplink.
+//!
+//! `InternetSharing` turns on IPv4 forwarding and masquerades traffic from the
+//! group subnet out of the uplink interface, so clients of the group reach
+//! whatever the uplink reaches, like a phone's hotspot. The subnet is taken from
+//! the address on the group interface, so something has to assign one first
+//! (`dhcp_server` or P2P IP allocation). Rules go into a table of their own with
+//! nftables, or are inserted into the main chains with iptables; either way they
+//! are removed again on drop. A drop policy in another firewall table still wins.
+//! Needs `CAP_NET_ADMIN`.
+
+use std::io::Write;
+use std::net::Ipv4Addr;
+use std::process::{Command, Stdio};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::error::P2pError;
+
+const IP_FORWARD: &str = "/proc/sys/net/ipv4/ip_forward";
+
+// Groups currently sharing, and the ip_forward value from before the first one.
+static FORWARDING: Mutex<(usize, Option<String>)> = Mutex::new((0, None));
+
+/// Which firewall frontend installs the NAT rules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Firewall {
+    /// nftables when `nft` is installed, iptables otherwise.
+    #[default]
+    Auto,
+    Nftables,
+    Iptables,
+}
+
+/// Where to send the group's traffic; see `WifiP2pManagerBuilder::internet_sharing`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SharingConfig {
+    /// Interface with the upstream connection, e.g. "eth0" or "wwan0".
+    pub uplink: String,
+    pub firewall: Firewall,
+}
+
+impl SharingConfig {
+    pub fn new(uplink: impl Into<String>) -> Self {
+        Self {
+            uplink: uplink.into(),
+            firewall: Firewall::Auto,
+        }
+    }
+}
+
+/// Forwarding and NAT from one group interface; undone on drop.
+#[derive(Debug)]
+pub struct InternetSharing {
+    interface: String,
+    uplink: String,
+    subnet: String,
+    firewall: Firewall,
+}
+
+impl InternetSharing {
+    /// Share `config.uplink` with the subnet configured on `interface`.
+    pub fn start(interface: &str, config: &SharingConfig) -> Result<Self, P2pError> {
+        let (ip, prefix_len) = interface_subnet(interface).ok_or_else(|| {
+            sharing_error(&format!("{interface} has no IPv4 address to share from"))
+        })?;
+        let mask = u32::MAX
+            .checked_shl(32 - u32::from(prefix_len))
+            .unwrap_or(0);
+        let network = Ipv4Addr::from(u32::from(ip) & mask);
+        let firewall = match config.firewall {
+            Firewall::Auto if has_nft() => Firewall::Nftables,
+            Firewall::Auto => Firewall::Iptables,
+            firewall => firewall,
+        };
+        enable_forwarding()?;
+        // From here on, drop undoes the forwarding and whatever rules were added.
+        let sharing = Self {
+            interface: interface.to_string(),
+            uplink: config.uplink.clone(),
+            subnet: format!("{network}/{prefix_len}"),
+            firewall,
+        };
+        match firewall {
+            Firewall::Nftables => sharing.add_nft_table()?,
+            _ => {
+                for (table, chain, rule) in sharing.iptables_rules() {
+                    let mut args = vec!["-t", table, "-I", chain];
+                    args.extend(rule);
+                    run("iptables", &args)?;
+                }
+            }
+        }
+        Ok(sharing)
+    }
+
+    pub fn interface(&self) -> &str {
+        &self.interface
+    }
+
+    pub fn uplink(&self) -> &str {
+        &self.uplink
+    }
+
+    /// The shared subnet, e.g. "192.168.49.0/24".
+    pub fn subnet(&self) -> &str {
+        &self.subnet
+    }
+
+    fn table(&self) -> String {
+        // Table names only allow letters, digits and underscores.
+        let name: String = self
+            .interface
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        format!("wifi_p2p_{name}")
+    }
+
+    fn add_nft_table(&self) -> Result<(), P2pError> {
+        let (interface, uplink, subnet) = (&self.interface, &self.uplink, &self.subnet);
+        let script = format!(
+            "table ip {table} {{
+  chain forward {{
+    type filter hook forward priority filter; policy accept;
+    iifname \"{interface}\" oifname \"{uplink}\" accept
+    iifname \"{uplink}\" oifname \"{interface}\" ct state established,related accept
+  }}
+  chain postrouting {{
+    type nat hook postrouting priority srcnat; policy accept;
+    ip saddr {subnet} oifname \"{uplink}\" masquerade
+  }}
+}}
+",
+            table = self.table()
+        );
+        let mut child = Command::new("nft")
+            .args(["-f", "-"])
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|err| sharing_error(&format!("nft: {err}")))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(script.as_bytes())
+                .map_err(|err| sharing_error(&format!("nft: {err}")))?;
+        }
+        let status = child
+            .wait()
+            .map_err(|err| sharing_error(&format!("nft: {err}")))?;
+        if !status.success() {
+            return Err(sharing_error(&format!("nft -f failed: {status}")));
+        }
+        Ok(())
+    }
+
+    /// (table, chain, rule) for each iptables rule we insert.
+    fn iptables_rules(&self) -> [(&str, &str, Vec<&str>); 3] {
+        let (interface, uplink, subnet) = (&*self.interface, &*self.uplink, &*self.subnet);
+        [
+            (
+                "nat",
+                "POSTROUTING",
+                vec!["-s", subnet, "-o", uplink, "-j", "MASQUERADE"],
+            ),
+            (
+                "filter",
+                "FORWARD",
+                vec!["-i", interface, "-o", uplink, "-j", "ACCEPT"],
+            ),
+            (
+                "filter",
+                "FORWARD",
+                vec![
+                    "-i",
+                    uplink,
+                    "-o",
+                    interface,
+                    "-m",
+                    "conntrack",
+                    "--ctstate",
+                    "RELATED,ESTABLISHED",
+                    "-j",
+                    "ACCEPT",
+                ],
+            ),
+        ]
+    }
+}
+
+impl Drop for InternetSharing {
+    fn drop(&mut self) {
+        // Best effort: the rules may already be gone (e.g. a firewall reload).
+        match self.firewall {
+            Firewall::Nftables => {
+                let _ = run("nft", &["delete", "table", "ip", &self.table()]);
+            }
+            _ => {
+                for (table, chain, rule) in self.iptables_rules() {
+                    let mut args = vec!["-t", table, "-D", chain];
+                    args.extend(rule);
+                    let _ = run("iptables", &args);
+                }
+            }
+        }
+        disable_forwarding();
+    }
+}
+
+fn enable_forwarding() -> Result<(), P2pError> {
+    let mut forwarding = lock_forwarding();
+    if forwarding.0 == 0 {
+        let previous = std::fs::read_to_string(IP_FORWARD)
+            .map_err(|err| sharing_error(&format!("{IP_FORWARD}: {err}")))?;
+        std::fs::write(IP_FORWARD, "1")
+            .map_err(|err| sharing_error(&format!("{IP_FORWARD}: {err}")))?;
+        forwarding.1 = Some(previous.trim().to_string());
+    }
+    forwarding.0 += 1;
+    Ok(())
+}
+
+fn disable_forwarding() {
+    // Only the last group to stop sharing puts the old setting back.
+    let mut forwarding = lock_forwarding();
+    forwarding.0 = forwarding.0.saturating_sub(1);
+    if forwarding.0 == 0
+        && let Some(previous) = forwarding.1.take()
+    {
+        let _ = std::fs::write(IP_FORWARD, previous);
+    }
+}
+
+fn lock_forwarding() -> MutexGuard<'static, (usize, Option<String>)> {
+    FORWARDING
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn interface_subnet(interface: &str) -> Option<(Ipv4Addr, u8)> {
+    // One line per address: "3: p2p-wlan0-0    inet 192.168.49.1/24 brd ... scope global ...".
+    let output = Command::new("ip")
+        .args(["-4", "-o", "address", "show", "dev", interface])
+        .output()
+        .ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let mut words = text.split_whitespace();
+    words.find(|word| *word == "inet")?;
+    let (ip, prefix_len) = words.next()?.split_once('/')?;
+    Some((ip.parse().ok()?, prefix_len.parse().ok()?))
+}
+
+fn has_nft() -> bool {
+    Command::new("nft")
+        .arg("--version")
+        .stdout(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+fn run(program: &str, args: &[&str]) -> Result<(), P2pError> {
+    let status = Command::new(program)
+        .args(args)
+        .stderr(Stdio::null())
+        .status()
+        .map_err(|err| sharing_error(&format!("{program}: {err}")))?;
+    if !status.success() {
+        return Err(sharing_error(&format!(
+            "{program} {} failed: {status}",
+            args.join(" ")
+        )));
+    }
+    Ok(())
+}
+
+fn sharing_error(message: &str) -> P2pError {
+    P2pError::Backend(format!("internet sharing: {message}"))
+}
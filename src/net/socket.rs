@@ -0,0 +1,154 @@
+//! Sockets on the group link once a connection has been made; see
+//! `WifiP2pChannel::open_connection`.
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::channel::{P2pEvent, WifiP2pChannel};
+use crate::error::P2pError;
+use crate::group::{GroupRole, P2pGroup};
+use crate::mac::MacAddress;
+
+/// What `open_connection` gives back, depending on our role in the group.
+#[derive(Debug)]
+pub enum P2pSocket {
+    /// We are a client: connected to the peer (the group owner).
+    Stream(TcpStream),
+    /// We own the group: listening for the clients to connect.
+    Listener(TcpListener),
+}
+
+impl P2pSocket {
+    pub fn into_stream(self) -> Option<TcpStream> {
+        match self {
+            Self::Stream(stream) => Some(stream),
+            Self::Listener(_) => None,
+        }
+    }
+
+    pub fn into_listener(self) -> Option<TcpListener> {
+        match self {
+            Self::Listener(listener) => Some(listener),
+            Self::Stream(_) => None,
+        }
+    }
+
+    /// Tag the stream, or on Linux the connections the listener accepts, for
+    /// `category`; see `net::qos`.
+    #[cfg(feature = "qos")]
+    pub fn set_access_category(
+        &self,
+        category: crate::net::qos::AccessCategory,
+    ) -> Result<(), P2pError> {
+        match self {
+            Self::Stream(stream) => crate::net::qos::set_access_category(stream, category),
+            Self::Listener(listener) => crate::net::qos::set_access_category(listener, category),
+        }
+    }
+}
+
+pub(crate) async fn open(
+    channel: &WifiP2pChannel,
+    peer: MacAddress,
+    port: u16,
+) -> Result<P2pSocket, P2pError> {
+    let group = wait_for_group(channel).await?;
+    match group.role {
+        GroupRole::Owner => {
+            // Without a known address of our own, listen on every interface; clients
+            // can only reach it over the group link anyway.
+            let ip = group
+                .ip_allocation
+                .map_or(Ipv4Addr::UNSPECIFIED, |allocation| allocation.ip);
+            let listener = TcpListener::bind((ip, port))
+                .await
+                .map_err(|err| socket_error(&format!("listen on {ip}:{port}: {err}")))?;
+            Ok(P2pSocket::Listener(listener))
+        }
+        GroupRole::Client => Ok(P2pSocket::Stream(dial(channel, &group, peer, port).await?)),
+    }
+}
+
+/// Connect to `peer` on `port` once a group is up, whichever side of it we are on.
+pub(crate) async fn connect(
+    channel: &WifiP2pChannel,
+    peer: MacAddress,
+    port: u16,
+) -> Result<TcpStream, P2pError> {
+    let group = wait_for_group(channel).await?;
+    dial(channel, &group, peer, port).await
+}
+
+async fn dial(
+    channel: &WifiP2pChannel,
+    group: &P2pGroup,
+    peer: MacAddress,
+    port: u16,
+) -> Result<TcpStream, P2pError> {
+    let ip = peer_ip(channel, peer, group).await?;
+    let address = socket_address(group, ip, port)?;
+    TcpStream::connect(address)
+        .await
+        .map_err(|err| socket_error(&format!("connect to {address}: {err}")))
+}
+
+async fn wait_for_group(channel: &WifiP2pChannel) -> Result<P2pGroup, P2pError> {
+    let mut groups = channel.subscribe_groups();
+    let groups = groups
+        .wait_for(|groups| !groups.is_empty())
+        .await
+        .map_err(|_| P2pError::ChannelClosed("manager".to_string()))?;
+    Ok(groups[0].clone())
+}
+
+/// The peer's address on the group link, waiting for a lookup if needed.
+pub(crate) async fn peer_ip(
+    channel: &WifiP2pChannel,
+    peer: MacAddress,
+    group: &P2pGroup,
+) -> Result<IpAddr, P2pError> {
+    // As a client our only peer is the group owner, whose address came with the group.
+    if let (GroupRole::Client, Some(allocation)) = (group.role, group.ip_allocation) {
+        return Ok(IpAddr::V4(allocation.go_ip));
+    }
+    // Subscribe before asking, so a background lookup finishing in between is not missed.
+    let mut events = channel.subscribe_events();
+    let lookup = channel.resolve_peer_ip(peer).await?;
+    let resolved = lookup
+        .await
+        .map_err(|_| P2pError::ChannelClosed("manager".to_string()))?;
+    if let Some(ip) = resolved? {
+        return Ok(ip);
+    }
+    loop {
+        match events.recv().await {
+            Ok(P2pEvent::PeerIpResolved { mac, ip }) if mac == peer => return Ok(ip),
+            Ok(_) | Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => {
+                return Err(P2pError::ChannelClosed("manager".to_string()));
+            }
+        }
+    }
+}
+
+#[cfg_attr(
+    not(all(feature = "ipv6-link-local", target_os = "linux")),
+    allow(unused_variables)
+)]
+fn socket_address(group: &P2pGroup, ip: IpAddr, port: u16) -> Result<SocketAddr, P2pError> {
+    // Link-local addresses are ambiguous without the interface they live on.
+    #[cfg(all(feature = "ipv6-link-local", target_os = "linux"))]
+    if let IpAddr::V6(ip) = ip
+        && ip.is_unicast_link_local()
+    {
+        let scoped = super::link_local::scoped(&group.interface, ip, port)?;
+        return Ok(SocketAddr::V6(scoped));
+    }
+    Ok(SocketAddr::new(ip, port))
+}
+
+fn socket_error(message: &str) -> P2pError {
+    P2pError::Backend(format!("socket: {message}"))
+}
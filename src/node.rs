@@ -0,0 +1,256 @@
+//! Node.js bindings with napi-rs, loaded through node/index.js.
+//!
+//! Actions return promises resolved from napi's tokio runtime. Events reach a
+//! callback registered with `subscribe`, which node/index.js turns into an
+//! `EventEmitter`. Devices, groups and events cross over as the JSON values
+//! the HTTP API serves. A rejected promise's message starts with the
+//! `P2pError` variant name, e.g. `"Timeout: ..."`.
+
+use std::future::Future;
+use std::sync::Mutex;
+
+use napi::bindgen_prelude::{Error, Result, Status, spawn, within_runtime_if_available};
+use napi::threadsafe_function::{ErrorStrategy, ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use napi_derive::napi;
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio_stream::StreamExt;
+
+use crate::channel;
+use crate::config::{ConnectConfig, WpsMethod};
+use crate::error::P2pError;
+use crate::json::event_json;
+use crate::mac::MacAddress;
+use crate::manager;
+
+/// Opened with `await WifiP2pManager.open("wlan0")`.
+#[napi(js_name = "WifiP2pManager")]
+pub struct WifiP2pManager {
+    // Taken out by `shutdown`, which needs it across an await.
+    inner: Mutex<Option<manager::WifiP2pManager>>,
+}
+
+#[napi]
+impl WifiP2pManager {
+    /// Build the platform manager on `interfaceName`.
+    #[napi(factory)]
+    pub async fn open(interface_name: String) -> Result<WifiP2pManager> {
+        let manager = manager::WifiP2pManager::new(&interface_name)
+            .await
+            .map_err(to_js_err)?;
+        Ok(WifiP2pManager {
+            inner: Mutex::new(Some(manager)),
+        })
+    }
+
+    /// Start the command loop and return a channel to it.
+    #[napi]
+    pub fn initialize(&self) -> Result<WifiP2pChannel> {
+        // The manager spawns its tasks onto the ambient runtime, so enter napi's first.
+        let inner = within_runtime_if_available(|| {
+            lock(&self.inner)
+                .as_ref()
+                .map(manager::WifiP2pManager::initialize)
+                .ok_or_else(|| to_js_err(P2pError::ChannelClosed("manager shut down".to_string())))
+        })?;
+        Ok(WifiP2pChannel { inner })
+    }
+
+    /// Stop discovery, remove groups and end the command loop; see
+    /// the Rust `WifiP2pManager::shutdown`. The manager cannot be used afterwards.
+    #[napi]
+    pub async fn shutdown(&self) -> Result<()> {
+        let manager = lock(&self.inner).take();
+        match manager {
+            Some(mut manager) => manager.shutdown().await.map_err(to_js_err),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for WifiP2pManager {
+    fn drop(&mut self) {
+        // Dropping the manager spawns its cleanup, which needs the runtime.
+        within_runtime_if_available(|| lock(&self.inner).take());
+    }
+}
+
+/// Every action returns a promise.
+#[napi(js_name = "WifiP2pChannel")]
+pub struct WifiP2pChannel {
+    inner: channel::WifiP2pChannel,
+}
+
+#[napi]
+impl WifiP2pChannel {
+    #[napi]
+    pub async fn discover_peers(&self) -> Result<()> {
+        let channel = self.inner.clone();
+        reply(async move { channel.discover_peers().await }).await
+    }
+
+    #[napi]
+    pub async fn stop_discovery(&self) -> Result<()> {
+        let channel = self.inner.clone();
+        reply(async move { channel.stop_discovery().await }).await
+    }
+
+    /// Connect with `method` "pbc" (the default), "display", "keypad" or
+    /// "label"; resolves to the PIN the supplicant generated, if any.
+    #[napi]
+    pub async fn connect(
+        &self,
+        device_address: String,
+        method: Option<String>,
+        pin: Option<String>,
+    ) -> Result<Option<String>> {
+        let wps_method = match method.as_deref().unwrap_or("pbc") {
+            "pbc" => WpsMethod::Pbc,
+            "display" => WpsMethod::PinDisplay,
+            "keypad" => WpsMethod::PinKeypad,
+            "label" => WpsMethod::PinLabel,
+            other => {
+                let err = P2pError::InvalidArgs(format!("unknown WPS method {other:?}"));
+                return Err(to_js_err(err));
+            }
+        };
+        let config = ConnectConfig {
+            device_address: parse_mac(&device_address)?,
+            wps_method,
+            pin,
+        };
+        let channel = self.inner.clone();
+        reply(async move { channel.connect_with_config(config).await }).await
+    }
+
+    #[napi]
+    pub async fn cancel_connect(&self) -> Result<()> {
+        let channel = self.inner.clone();
+        reply(async move { channel.cancel_connect().await }).await
+    }
+
+    /// Resolves to an array of devices.
+    #[napi(ts_return_type = "Promise<Array<object>>")]
+    pub async fn request_peers(&self) -> Result<Value> {
+        let channel = self.inner.clone();
+        to_json(&reply(async move { channel.request_peers().await }).await?)
+    }
+
+    /// Resolves to an array of groups.
+    #[napi(ts_return_type = "Promise<Array<object>>")]
+    pub async fn request_groups(&self) -> Result<Value> {
+        let channel = self.inner.clone();
+        to_json(&reply(async move { channel.request_groups().await }).await?)
+    }
+
+    #[napi]
+    pub async fn create_group(&self) -> Result<()> {
+        let channel = self.inner.clone();
+        reply(async move { channel.create_group().await }).await
+    }
+
+    #[napi]
+    pub async fn invite(&self, peer: String) -> Result<()> {
+        let peer = parse_mac(&peer)?;
+        let channel = self.inner.clone();
+        reply(async move { channel.invite(peer).await }).await
+    }
+
+    #[napi]
+    pub async fn reject_peer(&self, peer: String) -> Result<()> {
+        let peer = parse_mac(&peer)?;
+        let channel = self.inner.clone();
+        reply(async move { channel.reject_peer(peer).await }).await
+    }
+
+    #[napi]
+    pub async fn remove_group(&self) -> Result<()> {
+        let channel = self.inner.clone();
+        reply(async move { channel.remove_group().await }).await
+    }
+
+    #[napi]
+    pub async fn discover_services(&self) -> Result<()> {
+        let channel = self.inner.clone();
+        reply(async move { channel.discover_services().await }).await
+    }
+
+    /// The manager's peer table, without going through the queue.
+    #[napi(ts_return_type = "Array<object>")]
+    pub fn peers_snapshot(&self) -> Result<Value> {
+        to_json(&self.inner.peers_snapshot())
+    }
+
+    /// Call `callback` with each event, `{time, type, data}`, from now on;
+    /// node/index.js wraps this as `events()`, an `EventEmitter`.
+    #[napi(ts_args_type = "callback: (event: object) => void")]
+    pub fn subscribe(
+        &self,
+        callback: ThreadsafeFunction<Value, ErrorStrategy::Fatal>,
+    ) -> EventSubscription {
+        let mut stream = self.inner.event_stream();
+        let task = spawn(async move {
+            while let Some(event) = stream.next().await {
+                callback.call(event_json(&event), ThreadsafeFunctionCallMode::NonBlocking);
+            }
+        });
+        EventSubscription { task }
+    }
+}
+
+/// Returned by `subscribe`; the callback is called until `unsubscribe` or the
+/// manager stops, and keeps the process alive until then.
+#[napi]
+pub struct EventSubscription {
+    task: JoinHandle<()>,
+}
+
+#[napi]
+impl EventSubscription {
+    #[napi]
+    pub fn unsubscribe(&self) {
+        self.task.abort();
+    }
+}
+
+impl Drop for EventSubscription {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Await a queued command's result.
+async fn reply<T>(
+    queued: impl Future<
+        Output = std::result::Result<oneshot::Receiver<std::result::Result<T, P2pError>>, P2pError>,
+    >,
+) -> Result<T> {
+    queued
+        .await
+        .map_err(to_js_err)?
+        .await
+        .map_err(|_| to_js_err(P2pError::ChannelClosed("manager".to_string())))?
+        .map_err(to_js_err)
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<Value> {
+    serde_json::to_value(value).map_err(|err| to_js_err(P2pError::Backend(err.to_string())))
+}
+
+fn parse_mac(address: &str) -> Result<MacAddress> {
+    address.parse().map_err(to_js_err)
+}
+
+fn to_js_err(err: P2pError) -> Error {
+    // napi sets `code` from the status, so the kind goes at the front of the message.
+    Error::new(Status::GenericFailure, format!("{}: {err}", err.kind()))
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    // The slot is only swapped whole, so a panicking holder leaves nothing half-done.
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
@@ -0,0 +1,296 @@
+//! One-call pairing for the common case; see `WifiP2pChannel::pair`.
+//!
+//! `pair` finds a peer matching a `PeerSelector` (starting discovery if it is not
+//! in the peer table yet), connects with the chosen `Provisioning`, waits for the
+//! group and resolves the peer's address, then returns a `PairedLink` to open
+//! sockets on. Each phase has its own deadline in `PairOptions`.
+
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::channel::{P2pEvent, WifiP2pChannel};
+use crate::config::{ConnectConfig, WpsMethod};
+use crate::device::{DeviceStatus, P2pDevice};
+use crate::error::P2pError;
+use crate::group::P2pGroup;
+use crate::mac::MacAddress;
+use crate::net::socket::{self, P2pSocket};
+
+/// Shows the user the PIN generated for a peer.
+pub type ShowPin = Arc<dyn Fn(&P2pDevice, &str) + Send + Sync>;
+/// Asks the user for the PIN a peer displays; `None` cancels.
+pub type AskPin = Arc<dyn Fn(&P2pDevice) -> Option<String> + Send + Sync>;
+
+/// Which peer `pair` connects to; the first one found that matches wins.
+#[derive(Clone)]
+pub enum PeerSelector {
+    Address(MacAddress),
+    /// Exact device name as advertised in discovery.
+    Name(String),
+    /// Primary or secondary WPS device type, e.g. "7-0050F204-1" for a display;
+    /// this is how peers announce the service they offer.
+    DeviceType(String),
+    Matching(Arc<dyn Fn(&P2pDevice) -> bool + Send + Sync>),
+}
+
+impl PeerSelector {
+    pub fn matches(&self, device: &P2pDevice) -> bool {
+        match self {
+            Self::Address(address) => device.mac_address == *address,
+            Self::Name(name) => device.device_name.as_deref() == Some(name),
+            Self::DeviceType(kind) => {
+                device.primary_type.as_deref() == Some(kind)
+                    || device.secondary_types.iter().any(|other| other == kind)
+            }
+            Self::Matching(predicate) => predicate(device),
+        }
+    }
+}
+
+impl fmt::Debug for PeerSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Address(address) => f.debug_tuple("Address").field(address).finish(),
+            Self::Name(name) => f.debug_tuple("Name").field(name).finish(),
+            Self::DeviceType(kind) => f.debug_tuple("DeviceType").field(kind).finish(),
+            Self::Matching(_) => f.write_str("Matching(..)"),
+        }
+    }
+}
+
+/// How the WPS exchange with the selected peer is authorized.
+#[derive(Clone, Default)]
+pub enum Provisioning {
+    /// Push button on both sides.
+    #[default]
+    Pbc,
+    /// We display a PIN generated by the supplicant; the callback is handed the
+    /// peer and the PIN to show to the user.
+    DisplayPin(ShowPin),
+    /// We enter the PIN the peer displays; the callback asks the user for it and
+    /// returns `None` to cancel.
+    EnterPin(AskPin),
+}
+
+impl fmt::Debug for Provisioning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pbc => f.write_str("Pbc"),
+            Self::DisplayPin(_) => f.write_str("DisplayPin(..)"),
+            Self::EnterPin(_) => f.write_str("EnterPin(..)"),
+        }
+    }
+}
+
+/// Provisioning and deadlines for `WifiP2pChannel::pair`.
+#[derive(Debug, Clone)]
+pub struct PairOptions {
+    pub provisioning: Provisioning,
+    /// How long to look for a matching peer (default 30s).
+    pub discovery_timeout: Duration,
+    /// How long connecting and group formation may take, including the time the
+    /// user needs to press the button or enter the PIN (default 60s).
+    pub connect_timeout: Duration,
+    /// How long to wait for the peer's address on the group link (default 30s).
+    pub ip_timeout: Duration,
+}
+
+impl Default for PairOptions {
+    fn default() -> Self {
+        Self {
+            provisioning: Provisioning::Pbc,
+            discovery_timeout: Duration::from_secs(30),
+            connect_timeout: Duration::from_secs(60),
+            ip_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// A formed group with the paired peer, as returned by `WifiP2pChannel::pair`.
+#[derive(Clone)]
+pub struct PairedLink {
+    channel: WifiP2pChannel,
+    peer: P2pDevice,
+    group: P2pGroup,
+    peer_ip: IpAddr,
+}
+
+impl PairedLink {
+    /// The peer as it was found during discovery.
+    pub fn peer(&self) -> &P2pDevice {
+        &self.peer
+    }
+
+    pub fn group(&self) -> &P2pGroup {
+        &self.group
+    }
+
+    /// The peer's address on the group interface.
+    pub fn peer_ip(&self) -> IpAddr {
+        self.peer_ip
+    }
+
+    /// Connect to the peer as client, or listen as group owner; see
+    /// `WifiP2pChannel::open_connection`.
+    pub async fn open_connection(&self, port: u16) -> Result<P2pSocket, P2pError> {
+        self.channel
+            .open_connection(self.peer.mac_address, port)
+            .await
+    }
+
+    /// Connect to the peer on `port` whichever side of the group we are on.
+    pub async fn connect(&self, port: u16) -> Result<tokio::net::TcpStream, P2pError> {
+        socket::connect(&self.channel, self.peer.mac_address, port).await
+    }
+}
+
+pub(crate) async fn pair(
+    channel: &WifiP2pChannel,
+    selector: PeerSelector,
+    options: PairOptions,
+) -> Result<PairedLink, P2pError> {
+    // Subscribe before anything is started, so no event in between is missed.
+    let mut events = channel.subscribe_events();
+    let peer = within(
+        options.discovery_timeout,
+        find(channel, &mut events, &selector),
+    )
+    .await?;
+    link(channel, &mut events, peer, &options).await
+}
+
+/// Connect to `peer`, found already, and resolve its address.
+pub(crate) async fn link(
+    channel: &WifiP2pChannel,
+    events: &mut broadcast::Receiver<P2pEvent>,
+    peer: P2pDevice,
+    options: &PairOptions,
+) -> Result<PairedLink, P2pError> {
+    let group = within(
+        options.connect_timeout,
+        connect(channel, events, &peer, &options.provisioning),
+    )
+    .await?;
+    let peer_ip = within(
+        options.ip_timeout,
+        socket::peer_ip(channel, peer.mac_address, &group),
+    )
+    .await?;
+    Ok(PairedLink {
+        channel: channel.clone(),
+        peer,
+        group,
+        peer_ip,
+    })
+}
+
+pub(crate) async fn find(
+    channel: &WifiP2pChannel,
+    events: &mut broadcast::Receiver<P2pEvent>,
+    selector: &PeerSelector,
+) -> Result<P2pDevice, P2pError> {
+    let known = |channel: &WifiP2pChannel| {
+        channel
+            .peers_snapshot()
+            .into_iter()
+            .find(|device| device.status != DeviceStatus::Unavailable && selector.matches(device))
+    };
+    if let Some(device) = known(channel) {
+        return Ok(device);
+    }
+    channel.discover_peers().await?.await.map_err(closed)??;
+    // The peer may have been reported while discovery was being started.
+    if let Some(device) = known(channel) {
+        return Ok(device);
+    }
+    loop {
+        match next(events).await? {
+            P2pEvent::PeerFound(device) if selector.matches(&device) => {
+                return Ok(Arc::unwrap_or_clone(device));
+            }
+            _ => {}
+        }
+    }
+}
+
+async fn connect(
+    channel: &WifiP2pChannel,
+    events: &mut broadcast::Receiver<P2pEvent>,
+    peer: &P2pDevice,
+    provisioning: &Provisioning,
+) -> Result<P2pGroup, P2pError> {
+    let address = peer.mac_address;
+    let config = match provisioning {
+        Provisioning::Pbc => ConnectConfig::new(address),
+        Provisioning::DisplayPin(_) => ConnectConfig {
+            device_address: address,
+            wps_method: WpsMethod::PinDisplay,
+            pin: None,
+        },
+        Provisioning::EnterPin(ask) => {
+            let pin = ask(peer)
+                .ok_or_else(|| P2pError::InvalidConfig("no PIN was entered".to_string()))?;
+            ConnectConfig::with_pin(address, WpsMethod::PinKeypad, pin)
+        }
+    };
+    let generated = channel
+        .connect_with_config(config)
+        .await?
+        .await
+        .map_err(closed)??;
+    if let (Provisioning::DisplayPin(show), Some(pin)) = (provisioning, generated) {
+        show(peer, &pin);
+    }
+    // Other groups (a supervised link, a group we own on our own) may be up
+    // at the same time; only the one formed with this peer answers.
+    if let Some(group) = channel
+        .groups_snapshot()
+        .into_iter()
+        .find(|group| group.peer == Some(address))
+    {
+        return Ok(group);
+    }
+    loop {
+        match next(events).await? {
+            P2pEvent::GroupStarted(group) if group.peer == Some(address) => {
+                return Ok(Arc::unwrap_or_clone(group));
+            }
+            P2pEvent::GoNegotiationFailed { peer, status }
+                if peer.is_none_or(|failed| failed == address) =>
+            {
+                return Err(P2pError::Status(status));
+            }
+            P2pEvent::ProvisionDiscoveryFailed { peer, status } if peer == address => {
+                return Err(P2pError::Status(status));
+            }
+            _ => {}
+        }
+    }
+}
+
+async fn next(events: &mut broadcast::Receiver<P2pEvent>) -> Result<P2pEvent, P2pError> {
+    loop {
+        match events.recv().await {
+            Ok(event) => return Ok(event),
+            Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => return Err(closed(())),
+        }
+    }
+}
+
+pub(crate) async fn within<T>(
+    timeout: Duration,
+    phase: impl Future<Output = Result<T, P2pError>>,
+) -> Result<T, P2pError> {
+    tokio::time::timeout(timeout, phase)
+        .await
+        .map_err(|_| P2pError::Timeout(timeout))?
+}
+
+pub(crate) fn closed<E>(_: E) -> P2pError {
+    P2pError::ChannelClosed("manager".to_string())
+}
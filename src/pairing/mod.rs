@@ -0,0 +1,6 @@
+//! Out-of-band pairing: hand a peer what it needs to connect through another
+//! channel than Wi-Fi discovery, such as a QR code on a screen.
+//!
+//! For pairing over the air in one call, see `pair`.
+
+pub mod qr;
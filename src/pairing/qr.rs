@@ -0,0 +1,332 @@
+//! QR code payloads for one-scan onboarding.
+//!
+//! The device with a screen shows `QrPayload::to_string()` as a QR code, using
+//! any QR encoder; the device with a camera parses the scanned text with
+//! `str::parse::<QrPayload>()` and acts on it. Three payloads are understood:
+//!
+//! - `WIFI:T:WPA;S:<ssid>;P:<passphrase>;;`, the network code phone cameras join
+//!   directly, for legacy clients of a group we own;
+//! - `DPP:...;;` bootstrapping URIs, as printed by wpa_supplicant's
+//!   `dpp_bootstrap_get_uri`, carried through for a DPP-capable supplicant;
+//! - `WIFI-P2P:M:<device address>;P:<pin>;N:<name>;;`, this crate's own code for
+//!   a P2P connect with a WPS PIN, which `connect_config` turns into a keypad
+//!   connect on the scanning side.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::config::{ConnectConfig, WpsMethod};
+use crate::credentials::GroupCredentials;
+use crate::error::P2pError;
+use crate::mac::MacAddress;
+
+/// What a scanned QR code tells the joining side.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum QrPayload {
+    /// Join the network as a legacy (non-P2P) client; `passphrase` is `None` for
+    /// an open network.
+    Network {
+        ssid: String,
+        passphrase: Option<String>,
+        hidden: bool,
+    },
+    /// A DPP bootstrapping URI.
+    Dpp(DppUri),
+    /// Connect over P2P to `device_address`, entering `pin` in the WPS exchange.
+    P2pPin {
+        device_address: MacAddress,
+        pin: String,
+        device_name: Option<String>,
+    },
+}
+
+/// The fields of a `DPP:` URI (Wi-Fi Easy Connect 5.2.1).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DppUri {
+    /// Operating class/channel pairs to listen on, e.g. "81/1".
+    pub channels: Vec<String>,
+    pub mac: Option<MacAddress>,
+    pub info: Option<String>,
+    /// Base64 DER of the bootstrapping public key.
+    pub key: String,
+}
+
+impl QrPayload {
+    /// The network code for a group we own, so legacy clients can join it.
+    pub fn for_group(credentials: &GroupCredentials) -> Self {
+        Self::Network {
+            ssid: credentials.ssid.clone(),
+            passphrase: Some(credentials.psk.clone()),
+            hidden: false,
+        }
+    }
+
+    /// The connect request to make after scanning, for the payloads a P2P connect
+    /// can act on.
+    pub fn connect_config(&self) -> Option<ConnectConfig> {
+        match self {
+            Self::P2pPin {
+                device_address,
+                pin,
+                ..
+            } => Some(ConnectConfig::with_pin(
+                *device_address,
+                WpsMethod::PinKeypad,
+                pin.clone(),
+            )),
+            Self::Network { .. } | Self::Dpp(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for QrPayload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Network {
+                ssid,
+                passphrase,
+                hidden,
+            } => {
+                match passphrase {
+                    Some(passphrase) => {
+                        write!(f, "WIFI:T:WPA;S:{};P:{};", escape(ssid), escape(passphrase))?
+                    }
+                    None => write!(f, "WIFI:T:nopass;S:{};", escape(ssid))?,
+                }
+                if *hidden {
+                    f.write_str("H:true;")?;
+                }
+                f.write_str(";")
+            }
+            Self::Dpp(uri) => {
+                f.write_str("DPP:")?;
+                if !uri.channels.is_empty() {
+                    write!(f, "C:{};", uri.channels.join(","))?;
+                }
+                if let Some(mac) = uri.mac {
+                    write!(f, "M:{};", mac.to_hex())?;
+                }
+                if let Some(info) = &uri.info {
+                    write!(f, "I:{info};")?;
+                }
+                write!(f, "K:{};;", uri.key)
+            }
+            Self::P2pPin {
+                device_address,
+                pin,
+                device_name,
+            } => {
+                write!(
+                    f,
+                    "WIFI-P2P:M:{};P:{};",
+                    device_address.to_hex(),
+                    escape(pin)
+                )?;
+                if let Some(name) = device_name {
+                    write!(f, "N:{};", escape(name))?;
+                }
+                f.write_str(";")
+            }
+        }
+    }
+}
+
+impl FromStr for QrPayload {
+    type Err = P2pError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(body) = s.strip_prefix("WIFI-P2P:") {
+            let fields = fields(body, true)?;
+            let pin = field(&fields, "P").ok_or_else(|| invalid("missing PIN"))?;
+            if !matches!(pin.len(), 4 | 8) || !pin.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(invalid("PIN must be 4 or 8 digits"));
+            }
+            let address = field(&fields, "M").ok_or_else(|| invalid("missing device address"))?;
+            return Ok(Self::P2pPin {
+                device_address: parse_mac(address)?,
+                pin: pin.to_string(),
+                device_name: field(&fields, "N").map(str::to_string),
+            });
+        }
+        if let Some(body) = s.strip_prefix("WIFI:") {
+            let fields = fields(body, true)?;
+            let ssid = field(&fields, "S").ok_or_else(|| invalid("missing SSID"))?;
+            let passphrase = match field(&fields, "T") {
+                Some("WEP") => return Err(invalid("WEP networks are not supported")),
+                Some("nopass") => None,
+                _ => field(&fields, "P").map(str::to_string),
+            };
+            return Ok(Self::Network {
+                ssid: ssid.to_string(),
+                passphrase,
+                hidden: field(&fields, "H").is_some_and(|hidden| hidden == "true"),
+            });
+        }
+        if let Some(body) = s.strip_prefix("DPP:") {
+            // DPP values cannot contain ';', so there is nothing to unescape.
+            let fields = fields(body, false)?;
+            let key = field(&fields, "K").ok_or_else(|| invalid("missing bootstrapping key"))?;
+            return Ok(Self::Dpp(DppUri {
+                channels: field(&fields, "C")
+                    .map(|channels| channels.split(',').map(str::to_string).collect())
+                    .unwrap_or_default(),
+                mac: field(&fields, "M").map(parse_mac).transpose()?,
+                info: field(&fields, "I").map(str::to_string),
+                key: key.to_string(),
+            }));
+        }
+        Err(invalid("unknown scheme"))
+    }
+}
+
+/// Split `K:value;` pairs up to the terminating empty field. Some generators
+/// drop the final `;`, so the end of the text after a field is accepted too.
+fn fields(body: &str, escaped: bool) -> Result<Vec<(String, String)>, P2pError> {
+    let mut fields = Vec::new();
+    let mut current = String::new();
+    let mut chars = body.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if escaped => {
+                current.push(chars.next().ok_or_else(|| invalid("dangling escape"))?)
+            }
+            ';' if current.is_empty() => return Ok(fields),
+            ';' => {
+                let field = std::mem::take(&mut current);
+                let (key, value) = field
+                    .split_once(':')
+                    .ok_or_else(|| invalid("field without a key"))?;
+                fields.push((key.to_string(), value.to_string()));
+            }
+            _ => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        return Err(invalid("missing terminating ';;'"));
+    }
+    Ok(fields)
+}
+
+fn field<'a>(fields: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    fields
+        .iter()
+        .find_map(|(name, value)| (name == key).then_some(value.as_str()))
+}
+
+/// Accepts both the bare hex used in the codes and the usual colon form.
+fn parse_mac(value: &str) -> Result<MacAddress, P2pError> {
+    if value.len() != 12 || !value.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return value.parse();
+    }
+    let mut bytes = [0u8; 6];
+    for (byte, pair) in bytes.iter_mut().zip(value.as_bytes().chunks(2)) {
+        let pair = std::str::from_utf8(pair).unwrap_or_default();
+        *byte = u8::from_str_radix(pair, 16)
+            .map_err(|_| P2pError::InvalidMacAddress(value.to_string()))?;
+    }
+    Ok(MacAddress::new(bytes))
+}
+
+/// Backslash-escape the characters the `WIFI:` format reserves.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '\\' | ';' | ',' | ':' | '"') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn invalid(reason: &str) -> P2pError {
+    P2pError::InvalidArgs(format!("QR payload: {reason}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEVICE: MacAddress = MacAddress::new([0x02, 0x1a, 0x2b, 0x3c, 0x4d, 0x5e]);
+
+    fn round_trip(payload: QrPayload) {
+        assert_eq!(payload.to_string().parse::<QrPayload>().unwrap(), payload);
+    }
+
+    #[test]
+    fn payloads_round_trip() {
+        round_trip(QrPayload::Network {
+            ssid: r#"DIRECT-ab;c:d\e,"f""#.to_string(),
+            passphrase: Some("pass;word".to_string()),
+            hidden: false,
+        });
+        round_trip(QrPayload::Network {
+            ssid: "open".to_string(),
+            passphrase: None,
+            hidden: true,
+        });
+        round_trip(QrPayload::P2pPin {
+            device_address: DEVICE,
+            pin: "12345670".to_string(),
+            device_name: Some("Living: Room".to_string()),
+        });
+        round_trip(QrPayload::Dpp(DppUri {
+            channels: vec!["81/1".to_string(), "115/36".to_string()],
+            mac: Some(DEVICE),
+            info: Some("printer".to_string()),
+            key: "MDkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDIgAD".to_string(),
+        }));
+    }
+
+    #[test]
+    fn p2p_pin_code_is_encoded_compactly() {
+        let payload = QrPayload::P2pPin {
+            device_address: DEVICE,
+            pin: "1234".to_string(),
+            device_name: None,
+        };
+        assert_eq!(payload.to_string(), "WIFI-P2P:M:021a2b3c4d5e;P:1234;;");
+        let config = payload.connect_config().unwrap();
+        assert_eq!(config.device_address, DEVICE);
+        assert_eq!(config.wps_method, WpsMethod::PinKeypad);
+        assert_eq!(config.pin.as_deref(), Some("1234"));
+    }
+
+    #[test]
+    fn phone_network_codes_parse() {
+        // Field order varies between generators and the final ';' is often dropped.
+        let payload: QrPayload = "WIFI:S:home;T:WPA;P:secret;H:true;".parse().unwrap();
+        assert_eq!(
+            payload,
+            QrPayload::Network {
+                ssid: "home".to_string(),
+                passphrase: Some("secret".to_string()),
+                hidden: true,
+            }
+        );
+        assert_eq!(payload.connect_config(), None);
+    }
+
+    #[test]
+    fn malformed_payloads_are_rejected() {
+        for s in [
+            "",
+            "MECARD:N:someone;;",
+            "WIFI:T:WEP;S:old;P:abcde;;",
+            "WIFI:T:WPA;P:no-ssid;;",
+            "WIFI:T:WPA;S:home;P:secret",
+            "WIFI:T:WPA;S:home\\",
+            "WIFI:T:WPA;S;;",
+            "WIFI-P2P:M:021a2b3c4d5e;P:123;;",
+            "WIFI-P2P:M:021a2b3c4d5e;P:1234567a;;",
+            "WIFI-P2P:P:12345670;;",
+            "WIFI-P2P:M:+21a2b3c4d5e;P:12345670;;",
+            "DPP:C:81/1;M:021a2b3c4d5e;;",
+        ] {
+            assert!(s.parse::<QrPayload>().is_err(), "{s:?} parsed");
+        }
+    }
+}
@@ -0,0 +1,376 @@
+//! Picking peers by name, device type, capabilities and signal.
+//!
+//! A `PeerFilter` narrows `WifiP2pChannel::request_peers_matching` and event
+//! subscriptions made with `P2pEventStream::peers_matching`. A filtered stream
+//! keeps track of the peers it has shown: `PeerLost` and `PeerStatusChanged`
+//! only come through for those, and a shown peer that stops matching (its
+//! signal dropped below the minimum, say) is reported lost.
+
+use std::collections::HashSet;
+
+#[cfg(feature = "regex")]
+use regex::Regex;
+
+use crate::channel::P2pEvent;
+use crate::device::P2pDevice;
+#[cfg(feature = "regex")]
+use crate::error::P2pError;
+use crate::mac::MacAddress;
+
+/// Conditions a peer has to meet, all of them; the default matches every peer.
+#[derive(Debug, Clone, Default)]
+pub struct PeerFilter {
+    #[cfg(feature = "regex")]
+    name: Option<Regex>,
+    name_contains: Option<String>,
+    device_types: Vec<String>,
+    device_capability: u8,
+    group_capability: u8,
+    min_signal: Option<i32>,
+}
+
+impl PeerFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only peers whose name matches `pattern` somewhere; anchor it with `^`
+    /// and `$` to match the whole name.
+    #[cfg(feature = "regex")]
+    pub fn name(mut self, pattern: &str) -> Result<Self, P2pError> {
+        let regex = Regex::new(pattern)
+            .map_err(|err| P2pError::InvalidArgs(format!("name pattern: {err}")))?;
+        self.name = Some(regex);
+        Ok(self)
+    }
+
+    /// Only peers whose name contains `text`, ignoring case.
+    pub fn name_contains(mut self, text: impl Into<String>) -> Self {
+        self.name_contains = Some(text.into().to_lowercase());
+        self
+    }
+
+    /// Only peers with this primary or secondary device type; repeat for peers
+    /// of any of several types.
+    pub fn device_type(mut self, device_type: impl Into<String>) -> Self {
+        self.device_types.push(device_type.into());
+        self
+    }
+
+    /// Only peers with all of these `device_capability` bits.
+    pub fn device_capability(mut self, bits: u8) -> Self {
+        self.device_capability |= bits;
+        self
+    }
+
+    /// Only peers with all of these `group_capability` bits.
+    pub fn group_capability(mut self, bits: u8) -> Self {
+        self.group_capability |= bits;
+        self
+    }
+
+    /// Only peers heard at `dbm` or stronger; peers without a signal level do
+    /// not match.
+    pub fn min_signal(mut self, dbm: i32) -> Self {
+        self.min_signal = Some(dbm);
+        self
+    }
+
+    pub fn matches(&self, device: &P2pDevice) -> bool {
+        let name = device.device_name.as_deref();
+        #[cfg(feature = "regex")]
+        if let Some(regex) = &self.name
+            && !name.is_some_and(|name| regex.is_match(name))
+        {
+            return false;
+        }
+        if let Some(text) = &self.name_contains
+            && !name.is_some_and(|name| name.to_lowercase().contains(text))
+        {
+            return false;
+        }
+        (self.device_types.is_empty()
+            || self
+                .device_types
+                .iter()
+                .any(|device_type| device.has_device_type(device_type)))
+            && device.device_capability & self.device_capability == self.device_capability
+            && device.group_capability & self.group_capability == self.group_capability
+            && self
+                .min_signal
+                .is_none_or(|min| device.signal_level.is_some_and(|level| level >= min))
+    }
+}
+
+/// The peers a filtered event stream has let through so far. `PeerFound` and
+/// `PeersChanged` report the same changes, so each keeps its own set.
+pub(crate) struct PeerView {
+    filter: PeerFilter,
+    found: HashSet<MacAddress>,
+    listed: HashSet<MacAddress>,
+}
+
+impl PeerView {
+    pub(crate) fn new(filter: PeerFilter) -> Self {
+        Self {
+            filter,
+            found: HashSet::new(),
+            listed: HashSet::new(),
+        }
+    }
+
+    /// `event` as the subscriber should see it, or `None` if it is only about
+    /// peers outside the filter.
+    pub(crate) fn apply(&mut self, event: P2pEvent) -> Option<P2pEvent> {
+        match event {
+            P2pEvent::PeerFound(device) => {
+                let address = device.mac_address;
+                if self.filter.matches(&device) {
+                    self.found.insert(address);
+                    Some(P2pEvent::PeerFound(device))
+                } else {
+                    self.found
+                        .remove(&address)
+                        .then_some(P2pEvent::PeerLost(address))
+                }
+            }
+            P2pEvent::PeerLost(address) => self
+                .found
+                .remove(&address)
+                .then_some(P2pEvent::PeerLost(address)),
+            P2pEvent::PeerStatusChanged(address, status) => {
+                let shown = self.found.contains(&address) || self.listed.contains(&address);
+                shown.then_some(P2pEvent::PeerStatusChanged(address, status))
+            }
+            P2pEvent::PeersChanged {
+                added,
+                removed,
+                updated,
+            } => {
+                let mut shown_added = Vec::new();
+                let mut shown_removed = Vec::new();
+                let mut shown_updated = Vec::new();
+                for device in added {
+                    if self.filter.matches(&device) {
+                        self.listed.insert(device.mac_address);
+                        shown_added.push(device);
+                    }
+                }
+                // An update can move a peer into the filter or out of it.
+                for device in updated {
+                    let address = device.mac_address;
+                    if self.filter.matches(&device) {
+                        if self.listed.insert(address) {
+                            shown_added.push(device);
+                        } else {
+                            shown_updated.push(device);
+                        }
+                    } else if self.listed.remove(&address) {
+                        shown_removed.push(address);
+                    }
+                }
+                for address in removed {
+                    if self.listed.remove(&address) {
+                        shown_removed.push(address);
+                    }
+                }
+                let empty =
+                    shown_added.is_empty() && shown_removed.is_empty() && shown_updated.is_empty();
+                (!empty).then_some(P2pEvent::PeersChanged {
+                    added: shown_added,
+                    removed: shown_removed,
+                    updated: shown_updated,
+                })
+            }
+            other => Some(other),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::device::DeviceStatus;
+
+    const PRINTER: MacAddress = MacAddress::new([0x02, 0, 0, 0, 0, 0x01]);
+    const PHONE: MacAddress = MacAddress::new([0x02, 0, 0, 0, 0, 0x02]);
+
+    fn device(address: MacAddress, name: &str, signal: Option<i32>) -> Arc<P2pDevice> {
+        let mut device = P2pDevice::new(address);
+        device.device_name = Some(name.to_string());
+        device.primary_type = Some("3-0050F204-1".to_string());
+        device.device_capability = 0x25;
+        device.signal_level = signal;
+        Arc::new(device)
+    }
+
+    fn changed(
+        added: Vec<Arc<P2pDevice>>,
+        removed: Vec<MacAddress>,
+        updated: Vec<Arc<P2pDevice>>,
+    ) -> P2pEvent {
+        P2pEvent::PeersChanged {
+            added,
+            removed,
+            updated,
+        }
+    }
+
+    fn view() -> PeerView {
+        PeerView::new(PeerFilter::new().name_contains("print").min_signal(-70))
+    }
+
+    #[test]
+    fn every_condition_has_to_hold() {
+        let printer = device(PRINTER, "Office Printer", Some(-50));
+        assert!(PeerFilter::new().matches(&device(PHONE, "phone", None)));
+        assert!(view().filter.matches(&printer));
+        assert!(
+            PeerFilter::new()
+                .device_type("3-0050F204-1")
+                .matches(&printer)
+        );
+        assert!(
+            !PeerFilter::new()
+                .device_type("10-0050F204-5")
+                .matches(&printer)
+        );
+        assert!(PeerFilter::new().device_capability(0x21).matches(&printer));
+        assert!(!PeerFilter::new().device_capability(0x02).matches(&printer));
+        assert!(!PeerFilter::new().group_capability(0x01).matches(&printer));
+        assert!(
+            !view()
+                .filter
+                .matches(&device(PRINTER, "Office Printer", Some(-80)))
+        );
+        assert!(
+            !view()
+                .filter
+                .matches(&device(PRINTER, "Office Printer", None))
+        );
+        assert!(!view().filter.matches(&device(PHONE, "phone", Some(-40))));
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn name_patterns() {
+        let filter = PeerFilter::new().name("^Office").unwrap();
+        assert!(filter.matches(&device(PRINTER, "Office Printer", None)));
+        assert!(!filter.matches(&device(PRINTER, "Home Office", None)));
+        assert!(PeerFilter::new().name("(").is_err());
+    }
+
+    #[test]
+    fn found_peers_are_followed_until_lost() {
+        let mut view = view();
+        assert!(
+            view.apply(P2pEvent::PeerFound(device(PHONE, "phone", Some(-40))))
+                .is_none()
+        );
+        assert!(view.apply(P2pEvent::PeerLost(PHONE)).is_none());
+        let status = P2pEvent::PeerStatusChanged(PHONE, DeviceStatus::Invited);
+        assert!(view.apply(status).is_none());
+
+        let found = view.apply(P2pEvent::PeerFound(device(PRINTER, "Printer", Some(-50))));
+        assert!(matches!(found, Some(P2pEvent::PeerFound(_))));
+        let status = view.apply(P2pEvent::PeerStatusChanged(
+            PRINTER,
+            DeviceStatus::Connected,
+        ));
+        assert!(matches!(
+            status,
+            Some(P2pEvent::PeerStatusChanged(
+                PRINTER,
+                DeviceStatus::Connected
+            ))
+        ));
+        assert!(matches!(
+            view.apply(P2pEvent::PeerLost(PRINTER)),
+            Some(P2pEvent::PeerLost(PRINTER))
+        ));
+        assert!(view.apply(P2pEvent::PeerLost(PRINTER)).is_none());
+    }
+
+    #[test]
+    fn found_peer_that_stops_matching_is_lost() {
+        let mut view = view();
+        view.apply(P2pEvent::PeerFound(device(PRINTER, "Printer", Some(-50))));
+        let faded = view.apply(P2pEvent::PeerFound(device(PRINTER, "Printer", Some(-90))));
+        assert!(matches!(faded, Some(P2pEvent::PeerLost(PRINTER))));
+        let faded = view.apply(P2pEvent::PeerFound(device(PRINTER, "Printer", Some(-90))));
+        assert!(faded.is_none());
+    }
+
+    #[test]
+    fn listed_peers_are_narrowed_and_followed() {
+        let mut view = view();
+        let listed = view.apply(changed(
+            vec![
+                device(PRINTER, "Printer", Some(-50)),
+                device(PHONE, "phone", Some(-40)),
+            ],
+            Vec::new(),
+            Vec::new(),
+        ));
+        let Some(P2pEvent::PeersChanged { added, .. }) = listed else {
+            panic!("listing dropped: {listed:?}");
+        };
+        assert_eq!(added.len(), 1);
+        assert_eq!(added[0].mac_address, PRINTER);
+
+        // Status changes come through for peers seen only in a listing.
+        let status = view.apply(P2pEvent::PeerStatusChanged(PRINTER, DeviceStatus::Invited));
+        assert!(matches!(
+            status,
+            Some(P2pEvent::PeerStatusChanged(PRINTER, _))
+        ));
+        let status = view.apply(P2pEvent::PeerStatusChanged(PHONE, DeviceStatus::Invited));
+        assert!(status.is_none());
+
+        // Changes to peers outside the filter are dropped altogether.
+        let update = changed(Vec::new(), vec![PHONE], Vec::new());
+        assert!(view.apply(update).is_none());
+    }
+
+    #[test]
+    fn updates_move_peers_into_and_out_of_the_listing() {
+        let mut view = view();
+        let weak = device(PRINTER, "Printer", Some(-90));
+        assert!(
+            view.apply(changed(vec![weak], Vec::new(), Vec::new()))
+                .is_none()
+        );
+
+        let strong = device(PRINTER, "Printer", Some(-50));
+        let update = view.apply(changed(Vec::new(), Vec::new(), vec![strong.clone()]));
+        let Some(P2pEvent::PeersChanged { added, updated, .. }) = update else {
+            panic!("update dropped: {update:?}");
+        };
+        assert_eq!((added.len(), updated.len()), (1, 0));
+        let update = view.apply(changed(Vec::new(), Vec::new(), vec![strong]));
+        let Some(P2pEvent::PeersChanged { added, updated, .. }) = update else {
+            panic!("update dropped: {update:?}");
+        };
+        assert_eq!((added.len(), updated.len()), (0, 1));
+
+        let weak = device(PRINTER, "Printer", Some(-90));
+        let update = view.apply(changed(Vec::new(), Vec::new(), vec![weak]));
+        let Some(P2pEvent::PeersChanged { removed, .. }) = update else {
+            panic!("update dropped: {update:?}");
+        };
+        assert_eq!(removed, [PRINTER]);
+        let status = view.apply(P2pEvent::PeerStatusChanged(PRINTER, DeviceStatus::Failed));
+        assert!(status.is_none());
+    }
+
+    #[test]
+    fn other_events_pass() {
+        let mut view = view();
+        assert!(matches!(
+            view.apply(P2pEvent::DiscoveryStarted),
+            Some(P2pEvent::DiscoveryStarted)
+        ));
+    }
+}
@@ -0,0 +1,254 @@
+//! Latency and throughput of the group link, measured like a small iperf.
+//!
+//! One side serves probes (`WifiP2pChannel::serve_probes`) and the other runs
+//! them (`WifiP2pChannel::probe`) over a single TCP connection:
+//!
+//! ```text
+//! client -> "P2PP" version:u8
+//! client -> 'P' seq:u32             server -> 'P' seq:u32   (once per ping)
+//! client -> 'U' then chunks         server -> bytes:u64 micros:u64
+//! client -> 'D' millis:u32          server -> chunks
+//! client -> 'Q'
+//! ```
+//!
+//! A chunk is len:u32 followed by that many bytes, and a zero length ends the
+//! stream. All integers are big-endian. Throughput is timed on the receiving
+//! side, from the first chunk to the end marker, so it is not inflated by
+//! socket buffers. The I/O itself needs the `probe` feature.
+
+use std::time::Duration;
+
+use crate::mac::MacAddress;
+
+/// Port `serve_probes` listens on, next to the file transfer port.
+pub const DEFAULT_PORT: u16 = 8989;
+
+/// How much a probe measures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProbeOptions {
+    /// Round trips to time (default 10).
+    pub pings: u32,
+    /// How long to send in each direction (default 2s).
+    pub duration: Duration,
+}
+
+impl Default for ProbeOptions {
+    fn default() -> Self {
+        Self {
+            pings: 10,
+            duration: Duration::from_secs(2),
+        }
+    }
+}
+
+/// What a probe measured on the link to `peer`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LinkReport {
+    pub peer: MacAddress,
+    /// Fastest, mean and slowest round trip over the TCP connection.
+    pub rtt_min: Duration,
+    pub rtt_avg: Duration,
+    pub rtt_max: Duration,
+    /// Sustained throughput from us to the peer, in bit/s.
+    pub upload_bps: u64,
+    /// Sustained throughput from the peer to us, in bit/s.
+    pub download_bps: u64,
+}
+
+impl LinkReport {
+    /// Whether a stream of `bits_per_second` fits in both directions with a
+    /// mean round trip of at most `max_rtt`.
+    pub fn sustains(&self, bits_per_second: u64, max_rtt: Duration) -> bool {
+        self.upload_bps.min(self.download_bps) >= bits_per_second && self.rtt_avg <= max_rtt
+    }
+}
+
+#[cfg(feature = "probe")]
+pub(crate) use io::{run, serve};
+
+#[cfg(feature = "probe")]
+mod io {
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::time::Instant;
+
+    use super::{LinkReport, ProbeOptions};
+    use crate::error::P2pError;
+    use crate::mac::MacAddress;
+
+    const MAGIC: &[u8; 4] = b"P2PP";
+    const VERSION: u8 = 1;
+    const CHUNK_SIZE: usize = 64 * 1024;
+    const PING: u8 = b'P';
+    const UPLOAD: u8 = b'U';
+    const DOWNLOAD: u8 = b'D';
+    const QUIT: u8 = b'Q';
+
+    /// Run `options` against the probe server at the other end of `stream`.
+    pub(crate) async fn run(
+        peer: MacAddress,
+        mut stream: TcpStream,
+        options: ProbeOptions,
+    ) -> Result<LinkReport, P2pError> {
+        if options.pings == 0 {
+            return Err(P2pError::InvalidArgs(
+                "a probe needs at least one ping".to_string(),
+            ));
+        }
+        // Pings are a few bytes each; Nagle would hold them back.
+        stream.set_nodelay(true).map_err(link_error)?;
+        stream.write_all(MAGIC).await.map_err(link_error)?;
+        stream.write_u8(VERSION).await.map_err(link_error)?;
+
+        let mut rtts = Vec::with_capacity(options.pings as usize);
+        for seq in 0..options.pings {
+            let started = Instant::now();
+            stream.write_all(&ping(seq)).await.map_err(link_error)?;
+            if stream.read_u8().await.map_err(link_error)? != PING
+                || stream.read_u32().await.map_err(link_error)? != seq
+            {
+                return Err(probe_error("ping answered out of order"));
+            }
+            rtts.push(started.elapsed());
+        }
+
+        stream.write_u8(UPLOAD).await.map_err(link_error)?;
+        send_chunks(&mut stream, options.duration).await?;
+        let bytes = stream.read_u64().await.map_err(link_error)?;
+        let micros = stream.read_u64().await.map_err(link_error)?;
+        let upload_bps = bits_per_second(bytes, Duration::from_micros(micros));
+
+        let millis = u32::try_from(options.duration.as_millis()).unwrap_or(u32::MAX);
+        stream.write_u8(DOWNLOAD).await.map_err(link_error)?;
+        stream.write_u32(millis).await.map_err(link_error)?;
+        let (bytes, elapsed) = receive_chunks(&mut stream).await?;
+        let download_bps = bits_per_second(bytes, elapsed);
+
+        stream.write_u8(QUIT).await.map_err(link_error)?;
+        let total: Duration = rtts.iter().sum();
+        Ok(LinkReport {
+            peer,
+            rtt_min: rtts.iter().copied().min().unwrap_or_default(),
+            rtt_avg: total / options.pings,
+            rtt_max: rtts.iter().copied().max().unwrap_or_default(),
+            upload_bps,
+            download_bps,
+        })
+    }
+
+    /// Answer probes on `port` until the listener fails. Each connection is
+    /// handled on its own task.
+    pub(crate) async fn serve(port: u16) -> Result<(), P2pError> {
+        let listener = TcpListener::bind((listen_address(), port))
+            .await
+            .map_err(|err| probe_error(&format!("listen on port {port}: {err}")))?;
+        loop {
+            let (stream, _) = listener.accept().await.map_err(link_error)?;
+            tokio::spawn(async move { answer(stream).await });
+        }
+    }
+
+    fn listen_address() -> IpAddr {
+        // Dual-stack on Linux, so link-local peers can reach us as well.
+        if cfg!(all(feature = "ipv6-link-local", target_os = "linux")) {
+            IpAddr::V6(std::net::Ipv6Addr::UNSPECIFIED)
+        } else {
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+        }
+    }
+
+    async fn answer(mut stream: TcpStream) -> Result<(), P2pError> {
+        stream.set_nodelay(true).map_err(link_error)?;
+        let mut magic = [0u8; 4];
+        stream.read_exact(&mut magic).await.map_err(link_error)?;
+        if &magic != MAGIC || stream.read_u8().await.map_err(link_error)? != VERSION {
+            return Err(probe_error("not a probe"));
+        }
+        loop {
+            match stream.read_u8().await.map_err(link_error)? {
+                PING => {
+                    let seq = stream.read_u32().await.map_err(link_error)?;
+                    stream.write_all(&ping(seq)).await.map_err(link_error)?;
+                }
+                UPLOAD => {
+                    let (bytes, elapsed) = receive_chunks(&mut stream).await?;
+                    let micros = u64::try_from(elapsed.as_micros()).unwrap_or(u64::MAX);
+                    stream.write_u64(bytes).await.map_err(link_error)?;
+                    stream.write_u64(micros).await.map_err(link_error)?;
+                }
+                DOWNLOAD => {
+                    let millis = stream.read_u32().await.map_err(link_error)?;
+                    send_chunks(&mut stream, Duration::from_millis(u64::from(millis))).await?;
+                }
+                QUIT => return Ok(()),
+                other => return Err(probe_error(&format!("unknown request {other}"))),
+            }
+        }
+    }
+
+    /// A ping or its answer, in one write so it leaves as one segment.
+    fn ping(seq: u32) -> [u8; 5] {
+        let [a, b, c, d] = seq.to_be_bytes();
+        [PING, a, b, c, d]
+    }
+
+    async fn send_chunks(stream: &mut TcpStream, duration: Duration) -> Result<(), P2pError> {
+        let chunk = vec![0u8; CHUNK_SIZE];
+        let started = Instant::now();
+        while started.elapsed() < duration {
+            stream
+                .write_u32(CHUNK_SIZE as u32)
+                .await
+                .map_err(link_error)?;
+            stream.write_all(&chunk).await.map_err(link_error)?;
+        }
+        stream.write_u32(0).await.map_err(link_error)?;
+        stream.flush().await.map_err(link_error)
+    }
+
+    /// Bytes received after the first chunk up to the end marker, and the time
+    /// that took; the first chunk only starts the clock.
+    async fn receive_chunks(stream: &mut TcpStream) -> Result<(u64, Duration), P2pError> {
+        let mut buf = vec![0u8; CHUNK_SIZE];
+        let mut bytes = 0u64;
+        let mut started: Option<Instant> = None;
+        loop {
+            let len = stream.read_u32().await.map_err(link_error)? as usize;
+            if len == 0 {
+                let elapsed = started.map(|started| started.elapsed());
+                return Ok((bytes, elapsed.unwrap_or_default()));
+            }
+            if len > CHUNK_SIZE {
+                return Err(probe_error(&format!("chunk of {len} bytes")));
+            }
+            stream
+                .read_exact(&mut buf[..len])
+                .await
+                .map_err(link_error)?;
+            match started {
+                Some(_) => bytes += len as u64,
+                None => started = Some(Instant::now()),
+            }
+        }
+    }
+
+    fn bits_per_second(bytes: u64, elapsed: Duration) -> u64 {
+        if elapsed.is_zero() {
+            return 0;
+        }
+        (bytes as f64 * 8.0 / elapsed.as_secs_f64()) as u64
+    }
+
+    fn link_error(err: std::io::Error) -> P2pError {
+        probe_error(&err.to_string())
+    }
+
+    fn probe_error(message: &str) -> P2pError {
+        P2pError::Backend(format!("probe: {message}"))
+    }
+}
@@ -0,0 +1,298 @@
+//! The `wifi_p2p` Python module, built with maturin from pyproject.toml.
+//!
+//! Actions return awaitables for the running asyncio loop, resolved from the
+//! manager's tokio runtime, and `WifiP2pChannel.events()` is an async iterator.
+//! Devices, groups and events cross over as what `json.loads` makes of their
+//! serde form, the same shapes the HTTP API serves. Failures raise
+//! `WifiP2pError`, whose `kind` is the `P2pError` variant name.
+
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyStopAsyncIteration};
+use pyo3::prelude::*;
+use pyo3_async_runtimes::tokio::{future_into_py, get_runtime};
+use serde::Serialize;
+use tokio::sync::oneshot;
+use tokio_stream::StreamExt;
+
+use crate::channel::WifiP2pChannel;
+use crate::config::{ConnectConfig, WpsMethod};
+use crate::error::P2pError;
+use crate::json::event_json;
+use crate::mac::MacAddress;
+use crate::manager::WifiP2pManager;
+use crate::stream::P2pEventStream;
+
+create_exception!(
+    wifi_p2p,
+    WifiP2pError,
+    PyException,
+    "A failed action; `kind` names the error, e.g. \"Timeout\"."
+);
+
+#[pymodule]
+fn wifi_p2p(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyManager>()?;
+    module.add_class::<PyChannel>()?;
+    module.add_class::<PyEventStream>()?;
+    module.add("WifiP2pError", module.py().get_type::<WifiP2pError>())?;
+    Ok(())
+}
+
+/// `WifiP2pManager`, opened with `await WifiP2pManager.open("wlan0")`.
+#[pyclass(name = "WifiP2pManager")]
+struct PyManager {
+    // Taken out by `shutdown`, which needs it across an await.
+    inner: Mutex<Option<WifiP2pManager>>,
+}
+
+#[pymethods]
+impl PyManager {
+    /// Build the platform manager on `interface_name`.
+    #[staticmethod]
+    fn open(py: Python<'_>, interface_name: String) -> PyResult<Bound<'_, PyAny>> {
+        future_into_py(py, async move {
+            let manager = WifiP2pManager::new(&interface_name)
+                .await
+                .map_err(to_py_err)?;
+            Ok(PyManager {
+                inner: Mutex::new(Some(manager)),
+            })
+        })
+    }
+
+    /// Start the command loop and return a channel to it.
+    fn initialize(&self) -> PyResult<PyChannel> {
+        // The manager spawns its tasks onto the ambient runtime, so enter ours first.
+        let _guard = get_runtime().enter();
+        let inner = self.with_manager(|manager| manager.initialize())?;
+        Ok(PyChannel { inner })
+    }
+
+    /// Stop discovery, remove groups and end the command loop; see
+    /// `WifiP2pManager::shutdown`. The manager cannot be used afterwards.
+    fn shutdown<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let manager = lock(&self.inner).take();
+        future_into_py(py, async move {
+            match manager {
+                Some(mut manager) => manager.shutdown().await.map_err(to_py_err),
+                None => Ok(()),
+            }
+        })
+    }
+}
+
+impl PyManager {
+    fn with_manager<T>(&self, f: impl FnOnce(&WifiP2pManager) -> T) -> PyResult<T> {
+        lock(&self.inner)
+            .as_ref()
+            .map(f)
+            .ok_or_else(|| to_py_err(P2pError::ChannelClosed("manager shut down".to_string())))
+    }
+}
+
+impl Drop for PyManager {
+    fn drop(&mut self) {
+        // Dropping the manager spawns its cleanup, which needs the runtime.
+        let _guard = get_runtime().enter();
+        lock(&self.inner).take();
+    }
+}
+
+/// `WifiP2pChannel`; every action returns an awaitable.
+#[pyclass(name = "WifiP2pChannel")]
+struct PyChannel {
+    inner: WifiP2pChannel,
+}
+
+#[pymethods]
+impl PyChannel {
+    fn discover_peers<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let channel = self.inner.clone();
+        action(py, async move { channel.discover_peers().await })
+    }
+
+    fn stop_discovery<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let channel = self.inner.clone();
+        action(py, async move { channel.stop_discovery().await })
+    }
+
+    /// Connect with `method` "pbc", "display", "keypad" or "label"; resolves to
+    /// the PIN the supplicant generated, if any.
+    #[pyo3(signature = (device_address, method = "pbc", pin = None))]
+    fn connect<'py>(
+        &self,
+        py: Python<'py>,
+        device_address: &str,
+        method: &str,
+        pin: Option<String>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let wps_method = match method {
+            "pbc" => WpsMethod::Pbc,
+            "display" => WpsMethod::PinDisplay,
+            "keypad" => WpsMethod::PinKeypad,
+            "label" => WpsMethod::PinLabel,
+            other => {
+                let err = P2pError::InvalidArgs(format!("unknown WPS method {other:?}"));
+                return Err(to_py_err(err));
+            }
+        };
+        let config = ConnectConfig {
+            device_address: parse_mac(device_address)?,
+            wps_method,
+            pin,
+        };
+        let channel = self.inner.clone();
+        action(py, async move { channel.connect_with_config(config).await })
+    }
+
+    fn cancel_connect<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let channel = self.inner.clone();
+        action(py, async move { channel.cancel_connect().await })
+    }
+
+    /// Resolves to a list of device dicts.
+    fn request_peers<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let channel = self.inner.clone();
+        json_action(py, async move { channel.request_peers().await })
+    }
+
+    /// Resolves to a list of group dicts.
+    fn request_groups<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let channel = self.inner.clone();
+        json_action(py, async move { channel.request_groups().await })
+    }
+
+    fn create_group<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let channel = self.inner.clone();
+        action(py, async move { channel.create_group().await })
+    }
+
+    fn invite<'py>(&self, py: Python<'py>, peer: &str) -> PyResult<Bound<'py, PyAny>> {
+        let peer = parse_mac(peer)?;
+        let channel = self.inner.clone();
+        action(py, async move { channel.invite(peer).await })
+    }
+
+    fn reject_peer<'py>(&self, py: Python<'py>, peer: &str) -> PyResult<Bound<'py, PyAny>> {
+        let peer = parse_mac(peer)?;
+        let channel = self.inner.clone();
+        action(py, async move { channel.reject_peer(peer).await })
+    }
+
+    fn remove_group<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let channel = self.inner.clone();
+        action(py, async move { channel.remove_group().await })
+    }
+
+    fn discover_services<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let channel = self.inner.clone();
+        action(py, async move { channel.discover_services().await })
+    }
+
+    /// The manager's peer table as device dicts, without going through the queue.
+    fn peers_snapshot<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        to_json(&self.inner.peers_snapshot())?.into_pyobject(py)
+    }
+
+    /// An async iterator of event dicts, `{"time", "type", "data"}`, from now on.
+    fn events(&self) -> PyEventStream {
+        PyEventStream {
+            inner: Arc::new(tokio::sync::Mutex::new(self.inner.event_stream())),
+        }
+    }
+}
+
+/// `async for event in channel.events()`; ends when the manager stops.
+#[pyclass(name = "EventStream")]
+struct PyEventStream {
+    inner: Arc<tokio::sync::Mutex<P2pEventStream>>,
+}
+
+#[pymethods]
+impl PyEventStream {
+    fn __aiter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __anext__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let stream = Arc::clone(&self.inner);
+        future_into_py(py, async move {
+            match stream.lock().await.next().await {
+                Some(event) => Ok(Json(event_json(&event).to_string())),
+                None => Err(PyStopAsyncIteration::new_err(())),
+            }
+        })
+    }
+}
+
+/// A value handed to Python as `json.loads` of its JSON text.
+struct Json(String);
+
+impl<'py> IntoPyObject<'py> for Json {
+    type Target = PyAny;
+    type Output = Bound<'py, PyAny>;
+    type Error = PyErr;
+
+    fn into_pyobject(self, py: Python<'py>) -> PyResult<Self::Output> {
+        py.import("json")?.call_method1("loads", (self.0,))
+    }
+}
+
+/// Await a queued command's result on the tokio runtime.
+fn action<'py, T, Q>(py: Python<'py>, queued: Q) -> PyResult<Bound<'py, PyAny>>
+where
+    T: for<'a> IntoPyObject<'a> + Send + 'static,
+    Q: Future<Output = Result<oneshot::Receiver<Result<T, P2pError>>, P2pError>> + Send + 'static,
+{
+    future_into_py(py, async move { reply(queued).await.map_err(to_py_err) })
+}
+
+/// Like `action`, for results Python gets as JSON.
+fn json_action<'py, T, Q>(py: Python<'py>, queued: Q) -> PyResult<Bound<'py, PyAny>>
+where
+    T: Serialize + Send + 'static,
+    Q: Future<Output = Result<oneshot::Receiver<Result<T, P2pError>>, P2pError>> + Send + 'static,
+{
+    future_into_py(py, async move {
+        let value = reply(queued).await.map_err(to_py_err)?;
+        to_json(&value)
+    })
+}
+
+async fn reply<T>(
+    queued: impl Future<Output = Result<oneshot::Receiver<Result<T, P2pError>>, P2pError>>,
+) -> Result<T, P2pError> {
+    queued
+        .await?
+        .await
+        .map_err(|_| P2pError::ChannelClosed("manager".to_string()))?
+}
+
+fn to_json<T: Serialize>(value: &T) -> PyResult<Json> {
+    serde_json::to_string(value)
+        .map(Json)
+        .map_err(|err| to_py_err(P2pError::Backend(err.to_string())))
+}
+
+fn parse_mac(address: &str) -> PyResult<MacAddress> {
+    address.parse().map_err(to_py_err)
+}
+
+fn to_py_err(err: P2pError) -> PyErr {
+    Python::with_gil(|py| {
+        let py_err = WifiP2pError::new_err(err.to_string());
+        // A plain attribute on the instance; failing to set it still leaves the message.
+        let _ = py_err.value(py).setattr("kind", err.kind());
+        py_err
+    })
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    // The slot is only swapped whole, so a panicking holder leaves nothing half-done.
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
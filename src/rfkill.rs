@@ -0,0 +1,124 @@
+//! rfkill state of the radio behind an interface.
+//!
+//! A soft-blocked radio (airplane mode, `rfkill block wifi`) makes every P2P
+//! call fail with unrelated-looking supplicant errors, so the manager checks
+//! at startup and fails with `P2pError::RadioBlocked` instead. With the
+//! `rfkill` feature, `unblock_radio` lifts a soft block through `/dev/rfkill`;
+//! a hard block (a switch or key) can only be lifted by hand.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::P2pError;
+
+/// Whether a radio may transmit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RadioState {
+    Unblocked,
+    /// Blocked in software; `unblock_radio` or `rfkill unblock wifi` lifts it.
+    SoftBlocked,
+    /// Blocked by a hardware switch or key.
+    HardBlocked,
+}
+
+/// rfkill state of `interface_name`'s radio, or of every Wi-Fi radio when the
+/// interface cannot be tied to one. `None` when the kernel exposes no switch.
+pub fn radio_state(interface_name: &str) -> Option<RadioState> {
+    let switches = switches(interface_name);
+    if switches.is_empty() {
+        return None;
+    }
+    let blocked = |path: &Path, kind: &str| read_trimmed(&path.join(kind)).as_deref() == Some("1");
+    let state = if switches.iter().any(|path| blocked(path, "hard")) {
+        RadioState::HardBlocked
+    } else if switches.iter().any(|path| blocked(path, "soft")) {
+        RadioState::SoftBlocked
+    } else {
+        RadioState::Unblocked
+    };
+    Some(state)
+}
+
+/// Fail with `P2pError::RadioBlocked` when the radio cannot transmit.
+pub(crate) fn check(interface_name: &str) -> Result<(), P2pError> {
+    match radio_state(interface_name) {
+        Some(RadioState::HardBlocked) => Err(P2pError::RadioBlocked(format!(
+            "{interface_name} is hard-blocked by rfkill"
+        ))),
+        Some(RadioState::SoftBlocked) => Err(P2pError::RadioBlocked(format!(
+            "{interface_name} is soft-blocked by rfkill"
+        ))),
+        Some(RadioState::Unblocked) | None => Ok(()),
+    }
+}
+
+/// Lift a soft block on `interface_name`'s radio. Needs write access to
+/// `/dev/rfkill` (root, or the group udev gives it). A hard block stays and is
+/// reported as `P2pError::RadioBlocked`.
+#[cfg(feature = "rfkill")]
+pub fn unblock_radio(interface_name: &str) -> Result<(), P2pError> {
+    use std::io::Write;
+
+    // struct rfkill_event: idx (u32, native endian), type, op, soft, hard.
+    const RFKILL_TYPE_WLAN: u8 = 1;
+    const RFKILL_OP_CHANGE: u8 = 2;
+
+    let blocked: Vec<u32> = switches(interface_name)
+        .iter()
+        .filter(|path| read_trimmed(&path.join("soft")).as_deref() == Some("1"))
+        .filter_map(|path| read_trimmed(&path.join("index"))?.parse().ok())
+        .collect();
+    if !blocked.is_empty() {
+        let mut device = fs::OpenOptions::new()
+            .write(true)
+            .open("/dev/rfkill")
+            .map_err(rfkill_error)?;
+        for index in blocked {
+            let mut event = [0u8; 8];
+            event[..4].copy_from_slice(&index.to_ne_bytes());
+            event[4] = RFKILL_TYPE_WLAN;
+            event[5] = RFKILL_OP_CHANGE;
+            device.write_all(&event).map_err(rfkill_error)?;
+        }
+    }
+    check(interface_name)
+}
+
+#[cfg(feature = "rfkill")]
+fn rfkill_error(err: std::io::Error) -> P2pError {
+    if err.kind() == std::io::ErrorKind::PermissionDenied {
+        P2pError::PermissionDenied(format!("/dev/rfkill: {err}"))
+    } else {
+        P2pError::Backend(format!("rfkill: {err}"))
+    }
+}
+
+/// The interface's rfkill switches, or every Wi-Fi switch when it has none.
+fn switches(interface_name: &str) -> Vec<PathBuf> {
+    let phy = Path::new("/sys/class/net")
+        .join(interface_name)
+        .join("phy80211");
+    let switches: Vec<_> = fs::read_dir(&phy)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("rfkill"))
+        .map(|entry| entry.path())
+        .collect();
+    if !switches.is_empty() {
+        return switches;
+    }
+    fs::read_dir("/sys/class/rfkill")
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| read_trimmed(&path.join("type")).as_deref() == Some("wlan"))
+        .collect()
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    fs::read_to_string(path)
+        .ok()
+        .map(|text| text.trim().to_string())
+}
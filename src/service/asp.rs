@@ -0,0 +1,165 @@
+//! Wi-Fi P2P Services (P2PS): services advertised through the Application
+//! Service Platform (ASP), as commercial P2PS devices (printers, displays,
+//! Wi-Fi Direct Services send/play/print) offer them.
+//!
+//! An advertiser adds an `AspService` with `WifiP2pChannel::add_local_service`.
+//! A seeker names the services it wants in `DiscoveryOptions::seek`: probe
+//! requests then carry a hash of each name, and only advertisers with a match
+//! answer (ASNE matching). `discover_services` asks found peers for their
+//! advertisements, each reported as `P2pEvent::AspServiceFound`, and
+//! `WifiP2pChannel::asp_provision` opens a session with one of them through
+//! P2PS provision discovery; the group it forms is reported as usual.
+//!
+//! An answer's data holds a count followed by one record per advertisement:
+//!
+//! ```text
+//! adv_id:u32le config_methods:u16le name_len:u8 name status:u8 info_len:u16le info
+//! ```
+
+use crate::device::wps_config;
+use crate::mac::MacAddress;
+
+/// A service we advertise through ASP.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AspService {
+    /// Our identifier for the advertisement, quoted by seekers that provision.
+    pub advertisement_id: u32,
+    /// The name seekers look for, e.g. `org.wi-fi.wfds.print.tx`.
+    pub name: String,
+    /// Free-form text for seekers, e.g. a printer's capabilities.
+    pub info: Option<String>,
+    /// Accept sessions without asking the app. The supplicant only reports
+    /// session requests on its control socket, so it has to be set there.
+    pub auto_accept: bool,
+    /// Whether the service takes new sessions right now.
+    pub available: bool,
+    /// WPS config methods seekers may provision with (see `wps_config`).
+    pub config_methods: u16,
+}
+
+impl AspService {
+    /// An available service taking P2PS (PIN-less) provisioning.
+    pub fn new(advertisement_id: u32, name: impl Into<String>) -> Self {
+        Self {
+            advertisement_id,
+            name: name.into(),
+            info: None,
+            auto_accept: false,
+            available: true,
+            config_methods: wps_config::P2PS,
+        }
+    }
+
+    pub fn info(mut self, info: impl Into<String>) -> Self {
+        self.info = Some(info.into());
+        self
+    }
+
+    pub fn auto_accept(mut self, auto_accept: bool) -> Self {
+        self.auto_accept = auto_accept;
+        self
+    }
+
+    pub fn available(mut self, available: bool) -> Self {
+        self.available = available;
+        self
+    }
+
+    pub fn config_methods(mut self, config_methods: u16) -> Self {
+        self.config_methods = config_methods;
+        self
+    }
+
+    /// What a seeker gets back for this service alone.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn response_data(&self) -> Vec<u8> {
+        let info = self.info.as_deref().unwrap_or_default();
+        let mut data = vec![1];
+        data.extend_from_slice(&self.advertisement_id.to_le_bytes());
+        data.extend_from_slice(&self.config_methods.to_le_bytes());
+        data.push(self.name.len() as u8);
+        data.extend_from_slice(self.name.as_bytes());
+        data.push(u8::from(self.available));
+        data.extend_from_slice(&(info.len() as u16).to_le_bytes());
+        data.extend_from_slice(info.as_bytes());
+        data
+    }
+}
+
+/// A service a peer advertises, from its answer to a service query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AspAdvertisement {
+    pub advertisement_id: u32,
+    pub name: String,
+    pub config_methods: u16,
+    /// Whether the service takes new sessions.
+    pub available: bool,
+    pub info: String,
+}
+
+impl AspAdvertisement {
+    /// The advertisements in an answer's data; `None` if it is truncated.
+    pub fn parse_all(data: &[u8]) -> Option<Vec<Self>> {
+        let (&count, mut rest) = data.split_first()?;
+        let mut advertisements = Vec::with_capacity(usize::from(count));
+        for _ in 0..count {
+            let advertisement_id = u32::from_le_bytes(rest.get(..4)?.try_into().ok()?);
+            let config_methods = u16::from_le_bytes(rest.get(4..6)?.try_into().ok()?);
+            let name_len = usize::from(*rest.get(6)?);
+            let name = rest.get(7..7 + name_len)?;
+            rest = &rest[7 + name_len..];
+            let status = *rest.first()?;
+            let info_len = usize::from(u16::from_le_bytes(rest.get(1..3)?.try_into().ok()?));
+            let info = rest.get(3..3 + info_len)?;
+            rest = &rest[3 + info_len..];
+            advertisements.push(Self {
+                advertisement_id,
+                name: String::from_utf8_lossy(name).into_owned(),
+                config_methods,
+                available: status == 1,
+                info: String::from_utf8_lossy(info).into_owned(),
+            });
+        }
+        Some(advertisements)
+    }
+}
+
+/// A P2PS provision discovery request, opening a session with a peer's
+/// advertised service; see `WifiP2pChannel::asp_provision`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AspProvision {
+    pub peer: MacAddress,
+    /// `AspAdvertisement::advertisement_id` of the service.
+    pub advertisement_id: u32,
+    /// Ours to pick, different for each session we open.
+    pub session_id: u32,
+    /// Handed to the advertiser's application, e.g. what is about to be sent.
+    pub session_info: Option<String>,
+    /// The one WPS config method to provision with; P2PS needs no PIN.
+    pub config_method: u16,
+}
+
+impl AspProvision {
+    pub fn new(peer: MacAddress, advertisement_id: u32, session_id: u32) -> Self {
+        Self {
+            peer,
+            advertisement_id,
+            session_id,
+            session_info: None,
+            config_method: wps_config::P2PS,
+        }
+    }
+
+    pub fn session_info(mut self, info: impl Into<String>) -> Self {
+        self.session_info = Some(info.into());
+        self
+    }
+
+    pub fn config_method(mut self, config_method: u16) -> Self {
+        self.config_method = config_method;
+        self
+    }
+}
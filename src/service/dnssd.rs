@@ -0,0 +1,408 @@
+//! Bonjour records in the form Android and wpa_supplicant exchange them.
+//!
+//! `DnsSdServiceInfo` builds the PTR and TXT records that Android's
+//! `WifiP2pDnsSdServiceInfo.newInstance` advertises, and `DnsSdRecord::parse`
+//! reads either back out of a response TLV. Names are in DNS wire format,
+//! compressed against the "virtual packet" the supplicant matches queries in:
+//! the pointer `c00c` stands for `_tcp.local.`, `c011` for `local.` and `c01c`
+//! for `_udp.local.`, and `c027` in a PTR record's RDATA for the query name.
+//! A record compressed differently would never match an Android peer's query.
+//!
+//! The manager reads every Bonjour TLV of a `P2pEvent::ServicesFound` and
+//! follows it with a `DnsSdServiceAvailable` or `DnsSdTxtRecordAvailable`
+//! event, the answers Android's `setDnsSdResponseListeners` callbacks get.
+
+use std::collections::HashMap;
+
+use super::LocalService;
+use crate::channel::P2pEvent;
+use crate::error::P2pError;
+use crate::mac::MacAddress;
+
+const DNS_TYPE_PTR: u16 = 12;
+const DNS_TYPE_TXT: u16 = 16;
+/// The only Bonjour version Wi-Fi P2P defines.
+const VERSION: u8 = 1;
+
+/// Names the virtual packet holds, by their offset in it.
+const VIRTUAL_PACKET: [(u8, &str); 3] =
+    [(0x0c, "_tcp.local"), (0x11, "local"), (0x1c, "_udp.local")];
+/// Where the query name sits in the virtual packet.
+const QUERY_NAME_OFFSET: u8 = 0x27;
+
+/// A Bonjour service to advertise, e.g. instance `Office printer` of `_ipp._tcp`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DnsSdServiceInfo {
+    pub instance_name: String,
+    /// The service type without the domain, e.g. `_ipp._tcp`.
+    pub service_type: String,
+    /// TXT record entries in the order they are sent, each as `key=value`.
+    pub txt: Vec<(String, String)>,
+}
+
+impl DnsSdServiceInfo {
+    pub fn new(instance_name: impl Into<String>, service_type: impl Into<String>) -> Self {
+        Self {
+            instance_name: instance_name.into(),
+            service_type: service_type.into(),
+            txt: Vec::new(),
+        }
+    }
+
+    pub fn with_txt(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.txt.push((key.into(), value.into()));
+        self
+    }
+
+    /// The PTR and TXT records to pass to `add_local_service` one by one.
+    ///
+    /// Fails with `P2pError::InvalidArgs` when the service type is not
+    /// `_name._tcp` or `_name._udp`, a label is empty or over 63 bytes, or a
+    /// TXT entry has an empty key or is over 255 bytes.
+    pub fn local_services(&self) -> Result<Vec<LocalService>, P2pError> {
+        let service_type = self.service_type.trim_end_matches('.');
+        let service_type = service_type.strip_suffix(".local").unwrap_or(service_type);
+        let type_labels: Vec<&str> = service_type.split('.').collect();
+        if type_labels.len() < 2 || !matches!(type_labels.last(), Some(&("_tcp" | "_udp"))) {
+            return Err(invalid(format!(
+                "service type {:?} is not _name._tcp or _name._udp",
+                self.service_type
+            )));
+        }
+
+        let mut ptr_name = type_labels.clone();
+        ptr_name.push("local");
+        let mut instance = Vec::new();
+        push_label(&mut instance, &self.instance_name)?;
+        let ptr = LocalService::Bonjour {
+            query: query(&ptr_name, DNS_TYPE_PTR)?,
+            // The instance label followed by a pointer to the query name.
+            response: [instance.as_slice(), &[0xc0, QUERY_NAME_OFFSET]].concat(),
+        };
+
+        // Android lowercases the TXT record's name in its queries and
+        // advertisements alike.
+        let instance_name = self.instance_name.to_lowercase();
+        let service_type = service_type.to_lowercase();
+        let mut txt_labels = vec![instance_name.as_str()];
+        txt_labels.extend(service_type.split('.'));
+        txt_labels.push("local");
+        let txt = LocalService::Bonjour {
+            query: query(&txt_labels, DNS_TYPE_TXT)?,
+            response: txt_data(&self.txt)?,
+        };
+        Ok(vec![ptr, txt])
+    }
+}
+
+/// A Bonjour record read back from a response TLV's data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DnsSdRecord {
+    /// `instance_name` offers `service_type`, e.g. `_ipp._tcp`.
+    Ptr {
+        instance_name: String,
+        service_type: String,
+    },
+    /// The TXT record of `instance_name` of `service_type`. Android peers send
+    /// the instance name lowercased here.
+    Txt {
+        instance_name: String,
+        service_type: String,
+        txt: Vec<(String, String)>,
+    },
+}
+
+impl DnsSdRecord {
+    /// Read a record from Bonjour response data; `None` for other record types
+    /// and for names outside `local.` or pointing outside the virtual packet.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let mut pos = 0;
+        let name = read_name(data, &mut pos, &[])?;
+        let header = data.get(pos..pos + 3)?;
+        let record_type = u16::from_be_bytes([header[0], header[1]]);
+        pos += 3;
+        let (local, labels) = name.split_last()?;
+        if !local.eq_ignore_ascii_case("local") {
+            return None;
+        }
+        match record_type {
+            DNS_TYPE_PTR => {
+                let target = read_name(data, &mut pos, &name)?;
+                if target.len() <= name.len() {
+                    return None;
+                }
+                Some(Self::Ptr {
+                    instance_name: target[0].clone(),
+                    service_type: labels.join("."),
+                })
+            }
+            DNS_TYPE_TXT => {
+                let (instance_name, service_type) = labels.split_first()?;
+                if service_type.is_empty() {
+                    return None;
+                }
+                Some(Self::Txt {
+                    instance_name: instance_name.clone(),
+                    service_type: service_type.join("."),
+                    txt: parse_txt(&data[pos..])?,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A peer's Bonjour service instance, as `P2pEvent::DnsSdServiceAvailable`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DnsSdServiceResponse {
+    pub instance: String,
+    /// The service type with its domain, e.g. `_ipp._tcp.local.`.
+    pub registration_type: String,
+    /// The peer that answered.
+    pub source: MacAddress,
+}
+
+/// A peer's Bonjour TXT record, as `P2pEvent::DnsSdTxtRecordAvailable`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DnsSdTxtRecord {
+    /// The instance's full name, e.g. `myprinter._ipp._tcp.local.`.
+    pub full_domain: String,
+    pub txt_map: HashMap<String, String>,
+    /// The peer that answered.
+    pub source: MacAddress,
+}
+
+impl DnsSdRecord {
+    /// The event reporting this record, answered by `source`.
+    pub(crate) fn into_event(self, source: MacAddress) -> P2pEvent {
+        match self {
+            Self::Ptr {
+                instance_name,
+                service_type,
+            } => P2pEvent::DnsSdServiceAvailable(DnsSdServiceResponse {
+                instance: instance_name,
+                registration_type: format!("{service_type}.local."),
+                source,
+            }),
+            Self::Txt {
+                instance_name,
+                service_type,
+                txt,
+            } => P2pEvent::DnsSdTxtRecordAvailable(DnsSdTxtRecord {
+                full_domain: format!("{instance_name}.{service_type}.local."),
+                txt_map: txt.into_iter().collect(),
+                source,
+            }),
+        }
+    }
+}
+
+/// A name, record type and version, as the supplicant stores a Bonjour query.
+fn query(labels: &[&str], record_type: u16) -> Result<Vec<u8>, P2pError> {
+    let mut query = Vec::new();
+    for (i, label) in labels.iter().enumerate() {
+        let rest = labels[i..].join(".");
+        if let Some((offset, _)) = VIRTUAL_PACKET.iter().find(|(_, name)| *name == rest) {
+            query.extend_from_slice(&[0xc0, *offset]);
+            break;
+        }
+        push_label(&mut query, label)?;
+        if i == labels.len() - 1 {
+            query.push(0);
+        }
+    }
+    query.extend_from_slice(&record_type.to_be_bytes());
+    query.push(VERSION);
+    Ok(query)
+}
+
+fn push_label(wire: &mut Vec<u8>, label: &str) -> Result<(), P2pError> {
+    if label.is_empty() || label.len() > 63 {
+        return Err(invalid(format!(
+            "DNS label {label:?} must be 1 to 63 bytes"
+        )));
+    }
+    wire.push(label.len() as u8);
+    wire.extend_from_slice(label.as_bytes());
+    Ok(())
+}
+
+fn txt_data(txt: &[(String, String)]) -> Result<Vec<u8>, P2pError> {
+    if txt.is_empty() {
+        // An empty TXT record still holds one empty string.
+        return Ok(vec![0]);
+    }
+    let mut data = Vec::new();
+    for (key, value) in txt {
+        let entry = format!("{key}={value}");
+        if key.is_empty() || key.contains('=') || entry.len() > 255 {
+            return Err(invalid(format!("TXT entry {entry:?}")));
+        }
+        data.push(entry.len() as u8);
+        data.extend_from_slice(entry.as_bytes());
+    }
+    Ok(data)
+}
+
+/// Read a name at `pos`, resolving pointers into the virtual packet, whose
+/// query name is `query`.
+fn read_name(data: &[u8], pos: &mut usize, query: &[String]) -> Option<Vec<String>> {
+    let mut labels = Vec::new();
+    loop {
+        let len = *data.get(*pos)?;
+        *pos += 1;
+        match len {
+            0 => return Some(labels),
+            0xc0.. => {
+                let offset = *data.get(*pos)?;
+                *pos += 1;
+                if offset == QUERY_NAME_OFFSET && !query.is_empty() {
+                    labels.extend_from_slice(query);
+                } else {
+                    let (_, name) = VIRTUAL_PACKET.iter().find(|(at, _)| *at == offset)?;
+                    labels.extend(name.split('.').map(str::to_string));
+                }
+                return Some(labels);
+            }
+            1..=63 => {
+                let label = data.get(*pos..*pos + usize::from(len))?;
+                *pos += usize::from(len);
+                labels.push(String::from_utf8_lossy(label).into_owned());
+            }
+            _ => return None,
+        }
+    }
+}
+
+/// Split TXT RDATA into entries; one without `=` gets an empty value.
+fn parse_txt(mut data: &[u8]) -> Option<Vec<(String, String)>> {
+    let mut txt = Vec::new();
+    while let [len, rest @ ..] = data {
+        let entry = rest.get(..usize::from(*len))?;
+        data = &rest[usize::from(*len)..];
+        if entry.is_empty() {
+            continue;
+        }
+        let entry = String::from_utf8_lossy(entry);
+        let (key, value) = entry.split_once('=').unwrap_or((&entry, ""));
+        txt.push((key.to_string(), value.to_string()));
+    }
+    Some(txt)
+}
+
+fn invalid(reason: String) -> P2pError {
+    P2pError::InvalidArgs(format!("Bonjour service: {reason}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bonjour(service: &LocalService) -> (&[u8], &[u8]) {
+        match service {
+            LocalService::Bonjour { query, response } => (query, response),
+            other => panic!("not a Bonjour record: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn records_match_android() {
+        let info = DnsSdServiceInfo::new("MyPrinter", "_ipp._tcp").with_txt("txtvers", "1");
+        let services = info.local_services().unwrap();
+        let (query, response) = bonjour(&services[0]);
+        // What WifiP2pDnsSdServiceInfo.newInstance advertises for the same service.
+        assert_eq!(query, b"\x04_ipp\xc0\x0c\x00\x0c\x01");
+        assert_eq!(response, b"\x09MyPrinter\xc0\x27");
+        let (query, response) = bonjour(&services[1]);
+        assert_eq!(query, b"\x09myprinter\x04_ipp\xc0\x0c\x00\x10\x01");
+        assert_eq!(response, b"\x09txtvers=1");
+    }
+
+    #[test]
+    fn records_parse_back() {
+        let info = DnsSdServiceInfo::new("Speaker", "_raop._udp.local.")
+            .with_txt("a", "1")
+            .with_txt("flag", "");
+        let services = info.local_services().unwrap();
+        let records: Vec<DnsSdRecord> = services
+            .iter()
+            .map(|service| {
+                let (query, response) = bonjour(service);
+                DnsSdRecord::parse(&[query, response].concat()).unwrap()
+            })
+            .collect();
+        assert_eq!(
+            records,
+            [
+                DnsSdRecord::Ptr {
+                    instance_name: "Speaker".to_string(),
+                    service_type: "_raop._udp".to_string(),
+                },
+                DnsSdRecord::Txt {
+                    instance_name: "speaker".to_string(),
+                    service_type: "_raop._udp".to_string(),
+                    txt: vec![
+                        ("a".to_string(), "1".to_string()),
+                        ("flag".to_string(), String::new()),
+                    ],
+                },
+            ]
+        );
+        let P2pEvent::DnsSdServiceAvailable(response) = records[0]
+            .clone()
+            .into_event(MacAddress::new([2, 0, 0, 0, 0, 1]))
+        else {
+            panic!("PTR record reported as another event");
+        };
+        assert_eq!(response.registration_type, "_raop._udp.local.");
+    }
+
+    #[test]
+    fn empty_txt_record_holds_one_empty_string() {
+        let services = DnsSdServiceInfo::new("x", "_http._tcp")
+            .local_services()
+            .unwrap();
+        let (query, response) = bonjour(&services[1]);
+        assert_eq!(response, [0]);
+        let Some(DnsSdRecord::Txt { txt, .. }) = DnsSdRecord::parse(&[query, response].concat())
+        else {
+            panic!("TXT record not parsed");
+        };
+        assert!(txt.is_empty());
+    }
+
+    #[test]
+    fn invalid_services_are_rejected() {
+        for info in [
+            DnsSdServiceInfo::new("x", "_ipp"),
+            DnsSdServiceInfo::new("x", "_ipp._sctp"),
+            DnsSdServiceInfo::new("", "_ipp._tcp"),
+            DnsSdServiceInfo::new("x".repeat(64), "_ipp._tcp"),
+            DnsSdServiceInfo::new("x", "_ipp._tcp").with_txt("", "1"),
+            DnsSdServiceInfo::new("x", "_ipp._tcp").with_txt("a=b", "1"),
+            DnsSdServiceInfo::new("x", "_ipp._tcp").with_txt("k", "v".repeat(254)),
+        ] {
+            assert!(info.local_services().is_err(), "{info:?} accepted");
+        }
+    }
+
+    #[test]
+    fn foreign_or_truncated_records_are_ignored() {
+        let ptr = b"\x04_ipp\xc0\x0c\x00\x0c\x01\x09MyPrinter\xc0\x27";
+        assert!(DnsSdRecord::parse(ptr).is_some());
+        for len in 0..ptr.len() {
+            assert!(
+                DnsSdRecord::parse(&ptr[..len]).is_none(),
+                "{len} bytes parsed"
+            );
+        }
+        // An A record, a name outside `local.` and a pointer outside the virtual packet.
+        assert!(DnsSdRecord::parse(b"\x04_ipp\xc0\x0c\x00\x01\x01\xc0\xa8\x31\x01").is_none());
+        assert!(DnsSdRecord::parse(b"\x04_ipp\x03com\x00\x00\x0c\x01\x01x\xc0\x27").is_none());
+        assert!(DnsSdRecord::parse(b"\x04_ipp\xc0\x20\x00\x0c\x01\x01x\xc0\x27").is_none());
+    }
+}
@@ -0,0 +1,280 @@
+//! Service discovery: what peers offer, learned before connecting to them.
+//!
+//! Services we advertise with `WifiP2pChannel::add_local_service` are answered
+//! by the supplicant itself whenever a peer queries us. `discover_services`
+//! queries the peers that discovery finds, so it only makes progress while
+//! discovery runs; each answer arrives as `P2pEvent::ServicesFound`, holding one
+//! TLV per protocol:
+//!
+//! ```text
+//! length:u16le protocol:u8 transaction_id:u8 status:u8 data   (Wi-Fi P2P 6.1.3)
+//! ```
+//!
+//! `data` is the protocol's own response: for Bonjour the advertised query
+//! followed by its RDATA, for UPnP a version byte followed by the service
+//! strings.
+//!
+//! Rather than writing the bytes by hand, build the records with
+//! `DnsSdServiceInfo` and `UpnpServiceInfo`, and read answers with
+//! `ServiceTlv::dns_sd` and `ServiceTlv::upnp`. P2PS services are covered in
+//! `asp`.
+
+pub mod asp;
+pub mod dnssd;
+pub mod upnp;
+
+pub use asp::{AspAdvertisement, AspProvision, AspService};
+pub use dnssd::{DnsSdRecord, DnsSdServiceInfo, DnsSdServiceResponse, DnsSdTxtRecord};
+pub use upnp::{UPNP_VERSION, UpnpResponse, UpnpServiceInfo};
+
+use crate::mac::MacAddress;
+
+/// A service we answer queries for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LocalService {
+    /// A Bonjour record: `query` is the DNS name in wire format followed by the
+    /// record type and a version byte, `response` the record's RDATA.
+    Bonjour { query: Vec<u8>, response: Vec<u8> },
+    /// A UPnP service, e.g.
+    /// `uuid:6859dede-8574-59ab-9332-123456789012::urn:schemas-upnp-org:service:ContentDirectory:2`.
+    Upnp { version: u8, service: String },
+    /// A P2PS service, advertised through ASP.
+    Asp(AspService),
+}
+
+impl LocalService {
+    pub fn protocol(&self) -> ServiceProtocol {
+        match self {
+            Self::Bonjour { .. } => ServiceProtocol::Bonjour,
+            Self::Upnp { .. } => ServiceProtocol::Upnp,
+            Self::Asp(_) => ServiceProtocol::Asp,
+        }
+    }
+
+    /// What a peer gets back for this service in a response TLV.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn response_data(&self) -> Vec<u8> {
+        match self {
+            Self::Bonjour { query, response } => [query.as_slice(), response].concat(),
+            Self::Upnp { version, service } => {
+                let mut data = vec![*version];
+                data.extend_from_slice(service.as_bytes());
+                data
+            }
+            Self::Asp(service) => service.response_data(),
+        }
+    }
+}
+
+/// The service protocol a TLV is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ServiceProtocol {
+    /// Every protocol; only used in queries.
+    All,
+    Bonjour,
+    Upnp,
+    WsDiscovery,
+    WifiDisplay,
+    /// P2PS advertisements (ASP).
+    Asp,
+    VendorSpecific,
+    Other(u8),
+}
+
+impl ServiceProtocol {
+    pub fn from_code(code: u8) -> Self {
+        match code {
+            0 => Self::All,
+            1 => Self::Bonjour,
+            2 => Self::Upnp,
+            3 => Self::WsDiscovery,
+            4 => Self::WifiDisplay,
+            11 => Self::Asp,
+            255 => Self::VendorSpecific,
+            code => Self::Other(code),
+        }
+    }
+
+    pub fn code(self) -> u8 {
+        match self {
+            Self::All => 0,
+            Self::Bonjour => 1,
+            Self::Upnp => 2,
+            Self::WsDiscovery => 3,
+            Self::WifiDisplay => 4,
+            Self::Asp => 11,
+            Self::VendorSpecific => 255,
+            Self::Other(code) => code,
+        }
+    }
+}
+
+/// One protocol's part of a service discovery response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ServiceTlv {
+    pub protocol: ServiceProtocol,
+    pub transaction_id: u8,
+    /// 0 on success; 1 when the peer does not support the protocol, 2 when it
+    /// has nothing matching the query, 3 for a malformed query.
+    pub status: u8,
+    pub data: Vec<u8>,
+}
+
+impl ServiceTlv {
+    pub fn is_success(&self) -> bool {
+        self.status == 0
+    }
+
+    /// The Bonjour record in a successful Bonjour TLV.
+    pub fn dns_sd(&self) -> Option<DnsSdRecord> {
+        if self.protocol != ServiceProtocol::Bonjour || !self.is_success() {
+            return None;
+        }
+        DnsSdRecord::parse(&self.data)
+    }
+
+    /// The service names in a successful UPnP TLV.
+    pub fn upnp(&self) -> Option<UpnpResponse> {
+        if self.protocol != ServiceProtocol::Upnp || !self.is_success() {
+            return None;
+        }
+        UpnpResponse::parse(&self.data)
+    }
+
+    /// The advertisements in a successful P2PS TLV.
+    pub fn asp(&self) -> Option<Vec<AspAdvertisement>> {
+        if self.protocol != ServiceProtocol::Asp || !self.is_success() {
+            return None;
+        }
+        AspAdvertisement::parse_all(&self.data)
+    }
+}
+
+/// A peer's answer to a service discovery query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ServiceResponse {
+    pub peer: MacAddress,
+    /// Bumped by the peer whenever its services change.
+    pub update_indicator: u16,
+    pub tlvs: Vec<ServiceTlv>,
+}
+
+impl ServiceResponse {
+    /// Split the TLVs of a raw response; a truncated last TLV is dropped.
+    pub fn parse(peer: MacAddress, update_indicator: u16, mut tlvs: &[u8]) -> Self {
+        let mut parsed = Vec::new();
+        while tlvs.len() >= 2 {
+            let len = usize::from(u16::from_le_bytes([tlvs[0], tlvs[1]]));
+            let Some(tlv) = tlvs.get(2..2 + len) else {
+                break;
+            };
+            // The length covers protocol, transaction id and status as well.
+            if let [protocol, transaction_id, status, data @ ..] = tlv {
+                parsed.push(ServiceTlv {
+                    protocol: ServiceProtocol::from_code(*protocol),
+                    transaction_id: *transaction_id,
+                    status: *status,
+                    data: data.to_vec(),
+                });
+            }
+            tlvs = &tlvs[2 + len..];
+        }
+        Self {
+            peer,
+            update_indicator,
+            tlvs: parsed,
+        }
+    }
+
+    /// The raw TLVs, as a peer sends them.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for tlv in &self.tlvs {
+            let len = (3 + tlv.data.len()) as u16;
+            bytes.extend_from_slice(&len.to_le_bytes());
+            bytes.extend_from_slice(&[tlv.protocol.code(), tlv.transaction_id, tlv.status]);
+            bytes.extend_from_slice(&tlv.data);
+        }
+        bytes
+    }
+}
+
+/// A query TLV asking for every service of every protocol.
+#[cfg(target_os = "linux")]
+pub(crate) const QUERY_ALL: [u8; 4] = [2, 0, 0, 1];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PEER: MacAddress = MacAddress::new([2, 0, 0, 0, 0, 1]);
+
+    fn response() -> ServiceResponse {
+        ServiceResponse {
+            peer: PEER,
+            update_indicator: 7,
+            tlvs: vec![
+                ServiceTlv {
+                    protocol: ServiceProtocol::Bonjour,
+                    transaction_id: 1,
+                    status: 0,
+                    data: b"\x04_ipp\xc0\x0c\x00\x0c\x01\x09MyPrinter\xc0\x27".to_vec(),
+                },
+                ServiceTlv {
+                    protocol: ServiceProtocol::Upnp,
+                    transaction_id: 1,
+                    status: 2,
+                    data: Vec::new(),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn tlvs_round_trip() {
+        let sent = response();
+        let bytes = sent.to_bytes();
+        assert_eq!(&bytes[..5], [25, 0, 1, 1, 0]);
+        assert_eq!(ServiceResponse::parse(PEER, 7, &bytes), sent);
+    }
+
+    #[test]
+    fn truncated_and_short_tlvs_are_dropped() {
+        let bytes = response().to_bytes();
+        let parsed = ServiceResponse::parse(PEER, 7, &bytes[..bytes.len() - 1]);
+        assert_eq!(parsed.tlvs, response().tlvs[..1]);
+        // A length too short for the protocol, transaction id and status.
+        let mut short = vec![2, 0, 1, 1];
+        short.extend_from_slice(&bytes);
+        assert_eq!(
+            ServiceResponse::parse(PEER, 7, &short).tlvs,
+            response().tlvs
+        );
+    }
+
+    #[test]
+    fn records_are_only_read_from_successful_tlvs() {
+        let tlvs = response().tlvs;
+        assert_eq!(
+            tlvs[0].dns_sd(),
+            Some(DnsSdRecord::Ptr {
+                instance_name: "MyPrinter".to_string(),
+                service_type: "_ipp._tcp".to_string(),
+            })
+        );
+        assert_eq!(tlvs[0].upnp(), None);
+        assert_eq!(tlvs[1].upnp(), None);
+    }
+
+    #[test]
+    fn protocol_codes_round_trip() {
+        for code in 0..=u8::MAX {
+            assert_eq!(ServiceProtocol::from_code(code).code(), code);
+        }
+        assert_eq!(ServiceProtocol::from_code(11), ServiceProtocol::Asp);
+    }
+}
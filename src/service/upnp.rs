@@ -0,0 +1,172 @@
+//! UPnP services in the form Android and wpa_supplicant exchange them.
+//!
+//! A UPnP device is advertised as one service string per unique service name
+//! (USN), as Android's `WifiP2pUpnpServiceInfo.newInstance` does: `uuid:<uuid>`,
+//! `uuid:<uuid>::upnp:rootdevice`, `uuid:<uuid>::<device type>` and
+//! `uuid:<uuid>::<service type>` for each service. A peer's answer is a version
+//! byte followed by the matching USNs, separated by commas.
+
+use super::LocalService;
+use crate::error::P2pError;
+
+/// UPnP 1.0, the version Android advertises and queries for.
+pub const UPNP_VERSION: u8 = 0x10;
+
+/// A UPnP device to advertise, e.g. a `urn:schemas-upnp-org:device:MediaServer:1`
+/// offering `urn:schemas-upnp-org:service:ContentDirectory:1`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UpnpServiceInfo {
+    /// The device UUID, e.g. `6859dede-8574-59ab-9332-123456789012`.
+    pub uuid: String,
+    pub device_type: String,
+    pub services: Vec<String>,
+}
+
+impl UpnpServiceInfo {
+    pub fn new(uuid: impl Into<String>, device_type: impl Into<String>) -> Self {
+        Self {
+            uuid: uuid.into(),
+            device_type: device_type.into(),
+            services: Vec::new(),
+        }
+    }
+
+    pub fn with_service(mut self, service_type: impl Into<String>) -> Self {
+        self.services.push(service_type.into());
+        self
+    }
+
+    /// The service strings to pass to `add_local_service` one by one.
+    ///
+    /// Fails with `P2pError::InvalidArgs` when the UUID is not in the
+    /// `8-4-4-4-12` hex form or the device type is empty.
+    pub fn local_services(&self) -> Result<Vec<LocalService>, P2pError> {
+        if !is_uuid(&self.uuid) {
+            return Err(P2pError::InvalidArgs(format!(
+                "UPnP service: {:?} is not a UUID",
+                self.uuid
+            )));
+        }
+        if self.device_type.is_empty() {
+            return Err(P2pError::InvalidArgs(
+                "UPnP service: empty device type".to_string(),
+            ));
+        }
+        let uuid = format!("uuid:{}", self.uuid);
+        let services = [uuid.clone(), format!("{uuid}::upnp:rootdevice")]
+            .into_iter()
+            .chain(
+                std::iter::once(&self.device_type)
+                    .chain(&self.services)
+                    .map(|target| format!("{uuid}::{target}")),
+            )
+            .map(|service| LocalService::Upnp {
+                version: UPNP_VERSION,
+                service,
+            })
+            .collect();
+        Ok(services)
+    }
+}
+
+/// A peer's UPnP answer read back from a response TLV's data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UpnpResponse {
+    pub version: u8,
+    /// The unique service names, e.g. `uuid:...::upnp:rootdevice`.
+    pub services: Vec<String>,
+}
+
+impl UpnpResponse {
+    /// `None` when `data` is empty.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let (version, names) = data.split_first()?;
+        let services = String::from_utf8_lossy(names)
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect();
+        Some(Self {
+            version: *version,
+            services,
+        })
+    }
+}
+
+fn is_uuid(uuid: &str) -> bool {
+    let groups: Vec<&str> = uuid.split('-').collect();
+    groups.len() == 5
+        && groups
+            .iter()
+            .zip([8, 4, 4, 4, 12])
+            .all(|(group, len)| group.len() == len && group.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UUID: &str = "6859dede-8574-59ab-9332-123456789012";
+
+    #[test]
+    fn one_service_string_per_usn() {
+        let info = UpnpServiceInfo::new(UUID, "urn:schemas-upnp-org:device:MediaServer:1")
+            .with_service("urn:schemas-upnp-org:service:ContentDirectory:1");
+        let services: Vec<String> = info
+            .local_services()
+            .unwrap()
+            .into_iter()
+            .map(|service| match service {
+                LocalService::Upnp { version, service } => {
+                    assert_eq!(version, UPNP_VERSION);
+                    service
+                }
+                other => panic!("not a UPnP service: {other:?}"),
+            })
+            .collect();
+        assert_eq!(
+            services,
+            [
+                format!("uuid:{UUID}"),
+                format!("uuid:{UUID}::upnp:rootdevice"),
+                format!("uuid:{UUID}::urn:schemas-upnp-org:device:MediaServer:1"),
+                format!("uuid:{UUID}::urn:schemas-upnp-org:service:ContentDirectory:1"),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_devices_are_rejected() {
+        for info in [
+            UpnpServiceInfo::new("6859dede-8574-59ab-9332", "device"),
+            UpnpServiceInfo::new("6859dede-8574-59ab-9332-12345678901g", "device"),
+            UpnpServiceInfo::new(format!("uuid:{UUID}"), "device"),
+            UpnpServiceInfo::new(UUID, ""),
+        ] {
+            assert!(info.local_services().is_err(), "{info:?} accepted");
+        }
+    }
+
+    #[test]
+    fn responses_split_on_commas() {
+        let data = format!("\x10uuid:{UUID}, uuid:{UUID}::upnp:rootdevice,");
+        assert_eq!(
+            UpnpResponse::parse(data.as_bytes()),
+            Some(UpnpResponse {
+                version: UPNP_VERSION,
+                services: vec![
+                    format!("uuid:{UUID}"),
+                    format!("uuid:{UUID}::upnp:rootdevice"),
+                ],
+            })
+        );
+        assert_eq!(
+            UpnpResponse::parse(&[UPNP_VERSION]).unwrap().services.len(),
+            0
+        );
+        assert_eq!(UpnpResponse::parse(&[]), None);
+    }
+}
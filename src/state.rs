@@ -0,0 +1,36 @@
+//! Whether discovery runs, whether we are discoverable and whether P2P is usable
+//! at all, the answers to Android's `requestDiscoveryState`, `getListenState`
+//! and `requestP2pState`, for UI toggles.
+//!
+//! `WifiP2pChannel::discovery_state` reports what the manager tracks from its
+//! own `discover_peers`/`stop_discovery` calls and the supplicant's
+//! `FindStopped` signal. `WifiP2pChannel::listen_state` does the same for
+//! `start_listening`/`stop_listening` and listen periods the platform ends. `WifiP2pChannel::p2p_state` asks the platform: P2P is
+//! enabled while the backend is attached to the interface, answers a ping and,
+//! on Linux, the radio is not blocked by rfkill.
+
+/// From `WifiP2pChannel::discovery_state`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DiscoveryState {
+    Stopped,
+    Started,
+}
+
+/// From `WifiP2pChannel::listen_state` and `P2pEvent::ListenStateChanged`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ListenState {
+    Stopped,
+    /// We answer probe requests, so scanning peers can find us.
+    Started,
+}
+
+/// From `WifiP2pChannel::p2p_state`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum P2pState {
+    /// The interface is gone, the supplicant does not answer or the radio is off.
+    Disabled,
+    Enabled,
+}
@@ -0,0 +1,159 @@
+//! Link quality of connected peers.
+//!
+//! With `WifiP2pManagerBuilder::link_stats_interval` the manager asks the backend
+//! for the stations on every group interface at that interval and reports each
+//! one as `P2pEvent::LinkStats`. On Linux the numbers come from nl80211 station
+//! info (what `iw dev <ifname> station dump` prints), which needs the `stats`
+//! feature; as client the only station is the group owner.
+//!
+//! `WifiP2pChannel::watch_link_quality` checks those samples for one peer
+//! against `LinkThresholds` and reports crossings as `P2pEvent::LinkDegraded`
+//! and `P2pEvent::LinkRecovered`.
+
+use crate::channel::P2pEvent;
+use crate::mac::MacAddress;
+
+/// Frames that must have been sent between two samples before their retry rate
+/// counts; an idle link says nothing about its quality.
+const MIN_RETRY_SAMPLE_FRAMES: u32 = 20;
+
+/// Signal and throughput of the link to one peer, as last measured by the driver.
+/// Fields the driver does not report are `None`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LinkStats {
+    /// The peer's P2P device address, or its interface address when the two
+    /// cannot be matched up.
+    pub peer: MacAddress,
+    /// Group interface the peer is reached over.
+    pub interface: String,
+    /// Signal strength of frames received from the peer, in dBm.
+    pub rssi: Option<i32>,
+    /// Bitrate of the last frame sent to the peer, in kbit/s.
+    pub tx_rate: Option<u32>,
+    /// Bitrate of the last frame received from the peer, in kbit/s.
+    pub rx_rate: Option<u32>,
+    /// Frames to the peer that were never acknowledged since it associated.
+    pub tx_failed: Option<u32>,
+    /// Frames sent to the peer since it associated.
+    pub tx_packets: Option<u32>,
+    /// Retransmissions to the peer since it associated.
+    pub tx_retries: Option<u32>,
+}
+
+/// When `WifiP2pChannel::watch_link_quality` calls a link degraded. A link
+/// recovers once every measure is back past its limit by the hysteresis, so a
+/// value hovering around a limit does not flap.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LinkThresholds {
+    /// Degraded below this signal strength, in dBm (default -75).
+    pub min_rssi: Option<i32>,
+    /// Degraded above this many retransmissions per frame sent between two
+    /// samples (default 0.5).
+    pub max_retry_rate: Option<f32>,
+    /// How far above `min_rssi` the signal must climb to recover, in dB (default 5).
+    pub rssi_hysteresis: i32,
+    /// How far below `max_retry_rate` the rate must fall to recover (default 0.1).
+    pub retry_rate_hysteresis: f32,
+}
+
+impl Default for LinkThresholds {
+    fn default() -> Self {
+        Self {
+            min_rssi: Some(-75),
+            max_retry_rate: Some(0.5),
+            rssi_hysteresis: 5,
+            retry_rate_hysteresis: 0.1,
+        }
+    }
+}
+
+/// Which threshold a degraded link crossed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LinkIssue {
+    /// The signal fell below `min_rssi`.
+    WeakSignal { rssi: i32 },
+    /// Retransmissions per frame rose above `max_retry_rate`.
+    Retries { rate: f32 },
+}
+
+/// Threshold state for one watched peer, fed every `LinkStats` sample for it.
+#[derive(Debug)]
+pub(crate) struct LinkWatch {
+    thresholds: LinkThresholds,
+    degraded: bool,
+    // Counters at the last sample the retry rate was taken from.
+    counters: Option<(u32, u32)>,
+}
+
+impl LinkWatch {
+    pub(crate) fn new(thresholds: LinkThresholds) -> Self {
+        Self {
+            thresholds,
+            degraded: false,
+            counters: None,
+        }
+    }
+
+    /// The event to report for `stats`, if it crosses a threshold.
+    pub(crate) fn sample(&mut self, stats: &LinkStats) -> Option<P2pEvent> {
+        let thresholds = self.thresholds;
+        let retry_rate = self.retry_rate(stats);
+        let weak = |margin: i32| {
+            thresholds
+                .min_rssi
+                .zip(stats.rssi)
+                .is_some_and(|(min, rssi)| rssi < min + margin)
+        };
+        let retrying = |margin: f32| {
+            thresholds
+                .max_retry_rate
+                .zip(retry_rate)
+                .is_some_and(|(max, rate)| rate > max - margin)
+        };
+        if !self.degraded {
+            let issue = if weak(0) {
+                LinkIssue::WeakSignal { rssi: stats.rssi? }
+            } else if retrying(0.0) {
+                LinkIssue::Retries { rate: retry_rate? }
+            } else {
+                return None;
+            };
+            self.degraded = true;
+            return Some(P2pEvent::LinkDegraded {
+                peer: stats.peer,
+                issue,
+            });
+        }
+        if weak(thresholds.rssi_hysteresis) || retrying(thresholds.retry_rate_hysteresis) {
+            return None;
+        }
+        self.degraded = false;
+        Some(P2pEvent::LinkRecovered { peer: stats.peer })
+    }
+
+    /// Retransmissions per frame since the last sample that had enough traffic.
+    fn retry_rate(&mut self, stats: &LinkStats) -> Option<f32> {
+        let (packets, retries) = stats.tx_packets.zip(stats.tx_retries)?;
+        let Some((last_packets, last_retries)) = self.counters else {
+            self.counters = Some((packets, retries));
+            return None;
+        };
+        // Counters restart when the peer reassociates.
+        if packets < last_packets || retries < last_retries {
+            self.counters = Some((packets, retries));
+            return None;
+        }
+        let sent = packets - last_packets;
+        if sent < MIN_RETRY_SAMPLE_FRAMES {
+            return None;
+        }
+        self.counters = Some((packets, retries));
+        Some((retries - last_retries) as f32 / sent as f32)
+    }
+}
+
+#[cfg(all(feature = "stats", target_os = "linux"))]
+pub(crate) mod nl80211;
@@ -0,0 +1,113 @@
+//! Station info over nl80211 (generic netlink).
+//!
+//! Resolves the nl80211 family through the generic netlink controller, then
+//! dumps `NL80211_CMD_GET_STATION` for one interface. The socket is blocking;
+//! the kernel answers both requests right away.
+
+use std::ffi::CString;
+use std::io;
+
+use super::LinkStats;
+use crate::mac::MacAddress;
+use crate::net::netlink::{self, attributes, exchange, family_id, generic_message, u16_at, u32_at};
+
+const NL80211_CMD_GET_STATION: u8 = 17;
+const NL80211_ATTR_IFINDEX: u16 = 3;
+const NL80211_ATTR_MAC: u16 = 6;
+const NL80211_ATTR_STA_INFO: u16 = 21;
+const NL80211_STA_INFO_SIGNAL: u16 = 7;
+const NL80211_STA_INFO_TX_BITRATE: u16 = 8;
+const NL80211_STA_INFO_TX_PACKETS: u16 = 10;
+const NL80211_STA_INFO_TX_RETRIES: u16 = 11;
+const NL80211_STA_INFO_TX_FAILED: u16 = 12;
+const NL80211_STA_INFO_RX_BITRATE: u16 = 14;
+// Bitrates are in units of 100 kbit/s; the 16-bit one saturates above 6.5 Gbit/s.
+const NL80211_RATE_INFO_BITRATE: u16 = 1;
+const NL80211_RATE_INFO_BITRATE32: u16 = 5;
+
+/// The stations associated over `interface`, keyed by their interface address.
+pub(crate) fn stations(interface: &str) -> io::Result<Vec<LinkStats>> {
+    let index = interface_index(interface)?;
+    let socket = netlink::open(libc::NETLINK_GENERIC, 0, 0)?;
+    let family = family_id(&socket, "nl80211")?;
+    let request = generic_message(
+        family,
+        libc::NLM_F_DUMP,
+        NL80211_CMD_GET_STATION,
+        &[(NL80211_ATTR_IFINDEX, &index.to_ne_bytes())],
+    );
+    let mut stations = Vec::new();
+    exchange(&socket, &request, |payload| {
+        stations.extend(station(interface, payload));
+    })?;
+    Ok(stations)
+}
+
+fn interface_index(interface: &str) -> io::Result<u32> {
+    let name = CString::new(interface)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "interface name"))?;
+    // SAFETY: if_nametoindex(3) only reads the NUL-terminated name.
+    match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+        0 => Err(io::Error::last_os_error()),
+        index => Ok(index),
+    }
+}
+
+fn station(interface: &str, payload: &[u8]) -> Option<LinkStats> {
+    let mut peer = None;
+    let mut info = None;
+    for (kind, value) in attributes(payload) {
+        match kind {
+            NL80211_ATTR_MAC => peer = MacAddress::try_from(value).ok(),
+            NL80211_ATTR_STA_INFO => info = Some(value),
+            _ => {}
+        }
+    }
+    let mut stats = LinkStats {
+        peer: peer?,
+        interface: interface.to_string(),
+        rssi: None,
+        tx_rate: None,
+        rx_rate: None,
+        tx_failed: None,
+        tx_packets: None,
+        tx_retries: None,
+    };
+    for (kind, value) in attributes(info?) {
+        match kind {
+            NL80211_STA_INFO_SIGNAL if !value.is_empty() => {
+                stats.rssi = Some(i32::from(value[0] as i8));
+            }
+            NL80211_STA_INFO_TX_BITRATE => stats.tx_rate = bitrate(value),
+            NL80211_STA_INFO_RX_BITRATE => stats.rx_rate = bitrate(value),
+            NL80211_STA_INFO_TX_FAILED if value.len() == 4 => {
+                stats.tx_failed = Some(u32_at(value, 0));
+            }
+            NL80211_STA_INFO_TX_PACKETS if value.len() == 4 => {
+                stats.tx_packets = Some(u32_at(value, 0));
+            }
+            NL80211_STA_INFO_TX_RETRIES if value.len() == 4 => {
+                stats.tx_retries = Some(u32_at(value, 0));
+            }
+            _ => {}
+        }
+    }
+    Some(stats)
+}
+
+/// A nested rate info in kbit/s, preferring the 32-bit bitrate.
+fn bitrate(rate_info: &[u8]) -> Option<u32> {
+    let mut rate = None;
+    for (kind, value) in attributes(rate_info) {
+        match kind {
+            NL80211_RATE_INFO_BITRATE32 if value.len() == 4 => {
+                return Some(u32_at(value, 0) * 100);
+            }
+            NL80211_RATE_INFO_BITRATE if value.len() == 2 => {
+                rate = Some(u32::from(u16_at(value, 0)) * 100);
+            }
+            _ => {}
+        }
+    }
+    rate
+}
@@ -0,0 +1,155 @@
+use std::fmt;
+
+use crate::error::P2pError;
+
+/// Wi-Fi P2P status codes (Wi-Fi P2P spec, table "Status Attribute").
+///
+/// Reported by GO negotiation, provision discovery and invitation signals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum P2pStatusCode {
+    Success,
+    /// Fail; information is currently unavailable.
+    InformationUnavailable,
+    /// Fail; incompatible parameters.
+    IncompatibleParameters,
+    /// Fail; limit reached.
+    LimitReached,
+    /// Fail; invalid parameters.
+    InvalidParameters,
+    /// Fail; unable to accommodate request.
+    UnableToAccommodate,
+    /// Fail; previous protocol error, or disruptive behavior.
+    PreviousProtocolError,
+    /// Fail; no common channels.
+    NoCommonChannels,
+    /// Fail; unknown P2P group.
+    UnknownGroup,
+    /// Fail; both P2P devices indicated an intent of 15 in GO negotiation.
+    BothGoIntent15,
+    /// Fail; incompatible provisioning method.
+    IncompatibleProvisioningMethod,
+    /// Fail; rejected by user.
+    RejectedByUser,
+    /// Success; accepted by user (deferred response).
+    AcceptedByUser,
+    /// A code not defined by the spec (or a negative supplicant-internal value).
+    Unknown(i32),
+}
+
+impl P2pStatusCode {
+    pub fn from_code(code: i32) -> Self {
+        match code {
+            0 => Self::Success,
+            1 => Self::InformationUnavailable,
+            2 => Self::IncompatibleParameters,
+            3 => Self::LimitReached,
+            4 => Self::InvalidParameters,
+            5 => Self::UnableToAccommodate,
+            6 => Self::PreviousProtocolError,
+            7 => Self::NoCommonChannels,
+            8 => Self::UnknownGroup,
+            9 => Self::BothGoIntent15,
+            10 => Self::IncompatibleProvisioningMethod,
+            11 => Self::RejectedByUser,
+            12 => Self::AcceptedByUser,
+            other => Self::Unknown(other),
+        }
+    }
+
+    pub fn code(self) -> i32 {
+        match self {
+            Self::Success => 0,
+            Self::InformationUnavailable => 1,
+            Self::IncompatibleParameters => 2,
+            Self::LimitReached => 3,
+            Self::InvalidParameters => 4,
+            Self::UnableToAccommodate => 5,
+            Self::PreviousProtocolError => 6,
+            Self::NoCommonChannels => 7,
+            Self::UnknownGroup => 8,
+            Self::BothGoIntent15 => 9,
+            Self::IncompatibleProvisioningMethod => 10,
+            Self::RejectedByUser => 11,
+            Self::AcceptedByUser => 12,
+            Self::Unknown(code) => code,
+        }
+    }
+
+    pub fn is_success(self) -> bool {
+        matches!(self, Self::Success | Self::AcceptedByUser)
+    }
+
+    /// Turn failure codes into `P2pError::Status`.
+    pub fn into_result(self) -> Result<(), P2pError> {
+        if self.is_success() {
+            Ok(())
+        } else {
+            Err(P2pError::Status(self))
+        }
+    }
+}
+
+impl From<i32> for P2pStatusCode {
+    fn from(code: i32) -> Self {
+        Self::from_code(code)
+    }
+}
+
+impl fmt::Display for P2pStatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Self::Success => "success",
+            Self::InformationUnavailable => "information currently unavailable",
+            Self::IncompatibleParameters => "incompatible parameters",
+            Self::LimitReached => "limit reached",
+            Self::InvalidParameters => "invalid parameters",
+            Self::UnableToAccommodate => "unable to accommodate request",
+            Self::PreviousProtocolError => "previous protocol error",
+            Self::NoCommonChannels => "no common channels",
+            Self::UnknownGroup => "unknown P2P group",
+            Self::BothGoIntent15 => "both devices requested GO intent 15",
+            Self::IncompatibleProvisioningMethod => "incompatible provisioning method",
+            Self::RejectedByUser => "rejected by user",
+            Self::AcceptedByUser => "accepted by user",
+            Self::Unknown(code) => return write!(f, "unknown status {code}"),
+        };
+        f.write_str(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_round_trip() {
+        for code in -5..=20 {
+            assert_eq!(P2pStatusCode::from_code(code).code(), code);
+        }
+        assert_eq!(P2pStatusCode::from(9), P2pStatusCode::BothGoIntent15);
+        assert_eq!(P2pStatusCode::from(13), P2pStatusCode::Unknown(13));
+        assert_eq!(P2pStatusCode::from(-1), P2pStatusCode::Unknown(-1));
+    }
+
+    #[test]
+    fn only_success_and_deferred_accept_succeed() {
+        for code in -1..=13 {
+            let status = P2pStatusCode::from_code(code);
+            match status.into_result() {
+                Ok(()) => assert!(matches!(code, 0 | 12), "{status} succeeded"),
+                Err(P2pError::Status(reported)) => assert_eq!(reported, status),
+                Err(err) => panic!("{status} failed with {err}"),
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_codes_display_the_number() {
+        assert_eq!(
+            P2pStatusCode::RejectedByUser.to_string(),
+            "rejected by user"
+        );
+        assert_eq!(P2pStatusCode::Unknown(-3).to_string(), "unknown status -3");
+    }
+}
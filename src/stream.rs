@@ -0,0 +1,124 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::sync::broadcast;
+use tokio_stream::Stream;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+
+use crate::channel::{EventKind, P2pEvent};
+#[cfg(feature = "metrics")]
+use crate::metrics::Metrics;
+use crate::peer_filter::{PeerFilter, PeerView};
+
+/// Set of event kinds a subscription is interested in.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EventFilter {
+    kinds: Vec<EventKind>,
+}
+
+impl EventFilter {
+    /// A filter that accepts every event.
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    pub fn only(kind: EventKind) -> Self {
+        Self { kinds: vec![kind] }
+    }
+
+    /// Also accept events of `kind`.
+    pub fn with(mut self, kind: EventKind) -> Self {
+        if !self.kinds.contains(&kind) {
+            self.kinds.push(kind);
+        }
+        self
+    }
+
+    pub fn matches(&self, event: &P2pEvent) -> bool {
+        self.kinds.is_empty() || self.kinds.contains(&event.kind())
+    }
+}
+
+/// A `Stream` of P2P events for use with `select!` and `StreamExt`.
+///
+/// Slow consumers that fall behind the broadcast buffer skip the missed events
+/// instead of ending the stream; the number skipped is available via `lagged()`.
+/// The stream ends once the manager task and every channel are gone.
+pub struct P2pEventStream {
+    inner: BroadcastStream<P2pEvent>,
+    filter: EventFilter,
+    peers: Option<PeerView>,
+    lagged: u64,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Metrics>,
+}
+
+impl P2pEventStream {
+    pub(crate) fn with_filter(
+        receiver: broadcast::Receiver<P2pEvent>,
+        filter: EventFilter,
+    ) -> Self {
+        Self {
+            inner: BroadcastStream::new(receiver),
+            filter,
+            peers: None,
+            lagged: 0,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+        }
+    }
+
+    /// Also count skipped events in `metrics`.
+    #[cfg(feature = "metrics")]
+    pub(crate) fn counted(mut self, metrics: Metrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Only pass peer events about peers matching `filter`; see the
+    /// `peer_filter` module docs. Other events are left alone.
+    pub fn peers_matching(mut self, filter: PeerFilter) -> Self {
+        self.peers = Some(PeerView::new(filter));
+        self
+    }
+
+    /// Total number of events dropped because this stream fell behind.
+    pub fn lagged(&self) -> u64 {
+        self.lagged
+    }
+}
+
+impl Stream for P2pEventStream {
+    type Item = P2pEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match Pin::new(&mut self.inner).poll_next(cx) {
+                Poll::Ready(Some(Ok(event))) => {
+                    if !self.filter.matches(&event) {
+                        continue;
+                    }
+                    let event = match &mut self.peers {
+                        Some(peers) => peers.apply(event),
+                        None => Some(event),
+                    };
+                    if event.is_some() {
+                        return Poll::Ready(event);
+                    }
+                }
+                // The receiver has already moved to the oldest retained event; keep going.
+                Poll::Ready(Some(Err(BroadcastStreamRecvError::Lagged(missed)))) => {
+                    self.lagged += missed;
+                    #[cfg(feature = "metrics")]
+                    if let Some(metrics) = &self.metrics {
+                        metrics.events_lagged(missed);
+                    }
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
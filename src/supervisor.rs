@@ -0,0 +1,498 @@
+//! Keep the connection to one peer alive.
+//!
+//! `WifiP2pChannel::supervise` starts a `ConnectionSupervisor`, which connects to
+//! the peer, waits for the group to form and, whenever the group goes away or GO
+//! negotiation fails, discovers the peer again and reconnects, backing off
+//! between failed attempts. Every transition is reported as
+//! `P2pEvent::SupervisorStateChanged`.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use tokio::sync::Notify;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::channel::{P2pEvent, WifiP2pChannel};
+use crate::config::ConnectConfig;
+use crate::device::DeviceStatus;
+use crate::executor::Executor;
+use crate::group::P2pGroup;
+use crate::mac::MacAddress;
+
+/// When and how often a `ConnectionSupervisor` retries.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SupervisorPolicy {
+    /// Delay after the first failed attempt.
+    pub initial_delay: Duration,
+    /// Upper bound for the growing delay.
+    pub max_delay: Duration,
+    /// Factor the delay grows by after each further failure.
+    pub multiplier: f64,
+    /// Connect attempts in a row before giving up (unbounded if `None`). A
+    /// successful connection resets the count.
+    pub max_attempts: Option<u32>,
+    /// How long one attempt (finding the peer, connecting and forming the group)
+    /// may take before it counts as failed.
+    pub attempt_timeout: Duration,
+}
+
+impl Default for SupervisorPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            multiplier: 2.0,
+            max_attempts: None,
+            attempt_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+impl SupervisorPolicy {
+    /// Delay after failed attempt number `failures` (starting at 1), or `None` to give up.
+    pub fn delay_for(&self, failures: u32) -> Option<Duration> {
+        if self.max_attempts.is_some_and(|max| failures >= max) {
+            return None;
+        }
+        let exponent = i32::try_from(failures.saturating_sub(1)).unwrap_or(i32::MAX);
+        let factor = self.multiplier.max(1.0).powi(exponent);
+        let delay = self.initial_delay.as_secs_f64() * factor;
+        Some(Duration::from_secs_f64(
+            delay.min(self.max_delay.as_secs_f64()),
+        ))
+    }
+}
+
+/// Where a `ConnectionSupervisor` is, as reported by `SupervisorStateChanged`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SupervisorState {
+    /// Looking for the peer and connecting; `attempt` counts from 1 since the
+    /// last successful connection.
+    Connecting { attempt: u32 },
+    /// A group with the peer is up.
+    Connected,
+    /// The last attempt failed or the group went away; retrying after `delay`.
+    Backoff { attempt: u32, delay: Duration },
+    /// `max_attempts` failed in a row; the supervisor has stopped.
+    GaveUp,
+}
+
+/// Handle returned by `WifiP2pChannel::supervise`.
+///
+/// Dropping the handle leaves the supervisor running; call `stop` to end it.
+pub struct ConnectionSupervisor {
+    peer: MacAddress,
+    stop: Arc<Notify>,
+    finished: Arc<AtomicBool>,
+}
+
+impl ConnectionSupervisor {
+    pub(crate) fn spawn(
+        channel: WifiP2pChannel,
+        executor: Arc<dyn Executor>,
+        config: ConnectConfig,
+        policy: SupervisorPolicy,
+    ) -> Self {
+        let peer = config.device_address;
+        let stop = Arc::new(Notify::new());
+        let finished = Arc::new(AtomicBool::new(false));
+        let (stop_task, finished_task) = (Arc::clone(&stop), Arc::clone(&finished));
+        let task_executor = Arc::clone(&executor);
+        executor.spawn(Box::pin(async move {
+            let supervisor = Supervisor {
+                events: channel.subscribe_events(),
+                channel,
+                executor: task_executor,
+                config,
+                policy,
+            };
+            tokio::select! {
+                biased;
+                () = stop_task.notified() => {}
+                () = supervisor.run() => {}
+            }
+            finished_task.store(true, Ordering::Release);
+        }));
+        Self {
+            peer,
+            stop,
+            finished,
+        }
+    }
+
+    /// The peer being kept connected.
+    pub fn peer(&self) -> MacAddress {
+        self.peer
+    }
+
+    /// Stop reconnecting. An established group is left up.
+    pub fn stop(self) {
+        // notify_one stores a permit, so this works even while the task is busy.
+        self.stop.notify_one();
+    }
+
+    /// Whether the supervisor is still running (it ends after `GaveUp`).
+    pub fn is_running(&self) -> bool {
+        !self.finished.load(Ordering::Acquire)
+    }
+}
+
+/// How one connect attempt ended.
+enum Attempt {
+    /// A group with the peer is up on this interface.
+    Connected(String),
+    Failed,
+    /// The manager went away; nothing left to supervise.
+    Closed,
+}
+
+/// What `Supervisor::next_event` got.
+enum Next {
+    Event(P2pEvent),
+    /// Events were dropped while we lagged behind; check the snapshots instead.
+    Lagged,
+    /// The manager is gone.
+    Closed,
+}
+
+struct Supervisor {
+    channel: WifiP2pChannel,
+    executor: Arc<dyn Executor>,
+    events: broadcast::Receiver<P2pEvent>,
+    config: ConnectConfig,
+    policy: SupervisorPolicy,
+}
+
+impl Supervisor {
+    async fn run(mut self) {
+        let mut group = self.peer_group().map(|group| group.interface);
+        let mut failures = 0;
+        loop {
+            let interface = match group.take() {
+                Some(interface) => interface,
+                None => {
+                    self.report(SupervisorState::Connecting {
+                        attempt: failures + 1,
+                    });
+                    let deadline = self.executor.sleep(self.policy.attempt_timeout);
+                    let attempt = tokio::select! {
+                        attempt = self.attempt() => attempt,
+                        () = deadline => Attempt::Failed,
+                    };
+                    match attempt {
+                        Attempt::Connected(interface) => interface,
+                        Attempt::Closed => return,
+                        Attempt::Failed => {
+                            failures += 1;
+                            let Some(delay) = self.policy.delay_for(failures) else {
+                                self.report(SupervisorState::GaveUp);
+                                return;
+                            };
+                            self.report(SupervisorState::Backoff {
+                                attempt: failures,
+                                delay,
+                            });
+                            self.executor.sleep(delay).await;
+                            continue;
+                        }
+                    }
+                }
+            };
+            failures = 0;
+            self.report(SupervisorState::Connected);
+            if !self.wait_for_loss(&interface).await {
+                return;
+            }
+        }
+    }
+
+    fn peer(&self) -> MacAddress {
+        self.config.device_address
+    }
+
+    /// The group formed with the peer, if one is up.
+    fn peer_group(&self) -> Option<P2pGroup> {
+        let peer = self.peer();
+        self.channel
+            .groups_snapshot()
+            .into_iter()
+            .find(|group| group.peer == Some(peer))
+    }
+
+    fn peer_visible(&self) -> bool {
+        let peer = self.peer();
+        self.channel
+            .peers_snapshot()
+            .iter()
+            .any(|device| device.mac_address == peer && device.status != DeviceStatus::Unavailable)
+    }
+
+    async fn attempt(&mut self) -> Attempt {
+        // The supplicant only connects to peers it has seen recently, so look
+        // for the peer first unless it is in the table right now.
+        let peer = self.peer();
+        if !self.peer_visible() {
+            match self.channel.discover_peers().await {
+                Ok(started) => {
+                    let _ = started.await;
+                }
+                Err(_) => return Attempt::Closed,
+            }
+            loop {
+                let found = self
+                    .next_event(|event| match event {
+                        P2pEvent::PeerFound(device) => device.mac_address == peer,
+                        _ => false,
+                    })
+                    .await;
+                match found {
+                    Next::Event(_) => break,
+                    Next::Lagged if self.peer_visible() => break,
+                    Next::Lagged => {}
+                    Next::Closed => return Attempt::Closed,
+                }
+            }
+        }
+        let connect = match self.channel.connect_with_config(self.config.clone()).await {
+            Ok(connect) => connect,
+            Err(_) => return Attempt::Closed,
+        };
+        if !matches!(connect.await, Ok(Ok(_))) {
+            return Attempt::Failed;
+        }
+        loop {
+            // Groups formed with other peers, or started on our own, are not ours.
+            let outcome = self
+                .next_event(|event| match event {
+                    P2pEvent::GroupStarted(group) => group.peer == Some(peer),
+                    P2pEvent::GoNegotiationFailed { peer: failed, .. } => {
+                        failed.is_none_or(|failed| failed == peer)
+                    }
+                    _ => false,
+                })
+                .await;
+            match outcome {
+                Next::Event(P2pEvent::GroupStarted(group)) => {
+                    return Attempt::Connected(group.interface.clone());
+                }
+                Next::Event(_) => return Attempt::Failed,
+                Next::Lagged => {
+                    if let Some(group) = self.peer_group() {
+                        return Attempt::Connected(group.interface);
+                    }
+                }
+                Next::Closed => return Attempt::Closed,
+            }
+        }
+    }
+
+    /// Wait until the group with the peer on `interface` is gone; false once the
+    /// manager is.
+    async fn wait_for_loss(&mut self, interface: &str) -> bool {
+        loop {
+            let lost = self
+                .next_event(|event| match event {
+                    P2pEvent::GroupFinished(group) => group.interface == interface,
+                    _ => false,
+                })
+                .await;
+            match lost {
+                Next::Event(_) => return true,
+                Next::Lagged => {
+                    let group = self.peer_group();
+                    if group.is_none_or(|group| group.interface != interface) {
+                        return true;
+                    }
+                }
+                Next::Closed => return false,
+            }
+        }
+    }
+
+    /// The next event matching `wanted`. The channel we hold keeps the event
+    /// stream open, so the manager stopping is noticed through `closed`.
+    async fn next_event(&mut self, wanted: impl Fn(&P2pEvent) -> bool) -> Next {
+        loop {
+            let received = tokio::select! {
+                received = self.events.recv() => received,
+                () = self.channel.closed() => return Next::Closed,
+            };
+            match received {
+                Ok(event) if wanted(&event) => return Next::Event(event),
+                Ok(_) => {}
+                Err(RecvError::Lagged(_)) => return Next::Lagged,
+                Err(RecvError::Closed) => return Next::Closed,
+            }
+        }
+    }
+
+    fn report(&self, state: SupervisorState) {
+        self.channel.emit(P2pEvent::SupervisorStateChanged {
+            peer: self.peer(),
+            state,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_grows_up_to_the_cap() {
+        let policy = SupervisorPolicy {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(10),
+            multiplier: 2.0,
+            ..SupervisorPolicy::default()
+        };
+        let delays: Vec<_> = (1..=6).map(|failures| policy.delay_for(failures)).collect();
+        let secs = |secs| Some(Duration::from_secs(secs));
+        assert_eq!(
+            delays,
+            [secs(1), secs(2), secs(4), secs(8), secs(10), secs(10)]
+        );
+        assert_eq!(policy.delay_for(u32::MAX), secs(10));
+    }
+
+    #[test]
+    fn shrinking_multiplier_keeps_the_initial_delay() {
+        let policy = SupervisorPolicy {
+            multiplier: 0.5,
+            ..SupervisorPolicy::default()
+        };
+        assert_eq!(policy.delay_for(5), Some(policy.initial_delay));
+    }
+
+    #[test]
+    fn gives_up_after_max_attempts() {
+        let policy = SupervisorPolicy {
+            max_attempts: Some(3),
+            ..SupervisorPolicy::default()
+        };
+        assert!(policy.delay_for(2).is_some());
+        assert_eq!(policy.delay_for(3), None);
+        assert_eq!(policy.delay_for(4), None);
+    }
+
+    #[cfg(feature = "tokio")]
+    mod sim {
+        use super::*;
+        use crate::backend::mock::{SIM_GROUP_INTERFACE, SimBackend, VirtualPeer};
+        use crate::group::GroupRole;
+        use crate::manager::WifiP2pManager;
+
+        const PEER: MacAddress = MacAddress::new([0x02, 0, 0, 0, 0, 0x10]);
+        const POLICY: SupervisorPolicy = SupervisorPolicy {
+            initial_delay: Duration::from_millis(50),
+            max_delay: Duration::from_millis(50),
+            multiplier: 1.0,
+            max_attempts: None,
+            attempt_timeout: Duration::from_millis(500),
+        };
+
+        fn group(interface: &str, peer: Option<MacAddress>) -> Arc<P2pGroup> {
+            Arc::new(P2pGroup {
+                interface: interface.to_string(),
+                role: GroupRole::Owner,
+                ip_allocation: None,
+                peer,
+            })
+        }
+
+        async fn next_state(events: &mut broadcast::Receiver<P2pEvent>) -> SupervisorState {
+            let state = async {
+                loop {
+                    if let Ok(P2pEvent::SupervisorStateChanged { state, .. }) = events.recv().await
+                    {
+                        return state;
+                    }
+                }
+            };
+            tokio::time::timeout(Duration::from_secs(5), state)
+                .await
+                .expect("no supervisor state change")
+        }
+
+        #[tokio::test]
+        async fn reconnects_when_the_group_is_lost() {
+            let sim = SimBackend::new();
+            sim.add_peer(VirtualPeer::new(PEER));
+            let manager = WifiP2pManager::with_backend(Arc::new(sim.clone()));
+            let channel = manager.initialize();
+            let mut events = channel.subscribe_events();
+            let supervisor = channel.supervise(ConnectConfig::new(PEER), POLICY).unwrap();
+            assert_eq!(
+                next_state(&mut events).await,
+                SupervisorState::Connecting { attempt: 1 }
+            );
+            assert_eq!(next_state(&mut events).await, SupervisorState::Connected);
+
+            // Another group going away leaves the supervised one alone.
+            channel.emit(P2pEvent::GroupFinished(group("p2p-other-0", None)));
+            let unrelated =
+                tokio::time::timeout(Duration::from_millis(200), next_state(&mut events)).await;
+            assert!(unrelated.is_err(), "reacted with {unrelated:?}");
+
+            sim.end_group();
+            assert_eq!(
+                next_state(&mut events).await,
+                SupervisorState::Connecting { attempt: 1 }
+            );
+            assert_eq!(next_state(&mut events).await, SupervisorState::Connected);
+            assert!(supervisor.is_running());
+            supervisor.stop();
+        }
+
+        #[tokio::test]
+        async fn groups_without_the_peer_do_not_count() {
+            let sim = SimBackend::new();
+            sim.add_peer(VirtualPeer::new(PEER));
+            let manager = WifiP2pManager::with_backend(Arc::new(sim.clone()));
+            let channel = manager.initialize();
+            // With our own group up the simulated peer joins it, so no group
+            // with the peer is ever reported.
+            channel
+                .create_group()
+                .await
+                .unwrap()
+                .await
+                .unwrap()
+                .unwrap();
+            let mut events = channel.subscribe_events();
+            let supervisor = channel.supervise(ConnectConfig::new(PEER), POLICY).unwrap();
+            assert_eq!(
+                next_state(&mut events).await,
+                SupervisorState::Connecting { attempt: 1 }
+            );
+            channel.emit(P2pEvent::GroupStarted(group(SIM_GROUP_INTERFACE, None)));
+            assert!(matches!(
+                next_state(&mut events).await,
+                SupervisorState::Backoff { attempt: 1, .. }
+            ));
+            supervisor.stop();
+        }
+
+        #[tokio::test]
+        async fn ends_when_the_manager_shuts_down() {
+            let sim = SimBackend::new();
+            sim.add_peer(VirtualPeer::new(PEER));
+            let mut manager = WifiP2pManager::with_backend(Arc::new(sim));
+            let channel = manager.initialize();
+            let mut events = channel.subscribe_events();
+            let supervisor = channel.supervise(ConnectConfig::new(PEER), POLICY).unwrap();
+            while next_state(&mut events).await != SupervisorState::Connected {}
+            manager.shutdown().await.unwrap();
+            for _ in 0..50 {
+                if !supervisor.is_running() {
+                    return;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            panic!("supervisor still running after shutdown");
+        }
+    }
+}
@@ -0,0 +1,107 @@
+//! Running under systemd: readiness notification, watchdog pings and socket
+//! activation, implemented on the documented environment protocol without
+//! linking libsystemd. Everything here is a no-op outside a systemd unit.
+//!
+//! - `notify_ready` tells a `Type=notify` unit that startup finished.
+//! - `WifiP2pManagerBuilder::systemd_watchdog` pings `WATCHDOG=1` from the
+//!   manager loop, so a wedged loop gets the service restarted.
+//! - `listen_fds` hands over the sockets of a `.socket` unit, e.g. for
+//!   `grpc::serve_listener` or `http::serve_listener`.
+
+use std::env;
+use std::os::fd::{FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::UnixDatagram;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use crate::error::P2pError;
+
+/// The first descriptor systemd passes (SD_LISTEN_FDS_START).
+const LISTEN_FDS_START: RawFd = 3;
+
+static FDS_TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// Send `state` (e.g. `"READY=1"` or `"STATUS=..."`) to the service manager.
+/// Returns `Ok(false)` when not started by systemd.
+pub fn notify(state: &str) -> Result<bool, P2pError> {
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return Ok(false);
+    };
+    let socket = UnixDatagram::unbound().map_err(systemd_error)?;
+    match path.as_encoded_bytes().strip_prefix(b"@") {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            use std::os::unix::net::SocketAddr;
+
+            let addr = SocketAddr::from_abstract_name(name).map_err(systemd_error)?;
+            socket
+                .send_to_addr(state.as_bytes(), &addr)
+                .map_err(systemd_error)?;
+        }
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => {
+            return Err(P2pError::Unsupported(
+                "abstract NOTIFY_SOCKET outside Linux".to_string(),
+            ));
+        }
+        None => {
+            socket
+                .send_to(state.as_bytes(), &path)
+                .map_err(systemd_error)?;
+        }
+    }
+    Ok(true)
+}
+
+/// Report that startup finished (`READY=1`).
+pub fn notify_ready() -> Result<bool, P2pError> {
+    notify("READY=1")
+}
+
+/// The unit's `WatchdogSec=`, if the watchdog is enabled for this process.
+pub fn watchdog_interval() -> Option<Duration> {
+    // Without WATCHDOG_PID the watchdog is meant for whoever reads it.
+    if let Ok(pid) = env::var("WATCHDOG_PID")
+        && pid.parse::<u32>().ok()? != std::process::id()
+    {
+        return None;
+    }
+    let usec: u64 = env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec))
+}
+
+/// Take the sockets passed by socket activation, paired with their
+/// `FileDescriptorName=` (systemd's default is `"unknown"`). Only the first
+/// call gets them; later calls and processes not socket-activated get none.
+pub fn listen_fds() -> Vec<(String, OwnedFd)> {
+    let Some(count) = listen_fd_count() else {
+        return Vec::new();
+    };
+    if FDS_TAKEN.swap(true, Ordering::SeqCst) {
+        return Vec::new();
+    }
+    let names = env::var("LISTEN_FDNAMES").unwrap_or_default();
+    let mut names = names.split(':');
+    (0..count)
+        .map(|offset| {
+            let name = names.next().unwrap_or("unknown").to_string();
+            // SAFETY: systemd passes `count` open descriptors from 3 that
+            // nothing else in the process owns, and FDS_TAKEN hands them out once.
+            let fd = unsafe { OwnedFd::from_raw_fd(LISTEN_FDS_START + offset) };
+            (name, fd)
+        })
+        .collect()
+}
+
+fn listen_fd_count() -> Option<RawFd> {
+    let pid: u32 = env::var("LISTEN_PID").ok()?.parse().ok()?;
+    if pid != std::process::id() {
+        return None;
+    }
+    env::var("LISTEN_FDS").ok()?.parse().ok()
+}
+
+fn systemd_error(err: std::io::Error) -> P2pError {
+    P2pError::Backend(format!("systemd: {err}"))
+}
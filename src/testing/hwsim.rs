@@ -0,0 +1,176 @@
+//! Virtual radios for end-to-end tests against a real wpa_supplicant.
+//!
+//! `HwsimHarness::start` loads `mac80211_hwsim`, writes a P2P-enabled config per
+//! radio and runs one wpa_supplicant with the D-Bus interface (`-u`) managing all
+//! of them. Everything is torn down again on drop. Needs root, the hwsim kernel
+//! module and a wpa_supplicant built with P2P and D-Bus support.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::P2pError;
+
+const CTRL_DIR: &str = "/var/run/wpa_supplicant";
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A set of mac80211_hwsim radios driven by a dedicated wpa_supplicant.
+pub struct HwsimHarness {
+    interfaces: Vec<String>,
+    supplicant: Child,
+    config_dir: PathBuf,
+}
+
+impl HwsimHarness {
+    /// Create `radios` virtual radios and start wpa_supplicant on all of them.
+    pub fn start(radios: u32) -> Result<Self, P2pError> {
+        let before = wireless_interfaces()?;
+        run("modprobe", &["mac80211_hwsim", &format!("radios={radios}")])?;
+        let interfaces = wait_for(|| {
+            let added: Vec<String> = wireless_interfaces()
+                .ok()?
+                .difference(&before)
+                .cloned()
+                .collect();
+            (added.len() == radios as usize).then_some(added)
+        })
+        .ok_or_else(|| {
+            unload();
+            harness_error("hwsim radios did not show up")
+        })?;
+
+        let config_dir =
+            std::env::temp_dir().join(format!("wifi-p2p-rs-hwsim-{}", std::process::id()));
+        let result = Self::spawn_supplicant(&interfaces, &config_dir);
+        let supplicant = match result {
+            Ok(child) => child,
+            Err(err) => {
+                let _ = fs::remove_dir_all(&config_dir);
+                unload();
+                return Err(err);
+            }
+        };
+        let harness = Self {
+            interfaces,
+            supplicant,
+            config_dir,
+        };
+        // Control sockets appear once each interface is fully initialized.
+        let ready = wait_for(|| {
+            harness
+                .interfaces
+                .iter()
+                .all(|interface| Path::new(CTRL_DIR).join(interface).exists())
+                .then_some(())
+        });
+        ready.ok_or_else(|| harness_error("wpa_supplicant did not come up"))?;
+        Ok(harness)
+    }
+
+    /// Interface names of the radios, in creation order.
+    pub fn interfaces(&self) -> &[String] {
+        &self.interfaces
+    }
+
+    /// The P2P device name configured for radio `index`, as seen by its peers.
+    pub fn device_name(index: usize) -> String {
+        format!("hwsim-{index}")
+    }
+
+    fn spawn_supplicant(interfaces: &[String], config_dir: &Path) -> Result<Child, P2pError> {
+        fs::create_dir_all(config_dir).map_err(io_error)?;
+        let mut command = Command::new("wpa_supplicant");
+        command.args(["-u", "-D", "nl80211"]);
+        for (index, interface) in interfaces.iter().enumerate() {
+            let config = config_dir.join(format!("{interface}.conf"));
+            fs::write(&config, supplicant_config(index)).map_err(io_error)?;
+            if index > 0 {
+                // -N starts the next interface's option block.
+                command.arg("-N");
+            }
+            command.args(["-i", interface, "-c"]).arg(&config);
+        }
+        command
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(io_error)
+    }
+}
+
+impl Drop for HwsimHarness {
+    fn drop(&mut self) {
+        let _ = self.supplicant.kill();
+        let _ = self.supplicant.wait();
+        let _ = fs::remove_dir_all(&self.config_dir);
+        unload();
+    }
+}
+
+fn supplicant_config(index: usize) -> String {
+    format!(
+        "ctrl_interface={CTRL_DIR}\n\
+         update_config=0\n\
+         device_name={}\n\
+         device_type=1-0050F204-1\n\
+         config_methods=push_button display keypad\n\
+         p2p_listen_reg_class=81\n\
+         p2p_listen_channel=1\n\
+         p2p_oper_reg_class=81\n\
+         p2p_oper_channel=6\n",
+        HwsimHarness::device_name(index)
+    )
+}
+
+fn wireless_interfaces() -> Result<BTreeSet<String>, P2pError> {
+    // Wireless netdevs carry a phy80211 link in sysfs.
+    let entries = fs::read_dir("/sys/class/net").map_err(io_error)?;
+    Ok(entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().join("phy80211").exists())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect())
+}
+
+fn wait_for<T>(mut probe: impl FnMut() -> Option<T>) -> Option<T> {
+    let deadline = Instant::now() + STARTUP_TIMEOUT;
+    loop {
+        if let Some(value) = probe() {
+            return Some(value);
+        }
+        if Instant::now() >= deadline {
+            return None;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn run(program: &str, args: &[&str]) -> Result<(), P2pError> {
+    let status = Command::new(program)
+        .args(args)
+        .status()
+        .map_err(io_error)?;
+    if !status.success() {
+        return Err(harness_error(&format!(
+            "{program} {} failed: {status}",
+            args.join(" ")
+        )));
+    }
+    Ok(())
+}
+
+fn unload() {
+    let _ = run("modprobe", &["-r", "mac80211_hwsim"]);
+}
+
+fn io_error(err: std::io::Error) -> P2pError {
+    harness_error(&err.to_string())
+}
+
+fn harness_error(message: &str) -> P2pError {
+    P2pError::Backend(format!("hwsim harness: {message}"))
+}
@@ -0,0 +1,3 @@
+//! Test support that needs more than the simulation backend, behind the `hwsim` feature.
+
+pub mod hwsim;
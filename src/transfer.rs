@@ -0,0 +1,394 @@
+//! File transfer over the group link, the canonical Wi-Fi Direct use case.
+//!
+//! The receiver listens (`WifiP2pChannel::receive_files`) and the sender connects
+//! to it (`WifiP2pChannel::send_file`), so either side of the group can send.
+//! Each connection carries one file:
+//!
+//! ```text
+//! sender   -> "P2PF" version:u8 name_len:u16 name size:u64 sha256:[u8; 32]
+//! receiver -> offset:u64            (bytes already received in an earlier attempt)
+//! sender   -> file contents from offset
+//! receiver -> status:u8             (0: checksum verified, 1: mismatch)
+//! ```
+//!
+//! All integers are big-endian. Partial data is kept next to the destination as
+//! a hidden `.part` file named after the checksum, so an interrupted transfer of
+//! the same file resumes and a different file with the same name starts over.
+//! Progress is reported as `P2pEvent::TransferProgress`; the I/O itself needs the
+//! `transfer` feature.
+
+/// Which way a file is going, from our side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TransferDirection {
+    Send,
+    Receive,
+}
+
+/// How far a file transfer has come.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TransferProgress {
+    pub direction: TransferDirection,
+    pub file_name: String,
+    /// Bytes the receiver has, including any resumed from an earlier attempt.
+    pub transferred: u64,
+    pub total: u64,
+}
+
+/// Port `receive_files` listens on, the one Android's Wi-Fi Direct sample uses.
+pub const DEFAULT_PORT: u16 = 8988;
+
+#[cfg(feature = "transfer")]
+pub(crate) use io::{receive, send};
+
+#[cfg(feature = "transfer")]
+mod io {
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::path::{Path, PathBuf};
+
+    use sha2::{Digest, Sha256};
+    use tokio::fs::{self, File, OpenOptions};
+    use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    use super::{TransferDirection, TransferProgress};
+    use crate::channel::{P2pEvent, WifiP2pChannel};
+    use crate::error::P2pError;
+
+    const MAGIC: &[u8; 4] = b"P2PF";
+    const VERSION: u8 = 1;
+    const CHUNK_SIZE: usize = 64 * 1024;
+    // Report progress at most once per this many bytes, plus once at the end, so
+    // slow subscribers do not lag behind the broadcast channel.
+    const PROGRESS_STEP: u64 = 1024 * 1024;
+    const STATUS_OK: u8 = 0;
+    const STATUS_CHECKSUM_MISMATCH: u8 = 1;
+
+    struct Header {
+        file_name: String,
+        size: u64,
+        checksum: [u8; 32],
+    }
+
+    /// Send the file at `path` over `stream`, which is connected to a receiver.
+    pub(crate) async fn send(
+        channel: &WifiP2pChannel,
+        mut stream: TcpStream,
+        path: &Path,
+    ) -> Result<(), P2pError> {
+        let file_name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| P2pError::InvalidArgs(format!("{} has no file name", path.display())))?
+            .to_string();
+        background(&stream);
+        let result = send_file(channel, &mut stream, path, &file_name).await;
+        finish(channel, TransferDirection::Send, file_name, result)
+    }
+
+    async fn send_file(
+        channel: &WifiP2pChannel,
+        stream: &mut TcpStream,
+        path: &Path,
+        file_name: &str,
+    ) -> Result<(), P2pError> {
+        let mut file = File::open(path).await.map_err(|err| io_error(path, err))?;
+        let size = file
+            .metadata()
+            .await
+            .map_err(|err| io_error(path, err))?
+            .len();
+        let checksum = checksum(&mut file)
+            .await
+            .map_err(|err| io_error(path, err))?;
+
+        let name = file_name.as_bytes();
+        let name_len = u16::try_from(name.len())
+            .map_err(|_| P2pError::InvalidArgs(format!("file name {file_name} is too long")))?;
+        let mut header = Vec::with_capacity(47 + name.len());
+        header.extend_from_slice(MAGIC);
+        header.push(VERSION);
+        header.extend_from_slice(&name_len.to_be_bytes());
+        header.extend_from_slice(name);
+        header.extend_from_slice(&size.to_be_bytes());
+        header.extend_from_slice(&checksum);
+        stream.write_all(&header).await.map_err(link_error)?;
+
+        let offset = stream.read_u64().await.map_err(link_error)?;
+        if offset > size {
+            return Err(transfer_error(&format!(
+                "receiver asked to resume at {offset} of {size} bytes"
+            )));
+        }
+        file.seek(std::io::SeekFrom::Start(offset))
+            .await
+            .map_err(|err| io_error(path, err))?;
+        let mut progress = Progress::new(channel, TransferDirection::Send, file_name, offset, size);
+        let mut buf = vec![0u8; CHUNK_SIZE];
+        loop {
+            let len = file
+                .read(&mut buf)
+                .await
+                .map_err(|err| io_error(path, err))?;
+            if len == 0 {
+                break;
+            }
+            stream.write_all(&buf[..len]).await.map_err(link_error)?;
+            progress.advance(len as u64);
+        }
+        stream.flush().await.map_err(link_error)?;
+        match stream.read_u8().await.map_err(link_error)? {
+            STATUS_OK => Ok(()),
+            STATUS_CHECKSUM_MISMATCH => {
+                Err(transfer_error("receiver reported a checksum mismatch"))
+            }
+            status => Err(transfer_error(&format!("unknown receiver status {status}"))),
+        }
+    }
+
+    /// Accept transfers on `port` into `dir` until the listener fails. Each
+    /// connection is handled on its own task, so transfers run in parallel.
+    pub(crate) async fn receive(
+        channel: &WifiP2pChannel,
+        dir: &Path,
+        port: u16,
+    ) -> Result<(), P2pError> {
+        fs::create_dir_all(dir)
+            .await
+            .map_err(|err| io_error(dir, err))?;
+        let listener = TcpListener::bind((listen_address(), port))
+            .await
+            .map_err(|err| transfer_error(&format!("listen on port {port}: {err}")))?;
+        loop {
+            let (stream, _) = listener.accept().await.map_err(link_error)?;
+            let channel = channel.clone();
+            let dir = dir.to_path_buf();
+            tokio::spawn(async move { receive_one(&channel, stream, &dir).await });
+        }
+    }
+
+    /// Let bulk data yield to other traffic on the link; best effort.
+    #[cfg_attr(not(feature = "qos"), allow(unused_variables))]
+    fn background(stream: &TcpStream) {
+        #[cfg(feature = "qos")]
+        let _ = crate::net::qos::set_access_category(
+            stream,
+            crate::net::qos::AccessCategory::Background,
+        );
+    }
+
+    fn listen_address() -> IpAddr {
+        // Dual-stack on Linux, so link-local peers can reach us as well.
+        if cfg!(all(feature = "ipv6-link-local", target_os = "linux")) {
+            IpAddr::V6(std::net::Ipv6Addr::UNSPECIFIED)
+        } else {
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+        }
+    }
+
+    async fn receive_one(channel: &WifiP2pChannel, mut stream: TcpStream, dir: &Path) {
+        background(&stream);
+        // Connections that do not even send a valid header are not transfers.
+        let Ok(header) = read_header(&mut stream).await else {
+            return;
+        };
+        let file_name = header.file_name.clone();
+        let result = receive_file(channel, &mut stream, dir, &header).await;
+        let _ = finish(channel, TransferDirection::Receive, file_name, result);
+    }
+
+    async fn read_header(stream: &mut TcpStream) -> Result<Header, P2pError> {
+        let mut magic = [0u8; 4];
+        stream.read_exact(&mut magic).await.map_err(link_error)?;
+        let version = stream.read_u8().await.map_err(link_error)?;
+        if &magic != MAGIC || version != VERSION {
+            return Err(transfer_error("not a transfer header"));
+        }
+        let mut name = vec![0u8; usize::from(stream.read_u16().await.map_err(link_error)?)];
+        stream.read_exact(&mut name).await.map_err(link_error)?;
+        let size = stream.read_u64().await.map_err(link_error)?;
+        let mut checksum = [0u8; 32];
+        stream.read_exact(&mut checksum).await.map_err(link_error)?;
+        let file_name = String::from_utf8(name)
+            .ok()
+            .filter(|name| is_plain_file_name(name))
+            .ok_or_else(|| transfer_error("invalid file name"))?;
+        Ok(Header {
+            file_name,
+            size,
+            checksum,
+        })
+    }
+
+    fn is_plain_file_name(name: &str) -> bool {
+        // Only a bare name, never a path out of the destination directory.
+        Path::new(name).file_name().and_then(|base| base.to_str()) == Some(name)
+    }
+
+    async fn receive_file(
+        channel: &WifiP2pChannel,
+        stream: &mut TcpStream,
+        dir: &Path,
+        header: &Header,
+    ) -> Result<(), P2pError> {
+        let part = part_path(dir, header);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(&part)
+            .await
+            .map_err(|err| io_error(&part, err))?;
+        let mut offset = file
+            .metadata()
+            .await
+            .map_err(|err| io_error(&part, err))?
+            .len();
+        if offset > header.size {
+            file.set_len(0).await.map_err(|err| io_error(&part, err))?;
+            offset = 0;
+        }
+        file.seek(std::io::SeekFrom::Start(offset))
+            .await
+            .map_err(|err| io_error(&part, err))?;
+        stream.write_u64(offset).await.map_err(link_error)?;
+
+        let mut progress = Progress::new(
+            channel,
+            TransferDirection::Receive,
+            &header.file_name,
+            offset,
+            header.size,
+        );
+        let mut remaining = header.size - offset;
+        let mut buf = vec![0u8; CHUNK_SIZE];
+        while remaining > 0 {
+            let want = buf
+                .len()
+                .min(usize::try_from(remaining).unwrap_or(usize::MAX));
+            let len = stream.read(&mut buf[..want]).await.map_err(link_error)?;
+            if len == 0 {
+                return Err(transfer_error("sender closed the connection early"));
+            }
+            file.write_all(&buf[..len])
+                .await
+                .map_err(|err| io_error(&part, err))?;
+            remaining -= len as u64;
+            progress.advance(len as u64);
+        }
+        file.flush().await.map_err(|err| io_error(&part, err))?;
+
+        // Resumed data is only trusted once the whole file checks out.
+        file.rewind().await.map_err(|err| io_error(&part, err))?;
+        let checksum = checksum(&mut file)
+            .await
+            .map_err(|err| io_error(&part, err))?;
+        drop(file);
+        if checksum != header.checksum {
+            let _ = fs::remove_file(&part).await;
+            let _ = stream.write_u8(STATUS_CHECKSUM_MISMATCH).await;
+            return Err(transfer_error("checksum mismatch"));
+        }
+        let destination = dir.join(&header.file_name);
+        fs::rename(&part, &destination)
+            .await
+            .map_err(|err| io_error(&destination, err))?;
+        stream.write_u8(STATUS_OK).await.map_err(link_error)?;
+        Ok(())
+    }
+
+    fn part_path(dir: &Path, header: &Header) -> PathBuf {
+        let tag: String = header.checksum[..8]
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        dir.join(format!(".{}.{tag}.part", header.file_name))
+    }
+
+    async fn checksum(reader: &mut (impl AsyncRead + Unpin)) -> std::io::Result<[u8; 32]> {
+        let mut hasher = Sha256::new();
+        let mut buf = vec![0u8; CHUNK_SIZE];
+        loop {
+            let len = reader.read(&mut buf).await?;
+            if len == 0 {
+                return Ok(hasher.finalize().into());
+            }
+            hasher.update(&buf[..len]);
+        }
+    }
+
+    /// Emits `TransferProgress` as bytes go by, throttled to `PROGRESS_STEP`.
+    struct Progress<'a> {
+        channel: &'a WifiP2pChannel,
+        progress: TransferProgress,
+        reported: u64,
+    }
+
+    impl<'a> Progress<'a> {
+        fn new(
+            channel: &'a WifiP2pChannel,
+            direction: TransferDirection,
+            file_name: &str,
+            transferred: u64,
+            total: u64,
+        ) -> Self {
+            let progress = TransferProgress {
+                direction,
+                file_name: file_name.to_string(),
+                transferred,
+                total,
+            };
+            channel.emit(P2pEvent::TransferProgress(progress.clone()));
+            Self {
+                channel,
+                progress,
+                reported: transferred,
+            }
+        }
+
+        fn advance(&mut self, len: u64) {
+            self.progress.transferred += len;
+            let done = self.progress.transferred == self.progress.total;
+            if done || self.progress.transferred - self.reported >= PROGRESS_STEP {
+                self.reported = self.progress.transferred;
+                self.channel
+                    .emit(P2pEvent::TransferProgress(self.progress.clone()));
+            }
+        }
+    }
+
+    fn finish(
+        channel: &WifiP2pChannel,
+        direction: TransferDirection,
+        file_name: String,
+        result: Result<(), P2pError>,
+    ) -> Result<(), P2pError> {
+        let event = match &result {
+            Ok(()) => P2pEvent::TransferCompleted {
+                direction,
+                file_name,
+            },
+            Err(err) => P2pEvent::TransferFailed {
+                direction,
+                file_name,
+                reason: err.to_string(),
+            },
+        };
+        channel.emit(event);
+        result
+    }
+
+    fn io_error(path: &Path, err: std::io::Error) -> P2pError {
+        transfer_error(&format!("{}: {err}", path.display()))
+    }
+
+    fn link_error(err: std::io::Error) -> P2pError {
+        transfer_error(&err.to_string())
+    }
+
+    fn transfer_error(message: &str) -> P2pError {
+        P2pError::Backend(format!("transfer: {message}"))
+    }
+}
@@ -0,0 +1,52 @@
+/// Frames that can carry application vendor elements (wpa_supplicant `vendor_elem` ids).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VendorElementFrame {
+    /// Probe Request sent during P2P discovery.
+    ProbeRequest,
+    /// Probe Response sent as a P2P device.
+    ProbeResponse,
+    /// Probe Response sent as group owner.
+    GoProbeResponse,
+    /// Beacon sent as group owner.
+    GoBeacon,
+    /// Provision Discovery Request.
+    ProvisionDiscoveryRequest,
+    /// Provision Discovery Response.
+    ProvisionDiscoveryResponse,
+    /// GO Negotiation Request.
+    GoNegotiationRequest,
+    /// GO Negotiation Response.
+    GoNegotiationResponse,
+    /// GO Negotiation Confirmation.
+    GoNegotiationConfirm,
+    /// Invitation Request.
+    InvitationRequest,
+    /// Invitation Response.
+    InvitationResponse,
+    /// Association Request sent as P2P client.
+    AssocRequest,
+    /// Association Response sent as group owner.
+    AssocResponse,
+}
+
+impl VendorElementFrame {
+    /// The frame id used by wpa_supplicant's VendorElement* methods.
+    pub fn id(self) -> i32 {
+        match self {
+            Self::ProbeRequest => 0,
+            Self::ProbeResponse => 1,
+            Self::GoProbeResponse => 2,
+            Self::GoBeacon => 3,
+            Self::ProvisionDiscoveryRequest => 4,
+            Self::ProvisionDiscoveryResponse => 5,
+            Self::GoNegotiationRequest => 6,
+            Self::GoNegotiationResponse => 7,
+            Self::GoNegotiationConfirm => 8,
+            Self::InvitationRequest => 9,
+            Self::InvitationResponse => 10,
+            Self::AssocRequest => 11,
+            Self::AssocResponse => 12,
+        }
+    }
+}
@@ -0,0 +1,338 @@
+//! Wi-Fi Display (Miracast) sessions.
+//!
+//! Devices taking part in Wi-Fi Display announce a WFD Device Information
+//! subelement in their P2P frames: whether they are a source or a sink, whether
+//! they take a session now and the TCP port of their RTSP server. We announce
+//! ours with `WifiP2pChannel::set_wfd_info`, and peers' arrive as
+//! `P2pDevice::wfd`.
+//!
+//! `WifiP2pChannel::start_wfd_session` pairs with a peer whose role fits ours
+//! and returns a `WfdSession`: which side we stream on, the group, the peer's
+//! address and the source's RTSP port. The RTSP exchange (M1 to M7) and the
+//! stream itself are left to a Miracast stack; the sink opens the RTSP
+//! connection to the source.
+
+#[cfg(feature = "tokio")]
+use std::net::{IpAddr, SocketAddr};
+
+#[cfg(feature = "tokio")]
+use crate::channel::WifiP2pChannel;
+#[cfg(feature = "tokio")]
+use crate::device::P2pDevice;
+#[cfg(feature = "tokio")]
+use crate::error::P2pError;
+#[cfg(feature = "tokio")]
+use crate::group::{GroupRole, P2pGroup};
+#[cfg(feature = "tokio")]
+use crate::mac::MacAddress;
+#[cfg(feature = "tokio")]
+use crate::pair::{self, PairOptions, PeerSelector};
+
+/// Subelement id of WFD Device Information.
+const DEVICE_INFORMATION: u8 = 0;
+
+/// The RTSP port sources listen on unless they announce another.
+pub const DEFAULT_RTSP_PORT: u16 = 7236;
+
+/// What a device does in Wi-Fi Display (WFD Device Information bits 0-1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
+pub enum WfdDeviceType {
+    /// Sends its screen, e.g. a phone or laptop.
+    Source,
+    /// Shows a source's screen, e.g. a TV or display adapter.
+    PrimarySink,
+    /// Plays only the audio of a session a primary sink is coupled to.
+    SecondarySink,
+    /// Either, depending on the peer.
+    DualRole,
+}
+
+impl WfdDeviceType {
+    fn bits(self) -> u16 {
+        match self {
+            Self::Source => 0,
+            Self::PrimarySink => 1,
+            Self::SecondarySink => 2,
+            Self::DualRole => 3,
+        }
+    }
+
+    fn from_bits(bits: u16) -> Self {
+        match bits & 0x3 {
+            0 => Self::Source,
+            1 => Self::PrimarySink,
+            2 => Self::SecondarySink,
+            _ => Self::DualRole,
+        }
+    }
+
+    /// Our role in a session between us (`self`) and a `peer`; `None` if
+    /// neither side can send to the other, or with a secondary sink, which
+    /// only joins a session through its coupled primary sink. Two dual-role
+    /// devices get `None` too: the types alone cannot tell them apart, so
+    /// `start_wfd_session` makes the group owner the source.
+    pub fn role_with(self, peer: WfdDeviceType) -> Option<WfdRole> {
+        use WfdDeviceType::*;
+        match (self, peer) {
+            (Source | DualRole, PrimarySink) | (Source, DualRole) => Some(WfdRole::Source),
+            (PrimarySink | DualRole, Source) | (PrimarySink, DualRole) => Some(WfdRole::Sink),
+            _ => None,
+        }
+    }
+}
+
+/// Our side of a Wi-Fi Display session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
+pub enum WfdRole {
+    /// We stream and run the RTSP server.
+    Source,
+    /// We receive and open the RTSP connection.
+    Sink,
+}
+
+/// The WFD Device Information subelement, ours or a peer's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct WfdDeviceInfo {
+    pub device_type: WfdDeviceType,
+    /// Whether the device takes a new session right now.
+    pub session_available: bool,
+    /// TCP port of the device's RTSP server (Session Management Control Port).
+    pub rtsp_port: u16,
+    /// Highest throughput the device can stream or render, in Mbit/s.
+    pub max_throughput: u16,
+    /// HDCP 2.x content protection is supported.
+    pub content_protection: bool,
+}
+
+impl WfdDeviceInfo {
+    /// An available device of `device_type` on the default RTSP port, up to
+    /// 50 Mbit/s and without content protection.
+    pub fn new(device_type: WfdDeviceType) -> Self {
+        Self {
+            device_type,
+            session_available: true,
+            rtsp_port: DEFAULT_RTSP_PORT,
+            max_throughput: 50,
+            content_protection: false,
+        }
+    }
+
+    pub fn session_available(mut self, available: bool) -> Self {
+        self.session_available = available;
+        self
+    }
+
+    pub fn rtsp_port(mut self, port: u16) -> Self {
+        self.rtsp_port = port;
+        self
+    }
+
+    pub fn max_throughput(mut self, mbps: u16) -> Self {
+        self.max_throughput = mbps;
+        self
+    }
+
+    pub fn content_protection(mut self, supported: bool) -> Self {
+        self.content_protection = supported;
+        self
+    }
+
+    /// The Device Information out of a peer's WFD subelements (the body of its
+    /// WFD IE); `None` if there is none.
+    pub fn parse(subelements: &[u8]) -> Option<Self> {
+        let mut rest = subelements;
+        while let [id, high, low, body @ ..] = rest {
+            let len = usize::from(u16::from_be_bytes([*high, *low]));
+            let value = body.get(..len)?;
+            if *id == DEVICE_INFORMATION && len == 6 {
+                let field = |at: usize| u16::from_be_bytes([value[at], value[at + 1]]);
+                let bits = field(0);
+                return Some(Self {
+                    device_type: WfdDeviceType::from_bits(bits),
+                    session_available: (bits >> 4) & 0x3 == 1,
+                    rtsp_port: field(2),
+                    max_throughput: field(4),
+                    content_protection: bits & 0x100 != 0,
+                });
+            }
+            rest = &body[len..];
+        }
+        None
+    }
+
+    /// The subelement as sent: id, length and the six-byte body.
+    pub fn subelement(&self) -> [u8; 9] {
+        let mut bits = self.device_type.bits();
+        if self.session_available {
+            bits |= 1 << 4;
+        }
+        if self.content_protection {
+            bits |= 0x100;
+        }
+        let mut bytes = [DEVICE_INFORMATION, 0, 6, 0, 0, 0, 0, 0, 0];
+        bytes[3..5].copy_from_slice(&bits.to_be_bytes());
+        bytes[5..7].copy_from_slice(&self.rtsp_port.to_be_bytes());
+        bytes[7..9].copy_from_slice(&self.max_throughput.to_be_bytes());
+        bytes
+    }
+}
+
+/// What a Miracast stack needs to run a session with a paired peer, as
+/// returned by `WifiP2pChannel::start_wfd_session`.
+#[cfg(feature = "tokio")]
+#[derive(Debug, Clone)]
+pub struct WfdSession {
+    pub role: WfdRole,
+    /// The peer as it was found during discovery.
+    pub peer: P2pDevice,
+    pub peer_info: WfdDeviceInfo,
+    pub group: P2pGroup,
+    /// The peer's address on the group interface; a source sends RTP there.
+    pub peer_ip: IpAddr,
+    /// The source's RTSP port: where we listen as source, where we connect as sink.
+    pub rtsp_port: u16,
+    /// Both sides support HDCP 2.x.
+    pub content_protection: bool,
+}
+
+#[cfg(feature = "tokio")]
+impl WfdSession {
+    /// The source's RTSP server, for a sink to connect to; `None` when we are
+    /// the source.
+    pub fn source_address(&self) -> Option<SocketAddr> {
+        match self.role {
+            WfdRole::Source => None,
+            WfdRole::Sink => Some(SocketAddr::new(self.peer_ip, self.rtsp_port)),
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+pub(crate) async fn start_session(
+    channel: &WifiP2pChannel,
+    peer: MacAddress,
+) -> Result<WfdSession, P2pError> {
+    let local = channel
+        .request_wfd_info()
+        .await?
+        .await
+        .map_err(pair::closed)??
+        .ok_or_else(|| {
+            P2pError::InvalidConfig("Wi-Fi Display is off; see set_wfd_info".to_string())
+        })?;
+    let options = PairOptions::default();
+    // Subscribe before anything is started, so no event in between is missed.
+    let mut events = channel.subscribe_events();
+    let selector = PeerSelector::Address(peer);
+    let device = pair::within(
+        options.discovery_timeout,
+        pair::find(channel, &mut events, &selector),
+    )
+    .await?;
+    let peer_info = device
+        .wfd
+        .ok_or_else(|| P2pError::Unsupported(format!("{peer} does not do Wi-Fi Display")))?;
+    let role = local.device_type.role_with(peer_info.device_type);
+    let both_dual = local.device_type == WfdDeviceType::DualRole
+        && peer_info.device_type == WfdDeviceType::DualRole;
+    if role.is_none() && !both_dual {
+        return Err(P2pError::InvalidArgs(format!(
+            "{peer} is a {:?} and cannot stream with a {:?}",
+            peer_info.device_type, local.device_type
+        )));
+    }
+    if !peer_info.session_available {
+        return Err(P2pError::Busy(format!(
+            "{peer} takes no Wi-Fi Display session now"
+        )));
+    }
+    let link = pair::link(channel, &mut events, device, &options).await?;
+    // Both sides see the same group owner, so they pick opposite roles.
+    let role = role.unwrap_or(match link.group().role {
+        GroupRole::Owner => WfdRole::Source,
+        GroupRole::Client => WfdRole::Sink,
+    });
+    let rtsp_port = match role {
+        WfdRole::Source => local.rtsp_port,
+        WfdRole::Sink => peer_info.rtsp_port,
+    };
+    Ok(WfdSession {
+        role,
+        peer: link.peer().clone(),
+        peer_info,
+        group: link.group().clone(),
+        peer_ip: link.peer_ip(),
+        rtsp_port,
+        content_protection: local.content_protection && peer_info.content_protection,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roles_are_opposite_on_both_sides() {
+        use WfdDeviceType::*;
+        let types = [Source, PrimarySink, SecondarySink, DualRole];
+        for ours in types {
+            for theirs in types {
+                let role = ours.role_with(theirs);
+                let opposite = role.map(|role| match role {
+                    WfdRole::Source => WfdRole::Sink,
+                    WfdRole::Sink => WfdRole::Source,
+                });
+                assert_eq!(theirs.role_with(ours), opposite, "{ours:?} with {theirs:?}");
+            }
+        }
+        assert_eq!(Source.role_with(PrimarySink), Some(WfdRole::Source));
+        assert_eq!(DualRole.role_with(Source), Some(WfdRole::Sink));
+        assert_eq!(Source.role_with(Source), None);
+        assert_eq!(SecondarySink.role_with(Source), None);
+        assert_eq!(DualRole.role_with(DualRole), None);
+    }
+
+    #[test]
+    fn device_information_round_trips() {
+        let info = WfdDeviceInfo::new(WfdDeviceType::PrimarySink)
+            .rtsp_port(7000)
+            .max_throughput(300)
+            .content_protection(true);
+        let bytes = info.subelement();
+        assert_eq!(
+            bytes,
+            [0x00, 0x00, 0x06, 0x01, 0x11, 0x1b, 0x58, 0x01, 0x2c]
+        );
+        assert_eq!(WfdDeviceInfo::parse(&bytes), Some(info));
+
+        let busy = WfdDeviceInfo::new(WfdDeviceType::Source).session_available(false);
+        assert_eq!(WfdDeviceInfo::parse(&busy.subelement()), Some(busy));
+    }
+
+    #[test]
+    fn device_information_is_found_after_other_subelements() {
+        let info = WfdDeviceInfo::new(WfdDeviceType::DualRole);
+        // Associated BSSID (id 1) first, as some sinks send it.
+        let mut subelements = vec![0x01, 0x00, 0x06, 1, 2, 3, 4, 5, 6];
+        subelements.extend_from_slice(&info.subelement());
+        assert_eq!(WfdDeviceInfo::parse(&subelements), Some(info));
+    }
+
+    #[test]
+    fn truncated_or_missing_device_information_is_rejected() {
+        let bytes = WfdDeviceInfo::new(WfdDeviceType::Source).subelement();
+        assert_eq!(WfdDeviceInfo::parse(&bytes[..8]), None);
+        assert_eq!(
+            WfdDeviceInfo::parse(&[0x01, 0x00, 0x06, 1, 2, 3, 4, 5, 6]),
+            None
+        );
+        assert_eq!(WfdDeviceInfo::parse(&[0x00, 0x00, 0x02, 0x00, 0x10]), None);
+        assert_eq!(WfdDeviceInfo::parse(&[]), None);
+    }
+}
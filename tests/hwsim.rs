@@ -0,0 +1,83 @@
+//! End-to-end tests on mac80211_hwsim radios. Run as root in a VM with:
+//! `cargo test --features hwsim --test hwsim -- --ignored --test-threads=1`
+#![cfg(feature = "hwsim")]
+
+use std::time::Duration;
+
+use tokio_stream::StreamExt;
+use wifi_p2p_rs::testing::hwsim::HwsimHarness;
+use wifi_p2p_rs::{MacAddress, P2pEvent, P2pEventStream, WifiP2pManager};
+
+const STEP_TIMEOUT: Duration = Duration::from_secs(30);
+
+async fn wait_for_peer(events: &mut P2pEventStream, name: &str) -> MacAddress {
+    let found = async {
+        while let Some(event) = events.next().await {
+            if let P2pEvent::PeerFound(device) = event
+                && device.device_name.as_deref() == Some(name)
+            {
+                return device.mac_address;
+            }
+        }
+        panic!("event stream ended before {name} was found");
+    };
+    tokio::time::timeout(STEP_TIMEOUT, found)
+        .await
+        .unwrap_or_else(|_| panic!("{name} was not discovered"))
+}
+
+#[tokio::test]
+#[ignore = "needs root, mac80211_hwsim and wpa_supplicant with D-Bus"]
+async fn discover_connect_and_form_group() {
+    let harness = HwsimHarness::start(2).expect("start hwsim harness");
+    let [first, second] = harness.interfaces() else {
+        panic!("expected two radios");
+    };
+    let mut first_manager = WifiP2pManager::new(first).await.expect("first manager");
+    let mut second_manager = WifiP2pManager::new(second).await.expect("second manager");
+    let first_channel = first_manager.initialize();
+    let second_channel = second_manager.initialize();
+    let mut first_events = first_channel.event_stream();
+    let mut second_events = second_channel.event_stream();
+
+    // Discover: both sides scan until they see each other.
+    first_channel
+        .discover_peers()
+        .await
+        .unwrap()
+        .await
+        .unwrap()
+        .unwrap();
+    second_channel
+        .discover_peers()
+        .await
+        .unwrap()
+        .await
+        .unwrap()
+        .unwrap();
+    let second_address = wait_for_peer(&mut first_events, &HwsimHarness::device_name(1)).await;
+    let first_address = wait_for_peer(&mut second_events, &HwsimHarness::device_name(0)).await;
+
+    // Connect: push-button on both ends runs GO negotiation and forms the group.
+    let first_connect = first_channel.connect(second_address).await.unwrap();
+    let second_connect = second_channel.connect(first_address).await.unwrap();
+    let (first_result, second_result) = tokio::join!(first_connect, second_connect);
+    first_result.unwrap().expect("first side connects");
+    second_result.unwrap().expect("second side connects");
+
+    let connected = async {
+        while let Some(event) = first_events.next().await {
+            if let P2pEvent::Connected(address) = event {
+                return address;
+            }
+        }
+        panic!("event stream ended before Connected");
+    };
+    let address = tokio::time::timeout(STEP_TIMEOUT, connected)
+        .await
+        .expect("Connected event");
+    assert_eq!(address, second_address);
+
+    first_manager.shutdown().await.expect("first shutdown");
+    second_manager.shutdown().await.expect("second shutdown");
+}